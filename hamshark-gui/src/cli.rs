@@ -0,0 +1,59 @@
+use clap::Parser;
+use log::LevelFilter;
+use std::path::PathBuf;
+
+/// Record and explore amateur radio audio
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// Use this settings file instead of the usual one
+    #[arg(long, value_name = "FILE")]
+    pub settings: Option<PathBuf>,
+
+    /// Reopen an existing session directory instead of starting a new one
+    #[arg(long, value_name = "DIR")]
+    pub session: Option<PathBuf>,
+
+    /// Copy a WAV file into the session as a clip. Can be given more than once.
+    #[arg(long, value_name = "WAV")]
+    pub import: Vec<PathBuf>,
+
+    /// Record from the input device with this name instead of the default
+    #[arg(long, value_name = "NAME")]
+    pub device: Option<String>,
+
+    /// Record from an earlier session's clips, played back in real time, instead of an input
+    /// device. For trying out decoders and settings on what was heard before.
+    #[arg(long, value_name = "DIR", conflicts_with = "device")]
+    pub replay: Option<PathBuf>,
+
+    /// Start the replay again from the beginning when it gets to the end, rather than
+    /// stopping the recording
+    #[arg(long, requires = "replay")]
+    pub replay_loop: bool,
+
+    /// Record without the GUI until interrupted, for an unattended receiver
+    #[arg(long)]
+    pub headless: bool,
+
+    /// In headless mode, start a new clip after this many seconds of recording. Overrides
+    /// the segment interval in the settings file.
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub segment: Option<u64>,
+
+    /// In headless mode, serve remote control on this address:port. Overrides the server
+    /// settings in the settings file.
+    #[cfg(feature = "server")]
+    #[arg(long, value_name = "ADDR")]
+    pub serve: Option<String>,
+
+    /// Run a Rhai script that automates recording, calling it back as levels change and text
+    /// is decoded. In headless mode, recording starts and stops when the script says.
+    #[cfg(feature = "scripting")]
+    #[arg(long, value_name = "FILE")]
+    pub script: Option<PathBuf>,
+
+    /// How much to log: off, error, warn, info, debug or trace. Overrides RUST_LOG.
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<LevelFilter>,
+}
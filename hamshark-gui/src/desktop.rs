@@ -0,0 +1,169 @@
+use hamshark::config::DesktopSettings;
+use keepawake::KeepAwake;
+use log::{debug, warn};
+use souvlaki::{MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, PlatformConfig};
+use std::{
+    ffi::c_void,
+    sync::mpsc::{self, Receiver},
+};
+
+/// How our audio streams describe themselves to PipeWire and PulseAudio. cpal only speaks
+/// ALSA, but the sound servers' ALSA plugins read these from the environment.
+#[cfg(target_os = "linux")]
+const STREAM_PROPERTIES: [(&str, &str); 2] = [
+    (
+        "PIPEWIRE_PROPS",
+        "{ application.name = Hamshark application.id = hamshark \
+         application.icon-name = hamshark media.role = Production media.category = Capture \
+         node.name = hamshark node.description = Hamshark }",
+    ),
+    (
+        "PULSE_PROP",
+        "application.name=Hamshark application.id=hamshark application.icon_name=hamshark \
+         media.role=production",
+    ),
+];
+
+/// Name our audio streams, so they show up as Hamshark in pavucontrol and helvum and get
+/// treated as a recording by the sound server. Recording from its default device, the sound
+/// server moves the stream along when the default changes. Anything already set in the
+/// environment is left alone.
+pub fn describe_audio_streams() {
+    #[cfg(target_os = "linux")]
+    for (key, value) in STREAM_PROPERTIES {
+        if std::env::var_os(key).is_none() {
+            // SAFETY: called first thing in main, before there are other threads to read
+            // the environment
+            unsafe { std::env::set_var(key, value) };
+        }
+    }
+}
+
+/// What the desktop's media controls asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaRequest {
+    Start,
+    Stop,
+    /// Stop if we're recording, otherwise start
+    Toggle,
+}
+
+/// Keep the computer awake while recording, as the settings say. Sleep is allowed again when
+/// it's dropped.
+pub fn keep_awake(settings: &DesktopSettings) -> Option<KeepAwake> {
+    if !settings.keep_awake {
+        return None;
+    }
+    keepawake::Builder::default()
+        .idle(true)
+        .sleep(true)
+        .display(settings.keep_display_on)
+        .reason("Recording")
+        .app_name("Hamshark")
+        .app_reverse_domain("com.jefftickle.hamshark")
+        .create()
+        .inspect_err(|error| warn!("Unable to keep the computer awake: {}", error))
+        .ok()
+}
+
+/// Keeps the desktop up to date with whether we're recording: no sleeping while we are, and
+/// the media controls showing it and starting and stopping it.
+pub struct Desktop {
+    settings: DesktopSettings,
+    awake: Option<KeepAwake>,
+    controls: Option<MediaControls>,
+    requests: Receiver<MediaRequest>,
+    /// The clip being recorded
+    recording: Option<String>,
+    hwnd: Option<*mut c_void>,
+}
+
+impl Desktop {
+    /// `hwnd` is the main window, which the media controls need on Windows
+    pub fn new(settings: &DesktopSettings, hwnd: Option<*mut c_void>) -> Self {
+        let (sender, requests) = mpsc::channel();
+        let controls = settings
+            .media_controls
+            .then(|| {
+                let mut controls = MediaControls::new(PlatformConfig {
+                    display_name: "Hamshark",
+                    dbus_name: "hamshark",
+                    hwnd,
+                })?;
+                // Recording is the closest thing we have to playing
+                controls.attach(move |event| {
+                    let request = match event {
+                        MediaControlEvent::Play => MediaRequest::Start,
+                        MediaControlEvent::Pause | MediaControlEvent::Stop => MediaRequest::Stop,
+                        MediaControlEvent::Toggle => MediaRequest::Toggle,
+                        event => {
+                            debug!("Ignoring media control {:?}", event);
+                            return;
+                        }
+                    };
+                    let _ = sender.send(request);
+                })?;
+                controls.set_metadata(MediaMetadata {
+                    title: Some("Idle"),
+                    ..Default::default()
+                })?;
+                controls.set_playback(MediaPlayback::Stopped)?;
+                Ok::<_, souvlaki::Error>(controls)
+            })
+            .transpose()
+            .inspect_err(|error| warn!("Unable to set up media controls: {}", error))
+            .ok()
+            .flatten();
+        Self {
+            settings: settings.clone(),
+            awake: None,
+            controls,
+            requests,
+            recording: None,
+            hwnd,
+        }
+    }
+
+    /// Tell the desktop whether we're recording, and into which clip
+    pub fn set_recording(&mut self, recording: Option<&str>) {
+        if recording == self.recording.as_deref() {
+            return;
+        }
+        if recording.is_some() != self.recording.is_some() {
+            self.awake = recording.and_then(|_| keep_awake(&self.settings));
+        }
+        self.recording = recording.map(str::to_string);
+        if let Some(controls) = &mut self.controls {
+            let (playback, title) = match recording {
+                Some(clip) => (MediaPlayback::Playing { progress: None }, clip),
+                None => (MediaPlayback::Stopped, "Idle"),
+            };
+            let updated = controls.set_playback(playback).and_then(|()| {
+                controls.set_metadata(MediaMetadata {
+                    title: Some(title),
+                    ..Default::default()
+                })
+            });
+            if let Err(error) = updated {
+                warn!("Unable to update media controls: {}", error);
+            }
+        }
+    }
+
+    /// The next thing the media controls asked for, if any
+    pub fn poll(&self) -> Option<MediaRequest> {
+        self.requests.try_recv().ok()
+    }
+
+    /// Pick up changed settings
+    pub fn apply_settings(&mut self, settings: &DesktopSettings) {
+        if *settings == self.settings {
+            return;
+        }
+        let recording = self.recording.take();
+        // Let go of the media controls before registering new ones
+        self.controls = None;
+        *self = Self::new(settings, self.hwnd);
+        self.set_recording(recording.as_deref());
+    }
+}
@@ -0,0 +1,1283 @@
+pub mod audio;
+pub mod audioinput;
+pub mod calibrate;
+pub mod classify;
+pub mod cwspeed;
+pub mod distortion;
+pub mod doppler;
+pub mod drift;
+pub mod export;
+pub mod frequency;
+pub mod generator;
+pub mod imd;
+pub mod job;
+pub mod keyer;
+pub mod noisefloor;
+pub mod performance;
+pub mod pipeline;
+pub mod response;
+pub mod scope;
+pub mod setup;
+pub mod snr;
+pub mod spectrum;
+pub mod stability;
+pub mod statistics;
+pub mod tdoa;
+pub mod thumbnail;
+pub mod timeline;
+pub mod view;
+pub mod waterfall;
+
+use crate::{
+    desktop::{Desktop, MediaRequest},
+    gui::{
+        audio::OpenClips, generator::Generator, job::Job, keyer::CwKeyer,
+        noisefloor::NoiseFloorPlot, performance::PerformanceOverlay, response::SweepAnalyzer,
+        scope::Scope, setup::SetupWizard, spectrum::Spectrum, tdoa::ArrivalTime,
+    },
+};
+use cpal::traits::DeviceTrait;
+use eframe::egui::{CentralPanel, Context};
+use egui::{Button, Color32, Slider, ThemePreference, Window};
+#[cfg(feature = "scripting")]
+use hamshark::scripting::Script;
+use hamshark::{
+    birdies::{self, BirdieCatalog},
+    carriers,
+    config::{ChannelMapping, Configuration, LookupService, Settings, Theme},
+    data::{
+        audio::{ClipId, WavClip},
+        audioinput::AudioInputDeviceBuilder,
+        bandplan::Region,
+    },
+    lookup::CallsignLookup,
+    resample,
+    session::Session,
+    status::State,
+    timesource::SharedTimeSource,
+};
+use log::{error, info};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+const GPLV3: &str = "https://www.gnu.org/licenses/gpl-3.0.en.html";
+const REPO: &str = "https://git.serenity.jefftickle.com/jwt/hamshark";
+/// How often to look for changes to the settings file
+const SETTINGS_POLL: Duration = Duration::from_secs(1);
+/// How often to check on work done in the background, like writing the carrier log
+const BACKGROUND_POLL: Duration = Duration::from_millis(500);
+/// How often to look for samples while recording, when none arrived since the last frame
+const IDLE_REPAINT: Duration = Duration::from_millis(250);
+/// How often to look for a lost input device coming back
+const DEVICE_POLL: Duration = Duration::from_secs(2);
+/// How often a script is ticked while nothing else needs the window painting
+#[cfg(feature = "scripting")]
+const SCRIPT_POLL: Duration = Duration::from_millis(100);
+
+pub struct HamSharkGui {
+    session: Session,
+    /// The windows for exploring the session's clips
+    clips: OpenClips,
+    config: Configuration,
+    settings: Settings,
+
+    audio_input_selecting: Option<AudioInputDeviceBuilder>,
+    /// The first-run setup dialog, while it's open
+    setup: Option<SetupWizard>,
+    /// Name to save the current audio input under, as it's being typed
+    preset_name: String,
+    scope: Scope,
+    generator: Generator,
+    keyer: CwKeyer,
+    sweep: SweepAnalyzer,
+    noise_floor: NoiseFloorPlot,
+    arrival_time: ArrivalTime,
+    spectrum: Spectrum,
+    /// When the settings file was last read or written by us, to notice when it's edited
+    settings_modified: Option<SystemTime>,
+    settings_checked: Instant,
+    /// Keeping the computer awake and the media controls up to date
+    desktop: Desktop,
+    /// Looking up who the callsigns in clips belong to, if a service is set up
+    lookup: Option<CallsignLookup>,
+    /// Writing the session's carrier log in the background, and where to
+    logging_carriers: Option<(PathBuf, Job<Result<usize, io::Error>>)>,
+    /// The birdies found in the session's clips
+    birdies: BirdieCatalog,
+    /// Scanning the session for birdies in the background
+    scanning_birdies: Option<Job<BirdieCatalog>>,
+    /// How many samples the recording had at the last frame
+    painted_samples: usize,
+    /// Recording stopped because the input device went away
+    device_lost: Option<DeviceLoss>,
+    /// Clips recorded at a different rate from the rest of the session
+    odd_rates: Option<OddRates>,
+    performance: PerformanceOverlay,
+    /// Automating recording, given on the command line
+    #[cfg(feature = "scripting")]
+    script: Option<Script>,
+}
+
+/// Clips at a sample rate other than the one most of the session is at, which views and
+/// tools comparing clips take to be the same
+struct OddRates {
+    rate: u32,
+    clips: Vec<(ClipId, u32)>,
+    /// Resampling them in the background
+    resampling: Option<Job<Vec<Result<WavClip, resample::Error>>>>,
+}
+
+/// A clip cut short by the input device going away
+struct DeviceLoss {
+    clip: ClipId,
+    /// Whether the device was back when last looked for
+    back: bool,
+    checked: Instant,
+}
+
+impl HamSharkGui {
+    pub fn new(
+        cc: &eframe::CreationContext<'_>,
+        session: Session,
+        config: Configuration,
+        settings: Settings,
+        first_run: bool,
+    ) -> Self {
+        cc.egui_ctx
+            .set_theme(theme_preference(settings.display.theme));
+        let generator = Generator::new(&settings.generator);
+        let keyer = CwKeyer::new(&settings.keyer);
+        let sweep = SweepAnalyzer::new(&settings.sweep);
+        let noise_floor = NoiseFloorPlot::new(&settings.noise_floor);
+        let spectrum = Spectrum::new(settings.dsp.window_function, settings.display.averaging);
+        let setup = first_run.then(|| {
+            SetupWizard::new(
+                &settings.session_base_dir,
+                session
+                    .configuration()
+                    .map(AudioInputDeviceBuilder::from)
+                    .unwrap_or_default(),
+                settings.station.clone(),
+            )
+        });
+        let clips = OpenClips::new(config.thumbnail_cache_dir(&session.path));
+        let desktop = Desktop::new(&settings.desktop, window_handle(cc));
+        let lookup = start_lookup(&settings, &config, session.time_source());
+        let birdies = load_birdies(&session.path);
+        let odd_rates = session.odd_sample_rates().map(|(rate, ids)| OddRates {
+            rate,
+            clips: ids
+                .into_iter()
+                .map(|id| {
+                    let rate = session.clips[&id].read().sample_rate.0;
+                    (id, rate)
+                })
+                .collect(),
+            resampling: None,
+        });
+        let mut gui = Self {
+            session,
+            clips,
+            settings,
+            audio_input_selecting: None,
+            setup,
+            preset_name: String::new(),
+            scope: Default::default(),
+            generator,
+            keyer,
+            sweep,
+            noise_floor,
+            arrival_time: Default::default(),
+            spectrum,
+            settings_modified: modified_time(&config.settings_file_path),
+            settings_checked: Instant::now(),
+            desktop,
+            lookup,
+            logging_carriers: None,
+            birdies,
+            scanning_birdies: None,
+            painted_samples: 0,
+            device_lost: None,
+            odd_rates,
+            performance: Default::default(),
+            #[cfg(feature = "scripting")]
+            script: None,
+            config,
+        };
+        gui.mark_birdies();
+        gui
+    }
+
+    #[cfg(feature = "scripting")]
+    pub fn with_script(mut self, script: Option<Script>) -> Self {
+        self.script = script;
+        self
+    }
+
+    /// Let the script react to what's happened since the last frame
+    #[cfg(feature = "scripting")]
+    fn tick_script(&mut self, ctx: &Context) {
+        if let Some(script) = &mut self.script {
+            script.tick(&mut self.session);
+            ctx.request_repaint_after(SCRIPT_POLL);
+        }
+    }
+
+    /// Write the carrier log for every clip in the session, in the background
+    fn export_carrier_log(&mut self) {
+        let path = self.session.path.join(carriers::LOG_FILE);
+        let clips = self.session.clips.clone();
+        let settings = self.settings.carriers.clone();
+        let target = path.clone();
+        self.logging_carriers = Some((
+            path,
+            Job::spawn(move |progress| carriers::write_log(&clips, &settings, &target, progress)),
+        ));
+    }
+
+    /// Say how writing the carrier log went, once it's done
+    fn finish_carrier_log(&mut self, ctx: &Context) {
+        let Some((path, writing)) = self
+            .logging_carriers
+            .take_if(|(_, writing)| writing.is_finished())
+        else {
+            if self.logging_carriers.is_some() {
+                ctx.request_repaint_after(BACKGROUND_POLL);
+            }
+            return;
+        };
+        match writing.join() {
+            Ok(Ok(count)) => info!("Logged {} carriers to {}", count, path.display()),
+            Ok(Err(error)) => error!("Unable to write {}: {}", path.display(), error),
+            Err(_) => error!("Writing {} failed", path.display()),
+        }
+    }
+
+    /// Finish the clip if the input device has gone away, then watch for it coming back
+    fn check_device(&mut self, ctx: &Context) {
+        match self.session.check_device() {
+            Ok(Some(clip)) => {
+                self.device_lost = Some(DeviceLoss {
+                    clip,
+                    back: false,
+                    checked: Instant::now(),
+                })
+            }
+            Ok(None) => {}
+            Err(error) => error!(
+                "Unable to finish the clip the input device was lost from: {}",
+                error
+            ),
+        }
+        // Picking another device, or starting again on this one, is the end of it
+        if self.session.lost_device().is_none() {
+            self.device_lost = None;
+        }
+        if let Some(loss) = &mut self.device_lost {
+            if loss.checked.elapsed() >= DEVICE_POLL {
+                loss.back = self.session.lost_device_is_back();
+                loss.checked = Instant::now();
+            }
+            ctx.request_repaint_after(DEVICE_POLL);
+        }
+    }
+
+    /// Say which clip losing the input device cut short, offering to record on it again
+    /// once it's back
+    fn show_device_lost(&mut self, ctx: &Context) {
+        let Some(loss) = &self.device_lost else {
+            return;
+        };
+        let device = self
+            .session
+            .lost_device()
+            .map_or(String::new(), |preset| preset.device.clone());
+        let (mut resume, mut dismiss) = (false, false);
+        Window::new("Input Device Lost")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} went away, so {} was cut short.",
+                    device, loss.clip
+                ));
+                ui.label(if loss.back {
+                    "It's back now."
+                } else {
+                    "Waiting for it to come back…"
+                });
+                ui.horizontal(|ui| {
+                    resume = ui
+                        .add_enabled(loss.back, Button::new("Resume"))
+                        .on_hover_text("Start a new clip on the device")
+                        .clicked();
+                    dismiss = ui.button("Dismiss").clicked();
+                });
+            });
+        if resume {
+            if let Err(error) = self.session.resume_lost_device() {
+                error!("Unable to resume recording: {}", error);
+            }
+        } else if dismiss {
+            self.session.forget_lost_device();
+        }
+    }
+
+    /// Offer to bring clips at odd sample rates into line with the rest of the session
+    fn show_odd_rates(&mut self, ctx: &Context) {
+        let Some(odd) = &self.odd_rates else {
+            return;
+        };
+        let (mut resample, mut dismiss) = (false, false);
+        Window::new("Mixed Sample Rates")
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "Most of this session was recorded at {} Hz, but not these clips:",
+                    odd.rate
+                ));
+                for (id, rate) in &odd.clips {
+                    ui.label(format!("{} at {} Hz", id, rate));
+                }
+                ui.label("Tools that compare clips need them at the same rate.");
+                ui.horizontal(|ui| match &odd.resampling {
+                    Some(job) => {
+                        ui.spinner();
+                        ui.label(format!("Resampling, {:.0}% done", job.percent()));
+                    }
+                    None => {
+                        resample = ui
+                            .button(format!("Resample to {} Hz", odd.rate))
+                            .on_hover_text("The originals are kept alongside, ending in .orig")
+                            .clicked();
+                        dismiss = ui.button("Leave Them").clicked();
+                    }
+                });
+            });
+        if resample {
+            self.resample_odd_clips();
+        } else if dismiss {
+            self.odd_rates = None;
+        }
+    }
+
+    /// Resample the clips at odd rates one after the other, in the background
+    fn resample_odd_clips(&mut self) {
+        let Some(odd) = &mut self.odd_rates else {
+            return;
+        };
+        let rate = odd.rate;
+        let clips: Vec<_> = odd
+            .clips
+            .iter()
+            .filter_map(|(id, _)| self.session.clips.get(id).cloned())
+            .collect();
+        odd.resampling = Some(Job::spawn(move |progress| {
+            let count = clips.len() as f32;
+            clips
+                .iter()
+                .enumerate()
+                .map(|(i, clip)| {
+                    resample::resample_clip(clip, rate, &|fraction| {
+                        progress((i as f32 + fraction) / count)
+                    })
+                })
+                .collect()
+        }));
+    }
+
+    /// Swap in the resampled clips, once they're all done
+    fn finish_resampling(&mut self, ctx: &Context) {
+        let Some(odd) = &mut self.odd_rates else {
+            return;
+        };
+        let Some(resampling) = odd.resampling.take_if(|job| job.is_finished()) else {
+            if odd.resampling.is_some() {
+                ctx.request_repaint_after(BACKGROUND_POLL);
+            }
+            return;
+        };
+        self.odd_rates = None;
+        let Ok(results) = resampling.join() else {
+            error!("Resampling failed");
+            return;
+        };
+        for result in results {
+            match result {
+                Ok(clip) => {
+                    info!("Resampled {} to {} Hz", clip.id(), clip.sample_rate.0);
+                    self.clips.reopen(clip.id());
+                    self.session.replace_clip(clip);
+                }
+                Err(error) => error!("Unable to resample a clip: {}", error),
+            }
+        }
+    }
+
+    /// Look for birdies in every clip in the session, in the background
+    fn scan_birdies(&mut self) {
+        let clips = self.session.clips.clone();
+        let carriers = self.settings.carriers.clone();
+        let settings = self.settings.birdies.clone();
+        self.scanning_birdies = Some(Job::spawn(move |progress| {
+            BirdieCatalog::scan(&clips, &carriers, &settings, progress)
+        }));
+    }
+
+    /// Save and mark the birdies found, once the scan is done
+    fn finish_birdie_scan(&mut self, ctx: &Context) {
+        let Some(scanning) = self
+            .scanning_birdies
+            .take_if(|scanning| scanning.is_finished())
+        else {
+            if self.scanning_birdies.is_some() {
+                ctx.request_repaint_after(BACKGROUND_POLL);
+            }
+            return;
+        };
+        let Ok(catalog) = scanning.join() else {
+            error!("Scanning for birdies failed");
+            return;
+        };
+        info!("Found {} birdies", catalog.birdies.len());
+        if let Err(error) = catalog.save(&self.session.path) {
+            error!("{}", error);
+        }
+        self.birdies = catalog;
+        self.mark_birdies();
+    }
+
+    /// Mark the birdies seen often enough on the waterfalls, if they're to be shown
+    fn mark_birdies(&mut self) {
+        let birdies = if self.settings.layout.birdies_shown {
+            self.birdies
+                .persistent(self.settings.birdies.min_occurrences)
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self.clips.set_birdies(birdies);
+    }
+
+    /// Keep what was chosen in the setup wizard. If the session folder moved, start the
+    /// session over in the new one, as long as nothing's been put in this one yet.
+    fn finish_setup(&mut self, setup: SetupWizard) {
+        let session_base_dir = PathBuf::from(setup.session_base_dir.trim());
+        let moved = session_base_dir != self.settings.session_base_dir;
+        self.settings.session_base_dir = session_base_dir;
+        self.settings.station = setup.station;
+        self.save_settings();
+
+        if moved && self.session.clips.is_empty() && !self.session.is_recording() {
+            match Session::from_settings(&self.config, &self.settings) {
+                Ok(session) => {
+                    self.clips = OpenClips::new(self.config.thumbnail_cache_dir(&session.path));
+                    let abandoned = std::mem::replace(&mut self.session, session);
+                    // Only goes if it's empty
+                    fs::remove_dir(&abandoned.path).ok();
+                    self.birdies = load_birdies(&self.session.path);
+                    self.mark_birdies();
+                }
+                Err(error) => error!("Unable to start a session in the new folder: {}", error),
+            }
+        }
+        match setup.device.build() {
+            Ok(device) => {
+                if let Err(error) = self.session.configure(device) {
+                    error!("Unable to configure the audio input: {}", error);
+                }
+            }
+            Err(error) => error!("No audio input was chosen: {}", error),
+        }
+    }
+
+    fn settings_file_modified(&self) -> Option<SystemTime> {
+        modified_time(&self.config.settings_file_path)
+    }
+
+    /// Reload the settings file if it's been changed since we last read or wrote it
+    fn watch_settings(&mut self, ctx: &Context) {
+        ctx.request_repaint_after(SETTINGS_POLL);
+        if self.settings_checked.elapsed() < SETTINGS_POLL {
+            return;
+        }
+        self.settings_checked = Instant::now();
+        let modified = self.settings_file_modified();
+        if modified.is_some() && modified != self.settings_modified {
+            self.reload_settings(ctx);
+        }
+    }
+
+    /// Read the settings file again and apply whatever can be changed without a restart.
+    /// Clips that are already open keep the FFT and window they were opened with.
+    fn reload_settings(&mut self, ctx: &Context) {
+        self.settings_modified = self.settings_file_modified();
+        let settings = match Settings::from_file(&self.config.settings_file_path) {
+            Ok(settings) => settings,
+            Err(error) => {
+                error!("Unable to reload settings: {}", error);
+                return;
+            }
+        };
+        info!("Reloaded settings");
+        if settings.display.theme != self.settings.display.theme {
+            ctx.set_theme(theme_preference(settings.display.theme));
+        }
+        if settings.dsp.window_function != self.settings.dsp.window_function {
+            self.spectrum
+                .set_window_function(settings.dsp.window_function);
+        }
+        if settings.display.averaging != self.settings.display.averaging {
+            self.spectrum.set_averaging(settings.display.averaging);
+        }
+        self.session.apply_settings(&settings);
+        self.desktop.apply_settings(&settings.desktop);
+        if settings.lookup != self.settings.lookup {
+            // Let the old one go first, so it's done with the cache file
+            self.lookup = None;
+            self.lookup = start_lookup(&settings, &self.config, self.session.time_source());
+        }
+        let birdies_changed = settings.birdies != self.settings.birdies;
+        self.settings = settings;
+        if birdies_changed {
+            self.mark_birdies();
+        }
+    }
+
+    /// Keep track of where the main window is, so it can be put back there next time
+    /// How the recording is going, for the status bar
+    fn show_status(&self, ui: &mut egui::Ui) {
+        let status = self.session.status();
+        let (text, color) = match status.state {
+            State::Idle => ("Idle", ui.visuals().text_color()),
+            State::Recording => ("Recording", Color32::GREEN),
+            State::Paused => ("Paused", Color32::YELLOW),
+            State::Failed => ("Failed", Color32::RED),
+        };
+        ui.colored_label(color, text);
+        if let Some(location) = self.session.location() {
+            ui.label(&location.grid).on_hover_text(format!(
+                "{:.5}, {:.5}",
+                location.latitude, location.longitude
+            ));
+        }
+        if let Some(offset) = self.session.clock().and_then(|clock| clock.offset()) {
+            let color = if self
+                .session
+                .clock()
+                .is_some_and(|clock| clock.is_drifting())
+            {
+                Color32::RED
+            } else {
+                ui.visuals().text_color()
+            };
+            ui.colored_label(color, format!("Clock {:+} ms", offset.num_milliseconds()))
+                .on_hover_text("How far the system clock is behind a time server");
+        }
+        #[cfg(feature = "jack")]
+        if let Some(jack) = self.session.jack_info() {
+            let transport = if jack.transport_rolling {
+                "rolling"
+            } else {
+                "stopped"
+            };
+            ui.label(format!(
+                "JACK {} frames at {} Hz",
+                jack.buffer_size, jack.sample_rate
+            ))
+            .on_hover_text(format!(
+                "Transport {} at frame {}",
+                transport, jack.transport_frame
+            ));
+        }
+        if let Some(clip) = &status.clip {
+            ui.label(clip.to_string());
+            ui.label(format!("{} samples", status.samples_captured));
+            ui.label(format!("{} xruns", status.xruns));
+            if let Some(warning) = &status.watchdog {
+                ui.colored_label(Color32::YELLOW, "⚠ Input")
+                    .on_hover_text(format!("{warning}. Gaps are marked in the clip."));
+            }
+            if let Some(fill) = status.buffer_fill {
+                ui.label(format!("Buffer {:.0}%", fill * 100.0));
+            }
+            if self.settings.blanker.enabled
+                && let Some(impulses) = status.impulses_blanked
+            {
+                ui.label(format!("{impulses} impulses blanked"));
+            }
+        }
+    }
+
+    fn track_window_geometry(&mut self, ctx: &Context) {
+        let layout = &mut self.settings.layout;
+        ctx.input(|input| {
+            let viewport = input.viewport();
+            layout.maximized = viewport.maximized.unwrap_or(false);
+            // Remember the restored geometry rather than the maximized or minimized one
+            if layout.maximized || viewport.minimized.unwrap_or(false) {
+                return;
+            }
+            if let Some(inner) = viewport.inner_rect {
+                layout.window_size = Some(inner.size().into());
+            }
+            if let Some(outer) = viewport.outer_rect {
+                layout.window_position = Some(outer.min.into());
+            }
+        });
+    }
+
+    /// Switch to a saved audio input, save the current one, or forget one
+    fn show_presets_menu(&mut self, ui: &mut egui::Ui) {
+        let current = self.session.configuration();
+        let mut forget = None;
+        for (i, preset) in self.settings.device_presets.iter().enumerate() {
+            ui.horizontal(|ui| {
+                let selected = current
+                    .as_ref()
+                    .is_some_and(|device| device.to_preset(&preset.name) == *preset);
+                if ui
+                    .selectable_label(selected, &preset.name)
+                    .on_hover_text(format!(
+                        "{} on {}\n{} channel(s) at {} Hz",
+                        preset.device, preset.host, preset.channels, preset.sample_rate
+                    ))
+                    .clicked()
+                {
+                    match AudioInputDeviceBuilder::from_preset(preset).map(|b| b.build()) {
+                        Some(Ok(device)) => {
+                            if let Err(error) = self.session.configure(device) {
+                                error!("Unable to switch to {}: {}", preset.name, error);
+                            }
+                        }
+                        _ => error!("{} isn't available", preset.device),
+                    }
+                }
+                if ui.small_button("✖").on_hover_text("Forget").clicked() {
+                    forget = Some(i);
+                }
+            });
+        }
+        if let Some(i) = forget {
+            self.settings.device_presets.remove(i);
+            self.save_settings();
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.preset_name)
+                    .hint_text("Preset name")
+                    .desired_width(120.0),
+            );
+            let name = self.preset_name.trim().to_string();
+            if ui
+                .add_enabled(
+                    !name.is_empty() && current.is_some(),
+                    Button::new("Save current"),
+                )
+                .clicked()
+                && let Some(device) = current
+            {
+                let preset = device.to_preset(&name);
+                let presets = &mut self.settings.device_presets;
+                match presets.iter_mut().find(|p| p.name == name) {
+                    Some(existing) => *existing = preset,
+                    None => presets.push(preset),
+                }
+                self.preset_name.clear();
+                self.save_settings();
+            }
+        });
+    }
+
+    /// Pick a microphone to record on the right channel, alongside the rig on the left
+    fn show_microphone_menu(&mut self, ui: &mut egui::Ui) {
+        let mut changed = ui
+            .radio_value(&mut self.settings.mix.enabled, false, "None")
+            .changed();
+        let builder = self
+            .session
+            .configuration()
+            .map(AudioInputDeviceBuilder::from)
+            .unwrap_or_default();
+        let host = builder.host_id.name();
+        let devices = builder.input_devices().unwrap_or_default();
+        for name in devices.iter().filter_map(|device| device.name().ok()) {
+            let mix = &mut self.settings.mix;
+            let selected = mix.enabled && mix.host == host && mix.device == name;
+            if ui.radio(selected, &name).clicked() && !selected {
+                mix.enabled = true;
+                mix.host = host.to_string();
+                mix.device = name;
+                changed = true;
+            }
+        }
+        if changed {
+            self.session.apply_settings(&self.settings);
+            self.save_settings();
+        }
+    }
+
+    /// Which of the input's channels to record, taking effect from the next clip
+    fn show_channels_menu(&mut self, ui: &mut egui::Ui) {
+        let Some(config) = self.session.configuration() else {
+            ui.label("No audio input is configured");
+            return;
+        };
+        let host = config.host_id.name();
+        let device = config.device.name().unwrap_or_default();
+        let channels = config.config.channels;
+        let mut mapping = self.settings.channel_mapping(host, &device);
+        let mut changed = ui
+            .radio_value(&mut mapping, ChannelMapping::Average, "Average")
+            .on_hover_text("Record one channel, the average of them all")
+            .changed();
+        for channel in 0..channels {
+            changed |= ui
+                .radio_value(
+                    &mut mapping,
+                    ChannelMapping::Pick(channel),
+                    format!("Channel {}", channel + 1),
+                )
+                .changed();
+        }
+        changed |= ui
+            .radio_value(&mut mapping, ChannelMapping::KeepAll, "Keep All")
+            .on_hover_text(format!("Record all {} channels as they come", channels))
+            .changed();
+        if changed {
+            self.settings.set_channel_mapping(host, &device, mapping);
+            self.session.apply_settings(&self.settings);
+            self.save_settings();
+        }
+    }
+
+    /// Which plugin sinks recordings go through, from the next clip on
+    #[cfg(feature = "plugins")]
+    fn show_plugins_menu(&mut self, ui: &mut egui::Ui) {
+        if !self.settings.plugins.enabled {
+            ui.label("Plugins are turned off in the settings");
+            return;
+        }
+        let sinks = hamshark::plugins::sinks();
+        if sinks.is_empty() {
+            ui.label(format!(
+                "No plugins in {}",
+                self.config.plugins_dir().display()
+            ));
+            return;
+        }
+        for sink in sinks {
+            let mut enabled = self.settings.plugins.sink_enabled(sink.name());
+            if ui
+                .checkbox(&mut enabled, sink.name())
+                .on_hover_text(sink.path().display().to_string())
+                .changed()
+            {
+                self.settings.plugins.set_sink_enabled(sink.name(), enabled);
+                self.session.apply_settings(&self.settings);
+                self.save_settings();
+            }
+        }
+    }
+
+    /// Gains for the rig and microphone, adjustable while recording
+    fn show_mix_gains(&mut self, ui: &mut egui::Ui) {
+        let mix = &mut self.settings.mix;
+        let mut changed = false;
+        let mut settled = false;
+        for (label, gain) in [
+            ("Rig", &mut mix.rig_gain),
+            ("Mic", &mut mix.microphone_gain),
+        ] {
+            ui.label(label);
+            let response = ui.add(Slider::new(gain, -40.0..=20.0).suffix(" dB"));
+            changed |= response.changed();
+            settled |= response.drag_stopped() || (response.changed() && !response.dragged());
+        }
+        if changed {
+            self.session.apply_settings(&self.settings);
+        }
+        // Only write the file once the slider's let go
+        if settled {
+            self.save_settings();
+        }
+    }
+
+    /// Start and stop recording from the media controls, and keep them showing what we're doing
+    fn follow_media_controls(&mut self) {
+        while let Some(request) = self.desktop.poll() {
+            let recording = self.session.is_recording();
+            let result = match request {
+                MediaRequest::Start | MediaRequest::Toggle if !recording => {
+                    self.session.record_new_clip()
+                }
+                MediaRequest::Stop | MediaRequest::Toggle if recording => {
+                    self.session.stop_recording()
+                }
+                _ => Ok(()),
+            };
+            if let Err(error) = result {
+                error!("Unable to {:?} recording: {}", request, error);
+            }
+        }
+        let clip = self
+            .session
+            .recording_clip()
+            .map(|clip| clip.read().id().to_string());
+        self.desktop.set_recording(clip.as_deref());
+    }
+
+    /// Keep redrawing while recording, at the display's refresh rate while samples are
+    /// arriving and only now and then while they aren't, so an idle view doesn't keep the
+    /// CPU busy
+    fn pace_frames(&mut self, ctx: &egui::Context) {
+        let Some(clip) = self.session.recording_clip() else {
+            self.painted_samples = 0;
+            return;
+        };
+        let samples = clip.read().samples.len();
+        let interval = if samples != self.painted_samples {
+            Duration::from_secs_f64(1.0 / self.settings.display.refresh_rate.clamp(1, 120) as f64)
+        } else {
+            IDLE_REPAINT
+        };
+        self.painted_samples = samples;
+        ctx.request_repaint_after(interval);
+    }
+
+    /// Save what was connected to the JACK ports when recording stopped, so it's restored
+    /// next time
+    fn keep_jack_connections(&mut self) {
+        if let Some(connections) = self.session.take_jack_connections() {
+            self.settings.jack.connections = connections;
+            self.save_settings();
+        }
+    }
+
+    /// Keep a frequency correction measured in a clip for the input being recorded from
+    fn keep_calibration(&mut self) {
+        let Some(ppm) = self.clips.take_calibration() else {
+            return;
+        };
+        let Some((host, device)) = self.session.input_name() else {
+            error!("No audio input to save the calibration for");
+            return;
+        };
+        info!("Correcting frequencies from {} by {:+.3} ppm", device, ppm);
+        self.settings.set_frequency_correction(&host, &device, ppm);
+        self.session.apply_settings(&self.settings);
+        self.save_settings();
+    }
+
+    fn save_settings(&mut self) {
+        if let Err(error) = self.settings.save(&self.config.settings_file_path) {
+            error!("Unable to save settings: {}", error);
+        }
+        // Don't mistake our own write for someone editing the file
+        self.settings_modified = self.settings_file_modified();
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// The main window, which the media controls need on Windows
+#[cfg(target_os = "windows")]
+fn window_handle(cc: &eframe::CreationContext<'_>) -> Option<*mut std::ffi::c_void> {
+    use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+    match cc.window_handle().map(|handle| handle.as_raw()) {
+        Ok(RawWindowHandle::Win32(handle)) => Some(handle.hwnd.get() as *mut std::ffi::c_void),
+        _ => None,
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn window_handle(_: &eframe::CreationContext<'_>) -> Option<*mut std::ffi::c_void> {
+    None
+}
+
+/// Start looking up callsigns, unless no service is set up
+fn start_lookup(
+    settings: &Settings,
+    config: &Configuration,
+    time: &SharedTimeSource,
+) -> Option<CallsignLookup> {
+    if settings.lookup.service == LookupService::None {
+        return None;
+    }
+    match CallsignLookup::start(&settings.lookup, &config.cache_dir, time) {
+        Ok(lookup) => Some(lookup),
+        Err(error) => {
+            error!("Unable to start looking up callsigns: {}", error);
+            None
+        }
+    }
+}
+
+/// Open a folder or link with whatever the desktop uses for it
+fn open_or_log(target: &str) {
+    if let Err(error) = open::that(target) {
+        error!("Could not open {}: {}", target, error);
+    }
+}
+
+/// The session's birdie catalog, or an empty one if it can't be read
+fn load_birdies(session_dir: &Path) -> BirdieCatalog {
+    BirdieCatalog::load(session_dir).unwrap_or_else(|error| {
+        error!("{}", error);
+        BirdieCatalog::default()
+    })
+}
+
+fn theme_preference(theme: Theme) -> ThemePreference {
+    match theme {
+        Theme::System => ThemePreference::System,
+        Theme::Dark => ThemePreference::Dark,
+        Theme::Light => ThemePreference::Light,
+    }
+}
+
+pub trait View {
+    fn show(&mut self, ui: &mut egui::Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce());
+}
+
+impl eframe::App for HamSharkGui {
+    fn update(&mut self, ctx: &Context, frame: &mut eframe::Frame) {
+        let cpu_usage = frame.info().cpu_usage;
+        self.clips.sync(&self.session.clips, &self.settings);
+        self.finish_carrier_log(ctx);
+        self.finish_birdie_scan(ctx);
+        self.finish_resampling(ctx);
+        self.check_device(ctx);
+        #[cfg(feature = "scripting")]
+        self.tick_script(ctx);
+
+        // Top Menu Bar
+        egui::TopBottomPanel::top("menu").show(ctx, |ui| {
+            egui::MenuBar::new().ui(ui, |ui| {
+                ui.menu_button("File", |ui| {
+                    if ui.button("Configure Audio").clicked() {
+                        self.audio_input_selecting = match self.session.configuration() {
+                            Some(config) => Some(config.into()),
+                            None => Some(AudioInputDeviceBuilder::default()),
+                        };
+                    }
+                    ui.menu_button("Audio Presets", |ui| self.show_presets_menu(ui));
+                    ui.menu_button("Microphone", |ui| self.show_microphone_menu(ui));
+                    ui.menu_button("Channels", |ui| self.show_channels_menu(ui));
+                    if ui
+                        .checkbox(&mut self.settings.blanker.enabled, "Noise Blanker")
+                        .on_hover_text(
+                            "Blank short loud impulses, like ignition and power line noise, \
+                             out of what's recorded",
+                        )
+                        .changed()
+                    {
+                        self.session.apply_settings(&self.settings);
+                        self.save_settings();
+                    }
+                    ui.menu_button("Band Plan", |ui| {
+                        for region in Region::ALL {
+                            if ui
+                                .radio_value(
+                                    &mut self.settings.band_plan_region,
+                                    region,
+                                    region.to_string(),
+                                )
+                                .changed()
+                            {
+                                self.save_settings();
+                            }
+                        }
+                    });
+                    if ui
+                        .add_enabled(
+                            self.logging_carriers.is_none(),
+                            Button::new("Export Carrier Log"),
+                        )
+                        .on_hover_text(format!(
+                            "Find the carriers in every clip and list when they were on in {}",
+                            carriers::LOG_FILE
+                        ))
+                        .on_disabled_hover_text(self.logging_carriers.as_ref().map_or(
+                            String::new(),
+                            |(_, logging)| {
+                                format!("Tracking carriers, {:.0}% done", logging.percent())
+                            },
+                        ))
+                        .clicked()
+                    {
+                        self.export_carrier_log();
+                    }
+                    if ui
+                        .add_enabled(
+                            self.scanning_birdies.is_none(),
+                            Button::new("Scan for Birdies"),
+                        )
+                        .on_hover_text(format!(
+                            "Find the steady carriers that keep turning up in the clips and \
+                             list them in {}",
+                            birdies::CATALOG_FILE
+                        ))
+                        .on_disabled_hover_text(
+                            self.scanning_birdies
+                                .as_ref()
+                                .map_or(String::new(), |scanning| {
+                                    format!("Scanning, {:.0}% done", scanning.percent())
+                                }),
+                        )
+                        .clicked()
+                    {
+                        self.scan_birdies();
+                    }
+                    if ui.button("Reload Settings").clicked() {
+                        self.reload_settings(ui.ctx());
+                    }
+                    if ui.button("Quit").clicked() {
+                        ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
+                    }
+                });
+                ui.menu_button("View", |ui| {
+                    ui.checkbox(&mut self.settings.layout.clip_list_open, "Clip List");
+                    ui.checkbox(&mut self.settings.layout.scope_open, "Scope");
+                    ui.checkbox(&mut self.settings.layout.spectrum_open, "Spectrum");
+                    ui.checkbox(&mut self.settings.layout.generator_open, "Signal Generator");
+                    ui.checkbox(&mut self.settings.layout.keyer_open, "CW Keyer");
+                    ui.checkbox(
+                        &mut self.settings.layout.response_open,
+                        "Frequency Response",
+                    );
+                    ui.checkbox(&mut self.settings.layout.noise_floor_open, "Noise Floor");
+                    ui.checkbox(
+                        &mut self.settings.layout.tdoa_open,
+                        "Arrival Time Difference",
+                    );
+                    if ui
+                        .checkbox(&mut self.settings.layout.birdies_shown, "Birdies")
+                        .on_hover_text("Mark the session's birdies on the waterfalls")
+                        .changed()
+                    {
+                        self.mark_birdies();
+                    }
+                });
+                ui.menu_button("Developer", |ui| {
+                    ui.checkbox(
+                        &mut self.settings.layout.pipeline_inspector_open,
+                        "Pipeline Inspector",
+                    );
+                    ui.checkbox(
+                        &mut self.settings.layout.performance_overlay_open,
+                        "Performance Overlay",
+                    )
+                    .on_hover_text(
+                        "Frame times, how evenly the input's blocks come, buffer fill and write \
+                         throughput",
+                    );
+                    #[cfg(feature = "plugins")]
+                    ui.menu_button("Plugins", |ui| self.show_plugins_menu(ui));
+                });
+            });
+        });
+
+        // Tool Bar
+        egui::TopBottomPanel::top("toolbar").show(ctx, |ui| {
+            let button = Button::new("➕");
+            let enabled = !self.session.is_recording();
+            ui.horizontal(|ui| {
+                if ui.add_enabled(enabled, button).clicked()
+                    && let Err(error) = self.session.record_new_clip()
+                {
+                    error!("Unable to start recording: {}", error);
+                }
+                if self.settings.mix.enabled {
+                    ui.separator();
+                    self.show_mix_gains(ui);
+                }
+            });
+        });
+
+        // Add some status to the bottom of the window
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let path = self.session.path.to_str();
+                ui.label(format!(
+                    "Live Session: {}",
+                    path.unwrap_or("OS STR DECODE ERROR")
+                ));
+                if let Some(p) = path
+                    && ui.button("Browse").clicked()
+                {
+                    open_or_log(p);
+                }
+                ui.separator();
+                self.show_status(ui);
+                ui.separator();
+                if ui.button("GPLv3").clicked() {
+                    open_or_log(GPLV3);
+                }
+                ui.separator();
+                if ui.button("Source").clicked() {
+                    open_or_log(REPO);
+                }
+            })
+        });
+
+        // Session Overview
+        egui::SidePanel::left("clips").show_animated(
+            ctx,
+            self.settings.layout.clip_list_open,
+            |ui| {
+                self.clips.show_clip_list(ui);
+            },
+        );
+
+        // Main content panel
+        CentralPanel::default().show(ctx, |ui| {
+            log::trace!("Updating GUI, dt is {}", ctx.input(|i| i.stable_dt));
+
+            // Show all of the open clip viewers
+            // The waterfall draws with a shader when we have a GL context
+            let gpu_available = frame.gl().is_some();
+            self.clips
+                .show_editor_windows(ui, gpu_available, &self.settings, self.lookup.as_ref());
+
+            self.scope.show(
+                ctx,
+                &mut self.settings.layout.scope_open,
+                self.session.recording_clip(),
+            );
+
+            self.spectrum.show(
+                ctx,
+                &mut self.settings.layout.spectrum_open,
+                self.session.recording_clip(),
+                &self.settings.carriers,
+            );
+
+            self.generator
+                .show(ctx, &mut self.settings.layout.generator_open);
+            self.keyer
+                .show(ctx, &mut self.settings.layout.keyer_open, &mut self.session);
+            self.sweep
+                .show(ctx, &mut self.settings.layout.response_open);
+            self.arrival_time.show(
+                ctx,
+                &mut self.settings.layout.tdoa_open,
+                &self.session.clips,
+            );
+            if self.noise_floor.show(
+                ctx,
+                &mut self.settings.layout.noise_floor_open,
+                &mut self.settings.noise_floor,
+                self.session.noise_floor(),
+            ) {
+                self.session.apply_settings(&self.settings);
+                self.save_settings();
+            }
+
+            if self.settings.layout.performance_overlay_open {
+                let bits = self
+                    .settings
+                    .recording
+                    .bit_depth
+                    .wav_spec(0)
+                    .bits_per_sample;
+                self.performance
+                    .show(ctx, cpu_usage, self.session.pipeline(), bits / 8);
+            }
+            pipeline::show_inspector(
+                ctx,
+                &mut self.settings.layout.pipeline_inspector_open,
+                self.session.pipeline(),
+            );
+            self.show_device_lost(ctx);
+            self.show_odd_rates(ctx);
+
+            // Show the setup wizard on the first run
+            if let Some(mut setup) = self.setup.take() {
+                let mut should_save = false;
+                let mut should_cancel = false;
+                setup.show(
+                    ui,
+                    || {
+                        should_save = true;
+                    },
+                    || {
+                        should_cancel = true;
+                    },
+                );
+                if should_save {
+                    self.finish_setup(setup);
+                } else if !should_cancel {
+                    self.setup = Some(setup);
+                }
+            }
+
+            // Show audio configuration if open
+            if let Some(mut data) = self.audio_input_selecting.take() {
+                let mut should_save = false;
+                let mut should_cancel = false;
+                data.show(
+                    ui,
+                    || {
+                        should_save = true;
+                    },
+                    || {
+                        should_cancel = true;
+                    },
+                );
+                if should_save {
+                    match data.build() {
+                        Ok(device) => {
+                            if let Err(error) = self.session.configure(device) {
+                                error!("Unable to configure the audio input: {}", error);
+                            }
+                        }
+                        Err(error) => error!("Unable to configure the audio input: {}", error),
+                    }
+                } else if !should_cancel {
+                    self.audio_input_selecting = Option::Some(data);
+                }
+            }
+        });
+
+        self.track_window_geometry(ctx);
+        self.watch_settings(ctx);
+        self.keep_jack_connections();
+        self.keep_calibration();
+        self.follow_media_controls();
+
+        // Finish the clip before the window goes away rather than leave it to the exit
+        if ctx.input(|input| input.viewport().close_requested())
+            && let Err(error) = self.session.stop_recording()
+        {
+            error!("Unable to stop recording: {}", error);
+        }
+
+        self.pace_frames(ctx);
+    }
+
+    fn on_exit(&mut self, gl: Option<&eframe::glow::Context>) {
+        if let Err(error) = self.session.stop_recording() {
+            error!("Unable to stop recording: {}", error);
+        }
+        if let Some(connections) = self.session.take_jack_connections() {
+            self.settings.jack.connections = connections;
+        }
+        self.save_settings();
+
+        if let Some(gl) = gl {
+            self.clips.destroy_gl(gl);
+        }
+    }
+}
@@ -0,0 +1,1166 @@
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use eframe::glow;
+use egui::{
+    Color32, PointerButton, Rect, Sense, Stroke, StrokeKind, TextEdit, Ui, Window,
+    scroll_area::ScrollBarVisibility,
+};
+use log::error;
+
+use crate::gui::{
+    View,
+    calibrate::Calibration,
+    classify::SignalClassification,
+    cwspeed::CwSpeedMeasurement,
+    distortion::DistortionAnalysis,
+    doppler::DopplerTrace,
+    drift::DriftPlot,
+    export::{self, DataExport, ExportRange, ImageExport},
+    frequency::FrequencyEstimate,
+    imd::ImdAnalysis,
+    job::Job,
+    snr::SnrMeasurement,
+    stability::StabilityMeasurement,
+    statistics::SelectionStatistics,
+    thumbnail::Thumbnail,
+    timeline::Timeline,
+    view::ColumnTexture,
+    view::{Scaler, ViewTransform, pointer_pos_from_response, show_averaging_controls},
+    waterfall::{RowParameters, Waterfall},
+};
+use hamshark::{
+    birdies::Birdie,
+    carriers::{self, Carrier},
+    config::Settings,
+    data::{
+        audio::{Clip, ClipId, Selection},
+        averaging::Averaging,
+        metadata::{LoopPoints, Marker, Qso, ViewState},
+    },
+    lookup::{CallsignLookup, Lookup},
+    segments,
+    tools::SamplePlayer,
+    vad::SpeechDetector,
+};
+
+/// Height of the whole-clip overview in split view
+const OVERVIEW_HEIGHT: usize = 64;
+/// How often to check on a callsign that's still being looked up
+const LOOKUP_POLL: Duration = Duration::from_millis(500);
+/// How often to check whether finding speech or tracking carriers has finished
+const ANALYSIS_POLL: Duration = Duration::from_millis(200);
+/// Samples read at a time while finding speech, so recording isn't held up
+const SPEECH_CHUNK: usize = 1 << 16;
+/// Labels of the markers put around speech
+const SPEECH_START: &str = "Speech";
+const SPEECH_END: &str = "Speech end";
+
+pub struct ClipExplorer {
+    pub open: bool,
+    title: String,
+    clip: Clip,
+    /// Shared by all of the views so they pan and zoom together
+    view: ViewTransform,
+    timeline: Timeline,
+    waterfall: Waterfall,
+    /// The export image dialog, while it's open
+    exporting: Option<ImageExport>,
+    /// The export spectrogram data dialog, while it's open
+    exporting_data: Option<DataExport>,
+    /// The calibrate dialog, while it's open
+    calibrating: Option<Calibration>,
+    /// The satellite pass dialog, while it's open
+    tracing: Option<DopplerTrace>,
+    /// The SNR dialog, while it's open
+    measuring_snr: Option<SnrMeasurement>,
+    /// The distortion dialog, while it's open
+    measuring_distortion: Option<DistortionAnalysis>,
+    classifying: Option<SignalClassification>,
+    measuring_cw_speed: Option<CwSpeedMeasurement>,
+    measuring_imd: Option<ImdAnalysis>,
+    /// The tone frequency dialog, while it's open
+    measuring_frequency: Option<FrequencyEstimate>,
+    /// The drift plot dialog, while it's open
+    plotting_drift: Option<DriftPlot>,
+    /// The frequency stability dialog, while it's open
+    measuring_stability: Option<StabilityMeasurement>,
+    /// Looking for speech to mark, while it's going
+    finding_speech: Option<Job<Vec<Range<usize>>>>,
+    /// Following the carriers through the clip in the background
+    tracking_carriers: Option<Job<Vec<Carrier>>>,
+    /// Splitting the clip into QSOs in the background
+    finding_qsos: Option<Job<Vec<Qso>>>,
+    /// A correction measured in the calibrate dialog, for the input to pick up
+    calibrated: Option<f64>,
+    /// The clip's callsign, as it's being typed
+    callsign: String,
+    /// Shown in the clip list
+    thumbnail: Thumbnail,
+    /// Playback, while we're playing
+    player: Option<SamplePlayer>,
+    /// Show an overview of the whole clip above the detail views
+    split: bool,
+    overview: ColumnTexture,
+    /// Show the selection's statistics under the controls
+    show_statistics: bool,
+    statistics: SelectionStatistics,
+    /// The waterfall's averaging, once it's been changed from the settings
+    averaging: Option<Averaging>,
+}
+
+impl ClipExplorer {
+    pub fn new(clip: Clip, settings: &Settings, thumbnail_cache: &Path) -> Self {
+        let title = clip.read().id().to_string();
+        let saved_state = clip.read().metadata.view.clone();
+        let callsign = clip.read().metadata.callsign.clone().unwrap_or_default();
+        let timeline = Timeline::new(clip.clone());
+        let waterfall = Waterfall::new(
+            clip.clone(),
+            RowParameters::new(&settings.dsp, settings.display.averaging),
+        );
+        let thumbnail =
+            Thumbnail::new(clip.clone(), thumbnail_cache.join(format!("{}.png", title)));
+        let mut explorer = Self {
+            title,
+            clip,
+            view: ViewTransform {
+                scale: settings.display.timeline_scale,
+                ..Default::default()
+            },
+            timeline,
+            waterfall,
+            exporting: None,
+            exporting_data: None,
+            calibrating: None,
+            tracing: None,
+            measuring_snr: None,
+            measuring_distortion: None,
+            classifying: None,
+            measuring_cw_speed: None,
+            measuring_imd: None,
+            measuring_frequency: None,
+            plotting_drift: None,
+            measuring_stability: None,
+            finding_speech: None,
+            finding_qsos: None,
+            tracking_carriers: None,
+            calibrated: None,
+            callsign,
+            thumbnail,
+            player: None,
+            split: false,
+            overview: ColumnTexture::new("overview"),
+            show_statistics: false,
+            statistics: Default::default(),
+            averaging: None,
+            open: true,
+        };
+        if let Some(state) = saved_state {
+            explorer.restore_view_state(state);
+        }
+        explorer
+    }
+
+    fn view_state(&self) -> ViewState {
+        ViewState {
+            open: self.open,
+            scale: self.view.scale,
+            vscale: self.timeline.vscale,
+            // Live follow moves the offset every frame, don't bother remembering it
+            offset: if self.view.live { 0 } else { self.view.offset },
+            live: self.view.live,
+            selection: self.timeline.selection,
+            loop_points: self.timeline.loop_points,
+            split: self.split,
+            statistics: self.show_statistics,
+            averaging: self.averaging,
+        }
+    }
+
+    fn restore_view_state(&mut self, state: ViewState) {
+        self.open = state.open;
+        self.view.scale = state.scale;
+        self.view.offset = state.offset;
+        self.view.live = state.live;
+        self.timeline.vscale = state.vscale;
+        self.timeline.selection = state.selection;
+        self.timeline.loop_points = state.loop_points;
+        self.split = state.split;
+        self.show_statistics = state.statistics;
+        self.averaging = state.averaging;
+    }
+
+    /// Draw the whole clip in a strip, outline the part the detail views are showing, and
+    /// move them to wherever is clicked
+    fn show_overview(&mut self, ui: &mut Ui) {
+        let len = self.view.sample_len;
+        let overview = ViewTransform::fit(&(0..len.max(1)), self.view.width, len);
+        let image = self.timeline.render_with_height(&overview, OVERVIEW_HEIGHT);
+        let response = self.overview.show(ui, image, None, Sense::click_and_drag());
+
+        let visible_end = self.view.screen_to_data_x(self.view.width as isize) as usize;
+        let outline = overview.data_x_range_to_screen_x_range(&(self.view.offset..visible_end));
+        let left = response.rect.min.x + outline.start as f32;
+        let right = response.rect.min.x + outline.end.max(outline.start + 1) as f32;
+        ui.painter().rect_stroke(
+            Rect::from_x_y_ranges(left..=right, response.rect.y_range()),
+            0.0,
+            Stroke::new(1.0, Color32::WHITE),
+            StrokeKind::Inside,
+        );
+
+        if (response.clicked() || response.dragged_by(PointerButton::Primary))
+            && let Some(pos) = pointer_pos_from_response(&response)
+        {
+            self.view
+                .seek(overview.screen_to_data_x(pos.x as isize) as usize);
+        }
+    }
+
+    /// Play the A/B loop if there is one, otherwise the selection, otherwise from the start
+    /// of the view to the end of the clip
+    fn play(&mut self) {
+        let (range, looping) = match (&self.timeline.loop_points.range(), &self.timeline.selection)
+        {
+            (Some(range), _) => (range.clone(), true),
+            (None, Some(selection)) => (selection.range(), false),
+            (None, None) => (self.view.offset..usize::MAX, false),
+        };
+        match SamplePlayer::new(self.clip.clone(), range, looping) {
+            Ok(player) => self.player = Some(player),
+            Err(error) => error!("Unable to play {}: {}", self.title, error),
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(player) = self.player.take() {
+            player.stop();
+        }
+        self.timeline.playhead = None;
+    }
+
+    fn show_transport(&mut self, ui: &mut Ui) {
+        if self.player.is_some() {
+            if ui.button("⏹").on_hover_text("Stop").clicked() {
+                self.stop();
+            }
+        } else if ui
+            .button("▶")
+            .on_hover_text("Play the A/B loop, or the selection, or from the left of the view.\nSet loop points with A and B while hovering the timeline.")
+            .clicked()
+        {
+            self.play();
+        }
+        if self.timeline.loop_points != LoopPoints::default() && ui.button("Clear loop").clicked() {
+            self.timeline.loop_points = LoopPoints::default();
+        }
+    }
+
+    /// Save the view state to the clip's metadata once it has settled, so we don't write
+    /// the file for every frame of a drag.
+    fn persist_view_state(&mut self, ui: &Ui) {
+        if ui.input(|input| input.pointer.any_down()) {
+            return;
+        }
+        let state = self.view_state();
+        if let Err(error) = self.clip.write().set_view_state(state) {
+            error!("Unable to save view state for {}: {}", self.title, error);
+        }
+    }
+
+    /// Render the chosen views to an image and ask where to save it
+    fn export_image(&self, options: &ImageExport) -> Result<(), export::Error> {
+        let view = match (&options.range, &self.timeline.selection) {
+            (ExportRange::Selection, Some(selection)) => {
+                ViewTransform::fit(&selection.range(), options.width, self.view.sample_len)
+            }
+            _ => self.view.clone(),
+        };
+
+        let mut images = Vec::new();
+        if options.waveform {
+            images.push(self.timeline.render(&view));
+        }
+        if options.waterfall {
+            images.push(self.waterfall.render(&view));
+        }
+        let image = export::stack(&images);
+
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("PNG image", &["png"])
+            .set_file_name(format!("{}.png", self.title))
+            .save_file()
+        else {
+            return Ok(());
+        };
+        export::write_png(&path, &image)
+    }
+
+    /// Take the waterfall's magnitudes for the chosen range and ask where to save them
+    fn export_data(&self, options: &DataExport) -> Result<(), export::Error> {
+        let range = match (&options.range, &self.timeline.selection) {
+            (ExportRange::Selection, Some(selection)) => selection.range(),
+            _ => self.view.visible_range(),
+        };
+        let spectrogram = self.waterfall.spectrogram(&range);
+
+        let extension = options.format.extension();
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter(options.format.description(), &[extension])
+            .set_file_name(format!("{}.{}", self.title, extension))
+            .save_file()
+        else {
+            return Ok(());
+        };
+        spectrogram.write(&path, options.format)
+    }
+
+    pub fn destroy_gl(&mut self, gl: &glow::Context) {
+        self.waterfall.destroy_gl(gl);
+    }
+
+    /// Measurements of the selection, or the whole clip
+    fn show_analyze_menu(&mut self, ui: &mut Ui, settings: &Settings) {
+        let selection = self.timeline.selection.map(|s| s.range());
+        if ui
+            .button("Calibrate…")
+            .on_hover_text("Measure how far off frequencies read, against a reference in the selection or the whole clip")
+            .clicked()
+        {
+            let range = selection.clone().unwrap_or(0..self.view.sample_len);
+            self.calibrating = Some(Calibration::new(&self.title, self.clip.clone(), range));
+        }
+        if ui
+            .button("Tone frequency…")
+            .on_hover_text("Measure the strongest tone in the selection or the whole clip to a fraction of a Hz")
+            .clicked()
+        {
+            let range = selection.clone().unwrap_or(0..self.view.sample_len);
+            self.measuring_frequency =
+                Some(FrequencyEstimate::new(&self.title, self.clip.clone(), range));
+        }
+        if let Some(range) = &selection
+            && ui
+                .button("Frequency stability…")
+                .on_hover_text("How far a reference tone in the selection drifts, in Hz and ppm")
+                .clicked()
+        {
+            self.measuring_stability = Some(StabilityMeasurement::new(
+                &self.title,
+                self.clip.clone(),
+                range.clone(),
+            ));
+        }
+        if let Some(range) = &selection
+            && ui
+                .button("Drift plot…")
+                .on_hover_text(
+                    "Follow the strongest carrier in the selection through the clip and plot its frequency",
+                )
+                .clicked()
+        {
+            self.plotting_drift = Some(DriftPlot::new(
+                &self.title,
+                self.clip.clone(),
+                range.clone(),
+            ));
+        }
+        if ui
+            .add_enabled(
+                self.finding_speech.is_none(),
+                egui::Button::new("Mark speech"),
+            )
+            .on_hover_text(
+                "Put markers where people start and stop talking, replacing any put there before",
+            )
+            .clicked()
+        {
+            self.find_speech(settings);
+        }
+        if ui
+            .add_enabled(self.finding_qsos.is_none(), egui::Button::new("Find QSOs"))
+            .on_hover_text(
+                "Split the clip into transmissions and QSOs, listed under it in the clip list",
+            )
+            .clicked()
+        {
+            let clip = self.clip.clone();
+            let (segments, vad) = (settings.segments.clone(), settings.vad.clone());
+            self.finding_qsos = Some(Job::spawn(move |progress| {
+                segments::segment_clip(&clip, &segments, &vad, progress)
+            }));
+        }
+        if self.waterfall.has_carriers() {
+            if ui
+                .button("Hide carriers")
+                .on_hover_text("Stop drawing the carriers' tracks on the waterfall")
+                .clicked()
+            {
+                self.waterfall.set_carriers(None);
+            }
+        } else if ui
+            .add_enabled(
+                self.tracking_carriers.is_none(),
+                egui::Button::new("Track carriers"),
+            )
+            .on_hover_text(
+                "Find the steady signals in the clip and draw where they went on the waterfall",
+            )
+            .clicked()
+        {
+            let clip = self.clip.clone();
+            let settings = settings.carriers.clone();
+            self.tracking_carriers = Some(Job::spawn(move |progress| {
+                carriers::track_clip(&clip, &settings, progress)
+            }));
+        }
+        if let Some(range) = &selection
+            && ui
+                .button("SNR…")
+                .on_hover_text("How far the selection's signal stands above the noise")
+                .clicked()
+        {
+            self.measuring_snr = Some(SnrMeasurement::new(
+                &self.title,
+                self.clip.clone(),
+                range.clone(),
+                settings.dsp.snr_bandwidth,
+            ));
+        }
+        if let Some(range) = &selection
+            && ui
+                .button("Distortion…")
+                .on_hover_text("THD, THD+N and the noise floor of a test tone in the selection")
+                .clicked()
+        {
+            self.measuring_distortion = Some(DistortionAnalysis::new(
+                &self.title,
+                &self.clip,
+                range.clone(),
+            ));
+        }
+        if let Some(range) = &selection
+            && ui
+                .button("IMD…")
+                .on_hover_text("Intermodulation products of a two-tone test in the selection")
+                .clicked()
+        {
+            self.measuring_imd = Some(ImdAnalysis::new(&self.title, &self.clip, range.clone()));
+        }
+        if let Some(range) = &selection
+            && ui
+                .button("Classify…")
+                .on_hover_text("Guess the mode of the signal in the selection")
+                .clicked()
+        {
+            self.classifying = Some(SignalClassification::new(
+                &self.title,
+                self.clip.clone(),
+                range.clone(),
+                &settings.decoders,
+            ));
+        }
+        if let Some(range) = &selection
+            && ui
+                .button("CW speed…")
+                .on_hover_text("How fast the CW in the selection was sent, from its keying")
+                .clicked()
+        {
+            self.measuring_cw_speed = Some(CwSpeedMeasurement::new(
+                &self.title,
+                &self.clip,
+                range.clone(),
+            ));
+        }
+    }
+
+    /// Look for speech in the background, to mark where it starts and ends
+    fn find_speech(&mut self, settings: &Settings) {
+        let clip = self.clip.clone();
+        let vad = settings.vad.clone();
+        self.finding_speech = Some(Job::spawn(move |progress| {
+            let mut detector = SpeechDetector::new(&vad, clip.read().sample_rate.0);
+            let mut position = 0;
+            loop {
+                let clip = clip.read();
+                let len = clip.samples.len();
+                let end = (position + SPEECH_CHUNK).min(len);
+                if position >= end {
+                    break;
+                }
+                detector.push(&clip.samples.range(position..end));
+                position = end;
+                progress(position as f32 / len as f32);
+            }
+            detector.finish()
+        }));
+    }
+
+    /// Mark the speech found, replacing any marked before
+    fn mark_speech(&mut self, ui: &Ui) {
+        let Some(finding) = self.finding_speech.take_if(|finding| finding.is_finished()) else {
+            if self.finding_speech.is_some() {
+                ui.ctx().request_repaint_after(ANALYSIS_POLL);
+            }
+            return;
+        };
+        let Ok(segments) = finding.join() else {
+            error!("Finding speech in {} failed", self.title);
+            return;
+        };
+        let markers = segments.into_iter().flat_map(|segment| {
+            [
+                Marker::new(segment.start, SPEECH_START),
+                Marker::new(segment.end, SPEECH_END),
+            ]
+        });
+        let replaced = |marker: &Marker| marker.label == SPEECH_START || marker.label == SPEECH_END;
+        if let Err(error) = self.clip.write().replace_markers(replaced, markers) {
+            error!("Unable to mark speech in {}: {}", self.title, error);
+        }
+    }
+
+    /// Keep the QSOs found, replacing any found before
+    fn keep_qsos(&mut self, ui: &Ui) {
+        let Some(finding) = self.finding_qsos.take_if(|finding| finding.is_finished()) else {
+            if self.finding_qsos.is_some() {
+                ui.ctx().request_repaint_after(ANALYSIS_POLL);
+            }
+            return;
+        };
+        let Ok(qsos) = finding.join() else {
+            error!("Finding QSOs in {} failed", self.title);
+            return;
+        };
+        if let Err(error) = self.clip.write().set_qsos(qsos) {
+            error!("Unable to save the QSOs in {}: {}", self.title, error);
+        }
+    }
+
+    /// Open the clip on part of it, zoomed to and selected
+    fn show_part(&mut self, range: &Range<usize>) {
+        self.open = true;
+        self.view.zoom_to(range);
+        self.timeline.selection = Some(Selection::from_range(range));
+    }
+
+    /// The clip's QSOs and their transmissions, as sub-clips that open the clip on them
+    fn show_qso_list(&mut self, ui: &mut Ui) {
+        let (qsos, sample_rate, started) = {
+            let clip = self.clip.read();
+            (
+                clip.metadata.qsos.clone(),
+                clip.sample_rate.0 as f64,
+                clip.metadata.started,
+            )
+        };
+        if qsos.is_empty() {
+            return;
+        }
+        let seconds = |position: usize| position as f64 / sample_rate;
+        // When it started, by the clock if the clip knows when it started
+        let at = |position: usize| match started {
+            Some(started) => {
+                let at =
+                    started + chrono::Duration::milliseconds((seconds(position) * 1000.0) as i64);
+                at.format("%H:%M:%SZ").to_string()
+            }
+            None => format_seconds(seconds(position)),
+        };
+        let mut shown = None;
+        let title = &self.title;
+        egui::CollapsingHeader::new(format!("{} QSOs", qsos.len()))
+            .id_salt(("qsos", title))
+            .show(ui, |ui| {
+                for (number, qso) in qsos.iter().enumerate() {
+                    let label = format!(
+                        "QSO {} at {}, {}, {} overs",
+                        number + 1,
+                        at(qso.range.start),
+                        format_seconds(seconds(qso.range.len())),
+                        qso.transmissions.len()
+                    );
+                    egui::CollapsingHeader::new(label)
+                        .id_salt(("qso", title, number))
+                        .show(ui, |ui| {
+                            if ui.button("Whole QSO").clicked() {
+                                shown = Some(qso.range.clone());
+                            }
+                            for (number, transmission) in qso.transmissions.iter().enumerate() {
+                                let label = format!(
+                                    "Over {} at {}, {}",
+                                    number + 1,
+                                    at(transmission.start),
+                                    format_seconds(seconds(transmission.len()))
+                                );
+                                if ui.button(label).clicked() {
+                                    shown = Some(transmission.clone());
+                                }
+                            }
+                        });
+                }
+            });
+        if let Some(range) = shown {
+            self.show_part(&range);
+        }
+    }
+
+    /// Hand the carriers found to the waterfall once they're all tracked
+    fn show_carriers(&mut self, ui: &Ui) {
+        let Some(tracking) = self
+            .tracking_carriers
+            .take_if(|tracking| tracking.is_finished())
+        else {
+            if self.tracking_carriers.is_some() {
+                ui.ctx().request_repaint_after(ANALYSIS_POLL);
+            }
+            return;
+        };
+        match tracking.join() {
+            Ok(carriers) => self.waterfall.set_carriers(Some(carriers)),
+            Err(_) => error!("Tracking carriers in {} failed", self.title),
+        }
+    }
+
+    /// Keep the result of a measurement as a marker
+    fn add_result_marker(&self, position: usize, label: Option<String>) {
+        let Some(label) = label else {
+            return;
+        };
+        if let Err(error) = self.clip.write().add_marker(Marker::new(position, label)) {
+            error!("Unable to add marker to {}: {}", self.title, error);
+        }
+    }
+
+    /// The callsign field, and who it belongs to if it's been looked up
+    fn show_callsign(&mut self, ui: &mut Ui, lookup: Option<&CallsignLookup>) {
+        ui.label("Call");
+        let response = ui.add(TextEdit::singleline(&mut self.callsign).desired_width(80.0));
+        if response.lost_focus() {
+            let callsign = self.callsign.trim().to_uppercase();
+            self.callsign = callsign.clone();
+            let callsign = (!callsign.is_empty()).then_some(callsign);
+            if let Err(error) = self.clip.write().set_callsign(callsign) {
+                error!("Unable to save callsign of {}: {}", self.title, error);
+            }
+        }
+        let Some(lookup) = lookup else {
+            return;
+        };
+        let Some(callsign) = self.clip.read().metadata.callsign.clone() else {
+            return;
+        };
+        match lookup.get(&callsign) {
+            Lookup::Pending => {
+                ui.spinner();
+                ui.ctx().request_repaint_after(LOOKUP_POLL);
+            }
+            Lookup::Found(info) => {
+                ui.label(info.summary())
+                    .on_hover_text(format!("Looked up {}", info.fetched.format("%Y-%m-%d")));
+            }
+            Lookup::NotFound => {
+                ui.weak("Not found");
+            }
+            Lookup::Failed(error) => {
+                ui.colored_label(ui.visuals().error_fg_color, "Lookup failed")
+                    .on_hover_text(error);
+            }
+        }
+    }
+
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        gpu_available: bool,
+        settings: &Settings,
+        lookup: Option<&CallsignLookup>,
+    ) {
+        let ctx = ui.ctx();
+
+        // TODO:
+        // Analysis - show window
+        // OpenClip - hold the transient data for GUI ie texture cache
+        let mut open = self.open;
+        Window::new(&self.title)
+            .constrain_to(ui.clip_rect())
+            .scroll(true)
+            .scroll_bar_visibility(ScrollBarVisibility::VisibleWhenNeeded)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                // Get the current screen real estate that we have to work with
+                self.view.width = ui.available_size().x.floor() as usize;
+                self.view.follow(self.clip.read().samples.len());
+
+                // Keep the playhead moving, and notice when playback runs out
+                if let Some(player) = &self.player {
+                    if player.is_finished() {
+                        self.stop();
+                    } else {
+                        self.timeline.playhead = Some(player.position());
+                        ui.ctx().request_repaint();
+                    }
+                }
+
+                // Show the timeline controls
+                ui.horizontal(|ui| {
+                    self.show_transport(ui);
+                    self.view.show_controls(ui);
+                    self.waterfall.show_readout(ui);
+                    let sample_rate = self.clip.read().sample_rate.0;
+                    let selection = self.timeline.selection.map(|s| s.range());
+                    self.view
+                        .show_zoom_presets(ui, sample_rate, selection.as_ref());
+                    ui.checkbox(&mut self.split, "Overview")
+                        .on_hover_text("Show the whole clip above, and click it to move around");
+                    ui.checkbox(&mut self.show_statistics, "Stats")
+                        .on_hover_text("Show the level and bandwidth of the selection");
+                    self.timeline.show_controls(ui);
+                    self.waterfall.show_controls(ui);
+                    let mut averaging = self.waterfall.averaging();
+                    if show_averaging_controls(ui, ("averaging", &self.title), &mut averaging) {
+                        self.averaging = Some(averaging);
+                    }
+                    ui.menu_button("Analyze", |ui| self.show_analyze_menu(ui, settings));
+                    if let Some(finding) = &self.finding_speech {
+                        ui.spinner().on_hover_text(format!(
+                            "Finding speech, {:.0}% done",
+                            finding.percent()
+                        ));
+                    }
+                    if let Some(tracking) = &self.tracking_carriers {
+                        ui.spinner().on_hover_text(format!(
+                            "Tracking carriers, {:.0}% done",
+                            tracking.percent()
+                        ));
+                    }
+                    if let Some(finding) = &self.finding_qsos {
+                        ui.spinner()
+                            .on_hover_text(format!("Finding QSOs, {:.0}% done", finding.percent()));
+                    }
+                    if ui
+                        .button("Satellite…")
+                        .on_hover_text(
+                            "Trace a satellite downlink's Doppler shift on the waterfall",
+                        )
+                        .clicked()
+                    {
+                        let clip = self.clip.read();
+                        self.tracing = Some(DopplerTrace::new(
+                            &self.title,
+                            clip.metadata.satellite.clone(),
+                            clip.metadata.dial_frequency,
+                            clip.metadata.started.is_some(),
+                        ));
+                    }
+                    self.show_callsign(ui, lookup);
+                    if ui.button("Export image…").clicked() {
+                        self.exporting = Some(ImageExport::new(
+                            &self.title,
+                            self.view.width,
+                            self.timeline.selection.is_some(),
+                        ));
+                    }
+                    if ui
+                        .button("Export data…")
+                        .on_hover_text("Save the waterfall's magnitudes as CSV or a NumPy array")
+                        .clicked()
+                    {
+                        self.exporting_data = Some(DataExport::new(
+                            &self.title,
+                            self.timeline.selection.is_some(),
+                        ));
+                    }
+                });
+                if self.show_statistics {
+                    ui.horizontal(|ui| {
+                        let selection = self.timeline.selection.map(|s| s.range());
+                        self.statistics.show(ui, &self.clip, selection.as_ref());
+                    });
+                }
+
+                if self.split {
+                    self.show_overview(ui);
+                }
+                self.waterfall.set_parameters(RowParameters::new(
+                    &settings.dsp,
+                    self.averaging.unwrap_or(settings.display.averaging),
+                ));
+                let samples = self.timeline.update_and_show(ui, &mut self.view);
+                let waterfall =
+                    self.waterfall
+                        .update_and_show(ui, &mut self.view, gpu_available, settings);
+                if !samples.hovered() && !waterfall.hovered() {
+                    self.view.cursor = None;
+                }
+            });
+        self.open = open;
+        if !self.open {
+            self.stop();
+        }
+
+        // Show the export dialog if open
+        if let Some(mut options) = self.exporting.take() {
+            let mut should_save = false;
+            let mut should_cancel = false;
+            options.show(
+                ui,
+                || {
+                    should_save = true;
+                },
+                || {
+                    should_cancel = true;
+                },
+            );
+            if should_save {
+                if let Err(error) = self.export_image(&options) {
+                    error!("Unable to export image of {}: {}", self.title, error);
+                }
+            } else if !should_cancel {
+                self.exporting = Some(options);
+            }
+        }
+
+        // Show the export data dialog if open
+        if let Some(mut options) = self.exporting_data.take() {
+            let mut should_save = false;
+            let mut should_cancel = false;
+            options.show(
+                ui,
+                || {
+                    should_save = true;
+                },
+                || {
+                    should_cancel = true;
+                },
+            );
+            if should_save {
+                if let Err(error) = self.export_data(&options) {
+                    error!("Unable to export data of {}: {}", self.title, error);
+                }
+            } else if !should_cancel {
+                self.exporting_data = Some(options);
+            }
+        }
+
+        // Show the calibrate dialog if open
+        if let Some(mut calibration) = self.calibrating.take() {
+            let mut should_save = false;
+            let mut should_cancel = false;
+            calibration.show(
+                ui,
+                || {
+                    should_save = true;
+                },
+                || {
+                    should_cancel = true;
+                },
+            );
+            if should_save {
+                let ppm = calibration.correction_ppm();
+                if let Err(error) = self.clip.write().set_frequency_correction(ppm) {
+                    error!("Unable to save calibration of {}: {}", self.title, error);
+                }
+                self.calibrated = ppm;
+            } else if !should_cancel {
+                self.calibrating = Some(calibration);
+            }
+        }
+
+        // Show the satellite pass dialog if open
+        if let Some(mut tracing) = self.tracing.take() {
+            let mut should_save = false;
+            let mut should_cancel = false;
+            tracing.show(
+                ui,
+                || {
+                    should_save = true;
+                },
+                || {
+                    should_cancel = true;
+                },
+            );
+            if should_save {
+                if let Err(error) = self.clip.write().set_satellite_pass(tracing.pass()) {
+                    error!("Unable to save satellite pass of {}: {}", self.title, error);
+                }
+            } else if !should_cancel {
+                self.tracing = Some(tracing);
+            }
+        }
+
+        // Show the SNR dialog if open
+        if let Some(mut measurement) = self.measuring_snr.take() {
+            let mut should_save = false;
+            let mut should_cancel = false;
+            measurement.show(
+                ui,
+                || {
+                    should_save = true;
+                },
+                || {
+                    should_cancel = true;
+                },
+            );
+            if should_save {
+                self.add_result_marker(measurement.start(), measurement.marker_label());
+            } else if !should_cancel {
+                self.measuring_snr = Some(measurement);
+            }
+        }
+
+        // Show the distortion dialog if open
+        if let Some(mut analysis) = self.measuring_distortion.take() {
+            let mut should_save = false;
+            let mut should_cancel = false;
+            analysis.show(
+                ui,
+                || {
+                    should_save = true;
+                },
+                || {
+                    should_cancel = true;
+                },
+            );
+            if should_save {
+                if let Some(label) = analysis.marker_label() {
+                    let marker = Marker::new(analysis.start(), label);
+                    if let Err(error) = self.clip.write().add_marker(marker) {
+                        error!(
+                            "Unable to add distortion marker to {}: {}",
+                            self.title, error
+                        );
+                    }
+                }
+            } else if !should_cancel {
+                self.measuring_distortion = Some(analysis);
+            }
+        }
+        if let Some(mut classification) = self.classifying.take() {
+            let mut should_save = false;
+            let mut should_cancel = false;
+            classification.show(
+                ui,
+                || {
+                    should_save = true;
+                },
+                || {
+                    should_cancel = true;
+                },
+            );
+            if should_save {
+                if let Some(label) = classification.marker_label() {
+                    let marker = Marker::new(classification.start(), label);
+                    if let Err(error) = self.clip.write().add_marker(marker) {
+                        error!(
+                            "Unable to add classification marker to {}: {}",
+                            self.title, error
+                        );
+                    }
+                }
+            } else if !should_cancel {
+                self.classifying = Some(classification);
+            }
+        }
+        if let Some(mut measurement) = self.measuring_cw_speed.take() {
+            let mut should_save = false;
+            let mut should_cancel = false;
+            measurement.show(
+                ui,
+                || {
+                    should_save = true;
+                },
+                || {
+                    should_cancel = true;
+                },
+            );
+            if should_save {
+                self.add_result_marker(measurement.start(), measurement.marker_label());
+            } else if !should_cancel {
+                self.measuring_cw_speed = Some(measurement);
+            }
+        }
+        if let Some(mut analysis) = self.measuring_imd.take() {
+            let mut should_save = false;
+            let mut should_cancel = false;
+            analysis.show(
+                ui,
+                || {
+                    should_save = true;
+                },
+                || {
+                    should_cancel = true;
+                },
+            );
+            if should_save {
+                self.add_result_marker(analysis.start(), analysis.marker_label());
+            } else if !should_cancel {
+                self.measuring_imd = Some(analysis);
+            }
+        }
+
+        // Show the tone frequency dialog if open
+        if let Some(mut estimate) = self.measuring_frequency.take() {
+            let mut should_save = false;
+            let mut should_cancel = false;
+            estimate.show(
+                ui,
+                || {
+                    should_save = true;
+                },
+                || {
+                    should_cancel = true;
+                },
+            );
+            if should_save {
+                self.add_result_marker(estimate.start(), estimate.marker_label());
+            } else if !should_cancel {
+                self.measuring_frequency = Some(estimate);
+            }
+        }
+
+        if let Some(mut plot) = self.plotting_drift.take() {
+            let mut should_save = false;
+            let mut should_cancel = false;
+            plot.show(
+                ui,
+                || {
+                    should_save = true;
+                },
+                || {
+                    should_cancel = true;
+                },
+            );
+            if should_save {
+                self.add_result_marker(plot.start(), plot.marker_label());
+            } else if !should_cancel {
+                self.plotting_drift = Some(plot);
+            }
+        }
+
+        if let Some(mut measurement) = self.measuring_stability.take() {
+            let mut should_save = false;
+            let mut should_cancel = false;
+            measurement.show(
+                ui,
+                || {
+                    should_save = true;
+                },
+                || {
+                    should_cancel = true;
+                },
+            );
+            if should_save {
+                self.add_result_marker(measurement.start(), measurement.marker_label());
+            } else if !should_cancel {
+                self.measuring_stability = Some(measurement);
+            }
+        }
+
+        self.mark_speech(ui);
+        self.keep_qsos(ui);
+        self.show_carriers(ui);
+        self.persist_view_state(ui);
+    }
+}
+
+/// An explorer for each of the session's clips
+pub struct OpenClips {
+    explorers: BTreeMap<ClipId, ClipExplorer>,
+    thumbnail_cache: PathBuf,
+    /// Marked on every waterfall
+    birdies: Vec<Birdie>,
+}
+
+impl OpenClips {
+    pub fn new(thumbnail_cache: PathBuf) -> Self {
+        Self {
+            explorers: Default::default(),
+            thumbnail_cache,
+            birdies: Vec::new(),
+        }
+    }
+
+    /// Open an explorer for any clip the session has that we don't yet, and close any for
+    /// clips it's let go of
+    pub fn sync(&mut self, clips: &BTreeMap<ClipId, Clip>, settings: &Settings) {
+        self.explorers.retain(|id, _| clips.contains_key(id));
+        for (id, clip) in clips {
+            if !self.explorers.contains_key(id) {
+                let mut explorer = ClipExplorer::new(clip.clone(), settings, &self.thumbnail_cache);
+                explorer.waterfall.set_birdies(self.birdies.clone());
+                self.explorers.insert(id.clone(), explorer);
+            }
+        }
+    }
+
+    /// Close a clip's explorer, for the next sync to open it again afresh
+    pub fn reopen(&mut self, id: &ClipId) {
+        self.explorers.remove(id);
+    }
+
+    /// Mark these birdies on every waterfall, now and as clips are opened
+    pub fn set_birdies(&mut self, birdies: Vec<Birdie>) {
+        for explorer in self.explorers.values_mut() {
+            explorer.waterfall.set_birdies(birdies.clone());
+        }
+        self.birdies = birdies;
+    }
+
+    pub fn show_editor_windows(
+        &mut self,
+        ui: &mut egui::Ui,
+        gpu_available: bool,
+        settings: &Settings,
+        lookup: Option<&CallsignLookup>,
+    ) {
+        for clipeditor in self.explorers.values_mut() {
+            clipeditor.show(ui, gpu_available, settings, lookup);
+        }
+    }
+
+    pub fn destroy_gl(&mut self, gl: &glow::Context) {
+        for clipeditor in self.explorers.values_mut() {
+            clipeditor.destroy_gl(gl);
+        }
+    }
+
+    /// A frequency correction measured in one of the explorers since last asked
+    pub fn take_calibration(&mut self) -> Option<f64> {
+        self.explorers
+            .values_mut()
+            .find_map(|explorer| explorer.calibrated.take())
+    }
+
+    pub fn show_clip_list(&mut self, ui: &mut egui::Ui) {
+        let mut first = true;
+        for (clip_id, clipeditor) in self.explorers.iter_mut() {
+            if !first {
+                ui.separator();
+            }
+            first = false;
+            ui.horizontal(|ui| {
+                clipeditor.thumbnail.show(ui);
+                if ui.button(clip_id.to_string()).clicked() {
+                    clipeditor.open = true;
+                }
+                if clipeditor.clip.read().metadata.device_lost {
+                    ui.label("⚠")
+                        .on_hover_text("Cut short when the input device went away");
+                }
+            });
+            clipeditor.show_qso_list(ui);
+        }
+    }
+}
+
+/// A time in seconds as minutes and seconds, like 2:05
+fn format_seconds(seconds: f64) -> String {
+    let seconds = seconds.max(0.0).round() as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
@@ -1,7 +1,7 @@
-use crate::data::audioinput::AudioInputDeviceBuilder;
 use crate::gui::View;
 use cpal::{SampleFormat, SupportedStreamConfigRange, available_hosts, traits::DeviceTrait};
-use egui::{ComboBox, Id, Modal, Ui};
+use egui::{Color32, ComboBox, Id, Modal, Ui};
+use hamshark::data::audioinput::AudioInputDeviceBuilder;
 
 impl View for AudioInputDeviceBuilder {
     fn show(&mut self, ui: &mut Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce()) {
@@ -10,7 +10,7 @@ impl View for AudioInputDeviceBuilder {
             ui.heading("Configure Audio Source");
 
             // Select Audio Host
-            let mut selected_host_id = self.host_id.clone();
+            let mut selected_host_id = self.host_id;
             ComboBox::new("audioinput_host", "Host")
                 .selected_text(selected_host_id.name())
                 .show_ui(ui, |ui| {
@@ -26,20 +26,22 @@ impl View for AudioInputDeviceBuilder {
             if self.device.is_none() {
                 self.device = self.get_default_device();
             }
-            let device = self.device.as_ref().unwrap().clone();
-            let current_selected_device_name = device
-                .name()
-                .expect("a device existed and should have a name");
+            let devices = self.input_devices().unwrap_or_else(|error| {
+                ui.colored_label(Color32::RED, error.to_string());
+                Vec::new()
+            });
+            let current_selected_device_name = self
+                .device
+                .as_ref()
+                .and_then(|device| device.name().ok())
+                .unwrap_or_default();
             let mut selected_device_name = current_selected_device_name.clone();
 
             // Show Audio Device Selector
             ComboBox::new("audioinput_device", "Device")
                 .selected_text(&selected_device_name)
                 .show_ui(ui, |ui| {
-                    for device in self.input_devices() {
-                        let device_name = device
-                            .name()
-                            .expect("There was a device, it ought to be named");
+                    for device_name in devices.iter().filter_map(|device| device.name().ok()) {
                         ui.selectable_value(
                             &mut selected_device_name,
                             device_name.clone(),
@@ -48,30 +50,32 @@ impl View for AudioInputDeviceBuilder {
                     }
                 });
             if selected_device_name != current_selected_device_name {
-                self.device = None;
-                for device in self.input_devices() {
-                    if device.name().expect("Device should have a name") == selected_device_name {
-                        self.device = Some(device);
-                        self.config = self.get_default_config();
-                        break;
-                    }
-                }
-                if self.device.is_none() {
-                    panic!("Unable to select a device by name");
-                }
+                self.device = devices
+                    .into_iter()
+                    .find(|device| device.name().is_ok_and(|name| name == selected_device_name));
+                self.config = self.get_default_config();
             }
 
             // If there is no selected config, then select the default one for the device.
             if self.config.is_none() {
                 self.config = self.get_default_config();
             }
-            let config = self.config.as_ref().unwrap().clone();
+            let (Some(device), Some(config)) = (self.device.clone(), self.config.clone()) else {
+                ui.label("No input device is available on this host.");
+                if ui.button("Cancel").clicked() {
+                    on_cancel();
+                }
+                return;
+            };
 
             let supported_configs_range: Vec<SupportedStreamConfigRange> = device
                 .supported_input_configs()
-                .expect("Selected device has no supported input configs")
-                .filter(|config| config.sample_format() == SampleFormat::F32)
-                .collect();
+                .map(|configs| {
+                    configs
+                        .filter(|config| config.sample_format() == SampleFormat::F32)
+                        .collect()
+                })
+                .unwrap_or_default();
             let selected_config_name = format!(
                 "Channels: {}, Rate: {}",
                 config.channels, config.sample_rate.0
@@ -106,7 +110,7 @@ impl View for AudioInputDeviceBuilder {
                 if ui.button("Cancel").clicked() {
                     on_cancel();
                 }
-            })
+            });
         });
     }
 }
@@ -0,0 +1,180 @@
+use crate::gui::View;
+use egui::{ComboBox, DragValue, Id, Modal, Ui};
+use hamshark::{
+    calibration::{self, Measurement, REFERENCES, Reference},
+    data::audio::Clip,
+};
+use std::ops::Range;
+
+/// How far either side of where the reference should be to look for it
+const SEARCH_HZ: f64 = 50.0;
+
+/// What the clip is measured against
+#[derive(Clone, Copy, PartialEq)]
+pub enum Against {
+    /// A station's carrier, tuned in upper sideband
+    Station(Reference),
+    /// An audio tone of a known frequency, like from a signal generator
+    Tone,
+}
+
+/// The calibrate dialog: find a reference in the clip and work out how far off it reads
+pub struct Calibration {
+    title: String,
+    clip: Clip,
+    /// The part of the clip to measure, the selection or all of it
+    range: Range<usize>,
+    pub against: Against,
+    /// The frequency of the tone, when measuring against one
+    pub tone_hz: f64,
+    /// What the receiver was tuned to, when measuring against a station
+    pub dial_khz: f64,
+    /// The last measurement, or why there wasn't one
+    pub result: Option<Result<Measurement, String>>,
+}
+
+impl Calibration {
+    pub fn new(title: &str, clip: Clip, range: Range<usize>) -> Self {
+        let dial = clip.read().metadata.dial_frequency_at(range.start);
+        // Start with the station closest to the dial, if we know it
+        let against = match dial {
+            Some(dial) => Against::Station(
+                *REFERENCES
+                    .iter()
+                    .min_by_key(|reference| reference.frequency.abs_diff(dial))
+                    .unwrap_or(&REFERENCES[0]),
+            ),
+            None => Against::Tone,
+        };
+        Self {
+            title: title.to_string(),
+            clip,
+            range,
+            against,
+            tone_hz: 1000.0,
+            dial_khz: dial.map_or(0.0, |hz| hz as f64 / 1000.0),
+            result: None,
+        }
+    }
+
+    /// The correction the last measurement came to, if it worked
+    pub fn correction_ppm(&self) -> Option<f64> {
+        match &self.result {
+            Some(Ok(measurement)) => Some(measurement.correction_ppm()),
+            _ => None,
+        }
+    }
+
+    fn measure(&self) -> Result<Measurement, String> {
+        let dial_hz = self.dial_khz * 1000.0;
+        let (expected_hz, audio_hz) = match self.against {
+            Against::Station(reference) => {
+                let expected = reference.frequency as f64;
+                (expected, expected - dial_hz)
+            }
+            Against::Tone => (self.tone_hz, self.tone_hz),
+        };
+        let nyquist = self.clip.read().sample_rate.0 as f64 / 2.0;
+        if audio_hz <= 0.0 || audio_hz >= nyquist {
+            return Err(format!(
+                "The reference would be at {:.0} Hz in the audio, outside of it. Check the dial.",
+                audio_hz
+            ));
+        }
+
+        let clip = self.clip.read();
+        let samples = clip.samples.range(self.range.clone());
+        let measured = calibration::measure_tone(&samples, clip.sample_rate.0, audio_hz, SEARCH_HZ)
+            .ok_or_else(|| "No tone found, try a longer stretch of the clip".to_string())?;
+        Ok(Measurement {
+            expected_hz,
+            measured_hz: match self.against {
+                Against::Station(_) => dial_hz + measured,
+                Against::Tone => measured,
+            },
+        })
+    }
+}
+
+impl View for Calibration {
+    fn show(&mut self, ui: &mut Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce()) {
+        Modal::new(Id::new(("Calibrate", &self.title))).show(ui.ctx(), |ui| {
+            ui.heading(format!("Calibrate against {}", self.title));
+
+            let selected = match self.against {
+                Against::Station(reference) => reference.name,
+                Against::Tone => "Audio tone",
+            };
+            ComboBox::new(("calibration_reference", &self.title), "Reference")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for reference in REFERENCES {
+                        ui.selectable_value(
+                            &mut self.against,
+                            Against::Station(reference),
+                            reference.name,
+                        );
+                    }
+                    ui.selectable_value(&mut self.against, Against::Tone, "Audio tone");
+                });
+            match self.against {
+                Against::Station(_) => {
+                    ui.horizontal(|ui| {
+                        ui.label("Dial");
+                        ui.add(
+                            DragValue::new(&mut self.dial_khz)
+                                .range(0.0..=300_000_000.0)
+                                .max_decimals(3)
+                                .speed(0.1)
+                                .suffix(" kHz"),
+                        )
+                        .on_hover_text("What the receiver was tuned to, in USB");
+                    });
+                }
+                Against::Tone => {
+                    ui.horizontal(|ui| {
+                        ui.label("Tone");
+                        ui.add(
+                            DragValue::new(&mut self.tone_hz)
+                                .range(1.0..=100_000.0)
+                                .max_decimals(3)
+                                .suffix(" Hz"),
+                        );
+                    });
+                }
+            }
+
+            if ui.button("Measure").clicked() {
+                self.result = Some(self.measure());
+            }
+            match &self.result {
+                Some(Ok(measurement)) => {
+                    ui.label(format!(
+                        "Expected {:.3} Hz, measured {:.3} Hz: correct by {:+.3} ppm",
+                        measurement.expected_hz,
+                        measurement.measured_hz,
+                        measurement.correction_ppm()
+                    ));
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                None => {}
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                let enabled = self.correction_ppm().is_some();
+                if ui
+                    .add_enabled(enabled, egui::Button::new("Save"))
+                    .on_hover_text("Correct frequencies from the current input, and read off this clip, from now on")
+                    .clicked()
+                {
+                    on_save();
+                }
+                if ui.button("Cancel").clicked() {
+                    on_cancel();
+                }
+            })
+        });
+    }
+}
@@ -0,0 +1,192 @@
+use crate::gui::View;
+use egui::{Grid, Id, Modal, ProgressBar, Ui};
+use hamshark::{
+    classify::{self, Classification},
+    config::ExternalDecoder,
+    data::audio::{BitDepth, Clip, WavClip},
+    progress,
+    resample::resample,
+};
+use std::{env, fs, ops::Range, path::PathBuf};
+
+/// Where selections are saved for decoders to open
+const DECODER_DIR: &str = "hamshark";
+
+/// The classify dialog: what mode the selection likely is, with decoders to open it in
+pub struct SignalClassification {
+    title: String,
+    clip: Clip,
+    range: Range<usize>,
+    /// The decoders set up in the settings, most likely mode first
+    decoders: Vec<ExternalDecoder>,
+    /// The classification, or why there wasn't one
+    result: Result<Classification, String>,
+    /// How the last launch went
+    launched: Option<Result<String, String>>,
+}
+
+impl SignalClassification {
+    pub fn new(title: &str, clip: Clip, range: Range<usize>, decoders: &[ExternalDecoder]) -> Self {
+        let result = {
+            let clip = clip.read();
+            let samples = clip.samples.range(range.clone());
+            classify::classify(&samples, clip.sample_rate.0)
+                .ok_or_else(|| "No signal found, select a longer stretch of one".to_string())
+        };
+        let mut decoders = decoders.to_vec();
+        if let Ok(classification) = &result {
+            let rank = |decoder: &ExternalDecoder| {
+                classification
+                    .modes
+                    .iter()
+                    .position(|(mode, _)| *mode == decoder.mode)
+            };
+            decoders.sort_by_key(rank);
+        }
+        Self {
+            title: title.to_string(),
+            clip,
+            range,
+            decoders,
+            result,
+            launched: None,
+        }
+    }
+
+    pub fn start(&self) -> usize {
+        self.range.start
+    }
+
+    /// What to label a marker at the start of the selection with
+    pub fn marker_label(&self) -> Option<String> {
+        let classification = self.result.as_ref().ok()?;
+        Some(format!("Looks like {}", classification.best()))
+    }
+
+    /// Save the selection where the decoder can read it and start the decoder on it
+    fn launch(&self, decoder: &ExternalDecoder) -> Result<String, String> {
+        let dir = env::temp_dir().join(DECODER_DIR);
+        fs::create_dir_all(&dir).map_err(|e| format!("Unable to create {}: {e}", dir.display()))?;
+        let clip = self.clip.read();
+        let path: PathBuf = dir.join(format!("{}-{}.wav", clip.id(), self.range.start));
+        let rate = decoder.sample_rate.unwrap_or(clip.sample_rate.0);
+        let spec = BitDepth::Int16.wav_spec(rate);
+        let samples = clip.samples.range(self.range.clone());
+        let samples = resample(&samples, 1, clip.sample_rate.0, rate, &progress::ignore);
+        WavClip::export_samples(&samples, &path, spec)
+            .map_err(|e| format!("Unable to save the selection: {e}"))?;
+        decoder
+            .launch(&path)
+            .map_err(|e| format!("Unable to start {}: {e}", decoder.program))?;
+        Ok(format!("Opened {} in {}", path.display(), decoder.program))
+    }
+
+    fn show_classification(&self, ui: &mut Ui, classification: &Classification) {
+        for (mode, likelihood) in &classification.modes {
+            ui.horizontal(|ui| {
+                ui.add_sized([80.0, 0.0], egui::Label::new(mode.to_string()));
+                ui.add(
+                    ProgressBar::new(*likelihood as f32)
+                        .desired_width(200.0)
+                        .text(format!("{:.0}%", likelihood * 100.0)),
+                );
+            });
+        }
+
+        let features = &classification.features;
+        ui.collapsing("Judged on", |ui| {
+            Grid::new(("classification", &self.title))
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Occupied");
+                    ui.label(format!(
+                        "{:.0} Hz, {:.0} to {:.0} Hz",
+                        features.bandwidth(),
+                        features.occupied.start,
+                        features.occupied.end
+                    ));
+                    ui.end_row();
+                    ui.label("Tones");
+                    let tones: Vec<String> =
+                        features.tones.iter().map(|t| format!("{t:.0}")).collect();
+                    ui.label(format!("{} Hz", tones.join(", ")));
+                    ui.end_row();
+                    ui.label("Envelope variation");
+                    ui.label(format!("{:.2}", features.envelope_variation));
+                    ui.end_row();
+                    ui.label("Keyed off");
+                    ui.label(format!("{:.0}% of the time", features.keyed * 100.0));
+                    ui.end_row();
+                    ui.label("Symbol rate");
+                    ui.label(match features.symbol_rate {
+                        Some(rate) => format!("{rate:.1} baud"),
+                        None => "None".to_string(),
+                    });
+                    ui.end_row();
+                    ui.label("CTCSS");
+                    ui.label(match features.subaudible_tone {
+                        Some(tone) => format!("{tone:.1} Hz"),
+                        None => "None".to_string(),
+                    });
+                    ui.end_row();
+                });
+        });
+    }
+}
+
+impl View for SignalClassification {
+    fn show(&mut self, ui: &mut Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce()) {
+        Modal::new(Id::new(("Classify", &self.title))).show(ui.ctx(), |ui| {
+            ui.heading(format!("Signal in {}", self.title));
+
+            match &self.result {
+                Ok(classification) => {
+                    self.show_classification(ui, classification);
+                    ui.separator();
+                    if self.decoders.is_empty() {
+                        ui.weak("Add decoders to the settings to open the selection in them");
+                    }
+                    let mut launch = None;
+                    for decoder in &self.decoders {
+                        if ui
+                            .button(format!("Open in {} ({})", decoder.program, decoder.mode))
+                            .clicked()
+                        {
+                            launch = Some(decoder.clone());
+                        }
+                    }
+                    if let Some(decoder) = launch {
+                        self.launched = Some(self.launch(&decoder));
+                    }
+                    match &self.launched {
+                        Some(Ok(message)) => {
+                            ui.label(message);
+                        }
+                        Some(Err(error)) => {
+                            ui.colored_label(ui.visuals().error_fg_color, error);
+                        }
+                        None => {}
+                    }
+                }
+                Err(error) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                if ui
+                    .add_enabled(self.result.is_ok(), egui::Button::new("Add marker"))
+                    .on_hover_text(
+                        "Note the likely mode with a marker at the start of the selection",
+                    )
+                    .clicked()
+                {
+                    on_save();
+                }
+                if ui.button("Close").clicked() {
+                    on_cancel();
+                }
+            })
+        });
+    }
+}
@@ -0,0 +1,131 @@
+use crate::gui::View;
+use egui::{Grid, Id, Modal, Ui};
+use hamshark::{
+    analysis::{self, CwSpeed, Durations},
+    data::audio::Clip,
+};
+use std::ops::Range;
+
+/// Element lengths as their mean and spread, and in dit units
+fn durations(durations: Option<Durations>, unit: f64) -> String {
+    match durations {
+        Some(d) => format!(
+            "{:.1} ± {:.1} ms, {:.2} units ({})",
+            d.mean,
+            d.std_dev,
+            d.mean / unit,
+            d.count
+        ),
+        None => "None".to_string(),
+    }
+}
+
+/// The CW speed dialog: how fast the CW in the selection was sent, from its keying
+pub struct CwSpeedMeasurement {
+    title: String,
+    start: usize,
+    /// The measurement, or why there wasn't one
+    result: Result<CwSpeed, String>,
+}
+
+impl CwSpeedMeasurement {
+    pub fn new(title: &str, clip: &Clip, range: Range<usize>) -> Self {
+        let clip = clip.read();
+        let samples = clip.samples.range(range.clone());
+        let result = analysis::cw_speed(&samples, clip.sample_rate.0)
+            .ok_or_else(|| "No keying found, select a few characters of CW".to_string());
+        Self {
+            title: title.to_string(),
+            start: range.start,
+            result,
+        }
+    }
+
+    /// What to label a marker at the start of the selection with
+    pub fn marker_label(&self) -> Option<String> {
+        let speed = self.result.as_ref().ok()?;
+        Some(format!("CW {:.1} WPM", speed.wpm))
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+}
+
+impl View for CwSpeedMeasurement {
+    fn show(&mut self, ui: &mut Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce()) {
+        Modal::new(Id::new(("CW Speed", &self.title))).show(ui.ctx(), |ui| {
+            ui.heading(format!("CW speed in {}", self.title));
+
+            match &self.result {
+                Ok(speed) => {
+                    let unit = speed.unit();
+                    Grid::new(("cw speed", &self.title))
+                        .num_columns(2)
+                        .show(ui, |ui| {
+                            ui.label("Speed");
+                            ui.label(format!("{:.1} WPM, {:.1} ms dit unit", speed.wpm, unit));
+                            ui.end_row();
+                            ui.label("Effective");
+                            ui.label(format!("{:.1} WPM", speed.effective_wpm))
+                                .on_hover_text(
+                                    "Counting the gaps between characters and words, lower \
+                                     than the speed when they're stretched out",
+                                );
+                            ui.end_row();
+                            ui.label("Tone");
+                            ui.label(format!("{:.0} Hz", speed.tone_hz));
+                            ui.end_row();
+                            ui.label("Dits");
+                            ui.label(durations(speed.dits, unit));
+                            ui.end_row();
+                            ui.label("Dahs");
+                            ui.label(durations(speed.dahs, unit));
+                            ui.end_row();
+                            ui.label("Element spaces");
+                            ui.label(durations(speed.element_spaces, unit));
+                            ui.end_row();
+                            ui.label("Character spaces");
+                            ui.label(durations(speed.character_spaces, unit));
+                            ui.end_row();
+                            ui.label("Word spaces");
+                            ui.label(durations(speed.word_spaces, unit));
+                            ui.end_row();
+                            ui.label("Weighting");
+                            ui.label(
+                                speed
+                                    .weighting()
+                                    .map_or("Unknown".to_string(), |w| format!("{:.2}", w)),
+                            )
+                            .on_hover_text("Dits over the spaces between elements, 1 is even");
+                            ui.end_row();
+                            ui.label("Dah ratio");
+                            ui.label(
+                                speed
+                                    .dah_ratio()
+                                    .map_or("Unknown".to_string(), |r| format!("{:.2}", r)),
+                            )
+                            .on_hover_text("Dahs over dits, 3 by the book");
+                            ui.end_row();
+                        });
+                }
+                Err(error) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                if ui
+                    .add_enabled(self.result.is_ok(), egui::Button::new("Add marker"))
+                    .on_hover_text("Note the speed with a marker at the start of the selection")
+                    .clicked()
+                {
+                    on_save();
+                }
+                if ui.button("Close").clicked() {
+                    on_cancel();
+                }
+            })
+        });
+    }
+}
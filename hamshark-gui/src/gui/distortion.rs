@@ -0,0 +1,151 @@
+use crate::gui::View;
+use egui::{Color32, Grid, Id, Modal, Pos2, Sense, Shape, Stroke, Ui, Vec2, pos2};
+use hamshark::{
+    analysis::{self, Distortion},
+    data::audio::Clip,
+};
+use std::ops::Range;
+
+const WIDTH: usize = 512;
+const HEIGHT: usize = 160;
+/// The quietest bin power shown, at the bottom of the plot
+const FLOOR_DB: f64 = -160.0;
+const TRACE_COLOR: Color32 = Color32::from_rgb(80, 255, 80);
+const HARMONIC_COLOR: Color32 = Color32::from_rgba_premultiplied(120, 60, 0, 120);
+
+/// A ratio of amplitudes as a percentage and in dB
+fn ratio(ratio: f64) -> String {
+    format!("{:.4}% ({:.1} dB)", ratio * 100.0, 20.0 * ratio.log10())
+}
+
+/// The distortion dialog: THD, THD+N and the noise floor of a test tone in the selection
+pub struct DistortionAnalysis {
+    title: String,
+    start: usize,
+    /// The measurement, or why there wasn't one
+    result: Result<Distortion, String>,
+}
+
+impl DistortionAnalysis {
+    pub fn new(title: &str, clip: &Clip, range: Range<usize>) -> Self {
+        let clip = clip.read();
+        let samples = clip.samples.range(range.clone());
+        let result = analysis::distortion(&samples, clip.sample_rate.0)
+            .ok_or_else(|| "No test tone found, select a longer stretch of one".to_string());
+        Self {
+            title: title.to_string(),
+            start: range.start,
+            result,
+        }
+    }
+
+    /// What to label a marker at the start of the selection with
+    pub fn marker_label(&self) -> Option<String> {
+        let distortion = self.result.as_ref().ok()?;
+        Some(format!(
+            "{:.0} Hz THD {:.3}%, THD+N {:.3}%",
+            distortion.fundamental_hz,
+            distortion.thd() * 100.0,
+            distortion.thd_n() * 100.0
+        ))
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The averaged spectrum, with where the harmonics should be
+    fn show_spectrum(ui: &mut Ui, distortion: &Distortion) {
+        let (rect, _) =
+            ui.allocate_exact_size(Vec2::new(WIDTH as f32, HEIGHT as f32), Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::BLACK);
+
+        let power = &distortion.spectrum.power;
+        let nyquist = distortion.spectrum.bin_hz * power.len() as f64;
+        let x = |hz: f64| rect.min.x + (hz / nyquist * WIDTH as f64) as f32;
+        let harmonics = distortion.harmonics.iter().map(|h| h.frequency);
+        for frequency in std::iter::once(distortion.fundamental_hz).chain(harmonics) {
+            painter.vline(
+                x(frequency),
+                rect.y_range(),
+                Stroke::new(1.0, HARMONIC_COLOR),
+            );
+        }
+
+        // Summarize the bins under each column by the loudest
+        let points: Vec<Pos2> = (0..WIDTH)
+            .map(|column| {
+                let first = column * power.len() / WIDTH;
+                let last = ((column + 1) * power.len() / WIDTH).max(first + 1);
+                let loudest = power[first..last].iter().copied().fold(0.0, f64::max);
+                let db = 10.0 * loudest.max(1e-30).log10();
+                let level = ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+                pos2(
+                    rect.min.x + column as f32,
+                    rect.min.y + ((HEIGHT - 1) as f64 * (1.0 - level)) as f32,
+                )
+            })
+            .collect();
+        painter.add(Shape::line(points, Stroke::new(1.0, TRACE_COLOR)));
+    }
+}
+
+impl View for DistortionAnalysis {
+    fn show(&mut self, ui: &mut Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce()) {
+        Modal::new(Id::new(("Distortion", &self.title))).show(ui.ctx(), |ui| {
+            ui.heading(format!("Distortion of the test tone in {}", self.title));
+
+            match &self.result {
+                Ok(distortion) => {
+                    Grid::new(("distortion", &self.title))
+                        .num_columns(2)
+                        .show(ui, |ui| {
+                            ui.label("Tone");
+                            ui.label(format!(
+                                "{:.2} Hz at {:.1} dBFS",
+                                distortion.fundamental_hz,
+                                10.0 * distortion.fundamental.log10()
+                            ));
+                            ui.end_row();
+                            ui.label("THD");
+                            ui.label(ratio(distortion.thd()));
+                            ui.end_row();
+                            ui.label("THD+N");
+                            ui.label(ratio(distortion.thd_n()));
+                            ui.end_row();
+                            ui.label("Noise floor");
+                            ui.label(format!("{:.1} dBFS/Hz", distortion.noise_floor_db()));
+                            ui.end_row();
+                            for harmonic in &distortion.harmonics {
+                                ui.label(format!("H{}", harmonic.order));
+                                ui.label(format!(
+                                    "{:.0} Hz at {:.1} dBc",
+                                    harmonic.frequency,
+                                    10.0 * (harmonic.power / distortion.fundamental).log10()
+                                ));
+                                ui.end_row();
+                            }
+                        });
+                    Self::show_spectrum(ui, distortion);
+                }
+                Err(error) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                if ui
+                    .add_enabled(self.result.is_ok(), egui::Button::new("Add marker"))
+                    .on_hover_text("Note THD and THD+N with a marker at the start of the selection")
+                    .clicked()
+                {
+                    on_save();
+                }
+                if ui.button("Close").clicked() {
+                    on_cancel();
+                }
+            })
+        });
+    }
+}
@@ -0,0 +1,100 @@
+use crate::gui::View;
+use egui::{DragValue, Id, Modal, TextEdit, Ui};
+use hamshark::{data::metadata::SatellitePass, satellite::Satellite};
+
+/// The satellite pass dialog: which satellite the clip heard, and on what downlink, so the
+/// waterfall can trace where its Doppler shift should put the signal
+pub struct DopplerTrace {
+    title: String,
+    pub tle: String,
+    pub downlink_khz: f64,
+    /// Whether the clip knows when it was recorded, which the trace needs
+    has_start: bool,
+}
+
+impl DopplerTrace {
+    pub fn new(
+        title: &str,
+        pass: Option<SatellitePass>,
+        dial: Option<u64>,
+        has_start: bool,
+    ) -> Self {
+        let (tle, downlink) = match pass {
+            Some(pass) => (pass.tle, Some(pass.downlink)),
+            None => (String::new(), dial),
+        };
+        Self {
+            title: title.to_string(),
+            tle,
+            downlink_khz: downlink.map_or(0.0, |hz| hz as f64 / 1000.0),
+            has_start,
+        }
+    }
+
+    /// What to save in the clip, or None to stop tracing
+    pub fn pass(&self) -> Option<SatellitePass> {
+        (!self.tle.trim().is_empty()).then(|| SatellitePass {
+            tle: self.tle.trim().to_string(),
+            downlink: (self.downlink_khz * 1000.0).round() as u64,
+        })
+    }
+}
+
+impl View for DopplerTrace {
+    fn show(&mut self, ui: &mut Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce()) {
+        Modal::new(Id::new(("Doppler Trace", &self.title))).show(ui.ctx(), |ui| {
+            ui.heading(format!("Satellite pass in {}", self.title));
+
+            ui.label("TLE, two lines or three with the name first");
+            ui.add(
+                TextEdit::multiline(&mut self.tle)
+                    .code_editor()
+                    .desired_rows(3)
+                    .desired_width(560.0),
+            );
+            ui.horizontal(|ui| {
+                ui.label("Downlink");
+                ui.add(
+                    DragValue::new(&mut self.downlink_khz)
+                        .range(0.0..=300_000_000.0)
+                        .max_decimals(3)
+                        .speed(0.1)
+                        .suffix(" kHz"),
+                );
+            });
+
+            let valid = match Satellite::from_tle(&self.tle) {
+                _ if self.tle.trim().is_empty() => true,
+                Ok(satellite) => {
+                    if let Some(name) = &satellite.name {
+                        ui.label(name);
+                    }
+                    true
+                }
+                Err(error) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+                    false
+                }
+            };
+            if !self.has_start {
+                ui.colored_label(
+                    ui.visuals().warn_fg_color,
+                    "The clip doesn't know when it was recorded, so there's nothing to trace",
+                );
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                if ui
+                    .add_enabled(valid, egui::Button::new("Save"))
+                    .on_hover_text("Leave the TLE empty to stop tracing")
+                    .clicked()
+                {
+                    on_save();
+                }
+                if ui.button("Cancel").clicked() {
+                    on_cancel();
+                }
+            })
+        });
+    }
+}
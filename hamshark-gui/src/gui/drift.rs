@@ -0,0 +1,258 @@
+use crate::gui::View;
+use egui::{Color32, DragValue, Id, Modal, Sense, Spinner, Stroke, Ui, Vec2, pos2};
+use hamshark::{
+    carriers::{self, DriftPoint},
+    data::audio::Clip,
+};
+use std::{
+    ops::Range,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const PLOT_SIZE: Vec2 = Vec2::new(560.0, 240.0);
+const POINT_COLOR: Color32 = Color32::from_rgb(255, 200, 0);
+const GRID_COLOR: Color32 = Color32::from_gray(60);
+/// Horizontal grid lines, about this many over the plot
+const GRID_LINES: f64 = 5.0;
+/// How often to check on the carrier being followed
+const TRACK_POLL: Duration = Duration::from_millis(200);
+
+/// The drift plot dialog: the strongest carrier in the selection followed through the whole
+/// clip, for watching ionospheric Doppler, an oscillator warming up or a satellite pass
+pub struct DriftPlot {
+    title: String,
+    clip: Clip,
+    /// Where the carrier was picked out
+    range: Range<usize>,
+    /// How far it may move between measurements
+    pub search_hz: f64,
+    /// How often it's measured
+    pub step_seconds: f64,
+    tracking: Option<JoinHandle<Vec<DriftPoint>>>,
+    points: Vec<DriftPoint>,
+    /// Why the last export failed
+    export_error: Option<String>,
+}
+
+impl DriftPlot {
+    pub fn new(title: &str, clip: Clip, range: Range<usize>) -> Self {
+        let mut plot = Self {
+            title: title.to_string(),
+            clip,
+            range,
+            search_hz: 20.0,
+            step_seconds: 1.0,
+            tracking: None,
+            points: Vec::new(),
+            export_error: None,
+        };
+        plot.track();
+        plot
+    }
+
+    pub fn start(&self) -> usize {
+        self.range.start
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.clip.read().sample_rate.0
+    }
+
+    /// Follow the carrier again, in the background
+    fn track(&mut self) {
+        let clip = self.clip.clone();
+        let range = self.range.clone();
+        let (search_hz, step_seconds) = (self.search_hz, self.step_seconds);
+        self.tracking = Some(thread::spawn(move || {
+            carriers::follow_carrier(&clip, range, search_hz, step_seconds)
+        }));
+    }
+
+    /// What to label a marker at the start of the selection with, once there's a drift rate
+    pub fn marker_label(&self) -> Option<String> {
+        let rate = carriers::drift_rate(&self.points, self.sample_rate())?;
+        let first = self.points.first()?;
+        Some(format!(
+            "Carrier {:.1} Hz drifting {:.2} Hz/min",
+            first.frequency,
+            rate * 60.0
+        ))
+    }
+
+    /// Ask where to save the points, and save them there
+    fn export(&mut self) {
+        let Some(path) = rfd::FileDialog::new()
+            .add_filter("CSV", &["csv"])
+            .set_file_name(format!("{}-drift.csv", self.title))
+            .save_file()
+        else {
+            return;
+        };
+        self.export_error = carriers::write_drift(&self.clip, &self.points, &path)
+            .err()
+            .map(|error| format!("Error exporting the drift: {error}"));
+    }
+
+    /// Frequency against time over the whole clip, with the point under the pointer
+    fn show_plot(&self, ui: &mut Ui) {
+        let (Some(low), Some(high)) = (
+            self.points
+                .iter()
+                .map(|p| p.frequency)
+                .min_by(f64::total_cmp),
+            self.points
+                .iter()
+                .map(|p| p.frequency)
+                .max_by(f64::total_cmp),
+        ) else {
+            return;
+        };
+        let (sample_rate, length) = {
+            let clip = self.clip.read();
+            (clip.sample_rate.0 as f64, clip.samples.len().max(1) as f64)
+        };
+        // Grid lines at a round number of Hz, with a little room above and below
+        let rough = (high - low).max(0.1) / GRID_LINES;
+        let magnitude = 10f64.powf(rough.log10().floor());
+        let grid_hz = [1.0, 2.0, 5.0, 10.0]
+            .into_iter()
+            .map(|step| step * magnitude)
+            .find(|step| *step >= rough)
+            .unwrap_or(10.0 * magnitude);
+        let bottom = (low / grid_hz).floor() * grid_hz;
+        let top = ((high / grid_hz).ceil() * grid_hz).max(bottom + grid_hz);
+
+        let (rect, hover) = ui.allocate_exact_size(PLOT_SIZE, Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::BLACK);
+        let x = |position: usize| rect.min.x + (position as f64 / length) as f32 * rect.width();
+        let y = |hz: f64| rect.min.y + ((top - hz) / (top - bottom)) as f32 * rect.height();
+        let text_color = ui.visuals().weak_text_color();
+        let font = egui::FontId::monospace(10.0);
+
+        let mut hz = bottom;
+        while hz <= top {
+            painter.hline(rect.x_range(), y(hz), Stroke::new(1.0, GRID_COLOR));
+            painter.text(
+                pos2(rect.min.x + 2.0, y(hz) + 1.0),
+                egui::Align2::LEFT_TOP,
+                format!("{hz:.1} Hz"),
+                font.clone(),
+                text_color,
+            );
+            hz += grid_hz;
+        }
+        for point in &self.points {
+            painter.circle_filled(
+                pos2(x(point.position), y(point.frequency)),
+                1.5,
+                POINT_COLOR,
+            );
+        }
+
+        if let Some(pointer) = hover.hover_pos()
+            && let Some(point) = self.points.iter().min_by(|a, b| {
+                (x(a.position) - pointer.x)
+                    .abs()
+                    .total_cmp(&(x(b.position) - pointer.x).abs())
+            })
+        {
+            painter.text(
+                pos2(rect.max.x - 4.0, rect.min.y + 4.0),
+                egui::Align2::RIGHT_TOP,
+                format!(
+                    "{:.1} s  {:.2} Hz  {:.1} dBFS",
+                    point.position as f64 / sample_rate,
+                    point.frequency,
+                    point.level_db
+                ),
+                font,
+                Color32::WHITE,
+            );
+        }
+    }
+}
+
+impl View for DriftPlot {
+    fn show(&mut self, ui: &mut Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce()) {
+        if let Some(tracking) = self.tracking.take_if(|t| t.is_finished()) {
+            self.points = tracking.join().unwrap_or_default();
+        }
+        Modal::new(Id::new(("Drift Plot", &self.title))).show(ui.ctx(), |ui| {
+            ui.heading(format!("Carrier drift in {}", self.title));
+
+            ui.add_enabled_ui(self.tracking.is_none(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Measure every");
+                    ui.add(
+                        DragValue::new(&mut self.step_seconds)
+                            .range(0.05..=60.0)
+                            .speed(0.05)
+                            .suffix(" s"),
+                    )
+                    .on_hover_text("Shorter follows faster changes, longer is more precise");
+                    ui.label("moving up to");
+                    ui.add(
+                        DragValue::new(&mut self.search_hz)
+                            .range(1.0..=1000.0)
+                            .suffix(" Hz"),
+                    )
+                    .on_hover_text("How far the carrier may move from one measurement to the next");
+                    if ui.button("Track").clicked() {
+                        self.track();
+                    }
+                });
+            });
+
+            if self.tracking.is_some() {
+                ui.horizontal(|ui| {
+                    ui.add(Spinner::new());
+                    ui.label("Following the carrier…");
+                });
+                ui.ctx().request_repaint_after(TRACK_POLL);
+            } else if self.points.is_empty() {
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    "No carrier found, select a stretch where it's clear of the noise",
+                );
+            } else {
+                self.show_plot(ui);
+                if let Some(rate) = carriers::drift_rate(&self.points, self.sample_rate()) {
+                    ui.label(format!(
+                        "Drifting {:.3} Hz/s, {:.2} Hz/min, over {} measurements",
+                        rate,
+                        rate * 60.0,
+                        self.points.len()
+                    ));
+                }
+            }
+            if let Some(error) = &self.export_error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                if ui
+                    .add_enabled(
+                        self.marker_label().is_some(),
+                        egui::Button::new("Add marker"),
+                    )
+                    .on_hover_text("Note the drift with a marker at the start of the selection")
+                    .clicked()
+                {
+                    on_save();
+                }
+                if ui.button("Close").clicked() {
+                    on_cancel();
+                }
+                if ui
+                    .add_enabled(!self.points.is_empty(), egui::Button::new("Export CSV…"))
+                    .on_hover_text("Save the time, frequency and level of each measurement")
+                    .clicked()
+                {
+                    self.export();
+                }
+            })
+        });
+    }
+}
@@ -0,0 +1,268 @@
+use crate::gui::View;
+use egui::{Color32, ColorImage, DragValue, Id, Modal, Ui};
+use png::{BitDepth, ColorType, Encoder};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error creating image file: {0}")]
+    Create(#[source] io::Error),
+    #[error("Error encoding PNG: {0}")]
+    Encoding(#[from] png::EncodingError),
+    #[error("Error writing data file: {0}")]
+    Write(#[source] io::Error),
+    #[error("Nothing to export")]
+    Empty,
+}
+
+/// Which part of the clip ends up in the image
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExportRange {
+    /// Exactly what's on screen
+    View,
+    /// The whole selection, at a width of our choosing
+    Selection,
+}
+
+/// The choices made in the export image dialog
+pub struct ImageExport {
+    /// The title of the clip being exported, to tell the dialogs apart
+    title: String,
+    pub range: ExportRange,
+    /// Width in pixels of the image when exporting the selection
+    pub width: usize,
+    pub waveform: bool,
+    pub waterfall: bool,
+    /// Whether there is a selection to export at all
+    has_selection: bool,
+}
+
+impl ImageExport {
+    pub fn new(title: &str, width: usize, has_selection: bool) -> Self {
+        Self {
+            title: title.to_string(),
+            range: ExportRange::View,
+            width,
+            waveform: true,
+            waterfall: true,
+            has_selection,
+        }
+    }
+}
+
+impl View for ImageExport {
+    fn show(&mut self, ui: &mut Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce()) {
+        Modal::new(Id::new(("Export Image", &self.title))).show(ui.ctx(), |ui| {
+            ui.heading(format!("Export {}", self.title));
+
+            ui.radio_value(&mut self.range, ExportRange::View, "Current view");
+            ui.add_enabled_ui(self.has_selection, |ui| {
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.range, ExportRange::Selection, "Selection");
+                    ui.add_enabled(
+                        self.range == ExportRange::Selection,
+                        DragValue::new(&mut self.width)
+                            .range(1..=16384)
+                            .suffix(" px"),
+                    )
+                    .on_hover_text("Width of the image. Capped at one pixel per sample.");
+                });
+            });
+
+            ui.checkbox(&mut self.waveform, "Waveform");
+            ui.checkbox(&mut self.waterfall, "Waterfall");
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                let enabled = self.waveform || self.waterfall;
+                if ui.add_enabled(enabled, egui::Button::new("Save")).clicked() {
+                    on_save();
+                }
+                if ui.button("Cancel").clicked() {
+                    on_cancel();
+                }
+            })
+        });
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DataFormat {
+    Csv,
+    /// NumPy's .npy
+    Npy,
+}
+
+impl DataFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DataFormat::Csv => "csv",
+            DataFormat::Npy => "npy",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            DataFormat::Csv => "CSV",
+            DataFormat::Npy => "NumPy array",
+        }
+    }
+}
+
+/// The choices made in the export spectrogram data dialog
+pub struct DataExport {
+    title: String,
+    pub range: ExportRange,
+    pub format: DataFormat,
+    has_selection: bool,
+}
+
+impl DataExport {
+    pub fn new(title: &str, has_selection: bool) -> Self {
+        Self {
+            title: title.to_string(),
+            range: if has_selection {
+                ExportRange::Selection
+            } else {
+                ExportRange::View
+            },
+            format: DataFormat::Csv,
+            has_selection,
+        }
+    }
+}
+
+impl View for DataExport {
+    fn show(&mut self, ui: &mut Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce()) {
+        Modal::new(Id::new(("Export Data", &self.title))).show(ui.ctx(), |ui| {
+            ui.heading(format!("Export spectrogram data of {}", self.title));
+
+            ui.radio_value(&mut self.range, ExportRange::View, "Current view");
+            ui.add_enabled_ui(self.has_selection, |ui| {
+                ui.radio_value(&mut self.range, ExportRange::Selection, "Selection");
+            });
+            ui.separator();
+            for format in [DataFormat::Csv, DataFormat::Npy] {
+                ui.radio_value(&mut self.format, format, format.description());
+            }
+            ui.label("Magnitudes are in dB, with times in seconds down the first column and frequencies in Hz across the first row");
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                if ui.button("Save").clicked() {
+                    on_save();
+                }
+                if ui.button("Cancel").clicked() {
+                    on_cancel();
+                }
+            })
+        });
+    }
+}
+
+/// Waterfall magnitudes with their axes, one row per FFT
+pub struct Spectrogram {
+    /// Seconds from the start of the clip to the middle of each row's FFT
+    pub times: Vec<f64>,
+    /// Audio frequency of each bin, in Hz
+    pub frequencies: Vec<f64>,
+    /// Magnitudes in dB, as the waterfall shows them
+    pub rows: Vec<Vec<f32>>,
+}
+
+impl Spectrogram {
+    pub fn write(&self, path: &Path, format: DataFormat) -> Result<(), Error> {
+        if self.rows.is_empty() {
+            return Err(Error::Empty);
+        }
+        let file = File::create(path).map_err(Error::Create)?;
+        let mut writer = BufWriter::new(file);
+        match format {
+            DataFormat::Csv => self.write_csv(&mut writer),
+            DataFormat::Npy => self.write_npy(&mut writer),
+        }
+        .and_then(|_| writer.flush())
+        .map_err(Error::Write)
+    }
+
+    fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        write!(writer, "time_s")?;
+        for frequency in &self.frequencies {
+            write!(writer, ",{}", frequency)?;
+        }
+        writeln!(writer)?;
+        for (time, row) in self.times.iter().zip(&self.rows) {
+            write!(writer, "{}", time)?;
+            for db in row {
+                write!(writer, ",{}", db)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// A float32 array with the same layout as the CSV, NaN in the corner
+    fn write_npy(&self, writer: &mut impl Write) -> io::Result<()> {
+        let shape = (self.rows.len() + 1, self.frequencies.len() + 1);
+        let mut header = format!(
+            "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}), }}",
+            shape.0, shape.1
+        );
+        // The magic, version and header length take 10 bytes, and the whole header is
+        // padded to a multiple of 64 ending in a newline
+        let padded = (10 + header.len() + 1).div_ceil(64) * 64 - 10;
+        header.extend(std::iter::repeat_n(' ', padded - header.len() - 1));
+        header.push('\n');
+        writer.write_all(b"\x93NUMPY\x01\x00")?;
+        writer.write_all(&(header.len() as u16).to_le_bytes())?;
+        writer.write_all(header.as_bytes())?;
+
+        writer.write_all(&f32::NAN.to_le_bytes())?;
+        for frequency in &self.frequencies {
+            writer.write_all(&(*frequency as f32).to_le_bytes())?;
+        }
+        for (time, row) in self.times.iter().zip(&self.rows) {
+            writer.write_all(&(*time as f32).to_le_bytes())?;
+            for db in row {
+                writer.write_all(&db.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Stack images on top of each other. They're all drawn through the same view so they share
+/// a width, but go with the widest just in case.
+pub fn stack(images: &[ColorImage]) -> ColorImage {
+    let width = images.iter().map(|image| image.width()).max().unwrap_or(0);
+    let height = images.iter().map(|image| image.height()).sum();
+    let mut pixels = Vec::with_capacity(width * height);
+    for image in images {
+        for row in image.pixels.chunks(image.width()) {
+            pixels.extend_from_slice(row);
+            pixels.resize(pixels.len() + width - row.len(), Color32::BLACK);
+        }
+    }
+    ColorImage::new([width, height], pixels)
+}
+
+pub fn write_png(path: &Path, image: &ColorImage) -> Result<(), Error> {
+    if image.width() == 0 || image.height() == 0 {
+        return Err(Error::Empty);
+    }
+    let file = File::create(path).map_err(Error::Create)?;
+    let mut encoder = Encoder::new(
+        BufWriter::new(file),
+        image.width() as u32,
+        image.height() as u32,
+    );
+    encoder.set_color(ColorType::Rgba);
+    encoder.set_depth(BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(image.as_raw())?;
+    writer.finish()?;
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use crate::gui::View;
+use egui::{DragValue, Id, Modal, Ui};
+use hamshark::{
+    analysis::{self, ToneEstimate},
+    data::audio::Clip,
+};
+use std::ops::Range;
+
+/// The tone frequency dialog: where the strongest tone in the selection is, to a fraction of
+/// a Hz, and on the air if the dial's known
+pub struct FrequencyEstimate {
+    title: String,
+    clip: Clip,
+    /// The part of the clip to measure, the selection or all of it
+    range: Range<usize>,
+    /// Only look for the tone between these audio frequencies
+    pub band: bool,
+    pub low_hz: f64,
+    pub high_hz: f64,
+    /// The last estimate, or why there wasn't one
+    pub result: Option<Result<ToneEstimate, String>>,
+}
+
+impl FrequencyEstimate {
+    pub fn new(title: &str, clip: Clip, range: Range<usize>) -> Self {
+        let nyquist = clip.read().sample_rate.0 as f64 / 2.0;
+        let mut estimate = Self {
+            title: title.to_string(),
+            clip,
+            range,
+            band: false,
+            low_hz: 0.0,
+            high_hz: nyquist,
+            result: None,
+        };
+        estimate.result = Some(estimate.measure());
+        estimate
+    }
+
+    pub fn start(&self) -> usize {
+        self.range.start
+    }
+
+    /// The tone's frequency on the air, from the dial and the clip's calibration
+    fn on_air(&self, audio_hz: f64) -> Option<f64> {
+        let clip = self.clip.read();
+        let dial = clip.metadata.dial_frequency_at(self.range.start)?;
+        Some(clip.metadata.corrected(dial as f64 + audio_hz))
+    }
+
+    /// What to label a marker at the start of the selection with, once there's an estimate
+    pub fn marker_label(&self) -> Option<String> {
+        let Some(Ok(estimate)) = &self.result else {
+            return None;
+        };
+        Some(match self.on_air(estimate.frequency) {
+            Some(rf) => format!("Tone {:.2} Hz, {:.2} Hz on air", estimate.frequency, rf),
+            None => format!("Tone {:.2} Hz", estimate.frequency),
+        })
+    }
+
+    fn measure(&self) -> Result<ToneEstimate, String> {
+        let clip = self.clip.read();
+        let samples = clip.samples.range(self.range.clone());
+        let band = self
+            .band
+            .then(|| self.low_hz.min(self.high_hz)..self.low_hz.max(self.high_hz));
+        analysis::estimate_frequency(&samples, clip.sample_rate.0, band)
+            .ok_or_else(|| "No tone found, try a longer stretch of the clip".to_string())
+    }
+}
+
+impl View for FrequencyEstimate {
+    fn show(&mut self, ui: &mut Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce()) {
+        Modal::new(Id::new(("Tone Frequency", &self.title))).show(ui.ctx(), |ui| {
+            ui.heading(format!("Tone frequency in {}", self.title));
+
+            let nyquist = self.clip.read().sample_rate.0 as f64 / 2.0;
+            ui.checkbox(&mut self.band, "Only look between")
+                .on_hover_text("Otherwise the strongest tone anywhere is measured");
+            ui.add_enabled_ui(self.band, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        DragValue::new(&mut self.low_hz)
+                            .range(0.0..=nyquist)
+                            .suffix(" Hz"),
+                    );
+                    ui.label("and");
+                    ui.add(
+                        DragValue::new(&mut self.high_hz)
+                            .range(0.0..=nyquist)
+                            .suffix(" Hz"),
+                    );
+                });
+            });
+
+            if ui.button("Measure").clicked() {
+                self.result = Some(self.measure());
+            }
+            match &self.result {
+                Some(Ok(estimate)) => {
+                    ui.label(format!(
+                        "{:.3} Hz at {:.1} dBFS",
+                        estimate.frequency, estimate.level_db
+                    ))
+                    .on_hover_text(format!(
+                        "{:.3} Hz between FFT bins, refined by the tone's phase",
+                        estimate.interpolated
+                    ));
+                    if let Some(rf) = self.on_air(estimate.frequency) {
+                        ui.label(format!("{:.3} kHz on the air", rf / 1000.0));
+                    }
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                None => {}
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                if ui
+                    .add_enabled(
+                        self.marker_label().is_some(),
+                        egui::Button::new("Add marker"),
+                    )
+                    .on_hover_text("Note the frequency with a marker at the start of the selection")
+                    .clicked()
+                {
+                    on_save();
+                }
+                if ui.button("Close").clicked() {
+                    on_cancel();
+                }
+            })
+        });
+    }
+}
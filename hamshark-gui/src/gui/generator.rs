@@ -0,0 +1,109 @@
+use egui::{Context, DragValue, Ui, Window};
+use hamshark::{
+    config::GeneratorSettings,
+    generator::{Signal, SignalGenerator},
+};
+
+/// Plays a tone or a two-tone test out of the default output device, for feeding a
+/// transmitter or checking a sound card loop
+pub struct Generator {
+    /// Frequencies and level, starting from the settings
+    settings: GeneratorSettings,
+    two_tone: bool,
+    /// What's playing, and the signal and level it was started with
+    playing: Option<(SignalGenerator, Signal, f64)>,
+    error: Option<String>,
+}
+
+impl Generator {
+    pub fn new(settings: &GeneratorSettings) -> Self {
+        Self {
+            settings: settings.clone(),
+            two_tone: false,
+            playing: None,
+            error: None,
+        }
+    }
+
+    fn signal(&self) -> Signal {
+        if self.two_tone {
+            Signal::TwoTone(
+                self.settings.two_tone_low_hz,
+                self.settings.two_tone_high_hz,
+            )
+        } else {
+            Signal::Tone(self.settings.tone_hz)
+        }
+    }
+
+    fn start(&mut self) {
+        let (signal, level) = (self.signal(), self.settings.level_db);
+        match SignalGenerator::start(signal, level) {
+            Ok(generator) => {
+                self.playing = Some((generator, signal, level));
+                self.error = None;
+            }
+            Err(error) => self.error = Some(error.to_string()),
+        }
+    }
+
+    fn show_controls(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.two_tone, false, "Tone");
+            ui.radio_value(&mut self.two_tone, true, "Two-tone")
+                .on_hover_text("Two tones of equal level, for measuring a transmitter's IMD");
+        });
+        ui.horizontal(|ui| {
+            let hz = |value| DragValue::new(value).range(10.0..=20_000.0).suffix(" Hz");
+            if self.two_tone {
+                ui.add(hz(&mut self.settings.two_tone_low_hz));
+                ui.label("and");
+                ui.add(hz(&mut self.settings.two_tone_high_hz));
+            } else {
+                ui.add(hz(&mut self.settings.tone_hz));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Level");
+            ui.add(
+                DragValue::new(&mut self.settings.level_db)
+                    .range(-60.0..=0.0)
+                    .speed(0.5)
+                    .suffix(" dBFS"),
+            )
+            .on_hover_text("The peak of the whole signal, so each of two tones is 6 dB below");
+        });
+    }
+
+    pub fn show(&mut self, ctx: &Context, open: &mut bool) {
+        Window::new("Signal Generator")
+            .open(open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                self.show_controls(ui);
+                let playing = self.playing.is_some();
+                ui.horizontal(|ui| {
+                    if ui.selectable_label(playing, "▶ Play").clicked() && !playing {
+                        self.start();
+                    }
+                    if ui.selectable_label(!playing, "⏹ Stop").clicked() {
+                        self.playing = None;
+                    }
+                });
+                if let Some(error) = &self.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+            });
+        if !*open {
+            self.playing = None;
+        }
+        // Follow changes made while playing
+        let (signal, level) = (self.signal(), self.settings.level_db);
+        if let Some((generator, playing, playing_level)) = &mut self.playing
+            && (*playing != signal || *playing_level != level)
+        {
+            generator.set_signal(signal, level);
+            (*playing, *playing_level) = (signal, level);
+        }
+    }
+}
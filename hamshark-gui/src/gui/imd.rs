@@ -0,0 +1,127 @@
+use crate::gui::View;
+use egui::{Grid, Id, Modal, Ui};
+use hamshark::{
+    analysis::{self, Intermodulation},
+    data::audio::Clip,
+};
+use std::ops::Range;
+
+/// dB, or what it means when there's nothing to measure
+fn db(db: Option<f64>) -> String {
+    db.map_or("Below the noise".to_string(), |db| format!("{:.1} dB", db))
+}
+
+/// The IMD dialog: the intermodulation products of a two-tone test in the selection
+pub struct ImdAnalysis {
+    title: String,
+    start: usize,
+    /// The measurement, or why there wasn't one
+    result: Result<Intermodulation, String>,
+}
+
+impl ImdAnalysis {
+    pub fn new(title: &str, clip: &Clip, range: Range<usize>) -> Self {
+        let clip = clip.read();
+        let samples = clip.samples.range(range.clone());
+        let result = analysis::intermodulation(&samples, clip.sample_rate.0)
+            .ok_or_else(|| "No two-tone test found, select a longer stretch of one".to_string());
+        Self {
+            title: title.to_string(),
+            start: range.start,
+            result,
+        }
+    }
+
+    /// What to label a marker at the start of the selection with
+    pub fn marker_label(&self) -> Option<String> {
+        let imd = self.result.as_ref().ok()?;
+        Some(match (imd.below_pep(3), imd.below_pep(5)) {
+            (Some(third), Some(fifth)) => {
+                format!("IMD3 {:.1} dB, IMD5 {:.1} dB below PEP", third, fifth)
+            }
+            (Some(third), None) => format!("IMD3 {:.1} dB below PEP", third),
+            _ => "IMD below the noise".to_string(),
+        })
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+}
+
+impl View for ImdAnalysis {
+    fn show(&mut self, ui: &mut Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce()) {
+        Modal::new(Id::new(("IMD", &self.title))).show(ui.ctx(), |ui| {
+            ui.heading(format!(
+                "Intermodulation of the two-tone test in {}",
+                self.title
+            ));
+
+            match &self.result {
+                Ok(imd) => {
+                    Grid::new(("imd", &self.title))
+                        .num_columns(3)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.label("Tones");
+                            ui.label(format!("{:.1} and {:.1} Hz", imd.tones.0, imd.tones.1));
+                            ui.label(format!(
+                                "{:.1} and {:.1} dBFS",
+                                10.0 * imd.tone_power.0.log10(),
+                                10.0 * imd.tone_power.1.log10()
+                            ));
+                            ui.end_row();
+                            ui.strong("Order");
+                            ui.strong("Relative to a tone");
+                            ui.strong("Below PEP");
+                            ui.end_row();
+                            for order in [3, 5, 7, 9] {
+                                if !imd.products.iter().any(|p| p.order == order) {
+                                    continue;
+                                }
+                                ui.label(format!("IMD{}", order));
+                                ui.label(db(imd.dbc(order)));
+                                ui.label(db(imd.below_pep(order)));
+                                ui.end_row();
+                            }
+                        });
+                    ui.collapsing("Products", |ui| {
+                        for product in &imd.products {
+                            ui.label(format!(
+                                "Order {} at {:.1} Hz: {}",
+                                product.order,
+                                product.frequency,
+                                match product.power {
+                                    power if power > 0.0 => {
+                                        format!("{:.1} dBFS", 10.0 * power.log10())
+                                    }
+                                    _ => "below the noise".to_string(),
+                                }
+                            ));
+                        }
+                    });
+                    ui.weak(format!(
+                        "Noise floor {:.1} dBFS/Hz",
+                        10.0 * imd.noise_density.log10()
+                    ));
+                }
+                Err(error) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                if ui
+                    .add_enabled(self.result.is_ok(), egui::Button::new("Add marker"))
+                    .on_hover_text("Note the IMD with a marker at the start of the selection")
+                    .clicked()
+                {
+                    on_save();
+                }
+                if ui.button("Close").clicked() {
+                    on_cancel();
+                }
+            })
+        });
+    }
+}
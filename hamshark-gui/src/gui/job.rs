@@ -0,0 +1,35 @@
+use hamshark::progress::{Meter, Progress};
+use std::{
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
+
+/// Work on a thread of its own that says how far it's got
+pub struct Job<T> {
+    meter: Arc<Meter>,
+    thread: JoinHandle<T>,
+}
+
+impl<T: Send + 'static> Job<T> {
+    pub fn spawn(work: impl FnOnce(Progress) -> T + Send + 'static) -> Self {
+        let meter = Arc::new(Meter::default());
+        let thread = thread::spawn({
+            let meter = meter.clone();
+            move || work(&|fraction| meter.report(fraction))
+        });
+        Self { meter, thread }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    pub fn join(self) -> thread::Result<T> {
+        self.thread.join()
+    }
+
+    /// How far it's got, as a percentage
+    pub fn percent(&self) -> f32 {
+        self.meter.fraction() * 100.0
+    }
+}
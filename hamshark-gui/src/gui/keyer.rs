@@ -0,0 +1,140 @@
+use egui::{Button, Context, DragValue, ProgressBar, TextEdit, Ui, Window};
+use hamshark::{
+    config::KeyerSettings,
+    keyer::{self, Keyer},
+    session::Session,
+};
+
+/// What keyed clips are saved at when there's no input to match
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+
+/// Sends typed text as CW sidetone out of the default output device, or saves it into the
+/// session as a clip, for code practice and for trying the CW analysis on known code
+pub struct CwKeyer {
+    /// Speed, pitch and level, starting from the settings
+    settings: KeyerSettings,
+    text: String,
+    playing: Option<Keyer>,
+    /// What happened last, and whether it went wrong
+    message: Option<(String, bool)>,
+}
+
+impl CwKeyer {
+    pub fn new(settings: &KeyerSettings) -> Self {
+        Self {
+            settings: settings.clone(),
+            text: String::new(),
+            playing: None,
+            message: None,
+        }
+    }
+
+    fn show_controls(&mut self, ui: &mut Ui) {
+        ui.add(
+            TextEdit::multiline(&mut self.text)
+                .hint_text("CQ CQ DE …")
+                .desired_rows(3),
+        );
+        ui.horizontal(|ui| {
+            ui.add(
+                DragValue::new(&mut self.settings.wpm)
+                    .range(5.0..=60.0)
+                    .speed(0.2)
+                    .suffix(" WPM"),
+            );
+            ui.add(
+                DragValue::new(&mut self.settings.tone_hz)
+                    .range(200.0..=2000.0)
+                    .suffix(" Hz"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Rise time");
+            ui.add(
+                DragValue::new(&mut self.settings.rise_ms)
+                    .range(0.0..=20.0)
+                    .speed(0.1)
+                    .suffix(" ms"),
+            )
+            .on_hover_text("How long each element fades in and out. Too short and it clicks.");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Level");
+            ui.add(
+                DragValue::new(&mut self.settings.level_db)
+                    .range(-60.0..=0.0)
+                    .speed(0.5)
+                    .suffix(" dBFS"),
+            );
+        });
+    }
+
+    fn save(&mut self, session: &mut Session) {
+        let sample_rate = session
+            .configuration()
+            .map_or(DEFAULT_SAMPLE_RATE, |input| input.config.sample_rate.0);
+        let samples = keyer::key(&self.text, &self.settings, sample_rate);
+        self.message = Some(match session.add_samples(&samples, sample_rate) {
+            Ok(id) => (format!("Saved as {}", id), false),
+            Err(error) => (error.to_string(), true),
+        });
+    }
+
+    pub fn show(&mut self, ctx: &Context, open: &mut bool, session: &mut Session) {
+        Window::new("CW Keyer")
+            .open(open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                self.show_controls(ui);
+                let empty = self.text.trim().is_empty();
+                ui.horizontal(|ui| {
+                    let playing = self.playing.is_some();
+                    if ui
+                        .add_enabled(!empty && !playing, Button::new("▶ Send"))
+                        .clicked()
+                    {
+                        match Keyer::start(&self.text, &self.settings) {
+                            Ok(keyer) => {
+                                self.playing = Some(keyer);
+                                self.message = None;
+                            }
+                            Err(error) => self.message = Some((error.to_string(), true)),
+                        }
+                    }
+                    if ui.add_enabled(playing, Button::new("⏹ Stop")).clicked() {
+                        self.playing = None;
+                    }
+                    if ui
+                        .add_enabled(!empty, Button::new("Save as clip"))
+                        .on_hover_text("Add it to the session, to open like a recording")
+                        .clicked()
+                    {
+                        self.save(session);
+                    }
+                });
+                if let Some(keyer) = &self.playing {
+                    ui.add(ProgressBar::new(keyer.progress()));
+                }
+                match &self.message {
+                    Some((message, true)) => {
+                        ui.colored_label(ui.visuals().error_fg_color, message);
+                    }
+                    Some((message, false)) => {
+                        ui.label(message);
+                    }
+                    None => {}
+                }
+            });
+        if !*open
+            || self
+                .playing
+                .as_ref()
+                .is_some_and(|keyer| keyer.is_finished())
+        {
+            self.playing = None;
+        }
+        if self.playing.is_some() {
+            ctx.request_repaint();
+        }
+    }
+}
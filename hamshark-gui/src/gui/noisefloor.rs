@@ -0,0 +1,205 @@
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use egui::{Color32, Context, DragValue, Pos2, Sense, Shape, Stroke, Ui, Vec2, Window, pos2};
+use hamshark::{
+    config::NoiseFloorSettings,
+    noisefloor::{NoiseFloorLogger, NoiseFloorReading},
+};
+use std::time::Duration;
+
+const PLOT_SIZE: Vec2 = Vec2::new(560.0, 240.0);
+const TRACE_COLOR: Color32 = Color32::from_rgb(0, 200, 255);
+const GRID_COLOR: Color32 = Color32::from_gray(60);
+/// Level between horizontal grid lines
+const GRID_DB: f64 = 5.0;
+/// Time between vertical grid lines, the shortest that leaves no more than `MAX_TIME_LINES`
+const TIME_STEPS_MINUTES: [i64; 7] = [1, 5, 15, 60, 180, 360, 1440];
+const MAX_TIME_LINES: i64 = 8;
+/// How often to look for new readings
+const READING_POLL: Duration = Duration::from_secs(1);
+
+/// Plots the session's noise floor log against the time of day, so interference that comes
+/// and goes, like solar inverters in daylight or a TV in the evening, stands out
+pub struct NoiseFloorPlot {
+    /// The settings being edited, applied when asked
+    edited: NoiseFloorSettings,
+}
+
+impl NoiseFloorPlot {
+    pub fn new(settings: &NoiseFloorSettings) -> Self {
+        Self {
+            edited: settings.clone(),
+        }
+    }
+
+    fn show_controls(&mut self, ui: &mut Ui) {
+        ui.checkbox(&mut self.edited.enabled, "Log the noise floor");
+        ui.horizontal(|ui| {
+            ui.label("Between");
+            let hz = |value| DragValue::new(value).range(0.0..=24_000.0).suffix(" Hz");
+            ui.add(hz(&mut self.edited.low_hz));
+            ui.label("and");
+            ui.add(hz(&mut self.edited.high_hz));
+            ui.label("every");
+            ui.add(
+                DragValue::new(&mut self.edited.interval_seconds)
+                    .range(1..=3600)
+                    .suffix(" s"),
+            );
+        })
+        .response
+        .on_hover_text("Pick a stretch of the band that's usually clear of signals");
+    }
+
+    /// Level against time, with the reading under the pointer
+    fn show_plot(ui: &mut Ui, readings: &[&NoiseFloorReading], interval_seconds: u64) {
+        let (Some(first), Some(last)) = (readings.first(), readings.last()) else {
+            return;
+        };
+        let start = first.time;
+        let span = (last.time - start).max(TimeDelta::minutes(1));
+        let (min_db, max_db) = readings
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(min, max), reading| {
+                (min.min(reading.level_db), max.max(reading.level_db))
+            });
+        let top = (max_db / GRID_DB).ceil() * GRID_DB;
+        let bottom = ((min_db / GRID_DB).floor() * GRID_DB).min(top - GRID_DB);
+
+        let (rect, hover) = ui.allocate_exact_size(PLOT_SIZE, Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::BLACK);
+        let x = |time: DateTime<Utc>| {
+            rect.min.x
+                + ((time - start).as_seconds_f64() / span.as_seconds_f64()) as f32 * rect.width()
+        };
+        let y = |db: f64| rect.min.y + ((top - db) / (top - bottom)) as f32 * rect.height();
+        let text_color = ui.visuals().weak_text_color();
+        let font = egui::FontId::monospace(10.0);
+
+        let step = TIME_STEPS_MINUTES
+            .into_iter()
+            .find(|minutes| span.num_minutes() / minutes < MAX_TIME_LINES)
+            .unwrap_or(1440);
+        let mut line = start
+            .duration_trunc(TimeDelta::minutes(step))
+            .unwrap_or(start)
+            + TimeDelta::minutes(step);
+        while line <= start + span {
+            painter.vline(x(line), rect.y_range(), Stroke::new(1.0, GRID_COLOR));
+            painter.text(
+                pos2(x(line) + 2.0, rect.max.y - 2.0),
+                egui::Align2::LEFT_BOTTOM,
+                line.format("%H:%MZ").to_string(),
+                font.clone(),
+                text_color,
+            );
+            line += TimeDelta::minutes(step);
+        }
+        let mut db = bottom;
+        while db <= top {
+            painter.hline(rect.x_range(), y(db), Stroke::new(1.0, GRID_COLOR));
+            painter.text(
+                pos2(rect.min.x + 2.0, y(db) + 1.0),
+                egui::Align2::LEFT_TOP,
+                format!("{db:.0} dBFS"),
+                font.clone(),
+                text_color,
+            );
+            db += GRID_DB;
+        }
+
+        // Break the trace where nothing was recorded for a while, rather than bridge the gap
+        let gap = TimeDelta::seconds(2 * interval_seconds.max(1) as i64);
+        let mut run: Vec<Pos2> = Vec::new();
+        for (index, reading) in readings.iter().enumerate() {
+            if index > 0 && reading.time - readings[index - 1].time > gap {
+                painter.add(Shape::line(
+                    std::mem::take(&mut run),
+                    Stroke::new(1.5, TRACE_COLOR),
+                ));
+            }
+            run.push(pos2(x(reading.time), y(reading.level_db)));
+        }
+        if run.len() == 1 {
+            painter.circle_filled(run[0], 1.5, TRACE_COLOR);
+        }
+        painter.add(Shape::line(run, Stroke::new(1.5, TRACE_COLOR)));
+
+        if let Some(pointer) = hover.hover_pos()
+            && let Some(reading) = readings.iter().min_by(|a, b| {
+                (x(a.time) - pointer.x)
+                    .abs()
+                    .total_cmp(&(x(b.time) - pointer.x).abs())
+            })
+        {
+            painter.text(
+                pos2(rect.max.x - 4.0, rect.min.y + 4.0),
+                egui::Align2::RIGHT_TOP,
+                format!(
+                    "{}  {:.1} dBFS",
+                    reading.time.format("%Y-%m-%d %H:%M:%SZ"),
+                    reading.level_db
+                ),
+                font,
+                Color32::WHITE,
+            );
+        }
+    }
+
+    /// Returns whether the settings were changed, to apply to the session
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        open: &mut bool,
+        settings: &mut NoiseFloorSettings,
+        logger: Option<&NoiseFloorLogger>,
+    ) -> bool {
+        let mut changed = false;
+        Window::new("Noise Floor")
+            .open(open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                self.show_controls(ui);
+                if ui
+                    .add_enabled(*settings != self.edited, egui::Button::new("Apply"))
+                    .clicked()
+                {
+                    *settings = self.edited.clone();
+                    changed = true;
+                }
+
+                let Some(logger) = logger else {
+                    ui.label("Not logging");
+                    return;
+                };
+                // Readings in another band can't be compared with these, so they're left out
+                let all = logger.readings();
+                let readings: Vec<&NoiseFloorReading> = all
+                    .iter()
+                    .filter(|reading| {
+                        (reading.band.start - settings.low_hz.min(settings.high_hz)).abs() < 1.0
+                            && (reading.band.end - settings.low_hz.max(settings.high_hz)).abs()
+                                < 1.0
+                    })
+                    .collect();
+                match readings.last() {
+                    Some(latest) => {
+                        Self::show_plot(ui, &readings, settings.interval_seconds);
+                        ui.label(format!(
+                            "{} readings, the latest {:.1} dBFS at {}",
+                            readings.len(),
+                            latest.level_db,
+                            latest.time.format("%H:%M:%SZ")
+                        ));
+                    }
+                    None => {
+                        ui.label("No readings in this band yet, they're taken while recording");
+                    }
+                }
+            });
+        if logger.is_some() && *open {
+            ctx.request_repaint_after(READING_POLL);
+        }
+        changed
+    }
+}
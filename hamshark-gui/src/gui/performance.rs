@@ -0,0 +1,152 @@
+use egui::{Align2, Area, Context, Frame, Grid, Id, RichText};
+use hamshark::{pipeline::Pipeline, tools::WRITER_ELEMENT_NAME};
+use std::time::{Duration, Instant};
+
+/// How often the write throughput is worked out again
+const THROUGHPUT_PERIOD: Duration = Duration::from_secs(1);
+/// How many frames the frame times are smoothed over
+const FRAME_SMOOTHING: f32 = 30.0;
+/// A gap between frames longer than this is the overlay having been closed, not a slow frame
+const FRAME_GAP: Duration = Duration::from_secs(1);
+/// How often to paint again to keep the numbers current
+const REFRESH: Duration = Duration::from_millis(250);
+
+/// A corner of the window showing how the GUI and the recording are keeping up, with the
+/// numbers to copy into a bug report
+#[derive(Default)]
+pub struct PerformanceOverlay {
+    last_frame: Option<Instant>,
+    /// Smoothed seconds between frames, and spent drawing them
+    frame_interval: f32,
+    frame_cpu: f32,
+    /// What the clip writer had written at the last throughput measurement, and when
+    written: Option<(usize, Instant)>,
+    /// Samples written per second over the last measurement
+    throughput: Option<f64>,
+}
+
+impl PerformanceOverlay {
+    fn time_frame(&mut self, cpu_usage: Option<f32>) {
+        let now = Instant::now();
+        let smooth = |value: &mut f32, sample: f32| {
+            *value = if *value == 0.0 {
+                sample
+            } else {
+                *value + (sample - *value) / FRAME_SMOOTHING
+            }
+        };
+        if let Some(last) = self.last_frame
+            && now - last < FRAME_GAP
+        {
+            smooth(&mut self.frame_interval, (now - last).as_secs_f32());
+        }
+        if let Some(cpu) = cpu_usage {
+            smooth(&mut self.frame_cpu, cpu);
+        }
+        self.last_frame = Some(now);
+    }
+
+    fn measure_throughput(&mut self, pipeline: Option<&Pipeline>) {
+        let Some(writer) = pipeline.and_then(|pipeline| {
+            pipeline
+                .elements()
+                .iter()
+                .find(|e| e.name() == WRITER_ELEMENT_NAME)
+        }) else {
+            self.written = None;
+            self.throughput = None;
+            return;
+        };
+        let now = Instant::now();
+        let processed = writer.processed();
+        match self.written {
+            // A new recording starts counting again
+            Some((written, at)) if processed >= written => {
+                if now - at >= THROUGHPUT_PERIOD {
+                    let seconds = (now - at).as_secs_f64();
+                    self.throughput = Some((processed - written) as f64 / seconds);
+                    self.written = Some((processed, now));
+                }
+            }
+            _ => self.written = Some((processed, now)),
+        }
+    }
+
+    /// What's shown, as labels and values
+    fn rows(&self, pipeline: Option<&Pipeline>, bytes_per_sample: u16) -> Vec<(String, String)> {
+        let ms = |seconds: f64| format!("{:.2} ms", seconds * 1000.0);
+        let mut rows = vec![
+            ("Frame interval".to_string(), ms(self.frame_interval as f64)),
+            ("Frame CPU time".to_string(), ms(self.frame_cpu as f64)),
+        ];
+        let Some(pipeline) = pipeline else {
+            rows.push(("Pipeline".to_string(), "Not recording".to_string()));
+            return rows;
+        };
+        for element in pipeline.elements() {
+            if let (Some(interval), Some(jitter)) =
+                (element.block_interval(), element.block_jitter())
+            {
+                rows.push((
+                    format!("{} blocks", element.name()),
+                    format!(
+                        "every {} ± {}",
+                        ms(interval.as_secs_f64()),
+                        ms(jitter.as_secs_f64())
+                    ),
+                ));
+            }
+            if let Some(fill) = element.fill() {
+                rows.push((
+                    format!("{} fill", element.name()),
+                    format!("{:.1}%", fill * 100.0),
+                ));
+            }
+        }
+        rows.push((
+            "Write throughput".to_string(),
+            match self.throughput {
+                Some(samples) => format!(
+                    "{:.0} samples/s, {:.1} KiB/s",
+                    samples,
+                    samples * bytes_per_sample as f64 / 1024.0
+                ),
+                None => "Measuring".to_string(),
+            },
+        ));
+        rows
+    }
+
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        cpu_usage: Option<f32>,
+        pipeline: Option<&Pipeline>,
+        bytes_per_sample: u16,
+    ) {
+        self.time_frame(cpu_usage);
+        self.measure_throughput(pipeline);
+        let rows = self.rows(pipeline, bytes_per_sample);
+        Area::new(Id::new("performance overlay"))
+            .anchor(Align2::RIGHT_BOTTOM, [-8.0, -32.0])
+            .show(ctx, |ui| {
+                Frame::popup(ui.style()).show(ui, |ui| {
+                    Grid::new("performance").num_columns(2).show(ui, |ui| {
+                        for (label, value) in &rows {
+                            ui.label(label);
+                            ui.label(RichText::new(value).monospace());
+                            ui.end_row();
+                        }
+                    });
+                    if ui.button("Copy").clicked() {
+                        let text: Vec<String> = rows
+                            .iter()
+                            .map(|(label, value)| format!("{}: {}", label, value))
+                            .collect();
+                        ctx.copy_text(text.join("\n"));
+                    }
+                });
+            });
+        ctx.request_repaint_after(REFRESH);
+    }
+}
@@ -0,0 +1,65 @@
+use egui::{Color32, Context, Frame, ProgressBar, RichText, Ui, Window};
+use hamshark::pipeline::{ElementState, ElementStatus, Pipeline};
+
+/// Developer panel showing each element of the running pipeline, how it's doing, and
+/// letting it be paused.
+pub fn show_inspector(ctx: &Context, open: &mut bool, pipeline: Option<&Pipeline>) {
+    Window::new("Pipeline Inspector")
+        .open(open)
+        .resizable(false)
+        .show(ctx, |ui| match pipeline {
+            Some(pipeline) => {
+                ui.horizontal(|ui| {
+                    for (i, element) in pipeline.elements().iter().enumerate() {
+                        if i > 0 {
+                            ui.label("➡");
+                        }
+                        show_element(ui, element);
+                    }
+                });
+            }
+            None => {
+                ui.label("Nothing is running");
+            }
+        });
+}
+
+fn show_element(ui: &mut Ui, element: &ElementStatus) {
+    Frame::group(ui.style()).show(ui, |ui| {
+        ui.vertical(|ui| {
+            ui.label(RichText::new(element.name()).strong());
+
+            let state = element.state();
+            let color = match state {
+                ElementState::Running => Color32::GREEN,
+                ElementState::Paused => Color32::YELLOW,
+                ElementState::Failed => Color32::RED,
+            };
+            ui.colored_label(color, state.to_string());
+
+            if let Some(fill) = element.fill() {
+                ui.add(
+                    ProgressBar::new(fill)
+                        .desired_width(100.0)
+                        .text(format!("{:.0}% full", fill * 100.0)),
+                );
+            }
+
+            ui.label(format!("Samples: {}", element.processed()));
+            let errors = ui.label(format!("Errors: {}", element.errors()));
+            if let Some(last_error) = element.last_error() {
+                errors.on_hover_text(last_error);
+            }
+            if element.events() > 0 {
+                ui.label(format!("Events: {}", element.events()));
+            }
+
+            if element.is_pausable() && state != ElementState::Failed {
+                let paused = element.is_paused();
+                if ui.button(if paused { "Resume" } else { "Pause" }).clicked() {
+                    element.set_paused(!paused);
+                }
+            }
+        });
+    });
+}
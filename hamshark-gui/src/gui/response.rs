@@ -0,0 +1,225 @@
+use egui::{
+    Color32, ComboBox, Context, DragValue, Pos2, Sense, Shape, Spinner, Stroke, Ui, Vec2, Window,
+    pos2,
+};
+use hamshark::{
+    config::SweepSettings,
+    response::{self, FrequencyResponse, SweepMeasurement},
+};
+use std::time::Duration;
+
+const PLOT_SIZE: Vec2 = Vec2::new(480.0, 240.0);
+const TRACE_COLOR: Color32 = Color32::from_rgb(0, 200, 255);
+const GRID_COLOR: Color32 = Color32::from_gray(60);
+/// Gain between horizontal grid lines
+const GRID_DB: f64 = 10.0;
+/// How often to check on a measurement
+const MEASURE_POLL: Duration = Duration::from_millis(200);
+
+/// Plays a sweep out of one device while recording through another and plots the response
+/// of what's between them, like a rig's audio chain or a sound card loop
+pub struct SweepAnalyzer {
+    /// Devices and sweep, starting from the settings
+    settings: SweepSettings,
+    /// Device names, listed when the window is first shown
+    devices: Option<(Vec<String>, Vec<String>)>,
+    measuring: Option<SweepMeasurement>,
+    result: Option<Result<FrequencyResponse, String>>,
+}
+
+impl SweepAnalyzer {
+    pub fn new(settings: &SweepSettings) -> Self {
+        Self {
+            settings: settings.clone(),
+            devices: None,
+            measuring: None,
+            result: None,
+        }
+    }
+
+    fn show_device(ui: &mut Ui, label: &str, selected: &mut String, names: &[String]) {
+        let text = if selected.is_empty() {
+            "Default".to_string()
+        } else {
+            selected.clone()
+        };
+        ComboBox::new(("response_device", label), label)
+            .selected_text(text)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(selected, String::new(), "Default");
+                for name in names {
+                    ui.selectable_value(selected, name.clone(), name);
+                }
+            });
+    }
+
+    fn show_controls(&mut self, ui: &mut Ui) {
+        let (outputs, inputs) = self
+            .devices
+            .get_or_insert_with(|| (response::output_devices(), response::input_devices()));
+        Self::show_device(ui, "Play out of", &mut self.settings.output_device, outputs);
+        Self::show_device(ui, "Record from", &mut self.settings.input_device, inputs);
+        ui.horizontal(|ui| {
+            ui.label("Sweep");
+            let hz = |value| DragValue::new(value).range(10.0..=24_000.0).suffix(" Hz");
+            ui.add(hz(&mut self.settings.start_hz));
+            ui.label("to");
+            ui.add(hz(&mut self.settings.end_hz));
+            ui.label("over");
+            ui.add(
+                DragValue::new(&mut self.settings.seconds)
+                    .range(1.0..=30.0)
+                    .suffix(" s"),
+            );
+        });
+        ui.horizontal(|ui| {
+            ui.label("Level");
+            ui.add(
+                DragValue::new(&mut self.settings.level_db)
+                    .range(-60.0..=0.0)
+                    .speed(0.5)
+                    .suffix(" dBFS"),
+            )
+            .on_hover_text("Keep it low enough that nothing along the way clips");
+        });
+    }
+
+    /// Gain against log frequency, with the value under the pointer
+    fn show_plot(ui: &mut Ui, response: &FrequencyResponse) {
+        let (Some(first), Some(last)) = (response.points.first(), response.points.last()) else {
+            return;
+        };
+        let (low_hz, high_hz) = (first.0, last.0.max(first.0 * 2.0));
+        let (min_db, max_db) = response
+            .points
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(min, max), (_, db)| {
+                (min.min(*db), max.max(*db))
+            });
+        let top = (max_db / GRID_DB).ceil() * GRID_DB;
+        let bottom = ((min_db / GRID_DB).floor() * GRID_DB).min(top - GRID_DB);
+
+        let (rect, hover) = ui.allocate_exact_size(PLOT_SIZE, Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::BLACK);
+        let x = |hz: f64| {
+            rect.min.x + ((hz / low_hz).ln() / (high_hz / low_hz).ln()) as f32 * rect.width()
+        };
+        let y = |db: f64| rect.min.y + ((top - db) / (top - bottom)) as f32 * rect.height();
+        let text_color = ui.visuals().weak_text_color();
+        let font = egui::FontId::monospace(10.0);
+
+        let mut decade = 10f64.powf(low_hz.log10().floor());
+        while decade <= high_hz {
+            for step in 1..10 {
+                let hz = decade * step as f64;
+                if (low_hz..=high_hz).contains(&hz) {
+                    painter.vline(x(hz), rect.y_range(), Stroke::new(1.0, GRID_COLOR));
+                    if step == 1 {
+                        let label = if hz >= 1000.0 {
+                            format!("{}k", hz / 1000.0)
+                        } else {
+                            format!("{hz}")
+                        };
+                        painter.text(
+                            pos2(x(hz) + 2.0, rect.max.y - 2.0),
+                            egui::Align2::LEFT_BOTTOM,
+                            label,
+                            font.clone(),
+                            text_color,
+                        );
+                    }
+                }
+            }
+            decade *= 10.0;
+        }
+        let mut db = bottom;
+        while db <= top {
+            painter.hline(rect.x_range(), y(db), Stroke::new(1.0, GRID_COLOR));
+            painter.text(
+                pos2(rect.min.x + 2.0, y(db) + 1.0),
+                egui::Align2::LEFT_TOP,
+                format!("{db:.0} dB"),
+                font.clone(),
+                text_color,
+            );
+            db += GRID_DB;
+        }
+
+        let points: Vec<Pos2> = response
+            .points
+            .iter()
+            .map(|(hz, db)| pos2(x(*hz), y(*db)))
+            .collect();
+        painter.add(Shape::line(points, Stroke::new(1.5, TRACE_COLOR)));
+
+        if let Some(pointer) = hover.hover_pos() {
+            let fraction = ((pointer.x - rect.min.x) / rect.width()) as f64;
+            let hz = low_hz * (high_hz / low_hz).powf(fraction);
+            if let Some(db) = response.gain_at(hz) {
+                painter.text(
+                    pos2(rect.max.x - 4.0, rect.min.y + 4.0),
+                    egui::Align2::RIGHT_TOP,
+                    format!("{hz:.0} Hz  {db:.1} dB"),
+                    font,
+                    Color32::WHITE,
+                );
+            }
+        }
+    }
+
+    fn show_result(ui: &mut Ui, response: &FrequencyResponse) {
+        Self::show_plot(ui, response);
+        ui.horizontal(|ui| {
+            if let Some(gain) = response.gain_at(1000.0) {
+                ui.label(format!("Gain at 1 kHz: {gain:.1} dB"));
+            }
+            if let Some(passband) = response.passband(1000.0, 3.0) {
+                ui.label(format!(
+                    "-3 dB from {:.0} to {:.0} Hz",
+                    passband.start, passband.end
+                ));
+            }
+            ui.label(format!("Latency: {:.1} ms", response.latency * 1000.0));
+        });
+    }
+
+    pub fn show(&mut self, ctx: &Context, open: &mut bool) {
+        if let Some(measuring) = self.measuring.take_if(|m| m.is_finished()) {
+            self.result = Some(measuring.finish().map_err(|e| e.to_string()));
+        }
+        Window::new("Frequency Response")
+            .open(open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.add_enabled_ui(self.measuring.is_none(), |ui| self.show_controls(ui));
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(self.measuring.is_none(), egui::Button::new("Measure"))
+                        .on_hover_text("Play the sweep and record what comes back")
+                        .clicked()
+                    {
+                        self.result = None;
+                        self.measuring = Some(SweepMeasurement::start(&self.settings));
+                    }
+                    if ui.button("Refresh devices").clicked() {
+                        self.devices = None;
+                    }
+                    if self.measuring.is_some() {
+                        ui.add(Spinner::new());
+                        ui.label("Sweeping…");
+                    }
+                });
+                match &self.result {
+                    Some(Ok(response)) => Self::show_result(ui, response),
+                    Some(Err(error)) => {
+                        ui.colored_label(ui.visuals().error_fg_color, error);
+                    }
+                    None => {}
+                }
+            });
+        if self.measuring.is_some() {
+            ctx.request_repaint_after(MEASURE_POLL);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use egui::{
+    Align2, Color32, Context, DragValue, FontId, Pos2, Sense, Shape, Stroke, Ui, Vec2, Window, pos2,
+};
+use hamshark::data::audio::Clip;
+
+const SIZE: Vec2 = Vec2::new(400.0, 256.0);
+const DIVISIONS_X: usize = 10;
+const DIVISIONS_Y: usize = 8;
+const GRID_COLOR: Color32 = Color32::from_gray(60);
+const TRACE_COLOR: Color32 = Color32::from_rgb(80, 255, 80);
+const TRIGGER_COLOR: Color32 = Color32::from_rgb(255, 200, 0);
+
+/// An oscilloscope on the live input, for setting levels and looking at test tones
+pub struct Scope {
+    /// Milliseconds per horizontal division
+    time_per_div: f32,
+    /// Full scale is 1.0
+    amplitude_per_div: f32,
+    /// Line up the trace on a rising edge through the trigger level
+    trigger: bool,
+    trigger_level: f32,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Self {
+            time_per_div: 1.0,
+            amplitude_per_div: 0.25,
+            trigger: true,
+            trigger_level: 0.0,
+        }
+    }
+}
+
+impl Scope {
+    pub fn show(&mut self, ctx: &Context, open: &mut bool, clip: Option<&Clip>) {
+        Window::new("Scope")
+            .open(open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        DragValue::new(&mut self.time_per_div)
+                            .range(0.1f32..=50.0f32)
+                            .speed(0.1)
+                            .suffix(" ms/div"),
+                    );
+                    ui.add(
+                        DragValue::new(&mut self.amplitude_per_div)
+                            .range(0.01f32..=1.0f32)
+                            .speed(0.01)
+                            .suffix("/div"),
+                    );
+                    ui.checkbox(&mut self.trigger, "Trigger");
+                    ui.add_enabled(
+                        self.trigger,
+                        DragValue::new(&mut self.trigger_level)
+                            .range(-1.0f32..=1.0f32)
+                            .speed(0.01)
+                            .prefix("Level: "),
+                    )
+                    .on_hover_text("Trigger on a rising edge through this level");
+                });
+
+                match clip {
+                    Some(clip) => self.show_trace(ui, clip),
+                    None => {
+                        ui.label("Not recording");
+                    }
+                }
+            });
+    }
+
+    /// The sample the trace should start from, and whether it was triggered
+    fn find_start(&self, samples: &[f32], window: usize) -> (usize, bool) {
+        let free_run = samples.len().saturating_sub(window);
+        if !self.trigger {
+            return (free_run, false);
+        }
+        // Look back over a couple of screens for the most recent rising edge that still
+        // leaves a full screen after it
+        let earliest = samples.len().saturating_sub(window * 3).max(1);
+        (earliest..=free_run)
+            .rev()
+            .find(|&i| samples[i - 1] < self.trigger_level && samples[i] >= self.trigger_level)
+            .map_or((free_run, false), |i| (i, true))
+    }
+
+    fn show_trace(&self, ui: &mut Ui, clip: &Clip) {
+        let (rect, _) = ui.allocate_exact_size(SIZE, Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::BLACK);
+
+        for i in 0..=DIVISIONS_X {
+            let x = rect.min.x + rect.width() * i as f32 / DIVISIONS_X as f32;
+            painter.vline(x, rect.y_range(), Stroke::new(1.0, GRID_COLOR));
+        }
+        for i in 0..=DIVISIONS_Y {
+            let y = rect.min.y + rect.height() * i as f32 / DIVISIONS_Y as f32;
+            painter.hline(rect.x_range(), y, Stroke::new(1.0, GRID_COLOR));
+        }
+
+        let amplitude_to_y = |amplitude: f32| {
+            let pixels_per_div = rect.height() / DIVISIONS_Y as f32;
+            rect.center().y - amplitude / self.amplitude_per_div * pixels_per_div
+        };
+
+        if self.trigger {
+            painter.hline(
+                rect.x_range(),
+                amplitude_to_y(self.trigger_level),
+                Stroke::new(1.0, TRIGGER_COLOR),
+            );
+        }
+
+        let read_lock = clip.read();
+        let sample_rate = read_lock.sample_rate.0 as f32;
+        let window =
+            ((self.time_per_div * DIVISIONS_X as f32 / 1000.0 * sample_rate) as usize).max(2);
+        // Only the last few screens are searched for a trigger
+        let len = read_lock.samples.len();
+        let samples = read_lock.samples.from(len.saturating_sub(window * 3));
+        if samples.len() < window {
+            return;
+        }
+
+        let (start, triggered) = self.find_start(&samples, window);
+        let points: Vec<Pos2> = samples[start..start + window]
+            .iter()
+            .enumerate()
+            .map(|(i, sample)| {
+                let x = rect.min.x + rect.width() * i as f32 / (window - 1) as f32;
+                pos2(x, amplitude_to_y(*sample))
+            })
+            .collect();
+        drop(read_lock);
+
+        painter.add(Shape::line(points, Stroke::new(1.0, TRACE_COLOR)));
+        if self.trigger && !triggered {
+            painter.text(
+                rect.min + Vec2::new(4.0, 4.0),
+                Align2::LEFT_TOP,
+                "Untriggered",
+                FontId::proportional(11.0),
+                TRIGGER_COLOR,
+            );
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use crate::gui::View;
+use cpal::traits::DeviceTrait;
+use egui::{ComboBox, Id, Modal, ProgressBar, TextEdit, Ui};
+use hamshark::{
+    config::StationSettings, data::audioinput::AudioInputDeviceBuilder, tools::LevelMeter,
+};
+use log::error;
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// How long the test recording listens for
+const TEST_LENGTH: Duration = Duration::from_secs(3);
+/// Peaks below this (-40 dBFS) probably mean nothing is plugged in
+const QUIET_PEAK: f32 = 0.01;
+/// Peaks above this are probably clipping
+const LOUD_PEAK: f32 = 0.99;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    Sessions,
+    Audio,
+    Station,
+    Test,
+}
+
+/// A level check in progress
+struct LevelTest {
+    meter: LevelMeter,
+    started: Instant,
+    /// The latest level, for the meter
+    level: f32,
+    /// The loudest it's been
+    peak: f32,
+}
+
+/// The first-run dialog, walking through where sessions go, which input to record, who's
+/// operating, and whether the input level is sensible
+pub struct SetupWizard {
+    step: Step,
+    pub session_base_dir: String,
+    pub device: AudioInputDeviceBuilder,
+    pub station: StationSettings,
+    test: Option<LevelTest>,
+    /// The loudest peak of the last finished test
+    tested_peak: Option<f32>,
+}
+
+impl SetupWizard {
+    pub fn new(
+        session_base_dir: &Path,
+        device: AudioInputDeviceBuilder,
+        station: StationSettings,
+    ) -> Self {
+        Self {
+            step: Step::Sessions,
+            session_base_dir: session_base_dir.to_string_lossy().to_string(),
+            device,
+            station,
+            test: None,
+            tested_peak: None,
+        }
+    }
+
+    fn show_sessions(&mut self, ui: &mut Ui) {
+        ui.label(
+            "Each time Hamshark starts it makes a new session folder in here for its recordings.",
+        );
+        ui.horizontal(|ui| {
+            ui.add(TextEdit::singleline(&mut self.session_base_dir).desired_width(300.0));
+            if ui.button("Browse…").clicked()
+                && let Some(dir) = rfd::FileDialog::new()
+                    .set_directory(&self.session_base_dir)
+                    .pick_folder()
+            {
+                self.session_base_dir = dir.to_string_lossy().to_string();
+            }
+        });
+    }
+
+    fn show_audio(&mut self, ui: &mut Ui) {
+        ui.label("Which input is your radio connected to?");
+        let current = self
+            .device
+            .device
+            .as_ref()
+            .and_then(|device| device.name().ok())
+            .unwrap_or_default();
+        let mut selected = current.clone();
+        ComboBox::new("setup_device", "Device")
+            .selected_text(&selected)
+            .show_ui(ui, |ui| {
+                for device in self.device.input_devices().unwrap_or_default() {
+                    if let Ok(name) = device.name() {
+                        ui.selectable_value(&mut selected, name.clone(), name);
+                    }
+                }
+            });
+        if selected != current
+            && let Some(device) = self.device.clone().with_device_named(&selected)
+        {
+            self.device = device;
+            self.tested_peak = None;
+        }
+        ui.label(
+            "The host, channels and sample rate can be changed later from File → Configure Audio.",
+        );
+    }
+
+    fn show_station(&mut self, ui: &mut Ui) {
+        ui.label("Who's on the air? All of these are optional.");
+        egui::Grid::new("setup_station").show(ui, |ui| {
+            ui.label("Callsign");
+            ui.text_edit_singleline(&mut self.station.callsign);
+            ui.end_row();
+            ui.label("Operator");
+            ui.text_edit_singleline(&mut self.station.operator);
+            ui.end_row();
+            ui.label("Grid square");
+            ui.text_edit_singleline(&mut self.station.grid_square);
+            ui.end_row();
+        });
+    }
+
+    fn show_test(&mut self, ui: &mut Ui) {
+        ui.label("Tune to something and make a short test recording to check the level.");
+        if let Some(test) = &mut self.test {
+            let latest = test.meter.take_peak();
+            // Let the meter fall back gently rather than flicker
+            test.level = latest.max(test.level * 0.9);
+            test.peak = test.peak.max(latest);
+            ui.add(ProgressBar::new(test.level).text(format!("{:.1} dBFS", dbfs(test.level))));
+            ui.ctx().request_repaint();
+            if test.started.elapsed() >= TEST_LENGTH {
+                self.tested_peak = Some(test.peak);
+                self.test = None;
+            }
+            return;
+        }
+
+        if ui.button("Start test").clicked() {
+            match self
+                .device
+                .build()
+                .ok()
+                .map(|device| LevelMeter::new(&device))
+            {
+                Some(Ok(meter)) => {
+                    self.test = Some(LevelTest {
+                        meter,
+                        started: Instant::now(),
+                        level: 0.0,
+                        peak: 0.0,
+                    })
+                }
+                Some(Err(error)) => error!("Unable to test the input level: {}", error),
+                None => error!("No input device to test"),
+            }
+        }
+        if let Some(peak) = self.tested_peak {
+            let verdict = if peak < QUIET_PEAK {
+                "Very quiet. Check the radio is connected and its audio is turned up."
+            } else if peak > LOUD_PEAK {
+                "Clipping. Turn the radio's audio or the input gain down."
+            } else {
+                "The level looks good."
+            };
+            ui.label(format!("Peak {:.1} dBFS. {}", dbfs(peak), verdict));
+        }
+    }
+}
+
+fn dbfs(level: f32) -> f32 {
+    20.0 * level.max(1e-5).log10()
+}
+
+impl View for SetupWizard {
+    fn show(&mut self, ui: &mut Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce()) {
+        Modal::new(Id::new("Setup")).show(ui.ctx(), |ui| {
+            ui.heading("Welcome to Hamshark");
+            let (title, previous, next) = match self.step {
+                Step::Sessions => ("Where should recordings go?", None, Some(Step::Audio)),
+                Step::Audio => ("Audio input", Some(Step::Sessions), Some(Step::Station)),
+                Step::Station => ("Station", Some(Step::Audio), Some(Step::Test)),
+                Step::Test => ("Level check", Some(Step::Station), None),
+            };
+            ui.strong(title);
+            match self.step {
+                Step::Sessions => self.show_sessions(ui),
+                Step::Audio => self.show_audio(ui),
+                Step::Station => self.show_station(ui),
+                Step::Test => self.show_test(ui),
+            }
+
+            ui.separator();
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                let testing = self.test.is_some();
+                match next {
+                    Some(next) => {
+                        let ready =
+                            self.step != Step::Sessions || !self.session_base_dir.trim().is_empty();
+                        if ui.add_enabled(ready, egui::Button::new("Next")).clicked() {
+                            self.step = next;
+                        }
+                    }
+                    None => {
+                        if ui
+                            .add_enabled(!testing, egui::Button::new("Finish"))
+                            .clicked()
+                        {
+                            on_save();
+                        }
+                    }
+                }
+                if let Some(previous) = previous
+                    && ui
+                        .add_enabled(!testing, egui::Button::new("Back"))
+                        .clicked()
+                {
+                    self.step = previous;
+                }
+                if ui
+                    .add_enabled(!testing, egui::Button::new("Skip setup"))
+                    .on_hover_text("Keep the defaults. Everything can be changed in hamshark.toml.")
+                    .clicked()
+                {
+                    on_cancel();
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,145 @@
+use crate::gui::View;
+use egui::{DragValue, Id, Modal, Ui};
+use hamshark::{
+    analysis::{self, Snr},
+    data::audio::Clip,
+};
+use std::ops::Range;
+
+/// The SNR dialog: how far the selection's signal stands above the noise
+pub struct SnrMeasurement {
+    title: String,
+    clip: Clip,
+    /// The part of the clip to measure
+    range: Range<usize>,
+    /// Only count the signal between these audio frequencies
+    pub band: bool,
+    pub low_hz: f64,
+    pub high_hz: f64,
+    /// The bandwidth noise is counted over
+    pub bandwidth_hz: f64,
+    /// The last measurement, or why there wasn't one
+    pub result: Option<Result<Snr, String>>,
+}
+
+impl SnrMeasurement {
+    pub fn new(title: &str, clip: Clip, range: Range<usize>, bandwidth_hz: f64) -> Self {
+        let nyquist = clip.read().sample_rate.0 as f64 / 2.0;
+        Self {
+            title: title.to_string(),
+            clip,
+            range,
+            band: false,
+            low_hz: 0.0,
+            high_hz: nyquist,
+            bandwidth_hz,
+            result: None,
+        }
+    }
+
+    /// What to label a marker at the start of the selection with, once there's a measurement
+    pub fn marker_label(&self) -> Option<String> {
+        let Some(Ok(snr)) = &self.result else {
+            return None;
+        };
+        let band = if self.band {
+            format!(" {:.0}-{:.0} Hz", self.low_hz, self.high_hz)
+        } else {
+            String::new()
+        };
+        Some(format!(
+            "SNR{} {:+.1} dB in {:.0} Hz",
+            band,
+            snr.db(),
+            snr.reference_bandwidth
+        ))
+    }
+
+    pub fn start(&self) -> usize {
+        self.range.start
+    }
+
+    fn measure(&self) -> Result<Snr, String> {
+        let clip = self.clip.read();
+        let samples = clip.samples.range(self.range.clone());
+        let band = self
+            .band
+            .then(|| self.low_hz.min(self.high_hz)..self.low_hz.max(self.high_hz));
+        let snr = analysis::snr(&samples, clip.sample_rate.0, band, self.bandwidth_hz)
+            .ok_or_else(|| "Not enough audio, select a longer stretch".to_string())?;
+        if snr.signal <= 0.0 {
+            return Err("No signal above the noise".to_string());
+        }
+        Ok(snr)
+    }
+}
+
+impl View for SnrMeasurement {
+    fn show(&mut self, ui: &mut Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce()) {
+        Modal::new(Id::new(("SNR", &self.title))).show(ui.ctx(), |ui| {
+            ui.heading(format!("SNR of the selection in {}", self.title));
+
+            let nyquist = self.clip.read().sample_rate.0 as f64 / 2.0;
+            ui.checkbox(&mut self.band, "Only the signal between")
+                .on_hover_text("Otherwise everything above the noise counts as signal");
+            ui.add_enabled_ui(self.band, |ui| {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        DragValue::new(&mut self.low_hz)
+                            .range(0.0..=nyquist)
+                            .suffix(" Hz"),
+                    );
+                    ui.label("and");
+                    ui.add(
+                        DragValue::new(&mut self.high_hz)
+                            .range(0.0..=nyquist)
+                            .suffix(" Hz"),
+                    );
+                });
+            });
+            ui.horizontal(|ui| {
+                ui.label("Noise in");
+                ui.add(
+                    DragValue::new(&mut self.bandwidth_hz)
+                        .range(1.0..=nyquist)
+                        .suffix(" Hz"),
+                )
+                .on_hover_text("The reference bandwidth. 2500 Hz compares with WSJT-X.");
+            });
+
+            if ui.button("Measure").clicked() {
+                self.result = Some(self.measure());
+            }
+            match &self.result {
+                Some(Ok(snr)) => {
+                    ui.label(format!(
+                        "SNR {:+.1} dB, noise floor {:.1} dBFS in {:.0} Hz",
+                        snr.db(),
+                        snr.noise_db(),
+                        snr.reference_bandwidth
+                    ));
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                None => {}
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                if ui
+                    .add_enabled(
+                        self.marker_label().is_some(),
+                        egui::Button::new("Add marker"),
+                    )
+                    .on_hover_text("Note the SNR with a marker at the start of the selection")
+                    .clicked()
+                {
+                    on_save();
+                }
+                if ui.button("Close").clicked() {
+                    on_cancel();
+                }
+            })
+        });
+    }
+}
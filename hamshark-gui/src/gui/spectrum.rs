@@ -0,0 +1,321 @@
+use crate::gui::view::{ColumnTexture, show_averaging_controls};
+use egui::{
+    Color32, ColorImage, Context, DragValue, Grid, Pos2, Rect, Sense, Shape, Stroke, Ui, Vec2,
+    Window, pos2,
+};
+use hamshark::{
+    carriers::CarrierTracker,
+    config::CarrierSettings,
+    data::{
+        audio::{Clip, ClipId},
+        averaging::{Averager, Averaging},
+        window::WindowFunction,
+    },
+    fft::Transform,
+};
+use rustfft::num_complex::Complex;
+
+const WIDTH: usize = 512;
+const HEIGHT: usize = 200;
+const FFT_SIZE: usize = 2048;
+/// The quietest magnitude shown, at the bottom of the display
+const FLOOR_DB: f32 = -100.0;
+const TRACE_COLOR: Color32 = Color32::from_rgb(80, 255, 80);
+/// How much brightness one frame of the trace adds to the phosphor
+const PHOSPHOR_HIT: f32 = 0.25;
+const CARRIER_COLOR: Color32 = Color32::from_rgb(255, 200, 60);
+
+/// Carriers being followed in the clip being recorded
+struct LiveCarriers {
+    clip: ClipId,
+    settings: CarrierSettings,
+    tracker: CarrierTracker,
+    /// Samples of the clip given to the tracker so far
+    fed: usize,
+}
+
+/// A live spectrum of the input, either as a plain trace or as a persistence display where
+/// each frame fades away slowly, like the phosphor on an analog spectrum analyzer.
+pub struct Spectrum {
+    fft: Transform<f32>,
+    /// Window function coefficients, one per FFT input sample
+    window: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    persistence: bool,
+    /// Seconds for the phosphor to fade to half brightness
+    half_life: f32,
+    /// Brightness of each pixel of the persistence display, from 0 to 1
+    phosphor: Vec<f32>,
+    texture: ColumnTexture,
+    /// How many samples the clip had when we last took a spectrum
+    last_len: usize,
+    /// The most recent spectrum, one dB value per display column
+    columns: Vec<f32>,
+    /// Averages the spectra before they're shown
+    averager: Averager,
+    /// Whether to find and list the carriers that are on
+    track_carriers: bool,
+    carriers: Option<LiveCarriers>,
+}
+
+impl Spectrum {
+    pub fn new(window_function: WindowFunction, averaging: Averaging) -> Self {
+        Self {
+            fft: Transform::forward(FFT_SIZE),
+            window: window_function.coefficients(FFT_SIZE),
+            buffer: vec![Complex::default(); FFT_SIZE],
+            persistence: false,
+            half_life: 1.0,
+            phosphor: vec![0.0; WIDTH * HEIGHT],
+            texture: ColumnTexture::new("spectrum"),
+            last_len: 0,
+            columns: Vec::new(),
+            averager: Averager::new(averaging),
+            track_carriers: false,
+            carriers: None,
+        }
+    }
+
+    pub fn set_window_function(&mut self, window_function: WindowFunction) {
+        self.window = window_function.coefficients(FFT_SIZE);
+    }
+
+    pub fn set_averaging(&mut self, averaging: Averaging) {
+        self.averager = Averager::new(averaging);
+    }
+
+    pub fn show(
+        &mut self,
+        ctx: &Context,
+        open: &mut bool,
+        clip: Option<&Clip>,
+        carrier_settings: &CarrierSettings,
+    ) {
+        Window::new("Spectrum")
+            .open(open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.checkbox(&mut self.persistence, "Persistence").changed() {
+                        self.phosphor.fill(0.0);
+                    }
+                    ui.add_enabled(
+                        self.persistence,
+                        DragValue::new(&mut self.half_life)
+                            .range(0.05f32..=10.0f32)
+                            .speed(0.05)
+                            .suffix(" s"),
+                    )
+                    .on_hover_text("How long a trace takes to fade to half brightness");
+                    ui.separator();
+                    let mut averaging = self.averager.averaging();
+                    if show_averaging_controls(ui, "spectrum_averaging", &mut averaging) {
+                        self.set_averaging(averaging);
+                    }
+                    ui.separator();
+                    ui.checkbox(&mut self.track_carriers, "Carriers")
+                        .on_hover_text("Find the steady signals that are on and how long for");
+                });
+
+                match clip {
+                    Some(clip) => {
+                        let updated = self.update(clip);
+                        self.fade(ui.input(|input| input.stable_dt));
+                        if updated {
+                            self.burn();
+                        }
+                        let rect = self.show_display(ui);
+                        if self.track_carriers {
+                            self.update_carriers(clip, carrier_settings);
+                            self.show_carriers(ui, rect, clip);
+                        } else {
+                            self.carriers = None;
+                        }
+                    }
+                    None => {
+                        ui.label("Not recording");
+                    }
+                }
+            });
+    }
+
+    fn db_to_y(db: f32) -> f32 {
+        let level = ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+        (HEIGHT - 1) as f32 * (1.0 - level)
+    }
+
+    /// Take the spectrum of the latest samples, if any have arrived. Returns whether it did.
+    fn update(&mut self, clip: &Clip) -> bool {
+        let read_lock = clip.read();
+        let len = read_lock.samples.len();
+        if len < FFT_SIZE || len == self.last_len {
+            return false;
+        }
+        self.last_len = len;
+
+        for ((out, sample), window) in self
+            .buffer
+            .iter_mut()
+            .zip(read_lock.samples.from(len - FFT_SIZE).iter())
+            .zip(&self.window)
+        {
+            *out = Complex::new(sample * window, 0.0);
+        }
+        drop(read_lock);
+        self.fft.process(&mut self.buffer);
+
+        let gain: f32 = 2.0 / self.window.iter().sum::<f32>();
+        let bins = FFT_SIZE / 2;
+        let spectrum = self.averager.push(
+            self.buffer[..bins]
+                .iter()
+                .map(|bin| 20.0 * (bin.norm() * gain).max(1e-10).log10())
+                .collect(),
+        );
+
+        // Summarize the bins under each column by the loudest
+        self.columns = (0..WIDTH)
+            .map(|x| {
+                let first = x * bins / WIDTH;
+                let last = ((x + 1) * bins / WIDTH).max(first + 1);
+                spectrum[first..last]
+                    .iter()
+                    .copied()
+                    .fold(f32::MIN, f32::max)
+            })
+            .collect();
+        true
+    }
+
+    /// Let the phosphor dim for dt seconds
+    fn fade(&mut self, dt: f32) {
+        let retention = 0.5f32.powf(dt / self.half_life);
+        for pixel in &mut self.phosphor {
+            *pixel *= retention;
+        }
+    }
+
+    /// Light up the phosphor along the latest trace, joining neighbouring columns so steep
+    /// edges don't leave gaps
+    fn burn(&mut self) {
+        let mut previous: Option<usize> = None;
+        for (x, db) in self.columns.iter().enumerate() {
+            let y = Self::db_to_y(*db) as usize;
+            let (top, bottom) = match previous {
+                Some(previous) => (y.min(previous), y.max(previous)),
+                None => (y, y),
+            };
+            for y in top..=bottom {
+                let pixel = &mut self.phosphor[y * WIDTH + x];
+                *pixel = (*pixel + PHOSPHOR_HIT).min(1.0);
+            }
+            previous = Some(y);
+        }
+    }
+
+    fn phosphor_color(brightness: f32) -> Color32 {
+        // Green, washing out to white where the trace lands most often
+        let wash = (brightness * brightness * 255.0) as u8;
+        Color32::from_rgb(wash, (brightness.sqrt() * 255.0) as u8, wash)
+    }
+
+    /// Give the carrier tracker what's been recorded since it last looked, starting it over
+    /// for a new clip or new settings
+    fn update_carriers(&mut self, clip: &Clip, settings: &CarrierSettings) {
+        let clip = clip.read();
+        if self
+            .carriers
+            .as_ref()
+            .is_none_or(|live| &live.clip != clip.id() || &live.settings != settings)
+        {
+            self.carriers = Some(LiveCarriers {
+                clip: clip.id().clone(),
+                settings: settings.clone(),
+                tracker: CarrierTracker::new(settings, clip.sample_rate.0),
+                fed: clip.samples.len(),
+            });
+        }
+        if let Some(live) = &mut self.carriers
+            && live.fed <= clip.samples.len()
+        {
+            live.tracker.push(&clip.samples.from(live.fed));
+            live.fed = clip.samples.len();
+        }
+    }
+
+    /// Mark the carriers that are on along the top of the display and list them below it
+    fn show_carriers(&self, ui: &mut Ui, rect: Rect, clip: &Clip) {
+        let Some(live) = &self.carriers else {
+            return;
+        };
+        let clip = clip.read();
+        let nyquist = clip.sample_rate.0 as f64 / 2.0;
+        let mut carriers: Vec<_> = live.tracker.active().collect();
+        carriers.sort_by(|a, b| a.frequency.total_cmp(&b.frequency));
+
+        let painter = ui.painter_at(rect);
+        for carrier in &carriers {
+            let x = rect.min.x + (carrier.frequency / nyquist * WIDTH as f64) as f32;
+            painter.vline(
+                x,
+                rect.min.y..=rect.min.y + 8.0,
+                Stroke::new(2.0, CARRIER_COLOR),
+            );
+        }
+
+        if carriers.is_empty() {
+            ui.weak("No carriers");
+            return;
+        }
+        let dial = clip.metadata.dial_frequency_at(live.fed);
+        Grid::new("carriers")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                for carrier in carriers {
+                    match dial {
+                        Some(dial) => ui.label(format!(
+                            "{:.3} kHz",
+                            clip.metadata.corrected(dial as f64 + carrier.frequency) / 1000.0
+                        )),
+                        None => ui.label(format!("{:.1} Hz", carrier.frequency)),
+                    };
+                    ui.label(format!("{:.1} dBFS", carrier.level_db));
+                    ui.label(format!("{:.0} s", carrier.duration(clip.sample_rate.0)));
+                    ui.end_row();
+                }
+            });
+    }
+
+    /// Draw the trace or the phosphor, returning where
+    fn show_display(&mut self, ui: &mut Ui) -> Rect {
+        if self.persistence {
+            let pixels = self
+                .phosphor
+                .iter()
+                .map(|brightness| Self::phosphor_color(*brightness))
+                .collect();
+            self.texture
+                .show(
+                    ui,
+                    ColorImage::new([WIDTH, HEIGHT], pixels),
+                    None,
+                    Sense::hover(),
+                )
+                .rect
+        } else {
+            let (rect, _) =
+                ui.allocate_exact_size(Vec2::new(WIDTH as f32, HEIGHT as f32), Sense::hover());
+            let painter = ui.painter_at(rect);
+            painter.rect_filled(rect, 0.0, Color32::BLACK);
+            let points: Vec<Pos2> = self
+                .columns
+                .iter()
+                .enumerate()
+                .map(|(x, db)| pos2(rect.min.x + x as f32, rect.min.y + Self::db_to_y(*db)))
+                .collect();
+            painter.add(Shape::line(points, Stroke::new(1.0, TRACE_COLOR)));
+            rect
+        }
+    }
+}
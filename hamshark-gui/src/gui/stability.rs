@@ -0,0 +1,224 @@
+use crate::gui::View;
+use egui::{Color32, DragValue, Id, Modal, Pos2, Sense, Shape, Stroke, Ui, Vec2, pos2};
+use hamshark::{
+    analysis::{self, Stability},
+    data::audio::Clip,
+};
+use std::ops::Range;
+
+const PLOT_SIZE: Vec2 = Vec2::new(480.0, 160.0);
+const TRACE_COLOR: Color32 = Color32::from_rgb(0, 200, 255);
+const FIT_COLOR: Color32 = Color32::from_rgb(255, 200, 0);
+const GRID_COLOR: Color32 = Color32::from_gray(60);
+
+/// The frequency stability dialog: how far a reference tone in the selection wanders, to
+/// check a rig and sound card are steady enough for WSPR and FT8
+pub struct StabilityMeasurement {
+    title: String,
+    clip: Clip,
+    range: Range<usize>,
+    /// How often the tone is measured
+    pub step_seconds: f64,
+    /// Compare the tone against what it should be, when it comes from an accurate source
+    pub against_nominal: bool,
+    pub nominal_hz: f64,
+    /// The last measurement, or why there wasn't one
+    pub result: Option<Result<Stability, String>>,
+}
+
+impl StabilityMeasurement {
+    pub fn new(title: &str, clip: Clip, range: Range<usize>) -> Self {
+        let mut measurement = Self {
+            title: title.to_string(),
+            clip,
+            range,
+            step_seconds: 1.0,
+            against_nominal: false,
+            nominal_hz: 1000.0,
+            result: None,
+        };
+        measurement.result = Some(measurement.measure());
+        measurement
+    }
+
+    pub fn start(&self) -> usize {
+        self.range.start
+    }
+
+    fn measure(&self) -> Result<Stability, String> {
+        let clip = self.clip.read();
+        let samples = clip.samples.range(self.range.clone());
+        analysis::frequency_stability(&samples, clip.sample_rate.0, self.step_seconds).ok_or_else(
+            || "No steady tone found, select at least two steps of a clear one".to_string(),
+        )
+    }
+
+    /// The tone's frequency on the air, from the dial and the clip's calibration
+    fn on_air(&self, audio_hz: f64) -> Option<f64> {
+        let clip = self.clip.read();
+        let dial = clip.metadata.dial_frequency_at(self.range.start)?;
+        Some(clip.metadata.corrected(dial as f64 + audio_hz))
+    }
+
+    /// What to label a marker at the start of the selection with, once there's a measurement
+    pub fn marker_label(&self) -> Option<String> {
+        let Some(Ok(stability)) = &self.result else {
+            return None;
+        };
+        let drift = stability.drift_hz();
+        Some(match self.on_air(stability.mean_hz) {
+            Some(rf) => format!(
+                "Drift {:+.2} Hz, {:+.3} ppm on air, over {:.0} s",
+                drift,
+                Stability::ppm(drift, rf),
+                stability.duration
+            ),
+            None => format!(
+                "Drift {:+.2} Hz, {:+.1} ppm, over {:.0} s",
+                drift,
+                Stability::ppm(drift, stability.mean_hz),
+                stability.duration
+            ),
+        })
+    }
+
+    /// How far the tone was from its mean at each step, with the fitted drift
+    fn show_plot(ui: &mut Ui, stability: &Stability) {
+        // Centred on the mean, with room for the furthest it got either way
+        let deviation = 2.0
+            * stability
+                .points
+                .iter()
+                .map(|(_, hz)| (hz - stability.mean_hz).abs())
+                .fold(0.005, f64::max);
+        let (rect, _) = ui.allocate_exact_size(PLOT_SIZE, Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, Color32::BLACK);
+        let x = |seconds: f64| rect.min.x + (seconds / stability.duration) as f32 * rect.width();
+        let y = |hz: f64| {
+            rect.center().y - ((hz - stability.mean_hz) / deviation) as f32 * rect.height()
+        };
+        painter.hline(
+            rect.x_range(),
+            rect.center().y,
+            Stroke::new(1.0, GRID_COLOR),
+        );
+        painter.text(
+            pos2(rect.min.x + 2.0, rect.min.y + 2.0),
+            egui::Align2::LEFT_TOP,
+            format!("+{:.2} Hz", deviation / 2.0),
+            egui::FontId::monospace(10.0),
+            ui.visuals().weak_text_color(),
+        );
+
+        let points: Vec<Pos2> = stability
+            .points
+            .iter()
+            .map(|(seconds, hz)| pos2(x(*seconds), y(*hz)))
+            .collect();
+        painter.add(Shape::line(points, Stroke::new(1.5, TRACE_COLOR)));
+        let middle = stability.duration / 2.0;
+        let fit = |seconds: f64| stability.mean_hz + stability.drift_rate * (seconds - middle);
+        painter.line_segment(
+            [
+                pos2(x(0.0), y(fit(0.0))),
+                pos2(x(stability.duration), y(fit(stability.duration))),
+            ],
+            Stroke::new(1.0, FIT_COLOR),
+        );
+    }
+
+    fn show_result(&self, ui: &mut Ui, stability: &Stability) {
+        Self::show_plot(ui, stability);
+        let drift = stability.drift_hz();
+        ui.label(format!(
+            "Tone at {:.3} Hz over {:.0} s",
+            stability.mean_hz, stability.duration
+        ));
+        ui.label(format!(
+            "Drifted {:+.3} Hz, {:+.2} Hz/min, {:+.2} ppm of the tone",
+            drift,
+            stability.drift_rate * 60.0,
+            Stability::ppm(drift, stability.mean_hz)
+        ))
+        .on_hover_text("From a straight line fitted through the measurements");
+        if let Some(rf) = self.on_air(stability.mean_hz) {
+            ui.label(format!(
+                "{:+.4} ppm of {:.3} kHz on the air",
+                Stability::ppm(drift, rf),
+                rf / 1000.0
+            ))
+            .on_hover_text("How far the rig's oscillator drifted, if the tone came in off the air");
+        }
+        ui.label(format!(
+            "Wandered over {:.3} Hz, lowest to highest",
+            stability.spread_hz
+        ));
+        if self.against_nominal {
+            let offset = stability.mean_hz - self.nominal_hz;
+            ui.label(format!(
+                "Off by {:+.3} Hz, {:+.1} ppm",
+                offset,
+                Stability::ppm(offset, self.nominal_hz)
+            ))
+            .on_hover_text(
+                "If the tone comes from an accurate source, this is how far off the sound card's \
+                 clock is",
+            );
+        }
+    }
+}
+
+impl View for StabilityMeasurement {
+    fn show(&mut self, ui: &mut Ui, on_save: impl FnOnce(), on_cancel: impl FnOnce()) {
+        Modal::new(Id::new(("Frequency Stability", &self.title))).show(ui.ctx(), |ui| {
+            ui.heading(format!("Frequency stability in {}", self.title));
+
+            ui.horizontal(|ui| {
+                ui.label("Measure every");
+                ui.add(
+                    DragValue::new(&mut self.step_seconds)
+                        .range(0.1..=60.0)
+                        .speed(0.1)
+                        .suffix(" s"),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.against_nominal, "Should be");
+                ui.add_enabled(
+                    self.against_nominal,
+                    DragValue::new(&mut self.nominal_hz)
+                        .range(1.0..=24_000.0)
+                        .max_decimals(3)
+                        .suffix(" Hz"),
+                );
+            });
+            if ui.button("Measure").clicked() {
+                self.result = Some(self.measure());
+            }
+            match &self.result {
+                Some(Ok(stability)) => self.show_result(ui, stability),
+                Some(Err(error)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                None => {}
+            }
+
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                if ui
+                    .add_enabled(
+                        self.marker_label().is_some(),
+                        egui::Button::new("Add marker"),
+                    )
+                    .on_hover_text("Note the drift with a marker at the start of the selection")
+                    .clicked()
+                {
+                    on_save();
+                }
+                if ui.button("Close").clicked() {
+                    on_cancel();
+                }
+            })
+        });
+    }
+}
@@ -0,0 +1,58 @@
+use egui::Ui;
+use hamshark::{
+    analysis::{self, Statistics},
+    data::audio::Clip,
+};
+use std::ops::Range;
+
+/// Level and bandwidth of the selection, kept up to date as it changes
+#[derive(Default)]
+pub struct SelectionStatistics {
+    /// What the statistics were taken of, the selection and how long the clip was
+    measured: Option<(Range<usize>, usize)>,
+    statistics: Option<Statistics>,
+}
+
+impl SelectionStatistics {
+    /// Measure the selection again if it, or the clip under it, has changed
+    fn update(&mut self, clip: &Clip, selection: &Range<usize>) {
+        let clip = clip.read();
+        let key = (selection.clone(), clip.samples.len());
+        if self.measured.as_ref() == Some(&key) {
+            return;
+        }
+        let samples = clip.samples.range(selection.clone());
+        self.statistics = Some(analysis::statistics(&samples, clip.sample_rate.0));
+        self.measured = Some(key);
+    }
+
+    pub fn show(&mut self, ui: &mut Ui, clip: &Clip, selection: Option<&Range<usize>>) {
+        let Some(selection) = selection else {
+            ui.weak("Select something for its statistics");
+            return;
+        };
+        self.update(clip, selection);
+        let Some(statistics) = &self.statistics else {
+            return;
+        };
+        let db = |level: f64| 20.0 * level.log10();
+        ui.label(format!("{:.3} s", statistics.duration));
+        ui.separator();
+        ui.label(format!("RMS {:.1} dBFS", db(statistics.rms)));
+        ui.separator();
+        ui.label(format!("Peak {:.1} dBFS", db(statistics.peak)));
+        ui.separator();
+        ui.label(format!("Crest {:.1} dB", db(statistics.crest_factor())))
+            .on_hover_text(format!("{:.2} times the RMS", statistics.crest_factor()));
+        ui.separator();
+        ui.label(format!("DC {:+.5}", statistics.dc_offset));
+        if let Some(occupied) = &statistics.occupied {
+            ui.separator();
+            ui.label(format!("Occupied {:.0} Hz", occupied.end - occupied.start))
+                .on_hover_text(format!(
+                    "99% of the power is between {:.0} and {:.0} Hz",
+                    occupied.start, occupied.end
+                ));
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use egui::{ComboBox, Context, DragValue, Ui, Window};
+use hamshark::{
+    data::audio::{Clip, ClipId},
+    tdoa::{self, ArrivalDifference},
+};
+use std::collections::BTreeMap;
+
+/// Metres a radio wave covers in a second
+const SPEED_OF_LIGHT: f64 = 299_792_458.0;
+/// Below this correlation the match is likely to be wrong
+const WEAK_CORRELATION: f64 = 0.3;
+
+/// Compares when a transmission reached two clips recorded on systems with synchronized
+/// clocks, the start of finding where it came from by the difference
+pub struct ArrivalTime {
+    first: Option<ClipId>,
+    second: Option<ClipId>,
+    /// The stretch of the first clip looked for in the second, in seconds
+    pub from_seconds: f64,
+    pub length_seconds: f64,
+    /// How far either side of where the clocks put it to look, in milliseconds
+    pub search_ms: f64,
+    /// Compare envelopes rather than waveforms, for receivers that aren't phase locked
+    pub envelopes: bool,
+    result: Option<Result<ArrivalDifference, String>>,
+}
+
+impl Default for ArrivalTime {
+    fn default() -> Self {
+        Self {
+            first: None,
+            second: None,
+            from_seconds: 0.0,
+            length_seconds: 1.0,
+            search_ms: 20.0,
+            envelopes: false,
+            result: None,
+        }
+    }
+}
+
+impl ArrivalTime {
+    fn show_clip(ui: &mut Ui, label: &str, selected: &mut Option<ClipId>, ids: &[&ClipId]) {
+        let text = selected
+            .as_ref()
+            .map_or("Pick a clip".to_string(), ToString::to_string);
+        ComboBox::new(("tdoa_clip", label), label)
+            .selected_text(text)
+            .show_ui(ui, |ui| {
+                for id in ids {
+                    ui.selectable_value(selected, Some((*id).clone()), id.to_string());
+                }
+            });
+    }
+
+    fn compare(&self, clips: &BTreeMap<ClipId, Clip>) -> Option<Result<ArrivalDifference, String>> {
+        let first = clips.get(self.first.as_ref()?)?;
+        let second = clips.get(self.second.as_ref()?)?;
+        let sample_rate = first.read().sample_rate.0 as f64;
+        let start = (self.from_seconds * sample_rate) as usize;
+        let end = start + (self.length_seconds * sample_rate) as usize;
+        Some(
+            tdoa::arrival_difference(
+                first,
+                start..end,
+                second,
+                self.search_ms / 1000.0,
+                self.envelopes,
+            )
+            .map_err(|error| error.to_string()),
+        )
+    }
+
+    fn show_result(&self, ui: &mut Ui, difference: &ArrivalDifference) {
+        let (first, second) = match (&self.first, &self.second) {
+            (Some(first), Some(second)) => (first.to_string(), second.to_string()),
+            _ => return,
+        };
+        let (earlier, later) = if difference.delay >= 0.0 {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        ui.label(format!(
+            "Reached {} {:.1} µs before {}",
+            earlier,
+            difference.delay.abs() * 1e6,
+            later
+        ));
+        ui.label(format!(
+            "A path {:.2} km shorter to {}",
+            difference.delay.abs() * SPEED_OF_LIGHT / 1000.0,
+            earlier
+        ))
+        .on_hover_text(
+            "How much closer the transmitter is to one receiver than the other, on a direct path",
+        );
+        let correlation = ui.label(format!("Correlation {:.2}", difference.correlation));
+        if difference.correlation < WEAK_CORRELATION {
+            correlation.on_hover_text(
+                "A weak match, try a longer stretch, comparing envelopes, or a wider search",
+            );
+        }
+    }
+
+    pub fn show(&mut self, ctx: &Context, open: &mut bool, clips: &BTreeMap<ClipId, Clip>) {
+        Window::new("Arrival Time Difference")
+            .open(open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let ids: Vec<&ClipId> = clips.keys().collect();
+                Self::show_clip(ui, "First", &mut self.first, &ids);
+                Self::show_clip(ui, "Second", &mut self.second, &ids);
+                ui.horizontal(|ui| {
+                    ui.label("Look for");
+                    ui.add(
+                        DragValue::new(&mut self.length_seconds)
+                            .range(0.01..=60.0)
+                            .speed(0.05)
+                            .suffix(" s"),
+                    );
+                    ui.label("of the first from");
+                    ui.add(
+                        DragValue::new(&mut self.from_seconds)
+                            .range(0.0..=f64::MAX)
+                            .speed(0.1)
+                            .suffix(" s"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Within");
+                    ui.add(
+                        DragValue::new(&mut self.search_ms)
+                            .range(0.1..=10_000.0)
+                            .suffix(" ms"),
+                    )
+                    .on_hover_text("Either side of where the clips' start times put it");
+                });
+                ui.checkbox(&mut self.envelopes, "Compare envelopes")
+                    .on_hover_text(
+                        "For SSB and other modes where the receivers' oscillators aren't locked \
+                         together, so only the envelopes line up",
+                    );
+                if ui
+                    .add_enabled(
+                        self.first.is_some() && self.second.is_some(),
+                        egui::Button::new("Compare"),
+                    )
+                    .clicked()
+                {
+                    self.result = self.compare(clips);
+                }
+                match &self.result {
+                    Some(Ok(difference)) => self.show_result(ui, difference),
+                    Some(Err(error)) => {
+                        ui.colored_label(ui.visuals().error_fg_color, error);
+                    }
+                    None => {}
+                }
+            });
+    }
+}
@@ -0,0 +1,183 @@
+use crate::gui::export;
+use egui::{Color32, ColorImage, Image, TextureHandle, TextureOptions, Ui, load::SizedTexture};
+use hamshark::data::audio::Clip;
+use log::warn;
+use rayon::prelude::*;
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+const WIDTH: usize = 96;
+const HEIGHT: usize = 24;
+const WAVE_COLOR: Color32 = Color32::from_rgb(127, 127, 255);
+
+/// A little picture of a clip's waveform for the clip list. Rendered in the background so a
+/// long clip doesn't hold up the GUI, and kept until the clip grows. Finished clips' thumbnails
+/// are cached on disk so reopening a session doesn't draw them all again.
+pub struct Thumbnail {
+    clip: Clip,
+    cache_file: PathBuf,
+    /// Whether we've looked in the cache yet
+    checked_cache: bool,
+    texture: Option<TextureHandle>,
+    /// How many samples the clip had when the current texture was rendered
+    rendered_len: usize,
+    rendering: Option<JoinHandle<(usize, ColorImage)>>,
+}
+
+impl Thumbnail {
+    pub fn new(clip: Clip, cache_file: PathBuf) -> Self {
+        Self {
+            clip,
+            cache_file,
+            checked_cache: false,
+            texture: None,
+            rendered_len: 0,
+            rendering: None,
+        }
+    }
+
+    /// Summarize the whole clip by the min and max of the samples under each column
+    fn render(clip: &Clip) -> (usize, ColorImage) {
+        let len = clip.read().samples.len();
+        let mut pixels = vec![Color32::TRANSPARENT; WIDTH * HEIGHT];
+        let to_y = |sample: f32| {
+            ((1.0 - sample.clamp(-1.0, 1.0)) * 0.5 * (HEIGHT - 1) as f32).round() as usize
+        };
+        // The columns are summarized on every core at once
+        let columns: Vec<Option<(f32, f32)>> = (0..WIDTH)
+            .into_par_iter()
+            .map(|x| {
+                let range = x * len / WIDTH..((x + 1) * len / WIDTH).max(x * len / WIDTH + 1);
+                // Take the lock a column at a time so a recording isn't held up, and not
+                // while reading back from the file
+                let bucket = clip.read().samples.fetch(range).read();
+                (!bucket.is_empty()).then(|| {
+                    bucket.iter().fold((f32::MIN, f32::MAX), |acc, x| {
+                        (acc.0.max(*x), acc.1.min(*x))
+                    })
+                })
+            })
+            .collect();
+        for (x, column) in columns.into_iter().enumerate() {
+            let Some((max, min)) = column else {
+                break;
+            };
+            for y in to_y(max)..=to_y(min) {
+                pixels[y * WIDTH + x] = WAVE_COLOR;
+            }
+        }
+        (len, ColorImage::new([WIDTH, HEIGHT], pixels))
+    }
+
+    /// Whether the clip has changed enough to be worth drawing again. A clip that's still
+    /// recording gets redrawn every second or so of new audio.
+    fn is_stale(&self) -> bool {
+        let clip = self.clip.read();
+        let len = clip.samples.len();
+        if clip.is_recording() {
+            len >= self.rendered_len + clip.sample_rate.0 as usize
+        } else {
+            len != self.rendered_len
+        }
+    }
+
+    /// The cached thumbnail, if the clip is finished and hasn't changed since it was cached
+    fn load_cached(&self) -> Option<ColorImage> {
+        let clip = self.clip.read();
+        if clip.is_recording() {
+            return None;
+        }
+        let clip_modified = fs::metadata(clip.path()).and_then(|m| m.modified()).ok()?;
+        drop(clip);
+        let cache_modified = fs::metadata(&self.cache_file)
+            .and_then(|m| m.modified())
+            .ok()?;
+        if cache_modified < clip_modified {
+            return None;
+        }
+        read_png(&self.cache_file).filter(|image| image.size == [WIDTH, HEIGHT])
+    }
+
+    fn save_cached(cache_file: &Path, image: &ColorImage) {
+        if let Some(dir) = cache_file.parent()
+            && let Err(error) = fs::create_dir_all(dir)
+        {
+            warn!("Unable to create thumbnail cache {:?}: {}", dir, error);
+            return;
+        }
+        if let Err(error) = export::write_png(cache_file, image) {
+            warn!("Unable to cache thumbnail {:?}: {}", cache_file, error);
+        }
+    }
+
+    fn set_texture(&mut self, ui: &Ui, len: usize, image: ColorImage) {
+        self.rendered_len = len;
+        let texture = ui.ctx().load_texture(
+            format!("thumbnail {}", self.clip.read().id()),
+            image,
+            TextureOptions::LINEAR,
+        );
+        self.texture = Some(texture);
+    }
+
+    pub fn show(&mut self, ui: &mut Ui) {
+        if !self.checked_cache {
+            self.checked_cache = true;
+            if let Some(image) = self.load_cached() {
+                let len = self.clip.read().samples.len();
+                self.set_texture(ui, len, image);
+            }
+        }
+
+        if let Some(rendering) = self.rendering.take_if(|rendering| rendering.is_finished())
+            && let Ok((len, image)) = rendering.join()
+        {
+            self.set_texture(ui, len, image);
+        }
+
+        if self.rendering.is_none() && self.is_stale() {
+            let clip = self.clip.clone();
+            let cache_file = self.cache_file.clone();
+            self.rendering = Some(thread::spawn(move || {
+                let (len, image) = Self::render(&clip);
+                if !clip.read().is_recording() {
+                    Self::save_cached(&cache_file, &image);
+                }
+                (len, image)
+            }));
+        }
+        if self.rendering.is_some() {
+            // Check back for the finished thumbnail
+            ui.ctx().request_repaint_after(Duration::from_millis(100));
+        }
+
+        match &self.texture {
+            Some(texture) => {
+                ui.add(Image::new(SizedTexture::new(texture, texture.size_vec2())));
+            }
+            None => {
+                ui.allocate_space(egui::Vec2::new(WIDTH as f32, HEIGHT as f32));
+            }
+        }
+    }
+}
+
+fn read_png(path: &Path) -> Option<ColorImage> {
+    let decoder = png::Decoder::new(BufReader::new(File::open(path).ok()?));
+    let mut reader = decoder.read_info().ok()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).ok()?;
+    if info.color_type != png::ColorType::Rgba || info.bit_depth != png::BitDepth::Eight {
+        return None;
+    }
+    // These were written straight out of a ColorImage, so they're already premultiplied
+    Some(ColorImage::from_rgba_premultiplied(
+        [info.width as usize, info.height as usize],
+        &buffer[..info.buffer_size()],
+    ))
+}
@@ -0,0 +1,449 @@
+use crate::gui::view::{
+    CURSOR_COLOR, ColumnTexture, DAMAGE_COLOR, DragState, LOOP_COLOR, MARKER_COLOR, PLAYHEAD_COLOR,
+    Scaler, ViewTransform, consume_scroll, pointer_pos_from_response, screen_to_image_idx,
+};
+use egui::{
+    Color32, ColorImage, DragValue, FontId, Key, PointerButton, Pos2, Rect, Response, Sense, Vec2,
+};
+use hamshark::data::{
+    audio::{Clip, Selection, nearest_zero_crossing},
+    metadata::{LoopPoints, Marker},
+};
+use log::error;
+
+/// How far either side of the pointer, in pixels, to look for a zero crossing to snap to
+const SNAP_PIXELS: f32 = 8.0;
+
+/// The sample amplitude view of a clip
+pub struct Timeline {
+    /// The allocated screen height of the timeline control
+    height: usize,
+    /// The desired vertical scale
+    pub vscale: f32,
+    /// The clip we're browsing
+    clip: Clip,
+    /// Selection Markers
+    pub selection: Option<Selection>,
+    /// Make drag operations more precise
+    drag_state: DragState,
+    /// Lock selection edges to zero crossings so the selection doesn't click at the ends
+    pub snap_to_zero: bool,
+    pub loop_points: LoopPoints,
+    /// Where playback is up to, if we're playing
+    pub playhead: Option<usize>,
+    /// The samples drawn so far while following live
+    live: Option<LiveWaveform>,
+    texture: ColumnTexture,
+}
+
+/// The samples of a live clip as last drawn, without the selection and markers, so the next
+/// frame only has to draw what's arrived since. Columns are on a grid fixed to the start of
+/// the clip, so they stay put as the view scrolls.
+struct LiveWaveform {
+    /// What it was drawn for, anything else starts it over
+    width: usize,
+    height: usize,
+    scale: f32,
+    vscale: f32,
+    /// The column of the grid on the left of the view
+    first: usize,
+    /// How many columns from the left have samples drawn in them
+    drawn: usize,
+    image: Vec<Color32>,
+}
+
+impl Timeline {
+    pub fn new(clip: Clip) -> Self {
+        Self {
+            clip,
+            height: 256,
+            vscale: 1.0,
+            selection: None,
+            drag_state: DragState::NotDragging,
+            snap_to_zero: false,
+            loop_points: Default::default(),
+            playhead: None,
+            live: None,
+            texture: ColumnTexture::new("samples"),
+        }
+    }
+
+    /// Translate a sample to a screen coordinate
+    fn sample_to_y_coordinate(&self, height: usize, sample: f32) -> usize {
+        let halfheight = height as f32 / 2f32;
+        (self.vscale * sample * halfheight + halfheight) as usize
+    }
+
+    /// Draw a labeled flag at the top of each visible marker. Hovering a flag shows the
+    /// full label and position, clicking it seeks the view to the marker.
+    fn show_marker_flags(
+        &mut self,
+        ui: &mut egui::Ui,
+        view: &mut ViewTransform,
+        bounds: Rect,
+        markers: &[Marker],
+    ) {
+        let painter = ui.painter_at(bounds);
+        for (i, marker) in markers.iter().enumerate() {
+            let Some(x) = view.marker_screen_x(marker) else {
+                continue;
+            };
+            let galley = painter.layout_no_wrap(
+                marker.label.clone(),
+                FontId::proportional(11.0),
+                Color32::BLACK,
+            );
+            let flag = Rect::from_min_size(
+                Pos2::new(bounds.min.x + x as f32, bounds.min.y),
+                galley.size() + Vec2::new(4.0, 2.0),
+            );
+            painter.rect_filled(flag, 2.0, MARKER_COLOR);
+            painter.galley(flag.min + Vec2::new(2.0, 1.0), galley, Color32::BLACK);
+
+            let response = ui
+                .interact(flag, ui.id().with(("marker", i)), Sense::click())
+                .on_hover_text(format!("{}\nSample {}", marker.label, marker.position));
+            if response.clicked() {
+                view.seek(marker.position);
+            }
+        }
+    }
+
+    /// Drop a new marker on the clip at the given screen X coordinate
+    fn add_marker_at(&mut self, view: &ViewTransform, x: usize, number: usize) {
+        let position = view.screen_to_data_x(x as isize) as usize;
+        let marker = Marker::new(position, format!("Marker {}", number));
+        if let Err(error) = self.clip.write().add_marker(marker) {
+            error!("Unable to save marker: {}", error);
+        }
+    }
+
+    /// Translate a screen X coordinate into a selection edge, snapping it if asked to
+    fn selection_edge(&self, view: &ViewTransform, x: usize) -> usize {
+        let position = view.screen_to_data_x(x as isize) as usize;
+        if !self.snap_to_zero {
+            return position;
+        }
+        // Look a few pixels either side, however many samples that is at this zoom
+        let radius = (view.scale * SNAP_PIXELS) as usize;
+        let from = position.saturating_sub(radius);
+        let nearby = self
+            .clip
+            .read()
+            .samples
+            .range(from..position + radius + 1)
+            .into_owned();
+        nearest_zero_crossing(&nearby, position - from, radius)
+            .map_or(position, |crossing| from + crossing)
+    }
+
+    /// Show the controls that only apply to the sample view
+    pub fn show_controls(&mut self, ui: &mut egui::Ui) {
+        ui.add(
+            DragValue::new(&mut self.vscale)
+                .range(1.0f32..=200.0f32)
+                .prefix("VScale: "),
+        )
+        .on_hover_text("Scales the timeline amplitude");
+        ui.checkbox(&mut self.snap_to_zero, "Snap")
+            .on_hover_text("Snap selection edges to the nearest zero crossing");
+    }
+
+    /// Draw the samples, selection and markers seen through view. The cursor is left off so
+    /// the image is also suitable for exporting.
+    pub fn render(&self, view: &ViewTransform) -> ColorImage {
+        self.render_with_height(view, self.height)
+    }
+
+    /// Like render, but at some other height than the timeline's own
+    pub fn render_with_height(&self, view: &ViewTransform, height: usize) -> ColorImage {
+        let width = view.width;
+
+        // The amplitude image is drawn horizontally.
+        // The most recent sample is on the right.
+        // Zero is in the center. Lines drawn at +-128
+        let mut samples_image = std::vec::from_elem(Color32::from_gray(0), width * height);
+
+        // Acquire read lock on samples, and fetch everything in view at once in case it has
+        // to be read back from the file
+        let read_lock = self.clip.read();
+        let first = view.screen_x_coordinate_to_data_range(0).start;
+        let last = view
+            .screen_x_coordinate_to_data_range(width.saturating_sub(1))
+            .end;
+        let samples = read_lock.samples.range(first..last.max(first));
+
+        // Draw the sample amplitudes by looping over the width of the timeline view
+        // Each pixel may represent one or more samples, we will deal with that inside the loo
+        for i in 0..width {
+            // Skip drawing anything if there are no samples yet
+            if samples.is_empty() {
+                break;
+            }
+
+            // Derive the sample range for the current screen X coordinate
+            let sample_range = view.screen_x_coordinate_to_data_range(i);
+
+            // sample_range will be empty if the beginning is beyond the length of the data, in which case we're done
+            if sample_range.is_empty() {
+                break;
+            }
+            let Some(bucket) = samples.get(sample_range.start - first..sample_range.end - first)
+            else {
+                break;
+            };
+            self.draw_column(&mut samples_image, width, height, i, bucket);
+        }
+        drop(read_lock);
+
+        self.draw_overlays(&mut samples_image, view, height);
+        ColorImage::new([width, height], samples_image)
+    }
+
+    /// Draw the samples one screen column stands for into it, on a black background
+    fn draw_column(
+        &self,
+        image: &mut [Color32],
+        width: usize,
+        height: usize,
+        x: usize,
+        bucket: &[f32],
+    ) {
+        let color = |y: usize| {
+            if y == 0 || y > height - 1 {
+                Color32::from_rgb(255, 0, 0)
+            } else {
+                Color32::from_rgb(127, 127, 255)
+            }
+        };
+        // If the range only contains one sample, just draw one sample. This means scaling factor is 1.
+        if let [sample] = bucket {
+            let y = self.sample_to_y_coordinate(height, *sample);
+            image[screen_to_image_idx(width, height, x, y)] = color(y);
+        }
+        // Otherwise we summarize a range of values within one pixel by their max and min
+        else {
+            // Take the maximum and minimum values over the samples in this bucket
+            let (f32max, f32min) = bucket.iter().fold((f32::MIN, f32::MAX), |acc, x| {
+                (acc.0.max(*x), acc.1.min(*x))
+            });
+
+            let displaymax = self.sample_to_y_coordinate(height, f32max);
+            let displaymin = self.sample_to_y_coordinate(height, f32min);
+
+            for y in displaymin..displaymax {
+                image[screen_to_image_idx(width, height, x, y)] = color(y);
+            }
+        }
+    }
+
+    /// Highlight the selection behind the samples and draw the markers and loop points over
+    /// them
+    fn draw_overlays(&self, image: &mut [Color32], view: &ViewTransform, height: usize) {
+        let width = view.width;
+        let background = Color32::from_gray(0);
+
+        // Draw selection area by highlighting background
+        if let Some(selection) = &self.selection {
+            for x in view.data_x_range_to_screen_x_range(&selection.range()) {
+                for y in 0..height {
+                    let idx = screen_to_image_idx(width, height, x, y);
+                    if image[idx] == background {
+                        image[idx] = Color32::from_rgb(0, 0, 128);
+                    }
+                }
+            }
+        }
+
+        // Shade what was damaged in the file, with a line where it just stops
+        for damage in &self.clip.read().metadata.damaged {
+            let columns = if damage.range.is_empty() {
+                view.position_screen_x(damage.range.start)
+                    .map_or(0..0, |x| x..x + 1)
+            } else {
+                view.data_x_range_to_screen_x_range(&damage.range)
+            };
+            for x in columns {
+                for y in 0..height {
+                    let idx = screen_to_image_idx(width, height, x, y);
+                    if image[idx] == background || damage.range.is_empty() {
+                        image[idx] = DAMAGE_COLOR;
+                    }
+                }
+            }
+        }
+
+        // Draw each visible marker as a vertical line, the flags get painted over the image later
+        for marker in &self.clip.read().metadata.markers {
+            if let Some(x) = view.marker_screen_x(marker) {
+                for y in 0..height {
+                    let idx = screen_to_image_idx(width, height, x, y);
+                    image[idx] = MARKER_COLOR;
+                }
+            }
+        }
+
+        // Draw the loop points
+        for position in [self.loop_points.a, self.loop_points.b]
+            .into_iter()
+            .flatten()
+        {
+            if let Some(x) = view.position_screen_x(position) {
+                for y in 0..height {
+                    let idx = screen_to_image_idx(width, height, x, y);
+                    image[idx] = LOOP_COLOR;
+                }
+            }
+        }
+    }
+
+    /// Like render, but while following a live clip only the columns with new samples in them
+    /// are drawn, and the rest is scrolled along from the last frame
+    fn render_live(&mut self, view: &ViewTransform) -> ColorImage {
+        let (width, height) = (view.width, self.height);
+        let (first, columns) = view.live_columns();
+        let mut live = self
+            .live
+            .take()
+            .filter(|live| {
+                live.width == width
+                    && live.height == height
+                    && live.scale == view.scale
+                    && live.vscale == self.vscale
+                    && live.first <= first
+            })
+            .unwrap_or_else(|| LiveWaveform {
+                width,
+                height,
+                scale: view.scale,
+                vscale: self.vscale,
+                first,
+                drawn: 0,
+                image: std::vec::from_elem(Color32::from_gray(0), width * height),
+            });
+
+        // Scroll what's already drawn left by however far the view moved
+        let shift = (first - live.first).min(width);
+        if shift > 0 {
+            for row in live.image.chunks_exact_mut(width) {
+                row.copy_within(shift.., 0);
+                row[width - shift..].fill(Color32::from_gray(0));
+            }
+            live.first = first;
+            live.drawn = live.drawn.saturating_sub(shift);
+        }
+
+        // The last column drawn may have been partway through filling, so it's drawn again
+        {
+            let clip = self.clip.read();
+            let len = clip.samples.len();
+            let bound = |bucket: usize| ((bucket as f32 * view.scale).floor() as usize).min(len);
+            let columns = live.drawn.saturating_sub(1)..columns.min(width);
+            let fetched_from = bound(first + columns.start);
+            let samples = clip.samples.range(fetched_from..bound(first + columns.end));
+            for x in columns {
+                let start = bound(first + x) - fetched_from;
+                let end = bound(first + x + 1) - fetched_from;
+                for row in live.image.chunks_exact_mut(width) {
+                    row[x] = Color32::from_gray(0);
+                }
+                if start < end {
+                    self.draw_column(&mut live.image, width, height, x, &samples[start..end]);
+                }
+            }
+        }
+        live.drawn = columns.min(width);
+
+        let mut samples_image = live.image.clone();
+        self.live = Some(live);
+        self.draw_overlays(&mut samples_image, view, height);
+        ColorImage::new([width, height], samples_image)
+    }
+
+    pub fn update_and_show(&mut self, ui: &mut egui::Ui, view: &mut ViewTransform) -> Response {
+        // I am assuming that egui will scale this properly but it may need to be revisited after
+        // experimentation. Look into ui.pixels_per_point() if necessary.
+        let width = view.width;
+        let mut samples_image = if view.live {
+            self.render_live(view)
+        } else {
+            self.live = None;
+            self.render(view)
+        };
+
+        // Markers are few, so take a copy rather than holding the lock while we paint
+        let markers = self.clip.read().metadata.markers.clone();
+
+        // Overlay the playhead and a vertical line representing the current cursor position
+        // if the mouse is hovering
+        if let Some(x) = self
+            .playhead
+            .and_then(|position| view.position_screen_x(position))
+        {
+            for y in 0..self.height {
+                let idx = screen_to_image_idx(width, self.height, x, y);
+                samples_image.pixels[idx] = PLAYHEAD_COLOR;
+            }
+        }
+        if let Some(x) = view.cursor {
+            for y in 0..self.height {
+                let idx = screen_to_image_idx(width, self.height, x, y);
+                samples_image.pixels[idx] = CURSOR_COLOR;
+            }
+        }
+
+        // Show the timeline, uploading only what's changed since the last frame
+        let origin = view.live.then(|| view.live_columns().0);
+        let samples_response = self.texture.show(
+            ui,
+            samples_image,
+            origin,
+            Sense::click_and_drag() | Sense::hover(),
+        );
+
+        // Label the marker lines with flags along the top of the view
+        self.show_marker_flags(ui, view, samples_response.rect, &markers);
+
+        // Handle mouse interaction with timeline
+        self.drag_state.track(&samples_response);
+        if samples_response.dragged_by(PointerButton::Primary)
+            && let Some(cur) = pointer_pos_from_response(&samples_response)
+        {
+            let current = self.selection_edge(view, cur.x);
+            if let DragState::DownButNotDragging(begin) = self.drag_state {
+                self.selection = Some(Selection::new(self.selection_edge(view, begin.x), current));
+            } else if let Some(selection) = &mut self.selection {
+                selection.extend_to(current);
+            }
+        }
+        view.interact(ui, &samples_response, &mut self.drag_state);
+        if samples_response.hovered() {
+            let (scroll, shift) =
+                ui.input(|input| (input.smooth_scroll_delta, input.modifiers.shift));
+            if shift && scroll != Vec2::ZERO {
+                // egui turns shift+wheel into horizontal scrolling, so take either direction
+                self.vscale =
+                    (self.vscale * (0.01 * (scroll.x + scroll.y)).exp()).clamp(1.0, 200.0);
+                consume_scroll(ui);
+            }
+        }
+        if samples_response.hovered()
+            && let Some(x) = view.cursor
+        {
+            let position = view.screen_to_data_x(x as isize) as usize;
+            ui.input(|input| {
+                if input.key_pressed(Key::M) {
+                    self.add_marker_at(view, x, markers.len() + 1);
+                }
+                if input.key_pressed(Key::A) {
+                    self.loop_points.a = Some(position);
+                }
+                if input.key_pressed(Key::B) {
+                    self.loop_points.b = Some(position);
+                }
+            });
+        }
+
+        samples_response
+    }
+}
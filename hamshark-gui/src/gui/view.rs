@@ -0,0 +1,559 @@
+use egui::{
+    Color32, ColorImage, ComboBox, DragValue, Image, Pos2, Rect, Response, Sense, TextureHandle,
+    TextureOptions, Vec2, load::SizedTexture, pos2,
+};
+use hamshark::data::{
+    averaging::{Averaging, AveragingMode},
+    metadata::Marker,
+};
+use mint::Vector2;
+use std::ops::Range;
+
+pub const MARKER_COLOR: Color32 = Color32::from_rgb(255, 200, 0);
+pub const CURSOR_COLOR: Color32 = Color32::from_rgb(255, 0, 0);
+pub const LOOP_COLOR: Color32 = Color32::from_rgb(0, 220, 220);
+pub const PLAYHEAD_COLOR: Color32 = Color32::from_rgb(255, 255, 255);
+/// Where a clip's WAV file was damaged when it was loaded
+pub const DAMAGE_COLOR: Color32 = Color32::from_rgb(110, 0, 0);
+
+#[derive(Default, PartialEq)]
+pub enum DragState {
+    DownButNotDragging(Vector2<usize>),
+    Dragging,
+    #[default]
+    NotDragging,
+}
+
+impl DragState {
+    /// In egui, the "drag" deltas start reporting after the mouse has moved, and so if you click
+    /// precisely where you mean to begin the drag, it will not begin where you expected.
+    /// Submitting a patch to egui is probably better than this mess...
+    pub fn track(&mut self, response: &Response) {
+        if response.is_pointer_button_down_on() {
+            if *self == DragState::NotDragging
+                && let Some(pos) = pointer_pos_from_response(response)
+            {
+                *self = DragState::DownButNotDragging(pos);
+            }
+        } else {
+            *self = DragState::NotDragging;
+        }
+    }
+
+    pub fn correct_drag_delta(&mut self, response: &Response) -> Vector2<isize> {
+        match self {
+            DragState::DownButNotDragging(pos) => {
+                if let Some(cur) = pointer_pos_from_response(response) {
+                    let delta = Vector2 {
+                        x: cur.x as isize - pos.x as isize,
+                        y: cur.y as isize - pos.y as isize,
+                    };
+                    *self = DragState::Dragging;
+                    delta
+                } else {
+                    panic!("In dragging state but no current mouse position")
+                }
+            }
+            DragState::Dragging => {
+                let delta = response.drag_delta();
+                Vector2 {
+                    x: delta.x.floor() as isize,
+                    y: delta.y.floor() as isize,
+                }
+            }
+            DragState::NotDragging => panic!("Should not be able to get to this state"),
+        }
+    }
+}
+
+pub fn input_pos(bounds: &Rect, pos: Option<Pos2>) -> Option<Vector2<usize>> {
+    let mut bounds = *bounds;
+    bounds.max.x -= 1.0f32;
+    bounds.max.y -= 1.0f32;
+    pos.and_then(|pos| {
+        if bounds.contains(pos) {
+            Some(Vector2 {
+                x: (pos.x - bounds.min.x).floor() as usize,
+                y: (pos.y - bounds.min.y).floor() as usize,
+            })
+        } else {
+            None
+        }
+    })
+}
+
+/// Stop the scroll wheel from also scrolling whatever the view is inside of
+pub fn consume_scroll(ui: &egui::Ui) {
+    ui.input_mut(|input| input.smooth_scroll_delta = Vec2::ZERO);
+}
+
+pub fn pointer_pos_from_response(response: &Response) -> Option<Vector2<usize>> {
+    input_pos(&response.rect, response.interact_pointer_pos())
+}
+
+/// The averaging mode, how many spectra and how much smoothing, side by side. `id` keeps
+/// the mode menus of different views apart. Returns whether anything changed.
+pub fn show_averaging_controls(
+    ui: &mut egui::Ui,
+    id: impl std::hash::Hash,
+    averaging: &mut Averaging,
+) -> bool {
+    let before = *averaging;
+    ComboBox::new(id, "Avg")
+        .selected_text(averaging.mode.to_string())
+        .show_ui(ui, |ui| {
+            for mode in AveragingMode::ALL {
+                ui.selectable_value(&mut averaging.mode, mode, mode.to_string());
+            }
+        })
+        .response
+        .on_hover_text("Average spectra over time, so weak steady signals stand out");
+    ui.add_enabled(
+        averaging.mode != AveragingMode::Off,
+        DragValue::new(&mut averaging.frames)
+            .range(2..=64)
+            .suffix(" frames"),
+    )
+    .on_hover_text("How many spectra to average");
+    ui.add(
+        DragValue::new(&mut averaging.smoothing)
+            .range(0..=16)
+            .prefix("Smooth ±")
+            .suffix(" bins"),
+    )
+    .on_hover_text("Average each bin with its neighbours");
+    *averaging != before
+}
+
+/// Translate screen coordinates to vector position
+pub fn screen_to_image_idx(width: usize, height: usize, x: usize, y: usize) -> usize {
+    (y.clamp(0, height - 1) * width) + x.clamp(0, width - 1)
+}
+
+/// Maps between screen X coordinates and sample indexes
+pub trait Scaler {
+    /// The allocated screen width of the view
+    fn width(&self) -> usize;
+    /// The length of the data being viewed
+    fn data_len(&self) -> usize;
+    /// Samples per pixel
+    fn scale(&self) -> f32;
+    /// The first sample in view
+    fn offset(&self) -> usize;
+
+    fn screen_to_data_x_without_offset(&self, x: isize) -> isize {
+        (x as f32 * self.scale()).floor() as isize
+    }
+
+    fn screen_x_coordinate_to_data_range(&self, x: usize) -> Range<usize> {
+        let x = x as isize;
+        (self.screen_to_data_x(x) as usize)
+            ..(self
+                .screen_to_data_x(x + 1)
+                .clamp(0, self.data_len() as isize) as usize)
+    }
+
+    fn data_x_range_to_screen_x_range(&self, range: &Range<usize>) -> Range<usize> {
+        (self
+            .data_to_screen_x(range.start as isize)
+            .clamp(0, self.width() as isize) as usize)
+            ..(self
+                .data_to_screen_x(range.end as isize)
+                .clamp(0, self.width() as isize) as usize)
+    }
+
+    fn screen_to_data_x(&self, x: isize) -> isize {
+        self.screen_to_data_x_without_offset(x) + self.offset() as isize
+    }
+
+    fn data_to_screen_x_without_offset(&self, x: isize) -> isize {
+        (x as f32 / self.scale()).floor() as isize
+    }
+
+    fn data_to_screen_x(&self, x: isize) -> isize {
+        self.data_to_screen_x_without_offset(x - self.offset() as isize)
+    }
+}
+
+/// The horizontal view state of a clip. Every view of the clip (samples, waterfall) draws
+/// through the same transform, so panning or zooming one of them moves all of them.
+#[derive(Clone)]
+pub struct ViewTransform {
+    /// The allocated screen width of the views
+    pub width: usize,
+    /// The last recorded length of the sample data
+    pub sample_len: usize,
+    /// The desired horizontal scale (samples:pixel, so a scale of 5 means 5:1)
+    pub scale: f32,
+    /// The "start" offset in data space
+    pub offset: usize,
+    /// Keep up with live
+    pub live: bool,
+    /// Cursor X position in screen space, shared so every view draws the same cursor line
+    pub cursor: Option<usize>,
+}
+
+impl Default for ViewTransform {
+    fn default() -> Self {
+        Self {
+            width: 1,
+            sample_len: 0,
+            scale: 1024.0,
+            offset: 0,
+            live: true,
+            cursor: None,
+        }
+    }
+}
+
+impl ViewTransform {
+    /// A still view showing exactly range across about width pixels. The width shrinks if the
+    /// range has fewer samples than pixels, since a pixel can't show less than one sample.
+    pub fn fit(range: &Range<usize>, width: usize, sample_len: usize) -> Self {
+        let width = width.clamp(1, range.len().max(1));
+        Self {
+            width,
+            sample_len,
+            scale: (range.len() as f32 / width as f32).max(1.0),
+            offset: range.start,
+            live: false,
+            cursor: None,
+        }
+    }
+
+    /// The samples in view, from the left edge to the right
+    pub fn visible_range(&self) -> Range<usize> {
+        let end = self
+            .screen_to_data_x(self.width as isize)
+            .clamp(0, self.sample_len as isize) as usize;
+        self.offset.min(end)..end
+    }
+
+    /// Update for any changes in the sample data, and if live, move with it
+    pub fn follow(&mut self, sample_len: usize) {
+        self.sample_len = sample_len;
+        if self.live {
+            // A whole column at a time, so what's been drawn can be scrolled along with it
+            let (first, _) = self.live_columns();
+            self.offset = (first as f32 * self.scale).floor() as usize;
+        }
+    }
+
+    /// Following live, the view is a window onto columns of `scale` samples each, counted
+    /// from the start of the clip. The column on the left of the view and how many have
+    /// samples in them, the last of which may still be filling.
+    pub fn live_columns(&self) -> (usize, usize) {
+        let total = (self.sample_len as f32 / self.scale).ceil() as usize;
+        let first = total.saturating_sub(self.width);
+        (first, total - first)
+    }
+
+    pub fn pan(&mut self, delta: Vector2<isize>) {
+        self.live = false;
+        let newoffset = self.offset as isize - self.screen_to_data_x_without_offset(delta.x);
+        self.offset = newoffset.clamp(0, isize::MAX) as usize;
+    }
+
+    /// Pan by a (possibly fractional) number of pixels, the way a scroll wheel does
+    pub fn scroll(&mut self, pixels: f32) {
+        self.live = false;
+        let newoffset = self.offset as f32 - pixels * self.scale;
+        self.offset = newoffset.max(0.0) as usize;
+    }
+
+    /// Updates the scale and offset, centered at screen_pos
+    /// If we're "live", then only update the scale. The "live" mechanism will take care of the offset.
+    pub fn update_scale(&mut self, scale: f32, screen_pos: usize) {
+        // If the scale doesn't change, don't change anything
+        let scale = scale.clamp(1.0f32, f32::MAX);
+        if self.scale == scale {
+            return;
+        }
+        let adj_offset = self.screen_to_data_x(screen_pos as isize);
+        self.scale = scale;
+        if !self.live {
+            let new_offset = adj_offset - self.screen_to_data_x_without_offset(screen_pos as isize);
+            self.offset = if new_offset < 0 {
+                0
+            } else {
+                new_offset as usize
+            };
+        }
+    }
+
+    /// Show exactly range across the view. This stops following live data.
+    pub fn zoom_to(&mut self, range: &Range<usize>) {
+        self.live = false;
+        self.scale = (range.len() as f32 / self.width.max(1) as f32).max(1.0);
+        self.offset = range.start;
+    }
+
+    /// Quick zooms to the levels that come up all the time
+    pub fn show_zoom_presets(
+        &mut self,
+        ui: &mut egui::Ui,
+        sample_rate: u32,
+        selection: Option<&Range<usize>>,
+    ) {
+        let halfwidth = self.width / 2;
+        // The scale that fits one second across the view
+        let second = sample_rate as f32 / self.width.max(1) as f32;
+        if ui
+            .button("Fit")
+            .on_hover_text("Fit the whole clip")
+            .clicked()
+        {
+            self.zoom_to(&(0..self.sample_len));
+        }
+        if let Some(selection) = selection
+            && ui
+                .button("Fit sel")
+                .on_hover_text("Fit the selection")
+                .clicked()
+        {
+            self.zoom_to(selection);
+        }
+        if ui
+            .button("1:1")
+            .on_hover_text("1 sample per pixel")
+            .clicked()
+        {
+            self.update_scale(1.0, halfwidth);
+        }
+        if ui
+            .button("1 s")
+            .on_hover_text("1 second across the view")
+            .clicked()
+        {
+            self.update_scale(second, halfwidth);
+        }
+        if ui
+            .button("1 min")
+            .on_hover_text("1 minute across the view")
+            .clicked()
+        {
+            self.update_scale(60.0 * second, halfwidth);
+        }
+    }
+
+    /// Center the view on a sample position. This stops following live data.
+    pub fn seek(&mut self, position: usize) {
+        self.live = false;
+        let halfwidth = self.screen_to_data_x_without_offset(self.width as isize / 2);
+        self.offset = (position as isize - halfwidth).clamp(0, isize::MAX) as usize;
+    }
+
+    /// The screen X coordinate of a marker, if it's currently in view
+    pub fn marker_screen_x(&self, marker: &Marker) -> Option<usize> {
+        self.position_screen_x(marker.position)
+    }
+
+    /// The screen X coordinate of a sample position, if it's currently in view
+    pub fn position_screen_x(&self, position: usize) -> Option<usize> {
+        let x = self.data_to_screen_x(position as isize);
+        if x >= 0 && (x as usize) < self.width {
+            Some(x as usize)
+        } else {
+            None
+        }
+    }
+
+    /// The interactions every view of a clip has in common: panning with the secondary
+    /// button, the scroll wheel or two fingers, zooming around the cursor (pinch or
+    /// ctrl+wheel) and tracking the cursor position.
+    pub fn interact(&mut self, ui: &egui::Ui, response: &Response, drag_state: &mut DragState) {
+        if response.dragged_by(egui::PointerButton::Secondary) {
+            let delta = drag_state.correct_drag_delta(response);
+            self.pan(delta);
+        }
+        if let Some(touch) = ui.input(|input| input.multi_touch())
+            && response.rect.contains(touch.center_pos)
+        {
+            // Two fingers pan and pinch around the point between them, the same as the
+            // wheel and zoom do around the mouse
+            let x = (touch.center_pos.x - response.rect.min.x).floor() as usize;
+            if touch.translation_delta.x != 0.0 {
+                self.scroll(touch.translation_delta.x);
+            }
+            self.update_scale(self.scale / touch.zoom_delta, x);
+        } else if response.hovered() {
+            self.cursor = input_pos(&response.rect, response.hover_pos()).map(|pos| pos.x);
+            if let Some(x) = self.cursor {
+                // zoom_delta is above 1 for zooming in, which means fewer samples per pixel
+                let newscale = self.scale / ui.input(|input| input.zoom_delta());
+                self.update_scale(newscale, x);
+            }
+            // Shift+wheel belongs to the individual views
+            let (scroll, shift) =
+                ui.input(|input| (input.smooth_scroll_delta, input.modifiers.shift));
+            if !shift && scroll != Vec2::ZERO {
+                self.scroll(scroll.x + scroll.y);
+                consume_scroll(ui);
+            }
+        }
+    }
+
+    /// Show the controls for the shared view state
+    pub fn show_controls(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.live, "Live")
+            .on_hover_text("If checked, the timeline will auto-scroll to keep up with live data.");
+
+        let mut newscale = self.scale;
+
+        ui.add(
+            DragValue::new(&mut newscale)
+                .range(1.0f32..=f32::MAX)
+                .prefix("Scale: "),
+        )
+        .on_hover_text("Scales the timeline view to N samples per 1 pixel.");
+
+        ui.label(format!("O: {}", self.offset));
+        if let Some(x) = self.cursor {
+            let range = self.screen_x_coordinate_to_data_range(x);
+            let text = if range.len() > 1 {
+                format!("P: {}-{}", range.start, range.end)
+            } else {
+                format!("P: {}", range.start)
+            };
+            ui.label(text);
+        }
+
+        // If zooming using the widget, keep it centered
+        let halfwidth = self.width / 2;
+        self.update_scale(newscale, halfwidth);
+    }
+}
+
+impl Scaler for ViewTransform {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn data_len(&self) -> usize {
+        self.sample_len
+    }
+
+    fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+/// A texture kept from one frame to the next, where only the columns that changed are
+/// uploaded again. Views that scroll along a fixed grid of columns say where they are on it,
+/// and the texture is used as a ring so columns that only moved don't go up again.
+pub struct ColumnTexture {
+    name: &'static str,
+    handle: Option<TextureHandle>,
+    /// What's in the texture, in screen order
+    shown: Vec<Color32>,
+    size: [usize; 2],
+    /// The texture column on the left of the screen
+    ring_start: usize,
+    /// The grid column on the left of the screen, last frame
+    origin: Option<usize>,
+}
+
+impl ColumnTexture {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            handle: None,
+            shown: Vec::new(),
+            size: [0, 0],
+            ring_start: 0,
+            origin: None,
+        }
+    }
+
+    /// Bring the texture up to date with image and show it. `origin` is the grid column on
+    /// the left of the image, for a view that scrolls along a fixed grid.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        image: ColorImage,
+        origin: Option<usize>,
+        sense: Sense,
+    ) -> Response {
+        self.update(ui.ctx(), image, origin);
+        let [width, height] = self.size;
+        let left = self.ring_start as f32 / width.max(1) as f32;
+        let Some(handle) = &self.handle else {
+            return ui.allocate_response(Vec2::ZERO, sense);
+        };
+        ui.add(
+            Image::new(SizedTexture::new(
+                handle.id(),
+                Vec2::new(width as f32, height as f32),
+            ))
+            .uv(Rect::from_min_max(pos2(left, 0.0), pos2(left + 1.0, 1.0)))
+            .sense(sense),
+        )
+    }
+
+    fn update(&mut self, ctx: &egui::Context, image: ColorImage, origin: Option<usize>) {
+        let [width, height] = image.size;
+        let Some(handle) = self.handle.as_mut().filter(|_| self.size == image.size) else {
+            self.shown = image.pixels.clone();
+            self.size = image.size;
+            self.ring_start = 0;
+            self.origin = origin;
+            self.handle = Some(ctx.load_texture(self.name, image, TextureOptions::NEAREST_REPEAT));
+            return;
+        };
+
+        // Whatever scrolled off the left comes round to the right, to be drawn over
+        let shift = match (self.origin, origin) {
+            (Some(last), Some(now)) if now >= last => (now - last).min(width),
+            _ => 0,
+        };
+        self.origin = origin;
+        if shift > 0 {
+            for row in self.shown.chunks_exact_mut(width) {
+                row.rotate_left(shift);
+            }
+            self.ring_start = (self.ring_start + shift) % width;
+        }
+
+        let changed: Vec<bool> = (0..width)
+            .map(|x| (0..height).any(|y| image.pixels[y * width + x] != self.shown[y * width + x]))
+            .collect();
+        if changed.iter().filter(|changed| **changed).count() > width / 2 {
+            // Cheaper to send the lot than piece it together
+            self.shown.copy_from_slice(&image.pixels);
+            self.ring_start = 0;
+            handle.set(image, TextureOptions::NEAREST_REPEAT);
+            return;
+        }
+
+        let mut x = 0;
+        while x < width {
+            if !changed[x] {
+                x += 1;
+                continue;
+            }
+            // A run of changed columns, stopping where the ring wraps round
+            let slot = (self.ring_start + x) % width;
+            let mut end = x + 1;
+            while end < width && changed[end] && slot + (end - x) < width {
+                end += 1;
+            }
+            let run = end - x;
+            let mut pixels = Vec::with_capacity(run * height);
+            for y in 0..height {
+                let row = &image.pixels[y * width + x..y * width + end];
+                pixels.extend_from_slice(row);
+                self.shown[y * width + x..y * width + end].copy_from_slice(row);
+            }
+            handle.set_partial(
+                [slot, 0],
+                ColorImage::new([run, height], pixels),
+                TextureOptions::NEAREST_REPEAT,
+            );
+            x = end;
+        }
+    }
+}
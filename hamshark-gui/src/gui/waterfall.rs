@@ -0,0 +1,683 @@
+use crate::gui::{
+    export::Spectrogram,
+    view::{
+        CURSOR_COLOR, ColumnTexture, DragState, MARKER_COLOR, Scaler, ViewTransform, input_pos,
+        screen_to_image_idx,
+    },
+};
+use eframe::glow;
+use egui::{
+    Align2, Color32, ColorImage, DragValue, FontId, Pos2, Rect, Response, Sense, Shape, Stroke,
+    StrokeKind, Vec2,
+};
+use gpu::{GpuWaterfall, MAX_TEXTURE_ROWS, RowTexture, Uniforms};
+use hamshark::{
+    birdies::Birdie,
+    carriers::Carrier,
+    config::Settings,
+    data::{
+        audio::Clip,
+        averaging::Averaging,
+        bandplan::{Region, SegmentMode},
+        colormap::Colormap,
+        metadata::SatellitePass,
+    },
+    gps::grid_square_center,
+    satellite::Satellite,
+};
+use log::error;
+use parking_lot::Mutex;
+use rows::RowCache;
+pub use rows::RowParameters;
+use std::{ops::Range, sync::Arc, time::Duration};
+
+mod gpu;
+mod rows;
+
+/// The quietest magnitude shown, anything below is drawn black
+const FLOOR_DB: f32 = -100.0;
+/// Width of the band plan strip along the right edge of the waterfall
+const BAND_PLAN_WIDTH: f32 = 48.0;
+/// Rig control annotations
+const RIG_COLOR: Color32 = Color32::from_rgb(200, 120, 255);
+/// Where a satellite's downlink should be
+const DOPPLER_COLOR: Color32 = Color32::from_rgb(255, 230, 80);
+/// Pixels between points on the Doppler trace
+const DOPPLER_STEP: usize = 4;
+/// Carriers tracked through the clip
+const CARRIER_COLOR: Color32 = Color32::from_rgb(255, 200, 60);
+const BIRDIE_COLOR: Color32 = Color32::from_rgba_premultiplied(160, 160, 160, 160);
+/// How often to look for new rows while the worker catches up
+const ROW_POLL: Duration = Duration::from_millis(100);
+
+/// The spectrogram view of a clip
+pub struct Waterfall {
+    /// The clip we're browsing
+    clip: Clip,
+    /// Magnitudes in dB, computed in the background
+    rows: RowCache,
+    /// The allocated screen height of the waterfall control
+    height: usize,
+    /// Make drag operations more precise
+    drag_state: DragState,
+    /// The image drawn on the CPU, when there's no GPU
+    texture: ColumnTexture,
+    /// GL state for drawing on the GPU
+    gpu: Arc<Mutex<GpuWaterfall>>,
+    /// The rows (first, last, decimation) currently uploaded to the GPU
+    uploaded: Option<(usize, usize, usize)>,
+    /// The dial frequency being edited, in kHz
+    dial_khz: f64,
+    /// Whether the dial frequency is mid-edit, so the clip's value shouldn't overwrite it
+    editing_dial: bool,
+    /// The FFT bin under the mouse, if it's hovering
+    hover_bin: Option<usize>,
+    /// The sample under the mouse, for the dial frequency at that point
+    hover_position: Option<usize>,
+    colormap: Colormap,
+    /// The satellite pass being traced, parsed, and the pass it came from
+    satellite: Option<(SatellitePass, Satellite)>,
+    /// Carriers found in the clip, to draw where they went
+    carriers: Option<Vec<Carrier>>,
+    /// The session's birdies, marked so they aren't taken for signals
+    birdies: Vec<Birdie>,
+}
+
+impl Waterfall {
+    pub fn new(clip: Clip, parameters: RowParameters) -> Self {
+        Self {
+            rows: RowCache::start(clip.clone(), parameters),
+            clip,
+            height: 128,
+            drag_state: DragState::NotDragging,
+            texture: ColumnTexture::new("waterfall"),
+            gpu: Default::default(),
+            uploaded: None,
+            dial_khz: 0.0,
+            editing_dial: false,
+            hover_bin: None,
+            hover_position: None,
+            colormap: Default::default(),
+            satellite: None,
+            carriers: None,
+            birdies: Vec::new(),
+        }
+    }
+
+    pub fn averaging(&self) -> Averaging {
+        self.rows.parameters().averaging
+    }
+
+    /// Compute the rows differently, starting them all again. The rows computed so far are
+    /// kept as long as the parameters don't change.
+    pub fn set_parameters(&mut self, parameters: RowParameters) {
+        if parameters == *self.rows.parameters() {
+            return;
+        }
+        self.rows = RowCache::start(self.clip.clone(), parameters);
+        self.uploaded = None;
+    }
+
+    /// Draw these carriers' tracks over the waterfall, or stop drawing them
+    pub fn set_carriers(&mut self, carriers: Option<Vec<Carrier>>) {
+        self.carriers = carriers;
+    }
+
+    pub fn has_carriers(&self) -> bool {
+        self.carriers.is_some()
+    }
+
+    /// Mark these birdies across the waterfall, or none
+    pub fn set_birdies(&mut self, birdies: Vec<Birdie>) {
+        self.birdies = birdies;
+    }
+
+    /// Release GPU resources. Call on exit while the GL context is still around.
+    pub fn destroy_gl(&mut self, gl: &glow::Context) {
+        self.gpu.lock().destroy(gl);
+        self.uploaded = None;
+    }
+
+    pub fn samples_per_fft(&self) -> usize {
+        self.rows.parameters().fft_size
+    }
+
+    /// Samples between the starts of successive rows
+    fn hop(&self) -> usize {
+        self.rows.parameters().hop
+    }
+
+    pub fn bins(&self) -> usize {
+        self.rows.parameters().bins()
+    }
+
+    /// Translate a screen Y coordinate into an FFT bin. Low frequencies are at the bottom.
+    fn y_to_bin(&self, y: usize) -> usize {
+        (self.height - 1 - y) * self.bins() / self.height
+    }
+
+    /// Translate an audio frequency into a (fractional) screen Y coordinate
+    fn audio_hz_to_y(&self, hz: f32, nyquist: f32) -> f32 {
+        self.height as f32 * (1.0 - hz / nyquist)
+    }
+
+    fn db_to_color(&self, db: f32) -> Color32 {
+        let [r, g, b] = self.colormap.rgb((db - FLOOR_DB) / -FLOOR_DB);
+        Color32::from_rgb(r, g, b)
+    }
+
+    /// The magnitudes of the FFTs covering a range of samples, with their axes
+    pub fn spectrogram(&self, range: &Range<usize>) -> Spectrogram {
+        let samples_per_fft = self.samples_per_fft();
+        let hop = self.hop();
+        let sample_rate = self.clip.read().sample_rate.0 as f64;
+        let rows = self.rows.rows();
+        let first = (range.start / hop).min(rows.len());
+        let last = range.end.div_ceil(hop).clamp(first, rows.len());
+        Spectrogram {
+            times: (first..last)
+                .map(|row| ((row * hop) as f64 + samples_per_fft as f64 / 2.0) / sample_rate)
+                .collect(),
+            frequencies: (0..self.bins())
+                .map(|bin| bin as f64 * sample_rate / samples_per_fft as f64)
+                .collect(),
+            rows: rows[first..last].to_vec(),
+        }
+    }
+
+    /// Draw the waterfall seen through view on the CPU, pixel by pixel. The cursor is left
+    /// off so the image is also suitable for exporting.
+    pub fn render(&self, view: &ViewTransform) -> ColorImage {
+        let width = view.width;
+        let hop = self.hop();
+        let rows = self.rows.rows();
+
+        // The waterfall image is drawn horizontally (yes unusual but bear with me)
+        // The most recent sample is on the right.
+        let mut waterfall_image = std::vec::from_elem(Color32::from_gray(0), width * self.height);
+
+        for i in 0..width {
+            let sample_range = view.screen_x_coordinate_to_data_range(i);
+            if sample_range.is_empty() {
+                break;
+            }
+
+            // When zoomed in past one row per pixel, smear the row over multiple pixels
+            let first = sample_range.start / hop;
+            let last = sample_range
+                .end
+                .div_ceil(hop)
+                .max(first + 1)
+                .min(rows.len());
+            if first >= last {
+                // The remaining samples haven't been computed yet
+                break;
+            }
+            let bucket = &rows[first..last];
+
+            for y in 0..self.height {
+                let bin = self.y_to_bin(y);
+                let db = bucket.iter().fold(f32::MIN, |acc, row| acc.max(row[bin]));
+                waterfall_image[screen_to_image_idx(width, self.height, i, y)] =
+                    self.db_to_color(db);
+            }
+        }
+
+        ColorImage::new([width, self.height], waterfall_image)
+    }
+
+    /// Draw the waterfall on the CPU
+    fn show_cpu(&mut self, ui: &mut egui::Ui, view: &ViewTransform) -> Response {
+        let mut waterfall_image = self.render(view);
+
+        // Overlay a vertical line representing the current cursor position
+        if let Some(x) = view.cursor {
+            for y in 0..self.height {
+                let idx = screen_to_image_idx(view.width, self.height, x, y);
+                waterfall_image.pixels[idx] = CURSOR_COLOR;
+            }
+        }
+
+        // Show the waterfall, uploading only what's changed since the last frame
+        let origin = view.live.then(|| view.live_columns().0);
+        self.texture.show(
+            ui,
+            waterfall_image,
+            origin,
+            Sense::click_and_drag() | Sense::hover(),
+        )
+    }
+
+    /// Draw the waterfall with a shader. Only the rows in view get uploaded, and only when
+    /// they change. Scaling and colormapping happen on the GPU.
+    fn show_gpu(&mut self, ui: &mut egui::Ui, view: &ViewTransform) -> Response {
+        let width = view.width;
+        let hop = self.hop();
+        let (rect, response) = ui.allocate_exact_size(
+            Vec2::new(width as f32, self.height as f32),
+            Sense::click_and_drag() | Sense::hover(),
+        );
+
+        // Work out which rows are in view, and how many rows to squash together to fit them
+        // in the texture
+        let visible = view.screen_to_data_x_without_offset(width as isize).max(0) as usize;
+        let rows = self.rows.rows();
+        let last = (view.offset + visible).div_ceil(hop).min(rows.len());
+        let first = (view.offset / hop).min(last);
+        let decimation = (last - first).div_ceil(MAX_TEXTURE_ROWS).max(1);
+        if self.uploaded != Some((first, last, decimation)) {
+            let bins = self.bins();
+            let mut data = Vec::with_capacity((last - first).div_ceil(decimation) * bins);
+            for chunk in rows[first..last].chunks(decimation) {
+                for bin in 0..bins {
+                    data.push(chunk.iter().fold(f32::MIN, |acc, row| acc.max(row[bin])));
+                }
+            }
+            self.gpu.lock().upload(RowTexture {
+                rows: data.len() / bins,
+                bins,
+                data,
+            });
+            self.uploaded = Some((first, last, decimation));
+        }
+        drop(rows);
+
+        let rows_per_sample = 1.0 / (hop * decimation) as f64;
+        let uniforms = Uniforms {
+            start_row: ((view.offset - first * hop) as f64 * rows_per_sample) as f32,
+            rows_per_pixel: (view.scale as f64 * rows_per_sample) as f32,
+            width: width as f32,
+            floor_db: FLOOR_DB,
+            colormap: self.colormap.stops(),
+        };
+        ui.painter().add(GpuWaterfall::paint_callback(
+            self.gpu.clone(),
+            rect,
+            uniforms,
+        ));
+
+        // Overlay a vertical line representing the current cursor position
+        if let Some(x) = view.cursor {
+            ui.painter().vline(
+                rect.min.x + x as f32 + 0.5,
+                rect.y_range(),
+                Stroke::new(1.0, CURSOR_COLOR),
+            );
+        }
+
+        response
+    }
+
+    /// Show the controls that only apply to the waterfall
+    pub fn show_controls(&mut self, ui: &mut egui::Ui) {
+        let dial_frequency = self.clip.read().metadata.dial_frequency;
+        let mut known = dial_frequency.is_some();
+        if let Some(hz) = dial_frequency
+            && !self.editing_dial
+        {
+            self.dial_khz = hz as f64 / 1000.0;
+        }
+
+        let mut changed = ui
+            .checkbox(&mut known, "Dial")
+            .on_hover_text("The frequency the receiver was tuned to, if known")
+            .changed();
+        let response = ui.add_enabled(
+            known,
+            DragValue::new(&mut self.dial_khz)
+                .range(0.0..=300_000_000.0)
+                .max_decimals(3)
+                .speed(0.1)
+                .suffix(" kHz"),
+        );
+        // Only save once the value settles, not for every step of a drag or keystroke
+        self.editing_dial = response.dragged() || response.has_focus();
+        changed |= response.drag_stopped()
+            || response.lost_focus()
+            || (response.changed() && !self.editing_dial);
+
+        if changed {
+            let dial_frequency = known.then(|| (self.dial_khz * 1000.0).round() as u64);
+            if let Err(error) = self.clip.write().set_dial_frequency(dial_frequency) {
+                error!("Unable to save dial frequency: {}", error);
+            }
+        }
+
+        // A recording keeps it a little behind all the time, which isn't worth showing
+        if self.rows.is_behind() && !self.clip.read().is_recording() {
+            ui.spinner().on_hover_text(format!(
+                "Computing the waterfall, {:.0}% done",
+                self.rows.progress() * 100.0
+            ));
+        }
+    }
+
+    /// Show the frequency under the mouse, as audio and, if we know the dial frequency, as RF.
+    /// Both are corrected by the clip's calibration.
+    pub fn show_readout(&self, ui: &mut egui::Ui) {
+        let Some(bin) = self.hover_bin else {
+            return;
+        };
+        let clip = self.clip.read();
+        let position = self.hover_position.unwrap_or_default();
+        let hz = bin as f64 * clip.sample_rate.0 as f64 / self.samples_per_fft() as f64;
+        let text = match clip.metadata.dial_frequency_at(position) {
+            Some(dial) => format!(
+                "F: {:.0} Hz ({:.6} MHz)",
+                clip.metadata.corrected(hz),
+                clip.metadata.corrected(dial as f64 + hz) / 1e6
+            ),
+            None => format!("F: {:.0} Hz", clip.metadata.corrected(hz)),
+        };
+        ui.label(text);
+    }
+
+    /// Mark out the band plan segments the waterfall covers, along its right edge. Only
+    /// possible when we know what frequency the receiver was tuned to at the left edge.
+    fn show_band_plan(&self, ui: &mut egui::Ui, bounds: Rect, region: Region, position: usize) {
+        let (dial_frequency, sample_rate) = {
+            let clip = self.clip.read();
+            (
+                clip.metadata
+                    .dial_frequency_at(position)
+                    .map(|dial| clip.metadata.corrected(dial as f64).round() as u64),
+                clip.sample_rate.0,
+            )
+        };
+        let Some(dial) = dial_frequency else {
+            return;
+        };
+        let nyquist = sample_rate as u64 / 2;
+        if nyquist == 0 {
+            return;
+        }
+
+        let painter = ui.painter_at(bounds);
+        for (i, segment) in region.segments_in(dial..dial + nyquist).enumerate() {
+            let color = match segment.mode {
+                SegmentMode::Cw => Color32::from_rgb(80, 140, 255),
+                SegmentMode::Digital => Color32::from_rgb(80, 220, 120),
+                SegmentMode::Phone => Color32::from_rgb(255, 150, 60),
+            };
+            let low = segment.start_hz.saturating_sub(dial) as f32;
+            let high = (segment.end_hz - dial).min(nyquist) as f32;
+            let top = bounds.min.y + self.audio_hz_to_y(high, nyquist as f32);
+            let bottom = bounds.min.y + self.audio_hz_to_y(low, nyquist as f32);
+            let strip = Rect::from_min_max(
+                Pos2::new(bounds.max.x - BAND_PLAN_WIDTH, top),
+                Pos2::new(bounds.max.x, bottom),
+            );
+
+            painter.rect_filled(strip, 0.0, color.gamma_multiply(0.4));
+            // Draw the segment edges across the whole waterfall
+            for (edge_hz, y) in [(segment.start_hz, bottom), (segment.end_hz, top)] {
+                if edge_hz > dial && edge_hz < dial + nyquist {
+                    painter.hline(
+                        bounds.x_range(),
+                        y,
+                        Stroke::new(1.0, color.gamma_multiply(0.6)),
+                    );
+                }
+            }
+            if strip.height() > 10.0 {
+                painter.text(
+                    strip.center(),
+                    Align2::CENTER_CENTER,
+                    segment.mode.to_string(),
+                    FontId::proportional(10.0),
+                    Color32::WHITE,
+                );
+            }
+
+            ui.interact(strip, ui.id().with(("band_segment", i)), Sense::hover())
+                .on_hover_text(format!(
+                    "{} {}\n{:.3}-{:.3} MHz",
+                    segment.band,
+                    segment.mode,
+                    segment.start_hz as f64 / 1e6,
+                    segment.end_hz as f64 / 1e6,
+                ));
+        }
+    }
+
+    /// Draw where a satellite's downlink should be as its Doppler shift changes over the
+    /// pass. Needs to know when the clip was recorded, what the receiver was tuned to, and
+    /// where the station was, from GPS or failing that the station's grid square.
+    fn show_doppler_trace(
+        &mut self,
+        ui: &mut egui::Ui,
+        bounds: Rect,
+        view: &ViewTransform,
+        grid_square: &str,
+    ) {
+        let clip = self.clip.read();
+        let Some(pass) = &clip.metadata.satellite else {
+            self.satellite = None;
+            return;
+        };
+        if self
+            .satellite
+            .as_ref()
+            .is_none_or(|(traced, _)| traced != pass)
+        {
+            self.satellite = match Satellite::from_tle(&pass.tle) {
+                Ok(satellite) => Some((pass.clone(), satellite)),
+                Err(error) => {
+                    error!("Unable to trace the satellite pass: {}", error);
+                    None
+                }
+            };
+        }
+        let Some((_, satellite)) = &self.satellite else {
+            return;
+        };
+        let Some(started) = clip.metadata.started else {
+            return;
+        };
+        let Some((latitude, longitude)) = clip
+            .metadata
+            .location
+            .as_ref()
+            .map(|location| (location.latitude, location.longitude))
+            .or_else(|| grid_square_center(grid_square))
+        else {
+            return;
+        };
+        let sample_rate = clip.sample_rate.0 as f64;
+        let nyquist = sample_rate / 2.0;
+        if nyquist == 0.0 {
+            return;
+        }
+
+        let painter = ui.painter_at(bounds);
+        let mut last: Option<Pos2> = None;
+        for x in (0..view.width).step_by(DOPPLER_STEP) {
+            let position = view.screen_to_data_x(x as isize).max(0) as usize;
+            if position >= clip.samples.len() {
+                break;
+            }
+            let Some(dial) = clip.metadata.dial_frequency_at(position) else {
+                last = None;
+                continue;
+            };
+            let time = started
+                + chrono::Duration::microseconds((position as f64 / sample_rate * 1e6) as i64);
+            let point = satellite
+                .look(latitude, longitude, time)
+                .ok()
+                .filter(|look| look.elevation >= 0.0)
+                .map(|look| {
+                    let downlink = pass.downlink as f64;
+                    downlink + look.doppler(downlink) - clip.metadata.corrected(dial as f64)
+                })
+                .filter(|audio| (0.0..nyquist).contains(audio))
+                .map(|audio| {
+                    Pos2::new(
+                        bounds.min.x + x as f32,
+                        bounds.min.y + self.audio_hz_to_y(audio as f32, nyquist as f32),
+                    )
+                });
+            if let (Some(from), Some(to)) = (last, point) {
+                painter.line_segment([from, to], Stroke::new(1.5, DOPPLER_COLOR));
+            } else if let (None, Some(to)) = (last, point) {
+                painter.text(
+                    to + Vec2::new(3.0, -3.0),
+                    Align2::LEFT_BOTTOM,
+                    satellite.name.as_deref().unwrap_or("Satellite"),
+                    FontId::proportional(10.0),
+                    DOPPLER_COLOR,
+                );
+            }
+            last = point;
+        }
+    }
+
+    /// Draw where each tracked carrier went, labelled with its frequency where it starts
+    fn show_carrier_tracks(&self, ui: &mut egui::Ui, bounds: Rect, view: &ViewTransform) {
+        let Some(carriers) = &self.carriers else {
+            return;
+        };
+        let nyquist = self.clip.read().sample_rate.0 as f32 / 2.0;
+        if nyquist == 0.0 {
+            return;
+        }
+        let painter = ui.painter_at(bounds);
+        for carrier in carriers {
+            let mut last: Option<Pos2> = None;
+            for (position, hz) in &carrier.track {
+                let point = view.position_screen_x(*position).map(|x| {
+                    Pos2::new(
+                        bounds.min.x + x as f32,
+                        bounds.min.y + self.audio_hz_to_y(*hz as f32, nyquist),
+                    )
+                });
+                match (last, point) {
+                    (Some(from), Some(to)) => {
+                        painter.line_segment([from, to], Stroke::new(1.5, CARRIER_COLOR));
+                    }
+                    (None, Some(to)) => {
+                        painter.text(
+                            to + Vec2::new(3.0, -3.0),
+                            Align2::LEFT_BOTTOM,
+                            format!("{:.1} Hz {:.0} dBFS", carrier.frequency, carrier.level_db),
+                            FontId::proportional(10.0),
+                            CARRIER_COLOR,
+                        );
+                    }
+                    _ => {}
+                }
+                last = point;
+            }
+        }
+    }
+
+    /// A dashed line across the waterfall at each birdie
+    fn show_birdies(&self, ui: &mut egui::Ui, bounds: Rect) {
+        let nyquist = self.clip.read().sample_rate.0 as f32 / 2.0;
+        if self.birdies.is_empty() || nyquist == 0.0 {
+            return;
+        }
+        let painter = ui.painter_at(bounds);
+        for birdie in &self.birdies {
+            let y = bounds.min.y + self.audio_hz_to_y(birdie.frequency as f32, nyquist);
+            painter.add(Shape::dashed_line(
+                &[Pos2::new(bounds.min.x, y), Pos2::new(bounds.max.x, y)],
+                Stroke::new(1.0, BIRDIE_COLOR),
+                6.0,
+                4.0,
+            ));
+            painter.text(
+                Pos2::new(bounds.max.x - 3.0, y - 1.0),
+                Align2::RIGHT_BOTTOM,
+                format!(
+                    "Birdie {:.1} Hz, in {} clips",
+                    birdie.frequency, birdie.occurrences
+                ),
+                FontId::proportional(10.0),
+                BIRDIE_COLOR,
+            );
+        }
+    }
+
+    pub fn update_and_show(
+        &mut self,
+        ui: &mut egui::Ui,
+        view: &mut ViewTransform,
+        gpu_available: bool,
+        settings: &Settings,
+    ) -> Response {
+        self.colormap = settings.display.colormap;
+        if self.rows.is_behind() {
+            ui.ctx().request_repaint_after(ROW_POLL);
+        }
+
+        let waterfall_response = if gpu_available && !self.gpu.lock().unsupported {
+            self.show_gpu(ui, view)
+        } else {
+            self.show_cpu(ui, view)
+        };
+
+        // Outline each visible marker with a box
+        let bounds = waterfall_response.rect;
+        let markers = self.clip.read().metadata.markers.clone();
+        let painter = ui.painter_at(bounds);
+        for (i, marker) in markers.iter().enumerate() {
+            let Some(x) = view.marker_screen_x(marker) else {
+                continue;
+            };
+            let x = bounds.min.x + x as f32;
+            let marker_box = Rect::from_min_max(
+                Pos2::new(x - 3.0, bounds.min.y),
+                Pos2::new(x + 3.0, bounds.max.y),
+            );
+            painter.rect_stroke(
+                marker_box,
+                0.0,
+                Stroke::new(1.0, MARKER_COLOR),
+                StrokeKind::Inside,
+            );
+            let response = ui
+                .interact(
+                    marker_box,
+                    ui.id().with(("waterfall_marker", i)),
+                    Sense::click(),
+                )
+                .on_hover_text(format!("{}\nSample {}", marker.label, marker.position));
+            if response.clicked() {
+                view.seek(marker.position);
+            }
+        }
+
+        // Label each change of frequency or mode rig control saw along the top
+        let readings = self.clip.read().metadata.rig.clone();
+        for reading in &readings {
+            let Some(x) = view.position_screen_x(reading.position) else {
+                continue;
+            };
+            let x = bounds.min.x + x as f32;
+            painter.vline(x, bounds.y_range(), Stroke::new(1.0, RIG_COLOR));
+            painter.text(
+                Pos2::new(x + 3.0, bounds.min.y + 2.0),
+                Align2::LEFT_TOP,
+                format!("{:.6} MHz {}", reading.frequency as f64 / 1e6, reading.mode),
+                FontId::proportional(10.0),
+                RIG_COLOR,
+            );
+        }
+
+        self.show_band_plan(ui, bounds, settings.band_plan_region, view.offset);
+        self.show_doppler_trace(ui, bounds, view, &settings.station.grid_square);
+        self.show_carrier_tracks(ui, bounds, view);
+        self.show_birdies(ui, bounds);
+
+        let hover = input_pos(&bounds, waterfall_response.hover_pos());
+        self.hover_bin = hover.map(|pos| self.y_to_bin(pos.y));
+        self.hover_position =
+            hover.map(|pos| view.screen_to_data_x(pos.x as isize).max(0) as usize);
+
+        self.drag_state.track(&waterfall_response);
+        view.interact(ui, &waterfall_response, &mut self.drag_state);
+
+        waterfall_response
+    }
+}
@@ -0,0 +1,268 @@
+use eframe::{
+    egui_glow::{self, ShaderVersion},
+    glow::{self, HasContext},
+};
+use egui::{PaintCallback, Rect};
+use hamshark::data::colormap::STOPS;
+use log::warn;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Upper bound on the rows uploaded per frame, comfortably inside every GL's texture size limit.
+/// When more rows than this are in view they get max-reduced on the CPU first.
+pub const MAX_TEXTURE_ROWS: usize = 2048;
+
+const VERTEX_SHADER: &str = r#"
+    const vec2 verts[4] = vec2[4](
+        vec2(0.0, 0.0),
+        vec2(1.0, 0.0),
+        vec2(0.0, 1.0),
+        vec2(1.0, 1.0)
+    );
+    out vec2 v_uv;
+    void main() {
+        v_uv = verts[gl_VertexID];
+        gl_Position = vec4(v_uv.x * 2.0 - 1.0, 1.0 - v_uv.y * 2.0, 0.0, 1.0);
+    }
+"#;
+
+// Each fragment works out which FFT rows fall under its screen column and takes the loudest,
+// the same way the CPU path summarizes a column. Long runs of rows are strided so the loop
+// stays bounded.
+const FRAGMENT_SHADER: &str = r#"
+    #ifdef GL_ES
+    precision highp float;
+    #endif
+    uniform highp sampler2D u_rows;
+    uniform float u_start_row;
+    uniform float u_rows_per_pixel;
+    uniform float u_width;
+    uniform float u_floor_db;
+    uniform int u_row_count;
+    uniform int u_bins;
+    uniform vec3 u_colormap[5];
+    in vec2 v_uv;
+    out vec4 out_color;
+
+    const int MAX_TAPS = 64;
+
+    void main() {
+        float px = floor(v_uv.x * u_width);
+        float start = u_start_row + px * u_rows_per_pixel;
+        int first = int(floor(start));
+        int last = min(max(int(ceil(start + u_rows_per_pixel)), first + 1), u_row_count);
+        if (first < 0 || first >= last) {
+            out_color = vec4(0.0, 0.0, 0.0, 1.0);
+            return;
+        }
+        int bin = clamp(int((1.0 - v_uv.y) * float(u_bins)), 0, u_bins - 1);
+        int stride = max(1, (last - first) / MAX_TAPS);
+        float db = -1.0e30;
+        for (int row = first; row < last; row += stride) {
+            db = max(db, texelFetch(u_rows, ivec2(bin, row), 0).r);
+        }
+        float level = clamp((db - u_floor_db) / -u_floor_db, 0.0, 1.0);
+        float x = level * 4.0;
+        int stop = min(int(x), 3);
+        out_color = vec4(mix(u_colormap[stop], u_colormap[stop + 1], x - float(stop)), 1.0);
+    }
+"#;
+
+/// FFT rows in dB, laid out row after row, waiting to be uploaded to the texture
+pub struct RowTexture {
+    pub rows: usize,
+    pub bins: usize,
+    pub data: Vec<f32>,
+}
+
+/// Everything the shader needs to map screen columns onto the uploaded rows
+#[derive(Clone, Copy)]
+pub struct Uniforms {
+    /// The (fractional) uploaded row at the left edge of the view
+    pub start_row: f32,
+    pub rows_per_pixel: f32,
+    pub width: f32,
+    pub floor_db: f32,
+    /// The colormap's stops, see `Colormap::stops`
+    pub colormap: [[f32; 3]; STOPS],
+}
+
+struct Resources {
+    program: glow::Program,
+    vertex_array: glow::VertexArray,
+    texture: glow::Texture,
+    rows: usize,
+    bins: usize,
+}
+
+/// GL state for one waterfall. Shared with the paint callback, which is where the GL
+/// context is available, so everything GL related happens lazily in there.
+#[derive(Default)]
+pub struct GpuWaterfall {
+    resources: Option<Resources>,
+    /// Rows waiting to be uploaded on the next paint
+    pending: Option<RowTexture>,
+    /// Set if the GL context can't run our shaders, so the caller falls back to the CPU
+    pub unsupported: bool,
+}
+
+impl GpuWaterfall {
+    pub fn upload(&mut self, rows: RowTexture) {
+        self.pending = Some(rows);
+    }
+
+    /// Build a paint callback that draws the most recently uploaded rows into rect
+    pub fn paint_callback(
+        state: Arc<Mutex<Self>>,
+        rect: Rect,
+        uniforms: Uniforms,
+    ) -> PaintCallback {
+        let callback = egui_glow::CallbackFn::new(move |_info, painter| {
+            state.lock().paint(painter.gl(), uniforms);
+        });
+        PaintCallback {
+            rect,
+            callback: Arc::new(callback),
+        }
+    }
+
+    fn paint(&mut self, gl: &glow::Context, uniforms: Uniforms) {
+        if self.unsupported {
+            return;
+        }
+        if self.resources.is_none() {
+            match unsafe { Self::create_resources(gl) } {
+                Ok(resources) => self.resources = Some(resources),
+                Err(message) => {
+                    warn!(
+                        "GPU waterfall unavailable, falling back to CPU: {}",
+                        message
+                    );
+                    self.unsupported = true;
+                    return;
+                }
+            }
+        }
+        let Some(resources) = self.resources.as_mut() else {
+            return;
+        };
+
+        unsafe {
+            gl.use_program(Some(resources.program));
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(resources.texture));
+
+            if let Some(pending) = self.pending.take() {
+                let bytes: Vec<u8> = pending
+                    .data
+                    .iter()
+                    .flat_map(|value| value.to_ne_bytes())
+                    .collect();
+                gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+                gl.tex_image_2d(
+                    glow::TEXTURE_2D,
+                    0,
+                    glow::R32F as i32,
+                    pending.bins as i32,
+                    pending.rows as i32,
+                    0,
+                    glow::RED,
+                    glow::FLOAT,
+                    glow::PixelUnpackData::Slice(Some(&bytes)),
+                );
+                resources.rows = pending.rows;
+                resources.bins = pending.bins;
+            }
+
+            let program = resources.program;
+            let location = |name: &str| gl.get_uniform_location(program, name);
+            gl.uniform_1_i32(location("u_rows").as_ref(), 0);
+            gl.uniform_1_f32(location("u_start_row").as_ref(), uniforms.start_row);
+            gl.uniform_1_f32(
+                location("u_rows_per_pixel").as_ref(),
+                uniforms.rows_per_pixel,
+            );
+            gl.uniform_1_f32(location("u_width").as_ref(), uniforms.width);
+            gl.uniform_1_f32(location("u_floor_db").as_ref(), uniforms.floor_db);
+            gl.uniform_1_i32(location("u_row_count").as_ref(), resources.rows as i32);
+            gl.uniform_1_i32(location("u_bins").as_ref(), resources.bins as i32);
+            gl.uniform_3_f32_slice(
+                location("u_colormap").as_ref(),
+                uniforms.colormap.as_flattened(),
+            );
+
+            gl.bind_vertex_array(Some(resources.vertex_array));
+            gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+        }
+    }
+
+    unsafe fn create_resources(gl: &glow::Context) -> Result<Resources, String> {
+        let shader_version = ShaderVersion::get(gl);
+        if !shader_version.is_new_shader_interface() {
+            return Err(format!(
+                "{:?} is too old for the waterfall shader",
+                shader_version
+            ));
+        }
+
+        unsafe {
+            let program = gl.create_program()?;
+            let mut shaders = Vec::new();
+            for (shader_type, source) in [
+                (glow::VERTEX_SHADER, VERTEX_SHADER),
+                (glow::FRAGMENT_SHADER, FRAGMENT_SHADER),
+            ] {
+                let shader = gl.create_shader(shader_type)?;
+                gl.shader_source(
+                    shader,
+                    &format!("{}\n{}", shader_version.version_declaration(), source),
+                );
+                gl.compile_shader(shader);
+                if !gl.get_shader_compile_status(shader) {
+                    return Err(gl.get_shader_info_log(shader));
+                }
+                gl.attach_shader(program, shader);
+                shaders.push(shader);
+            }
+            gl.link_program(program);
+            if !gl.get_program_link_status(program) {
+                return Err(gl.get_program_info_log(program));
+            }
+            for shader in shaders {
+                gl.detach_shader(program, shader);
+                gl.delete_shader(shader);
+            }
+
+            let vertex_array = gl.create_vertex_array()?;
+
+            let texture = gl.create_texture()?;
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            // Float textures aren't filterable everywhere, and we only ever texelFetch anyway
+            for parameter in [glow::TEXTURE_MIN_FILTER, glow::TEXTURE_MAG_FILTER] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, glow::NEAREST as i32);
+            }
+            for parameter in [glow::TEXTURE_WRAP_S, glow::TEXTURE_WRAP_T] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, glow::CLAMP_TO_EDGE as i32);
+            }
+
+            Ok(Resources {
+                program,
+                vertex_array,
+                texture,
+                rows: 0,
+                bins: 0,
+            })
+        }
+    }
+
+    /// Release the GL objects. Must be called with the same context that created them.
+    pub fn destroy(&mut self, gl: &glow::Context) {
+        if let Some(resources) = self.resources.take() {
+            unsafe {
+                gl.delete_program(resources.program);
+                gl.delete_vertex_array(resources.vertex_array);
+                gl.delete_texture(resources.texture);
+            }
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use hamshark::{
+    config::DspSettings,
+    data::{
+        audio::Clip,
+        averaging::{Averager, Averaging},
+        window::WindowFunction,
+    },
+    fft::Transform,
+};
+use log::warn;
+use parking_lot::{RwLock, RwLockReadGuard};
+use rayon::prelude::*;
+use rustfft::num_complex::Complex;
+use std::{
+    sync::{
+        Arc,
+        mpsc::{self, RecvTimeoutError, Sender, TryRecvError},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How long the worker waits for more samples once it's caught up
+const IDLE_POLL: Duration = Duration::from_millis(50);
+/// Rows computed from each copy of the samples, so recording isn't held up for long
+const BATCH_ROWS: usize = 256;
+
+/// What a waterfall's rows are computed with. Rows computed with one are no use for another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RowParameters {
+    pub fft_size: usize,
+    pub window_function: WindowFunction,
+    /// Samples between the starts of successive rows
+    pub hop: usize,
+    pub averaging: Averaging,
+}
+
+impl RowParameters {
+    pub fn new(dsp: &DspSettings, averaging: Averaging) -> Self {
+        let fft_size = dsp.fft_size.max(2);
+        Self {
+            fft_size,
+            window_function: dsp.window_function,
+            hop: dsp.hop.unwrap_or(fft_size).clamp(1, fft_size),
+            averaging,
+        }
+    }
+
+    /// Only the bins up to Nyquist are kept since the input is real
+    pub fn bins(&self) -> usize {
+        self.fft_size / 2
+    }
+
+    /// The samples needed to compute `count` rows
+    fn span(&self, count: usize) -> usize {
+        (count - 1) * self.hop + self.fft_size
+    }
+}
+
+/// Turns blocks of samples into rows of magnitudes
+struct RowComputer {
+    parameters: RowParameters,
+    window: Vec<f32>,
+    gain: f32,
+    averager: Averager,
+}
+
+impl RowComputer {
+    fn new(parameters: RowParameters) -> Self {
+        let window = parameters.window_function.coefficients(parameters.fft_size);
+        Self {
+            parameters,
+            gain: 2.0 / window.iter().sum::<f32>(),
+            window,
+            averager: Averager::new(parameters.averaging),
+        }
+    }
+
+    /// The rows for a run of samples taken from the start of a row, as many as fit. The
+    /// transforms are spread over every core, then averaged in order.
+    fn rows(&mut self, samples: &[f32]) -> Vec<Vec<f32>> {
+        let RowParameters { fft_size, hop, .. } = self.parameters;
+        let Some(count) = samples
+            .len()
+            .checked_sub(fft_size)
+            .map(|extra| extra / hop + 1)
+        else {
+            return Vec::new();
+        };
+        let magnitudes: Vec<Vec<f32>> = (0..count)
+            .into_par_iter()
+            .map_init(
+                || {
+                    (
+                        Transform::forward(fft_size),
+                        vec![Complex::default(); fft_size],
+                    )
+                },
+                |(fft, buffer), row| {
+                    let block = &samples[row * hop..row * hop + fft_size];
+                    for (i, sample) in block.iter().enumerate() {
+                        buffer[i] = Complex::new(sample * self.window[i], 0.0);
+                    }
+                    fft.process(buffer);
+                    buffer[..self.parameters.bins()]
+                        .iter()
+                        .map(|bin| 20.0 * (bin.norm() * self.gain).max(1e-10).log10())
+                        .collect()
+                },
+            )
+            .collect();
+        magnitudes
+            .into_iter()
+            .map(|row| self.averager.push(row))
+            .collect()
+    }
+}
+
+/// A clip's waterfall rows, computed on a background thread as samples arrive and kept for
+/// as long as the parameters stay the same. Stops when dropped.
+pub struct RowCache {
+    clip: Clip,
+    parameters: RowParameters,
+    /// Magnitudes in dB, one row every hop samples
+    rows: Arc<RwLock<Vec<Vec<f32>>>>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RowCache {
+    pub fn start(clip: Clip, parameters: RowParameters) -> Self {
+        let rows: Arc<RwLock<Vec<Vec<f32>>>> = Default::default();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn({
+            let clip = clip.clone();
+            let rows = rows.clone();
+            move || {
+                let mut computer = RowComputer::new(parameters);
+                loop {
+                    let computed = rows.read().len();
+                    match Self::next_batch(&clip, &parameters, computed) {
+                        Some(samples) => {
+                            let batch = computer.rows(&samples);
+                            rows.write().extend(batch);
+                            if !matches!(stopped.try_recv(), Err(TryRecvError::Empty)) {
+                                break;
+                            }
+                        }
+                        None => {
+                            if !matches!(
+                                stopped.recv_timeout(IDLE_POLL),
+                                Err(RecvTimeoutError::Timeout)
+                            ) {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        Self {
+            clip,
+            parameters,
+            rows,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Copy out the samples for the rows after the first `computed`, None if there aren't
+    /// enough for another row yet
+    fn next_batch(clip: &Clip, parameters: &RowParameters, computed: usize) -> Option<Vec<f32>> {
+        let clip = clip.read();
+        let start = computed * parameters.hop;
+        let available = clip
+            .samples
+            .len()
+            .checked_sub(start + parameters.fft_size)?;
+        let count = (available / parameters.hop + 1).min(BATCH_ROWS);
+        Some(
+            clip.samples
+                .range(start..start + parameters.span(count))
+                .into_owned(),
+        )
+    }
+
+    pub fn parameters(&self) -> &RowParameters {
+        &self.parameters
+    }
+
+    /// The rows computed so far, in order
+    pub fn rows(&self) -> RwLockReadGuard<'_, Vec<Vec<f32>>> {
+        self.rows.read()
+    }
+
+    /// Whether there are samples the worker hasn't got to yet
+    pub fn is_behind(&self) -> bool {
+        let computed = self.rows.read().len();
+        computed * self.parameters.hop + self.parameters.fft_size <= self.clip.read().samples.len()
+    }
+
+    /// How much of the clip the worker has got through, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        let computed = self.rows.read().len();
+        let total = match self
+            .clip
+            .read()
+            .samples
+            .len()
+            .checked_sub(self.parameters.fft_size)
+        {
+            Some(extra) => extra / self.parameters.hop + 1,
+            None => return 1.0,
+        };
+        (computed as f32 / total as f32).min(1.0)
+    }
+}
+
+impl Drop for RowCache {
+    fn drop(&mut self) {
+        // Hanging up stops the thread after its current batch
+        self.stop.take();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            warn!("Waterfall thread panicked");
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use crate::desktop;
+use chrono::{DateTime, Utc};
+#[cfg(feature = "scripting")]
+use hamshark::scripting::Script;
+use hamshark::{HamShark, config::DesktopSettings, session, timesource::SharedTimeSource};
+use log::{info, warn};
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    time::Duration,
+};
+use thiserror::Error as ThisError;
+
+/// How often to check on the recording pipeline between segments
+const POLL: Duration = Duration::from_secs(1);
+/// How often a script is ticked, so its hooks aren't called long after the fact
+#[cfg(feature = "scripting")]
+const SCRIPT_POLL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Session Error: {0}")]
+    Session(#[from] session::Error),
+    #[error("Unable to handle signals: {0}")]
+    Signal(#[from] ctrlc::Error),
+    #[error("{0} failed: {1}")]
+    Pipeline(&'static str, String),
+    #[cfg(feature = "server")]
+    #[error("{0}")]
+    Engine(#[from] hamshark::asynchronous::Error),
+    #[cfg(feature = "server")]
+    #[error("Unable to start the async runtime: {0}")]
+    Runtime(#[source] std::io::Error),
+    #[cfg(feature = "server")]
+    #[error("Remote control server failed: {0}")]
+    Server(#[source] std::io::Error),
+}
+
+/// Whether a segment started at `start` has run its length
+fn segment_over(time: &SharedTimeSource, start: DateTime<Utc>, segment: Duration) -> bool {
+    (time.now() - start)
+        .to_std()
+        .is_ok_and(|elapsed| elapsed >= segment)
+}
+
+/// Record without a GUI until SIGINT or SIGTERM, starting a new clip every `segment` so a
+/// long unattended recording ends up as files of a manageable size. With a script, it's the
+/// script that starts and stops recording.
+pub fn run(
+    mut hamshark: HamShark,
+    segment: Duration,
+    desktop: &DesktopSettings,
+    #[cfg(feature = "scripting")] mut script: Option<Script>,
+) -> Result<(), Error> {
+    let (stop_sender, stop) = mpsc::channel();
+    ctrlc::set_handler(move || {
+        let _ = stop_sender.send(());
+    })?;
+
+    info!(
+        "Recording headless into {:?}, a new clip every {:?}",
+        hamshark.session().path.as_os_str(),
+        segment
+    );
+    // Sleeping would be the end of an unattended recording
+    let _awake = desktop::keep_awake(desktop);
+    #[cfg(feature = "scripting")]
+    let poll = if script.is_some() { SCRIPT_POLL } else { POLL };
+    #[cfg(not(feature = "scripting"))]
+    let poll = POLL;
+    #[cfg(feature = "scripting")]
+    let scripted = script.is_some();
+    #[cfg(not(feature = "scripting"))]
+    let scripted = false;
+    if !scripted {
+        hamshark.start()?;
+    }
+    let time = hamshark.session().time_source().clone();
+    let mut segment_start = time.now();
+    let mut was_recording = hamshark.is_recording();
+    loop {
+        match stop.recv_timeout(poll) {
+            Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        #[cfg(feature = "scripting")]
+        if let Some(script) = &mut script {
+            script.tick(hamshark.session_mut());
+        }
+
+        // Losing the device finishes the clip. There's nobody to ask, so recording starts
+        // again by itself once the device is back.
+        if let Some(id) = hamshark.session_mut().check_device()? {
+            warn!("Lost the input device, {} was cut short", id);
+        }
+        if hamshark.session().lost_device().is_some() {
+            if hamshark.session().lost_device_is_back() {
+                info!("The input device is back, recording again");
+                hamshark.session_mut().resume_lost_device()?;
+                segment_start = time.now();
+            }
+            continue;
+        }
+
+        if let Some(element) = hamshark
+            .session()
+            .pipeline()
+            .and_then(|pipeline| pipeline.elements().iter().find(|e| e.is_failed()))
+        {
+            let error = Error::Pipeline(element.name(), element.last_error().unwrap_or_default());
+            hamshark.stop()?;
+            return Err(error);
+        }
+
+        let recording = hamshark.is_recording();
+        if recording && !was_recording {
+            segment_start = time.now();
+        }
+        was_recording = recording;
+        if recording && segment_over(&time, segment_start, segment) {
+            let finished = hamshark
+                .session()
+                .recording_clip()
+                .map(|clip| clip.read().id().clone());
+            // The input carries on, so nothing's lost between the clips. If the next one
+            // can't be made this one just runs on, rather than that being the end of it.
+            segment_start = time.now();
+            if let Err(error) = hamshark.next_clip() {
+                warn!(
+                    "Unable to start the next clip, carrying on with this one: {}",
+                    error
+                );
+                continue;
+            }
+            // Nobody is going to look at the finished clip, so don't keep it in memory
+            if let Some(id) = finished {
+                hamshark.session_mut().clips.remove(&id);
+            }
+        }
+    }
+
+    info!("Stopping, finishing the current clip");
+    hamshark.stop()?;
+    Ok(())
+}
+
+/// Record headless like [`run`], while serving remote control with `settings`. Clients can
+/// stop and start recording; segments are counted from whenever the current clip started.
+#[cfg(feature = "server")]
+pub fn serve(
+    open: impl FnOnce() -> Result<session::Session, session::Error> + Send + 'static,
+    segment: Duration,
+    settings: hamshark::config::ServerSettings,
+    desktop: &DesktopSettings,
+) -> Result<(), Error> {
+    use hamshark::{asynchronous::AsyncHamShark, pipeline::ElementState, server, status::State};
+    use tokio::{sync::watch, time};
+
+    let _awake = desktop::keep_awake(desktop);
+    let runtime = tokio::runtime::Runtime::new().map_err(Error::Runtime)?;
+    runtime.block_on(async {
+        let (stop_sender, mut stop) = watch::channel(false);
+        ctrlc::set_handler(move || {
+            let _ = stop_sender.send(true);
+        })?;
+
+        let hamshark = AsyncHamShark::spawn("headless", open)?;
+        let server = tokio::spawn(server::serve(hamshark.clone(), settings, {
+            let mut stop = stop.clone();
+            async move {
+                let _ = stop.wait_for(|stopped| *stopped).await;
+            }
+        }));
+
+        info!("Recording headless, a new clip every {:?}", segment);
+        let time = hamshark.time_source().clone();
+        let mut clip = Some(hamshark.start().await?);
+        let mut segment_start = time.now();
+        loop {
+            tokio::select! {
+                _ = stop.wait_for(|stopped| *stopped) => break,
+                _ = time::sleep(POLL) => {}
+            }
+
+            let status = hamshark.status().await?;
+            if status.state == State::Failed
+                && let Some(element) = status
+                    .elements
+                    .iter()
+                    .find(|e| e.state == ElementState::Failed)
+            {
+                let error =
+                    Error::Pipeline(element.name, element.last_error.clone().unwrap_or_default());
+                hamshark.stop().await?;
+                return Err(error);
+            }
+            // Somebody may have stopped or started recording remotely
+            if status.clip != clip {
+                clip = status.clip;
+                segment_start = time.now();
+            }
+
+            if clip.is_some() && segment_over(&time, segment_start, segment) {
+                segment_start = time.now();
+                match hamshark.next_clip().await {
+                    Ok(next) => {
+                        if let Some(finished) = clip.replace(next) {
+                            hamshark.forget(finished).await?;
+                        }
+                    }
+                    Err(error) => {
+                        warn!(
+                            "Unable to start the next clip, carrying on with this one: {}",
+                            error
+                        )
+                    }
+                }
+            }
+        }
+
+        info!("Stopping, finishing the current clip");
+        hamshark.stop().await?;
+        match server.await {
+            Ok(result) => result.map_err(Error::Server),
+            Err(_) => Ok(()),
+        }
+    })
+}
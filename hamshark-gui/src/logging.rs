@@ -0,0 +1,148 @@
+use chrono::Local;
+use hamshark::config::{Configuration, LoggingSettings};
+use log::{LevelFilter, Log, Metadata, Record, error};
+use parking_lot::Mutex;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+const LOG_FILE: &str = "hamshark.log";
+
+/// The log file and its level, once the settings have said whether there is one
+static FILE: OnceLock<(LevelFilter, Mutex<RotatingFile>)> = OnceLock::new();
+
+/// A log file that's moved aside to hamshark.log.1 once it gets big, with the older ones
+/// shuffling along to .2, .3 and so on until the oldest falls off the end
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, keep: usize) -> Result<Self, io::Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            written,
+            max_bytes,
+            keep,
+        })
+    }
+
+    fn numbered(path: &Path, n: usize) -> PathBuf {
+        let mut numbered = path.as_os_str().to_owned();
+        numbered.push(format!(".{}", n));
+        PathBuf::from(numbered)
+    }
+
+    fn rotate(&mut self) -> Result<(), io::Error> {
+        self.file.flush()?;
+        if self.keep > 0 {
+            for n in (1..self.keep).rev() {
+                let older = Self::numbered(&self.path, n);
+                if older.exists() {
+                    fs::rename(older, Self::numbered(&self.path, n + 1))?;
+                }
+            }
+            fs::rename(&self.path, Self::numbered(&self.path, 1))?;
+        }
+        self.file = File::create(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), io::Error> {
+        if self.written > 0 && self.written + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// Logs to stderr the usual env_logger way, and to a file as well once the settings ask
+/// for it
+struct Logger {
+    stderr: env_logger::Logger,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stderr.enabled(metadata)
+            || FILE
+                .get()
+                .is_some_and(|(level, _)| metadata.level() <= *level)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.stderr.matches(record) {
+            self.stderr.log(record);
+        }
+        if let Some((level, file)) = FILE.get()
+            && record.level() <= *level
+        {
+            let line = format!(
+                "{} {:5} {}: {}\n",
+                Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+            // Nowhere to report a failure to log, so the line is lost
+            let _ = file.lock().write_line(&line);
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+        if let Some((_, file)) = FILE.get() {
+            let _ = file.lock().file.flush();
+        }
+    }
+}
+
+/// Start logging to stderr, first thing, so nothing logged while starting up is missed.
+/// `level` overrides RUST_LOG.
+pub fn init(level: Option<LevelFilter>) {
+    let mut builder = env_logger::Builder::from_default_env();
+    if let Some(level) = level {
+        builder.filter_level(level);
+    }
+    let stderr = builder.build();
+    let max_level = stderr.filter();
+    if log::set_boxed_logger(Box::new(Logger { stderr })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// Log to a file as well, at its own level, if the settings ask for it. Only the first
+/// file opened is kept, so startup can call this again each time it has another go.
+pub fn log_to_file(config: &Configuration, settings: &LoggingSettings) {
+    if !settings.to_file || FILE.get().is_some() {
+        return;
+    }
+    let path = settings
+        .file
+        .clone()
+        .unwrap_or_else(|| config.data_dir.join(LOG_FILE));
+    match RotatingFile::open(path.clone(), settings.max_file_bytes, settings.keep_files) {
+        Ok(file) => {
+            if FILE.set((settings.level, Mutex::new(file))).is_ok() {
+                log::set_max_level(log::max_level().max(settings.level));
+            }
+        }
+        Err(error) => error!("Unable to open log file {:?}: {}", path, error),
+    }
+}
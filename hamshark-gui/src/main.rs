@@ -0,0 +1,182 @@
+use crate::cli::Args;
+use crate::gui::HamSharkGui;
+use crate::startup::Startup;
+use clap::{CommandFactory, Parser, error::ErrorKind};
+use egui::{ViewportBuilder, ViewportCommand};
+use hamshark::{HamShark, data::audioinput::AudioInputDeviceBuilder, replay::Replay};
+use log::{debug, error, warn};
+use std::{fmt::Display, process, time::Duration};
+
+mod cli;
+mod desktop;
+mod gui;
+mod headless;
+mod logging;
+mod startup;
+
+/// Before the first recording, since the pipeline takes the sinks there are when it starts
+#[cfg(feature = "plugins")]
+fn load_plugins(config: &hamshark::config::Configuration) {
+    let dir = config.plugins_dir();
+    match hamshark::plugins::load_dir(&dir) {
+        Ok(loaded) => debug!("Loaded {} plugins from {:?}", loaded, dir.as_os_str()),
+        Err(e) => error!("Unable to load plugins from {:?}: {}", dir.as_os_str(), e),
+    }
+}
+
+#[cfg(not(feature = "plugins"))]
+fn load_plugins(_config: &hamshark::config::Configuration) {
+    warn!("Plugins need hamshark built with the plugins feature");
+}
+
+fn main() -> eframe::Result<()> {
+    desktop::describe_audio_streams();
+    let args = Args::parse();
+    // Straight away, so what goes wrong opening the session is logged. The log file comes
+    // once the settings are loaded.
+    logging::init(args.log_level);
+
+    // There's nobody to show a window to when headless
+    let Startup {
+        config,
+        settings,
+        mut session,
+        first_run,
+    } = if args.headless {
+        startup::open(&args, None).unwrap_or_else(|e| fatal(e))
+    } else {
+        match startup::open_or_ask(&args)? {
+            Some(startup) => startup,
+            None => return Ok(()),
+        }
+    };
+    debug!("{:?}", config);
+    debug!("{:?}", settings);
+    if settings.plugins.enabled {
+        load_plugins(&config);
+    }
+
+    // Put the window back the way it was
+    let layout = &settings.layout;
+    let mut viewport = ViewportBuilder::default()
+        .with_app_id("hamshark")
+        .with_maximized(layout.maximized);
+    if let Some(size) = layout.window_size {
+        viewport = viewport.with_inner_size(size);
+    }
+    if let Some(position) = layout.window_position {
+        viewport = viewport.with_position(position);
+    }
+    let native_options = eframe::NativeOptions {
+        viewport,
+        ..Default::default()
+    };
+
+    for wav in &args.import {
+        if let Err(e) = session.import_clip(wav) {
+            error!("Unable to import {:?}: {}", wav.as_os_str(), e);
+        }
+    }
+
+    let device = match &args.device {
+        Some(name) => AudioInputDeviceBuilder::default()
+            .with_device_named(name)
+            .unwrap_or_else(|| {
+                Args::command()
+                    .error(
+                        ErrorKind::InvalidValue,
+                        format!("no input device named '{name}'"),
+                    )
+                    .exit()
+            }),
+        None => AudioInputDeviceBuilder::default(),
+    };
+    // Kept until the end, its clock stops when it's dropped
+    let replay = args.replay.as_ref().map(|dir| {
+        Replay::load(dir)
+            .and_then(|input| Replay::start(input, args.replay_loop))
+            .unwrap_or_else(|e| fatal(e))
+    });
+    if let Some(replay) = &replay {
+        if let Err(e) = session.configure_virtual(replay.input().clone()) {
+            error!("Unable to configure the replay: {}", e);
+        }
+    } else {
+        // Carry on without an input, it can be chosen in the GUI
+        match device.build() {
+            Ok(device) => {
+                if let Err(e) = session.configure(device) {
+                    error!("Unable to configure the audio input: {}", e);
+                }
+            }
+            Err(e) => warn!("No audio input: {}", e),
+        }
+    }
+
+    #[cfg(feature = "scripting")]
+    let script = args.script.as_ref().map(|path| {
+        hamshark::scripting::Script::load(path, &session, &settings).unwrap_or_else(|e| fatal(e))
+    });
+
+    if args.headless {
+        let segment = args
+            .segment
+            .unwrap_or(settings.recording.segment_seconds)
+            .max(1);
+        let segment = Duration::from_secs(segment);
+        #[cfg(feature = "server")]
+        {
+            let mut server = settings.server.clone();
+            if let Some(listen) = args.serve {
+                server.listen = listen;
+                server.enabled = true;
+            }
+            if server.enabled {
+                if let Err(e) =
+                    headless::serve(move || Ok(session), segment, server, &settings.desktop)
+                {
+                    error!("{}", e);
+                    process::exit(1);
+                }
+                return Ok(());
+            }
+        }
+        if let Err(e) = headless::run(
+            HamShark::new(session),
+            segment,
+            &settings.desktop,
+            #[cfg(feature = "scripting")]
+            script,
+        ) {
+            error!("{}", e);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
+    eframe::run_native(
+        "Hamshark",
+        native_options,
+        Box::new(|cc| {
+            // Being interrupted or terminated closes the window like anything else, so the
+            // clip being recorded is finished properly
+            let ctx = cc.egui_ctx.clone();
+            if let Err(e) = ctrlc::set_handler(move || {
+                ctx.send_viewport_cmd(ViewportCommand::Close);
+                ctx.request_repaint();
+            }) {
+                warn!("Unable to handle signals: {}", e);
+            }
+            let gui = HamSharkGui::new(cc, session, config, settings, first_run);
+            #[cfg(feature = "scripting")]
+            let gui = gui.with_script(script);
+            Ok(Box::new(gui))
+        }),
+    )
+}
+
+/// Give up before there's a GUI to show the problem in
+fn fatal(error: impl Display) -> ! {
+    eprintln!("{}", error);
+    process::exit(1)
+}
@@ -0,0 +1,205 @@
+use crate::{cli::Args, logging};
+use egui::{CentralPanel, ViewportBuilder, ViewportCommand};
+use hamshark::{
+    config::{Configuration, ConfigurationError, Settings, SettingsError},
+    session::{self, Session},
+};
+use log::error;
+use std::{
+    cell::Cell,
+    fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+use thiserror::Error as ThisError;
+
+/// Why the main window couldn't open
+#[derive(Debug, ThisError)]
+pub enum StartupError {
+    #[error("{0}")]
+    Configuration(#[from] ConfigurationError),
+    #[error("Unable to load the settings from {}: {error}", path.display())]
+    Settings { path: PathBuf, error: SettingsError },
+    #[error("Unable to open a session in {}: {error}", dir.display())]
+    Session { dir: PathBuf, error: session::Error },
+}
+
+/// What the user picked to do about a startup error
+#[derive(Debug, Clone, PartialEq)]
+pub enum Remedy {
+    /// Try again as things are, after fixing it some other way
+    Retry,
+    /// Create the directory the settings file goes in
+    CreateSettingsDir(PathBuf),
+    /// Put the settings file aside and start over with the defaults
+    ResetSettings(PathBuf),
+    /// Keep sessions under this directory from now on
+    SessionDir(PathBuf),
+}
+
+/// Everything the main window needs
+pub struct Startup {
+    pub config: Configuration,
+    pub settings: Settings,
+    pub session: Session,
+    pub first_run: bool,
+}
+
+/// Find the settings, load them, and open the session. `session_dir` replaces where
+/// sessions are kept, if the user picked somewhere else.
+pub fn open(args: &Args, session_dir: Option<&Path>) -> Result<Startup, StartupError> {
+    let mut config = Configuration::from_env()?;
+    if let Some(settings_file) = &args.settings {
+        config.settings_file_path = settings_file.clone();
+    }
+    let settings_path = config.settings_file_path.clone();
+    let first_run = !settings_path.exists();
+    let mut settings =
+        Settings::from_file(&settings_path).map_err(|error| StartupError::Settings {
+            path: settings_path.clone(),
+            error,
+        })?;
+    // Before the session's opened, so what's found wrong with its clips is in the file too
+    logging::log_to_file(&config, &settings.logging);
+    if let Some(dir) = session_dir {
+        settings.session_base_dir = dir.to_path_buf();
+        if let Err(error) = settings.save(&settings_path) {
+            error!("Unable to save the new session folder: {}", error);
+        }
+    }
+    let session = match (&args.session, session_dir) {
+        (Some(path), None) => {
+            Session::open(path.clone(), &settings).map_err(|error| StartupError::Session {
+                dir: path.clone(),
+                error,
+            })
+        }
+        _ => Session::from_settings(&config, &settings).map_err(|error| StartupError::Session {
+            dir: config.resolve(&settings.session_base_dir),
+            error,
+        }),
+    }?;
+    Ok(Startup {
+        config,
+        settings,
+        session,
+        first_run,
+    })
+}
+
+impl StartupError {
+    /// What can be done about it from the error screen, besides trying again
+    fn remedies(&self) -> Vec<(&'static str, &'static str, Remedy)> {
+        match self {
+            StartupError::Configuration(_) => Vec::new(),
+            StartupError::Settings { path, error } => {
+                let mut remedies = Vec::new();
+                if let Some(dir) = path.parent()
+                    && !dir.exists()
+                {
+                    remedies.push((
+                        "Create Settings Folder",
+                        "Make the folder the settings file goes in",
+                        Remedy::CreateSettingsDir(dir.to_path_buf()),
+                    ));
+                }
+                if let SettingsError::DeserializationError(_) = error {
+                    remedies.push((
+                        "Reset Settings",
+                        "Keep the broken file alongside as .broken and start over with the \
+                         defaults",
+                        Remedy::ResetSettings(path.clone()),
+                    ));
+                }
+                remedies
+            }
+            StartupError::Session { .. } => Vec::new(),
+        }
+    }
+}
+
+impl Remedy {
+    /// Make the fix. Picking a session folder is left to [`open`].
+    fn apply(&self) -> Result<(), io::Error> {
+        match self {
+            Remedy::Retry | Remedy::SessionDir(_) => Ok(()),
+            Remedy::CreateSettingsDir(dir) => fs::create_dir_all(dir),
+            Remedy::ResetSettings(path) => fs::rename(path, path.with_extension("broken")),
+        }
+    }
+}
+
+/// Explain what stopped the main window opening in a small window of its own, with what
+/// can be done about it. Returns what the user picked, or None if they gave up.
+pub fn show(error: &StartupError) -> eframe::Result<Option<Remedy>> {
+    let picked = Rc::new(Cell::new(None));
+    let options = eframe::NativeOptions {
+        viewport: ViewportBuilder::default()
+            .with_app_id("hamshark")
+            .with_inner_size([480.0, 220.0]),
+        ..Default::default()
+    };
+    let message = error.to_string();
+    let remedies = error.remedies();
+    let pick_session_dir = matches!(error, StartupError::Session { .. });
+    eframe::run_simple_native("Hamshark couldn't start", options, {
+        let picked = picked.clone();
+        move |ctx, _frame| {
+            CentralPanel::default().show(ctx, |ui| {
+                ui.heading("Hamshark couldn't start");
+                ui.add_space(8.0);
+                ui.label(&message);
+                ui.add_space(8.0);
+                ui.horizontal_wrapped(|ui| {
+                    let mut choice = None;
+                    for (label, hover, remedy) in &remedies {
+                        if ui.button(*label).on_hover_text(*hover).clicked() {
+                            choice = Some(remedy.clone());
+                        }
+                    }
+                    if pick_session_dir
+                        && ui
+                            .button("Choose Session Folder…")
+                            .on_hover_text("Keep sessions somewhere that can be written to")
+                            .clicked()
+                    {
+                        choice = rfd::FileDialog::new().pick_folder().map(Remedy::SessionDir);
+                    }
+                    if ui.button("Try Again").clicked() {
+                        choice = Some(Remedy::Retry);
+                    }
+                    if ui.button("Quit").clicked() {
+                        ctx.send_viewport_cmd(ViewportCommand::Close);
+                    }
+                    if choice.is_some() {
+                        picked.set(choice);
+                        ctx.send_viewport_cmd(ViewportCommand::Close);
+                    }
+                });
+            });
+        }
+    })?;
+    Ok(picked.take())
+}
+
+/// Keep asking until the settings and session open or the user gives up. Logging isn't
+/// set up until the settings are loaded, so errors go to the terminal.
+pub fn open_or_ask(args: &Args) -> eframe::Result<Option<Startup>> {
+    let mut session_dir = None;
+    loop {
+        let error = match open(args, session_dir.as_deref()) {
+            Ok(startup) => return Ok(Some(startup)),
+            Err(error) => error,
+        };
+        error!("{}", error);
+        let Some(remedy) = show(&error)? else {
+            return Ok(None);
+        };
+        if let Err(error) = remedy.apply() {
+            error!("Unable to fix it: {}", error);
+        }
+        if let Remedy::SessionDir(dir) = remedy {
+            session_dir = Some(dir);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+/* C API for the Hamshark recorder. Link against the hamshark cdylib.
+ *
+ * Functions returning int give 0 on success and -1 on failure, with the reason in
+ * hamshark_last_error() on the same thread.
+ */
+#ifndef HAMSHARK_H
+#define HAMSHARK_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct HamShark HamShark;
+
+typedef struct {
+    /* 0 idle, 1 recording, 2 paused, 3 failed */
+    int32_t state;
+    uint64_t samples_captured;
+    uint64_t xruns;
+    /* 0 to 1, or -1 when there's no buffer */
+    float buffer_fill;
+} HamSharkStatus;
+
+/* len samples, whole frames of channels interleaved */
+typedef void (*HamSharkSampleCallback)(const float *samples, size_t len, uint16_t channels,
+                                       void *user_data);
+
+/* Valid until the next failing call on this thread, or NULL */
+const char *hamshark_last_error(void);
+
+/* Record into session_dir (UTF-8, made if it doesn't exist), or a new session under the
+ * configured base directory if NULL */
+HamShark *hamshark_new(const char *session_dir);
+void hamshark_free(HamShark *hamshark);
+
+/* The input device called name (UTF-8), or the default input if NULL */
+int hamshark_configure_device(HamShark *hamshark, const char *name);
+int hamshark_start(HamShark *hamshark);
+int hamshark_stop(HamShark *hamshark);
+void hamshark_status(const HamShark *hamshark, HamSharkStatus *status);
+
+/* Runs on the clip writer thread. Fails if callback is NULL. */
+int hamshark_on_samples(HamShark *hamshark, HamSharkSampleCallback callback, void *user_data);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
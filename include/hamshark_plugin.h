@@ -0,0 +1,62 @@
+/* Plugin API for Hamshark. Build a shared library exporting hamshark_plugin_register and put
+ * it in the plugins directory under Hamshark's data directory. Plugins are only loaded with
+ * plugins.enabled set in the settings, and hamshark built with the plugins feature.
+ *
+ * A plugin registers sinks, which are handed every block of samples recorded, and can report
+ * lines of text they decode. Each sink shows up as an element of the recording pipeline.
+ *
+ * Sinks are the only thing a plugin can provide. There's no registering a source, so
+ * recordings still come from the inputs built into Hamshark.
+ */
+#ifndef HAMSHARK_PLUGIN_H
+#define HAMSHARK_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define HAMSHARK_PLUGIN_ABI_VERSION 1
+
+/* What a sink can call back into Hamshark with, valid until the sink is destroyed */
+typedef struct {
+    void *context;
+    /* Report a line of decoded text. frequency_hz is the audio frequency it was decoded
+     * at, or 0 if that doesn't apply. */
+    void (*decoded)(void *context, const char *text, double frequency_hz);
+} HamSharkSinkHost;
+
+typedef struct {
+    /* Shown in the pipeline and the Plugins menu. Copied when registered. */
+    const char *name;
+    /* Called on the clip writer thread when a clip starts recording. Samples are
+     * interleaved when channels is more than one. Returns the sink's state, or NULL if it
+     * can't take this recording. */
+    void *(*create)(uint32_t sample_rate, uint16_t channels, const HamSharkSinkHost *host);
+    /* Called on the clip writer thread with each block written to the clip. Keep it quick,
+     * the buffer fills while this runs. Returns 0, or -1 to be left out of the rest of the
+     * recording. */
+    int32_t (*process)(void *state, const float *samples, size_t len);
+    /* Called when the clip is finished */
+    void (*destroy)(void *state);
+} HamSharkSink;
+
+typedef struct {
+    /* HAMSHARK_PLUGIN_ABI_VERSION of the Hamshark loading the plugin */
+    uint32_t abi_version;
+    void *context;
+    /* Returns 0, or -1 if the sink is missing something */
+    int32_t (*register_sink)(void *context, const HamSharkSink *sink);
+} HamSharkPluginHost;
+
+/* Exported by the plugin. Register its sinks and return 0, or return -1 to not be loaded,
+ * like for an ABI version it doesn't know. */
+int32_t hamshark_plugin_register(const HamSharkPluginHost *host);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,863 @@
+use crate::{data::window::WindowFunction, fft::Transform};
+use rustfft::num_complex::Complex;
+use std::ops::Range;
+
+/// The power in each frequency bin of some samples, averaged over as many FFTs as fit
+#[derive(Debug, Clone, PartialEq)]
+pub struct PowerSpectrum {
+    /// How far apart the bins are
+    pub bin_hz: f64,
+    /// Mean square power in each bin from DC up to just under Nyquist, scaled so they add up
+    /// to the mean square of the samples
+    pub power: Vec<f64>,
+    /// How many FFTs were averaged
+    pub blocks: usize,
+}
+
+impl PowerSpectrum {
+    /// Average windowed FFTs of `size` over the samples. None if there aren't enough samples
+    /// for one.
+    pub fn new(
+        samples: &[f32],
+        sample_rate: u32,
+        size: usize,
+        window: WindowFunction,
+    ) -> Option<Self> {
+        if size < 4 || samples.len() < size || sample_rate == 0 {
+            return None;
+        }
+        let mut fft = Transform::forward(size);
+        let window = window.coefficients(size);
+        let bins = size / 2;
+        let mut power = vec![0.0f64; bins];
+        let mut buffer = vec![Complex::default(); size];
+        let mut blocks = 0;
+        for block in samples.chunks_exact(size) {
+            for ((out, sample), w) in buffer.iter_mut().zip(block).zip(&window) {
+                *out = Complex::new(sample * w, 0.0);
+            }
+            fft.process(&mut buffer);
+            for (total, bin) in power.iter_mut().zip(&buffer) {
+                *total += bin.norm_sqr() as f64;
+            }
+            blocks += 1;
+        }
+
+        // Parseval, undoing what the window took off, and folding the negative frequencies
+        // onto the positive ones
+        let window_power: f64 = window.iter().map(|w| (w * w) as f64).sum();
+        let scale = 1.0 / (blocks as f64 * size as f64 * window_power);
+        for (bin, total) in power.iter_mut().enumerate() {
+            *total *= if bin == 0 { scale } else { 2.0 * scale };
+        }
+        Some(Self {
+            bin_hz: sample_rate as f64 / size as f64,
+            power,
+            blocks,
+        })
+    }
+
+    /// The bins a band of frequencies covers, leaving out DC
+    pub fn bins(&self, band: Range<f64>) -> Range<usize> {
+        let bins = self.power.len();
+        let low = ((band.start / self.bin_hz).round().max(1.0) as usize).min(bins);
+        let high = ((band.end / self.bin_hz).round().max(1.0) as usize).min(bins);
+        low..high.max(low)
+    }
+
+    /// Total power in a band of frequencies
+    pub fn power_in(&self, band: Range<f64>) -> f64 {
+        self.power[self.bins(band)].iter().sum()
+    }
+
+    /// Noise power per Hz, from the median bin, so signals don't pull it up. Bins outside
+    /// `except` are used, or all of them if that leaves too few.
+    pub fn noise_density(&self, except: Option<Range<f64>>) -> f64 {
+        let mut bins: Vec<f64> = match except {
+            Some(band) => {
+                let band = self.bins(band);
+                self.power
+                    .iter()
+                    .enumerate()
+                    .skip(1)
+                    .filter(|(bin, _)| !band.contains(bin))
+                    .map(|(_, power)| *power)
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        if bins.len() < 8 {
+            bins = self.power.iter().skip(1).copied().collect();
+        }
+        if bins.is_empty() {
+            return 0.0;
+        }
+        bins.sort_by(f64::total_cmp);
+        let median = bins[bins.len() / 2];
+        // Averaged noise bins aren't symmetric about their mean, the median's low by about
+        // this much (Wilson-Hilferty)
+        let bias = (1.0 - 1.0 / (9.0 * self.blocks as f64)).powi(3);
+        median / bias / self.bin_hz
+    }
+
+    /// The strongest frequency in a band, to a fraction of a bin. A parabola through the log
+    /// power around the peak bin says where between bins it is. None if nothing peaks there.
+    pub fn peak(&self, band: Range<f64>) -> Option<f64> {
+        let bins = self.power.len();
+        if bins < 3 {
+            return None;
+        }
+        let low = ((band.start / self.bin_hz).floor().max(1.0) as usize).min(bins - 1);
+        let high = ((band.end / self.bin_hz).ceil().max(1.0) as usize).min(bins - 2);
+        if low > high {
+            return None;
+        }
+        let peak = (low..=high).max_by(|&a, &b| self.power[a].total_cmp(&self.power[b]))?;
+        let ln = |bin: usize| self.power[bin].max(f64::MIN_POSITIVE).ln();
+        let (left, centre, right) = (ln(peak - 1), ln(peak), ln(peak + 1));
+        let curvature = left - 2.0 * centre + right;
+        if curvature >= 0.0 {
+            return None;
+        }
+        let offset = 0.5 * (left - right) / curvature;
+        Some((peak as f64 + offset) * self.bin_hz)
+    }
+}
+
+/// How far a signal stands above the noise
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Snr {
+    /// Mean square power of the signal, without the noise under it
+    pub signal: f64,
+    /// Noise power per Hz
+    pub noise_density: f64,
+    /// The bandwidth the noise is counted over, in Hz
+    pub reference_bandwidth: f64,
+}
+
+impl Snr {
+    /// Signal to noise in the reference bandwidth, in dB. Minus infinity if there's no
+    /// signal above the noise.
+    pub fn db(&self) -> f64 {
+        10.0 * (self.signal / (self.noise_density * self.reference_bandwidth)).log10()
+    }
+
+    /// The noise floor in the reference bandwidth, in dB relative to full scale
+    pub fn noise_db(&self) -> f64 {
+        10.0 * (self.noise_density * self.reference_bandwidth).log10()
+    }
+}
+
+/// The FFT size SNR is measured with. Long enough to resolve a narrow signal from the
+/// noise, short enough to average a few seconds into a steady floor.
+const SNR_FFT: usize = 4096;
+
+/// Measure the SNR of the signal in `band`, or of everything if there's no band, against the
+/// noise in `reference_bandwidth` Hz. None if there aren't enough samples.
+pub fn snr(
+    samples: &[f32],
+    sample_rate: u32,
+    band: Option<Range<f64>>,
+    reference_bandwidth: f64,
+) -> Option<Snr> {
+    let size = SNR_FFT.min(1 << samples.len().max(1).ilog2());
+    let spectrum = PowerSpectrum::new(samples, sample_rate, size, WindowFunction::Hann)?;
+    let band = band.unwrap_or(0.0..sample_rate as f64 / 2.0);
+    let bins = spectrum.bins(band.clone());
+    let noise_density = spectrum.noise_density(Some(band.clone()));
+    let noise = noise_density * bins.len() as f64 * spectrum.bin_hz;
+    Some(Snr {
+        signal: (spectrum.power_in(band) - noise).max(0.0),
+        noise_density,
+        reference_bandwidth,
+    })
+}
+
+/// The FFT size distortion is measured with, fine enough to keep harmonics of a low test tone
+/// apart
+const DISTORTION_FFT: usize = 16384;
+/// Bins either side of a tone's peak that are counted as the tone. The Blackman-Harris window
+/// spreads it over four either side, the rest is room for it to wander.
+const TONE_BINS: usize = 6;
+/// The highest harmonic measured
+const MAX_HARMONIC: usize = 10;
+
+/// A harmonic of a test tone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Harmonic {
+    /// 2 for the second harmonic, and so on
+    pub order: usize,
+    pub frequency: f64,
+    /// Mean square power, without the noise under it
+    pub power: f64,
+}
+
+/// How cleanly a test tone came through
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distortion {
+    pub fundamental_hz: f64,
+    /// Mean square power of the tone
+    pub fundamental: f64,
+    /// The harmonics that fit under Nyquist
+    pub harmonics: Vec<Harmonic>,
+    /// Mean square power of everything that isn't the tone or its harmonics
+    pub noise: f64,
+    pub spectrum: PowerSpectrum,
+}
+
+impl Distortion {
+    /// Total harmonic distortion, as a ratio of amplitudes
+    pub fn thd(&self) -> f64 {
+        let harmonics: f64 = self.harmonics.iter().map(|h| h.power).sum();
+        (harmonics / self.fundamental).sqrt()
+    }
+
+    /// Total harmonic distortion and noise, as a ratio of amplitudes
+    pub fn thd_n(&self) -> f64 {
+        let harmonics: f64 = self.harmonics.iter().map(|h| h.power).sum();
+        ((harmonics + self.noise) / self.fundamental).sqrt()
+    }
+
+    /// The noise floor, in dB relative to full scale per Hz
+    pub fn noise_floor_db(&self) -> f64 {
+        10.0 * self.spectrum.noise_density(None).log10()
+    }
+}
+
+/// Power of a tone in the bins around `frequency`, less the noise under it
+fn tone_power(spectrum: &PowerSpectrum, frequency: f64, noise_density: f64) -> f64 {
+    let width = (TONE_BINS as f64 + 0.5) * spectrum.bin_hz;
+    let band = frequency - width..frequency + width;
+    let noise = noise_density * spectrum.bins(band.clone()).len() as f64 * spectrum.bin_hz;
+    (spectrum.power_in(band) - noise).max(0.0)
+}
+
+/// Measure the distortion of the strongest tone in the samples. None if there aren't enough
+/// samples, or no tone.
+pub fn distortion(samples: &[f32], sample_rate: u32) -> Option<Distortion> {
+    let size = DISTORTION_FFT.min(1 << samples.len().max(1).ilog2());
+    let spectrum = PowerSpectrum::new(samples, sample_rate, size, WindowFunction::BlackmanHarris)?;
+    let nyquist = sample_rate as f64 / 2.0;
+    // Stay clear of DC, which a sound card's offset puts a peak at
+    let lowest = (TONE_BINS + 1) as f64 * spectrum.bin_hz;
+    let fundamental_hz = spectrum.peak(lowest..nyquist)?;
+    let noise_density = spectrum.noise_density(None);
+    let fundamental = tone_power(&spectrum, fundamental_hz, noise_density);
+    if fundamental <= 0.0 {
+        return None;
+    }
+
+    let edge = nyquist - TONE_BINS as f64 * spectrum.bin_hz;
+    let harmonics: Vec<Harmonic> = (2..=MAX_HARMONIC)
+        .map(|order| (order, fundamental_hz * order as f64))
+        .take_while(|(_, frequency)| *frequency < edge)
+        .map(|(order, frequency)| Harmonic {
+            order,
+            frequency,
+            power: tone_power(&spectrum, frequency, noise_density),
+        })
+        .collect();
+
+    let total = spectrum.power_in(lowest..nyquist);
+    let harmonic_power: f64 = harmonics.iter().map(|h| h.power).sum();
+    Some(Distortion {
+        fundamental_hz,
+        fundamental,
+        noise: (total - fundamental - harmonic_power).max(0.0),
+        harmonics,
+        spectrum,
+    })
+}
+
+/// The highest order of intermodulation product measured
+const MAX_IMD_ORDER: usize = 9;
+
+/// An intermodulation product of a two-tone test, one either side of the tones for each order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImdProduct {
+    /// 3 for 2f1 - f2 and 2f2 - f1, and so on
+    pub order: usize,
+    pub frequency: f64,
+    /// Mean square power, without the noise under it
+    pub power: f64,
+}
+
+/// The intermodulation a two-tone test picked up on its way through
+#[derive(Debug, Clone, PartialEq)]
+pub struct Intermodulation {
+    /// The lower and upper tones, in Hz
+    pub tones: (f64, f64),
+    /// Mean square power of each tone
+    pub tone_power: (f64, f64),
+    /// The products that fit between DC and Nyquist, lowest order first
+    pub products: Vec<ImdProduct>,
+    /// Noise power per Hz
+    pub noise_density: f64,
+    pub spectrum: PowerSpectrum,
+}
+
+impl Intermodulation {
+    /// The stronger product of an order, in dB below one tone. None if there's no product
+    /// of that order above the noise, or it's past Nyquist.
+    pub fn dbc(&self, order: usize) -> Option<f64> {
+        let reference = self.tone_power.0.max(self.tone_power.1);
+        self.products
+            .iter()
+            .filter(|product| product.order == order && product.power > 0.0)
+            .map(|product| 10.0 * (product.power / reference).log10())
+            .max_by(f64::total_cmp)
+    }
+
+    /// The same, relative to the peak envelope power of the two tones, as the ARRL reports
+    /// it. PEP is 6 dB over each of two equal tones.
+    pub fn below_pep(&self, order: usize) -> Option<f64> {
+        let pep = (self.tone_power.0.sqrt() + self.tone_power.1.sqrt()).powi(2);
+        let reference = self.tone_power.0.max(self.tone_power.1);
+        Some(self.dbc(order)? - 10.0 * (pep / reference).log10())
+    }
+}
+
+/// Measure the intermodulation of the two strongest tones in the samples. None if there
+/// aren't enough samples, or not two tones.
+pub fn intermodulation(samples: &[f32], sample_rate: u32) -> Option<Intermodulation> {
+    let size = DISTORTION_FFT.min(1 << samples.len().max(1).ilog2());
+    let spectrum = PowerSpectrum::new(samples, sample_rate, size, WindowFunction::BlackmanHarris)?;
+    let nyquist = sample_rate as f64 / 2.0;
+    let lowest = (TONE_BINS + 1) as f64 * spectrum.bin_hz;
+    let first = spectrum.peak(lowest..nyquist)?;
+
+    // The second tone is the strongest peak clear of the first
+    let clear = 2.0 * TONE_BINS as f64 * spectrum.bin_hz;
+    let peak_bin = spectrum
+        .bins(lowest..nyquist)
+        .filter(|bin| (*bin as f64 * spectrum.bin_hz - first).abs() > clear)
+        .max_by(|a, b| spectrum.power[*a].total_cmp(&spectrum.power[*b]))?;
+    let centre = peak_bin as f64 * spectrum.bin_hz;
+    let second = spectrum.peak(centre..centre)?;
+
+    let noise_density = spectrum.noise_density(None);
+    let (low, high) = (first.min(second), first.max(second));
+    let tones = (
+        tone_power(&spectrum, low, noise_density),
+        tone_power(&spectrum, high, noise_density),
+    );
+    if tones.0 <= 0.0 || tones.1 <= 0.0 {
+        return None;
+    }
+
+    // Odd orders land either side of the tones: for order 2k+1, (k+1)f1 - kf2 below and
+    // (k+1)f2 - kf1 above
+    let edge = nyquist - TONE_BINS as f64 * spectrum.bin_hz;
+    let spacing = high - low;
+    let products = (3..=MAX_IMD_ORDER)
+        .step_by(2)
+        .flat_map(|order| {
+            let k = (order / 2) as f64;
+            [low - k * spacing, high + k * spacing].map(|frequency| (order, frequency))
+        })
+        .filter(|(_, frequency)| (lowest..edge).contains(frequency))
+        .map(|(order, frequency)| ImdProduct {
+            order,
+            frequency,
+            power: tone_power(&spectrum, frequency, noise_density),
+        })
+        .collect();
+
+    Some(Intermodulation {
+        tones: (low, high),
+        tone_power: tones,
+        products,
+        noise_density,
+        spectrum,
+    })
+}
+
+/// The longest FFT the frequency estimate starts from. At 48 kHz bins are 0.18 Hz apart.
+const ESTIMATE_FFT: usize = 1 << 18;
+
+/// Where the strongest tone in some samples is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneEstimate {
+    /// In Hz, from the phase the tone turns through
+    pub frequency: f64,
+    /// In Hz, from interpolating between FFT bins
+    pub interpolated: f64,
+    /// Power of the tone in dB relative to full scale
+    pub level_db: f64,
+}
+
+/// The phase of a tone at `frequency` in the samples, from a single Hann-windowed DFT bin
+fn phase_at(samples: &[f32], sample_rate: u32, frequency: f64) -> f64 {
+    let window = WindowFunction::Hann.coefficients(samples.len());
+    let step = -2.0 * std::f64::consts::PI * frequency / sample_rate as f64;
+    let (re, im) =
+        samples
+            .iter()
+            .zip(&window)
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (n, (sample, w))| {
+                let (sin, cos) = (step * n as f64).sin_cos();
+                let x = (sample * w) as f64;
+                (re + x * cos, im + x * sin)
+            });
+    im.atan2(re)
+}
+
+/// Estimate the frequency of the strongest tone in `band`, or anywhere, to a small fraction
+/// of a Hz. The FFT peak is interpolated between bins, then refined by how far the tone's
+/// phase turns from the first half of the samples to the second. None if there aren't
+/// enough samples, or no tone.
+pub fn estimate_frequency(
+    samples: &[f32],
+    sample_rate: u32,
+    band: Option<Range<f64>>,
+) -> Option<ToneEstimate> {
+    let size = ESTIMATE_FFT.min(1 << samples.len().max(1).ilog2());
+    let spectrum = PowerSpectrum::new(samples, sample_rate, size, WindowFunction::Hann)?;
+    let band = band.unwrap_or(0.0..sample_rate as f64 / 2.0);
+    let interpolated = spectrum.peak(band)?;
+    let noise_density = spectrum.noise_density(None);
+    let level_db = 10.0 * tone_power(&spectrum, interpolated, noise_density).log10();
+
+    // Between the halves a tone at the interpolated frequency turns through a known phase.
+    // Whatever it turns through beyond that is how far off the interpolation is, as long as
+    // it's within half a turn, which the interpolation is good for.
+    let half = samples.len() / 2;
+    let first = phase_at(&samples[..half], sample_rate, interpolated);
+    let second = phase_at(&samples[half..2 * half], sample_rate, interpolated);
+    let turn = std::f64::consts::TAU;
+    let expected = turn * interpolated * half as f64 / sample_rate as f64;
+    let extra = (second - first - expected).rem_euclid(turn);
+    let extra = if extra > turn / 2.0 {
+        extra - turn
+    } else {
+        extra
+    };
+    let frequency = interpolated + extra * sample_rate as f64 / (turn * half as f64);
+    Some(ToneEstimate {
+        frequency,
+        interpolated,
+        level_db,
+    })
+}
+
+/// How far either side of a tone each step of a stability measurement looks for it, in Hz
+const STABILITY_SEARCH_HZ: f64 = 20.0;
+
+/// The slope of a straight line fitted through some points by least squares. None with fewer
+/// than two, or when they're all at the same x.
+pub fn slope(points: impl Iterator<Item = (f64, f64)> + Clone) -> Option<f64> {
+    let n = points.clone().count() as f64;
+    if n < 2.0 {
+        return None;
+    }
+    let (sum_x, sum_y) = points
+        .clone()
+        .fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+    let (mean_x, mean_y) = (sum_x / n, sum_y / n);
+    let (covariance, variance) = points.fold((0.0, 0.0), |(c, v), (x, y)| {
+        let dx = x - mean_x;
+        (c + dx * (y - mean_y), v + dx * dx)
+    });
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+/// How steady a tone's frequency held over a stretch
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stability {
+    /// The frequency at each step, as (seconds from the start, Hz)
+    pub points: Vec<(f64, f64)>,
+    pub mean_hz: f64,
+    /// From a straight line through the points, in Hz per second
+    pub drift_rate: f64,
+    /// Between the lowest and highest it read
+    pub spread_hz: f64,
+    /// In seconds
+    pub duration: f64,
+}
+
+impl Stability {
+    /// How far it drifted from start to end, along the fitted line
+    pub fn drift_hz(&self) -> f64 {
+        self.drift_rate * self.duration
+    }
+
+    /// A shift in Hz as parts per million of `hz`, like the tone or the frequency on the air
+    pub fn ppm(shift_hz: f64, hz: f64) -> f64 {
+        shift_hz / hz * 1e6
+    }
+}
+
+/// Measure the strongest tone every `step_seconds` through the samples, to see how far it
+/// drifts. None if there's no tone, or room for fewer than two steps.
+pub fn frequency_stability(
+    samples: &[f32],
+    sample_rate: u32,
+    step_seconds: f64,
+) -> Option<Stability> {
+    let tone = estimate_frequency(samples, sample_rate, None)?.frequency;
+    let step = (step_seconds * sample_rate as f64) as usize;
+    if step == 0 {
+        return None;
+    }
+    let band = (tone - STABILITY_SEARCH_HZ).max(0.0)..tone + STABILITY_SEARCH_HZ;
+    let points: Vec<(f64, f64)> = samples
+        .chunks_exact(step)
+        .enumerate()
+        .filter_map(|(index, chunk)| {
+            let estimate = estimate_frequency(chunk, sample_rate, Some(band.clone()))?;
+            let middle = (index * step + step / 2) as f64 / sample_rate as f64;
+            Some((middle, estimate.frequency))
+        })
+        .collect();
+    let drift_rate = slope(points.iter().copied())?;
+    let (low, high) = points
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(low, high), (_, hz)| {
+            (low.min(*hz), high.max(*hz))
+        });
+    Some(Stability {
+        mean_hz: points.iter().map(|(_, hz)| hz).sum::<f64>() / points.len() as f64,
+        drift_rate,
+        spread_hz: high - low,
+        duration: (points.len() * step) as f64 / sample_rate as f64,
+        points,
+    })
+}
+
+/// The most samples occupied bandwidth is estimated from, about 20 seconds at 48 kHz, so
+/// the estimate keeps up with a selection being dragged out
+const BANDWIDTH_SAMPLES: usize = 1 << 20;
+/// The FFT size occupied bandwidth is estimated with
+const BANDWIDTH_FFT: usize = 4096;
+/// The share of the power left outside the occupied bandwidth, half each side, as the ITU
+/// defines it
+const OUTSIDE_BANDWIDTH: f64 = 0.01;
+
+/// Level statistics of some samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statistics {
+    /// In seconds
+    pub duration: f64,
+    pub rms: f64,
+    /// The largest absolute sample
+    pub peak: f64,
+    /// The mean, which should be zero
+    pub dc_offset: f64,
+    /// The audio frequencies holding 99% of the power, if there are enough samples to say
+    pub occupied: Option<Range<f64>>,
+}
+
+impl Statistics {
+    /// How far the peak stands above the RMS, as a ratio
+    pub fn crest_factor(&self) -> f64 {
+        self.peak / self.rms
+    }
+}
+
+/// Measure the level of some samples, and the bandwidth they take up
+pub fn statistics(samples: &[f32], sample_rate: u32) -> Statistics {
+    let duration = samples.len() as f64 / sample_rate.max(1) as f64;
+    let count = samples.len().max(1) as f64;
+    let (sum, sum_squares, peak) =
+        samples
+            .iter()
+            .fold((0.0, 0.0, 0.0f64), |(sum, sum_squares, peak), sample| {
+                let sample = *sample as f64;
+                (
+                    sum + sample,
+                    sum_squares + sample * sample,
+                    peak.max(sample.abs()),
+                )
+            });
+
+    let samples = &samples[..samples.len().min(BANDWIDTH_SAMPLES)];
+    let size = BANDWIDTH_FFT.min(1 << samples.len().max(1).ilog2());
+    let occupied =
+        PowerSpectrum::new(samples, sample_rate, size, WindowFunction::Hann).and_then(|spectrum| {
+            // Leave DC out, the offset's reported on its own
+            let power = &spectrum.power[1..];
+            let total: f64 = power.iter().sum();
+            if total <= 0.0 {
+                return None;
+            }
+            let mut running = 0.0;
+            let cumulative: Vec<f64> = power
+                .iter()
+                .map(|bin| {
+                    running += bin;
+                    running / total
+                })
+                .collect();
+            let low = cumulative.partition_point(|share| *share < OUTSIDE_BANDWIDTH / 2.0);
+            let high = cumulative.partition_point(|share| *share < 1.0 - OUTSIDE_BANDWIDTH / 2.0);
+            // Bins are numbered from 1, having left out DC
+            Some((low as f64 + 0.5) * spectrum.bin_hz..(high as f64 + 1.5) * spectrum.bin_hz)
+        });
+
+    Statistics {
+        duration,
+        rms: (sum_squares / count).sqrt(),
+        peak,
+        dc_offset: sum / count,
+        occupied,
+    }
+}
+
+/// The FFT size the CW tone is found with
+const CW_TONE_FFT: usize = 4096;
+/// How finely the keying envelope is followed, in ms
+const CW_STEP_MS: u32 = 1;
+/// The envelope averages this many steps, enough to hush the tone without blurring the
+/// elements of fast CW
+const CW_SMOOTHING_STEPS: usize = 5;
+/// Marks and spaces shorter than this are taken for noise, in ms
+const CW_GLITCH_MS: f64 = 4.0;
+/// Keying has to be about this many times louder than the gaps to be followed
+const CW_MIN_CONTRAST: f64 = 3.0;
+
+/// The mean and spread of some element lengths, in ms
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Durations {
+    pub count: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+impl Durations {
+    fn of(lengths: &[f64]) -> Option<Self> {
+        if lengths.is_empty() {
+            return None;
+        }
+        let count = lengths.len();
+        let mean = lengths.iter().sum::<f64>() / count as f64;
+        let variance = lengths.iter().map(|l| (l - mean).powi(2)).sum::<f64>() / count as f64;
+        Some(Self {
+            count,
+            mean,
+            std_dev: variance.sqrt(),
+        })
+    }
+}
+
+/// How fast and how evenly some CW was sent
+#[derive(Debug, Clone, PartialEq)]
+pub struct CwSpeed {
+    /// The tone the keying was followed on
+    pub tone_hz: f64,
+    /// Speed of the elements, from the length of a dit unit by PARIS
+    pub wpm: f64,
+    /// Speed counting the gaps between characters and words too, lower than `wpm` for
+    /// Farnsworth spacing
+    pub effective_wpm: f64,
+    pub dits: Option<Durations>,
+    pub dahs: Option<Durations>,
+    /// Spaces between the elements of a character
+    pub element_spaces: Option<Durations>,
+    pub character_spaces: Option<Durations>,
+    pub word_spaces: Option<Durations>,
+}
+
+impl CwSpeed {
+    /// Length of the dit unit, in ms
+    pub fn unit(&self) -> f64 {
+        1200.0 / self.wpm
+    }
+
+    /// Dits over the spaces between elements, one for even keying and more for heavy
+    pub fn weighting(&self) -> Option<f64> {
+        Some(self.dits?.mean / self.element_spaces?.mean)
+    }
+
+    /// Dahs over dits, three by the book
+    pub fn dah_ratio(&self) -> Option<f64> {
+        Some(self.dahs?.mean / self.dits?.mean)
+    }
+}
+
+/// The median of some lengths, which mustn't be empty
+fn median(lengths: &[f64]) -> f64 {
+    let mut sorted = lengths.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    sorted[sorted.len() / 2]
+}
+
+/// The level of the strongest tone over time, a step at a time, by mixing it down to DC and
+/// averaging
+fn keying_envelope(samples: &[f32], sample_rate: u32, tone: f64) -> Vec<f64> {
+    let step = (sample_rate * CW_STEP_MS / 1000).max(1) as usize;
+    let increment = std::f64::consts::TAU * tone / sample_rate as f64;
+    let sums: Vec<(f64, f64)> = samples
+        .chunks_exact(step)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut phase = (index * step) as f64 * increment;
+            chunk.iter().fold((0.0, 0.0), |(i, q), sample| {
+                let sample = *sample as f64;
+                let sum = (i + sample * phase.cos(), q - sample * phase.sin());
+                phase += increment;
+                sum
+            })
+        })
+        .collect();
+    sums.windows(CW_SMOOTHING_STEPS)
+        .map(|window| {
+            let (i, q) = window
+                .iter()
+                .fold((0.0, 0.0), |(i, q), (di, dq)| (i + di, q + dq));
+            i.hypot(q)
+        })
+        .collect()
+}
+
+/// Measure the speed of the CW in some samples from its keying, without decoding it. None
+/// if there's no keyed tone to follow, or too few elements of it.
+pub fn cw_speed(samples: &[f32], sample_rate: u32) -> Option<CwSpeed> {
+    let spectrum = PowerSpectrum::new(samples, sample_rate, CW_TONE_FFT, WindowFunction::Hann)?;
+    let nyquist = sample_rate as f64 / 2.0;
+    let tone_hz = spectrum.peak(100.0..nyquist - 100.0)?;
+
+    let envelope = keying_envelope(samples, sample_rate, tone_hz);
+    if envelope.len() < 16 {
+        return None;
+    }
+    let mut sorted = envelope.clone();
+    sorted.sort_by(f64::total_cmp);
+    let low = sorted[sorted.len() / 10];
+    let high = sorted[sorted.len() * 95 / 100];
+    if high < CW_MIN_CONTRAST * low || high <= 0.0 {
+        return None;
+    }
+    // A little hysteresis, so noise on an edge doesn't chatter
+    let on = low + 0.5 * (high - low);
+    let off = low + 0.4 * (high - low);
+
+    // Runs of key down and key up, in ms
+    let step_ms = CW_STEP_MS as f64;
+    let mut runs: Vec<(bool, f64)> = Vec::new();
+    let mut keyed = envelope[0] > on;
+    for level in &envelope {
+        keyed = if keyed { *level > off } else { *level > on };
+        match runs.last_mut() {
+            Some((state, length)) if *state == keyed => *length += step_ms,
+            _ => runs.push((keyed, step_ms)),
+        }
+    }
+    // Fold glitches into the runs either side of them
+    let mut merged: Vec<(bool, f64)> = Vec::new();
+    for (state, length) in runs {
+        match merged.last_mut() {
+            Some((last, total)) if *last == state => *total += length,
+            Some((_, total)) if length < CW_GLITCH_MS => *total += length,
+            _ => merged.push((state, length)),
+        }
+    }
+    // The first and last runs are cut off by the ends of the selection
+    if merged.len() < 3 {
+        return None;
+    }
+    let runs = &merged[1..merged.len() - 1];
+    let marks: Vec<f64> = runs.iter().filter(|(s, _)| *s).map(|(_, l)| *l).collect();
+    let spaces: Vec<f64> = runs.iter().filter(|(s, _)| !*s).map(|(_, l)| *l).collect();
+    if marks.len() < 4 || spaces.is_empty() {
+        return None;
+    }
+
+    // Dits and dahs are the two clusters of marks, split at the biggest jump between them.
+    // Sent all one kind, the spaces between elements say which.
+    let mut sorted = marks.clone();
+    sorted.sort_by(f64::total_cmp);
+    let (jump, split) = sorted
+        .windows(2)
+        .enumerate()
+        .map(|(index, pair)| (pair[1] / pair[0], index + 1))
+        .fold(
+            (0.0, 0),
+            |best, jump| if jump.0 > best.0 { jump } else { best },
+        );
+    let rough_unit = if jump >= 1.8 {
+        median(&sorted[..split])
+    } else {
+        let shortest_spaces = {
+            let mut spaces = spaces.clone();
+            spaces.sort_by(f64::total_cmp);
+            spaces[spaces.len() / 4]
+        };
+        let mark = median(&sorted);
+        if mark > 2.0 * shortest_spaces {
+            mark / 3.0
+        } else {
+            mark
+        }
+    };
+
+    let dits: Vec<f64> = marks
+        .iter()
+        .copied()
+        .filter(|l| *l < 2.0 * rough_unit)
+        .collect();
+    let dahs: Vec<f64> = marks
+        .iter()
+        .copied()
+        .filter(|l| *l >= 2.0 * rough_unit)
+        .collect();
+    let element_spaces: Vec<f64> = spaces
+        .iter()
+        .copied()
+        .filter(|l| *l < 2.0 * rough_unit)
+        .collect();
+    // The longer spaces are between characters and between words. Farnsworth spacing
+    // stretches both, so they're told apart by the jump between them rather than by units.
+    let mut gaps: Vec<f64> = spaces
+        .iter()
+        .copied()
+        .filter(|l| *l >= 2.0 * rough_unit)
+        .collect();
+    gaps.sort_by(f64::total_cmp);
+    let (jump, split) = gaps
+        .windows(2)
+        .enumerate()
+        .map(|(index, pair)| (pair[1] / pair[0], index + 1))
+        .fold(
+            (0.0, 0),
+            |best, jump| if jump.0 > best.0 { jump } else { best },
+        );
+    let split = if jump >= 1.6 {
+        split
+    } else if gaps.first().is_some_and(|gap| *gap >= 5.0 * rough_unit) {
+        0
+    } else {
+        gaps.len()
+    };
+    let (character_spaces, word_spaces) = gaps.split_at(split);
+
+    // Weighting lengthens marks by what it takes off the spaces, so a dit and the space
+    // after it still come to two units
+    let dit = Durations::of(&dits);
+    let dah = Durations::of(&dahs);
+    let element = Durations::of(&element_spaces);
+    let unit = match (dit, dah, element) {
+        (Some(dit), _, Some(element)) => (dit.mean + element.mean) / 2.0,
+        (None, Some(dah), Some(element)) => (dah.mean + element.mean) / 4.0,
+        (Some(dit), _, None) => dit.mean,
+        (None, Some(dah), None) => dah.mean / 3.0,
+        (None, None, _) => return None,
+    };
+    // And the unit that fits everything, gaps between characters and words included
+    let units = (dits.len() + 3 * dahs.len() + element_spaces.len()) as f64
+        + 3.0 * character_spaces.len() as f64
+        + 7.0 * word_spaces.len() as f64;
+    let sum = |lengths: &[f64]| lengths.iter().sum::<f64>();
+    let effective_unit = (sum(&marks) + sum(&spaces)) / units;
+
+    Some(CwSpeed {
+        tone_hz,
+        wpm: 1200.0 / unit,
+        effective_wpm: 1200.0 / effective_unit,
+        dits: dit,
+        dahs: dah,
+        element_spaces: element,
+        character_spaces: Durations::of(character_spaces),
+        word_spaces: Durations::of(word_spaces),
+    })
+}
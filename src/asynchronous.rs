@@ -0,0 +1,308 @@
+use crate::{
+    HamShark,
+    data::audio::ClipId,
+    events::ErrorEvent,
+    session::{self, Session},
+    status::Status,
+    timesource::SharedTimeSource,
+};
+use log::{debug, warn};
+use serde::Serialize;
+use std::{fs, path::PathBuf, sync::Arc, thread, time::Duration};
+use thiserror::Error as ThisError;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+
+/// How many events a slow subscriber can fall behind by before it starts missing them
+const EVENT_CAPACITY: usize = 256;
+/// How many commands can be waiting for the capture thread
+const COMMAND_CAPACITY: usize = 16;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Session Error: {0}")]
+    Session(#[from] session::Error),
+    #[error("Unable to start capture thread: {0}")]
+    Spawn(#[source] std::io::Error),
+    #[error("The capture thread has stopped")]
+    Stopped,
+    #[error("Started recording but there's no clip")]
+    NoClip,
+}
+
+/// Something that happened while capturing
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum Event {
+    ClipStarted(ClipId),
+    ClipFinished(ClipId),
+    Error(ErrorEvent),
+    /// The peak level of a block of recorded samples, 0 to 1
+    Level(f32),
+}
+
+/// A clip in the session directory
+#[derive(Debug, Clone, Serialize)]
+pub struct ClipInfo {
+    pub id: ClipId,
+    pub seconds: f64,
+    pub recording: bool,
+}
+
+/// Every clip in the session directory, including any that have been forgotten. Clips that
+/// aren't in memory are measured from their WAV headers.
+fn list_clips(session: &Session) -> Vec<ClipInfo> {
+    let recording = session
+        .recording_clip()
+        .map(|clip| clip.read().id().clone());
+    let mut paths: Vec<PathBuf> = fs::read_dir(&session.path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "wav"))
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.sort();
+    paths
+        .iter()
+        .filter_map(|path| {
+            let id = ClipId::from_path_ref(path)?;
+            let seconds = match session.clips.get(&id) {
+                Some(clip) => {
+                    let clip = clip.read();
+                    clip.samples.len() as f64 / clip.sample_rate.0.max(1) as f64
+                }
+                None => {
+                    let reader = hound::WavReader::open(path).ok()?;
+                    reader.duration() as f64 / reader.spec().sample_rate.max(1) as f64
+                }
+            };
+            Some(ClipInfo {
+                recording: recording.as_ref() == Some(&id),
+                id,
+                seconds,
+            })
+        })
+        .collect()
+}
+
+enum Command {
+    Start(oneshot::Sender<Result<ClipId, Error>>),
+    Pause(oneshot::Sender<()>),
+    Resume(oneshot::Sender<()>),
+    Stop(oneshot::Sender<Result<(), Error>>),
+    NextClip(oneshot::Sender<Result<ClipId, Error>>),
+    Status(oneshot::Sender<Status>),
+    Clips(oneshot::Sender<Vec<ClipInfo>>),
+    Forget(ClipId, oneshot::Sender<()>),
+}
+
+/// Controls a [`HamShark`] from async code. The audio stream can't leave the thread it was
+/// made on, so the recorder lives on a thread of its own and this sends it commands. It
+/// stops recording and exits once every handle is dropped. Any number can run at once,
+/// each with its own session, thread and channels.
+#[derive(Clone)]
+pub struct AsyncHamShark {
+    name: Arc<str>,
+    /// The session's, so times here agree with the clips
+    time: SharedTimeSource,
+    commands: mpsc::Sender<Command>,
+    events: broadcast::Sender<Event>,
+}
+
+impl AsyncHamShark {
+    /// Start the capture thread, opening the session on it with `open`. The session must be
+    /// configured with an input device. `name` tells the engine apart in logs and thread
+    /// names.
+    pub fn spawn(
+        name: &str,
+        open: impl FnOnce() -> Result<Session, session::Error> + Send + 'static,
+    ) -> Result<Self, Error> {
+        let (commands, mut receiver) = mpsc::channel(COMMAND_CAPACITY);
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let (opened_sender, opened) = std::sync::mpsc::channel();
+
+        thread::Builder::new()
+            .name(format!("capture {}", name))
+            .spawn({
+                let events = events.clone();
+                let name = name.to_string();
+                move || {
+                    let mut hamshark = match open() {
+                        Ok(session) => HamShark::new(session),
+                        Err(error) => {
+                            let _ = opened_sender.send(Err(error));
+                            return;
+                        }
+                    };
+                    forward_events(&hamshark, events);
+                    let _ = opened_sender.send(Ok(hamshark.session().time_source().clone()));
+
+                    while let Some(command) = receiver.blocking_recv() {
+                        match command {
+                            Command::Start(reply) => {
+                                let started =
+                                    hamshark.start().map_err(Error::from).and_then(|()| {
+                                        hamshark
+                                            .session()
+                                            .recording_clip()
+                                            .map(|clip| clip.read().id().clone())
+                                            .ok_or(Error::NoClip)
+                                    });
+                                let _ = reply.send(started);
+                            }
+                            Command::Pause(reply) => {
+                                hamshark.pause();
+                                let _ = reply.send(());
+                            }
+                            Command::Resume(reply) => {
+                                hamshark.resume();
+                                let _ = reply.send(());
+                            }
+                            Command::Stop(reply) => {
+                                let _ = reply.send(hamshark.stop().map_err(Error::from));
+                            }
+                            Command::NextClip(reply) => {
+                                let _ = reply.send(hamshark.next_clip().map_err(Error::from));
+                            }
+                            Command::Status(reply) => {
+                                let _ = reply.send(hamshark.status());
+                            }
+                            Command::Clips(reply) => {
+                                let _ = reply.send(list_clips(hamshark.session()));
+                            }
+                            Command::Forget(id, reply) => {
+                                let session = hamshark.session_mut();
+                                let recording = session
+                                    .recording_clip()
+                                    .is_some_and(|clip| clip.read().id() == &id);
+                                if !recording {
+                                    session.clips.remove(&id);
+                                }
+                                let _ = reply.send(());
+                            }
+                        }
+                    }
+                    // Every handle's gone, so finish the clip and let go of the input
+                    if let Err(error) = hamshark.stop() {
+                        warn!("Unable to finish recording in {}: {}", name, error);
+                    }
+                    debug!("Capture engine {} finished", name);
+                }
+            })
+            .map_err(Error::Spawn)?;
+
+        match opened.recv() {
+            Ok(Ok(time)) => Ok(Self {
+                name: name.into(),
+                time,
+                commands,
+                events,
+            }),
+            Ok(Err(error)) => Err(error.into()),
+            Err(_) => Err(Error::Stopped),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Where the session's time comes from
+    pub fn time_source(&self) -> &SharedTimeSource {
+        &self.time
+    }
+
+    async fn send<T>(
+        &self,
+        command: impl FnOnce(oneshot::Sender<T>) -> Command,
+    ) -> Result<T, Error> {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .await
+            .map_err(|_| Error::Stopped)?;
+        response.await.map_err(|_| Error::Stopped)
+    }
+
+    /// Start recording a new clip
+    pub async fn start(&self) -> Result<ClipId, Error> {
+        self.send(Command::Start).await?
+    }
+
+    pub async fn pause(&self) -> Result<(), Error> {
+        self.send(Command::Pause).await
+    }
+
+    pub async fn resume(&self) -> Result<(), Error> {
+        self.send(Command::Resume).await
+    }
+
+    /// Finish the clip being recorded
+    pub async fn stop(&self) -> Result<(), Error> {
+        self.send(Command::Stop).await?
+    }
+
+    /// Finish the clip being recorded and carry on into a new one without stopping the input
+    pub async fn next_clip(&self) -> Result<ClipId, Error> {
+        self.send(Command::NextClip).await?
+    }
+
+    pub async fn status(&self) -> Result<Status, Error> {
+        self.send(Command::Status).await
+    }
+
+    /// Every clip in the session directory, oldest first
+    pub async fn clips(&self) -> Result<Vec<ClipInfo>, Error> {
+        self.send(Command::Clips).await
+    }
+
+    /// Let go of a finished clip's samples. It stays on disk. Does nothing to the clip being
+    /// recorded.
+    pub async fn forget(&self, id: ClipId) -> Result<(), Error> {
+        self.send(|reply| Command::Forget(id, reply)).await
+    }
+
+    /// Record a clip `length` long. Needs a tokio runtime with the timer enabled.
+    pub async fn record(&self, length: Duration) -> Result<ClipId, Error> {
+        let id = self.start().await?;
+        tokio::time::sleep(length).await;
+        self.stop().await?;
+        Ok(id)
+    }
+
+    /// Everything that happens from now on. A subscriber that falls too far behind misses
+    /// the events it didn't keep up with.
+    pub fn events(&self) -> impl Stream<Item = Event> + use<> {
+        BroadcastStream::new(self.events.subscribe()).filter_map(Result::ok)
+    }
+}
+
+fn forward_events(hamshark: &HamShark, events: broadcast::Sender<Event>) {
+    // Sending only fails when nobody is subscribed, which is fine
+    hamshark.on_clip_started({
+        let events = events.clone();
+        move |id| {
+            let _ = events.send(Event::ClipStarted(id.clone()));
+        }
+    });
+    hamshark.on_clip_finished({
+        let events = events.clone();
+        move |id| {
+            let _ = events.send(Event::ClipFinished(id.clone()));
+        }
+    });
+    hamshark.on_error({
+        let events = events.clone();
+        move |error| {
+            let _ = events.send(Event::Error(error.clone()));
+        }
+    });
+    hamshark.on_samples(move |samples, _| {
+        if events.receiver_count() > 0 {
+            let peak = samples.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+            let _ = events.send(Event::Level(peak));
+        }
+    });
+}
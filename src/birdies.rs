@@ -0,0 +1,154 @@
+use crate::{
+    carriers::{self, Carrier},
+    config::{BirdieSettings, CarrierSettings},
+    data::audio::{Clip, ClipId},
+    progress::Progress,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, io, path::Path};
+use thiserror::Error as ThisError;
+
+/// The birdie catalog, in the session directory
+pub const CATALOG_FILE: &str = "birdies.toml";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error reading the birdie catalog: {0}")]
+    Read(#[source] io::Error),
+    #[error("Error writing the birdie catalog: {0}")]
+    Write(#[source] io::Error),
+    #[error("Serialization error writing the birdie catalog: {0}")]
+    Serialization(#[source] toml::ser::Error),
+    #[error("Deserialization error reading the birdie catalog: {0}")]
+    Deserialization(#[source] toml::de::Error),
+}
+
+/// A steady narrow carrier that keeps turning up at the same audio frequency, from the
+/// rig, the computer or something nearby rather than from the air
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Birdie {
+    /// Audio frequency, averaged over where it's been seen
+    pub frequency: f64,
+    /// Clips it's been seen in
+    pub occurrences: usize,
+    /// Time it's been on, over all of them
+    pub seconds: f64,
+    /// The strongest it's been, in dBFS
+    pub level_db: f64,
+}
+
+impl Birdie {
+    /// Fold in another sighting, from another clip or from later in the same one
+    fn merge(&mut self, other: &Birdie, another_clip: bool) {
+        if another_clip {
+            let total = (self.occurrences + other.occurrences) as f64;
+            self.frequency = (self.frequency * self.occurrences as f64
+                + other.frequency * other.occurrences as f64)
+                / total;
+            self.occurrences += other.occurrences;
+        }
+        self.seconds += other.seconds;
+        self.level_db = self.level_db.max(other.level_db);
+    }
+}
+
+/// The birdies found in a session's clips, in order of frequency
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct BirdieCatalog {
+    #[serde(default)]
+    pub birdies: Vec<Birdie>,
+}
+
+impl BirdieCatalog {
+    /// Load a session's catalog. A session that's never been scanned has an empty one.
+    pub fn load(session_dir: &Path) -> Result<Self, Error> {
+        match fs::read_to_string(session_dir.join(CATALOG_FILE)) {
+            Ok(serialized) => toml::from_str(serialized.as_str()).map_err(Error::Deserialization),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(Error::Read(error)),
+        }
+    }
+
+    pub fn save(&self, session_dir: &Path) -> Result<(), Error> {
+        let serialized = toml::to_string(self).map_err(Error::Serialization)?;
+        fs::write(session_dir.join(CATALOG_FILE), serialized).map_err(Error::Write)
+    }
+
+    /// Scan every clip of a session for birdies
+    pub fn scan(
+        clips: &BTreeMap<ClipId, Clip>,
+        carriers: &CarrierSettings,
+        settings: &BirdieSettings,
+        progress: Progress,
+    ) -> Self {
+        let mut catalog = Self::default();
+        let tracked = carriers::track_clips(clips, carriers, progress);
+        for (clip, found) in clips.values().zip(tracked) {
+            let clip = clip.read();
+            catalog.add_clip(&found, clip.samples.len(), clip.sample_rate.0, settings);
+        }
+        catalog
+    }
+
+    /// Count the carriers of one clip that were on for most of it without wandering
+    pub fn add_clip(
+        &mut self,
+        carriers: &[Carrier],
+        length: usize,
+        sample_rate: u32,
+        settings: &BirdieSettings,
+    ) {
+        if length == 0 {
+            return;
+        }
+        // A birdie broken up by fading is pieced back together, and counts once per clip
+        let mut found: Vec<Birdie> = Vec::new();
+        for carrier in carriers {
+            let (low, high) = carrier
+                .track
+                .iter()
+                .fold((f64::MAX, f64::MIN), |(low, high), (_, hz)| {
+                    (low.min(*hz), high.max(*hz))
+                });
+            if high - low > settings.max_spread_hz {
+                continue;
+            }
+            let birdie = Birdie {
+                frequency: carrier.mean_frequency(),
+                occurrences: 1,
+                seconds: carrier.duration(sample_rate),
+                level_db: carrier.level_db,
+            };
+            match found
+                .iter_mut()
+                .find(|other| (other.frequency - birdie.frequency).abs() <= settings.tolerance_hz)
+            {
+                Some(other) => other.merge(&birdie, false),
+                None => found.push(birdie),
+            }
+        }
+        let clip_seconds = length as f64 / sample_rate as f64;
+        for birdie in found
+            .into_iter()
+            .filter(|birdie| birdie.seconds >= settings.min_coverage * clip_seconds)
+        {
+            match self
+                .birdies
+                .iter_mut()
+                .find(|other| (other.frequency - birdie.frequency).abs() <= settings.tolerance_hz)
+            {
+                Some(other) => other.merge(&birdie, true),
+                None => self.birdies.push(birdie),
+            }
+        }
+        self.birdies
+            .sort_by(|a, b| a.frequency.total_cmp(&b.frequency));
+    }
+
+    /// The birdies seen in at least `min_occurrences` clips
+    pub fn persistent(&self, min_occurrences: usize) -> impl Iterator<Item = &Birdie> {
+        self.birdies
+            .iter()
+            .filter(move |birdie| birdie.occurrences >= min_occurrences)
+    }
+}
@@ -0,0 +1,78 @@
+use crate::{analysis::PowerSpectrum, data::window::WindowFunction};
+
+/// The longest FFT to measure with. At 48 kHz that's 5.5 seconds, and bins 0.18 Hz apart.
+const MAX_FFT: usize = 1 << 18;
+/// Anything shorter can't tell a carrier from its neighbours
+const MIN_FFT: usize = 1 << 10;
+
+/// A station transmitting on a frequency it holds to a time standard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    pub name: &'static str,
+    /// Carrier frequency in Hz
+    pub frequency: u64,
+}
+
+impl Reference {
+    const fn new(name: &'static str, frequency: u64) -> Self {
+        Self { name, frequency }
+    }
+}
+
+/// Standard frequency and time stations, for measuring against
+pub const REFERENCES: [Reference; 13] = [
+    Reference::new("DCF77 77.5 kHz", 77_500),
+    Reference::new("WWV 2.5 MHz", 2_500_000),
+    Reference::new("CHU 3.33 MHz", 3_330_000),
+    Reference::new("RWM 4.996 MHz", 4_996_000),
+    Reference::new("WWV 5 MHz", 5_000_000),
+    Reference::new("CHU 7.85 MHz", 7_850_000),
+    Reference::new("RWM 9.996 MHz", 9_996_000),
+    Reference::new("WWV 10 MHz", 10_000_000),
+    Reference::new("CHU 14.67 MHz", 14_670_000),
+    Reference::new("RWM 14.996 MHz", 14_996_000),
+    Reference::new("WWV 15 MHz", 15_000_000),
+    Reference::new("WWV 20 MHz", 20_000_000),
+    Reference::new("WWV 25 MHz", 25_000_000),
+];
+
+/// A frequency as it should have been, and as it was read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    pub expected_hz: f64,
+    pub measured_hz: f64,
+}
+
+impl Measurement {
+    /// Parts per million to correct frequencies by, so the measured one reads as expected
+    pub fn correction_ppm(&self) -> f64 {
+        (self.expected_hz / self.measured_hz - 1.0) * 1e6
+    }
+}
+
+/// A frequency corrected by parts per million
+pub fn correct(hz: f64, ppm: f64) -> f64 {
+    hz * (1.0 + ppm / 1e6)
+}
+
+/// Find the strongest tone within `within_hz` of `around_hz`, to a fraction of an FFT bin.
+/// As long an FFT as the samples allow is averaged over them, so a weak carrier stands out
+/// of the noise. None if there aren't enough samples, or nothing peaks in the range.
+pub fn measure_tone(
+    samples: &[f32],
+    sample_rate: u32,
+    around_hz: f64,
+    within_hz: f64,
+) -> Option<f64> {
+    if samples.len() < MIN_FFT || sample_rate == 0 {
+        return None;
+    }
+    let size = if samples.len() >= MAX_FFT {
+        MAX_FFT
+    } else {
+        // The biggest power of two that fits
+        1 << samples.len().ilog2()
+    };
+    PowerSpectrum::new(samples, sample_rate, size, WindowFunction::Hann)?
+        .peak(around_hz - within_hz..around_hz + within_hz)
+}
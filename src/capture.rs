@@ -0,0 +1,285 @@
+use crate::ring::{self, RingReader, RingWriter};
+use log::{info, warn};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use thiserror::Error as ThisError;
+
+/// What a capture file starts with
+const MAGIC: &[u8; 8] = b"HSBLOCKS";
+/// How many seconds of samples can wait to be written before blocks are dropped
+const BUFFER_SECONDS: usize = 10;
+/// How many blocks can wait to be written. Devices deliver a few hundred a second.
+const MAX_BLOCKS: usize = 8192;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error with input capture: {0}")]
+    IO(#[from] io::Error),
+    #[error("{0:?} isn't an input capture")]
+    NotACapture(PathBuf),
+    #[error("Error starting capture writer: {0}")]
+    SpawnWriter(#[source] io::Error),
+}
+
+/// Where the blocks delivered while recording a clip are captured to, next to it
+pub fn capture_path(wav: &Path) -> PathBuf {
+    wav.with_extension("blocks")
+}
+
+/// A block exactly as the input device delivered it
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedBlock {
+    /// When its callback came, after the first one
+    pub at: Duration,
+    /// How long before the callback the device says it was captured
+    pub latency: Duration,
+    /// Interleaved, all the device's channels
+    pub samples: Vec<f32>,
+}
+
+/// The blocks an input device delivered while recording, for playing back through a
+/// [`crate::virtualinput::VirtualInput`] to reproduce what happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub blocks: Vec<CapturedBlock>,
+}
+
+/// Takes `N` bytes off the front
+fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+    let (taken, rest) = bytes.split_first_chunk::<N>()?;
+    *bytes = rest;
+    Some(*taken)
+}
+
+impl Capture {
+    /// Read a capture file. One that was cut off, like by a crash, reads up to the last whole
+    /// block.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let contents = fs::read(path)?;
+        let not_a_capture = || Error::NotACapture(path.to_path_buf());
+        let mut bytes = contents
+            .strip_prefix(MAGIC.as_slice())
+            .ok_or_else(not_a_capture)?;
+        let sample_rate = u32::from_le_bytes(take(&mut bytes).ok_or_else(not_a_capture)?);
+        let channels = u16::from_le_bytes(take(&mut bytes).ok_or_else(not_a_capture)?);
+        let mut blocks = Vec::new();
+        while let Some(block) = Self::read_block(&mut bytes) {
+            blocks.push(block);
+        }
+        if !bytes.is_empty() {
+            warn!("{:?} was cut off partway through a block", path.as_os_str());
+        }
+        Ok(Self {
+            sample_rate,
+            channels,
+            blocks,
+        })
+    }
+
+    fn read_block(bytes: &mut &[u8]) -> Option<CapturedBlock> {
+        let mut rest = *bytes;
+        let at = u64::from_le_bytes(take(&mut rest)?);
+        let latency = u64::from_le_bytes(take(&mut rest)?);
+        let len = u32::from_le_bytes(take(&mut rest)?) as usize;
+        let (data, rest) = rest.split_at_checked(len * 4)?;
+        *bytes = rest;
+        Some(CapturedBlock {
+            at: Duration::from_nanos(at),
+            latency: Duration::from_nanos(latency),
+            samples: data
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+        })
+    }
+}
+
+/// What the writer needs to know of a block, along with its samples in the ring
+struct Header {
+    /// Samples in the ring ahead of this block, from blocks whose headers didn't fit
+    skip: usize,
+    len: usize,
+    at: Duration,
+    latency: Duration,
+}
+
+/// The audio thread's end of a capture. Hands each block to the capture's writer without
+/// allocating or locking, and drops blocks rather than wait if the writer falls behind.
+pub(crate) struct BlockTap {
+    samples: RingWriter,
+    headers: SyncSender<Header>,
+    /// Samples pushed for blocks whose headers didn't fit, for the writer to skip
+    orphaned: usize,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl BlockTap {
+    /// `at` is how long after the first callback this one came
+    pub(crate) fn push(&mut self, data: &[f32], at: Duration, latency: Duration) {
+        if !self.samples.push_iter(data.len(), data.iter().copied()) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let header = Header {
+            skip: self.orphaned,
+            len: data.len(),
+            at,
+            latency,
+        };
+        match self.headers.try_send(header) {
+            Ok(()) => self.orphaned = 0,
+            Err(_) => {
+                self.orphaned += data.len();
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Writes out what a [`BlockTap`] hands it, until the tap is dropped and it's all written
+pub(crate) struct CaptureWriter {
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CaptureWriter {
+    /// Start capturing into a new file at `path`
+    pub(crate) fn start(
+        path: &Path,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<(BlockTap, Self), Error> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&channels.to_le_bytes())?;
+
+        let capacity = BUFFER_SECONDS * sample_rate as usize * channels.max(1) as usize;
+        let (samples, reader) = ring::sample_ring(capacity);
+        let (headers, receiver) = mpsc::sync_channel(MAX_BLOCKS);
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let thread = thread::Builder::new()
+            .name("capture writer".to_string())
+            .spawn({
+                let path = path.to_path_buf();
+                let dropped = dropped.clone();
+                move || {
+                    if let Err(error) = Self::write(&mut file, &receiver, &reader) {
+                        warn!("Error capturing input to {:?}: {}", path.as_os_str(), error);
+                    }
+                    let dropped = dropped.load(Ordering::Relaxed);
+                    if dropped > 0 {
+                        warn!("Dropped {} blocks capturing input, it fell behind", dropped);
+                    }
+                    info!("Captured input to {:?}", path.as_os_str());
+                }
+            })
+            .map_err(Error::SpawnWriter)?;
+
+        let tap = BlockTap {
+            samples,
+            headers,
+            orphaned: 0,
+            dropped,
+        };
+        Ok((
+            tap,
+            Self {
+                thread: Some(thread),
+            },
+        ))
+    }
+
+    fn write(
+        file: &mut BufWriter<File>,
+        headers: &Receiver<Header>,
+        samples: &RingReader,
+    ) -> Result<(), io::Error> {
+        let mut block = Vec::new();
+        // A header only goes once its samples are in the ring
+        for header in headers {
+            samples.skip(header.skip);
+            block.clear();
+            samples.pop_into(&mut block, header.len);
+            file.write_all(&(header.at.as_nanos() as u64).to_le_bytes())?;
+            file.write_all(&(header.latency.as_nanos() as u64).to_le_bytes())?;
+            file.write_all(&(block.len() as u32).to_le_bytes())?;
+            for sample in &block {
+                file.write_all(&sample.to_le_bytes())?;
+            }
+        }
+        file.flush()
+    }
+}
+
+impl Drop for CaptureWriter {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            warn!("The capture writer thread panicked");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtualinput::VirtualInput;
+    use parking_lot::Mutex;
+
+    #[test]
+    fn captured_blocks_play_back_as_they_came() {
+        let path = std::env::temp_dir().join(format!("hamshark-{}.blocks", std::process::id()));
+        let blocks: Vec<CapturedBlock> = (0..50)
+            .map(|index| CapturedBlock {
+                // Unevenly, like a real device
+                at: Duration::from_micros(index * 2600 + (index % 3) * 500),
+                latency: Duration::from_micros(index % 4 * 100),
+                samples: (0..2 * (100 + index as usize % 7))
+                    .map(|i| (i as f32 + index as f32) / 1000.0)
+                    .collect(),
+            })
+            .collect();
+        let (mut tap, writer) = CaptureWriter::start(&path, 48000, 2).unwrap();
+        for block in &blocks {
+            tap.push(&block.samples, block.at, block.latency);
+        }
+        drop(tap);
+        drop(writer);
+
+        let capture = Capture::load(&path).unwrap();
+        assert_eq!((capture.sample_rate, capture.channels), (48000, 2));
+        assert_eq!(capture.blocks, blocks);
+
+        let input = VirtualInput::from_capture("Replay", &capture);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let _stream = input.play(
+            {
+                let received = received.clone();
+                move |data, latency| received.lock().push((data.to_vec(), latency))
+            },
+            |_| {},
+        );
+        input.advance(blocks[9].at);
+        assert_eq!(received.lock().len(), 10);
+        input.play_to_end();
+        let received = received.lock();
+        assert_eq!(received.len(), blocks.len());
+        for ((samples, latency), block) in received.iter().zip(&blocks) {
+            assert_eq!((samples, *latency), (&block.samples, block.latency));
+        }
+        fs::remove_file(&path).ok();
+    }
+}
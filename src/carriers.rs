@@ -0,0 +1,429 @@
+use crate::{
+    analysis::{self, PowerSpectrum},
+    config::CarrierSettings,
+    data::{
+        audio::{Clip, ClipId},
+        window::WindowFunction,
+    },
+    progress::{self, Progress, Tally},
+};
+use chrono::SecondsFormat;
+use rayon::prelude::*;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::Range,
+    path::Path,
+};
+
+/// The carrier log, in the session directory
+pub const LOG_FILE: &str = "carriers.csv";
+/// Spectra are taken with about this many Hz between bins
+const BIN_HZ: u32 = 10;
+/// FFTs averaged into each spectrum, to steady the noise floor
+const FRAME_BLOCKS: usize = 2;
+/// Bins either side a peak has to be the highest of. Blackman-Harris spreads a tone over
+/// about this many, and keeps its sidelobes far enough down not to be taken for carriers.
+const PEAK_BINS: usize = 4;
+/// The most peaks taken from one spectrum, loudest first
+const MAX_PEAKS: usize = 32;
+/// Samples read from a clip at a time, so recording isn't held up for long
+const CHUNK: usize = 1 << 16;
+/// How far above the noise a followed carrier has to be to count as seen, in dB
+const FOLLOW_SNR_DB: f64 = 10.0;
+/// The FFT size the noise is measured with when following a carrier
+const FOLLOW_NOISE_FFT: usize = 1024;
+
+/// A peak in one spectrum
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Peak {
+    /// Audio frequency, to a fraction of a bin
+    pub frequency: f64,
+    /// Power around the peak, in dBFS
+    pub level_db: f64,
+}
+
+/// The peaks standing `threshold_db` or more above the noise floor, loudest first
+pub fn find_peaks(spectrum: &PowerSpectrum, threshold_db: f64) -> Vec<Peak> {
+    let power = &spectrum.power;
+    if power.len() < 2 * PEAK_BINS + 2 {
+        return Vec::new();
+    }
+    let noise = (spectrum.noise_density(None) * spectrum.bin_hz).max(1e-16);
+    let limit = noise * 10f64.powf(threshold_db / 10.0);
+    let mut peaks: Vec<Peak> = (PEAK_BINS + 1..power.len() - PEAK_BINS)
+        .filter(|&bin| {
+            let around = bin - PEAK_BINS..=bin + PEAK_BINS;
+            // Strictly above the bins to the left, so a flat top only counts once
+            power[bin] > limit
+                && power[bin - PEAK_BINS..bin].iter().all(|p| *p < power[bin])
+                && power[around].iter().all(|p| *p <= power[bin])
+        })
+        .map(|bin| {
+            let centre = bin as f64 * spectrum.bin_hz;
+            let level: f64 = power[bin - PEAK_BINS..=bin + PEAK_BINS].iter().sum();
+            Peak {
+                frequency: spectrum.peak(centre..centre).unwrap_or(centre),
+                level_db: 10.0 * level.log10(),
+            }
+        })
+        .collect();
+    peaks.sort_by(|a, b| b.level_db.total_cmp(&a.level_db));
+    peaks.truncate(MAX_PEAKS);
+    peaks
+}
+
+/// A steady signal followed through the spectra it shows up in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Carrier {
+    /// Where it was last seen, in Hz of audio
+    pub frequency: f64,
+    /// The strongest it's been, in dBFS
+    pub level_db: f64,
+    /// Where it was first and last seen, in samples
+    pub start: usize,
+    pub end: usize,
+    /// Where it was in each spectrum it was seen in, as (sample, Hz)
+    pub track: Vec<(usize, f64)>,
+}
+
+impl Carrier {
+    /// Where it was on average, in Hz of audio
+    pub fn mean_frequency(&self) -> f64 {
+        self.track.iter().map(|(_, hz)| hz).sum::<f64>() / self.track.len().max(1) as f64
+    }
+
+    pub fn duration(&self, sample_rate: u32) -> f64 {
+        (self.end - self.start) as f64 / sample_rate as f64
+    }
+}
+
+/// Finds carriers in audio and follows them as they drift, come and go. Feed it a clip's
+/// samples in order, in pieces of any size.
+pub struct CarrierTracker {
+    settings: CarrierSettings,
+    sample_rate: u32,
+    size: usize,
+    /// Samples waiting for a spectrum's worth
+    pending: Vec<f32>,
+    /// Samples taken into spectra so far
+    position: usize,
+    active: Vec<Carrier>,
+    finished: Vec<Carrier>,
+}
+
+impl CarrierTracker {
+    pub fn new(settings: &CarrierSettings, sample_rate: u32) -> Self {
+        let size = (sample_rate / BIN_HZ).max(64).next_power_of_two() as usize;
+        Self {
+            settings: settings.clone(),
+            sample_rate,
+            size,
+            pending: Vec::with_capacity(size * FRAME_BLOCKS),
+            position: 0,
+            active: Vec::new(),
+            finished: Vec::new(),
+        }
+    }
+
+    fn samples_in(&self, ms: u64) -> usize {
+        (ms * self.sample_rate as u64 / 1000) as usize
+    }
+
+    pub fn push(&mut self, mut samples: &[f32]) {
+        let frame_len = self.size * FRAME_BLOCKS;
+        while !samples.is_empty() {
+            let take = (frame_len - self.pending.len()).min(samples.len());
+            self.pending.extend_from_slice(&samples[..take]);
+            samples = &samples[take..];
+            if self.pending.len() < frame_len {
+                break;
+            }
+            let spectrum = PowerSpectrum::new(
+                &self.pending,
+                self.sample_rate,
+                self.size,
+                WindowFunction::BlackmanHarris,
+            );
+            self.pending.clear();
+            if let Some(spectrum) = spectrum {
+                let peaks = find_peaks(&spectrum, self.settings.threshold_db);
+                self.follow(&peaks, self.position, self.position + frame_len);
+            }
+            self.position += frame_len;
+        }
+    }
+
+    /// Carry on the carriers the peaks of a spectrum are near, start new ones for the rest
+    /// and retire the ones that have been gone too long
+    fn follow(&mut self, peaks: &[Peak], start: usize, end: usize) {
+        let drift = self.settings.max_drift_hz;
+        let mut unclaimed: Vec<Option<&Peak>> = peaks.iter().map(Some).collect();
+        self.active
+            .sort_by(|a, b| b.level_db.total_cmp(&a.level_db));
+        for carrier in &mut self.active {
+            let nearest = unclaimed
+                .iter_mut()
+                .filter(|peak| {
+                    peak.is_some_and(|peak| (peak.frequency - carrier.frequency).abs() <= drift)
+                })
+                .min_by(|a, b| {
+                    let distance = |peak: &Option<&Peak>| {
+                        peak.map_or(f64::MAX, |peak| (peak.frequency - carrier.frequency).abs())
+                    };
+                    distance(a).total_cmp(&distance(b))
+                });
+            if let Some(peak) = nearest.and_then(Option::take) {
+                carrier.frequency = peak.frequency;
+                carrier.level_db = carrier.level_db.max(peak.level_db);
+                carrier.end = end;
+                carrier.track.push(((start + end) / 2, peak.frequency));
+            }
+        }
+        for peak in unclaimed.into_iter().flatten() {
+            self.active.push(Carrier {
+                frequency: peak.frequency,
+                level_db: peak.level_db,
+                start,
+                end,
+                track: vec![((start + end) / 2, peak.frequency)],
+            });
+        }
+
+        let hold = self.samples_in(self.settings.hold_ms);
+        let (gone, active) = self
+            .active
+            .drain(..)
+            .partition(|carrier| carrier.end + hold < end);
+        self.active = active;
+        self.retire(gone);
+    }
+
+    /// Keep the carriers that lasted long enough to count
+    fn retire(&mut self, carriers: Vec<Carrier>) {
+        let shortest = self.samples_in(self.settings.min_duration_ms);
+        self.finished.extend(
+            carriers
+                .into_iter()
+                .filter(|carrier| carrier.end - carrier.start >= shortest),
+        );
+    }
+
+    /// The carriers on now that have lasted long enough to count
+    pub fn active(&self) -> impl Iterator<Item = &Carrier> {
+        let shortest = self.samples_in(self.settings.min_duration_ms);
+        self.active
+            .iter()
+            .filter(move |carrier| carrier.end - carrier.start >= shortest)
+    }
+
+    /// Every carrier found, in the order they started
+    pub fn finish(mut self) -> Vec<Carrier> {
+        let active = std::mem::take(&mut self.active);
+        self.retire(active);
+        self.finished.sort_by_key(|carrier| carrier.start);
+        self.finished
+    }
+}
+
+/// Track the carriers through the whole of a clip, a piece at a time
+pub fn track_clip(clip: &Clip, settings: &CarrierSettings, progress: Progress) -> Vec<Carrier> {
+    let mut tracker = CarrierTracker::new(settings, clip.read().sample_rate.0);
+    let mut position = 0;
+    loop {
+        let clip = clip.read();
+        let len = clip.samples.len();
+        let end = (position + CHUNK).min(len);
+        if position >= end {
+            break;
+        }
+        tracker.push(&clip.samples.range(position..end));
+        position = end;
+        progress(position as f32 / len as f32);
+    }
+    tracker.finish()
+}
+
+/// Track the carriers through each of `clips`, several clips at once
+pub fn track_clips(
+    clips: &BTreeMap<ClipId, Clip>,
+    settings: &CarrierSettings,
+    progress: Progress,
+) -> Vec<Vec<Carrier>> {
+    let clips: Vec<(&Clip, usize)> = clips
+        .values()
+        .map(|clip| (clip, clip.read().samples.len()))
+        .collect();
+    let tally = Tally::new(clips.iter().map(|(_, length)| length).sum(), progress);
+    clips
+        .par_iter()
+        .map(|&(clip, length)| {
+            let carriers = track_clip(clip, settings, &progress::ignore);
+            tally.add(length);
+            carriers
+        })
+        .collect()
+}
+
+/// Track the carriers through every clip of a session and write when and where each was on
+/// to a CSV file. Returns how many there were.
+pub fn write_log(
+    clips: &BTreeMap<ClipId, Clip>,
+    settings: &CarrierSettings,
+    path: &Path,
+    progress: Progress,
+) -> Result<usize, io::Error> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(
+        out,
+        "clip,start_s,end_s,start_utc,duration_s,audio_hz,rf_hz,level_dbfs"
+    )?;
+    let mut count = 0;
+    for ((id, clip), carriers) in clips.iter().zip(track_clips(clips, settings, progress)) {
+        let clip = clip.read();
+        let sample_rate = clip.sample_rate.0;
+        let seconds = |position: usize| position as f64 / sample_rate as f64;
+        for carrier in &carriers {
+            let frequency = carrier.mean_frequency();
+            let started = clip.metadata.started.map(|started| {
+                let offset = chrono::Duration::microseconds((seconds(carrier.start) * 1e6) as i64);
+                (started + offset).to_rfc3339_opts(SecondsFormat::Millis, true)
+            });
+            let rf = clip
+                .metadata
+                .dial_frequency_at(carrier.start)
+                .map(|dial| format!("{:.1}", clip.metadata.corrected(dial as f64 + frequency)));
+            writeln!(
+                out,
+                "{},{:.3},{:.3},{},{:.3},{:.1},{},{:.1}",
+                id,
+                seconds(carrier.start),
+                seconds(carrier.end),
+                started.unwrap_or_default(),
+                carrier.duration(sample_rate),
+                frequency,
+                rf.unwrap_or_default(),
+                carrier.level_db
+            )?;
+        }
+        count += carriers.len();
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+/// Where a followed carrier was at one moment
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriftPoint {
+    /// The middle of the stretch it was measured over, in samples
+    pub position: usize,
+    /// Audio frequency, to a small fraction of a Hz
+    pub frequency: f64,
+    /// In dBFS
+    pub level_db: f64,
+}
+
+/// One step of following a carrier: where it is in `samples`, looking no further than
+/// `search_hz` from where it was last
+fn follow_step(samples: &[f32], sample_rate: u32, last: f64, search_hz: f64) -> Option<(f64, f64)> {
+    let band = (last - search_hz).max(0.0)..last + search_hz;
+    let estimate = analysis::estimate_frequency(samples, sample_rate, Some(band))?;
+    let size = FOLLOW_NOISE_FFT.min(1 << samples.len().max(1).ilog2());
+    let spectrum = PowerSpectrum::new(samples, sample_rate, size, WindowFunction::Hann)?;
+    let noise_db = 10.0 * (spectrum.noise_density(None) * spectrum.bin_hz).log10();
+    (estimate.level_db - noise_db >= FOLLOW_SNR_DB)
+        .then_some((estimate.frequency, estimate.level_db))
+}
+
+/// Follow the strongest carrier in `from` through the whole of a clip, forwards and
+/// backwards from there, measuring where it is every `step_seconds`. Stretches it can't be
+/// heard in are left out, and it's picked up again near where it was last heard.
+pub fn follow_carrier(
+    clip: &Clip,
+    from: Range<usize>,
+    search_hz: f64,
+    step_seconds: f64,
+) -> Vec<DriftPoint> {
+    let (sample_rate, length) = {
+        let clip = clip.read();
+        (clip.sample_rate.0, clip.samples.len())
+    };
+    let step = ((step_seconds * sample_rate as f64) as usize).max(256);
+    let start = {
+        let clip = clip.read();
+        let end = from.end.min(length);
+        analysis::estimate_frequency(&clip.samples.range(from.start..end), sample_rate, None)
+    };
+    let Some(start) = start else {
+        return Vec::new();
+    };
+
+    let measure = |position: usize, last: &mut f64| {
+        let clip = clip.read();
+        let samples = clip.samples.range(position..position + step);
+        if samples.len() < step {
+            return None;
+        }
+        let (frequency, level_db) = follow_step(&samples, sample_rate, *last, search_hz)?;
+        *last = frequency;
+        Some(DriftPoint {
+            position: position + step / 2,
+            frequency,
+            level_db,
+        })
+    };
+    // Steps line up with the start of the selection, so the first step is the selection,
+    // and go outwards from there both ways, each following on from the one before
+    let first = from.start.min(length);
+    let mut last = start.frequency;
+    let mut points: Vec<DriftPoint> = (1..=first / step)
+        .filter_map(|n| measure(first - n * step, &mut last))
+        .collect();
+    points.reverse();
+    let mut last = start.frequency;
+    points
+        .extend((0..(length - first) / step).filter_map(|n| measure(first + n * step, &mut last)));
+    points
+}
+
+/// How fast a carrier drifted, by a straight line fitted through where it was, in Hz per
+/// second. None with fewer than two points.
+pub fn drift_rate(points: &[DriftPoint], sample_rate: u32) -> Option<f64> {
+    analysis::slope(
+        points
+            .iter()
+            .map(|point| (point.position as f64 / sample_rate as f64, point.frequency)),
+    )
+}
+
+/// Write where a followed carrier was, and when, to a CSV file
+pub fn write_drift(clip: &Clip, points: &[DriftPoint], path: &Path) -> Result<(), io::Error> {
+    let clip = clip.read();
+    let sample_rate = clip.sample_rate.0;
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "time_s,utc,audio_hz,rf_hz,level_dbfs")?;
+    for point in points {
+        let seconds = point.position as f64 / sample_rate as f64;
+        let utc = clip.metadata.started.map(|started| {
+            let offset = chrono::Duration::microseconds((seconds * 1e6) as i64);
+            (started + offset).to_rfc3339_opts(SecondsFormat::Millis, true)
+        });
+        let rf = clip.metadata.dial_frequency_at(point.position).map(|dial| {
+            format!(
+                "{:.2}",
+                clip.metadata.corrected(dial as f64 + point.frequency)
+            )
+        });
+        writeln!(
+            out,
+            "{:.3},{},{:.3},{},{:.1}",
+            seconds,
+            utc.unwrap_or_default(),
+            point.frequency,
+            rf.unwrap_or_default(),
+            point.level_db
+        )?;
+    }
+    out.flush()
+}
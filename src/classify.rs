@@ -0,0 +1,335 @@
+use crate::{analysis::PowerSpectrum, data::window::WindowFunction, fft::Transform};
+use rustfft::num_complex::Complex;
+use serde::{Deserialize, Serialize};
+use std::{fmt, ops::Range};
+
+/// The FFT size the overall spectrum is taken with
+const SPECTRUM_FFT: usize = 16384;
+/// Envelope frames per second, about. Fast enough to see the symbols of 63 baud PSK.
+const ENVELOPE_RATE: u32 = 100;
+/// A bin counts as signal once it's this many times the noise floor
+const SIGNAL_OVER_NOISE: f64 = 4.0;
+/// Where CTCSS tones live
+const SUBAUDIBLE: Range<f64> = 67.0..255.0;
+/// Symbol rates looked for in the envelope
+const SYMBOL_RATES: Range<f64> = 3.0..90.0;
+
+/// The kinds of signal the classifier knows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    Cw,
+    SsbVoice,
+    Rtty,
+    Psk,
+    Ft8,
+    Fm,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 6] = [
+        Mode::Cw,
+        Mode::SsbVoice,
+        Mode::Rtty,
+        Mode::Psk,
+        Mode::Ft8,
+        Mode::Fm,
+    ];
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Cw => "CW",
+            Mode::SsbVoice => "SSB voice",
+            Mode::Rtty => "RTTY",
+            Mode::Psk => "PSK",
+            Mode::Ft8 => "FT8",
+            Mode::Fm => "FM",
+        })
+    }
+}
+
+/// What the classifier measured of a signal
+#[derive(Debug, Clone, PartialEq)]
+pub struct Features {
+    /// The audio frequencies the signal stands above the noise between
+    pub occupied: Range<f64>,
+    /// The strongest separate peaks in the occupied band, strongest first
+    pub tones: Vec<f64>,
+    /// How much the envelope varies while the signal's on, standard deviation over mean
+    pub envelope_variation: f64,
+    /// The share of the time the signal's off, like between the elements of CW
+    pub keyed: f64,
+    /// The strongest rate the signal changes at, in baud
+    pub symbol_rate: Option<f64>,
+    /// A CTCSS tone, which only FM carries
+    pub subaudible_tone: Option<f64>,
+}
+
+impl Features {
+    pub fn bandwidth(&self) -> f64 {
+        self.occupied.end - self.occupied.start
+    }
+}
+
+/// How likely each mode is, most likely first, with what it was judged on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Classification {
+    /// Modes with their share of the likelihood, adding up to one
+    pub modes: Vec<(Mode, f64)>,
+    pub features: Features,
+}
+
+impl Classification {
+    pub fn best(&self) -> Mode {
+        self.modes[0].0
+    }
+}
+
+/// Close to one near `target`, falling away over about `tolerance`
+fn near(value: f64, target: f64, tolerance: f64) -> f64 {
+    (-((value - target) / tolerance).powi(2)).exp()
+}
+
+/// Close to one well under `limit` and zero well over it
+fn below(value: f64, limit: f64, softness: f64) -> f64 {
+    1.0 / (1.0 + ((value - limit) / softness).exp())
+}
+
+fn above(value: f64, limit: f64, softness: f64) -> f64 {
+    1.0 - below(value, limit, softness)
+}
+
+/// Guess the mode of the signal in some samples. None if there aren't enough samples, or
+/// nothing stands above the noise.
+pub fn classify(samples: &[f32], sample_rate: u32) -> Option<Classification> {
+    let features = features(samples, sample_rate)?;
+    let bandwidth = features.bandwidth();
+    let symbol_near = |rates: &[f64], tolerance: f64| {
+        features.symbol_rate.map_or(0.0, |rate| {
+            rates
+                .iter()
+                .map(|target| near(rate, *target, tolerance))
+                .fold(0.0, f64::max)
+        })
+    };
+    let shift = match features.tones.as_slice() {
+        [first, second, ..] => (first - second).abs(),
+        _ => 0.0,
+    };
+    let steady = below(features.envelope_variation, 0.25, 0.05);
+    let unkeyed = below(features.keyed, 0.1, 0.03);
+
+    let score = |mode: Mode| match mode {
+        Mode::Cw => below(bandwidth, 150.0, 30.0) * above(features.keyed, 0.25, 0.05),
+        // Phase reversals dip the envelope to nothing, so PSK looks a little keyed
+        Mode::Psk => {
+            below(bandwidth, 200.0, 30.0)
+                * below(features.keyed, 0.3, 0.05)
+                * (0.2 + 0.8 * symbol_near(&[31.25, 62.5], 4.0))
+                * (0.5 + 0.5 * above(features.envelope_variation, 0.1, 0.03))
+        }
+        Mode::Ft8 => near(bandwidth, 50.0, 25.0) * steady * unkeyed,
+        Mode::Rtty => {
+            [170.0, 200.0, 425.0, 850.0]
+                .iter()
+                .map(|target| near(shift, *target, 25.0))
+                .fold(0.0, f64::max)
+                * steady
+                * (0.5 + 0.5 * symbol_near(&[45.45, 50.0, 75.0], 5.0))
+        }
+        Mode::SsbVoice => {
+            above(bandwidth, 800.0, 150.0)
+                * below(bandwidth, 3600.0, 200.0)
+                * above(features.envelope_variation, 0.4, 0.1)
+        }
+        Mode::Fm => match features.subaudible_tone {
+            Some(_) => 0.9,
+            None => 0.7 * above(bandwidth, 4500.0, 300.0),
+        },
+    };
+    let mut modes: Vec<(Mode, f64)> = Mode::ALL.iter().map(|mode| (*mode, score(*mode))).collect();
+    let total: f64 = modes.iter().map(|(_, score)| score).sum();
+    if total <= 0.0 {
+        return None;
+    }
+    for (_, score) in &mut modes {
+        *score /= total;
+    }
+    modes.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    Some(Classification { modes, features })
+}
+
+/// Measure the things modes are told apart by
+pub fn features(samples: &[f32], sample_rate: u32) -> Option<Features> {
+    let size = SPECTRUM_FFT.min(1 << samples.len().max(1).ilog2());
+    let spectrum = PowerSpectrum::new(samples, sample_rate, size, WindowFunction::Hann)?;
+    let noise = spectrum.noise_density(None) * spectrum.bin_hz;
+
+    // The occupied band is where 99% of the power above the noise is, leaving out CTCSS
+    let lowest = spectrum.bins(SUBAUDIBLE.end..SUBAUDIBLE.end).start;
+    let excess: Vec<f64> = spectrum
+        .power
+        .iter()
+        .enumerate()
+        .map(|(bin, power)| {
+            if bin >= lowest && *power > noise * SIGNAL_OVER_NOISE {
+                power - noise
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    let total: f64 = excess.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let mut running = 0.0;
+    let cumulative: Vec<f64> = excess
+        .iter()
+        .map(|bin| {
+            running += bin;
+            running / total
+        })
+        .collect();
+    let low = cumulative.partition_point(|share| *share < 0.005);
+    let high = cumulative.partition_point(|share| *share < 0.995);
+    let occupied = (low as f64 - 0.5) * spectrum.bin_hz..(high as f64 + 0.5) * spectrum.bin_hz;
+
+    // Separate peaks, each the highest within a quarter of the band or 20 Hz of it
+    let spacing = ((occupied.end - occupied.start) / 4.0).max(20.0) / spectrum.bin_hz;
+    let mut peaks: Vec<usize> = (low.max(1)..high.min(spectrum.power.len() - 1))
+        .filter(|&bin| {
+            excess[bin] > 0.0
+                && spectrum.power[bin] >= spectrum.power[bin - 1]
+                && spectrum.power[bin] >= spectrum.power[bin + 1]
+        })
+        .collect();
+    peaks.sort_by(|a, b| spectrum.power[*b].total_cmp(&spectrum.power[*a]));
+    let mut tones: Vec<usize> = Vec::new();
+    for peak in peaks {
+        if tones
+            .iter()
+            .all(|tone| (*tone as f64 - peak as f64).abs() > spacing)
+        {
+            tones.push(peak);
+        }
+        if tones.len() == 4 {
+            break;
+        }
+    }
+
+    // A CTCSS tone holds its frequency, where a voice's pitch wanders and smears its line
+    let subaudible_tone = spectrum.peak(SUBAUDIBLE).filter(|&hz| {
+        let bin = spectrum.bins(hz..hz).start;
+        let line = spectrum.power_in(hz - 1.5 * spectrum.bin_hz..hz + 1.5 * spectrum.bin_hz);
+        spectrum.power[bin] > noise * 100.0 && line > 0.8 * spectrum.power_in(hz - 15.0..hz + 15.0)
+    });
+
+    let tone_hz: Vec<f64> = tones
+        .iter()
+        .map(|bin| *bin as f64 * spectrum.bin_hz)
+        .collect();
+    let (envelope_variation, keyed, symbol_rate) =
+        envelope(samples, sample_rate, &occupied, &tone_hz);
+    Some(Features {
+        occupied,
+        tones: tone_hz,
+        envelope_variation,
+        keyed,
+        symbol_rate,
+        subaudible_tone,
+    })
+}
+
+/// How the signal's envelope in the occupied band varies, how much of the time it's off, and
+/// the rate its symbols change at. With two or more tones, the symbols are which tone is on.
+fn envelope(
+    samples: &[f32],
+    sample_rate: u32,
+    occupied: &Range<f64>,
+    tones: &[f64],
+) -> (f64, f64, Option<f64>) {
+    let size = ((sample_rate / ENVELOPE_RATE) as usize)
+        .next_power_of_two()
+        .max(16);
+    let hop = size / 4;
+    let bin_hz = sample_rate as f64 / size as f64;
+    let bin = |hz: f64| ((hz / bin_hz).round() as usize).min(size / 2 - 1);
+    let band = bin(occupied.start)..bin(occupied.end) + 1;
+
+    let mut fft = Transform::forward(size);
+    let window = WindowFunction::Hann.coefficients(size);
+    let mut buffer = vec![Complex::default(); size];
+    let mut levels = Vec::new();
+    let mut shifts = Vec::new();
+    let mut start = 0;
+    while start + size <= samples.len() {
+        for ((out, sample), w) in buffer
+            .iter_mut()
+            .zip(&samples[start..start + size])
+            .zip(&window)
+        {
+            *out = Complex::new(sample * w, 0.0);
+        }
+        fft.process(&mut buffer);
+        let power = |bins: Range<usize>| -> f64 {
+            buffer[bins].iter().map(|bin| bin.norm_sqr() as f64).sum()
+        };
+        levels.push(power(band.clone()).sqrt());
+        if let [first, second, ..] = tones {
+            let (first, second) = (bin(*first), bin(*second));
+            if first != second {
+                let first = power(first..first + 1).sqrt();
+                let second = power(second..second + 1).sqrt();
+                shifts.push((first - second) / (first + second).max(f64::MIN_POSITIVE));
+            }
+        }
+        start += hop;
+    }
+    if levels.len() < 8 {
+        return (0.0, 0.0, None);
+    }
+
+    let mut sorted = levels.clone();
+    sorted.sort_by(f64::total_cmp);
+    let loud = sorted[sorted.len() * 95 / 100];
+    let on: Vec<f64> = levels
+        .iter()
+        .copied()
+        .filter(|level| *level > loud * 0.3)
+        .collect();
+    let keyed = 1.0 - on.len() as f64 / levels.len() as f64;
+    let mean = on.iter().sum::<f64>() / on.len().max(1) as f64;
+    let variance =
+        on.iter().map(|level| (level - mean).powi(2)).sum::<f64>() / on.len().max(1) as f64;
+    let variation = variance.sqrt() / mean.max(f64::MIN_POSITIVE);
+
+    // Symbols show up as a line at their rate in the spectrum of how fast things change
+    let changes = if shifts.len() == levels.len() {
+        &shifts
+    } else {
+        &levels
+    };
+    let frame_rate = sample_rate as f64 / hop as f64;
+    let transitions: Vec<f32> = changes
+        .windows(2)
+        .map(|pair| ((pair[1] - pair[0]) / mean.max(f64::MIN_POSITIVE)).powi(2) as f32)
+        .collect();
+    // Taken a cycle per frame at a time, then scaled to Hz
+    let rate_fft = 1 << transitions.len().max(2).ilog2();
+    let symbol_rate =
+        PowerSpectrum::new(&transitions, 1, rate_fft, WindowFunction::Hann).and_then(|spectrum| {
+            let peak =
+                spectrum.peak(SYMBOL_RATES.start / frame_rate..SYMBOL_RATES.end / frame_rate)?;
+            let bin = spectrum
+                .bins(peak..peak)
+                .start
+                .min(spectrum.power.len() - 1);
+            // Only a clear line counts
+            (spectrum.power[bin] > 8.0 * spectrum.noise_density(None) * spectrum.bin_hz)
+                .then_some(peak * frame_rate)
+        });
+    (variation, keyed, symbol_rate)
+}
@@ -0,0 +1,143 @@
+use crate::config::ClockSettings;
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{info, warn};
+use parking_lot::RwLock;
+use std::{
+    io,
+    net::UdpSocket,
+    sync::{
+        Arc,
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use thiserror::Error as ThisError;
+
+/// How long to wait for the time server to answer
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Seconds from the NTP epoch, 1900, to the Unix epoch
+const NTP_TO_UNIX: i64 = 2_208_988_800;
+const NTP_PORT: u16 = 123;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error asking the time server: {0}")]
+    IO(#[from] io::Error),
+    #[error("The time server sent a short reply")]
+    ShortReply,
+    #[error("The time server sent a timestamp out of range")]
+    BadTimestamp,
+}
+
+/// Read a 64 bit NTP timestamp: seconds since 1900, then a binary fraction of a second
+fn ntp_timestamp(bytes: &[u8]) -> Result<DateTime<Utc>, Error> {
+    let seconds = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64;
+    let fraction = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as u64;
+    let nanos = (fraction * 1_000_000_000) >> 32;
+    DateTime::from_timestamp(seconds - NTP_TO_UNIX, nanos as u32).ok_or(Error::BadTimestamp)
+}
+
+/// Ask an SNTP server how far off our clock is. Positive means we're behind.
+pub fn query_offset(server: &str) -> Result<TimeDelta, Error> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    if server.contains(':') {
+        socket.connect(server)?;
+    } else {
+        socket.connect((server, NTP_PORT))?;
+    }
+
+    // Version 3, client mode, everything else zero
+    let mut request = [0u8; 48];
+    request[0] = 0x1b;
+    let sent = Utc::now();
+    socket.send(&request)?;
+    let mut reply = [0u8; 48];
+    if socket.recv(&mut reply)? < reply.len() {
+        return Err(Error::ShortReply);
+    }
+    let received = Utc::now();
+
+    let server_received = ntp_timestamp(&reply[32..40])?;
+    let server_sent = ntp_timestamp(&reply[40..48])?;
+    Ok(((server_received - sent) + (server_sent - received)) / 2)
+}
+
+/// Checks the system clock against a time server every so often, warning when it drifts
+/// too far. Stops when dropped.
+pub struct ClockMonitor {
+    offset: Arc<RwLock<Option<TimeDelta>>>,
+    warn: TimeDelta,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ClockMonitor {
+    pub fn start(settings: &ClockSettings) -> Result<Self, io::Error> {
+        let offset = Arc::new(RwLock::new(None));
+        let warn = TimeDelta::milliseconds(settings.warn_ms as i64);
+        let (stop, stopped) = mpsc::channel::<()>();
+        let server = settings.server.clone();
+        let interval = Duration::from_secs(settings.interval_minutes.max(1) * 60);
+        let thread = thread::Builder::new()
+            .name("clock check".to_string())
+            .spawn({
+                let offset = offset.clone();
+                move || {
+                    loop {
+                        match query_offset(&server) {
+                            Ok(measured) => {
+                                if measured.abs() > warn {
+                                    warn!(
+                                        "The system clock is off by {} ms, digital modes may not decode",
+                                        measured.num_milliseconds()
+                                    );
+                                } else {
+                                    info!(
+                                        "The system clock is off by {} ms",
+                                        measured.num_milliseconds()
+                                    );
+                                }
+                                *offset.write() = Some(measured);
+                            }
+                            Err(error) => warn!("Unable to check the clock: {}", error),
+                        }
+                        match stopped.recv_timeout(interval) {
+                            Err(RecvTimeoutError::Timeout) => {}
+                            _ => break,
+                        }
+                    }
+                }
+            })?;
+        Ok(Self {
+            offset,
+            warn,
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
+    /// How far off the clock was when last checked, if it's been checked. Positive means
+    /// it's behind.
+    pub fn offset(&self) -> Option<TimeDelta> {
+        *self.offset.read()
+    }
+
+    /// Whether the clock was off by more than the warning threshold when last checked
+    pub fn is_drifting(&self) -> bool {
+        self.offset().is_some_and(|offset| offset.abs() > self.warn)
+    }
+}
+
+impl Drop for ClockMonitor {
+    fn drop(&mut self) {
+        // Hanging up wakes the thread
+        self.stop.take();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            warn!("Clock check thread panicked");
+        }
+    }
+}
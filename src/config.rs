@@ -4,22 +4,46 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use log::LevelFilter;
 use serde::{Deserialize, Serialize};
 
+use crate::classify::Mode;
+use crate::data::{
+    audio::BitDepth, audioinput::DevicePreset, averaging::Averaging, bandplan::Region,
+    blanker::BlankerSettings, colormap::Colormap, window::WindowFunction,
+};
+
 use thiserror::Error;
 
 const QUALIFIER: &str = "com";
 const ORGANIZATION: &str = "JeffTickle";
 const APPLICATION: &str = "Hamshark";
 const SETTINGSFILE: &str = "hamshark.toml";
+/// Next to the executable, this makes Hamshark portable even without a settings file there
+const PORTABLE_MARKER: &str = "portable";
+/// Where sessions go in portable mode, relative to the executable
+const PORTABLE_SESSIONS_DIR: &str = "sessions";
 
 const HAMSHARK_SETTINGS_FILE_ENV: &str = "HAMSHARK_SETTINGS_FILE";
+const HAMSHARK_DATA_DIR_ENV: &str = "HAMSHARK_DATA_DIR";
+const HAMSHARK_CACHE_DIR_ENV: &str = "HAMSHARK_CACHE_DIR";
+/// Any other environment variable starting with this overrides a setting
+const HAMSHARK_SETTING_ENV_PREFIX: &str = "HAMSHARK_";
+/// Separates the parts of a nested setting's name, as in HAMSHARK_DSP__FFT_SIZE
+const ENV_NESTING: &str = "__";
 
 // Application configuration. Not user-servicible but environment variables
 // can generally override.
 #[derive(Debug, Clone)]
 pub struct Configuration {
     pub settings_file_path: PathBuf,
+    /// In portable mode, the executable's directory. Everything is kept in here, and relative
+    /// paths in the settings are relative to it.
+    pub portable_dir: Option<PathBuf>,
+    /// For things we make that are worth keeping, like logs and indexes
+    pub data_dir: PathBuf,
+    /// For things we can always make again. Safe to delete.
+    pub cache_dir: PathBuf,
 }
 
 #[derive(Debug, Error)]
@@ -29,6 +53,16 @@ pub enum ConfigurationError {
         HAMSHARK_SETTINGS_FILE_ENV
     )]
     SettingsPathResolution,
+    #[error(
+        "Unable to resolve the OS-specific Data Path automatically. You can specify one in the {} environment variable.",
+        HAMSHARK_DATA_DIR_ENV
+    )]
+    NoDataDir,
+    #[error(
+        "Unable to resolve the OS-specific Cache Path automatically. You can specify one in the {} environment variable.",
+        HAMSHARK_CACHE_DIR_ENV
+    )]
+    NoCacheDir,
 }
 
 pub type ConfigurationResult = Result<Configuration, ConfigurationError>;
@@ -37,6 +71,868 @@ pub type ConfigurationResult = Result<Configuration, ConfigurationError>;
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Settings {
     pub session_base_dir: PathBuf,
+    /// Which band plan to overlay on the waterfall
+    #[serde(default)]
+    pub band_plan_region: Region,
+    #[serde(default)]
+    pub layout: Layout,
+    #[serde(default)]
+    pub dsp: DspSettings,
+    #[serde(default)]
+    pub display: DisplaySettings,
+    #[serde(default)]
+    pub recording: RecordingSettings,
+    /// Blanking impulse noise out of what's recorded
+    #[serde(default)]
+    pub blanker: BlankerSettings,
+    /// Audio inputs saved by name, to switch between from the File menu
+    #[serde(default)]
+    pub device_presets: Vec<DevicePreset>,
+    #[serde(default)]
+    pub logging: LoggingSettings,
+    #[serde(default)]
+    pub station: StationSettings,
+    #[serde(default)]
+    pub rig: RigSettings,
+    #[serde(default)]
+    pub gps: GpsSettings,
+    #[serde(default)]
+    pub clock: ClockSettings,
+    #[serde(default)]
+    pub noise_floor: NoiseFloorSettings,
+    #[serde(default)]
+    pub streaming: StreamingSettings,
+    #[serde(default)]
+    pub vban: VbanSettings,
+    #[serde(default)]
+    pub jack: JackSettings,
+    #[serde(default)]
+    pub server: ServerSettings,
+    #[serde(default)]
+    pub desktop: DesktopSettings,
+    #[serde(default)]
+    pub contest: ContestSettings,
+    #[serde(default)]
+    pub decodes: DecodeOutputSettings,
+    #[serde(default)]
+    pub mix: MixSettings,
+    #[serde(default)]
+    pub lookup: LookupSettings,
+    #[serde(default)]
+    pub vad: VadSettings,
+    #[serde(default)]
+    pub segments: SegmentSettings,
+    #[serde(default)]
+    pub carriers: CarrierSettings,
+    #[serde(default)]
+    pub birdies: BirdieSettings,
+    #[serde(default)]
+    pub generator: GeneratorSettings,
+    #[serde(default)]
+    pub sweep: SweepSettings,
+    #[serde(default)]
+    pub plugins: PluginSettings,
+    #[serde(default)]
+    pub keyer: KeyerSettings,
+    /// Programs to open a selection in, by the mode it was classified as
+    #[serde(default)]
+    pub decoders: Vec<ExternalDecoder>,
+    /// Frequency corrections measured for each input
+    #[serde(default)]
+    pub calibrations: Vec<InputCalibration>,
+    /// Which channels are recorded from each input, for those that aren't averaged
+    #[serde(default)]
+    pub channel_mappings: Vec<InputChannels>,
+    /// Settings overridden from the environment, with their values from the file, so the
+    /// overrides aren't saved
+    #[serde(skip)]
+    overridden: Vec<(Vec<String>, Option<toml::Value>)>,
+}
+
+/// Who's on the air
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StationSettings {
+    pub callsign: String,
+    pub operator: String,
+    /// Maidenhead locator, like EM79
+    pub grid_square: String,
+}
+
+/// Reading the dial frequency and mode from rigctld while recording
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RigSettings {
+    pub enabled: bool,
+    /// Where rigctld is listening, as host:port
+    pub address: String,
+    /// How often to ask the rig what it's doing
+    pub poll_ms: u64,
+}
+
+impl Default for RigSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "localhost:4532".to_string(),
+            poll_ms: 1000,
+        }
+    }
+}
+
+/// Following the station's location from gpsd, for portable and rover operation
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GpsSettings {
+    pub enabled: bool,
+    /// Where gpsd is listening, as host:port
+    pub address: String,
+}
+
+impl Default for GpsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: "localhost:2947".to_string(),
+        }
+    }
+}
+
+/// Checking the system clock against a time server. Digital modes like FT8 and WSPR only
+/// decode when the clock is close to right.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ClockSettings {
+    pub check: bool,
+    /// An SNTP server, as host or host:port
+    pub server: String,
+    /// How often to check
+    pub interval_minutes: u64,
+    /// Warn when the clock is off by more than this
+    pub warn_ms: u64,
+}
+
+impl Default for ClockSettings {
+    fn default() -> Self {
+        Self {
+            check: false,
+            server: "pool.ntp.org".to_string(),
+            interval_minutes: 15,
+            warn_ms: 500,
+        }
+    }
+}
+
+/// Logging the noise floor in a band every so often through the session, to see when local
+/// interference comes and goes
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NoiseFloorSettings {
+    pub enabled: bool,
+    /// The audio frequencies measured, clear of where signals usually are if possible
+    pub low_hz: f64,
+    pub high_hz: f64,
+    /// How often to measure, averaging over everything recorded since the last time
+    pub interval_seconds: u64,
+}
+
+impl Default for NoiseFloorSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            low_hz: 300.0,
+            high_hz: 2700.0,
+            interval_seconds: 60,
+        }
+    }
+}
+
+/// Sending what's being recorded to an Icecast server as Ogg Opus, so others can listen
+/// along. Only works when built with the icecast feature.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StreamingSettings {
+    pub enabled: bool,
+    /// The Icecast server, as host:port
+    pub server: String,
+    pub mount: String,
+    pub user: String,
+    pub password: String,
+    /// What listeners see the stream called
+    pub name: String,
+    /// Bits per second
+    pub bitrate: u32,
+}
+
+impl Default for StreamingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            server: "localhost:8000".to_string(),
+            mount: "/hamshark.opus".to_string(),
+            user: "source".to_string(),
+            password: String::new(),
+            name: "Hamshark".to_string(),
+            bitrate: 32000,
+        }
+    }
+}
+
+/// Recording a VBAN stream from the network instead of the audio input
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VbanSettings {
+    pub enabled: bool,
+    /// Where to listen, as address:port
+    pub listen: String,
+    /// The stream name the sender uses
+    pub stream: String,
+}
+
+impl Default for VbanSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "0.0.0.0:6980".to_string(),
+            stream: "Stream1".to_string(),
+        }
+    }
+}
+
+/// Recording from JACK ports of our own instead of the audio input. Only works when built
+/// with the jack feature.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct JackSettings {
+    pub enabled: bool,
+    /// Our name in the JACK graph, the part of the port names before the colon
+    pub client_name: String,
+    pub channels: u16,
+    /// With no saved connections, connect to the system capture ports
+    pub auto_connect: bool,
+    /// What was connected to our ports last time, restored when recording starts
+    pub connections: Vec<JackConnection>,
+}
+
+impl Default for JackSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            client_name: "hamshark".to_string(),
+            channels: 2,
+            auto_connect: true,
+            connections: Vec::new(),
+        }
+    }
+}
+
+/// Another client's output port connected to one of ours
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct JackConnection {
+    /// Our port's short name, like in_L
+    pub port: String,
+    /// The other port's full name, like system:capture_1
+    pub source: String,
+}
+
+/// The remote control server for headless recording. Only works when built with the server
+/// feature.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServerSettings {
+    pub enabled: bool,
+    /// Where to listen, as address:port
+    pub listen: String,
+    /// Clients have to send this as a bearer token, or a token query parameter. Left empty,
+    /// anyone who can reach the server can control it.
+    pub token: String,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "127.0.0.1:7355".to_string(),
+            token: String::new(),
+        }
+    }
+}
+
+/// Marking contacts logged in N1MM+ or DXLog, from their UDP broadcasts
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ContestSettings {
+    pub enabled: bool,
+    /// Where to listen, as address:port. The loggers broadcast to port 12060 by default.
+    pub listen: String,
+    /// Save the audio around each contact as a clip of its own, in a qsos folder in the
+    /// session
+    pub export_qsos: bool,
+    /// How much audio from before the contact was logged to export
+    pub seconds_before: u64,
+    /// How much audio from after the contact was logged to export
+    pub seconds_after: u64,
+}
+
+impl Default for ContestSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: "0.0.0.0:12060".to_string(),
+            export_qsos: false,
+            seconds_before: 60,
+            seconds_after: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DecodeProtocol {
+    /// A datagram per line
+    #[default]
+    Udp,
+    /// A connection kept open, and reopened when it drops
+    Tcp,
+}
+
+/// Forwarding decoded text to other programs, a line of JSON at a time
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DecodeOutputSettings {
+    pub enabled: bool,
+    pub protocol: DecodeProtocol,
+    /// Where to send the lines, as address:port
+    pub address: String,
+}
+
+impl Default for DecodeOutputSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            protocol: Default::default(),
+            address: "127.0.0.1:7356".to_string(),
+        }
+    }
+}
+
+/// Recording a second input, like a shack microphone, on the right channel alongside the rig
+/// on the left, so recordings carry their own commentary
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MixSettings {
+    pub enabled: bool,
+    /// The audio host the microphone is on, like ALSA. Empty for the default host.
+    pub host: String,
+    /// The microphone's input device. Empty for the host's default input.
+    pub device: String,
+    /// Gain for the rig, in dB
+    pub rig_gain: f32,
+    /// Gain for the microphone, in dB
+    pub microphone_gain: f32,
+}
+
+impl Default for MixSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: String::new(),
+            device: String::new(),
+            rig_gain: 0.0,
+            microphone_gain: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LookupService {
+    /// Don't look callsigns up
+    #[default]
+    None,
+    HamQth,
+    Qrz,
+}
+
+/// Looking up who callsigns belong to. Both services need an account.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LookupSettings {
+    pub service: LookupService,
+    pub username: String,
+    pub password: String,
+}
+
+/// Finding where people are talking in a clip, to mark it
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VadSettings {
+    /// How far above the noise floor the voice band has to be, in dB
+    pub threshold_db: f64,
+    /// Pauses shorter than this don't end a segment
+    pub hangover_ms: u64,
+    /// Segments shorter than this are taken for clicks and squelch tails, and left out
+    pub min_speech_ms: u64,
+}
+
+impl Default for VadSettings {
+    fn default() -> Self {
+        Self {
+            threshold_db: 10.0,
+            hangover_ms: 600,
+            min_speech_ms: 300,
+        }
+    }
+}
+
+/// Splitting long recordings into transmissions and QSOs
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SegmentSettings {
+    /// How far above its floor the whole band has to be for the squelch to count as open
+    pub squelch_db: f64,
+    /// Gaps shorter than this don't end a transmission
+    pub hangover_ms: u64,
+    /// Transmissions shorter than this are taken for clicks and squelch tails, and left out
+    pub min_transmission_ms: u64,
+    /// Gaps shorter than this don't end a QSO
+    pub qso_gap_ms: u64,
+}
+
+impl Default for SegmentSettings {
+    fn default() -> Self {
+        Self {
+            squelch_db: 10.0,
+            hangover_ms: 1000,
+            min_transmission_ms: 500,
+            qso_gap_ms: 30_000,
+        }
+    }
+}
+
+/// The test signals the generator plays
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GeneratorSettings {
+    pub tone_hz: f64,
+    /// The two tones of the two-tone test. 700 and 1900 Hz are the usual pair, not
+    /// harmonically related and inside an SSB passband.
+    pub two_tone_low_hz: f64,
+    pub two_tone_high_hz: f64,
+    /// Peak level, in dB relative to full scale
+    pub level_db: f64,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        Self {
+            tone_hz: 1000.0,
+            two_tone_low_hz: 700.0,
+            two_tone_high_hz: 1900.0,
+            level_db: -6.0,
+        }
+    }
+}
+
+/// How the CW keyer sends
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KeyerSettings {
+    pub wpm: f64,
+    /// Sidetone pitch, in Hz
+    pub tone_hz: f64,
+    /// How long each element takes to fade in and out, in milliseconds. Too short and it
+    /// clicks, too long and fast code runs together.
+    pub rise_ms: f64,
+    /// Peak level, in dB relative to full scale
+    pub level_db: f64,
+}
+
+impl Default for KeyerSettings {
+    fn default() -> Self {
+        Self {
+            wpm: 20.0,
+            tone_hz: 600.0,
+            rise_ms: 5.0,
+            level_db: -6.0,
+        }
+    }
+}
+
+/// Measuring the frequency response of a sound card loop or a rig's audio chain
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SweepSettings {
+    /// Device the sweep is played out of, empty for the default
+    pub output_device: String,
+    /// Device it's recorded back through, empty for the default
+    pub input_device: String,
+    pub start_hz: f64,
+    pub end_hz: f64,
+    pub seconds: f64,
+    /// Peak level of the sweep, in dB relative to full scale
+    pub level_db: f64,
+    /// Points plotted per octave, each the average over that much of the spectrum
+    pub points_per_octave: u32,
+}
+
+impl Default for SweepSettings {
+    fn default() -> Self {
+        Self {
+            output_device: String::new(),
+            input_device: String::new(),
+            start_hz: 20.0,
+            end_hz: 20_000.0,
+            seconds: 5.0,
+            level_db: -12.0,
+            points_per_octave: 12,
+        }
+    }
+}
+
+/// Finding steady carriers in the spectrum and following them as they drift
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CarrierSettings {
+    /// How far above the noise floor a peak has to be, in dB
+    pub threshold_db: f64,
+    /// How far a carrier can move between one spectrum and the next and still be the same
+    pub max_drift_hz: f64,
+    /// Gaps shorter than this don't end a carrier
+    pub hold_ms: u64,
+    /// Carriers shorter than this are left out of the log
+    pub min_duration_ms: u64,
+}
+
+impl Default for CarrierSettings {
+    fn default() -> Self {
+        Self {
+            threshold_db: 15.0,
+            max_drift_hz: 25.0,
+            hold_ms: 1000,
+            min_duration_ms: 500,
+        }
+    }
+}
+
+/// What counts as a birdie when scanning a session for them
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BirdieSettings {
+    /// The fraction of a clip a carrier has to be on for
+    pub min_coverage: f64,
+    /// How far a carrier can wander over a clip and still be a birdie
+    pub max_spread_hz: f64,
+    /// Carriers this close together are the same birdie
+    pub tolerance_hz: f64,
+    /// Clips a birdie has to turn up in before it's drawn on the waterfall
+    pub min_occurrences: usize,
+}
+
+impl Default for BirdieSettings {
+    fn default() -> Self {
+        Self {
+            min_coverage: 0.5,
+            max_spread_hz: 5.0,
+            tolerance_hz: 10.0,
+            min_occurrences: 2,
+        }
+    }
+}
+
+/// How far off frequencies read from an input are, measured against a reference. Inputs are
+/// remembered by name, like device presets.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct InputCalibration {
+    pub host: String,
+    pub device: String,
+    /// Parts per million to correct frequencies by
+    pub ppm: f64,
+}
+
+/// Which of an input's channels go into a recording
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ChannelMapping {
+    /// Average them into one
+    #[default]
+    Average,
+    /// Just the one, counting from zero
+    Pick(u16),
+    /// All of them, as they come
+    KeepAll,
+}
+
+impl ChannelMapping {
+    /// How many channels are recorded from an input with this many
+    pub fn channels(&self, input_channels: u16) -> u16 {
+        match self {
+            ChannelMapping::KeepAll => input_channels.max(1),
+            ChannelMapping::Average | ChannelMapping::Pick(_) => 1,
+        }
+    }
+
+    /// One sample for a frame: the picked channel, or else the average of them all
+    pub fn mono(&self, frame: &[f32]) -> f32 {
+        match self {
+            ChannelMapping::Pick(channel) if !frame.is_empty() => {
+                frame[(*channel as usize).min(frame.len() - 1)]
+            }
+            _ => frame.iter().sum::<f32>() / frame.len().max(1) as f32,
+        }
+    }
+}
+
+/// The channel mapping for an input, remembered by name like calibrations
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct InputChannels {
+    pub host: String,
+    pub device: String,
+    pub mapping: ChannelMapping,
+}
+
+/// A program that decodes a mode Hamshark can't, like fldigi or WSJT-X
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ExternalDecoder {
+    pub mode: Mode,
+    pub program: String,
+    /// Arguments to run it with, {wav} is replaced with the path of the selection saved as
+    /// a WAV file
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// The sample rate it wants, if it's fussy. Selections at other rates are resampled.
+    #[serde(default)]
+    pub sample_rate: Option<u32>,
+}
+
+impl ExternalDecoder {
+    /// Start the program on a WAV file, leaving it running
+    pub fn launch(&self, wav: &Path) -> std::io::Result<std::process::Child> {
+        let wav = wav.to_string_lossy();
+        std::process::Command::new(&self.program)
+            .args(self.args.iter().map(|arg| arg.replace("{wav}", &wav)))
+            .spawn()
+    }
+}
+
+/// How the recorder fits in with the rest of the desktop
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DesktopSettings {
+    /// Keep the computer from going to sleep while recording
+    pub keep_awake: bool,
+    /// Keep the screen on while recording too
+    pub keep_display_on: bool,
+    /// Start and stop recording from the desktop's media controls and keys
+    pub media_controls: bool,
+}
+
+impl Default for DesktopSettings {
+    fn default() -> Self {
+        Self {
+            keep_awake: true,
+            keep_display_on: true,
+            media_controls: true,
+        }
+    }
+}
+
+/// Plugins from the plugins directory, see include/hamshark_plugin.h
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PluginSettings {
+    /// Load plugins at all. They run with all the recorder's permissions, so they're off
+    /// until asked for.
+    pub enabled: bool,
+    /// Sinks loaded but left out of recordings, by name
+    pub disabled: Vec<String>,
+}
+
+impl PluginSettings {
+    pub fn sink_enabled(&self, name: &str) -> bool {
+        !self.disabled.iter().any(|disabled| disabled == name)
+    }
+
+    /// Leave a sink in or out of recordings
+    pub fn set_sink_enabled(&mut self, name: &str, enabled: bool) {
+        self.disabled.retain(|disabled| disabled != name);
+        if !enabled {
+            self.disabled.push(name.to_string());
+        }
+    }
+}
+
+/// Logging to a file, for finding out what went wrong during an unattended recording
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LoggingSettings {
+    pub to_file: bool,
+    /// The log file. Left out, it's hamshark.log in the data directory.
+    pub file: Option<PathBuf>,
+    pub level: LevelFilter,
+    /// Start a new file once the current one gets this big
+    pub max_file_bytes: u64,
+    /// How many old files to keep, as hamshark.log.1, hamshark.log.2 and so on
+    pub keep_files: usize,
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        Self {
+            to_file: false,
+            file: None,
+            level: LevelFilter::Info,
+            max_file_bytes: 10 * 1024 * 1024,
+            keep_files: 5,
+        }
+    }
+}
+
+/// How clips are analyzed when they're opened
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DspSettings {
+    /// Samples per FFT for the waterfall. Bigger resolves frequency more finely and time
+    /// more coarsely.
+    pub fft_size: usize,
+    pub window_function: WindowFunction,
+    /// Samples between the starts of successive waterfall rows, for rows that overlap. None
+    /// for a whole FFT's worth, so they don't.
+    pub hop: Option<usize>,
+    /// The bandwidth SNR counts noise over, in Hz. 2500 matches what WSJT-X reports.
+    pub snr_bandwidth: f64,
+}
+
+impl Default for DspSettings {
+    fn default() -> Self {
+        Self {
+            fft_size: 128,
+            window_function: Default::default(),
+            hop: None,
+            snr_bandwidth: 2500.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    /// Follow the operating system's light or dark preference
+    #[default]
+    System,
+    Dark,
+    Light,
+}
+
+/// How things look
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DisplaySettings {
+    pub colormap: Colormap,
+    pub theme: Theme,
+    /// Samples per pixel for a clip that hasn't been zoomed yet
+    pub timeline_scale: f32,
+    /// Averaging for the spectrum and for waterfalls that haven't been set otherwise
+    pub averaging: Averaging,
+    /// Frames per second the views are redrawn at while recording
+    pub refresh_rate: u32,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            colormap: Default::default(),
+            theme: Default::default(),
+            timeline_scale: 1024.0,
+            averaging: Default::default(),
+            refresh_rate: 20,
+        }
+    }
+}
+
+/// How new clips are recorded
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RecordingSettings {
+    pub bit_depth: BitDepth,
+    /// In headless mode, start a new clip after this many seconds
+    pub segment_seconds: u64,
+    /// Run the clip writer at real-time priority so a busy GUI doesn't hold it up long
+    /// enough for the buffer to overflow. The audio input thread always runs at real-time
+    /// priority where the OS allows it.
+    pub realtime_priority: bool,
+    /// Name sessions and clips by UTC rather than local time. UTC names end in Z.
+    pub utc_names: bool,
+    /// Keep at most this many megabytes of a recording's samples in memory. Older ones are
+    /// read back from its WAV file when needed, so recordings can run for days.
+    pub memory_cap_mb: Option<usize>,
+    /// For debugging: capture every block the input device delivers, as it delivers them,
+    /// next to the clip in a .blocks file that can be played back through a virtual input
+    pub capture_blocks: bool,
+}
+
+impl RecordingSettings {
+    /// The memory cap in samples
+    pub fn memory_cap_samples(&self) -> Option<usize> {
+        self.memory_cap_mb
+            .map(|mb| mb * 1024 * 1024 / std::mem::size_of::<f32>())
+    }
+}
+
+impl Default for RecordingSettings {
+    fn default() -> Self {
+        Self {
+            bit_depth: Default::default(),
+            segment_seconds: 3600,
+            realtime_priority: false,
+            utc_names: false,
+            memory_cap_mb: Some(1024),
+            capture_blocks: false,
+        }
+    }
+}
+
+/// How the main window was laid out when we last closed, so it comes back the same way
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Layout {
+    /// Inner size of the main window, when it isn't maximized
+    pub window_size: Option<[f32; 2]>,
+    /// Outer position of the main window, when it isn't maximized
+    pub window_position: Option<[f32; 2]>,
+    pub maximized: bool,
+    pub clip_list_open: bool,
+    pub pipeline_inspector_open: bool,
+    pub performance_overlay_open: bool,
+    pub scope_open: bool,
+    pub spectrum_open: bool,
+    pub generator_open: bool,
+    pub keyer_open: bool,
+    pub response_open: bool,
+    pub noise_floor_open: bool,
+    pub tdoa_open: bool,
+    /// Whether the session's birdies are marked on the waterfalls
+    pub birdies_shown: bool,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            window_size: None,
+            window_position: None,
+            maximized: false,
+            clip_list_open: true,
+            pipeline_inspector_open: false,
+            performance_overlay_open: false,
+            scope_open: false,
+            spectrum_open: false,
+            generator_open: false,
+            keyer_open: false,
+            response_open: false,
+            noise_floor_open: false,
+            tdoa_open: false,
+            birdies_shown: true,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -53,6 +949,8 @@ pub enum SettingsError {
     FileExistenceError(#[source] std::io::Error),
     #[error("Error creating Hamshark settings directory: {0}")]
     DirectoryCreationError(#[source] std::io::Error),
+    #[error("{0} doesn't match any Hamshark setting")]
+    UnknownOverride(String),
 }
 
 pub type OwnedSettingsResult = Result<Settings, SettingsError>;
@@ -60,6 +958,8 @@ pub type SettingsResult = Result<(), SettingsError>;
 
 impl Configuration {
     pub fn from_env() -> ConfigurationResult {
+        let portable_dir = portable_dir();
+
         // Who knew figuring out the settings file path was going
         // to take so much damn code
         let settings_file_base = match env::var_os(HAMSHARK_SETTINGS_FILE_ENV) {
@@ -68,29 +968,77 @@ impl Configuration {
                 // and if it's invalid, too bad panic
                 PathBuf::from(env_config_path)
             }
-            None => {
-                // Try auto-determining settings dir from OS paths
-                match ProjectDirs::from(QUALIFIER, ORGANIZATION, APPLICATION) {
-                    // Able to determine the OS-specific config dir
-                    Some(project_dirs) => PathBuf::from(project_dirs.config_dir()),
-                    // Unable to determine where settings should be stored
-                    None => return Err(ConfigurationError::SettingsPathResolution),
+            // Portable mode keeps the settings next to the executable
+            None => match &portable_dir {
+                Some(portable_dir) => portable_dir.clone(),
+                None => {
+                    // Try auto-determining settings dir from OS paths
+                    match ProjectDirs::from(QUALIFIER, ORGANIZATION, APPLICATION) {
+                        // Able to determine the OS-specific config dir
+                        Some(project_dirs) => PathBuf::from(project_dirs.config_dir()),
+                        // Unable to determine where settings should be stored
+                        None => return Err(ConfigurationError::SettingsPathResolution),
+                    }
                 }
-            }
+            },
+        };
+
+        let project_dirs = ProjectDirs::from(QUALIFIER, ORGANIZATION, APPLICATION);
+        let data_dir = match env::var_os(HAMSHARK_DATA_DIR_ENV) {
+            Some(env_data_dir) => PathBuf::from(env_data_dir),
+            None => match (&portable_dir, &project_dirs) {
+                (Some(portable_dir), _) => portable_dir.join("data"),
+                (None, Some(project_dirs)) => PathBuf::from(project_dirs.data_dir()),
+                (None, None) => return Err(ConfigurationError::NoDataDir),
+            },
+        };
+        let cache_dir = match env::var_os(HAMSHARK_CACHE_DIR_ENV) {
+            Some(env_cache_dir) => PathBuf::from(env_cache_dir),
+            None => match (&portable_dir, &project_dirs) {
+                (Some(portable_dir), _) => portable_dir.join("cache"),
+                (None, Some(project_dirs)) => PathBuf::from(project_dirs.cache_dir()),
+                (None, None) => return Err(ConfigurationError::NoCacheDir),
+            },
         };
 
         Ok(Self {
             settings_file_path: settings_file_base.join(SETTINGSFILE),
+            portable_dir,
+            data_dir,
+            cache_dir,
         })
     }
+
+    /// Relative paths from the settings are relative to the executable in portable mode, so
+    /// they still work when the drive is mounted somewhere else
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        match &self.portable_dir {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    /// Where plugins are loaded from
+    pub fn plugins_dir(&self) -> PathBuf {
+        self.data_dir.join("plugins")
+    }
+
+    /// Where the clip thumbnails for a session are cached
+    pub fn thumbnail_cache_dir(&self, session_path: &Path) -> PathBuf {
+        let mut dir = self.cache_dir.join("thumbnails");
+        if let Some(session_name) = session_path.file_name() {
+            dir.push(session_name);
+        }
+        dir
+    }
 }
 
 impl Settings {
     pub fn from_file(file: &Path) -> OwnedSettingsResult {
         match fs::exists(file) {
             Ok(true) => match fs::read_to_string(file) {
-                Ok(serialized) => match toml::from_str(serialized.as_str()) {
-                    Ok(settings) => Ok(settings),
+                Ok(serialized) => match toml::from_str::<Settings>(serialized.as_str()) {
+                    Ok(settings) => settings.with_env_overrides(),
                     Err(error) => Err(SettingsError::DeserializationError(error)),
                 },
                 Err(error) => Err(SettingsError::FileReadError(error)),
@@ -98,7 +1046,7 @@ impl Settings {
             Ok(false) => {
                 let settings = Settings::from_sensible_defaults();
                 match settings.save(file) {
-                    Ok(_) => Ok(settings),
+                    Ok(_) => settings.with_env_overrides(),
                     Err(error) => Err(error),
                 }
             }
@@ -109,29 +1057,197 @@ impl Settings {
     pub fn from_sensible_defaults() -> Settings {
         Self {
             session_base_dir: Self::determine_session_base_dir(),
+            band_plan_region: Default::default(),
+            layout: Default::default(),
+            dsp: Default::default(),
+            display: Default::default(),
+            recording: Default::default(),
+            device_presets: Default::default(),
+            logging: Default::default(),
+            station: Default::default(),
+            rig: Default::default(),
+            gps: Default::default(),
+            clock: Default::default(),
+            noise_floor: Default::default(),
+            streaming: Default::default(),
+            vban: Default::default(),
+            jack: Default::default(),
+            server: Default::default(),
+            desktop: Default::default(),
+            contest: Default::default(),
+            decodes: Default::default(),
+            mix: Default::default(),
+            lookup: Default::default(),
+            vad: Default::default(),
+            carriers: Default::default(),
+            generator: Default::default(),
+            sweep: Default::default(),
+            plugins: Default::default(),
+            keyer: Default::default(),
+            blanker: Default::default(),
+            birdies: Default::default(),
+            segments: Default::default(),
+            decoders: Default::default(),
+            calibrations: Default::default(),
+            channel_mappings: Default::default(),
+            overridden: Default::default(),
+        }
+    }
+
+    /// The frequency correction measured for an input, if it's been calibrated
+    pub fn frequency_correction(&self, host: &str, device: &str) -> Option<f64> {
+        self.calibrations
+            .iter()
+            .find(|calibration| calibration.host == host && calibration.device == device)
+            .map(|calibration| calibration.ppm)
+    }
+
+    /// Remember the frequency correction for an input, replacing any it had
+    pub fn set_frequency_correction(&mut self, host: &str, device: &str, ppm: f64) {
+        self.calibrations
+            .retain(|calibration| calibration.host != host || calibration.device != device);
+        self.calibrations.push(InputCalibration {
+            host: host.to_string(),
+            device: device.to_string(),
+            ppm,
+        });
+    }
+
+    /// Which channels are recorded from an input, averaged unless it's been set
+    pub fn channel_mapping(&self, host: &str, device: &str) -> ChannelMapping {
+        self.channel_mappings
+            .iter()
+            .find(|channels| channels.host == host && channels.device == device)
+            .map(|channels| channels.mapping)
+            .unwrap_or_default()
+    }
+
+    /// Remember which channels to record from an input. Averaging is the default, so it
+    /// isn't kept.
+    pub fn set_channel_mapping(&mut self, host: &str, device: &str, mapping: ChannelMapping) {
+        self.channel_mappings
+            .retain(|channels| channels.host != host || channels.device != device);
+        if mapping != ChannelMapping::Average {
+            self.channel_mappings.push(InputChannels {
+                host: host.to_string(),
+                device: device.to_string(),
+                mapping,
+            });
         }
     }
 
     pub fn determine_session_base_dir() -> PathBuf {
-        // Get OS-specific document dir and create a directory named Hamshark
-        UserDirs::new()
-            .map(|user_dirs| {
-                user_dirs
-                    .document_dir()
-                    .map(|doc_dir| PathBuf::from(doc_dir).join(APPLICATION))
-            })
-            .flatten()
-            .expect("Could not determine OS base dir")
+        // Portable sessions stay with the executable, wherever it ends up
+        if portable_dir().is_some() {
+            return PathBuf::from(PORTABLE_SESSIONS_DIR);
+        }
+        // Get OS-specific document dir and create a directory named Hamshark. Without one,
+        // try the home directory, and failing that the working directory.
+        let user_dirs = UserDirs::new();
+        let base = user_dirs.as_ref().map(|user_dirs| {
+            user_dirs
+                .document_dir()
+                .unwrap_or_else(|| user_dirs.home_dir())
+        });
+        match base {
+            Some(base) => base.join(APPLICATION),
+            None => PathBuf::from(APPLICATION),
+        }
+    }
+
+    /// Layer HAMSHARK_* environment variables over these settings. The variable is the
+    /// setting's name in capitals, with sections separated by a double underscore, so
+    /// HAMSHARK_SESSION_BASE_DIR or HAMSHARK_DSP__FFT_SIZE. Values are written as in the
+    /// settings file, except strings don't need quoting.
+    pub fn with_env_overrides(self) -> OwnedSettingsResult {
+        let overrides = env::vars().filter_map(|(name, value)| {
+            let setting = name.strip_prefix(HAMSHARK_SETTING_ENV_PREFIX)?;
+            let configuration = [
+                HAMSHARK_SETTINGS_FILE_ENV,
+                HAMSHARK_DATA_DIR_ENV,
+                HAMSHARK_CACHE_DIR_ENV,
+            ];
+            (!configuration.contains(&name.as_str())).then(|| (setting.to_string(), value))
+        });
+        self.with_overrides(overrides)
+    }
+
+    fn with_overrides(
+        mut self,
+        overrides: impl IntoIterator<Item = (String, String)>,
+    ) -> OwnedSettingsResult {
+        let mut table = toml::Table::try_from(&self).map_err(SettingsError::SerializationError)?;
+        let mut overridden = std::mem::take(&mut self.overridden);
+        for (name, raw) in overrides {
+            let path: Vec<String> = name.split(ENV_NESTING).map(str::to_lowercase).collect();
+            let unknown = || {
+                SettingsError::UnknownOverride(
+                    HAMSHARK_SETTING_ENV_PREFIX.to_string() + name.as_str(),
+                )
+            };
+            let (section, key) = match lookup_section(&mut table, &path) {
+                Some(found) => found,
+                None => return Err(unknown()),
+            };
+            // Strings go in as they are, anything else is parsed like the settings file
+            let value = match section.get(key) {
+                Some(toml::Value::String(_)) => toml::Value::String(raw),
+                _ => toml::from_str::<toml::Table>(&format!("value = {}", raw))
+                    .ok()
+                    .and_then(|mut parsed| parsed.remove("value"))
+                    .unwrap_or(toml::Value::String(raw)),
+            };
+            let original = section.insert(key.to_string(), value);
+            if !overridden
+                .iter()
+                .any(|(overridden_path, _)| *overridden_path == path)
+            {
+                overridden.push((path, original));
+            }
+        }
+
+        let mut settings: Settings = table
+            .try_into()
+            .map_err(SettingsError::DeserializationError)?;
+        // Anything that didn't make it through the round trip isn't a setting
+        let mut check =
+            toml::Table::try_from(&settings).map_err(SettingsError::SerializationError)?;
+        for (path, _) in &overridden {
+            if lookup_section(&mut check, path)
+                .is_none_or(|(section, key)| !section.contains_key(key))
+            {
+                return Err(SettingsError::UnknownOverride(format!(
+                    "{}{}",
+                    HAMSHARK_SETTING_ENV_PREFIX,
+                    path.join(ENV_NESTING).to_uppercase()
+                )));
+            }
+        }
+        settings.overridden = overridden;
+        Ok(settings)
     }
 
     pub fn save(&self, file: &Path) -> SettingsResult {
-        match toml::to_string(self) {
+        let mut table = match toml::Table::try_from(self) {
+            Ok(table) => table,
+            Err(error) => return Err(SettingsError::SerializationError(error)),
+        };
+        // Put back what the file had for anything overridden from the environment
+        for (path, original) in &self.overridden {
+            if let Some((section, key)) = lookup_section(&mut table, path) {
+                match original {
+                    Some(value) => section.insert(key.to_string(), value.clone()),
+                    None => section.remove(key),
+                };
+            }
+        }
+        match toml::to_string(&table) {
             Ok(serialized) => {
                 // If we can resolve a parent directory, create it.
-                if let Some(parent) = file.parent() {
-                    if let Err(error) = fs::create_dir_all(parent) {
-                        return Err(SettingsError::DirectoryCreationError(error));
-                    }
+                if let Some(parent) = file.parent()
+                    && let Err(error) = fs::create_dir_all(parent)
+                {
+                    return Err(SettingsError::DirectoryCreationError(error));
                 };
                 match fs::write(file, serialized) {
                     Ok(()) => Ok(()),
@@ -142,3 +1258,24 @@ impl Settings {
         }
     }
 }
+
+/// The executable's directory, if there's a settings file or a portable marker in it
+fn portable_dir() -> Option<PathBuf> {
+    let exe = env::current_exe().ok()?;
+    let dir = exe.parent()?;
+    (dir.join(SETTINGSFILE).exists() || dir.join(PORTABLE_MARKER).exists())
+        .then(|| dir.to_path_buf())
+}
+
+/// The table holding the setting at path, and the setting's key in it
+fn lookup_section<'a, 'p>(
+    table: &'a mut toml::Table,
+    path: &'p [String],
+) -> Option<(&'a mut toml::Table, &'p str)> {
+    let (key, sections) = path.split_last()?;
+    let mut section = table;
+    for name in sections {
+        section = section.get_mut(name)?.as_table_mut()?;
+    }
+    Some((section, key))
+}
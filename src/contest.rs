@@ -0,0 +1,249 @@
+use crate::{
+    config::ContestSettings,
+    data::{
+        audio::{BitDepth, Clip},
+        metadata::Marker,
+    },
+};
+use log::{info, warn};
+use parking_lot::Mutex;
+use std::{
+    fs, io,
+    net::UdpSocket,
+    ops::Range,
+    path::PathBuf,
+    sync::{
+        Arc,
+        mpsc::{self, Sender, TryRecvError},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How often the listening thread checks whether it's time to stop, or to export
+const STOP_POLL: Duration = Duration::from_millis(250);
+/// Big enough for any contact broadcast
+const MAX_PACKET: usize = 65536;
+/// Where exported QSOs go, under the session directory
+const QSO_DIR: &str = "qsos";
+
+/// A QSO logged in N1MM+ or DXLog, from its contactinfo broadcast
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contact {
+    pub call: String,
+    pub band: String,
+    pub mode: String,
+    /// As the logger sends it, like 2020-01-17 16:43:38
+    pub timestamp: String,
+}
+
+impl Contact {
+    /// Read a contactinfo packet. Anything else, like contactreplace or RadioInfo, is None.
+    pub fn parse(packet: &str) -> Option<Self> {
+        let info = element(packet, "contactinfo")?;
+        Some(Self {
+            call: element(info, "call")?.trim().to_string(),
+            band: element(info, "band").unwrap_or_default().trim().to_string(),
+            mode: element(info, "mode").unwrap_or_default().trim().to_string(),
+            timestamp: element(info, "timestamp")
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+        })
+    }
+
+    /// What the marker in the clip says
+    pub fn label(&self) -> String {
+        [self.call.as_str(), &band_label(&self.band), &self.mode]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// A file name for the QSO's audio, like W1AW_2020-01-17_16-43-38.wav
+    fn file_name(&self) -> String {
+        let safe = |s: &str| {
+            s.chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+                .collect::<String>()
+        };
+        format!("{}_{}.wav", safe(&self.call), safe(&self.timestamp))
+    }
+}
+
+/// The loggers send the band in MHz, like 14, which reads better in meters
+fn band_label(band: &str) -> String {
+    let meters = match band.trim() {
+        "1.8" => "160m",
+        "3.5" => "80m",
+        "5" => "60m",
+        "7" => "40m",
+        "10" => "30m",
+        "14" => "20m",
+        "18" => "17m",
+        "21" => "15m",
+        "24" => "12m",
+        "28" => "10m",
+        "50" => "6m",
+        "144" => "2m",
+        "432" => "70cm",
+        other => return other.to_string(),
+    };
+    meters.to_string()
+}
+
+/// The text inside the first `<name>…</name>`. The loggers' XML is simple enough not to
+/// need a real parser.
+pub(crate) fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&close)?;
+    Some(&xml[start..end])
+}
+
+/// A QSO's audio waiting for enough to be recorded after it
+struct PendingExport {
+    clip: Clip,
+    range: Range<usize>,
+    path: PathBuf,
+}
+
+impl PendingExport {
+    fn is_ready(&self) -> bool {
+        let clip = self.clip.read();
+        clip.samples.len() >= self.range.end || !clip.is_recording()
+    }
+
+    fn export(&self, bit_depth: BitDepth) {
+        let clip = self.clip.read();
+        let end = self.range.end.min(clip.samples.len());
+        let range = self.range.start.min(end)..end;
+        let spec = bit_depth.wav_spec(clip.sample_rate.0);
+        match clip.export_range(range, &self.path, spec) {
+            Ok(()) => info!("Exported QSO audio to {:?}", self.path.as_os_str()),
+            Err(error) => warn!(
+                "Unable to export QSO audio to {:?}: {}",
+                self.path.as_os_str(),
+                error
+            ),
+        }
+    }
+}
+
+/// Listens for contacts logged in N1MM+ or DXLog, marking each one in the clip being
+/// recorded, and exporting the audio around it if asked to. Stops when dropped.
+pub struct ContestListener {
+    clip: Arc<Mutex<Option<Clip>>>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ContestListener {
+    pub fn start(
+        settings: &ContestSettings,
+        bit_depth: BitDepth,
+        session_path: PathBuf,
+    ) -> Result<Self, io::Error> {
+        let socket = UdpSocket::bind(&settings.listen)?;
+        socket.set_read_timeout(Some(STOP_POLL))?;
+        info!("Listening for logged contacts on {}", settings.listen);
+        if settings.export_qsos {
+            fs::create_dir_all(session_path.join(QSO_DIR))?;
+        }
+        let clip = Arc::new(Mutex::new(None::<Clip>));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let settings = settings.clone();
+        let thread = thread::Builder::new()
+            .name("contest logger".to_string())
+            .spawn({
+                let clip = clip.clone();
+                move || {
+                    let mut buffer = vec![0u8; MAX_PACKET];
+                    let mut pending: Vec<PendingExport> = Vec::new();
+                    while matches!(stopped.try_recv(), Err(TryRecvError::Empty)) {
+                        pending.retain(|export| {
+                            let ready = export.is_ready();
+                            if ready {
+                                export.export(bit_depth);
+                            }
+                            !ready
+                        });
+
+                        let len = match socket.recv(&mut buffer) {
+                            Ok(len) => len,
+                            Err(error)
+                                if matches!(
+                                    error.kind(),
+                                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                                ) =>
+                            {
+                                continue;
+                            }
+                            Err(error) => {
+                                warn!("Error receiving logged contacts: {}", error);
+                                continue;
+                            }
+                        };
+                        let Some(contact) = String::from_utf8(buffer[..len].to_vec())
+                            .ok()
+                            .and_then(|packet| Contact::parse(&packet))
+                        else {
+                            continue;
+                        };
+                        let Some(clip) = clip.lock().clone() else {
+                            info!("Logged {} while not recording", contact.call);
+                            continue;
+                        };
+                        let (position, sample_rate) = {
+                            let clip = clip.read();
+                            (clip.samples.len(), clip.sample_rate.0 as usize)
+                        };
+                        info!("Logged {}, marking the clip", contact.call);
+                        if let Err(error) = clip
+                            .write()
+                            .add_marker(Marker::new(position, contact.label()))
+                        {
+                            warn!("Unable to mark {}: {}", contact.call, error);
+                        }
+                        if settings.export_qsos {
+                            pending.push(PendingExport {
+                                range: position
+                                    .saturating_sub(settings.seconds_before as usize * sample_rate)
+                                    ..position + settings.seconds_after as usize * sample_rate,
+                                path: session_path.join(QSO_DIR).join(contact.file_name()),
+                                clip,
+                            });
+                        }
+                    }
+                    // Export what there is rather than lose it
+                    for export in &pending {
+                        export.export(bit_depth);
+                    }
+                }
+            })?;
+        Ok(Self {
+            clip,
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
+    /// Mark contacts in this clip from now on, or none
+    pub fn track_clip(&self, clip: Option<Clip>) {
+        *self.clip.lock() = clip;
+    }
+}
+
+impl Drop for ContestListener {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            warn!("Contest logger thread panicked");
+        }
+    }
+}
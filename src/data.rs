@@ -1,2 +1,9 @@
 pub mod audio;
 pub mod audioinput;
+pub mod averaging;
+pub mod bandplan;
+pub mod blanker;
+pub mod colormap;
+pub mod metadata;
+pub mod salvage;
+pub mod window;
@@ -1,21 +1,229 @@
-use chrono::{DateTime, Local};
+use crate::data::{
+    metadata::{self, ClipMetadata, Location, Marker, Qso, RigReading, SatellitePass, ViewState},
+    salvage::{self, Damage},
+};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
 use cpal::SampleRate;
-use hound::{WavReader, WavSpec, WavWriter};
-use log::debug;
-use parking_lot::RwLock;
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+use log::{debug, warn};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
 use std::{
-    fmt::Display,
+    borrow::Cow,
+    fmt::{self, Debug, Display},
     fs::File,
-    io::BufWriter,
+    io::{self, BufReader, BufWriter},
     ops::Range,
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 use thiserror::Error as ThisError;
 
-pub type Samples = Vec<f32>;
+/// A clip's samples, one a frame, averaged over the WAV file's channels. A long recording
+/// lets go of its oldest samples once it has more than its memory cap, and reads them back
+/// from its WAV file when they're asked for.
+#[derive(Debug, Default)]
+pub struct Samples {
+    /// How many samples from the start have been let go
+    spilled: usize,
+    resident: Vec<f32>,
+    /// Most samples to hold in memory, or no limit
+    cap: Option<usize>,
+    /// The WAV file spilled samples are read back from
+    file: Arc<SpillFile>,
+    /// How many channels the WAV file has
+    channels: u16,
+}
+
+impl Samples {
+    fn new(path: &Path, channels: u16) -> Self {
+        Self {
+            file: Arc::new(SpillFile {
+                path: path.to_path_buf(),
+                reader: Default::default(),
+            }),
+            channels,
+            ..Default::default()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.spilled + self.resident.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The samples in `range`, cut short at the end of the clip. They're borrowed when
+    /// they're in memory and read back from the file when they've been let go.
+    pub fn range(&self, range: Range<usize>) -> Cow<'_, [f32]> {
+        let end = range.end.min(self.len());
+        let start = range.start.min(end);
+        if start >= self.spilled {
+            return Cow::Borrowed(&self.resident[start - self.spilled..end - self.spilled]);
+        }
+        Cow::Owned(self.fetch(start..end).read())
+    }
+
+    /// Everything from `start` to the end
+    pub fn from(&self, start: usize) -> Cow<'_, [f32]> {
+        self.range(start..self.len())
+    }
+
+    /// The samples in `range`, for reading once the clip's lock has been let go. Reading
+    /// back ones that have been let go from the file then doesn't hold up the recording.
+    pub fn fetch(&self, range: Range<usize>) -> Fetch {
+        let end = range.end.min(self.len());
+        let start = range.start.min(end);
+        Fetch {
+            file: self.file.clone(),
+            spilled: start.min(self.spilled)..end.min(self.spilled),
+            resident: self.resident
+                [start.max(self.spilled) - self.spilled..end.max(self.spilled) - self.spilled]
+                .to_vec(),
+        }
+    }
+
+    /// Let go of the WAV file if it's open for reading back, so it can be moved
+    pub(crate) fn close_file(&self) {
+        self.file.reader.lock().take();
+    }
+
+    /// Hold at most `cap` samples in memory, or all of them
+    pub fn set_cap(&mut self, cap: Option<usize>) {
+        self.cap = cap;
+        self.spill();
+        self.resident.shrink_to_fit();
+    }
+
+    /// Add frames of the WAV file's channels, interleaved
+    fn extend(&mut self, samples: &[f32]) {
+        self.resident
+            .extend_from_slice(&downmix(samples, self.channels));
+        self.spill();
+    }
+
+    /// Let go of the oldest samples if there are too many in memory. It waits for an
+    /// eighth over the cap so the rest aren't moved down on every write.
+    fn spill(&mut self) {
+        let Some(cap) = self.cap else {
+            return;
+        };
+        if self.resident.len() > cap + cap / 8 {
+            let excess = self.resident.len() - cap;
+            self.resident.drain(..excess);
+            self.spilled += excess;
+        }
+    }
+}
 
-#[derive(Eq, Ord, PartialEq, PartialOrd, Clone, Debug)]
+/// Samples taken from a clip, some of them maybe still to be read back from its WAV file
+pub struct Fetch {
+    file: Arc<SpillFile>,
+    spilled: Range<usize>,
+    resident: Vec<f32>,
+}
+
+impl Fetch {
+    pub fn read(self) -> Vec<f32> {
+        if self.spilled.is_empty() {
+            return self.resident;
+        }
+        let mut samples = self
+            .file
+            .read(self.spilled.clone())
+            .unwrap_or_else(|error| {
+                warn!(
+                    "Failed to read samples back from {:?}: {}",
+                    self.file.path.as_os_str(),
+                    error
+                );
+                Vec::new()
+            });
+        // Whatever couldn't be read comes back as silence rather than moving what follows
+        samples.resize(self.spilled.len(), 0.0);
+        samples.extend_from_slice(&self.resident);
+        samples
+    }
+}
+
+/// A clip's WAV file, kept open for reading back the samples it's let go of
+#[derive(Default)]
+struct SpillFile {
+    path: PathBuf,
+    reader: Mutex<Option<WavReader<BufReader<File>>>>,
+}
+
+impl Debug for SpillFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpillFile")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SpillFile {
+    /// Frames in `range`, averaged over the channels
+    fn read(&self, range: Range<usize>) -> Result<Vec<f32>, hound::Error> {
+        let mut reader = self.reader.lock();
+        let read = Self::read_from(&mut reader, &self.path, range);
+        if read.is_err() {
+            // Start afresh next time, rather than trusting where it was left
+            *reader = None;
+        }
+        read
+    }
+
+    fn read_from(
+        reader: &mut Option<WavReader<BufReader<File>>>,
+        path: &Path,
+        range: Range<usize>,
+    ) -> Result<Vec<f32>, hound::Error> {
+        // It only knows how long the file was when it was opened, so anything written
+        // since needs it opening again
+        let reader = match reader {
+            Some(reader) if reader.duration() as usize >= range.end => reader,
+            stale => stale.insert(WavReader::open(path)?),
+        };
+        let spec = reader.spec();
+        reader.seek(range.start as u32)?;
+        let count = range.len() * spec.channels.max(1) as usize;
+        let samples: Vec<f32> = match spec.sample_format {
+            SampleFormat::Float => reader
+                .samples::<f32>()
+                .take(count)
+                .collect::<Result<_, _>>()?,
+            SampleFormat::Int => reader
+                .samples::<i32>()
+                .take(count)
+                .map(|sample| Ok(WavClip::int_to_f32(sample?, spec.bits_per_sample)))
+                .collect::<Result<_, hound::Error>>()?,
+        };
+        Ok(downmix(&samples, spec.channels).into_owned())
+    }
+}
+
+/// Interleaved frames averaged down to one sample each
+fn downmix(samples: &[f32], channels: u16) -> Cow<'_, [f32]> {
+    match channels {
+        0 | 1 => Cow::Borrowed(samples),
+        channels => Cow::Owned(
+            samples
+                .chunks(channels as usize)
+                .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+                .collect(),
+        ),
+    }
+}
+
+#[derive(Eq, Ord, PartialEq, PartialOrd, Clone, Debug, Serialize)]
 pub struct ClipId(String);
 
 #[derive(Debug, ThisError)]
@@ -24,8 +232,14 @@ pub enum Error {
     ClipIdResolutionFailure(PathBuf),
     #[error("Clip is open read-only: {0}")]
     ReadOnly(ClipId),
+    #[error("Every numbered clip name after {0} is taken")]
+    NoFreeClipId(ClipId),
     #[error("Error with Hound library: {0}")]
     HoundError(#[from] hound::Error),
+    #[error("Error with clip metadata: {0}")]
+    Metadata(#[from] metadata::Error),
+    #[error("{0}")]
+    Salvage(#[from] salvage::Error),
 }
 
 impl ClipId {
@@ -33,17 +247,53 @@ impl ClipId {
         Self(time.format("%Y-%m-%d_%H-%M-%S%.9f").to_string())
     }
 
+    /// Named like a local time clip, with a Z on the end
+    pub fn from_datetimeutc(time: DateTime<Utc>) -> Self {
+        Self(time.format("%Y-%m-%d_%H-%M-%S%.9fZ").to_string())
+    }
+
     pub fn from_path_ref(path: &Path) -> Option<Self> {
         path.file_stem()
-            .map(|os| os.to_str().map(|str| Self(str.to_string())))
-            .flatten()
+            .and_then(|os| os.to_str().map(|str| Self(str.to_string())))
     }
 
     pub fn absolute_path_wav(&self, path: &Path) -> PathBuf {
-        let mut buf = path.to_path_buf();
-        buf.push(self);
-        buf.set_extension("wav");
-        buf
+        // Not set_extension, which would take the fraction of a second off the name
+        path.join(format!("{}.wav", self.0))
+    }
+
+    /// This id if nothing has it yet, or else the first of `id_2`, `id_3`, … that's free.
+    /// Two recordings started together, or a clock stepped back, can land on the same time.
+    pub fn next_free(self, taken: impl Fn(&ClipId) -> bool) -> Result<Self, Error> {
+        if !taken(&self) {
+            return Ok(self);
+        }
+        (2..=u32::MAX)
+            .map(|n| Self(format!("{}_{}", self.0, n)))
+            .find(|id| !taken(id))
+            .ok_or(Error::NoFreeClipId(self))
+    }
+
+    /// When the clip was started, read back from its name. Names with or without the
+    /// fraction of a second, the Z for UTC, or a number on the end are all understood.
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        let name = match self.0.rsplit_once('_') {
+            Some((name, n)) if n.bytes().all(|b| b.is_ascii_digit()) => name,
+            _ => self.0.as_str(),
+        };
+        let (name, utc) = match name.strip_suffix('Z') {
+            Some(name) => (name, true),
+            None => (name, false),
+        };
+        let naive = NaiveDateTime::parse_from_str(name, "%Y-%m-%d_%H-%M-%S%.f").ok()?;
+        if utc {
+            Some(naive.and_utc())
+        } else {
+            Local
+                .from_local_datetime(&naive)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc))
+        }
     }
 }
 
@@ -59,59 +309,278 @@ impl AsRef<Path> for ClipId {
     }
 }
 
-#[derive(Default, Debug)]
+/// A stretch of a clip picked out by dragging across it. It's anchored where the drag
+/// started and its other edge follows the pointer, crossing over the anchor if the pointer
+/// does. Both ends are samples in the selection.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "SavedSelection")]
 pub struct Selection {
-    pub range: Range<usize>,
+    anchor: usize,
+    edge: usize,
 }
 
-impl Selection {
-    pub fn new(a: usize, b: usize) -> Self {
-        Self {
-            range: usize::min(a, b)..usize::max(a, b),
+/// A selection as saved in a clip's metadata, either way it's been saved
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedSelection {
+    Ends {
+        anchor: usize,
+        edge: usize,
+    },
+    /// From before selections had an anchor, as a range with the end not in it
+    Range {
+        range: Range<usize>,
+    },
+}
+
+impl From<SavedSelection> for Selection {
+    fn from(saved: SavedSelection) -> Self {
+        match saved {
+            SavedSelection::Ends { anchor, edge } => Self::new(anchor, edge),
+            SavedSelection::Range { range } => Self::from_range(&range),
         }
     }
+}
+
+impl Selection {
+    /// From the anchor to the edge, whichever way round they are
+    pub fn new(anchor: usize, edge: usize) -> Self {
+        Self { anchor, edge }
+    }
 
-    pub fn update_bounds(&mut self, n: usize) -> Self {
-        self.range.start = 7;
-        if n < self.range.start {
-            Self {
-                range: n..self.range.end,
-            }
-        } else {
-            Self {
-                range: self.range.start..n,
-            }
+    /// The samples in a range, anchored at its start. An empty range selects the sample at
+    /// its start.
+    pub fn from_range(range: &Range<usize>) -> Self {
+        Self::new(range.start, range.end.saturating_sub(1).max(range.start))
+    }
+
+    /// Move the edge, keeping the anchor where it is
+    pub fn extend_to(&mut self, edge: usize) {
+        self.edge = edge;
+    }
+
+    pub fn anchor(&self) -> usize {
+        self.anchor
+    }
+
+    pub fn edge(&self) -> usize {
+        self.edge
+    }
+
+    /// The first sample in the selection
+    pub fn first(&self) -> usize {
+        self.anchor.min(self.edge)
+    }
+
+    /// The last sample in the selection
+    pub fn last(&self) -> usize {
+        self.anchor.max(self.edge)
+    }
+
+    /// The selected samples as a range, for slicing
+    pub fn range(&self) -> Range<usize> {
+        self.first()..self.last() + 1
+    }
+
+    /// Both ends moved by `ratio`, for when the clip's been resampled
+    pub fn rescale(&self, ratio: f64) -> Self {
+        let scale = |position: usize| (position as f64 * ratio).round() as usize;
+        Self::new(scale(self.anchor), scale(self.edge))
+    }
+}
+
+/// The zero crossing closest to position, looking no further than radius samples either
+/// side. A crossing is reported as the index of the first sample past it.
+pub fn nearest_zero_crossing(samples: &[f32], position: usize, radius: usize) -> Option<usize> {
+    let is_crossing =
+        |i: usize| i > 0 && i < samples.len() && (samples[i - 1] < 0.0) != (samples[i] < 0.0);
+    (0..=radius).find_map(|distance| {
+        [
+            position.checked_sub(distance),
+            position.checked_add(distance),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|&i| is_crossing(i))
+    })
+}
+
+/// Keeps a clip's metadata saves in order. Each is numbered while the clip is locked, and
+/// one that's written after a later one is left out rather than putting old metadata back.
+#[derive(Debug, Default)]
+struct SaveOrder {
+    next: AtomicU64,
+    /// The last one written
+    saved: Mutex<u64>,
+}
+
+impl SaveOrder {
+    fn save(&self, order: u64, metadata: &ClipMetadata, path: &Path) -> Result<(), Error> {
+        let mut saved = self.saved.lock();
+        if *saved > order {
+            return Ok(());
         }
+        metadata.save(path)?;
+        *saved = order;
+        Ok(())
+    }
+}
+
+/// A clip's metadata, taken to be saved without holding the clip's lock
+pub struct MetadataSave {
+    order: u64,
+    metadata: ClipMetadata,
+    path: PathBuf,
+    saves: Arc<SaveOrder>,
+}
+
+impl MetadataSave {
+    /// Write it out, unless something newer has been already
+    pub fn save(self) -> Result<(), Error> {
+        self.saves.save(self.order, &self.metadata, &self.path)
     }
 }
 
 pub struct WavClip {
     pub(crate) id: ClipId,
     pub(crate) path: PathBuf,
+    /// One a frame, whatever the channels, so positions are in frames
     pub samples: Samples,
     pub sample_rate: SampleRate,
+    /// How many channels the WAV file has
+    pub channels: u16,
     pub resolution: usize,
-    pub(crate) writer: Option<WavWriter<BufWriter<File>>>,
+    pub(crate) writer: Option<WavFileWriter>,
     pub selection: Option<Selection>,
+    pub metadata: ClipMetadata,
+    saves: Arc<SaveOrder>,
 }
 
 const DEFAULT_RESOLUTION: usize = 256;
+/// How often a recording's WAV file is brought up to date on disk
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Encodes a recording into its WAV file on a thread of its own, so a slow disk holds up
+/// nothing but that thread. Whatever has arrived is written in one go, and flushed every
+/// `FLUSH_INTERVAL`.
+pub(crate) struct WavFileWriter {
+    sender: Option<Sender<Vec<f32>>>,
+    /// Gone once the thread has finished, or stopped on an error
+    thread: Option<JoinHandle<Result<(), hound::Error>>>,
+}
+
+impl WavFileWriter {
+    fn create(path: &Path, spec: WavSpec) -> Result<Self, hound::Error> {
+        let mut writer = WavWriter::create(path, spec)?;
+        let (sender, receiver) = mpsc::channel::<Vec<f32>>();
+        let thread = thread::Builder::new()
+            .name("wav writer".to_string())
+            .spawn(move || {
+                let mut flushed = Instant::now();
+                loop {
+                    match receiver.recv_timeout(FLUSH_INTERVAL) {
+                        Ok(batch) => {
+                            WavClip::encode(&mut writer, &batch)?;
+                            for batch in receiver.try_iter() {
+                                WavClip::encode(&mut writer, &batch)?;
+                            }
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                    if flushed.elapsed() >= FLUSH_INTERVAL {
+                        writer.flush()?;
+                        flushed = Instant::now();
+                    }
+                }
+                writer.finalize()
+            })
+            .map_err(hound::Error::IoError)?;
+        Ok(Self {
+            sender: Some(sender),
+            thread: Some(thread),
+        })
+    }
+
+    /// Queue samples to be written. Fails if the thread stopped on an error, with that error.
+    fn write(&mut self, samples: &[f32]) -> Result<(), hound::Error> {
+        let Some(sender) = &self.sender else {
+            return Err(Self::stopped());
+        };
+        if sender.send(samples.to_vec()).is_err() {
+            self.finish()?;
+            return Err(Self::stopped());
+        }
+        Ok(())
+    }
+
+    /// Write out everything queued and fix up the WAV header
+    fn finish(&mut self) -> Result<(), hound::Error> {
+        self.sender.take();
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(Self::stopped()),
+            None => Ok(()),
+        }
+    }
+
+    fn stopped() -> hound::Error {
+        hound::Error::IoError(io::Error::other("the WAV writer thread has stopped"))
+    }
+}
+
+/// How new recordings are stored in their WAV files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum BitDepth {
+    #[default]
+    #[serde(rename = "16")]
+    Int16,
+    #[serde(rename = "24")]
+    Int24,
+    #[serde(rename = "32-float")]
+    Float32,
+}
+
+impl BitDepth {
+    pub fn wav_spec(&self, sample_rate: u32) -> WavSpec {
+        let (bits_per_sample, sample_format) = match self {
+            BitDepth::Int16 => (16, SampleFormat::Int),
+            BitDepth::Int24 => (24, SampleFormat::Int),
+            BitDepth::Float32 => (32, SampleFormat::Float),
+        };
+        WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample,
+            sample_format,
+        }
+    }
+}
 
 impl WavClip {
     pub fn record_new(id: ClipId, base: &Path, spec: WavSpec) -> Result<Self, Error> {
         let path = id.absolute_path_wav(base);
         debug!("Recording new clip at {:?}", path);
-        let writer = WavWriter::create(path.as_path(), spec)?;
+        let writer = WavFileWriter::create(path.as_path(), spec)?;
 
-        Ok(Self {
+        let clip = Self {
             id,
+            samples: Samples::new(&path, spec.channels),
             path,
-            samples: Default::default(),
             sample_rate: SampleRate(spec.sample_rate),
+            channels: spec.channels,
             resolution: DEFAULT_RESOLUTION, // TODO: I don't know? This is used to limit amplitude scaling in the UI
             writer: Some(writer),
             selection: None,
-        })
+            metadata: ClipMetadata {
+                closed_cleanly: Some(false),
+                ..Default::default()
+            },
+            saves: Default::default(),
+        };
+        // Saved now so a clip that's never finished says so
+        clip.save_metadata()?;
+        Ok(clip)
     }
 
     pub fn from_file(path: &Path) -> Result<Self, Error> {
@@ -120,20 +589,45 @@ impl WavClip {
             Some(id) => {
                 let mut clip = Self {
                     id,
+                    samples: Samples::default(),
                     path: pathbuf,
-                    samples: Default::default(),
                     sample_rate: SampleRate(0),
+                    channels: 1,
                     resolution: DEFAULT_RESOLUTION,
                     writer: None,
                     selection: None,
+                    metadata: Self::load_metadata(path),
+                    saves: Default::default(),
                 };
 
-                let mut reader = WavReader::open(path)?;
-                clip.sample_rate = SampleRate(reader.spec().sample_rate);
-                for sample in reader.samples::<i16>() {
-                    clip.samples.push(Self::i16_to_f32(sample?));
+                if clip.metadata.closed_cleanly == Some(false) {
+                    warn!(
+                        "{} wasn't closed cleanly, the end of it may be missing",
+                        clip.id
+                    );
                 }
-                drop(reader);
+                let (spec, samples, damage) = Self::read_file(path)?;
+                clip.sample_rate = SampleRate(spec.sample_rate);
+                clip.channels = spec.channels;
+                clip.samples = Samples::new(path, spec.channels);
+                clip.samples.extend(&samples);
+                // Damage is found in samples, but the clip counts frames
+                let channels = spec.channels.max(1) as usize;
+                let mut damage: Vec<Damage> = damage
+                    .into_iter()
+                    .map(|damage| Damage {
+                        range: damage.range.start / channels..damage.range.end.div_ceil(channels),
+                        ..damage
+                    })
+                    .collect();
+                for damage in &damage {
+                    warn!(
+                        "{} at sample {}: {}",
+                        clip.id, damage.range.start, damage.description
+                    );
+                }
+                damage.sort_by_key(|damage| damage.range.start);
+                clip.metadata.damaged = damage;
 
                 Ok(clip)
             }
@@ -141,34 +635,443 @@ impl WavClip {
         }
     }
 
+    /// A clip's metadata, or none if its sidecar can't be read. The WAV file is what
+    /// matters, so it isn't left out over its sidecar. One that's garbage is kept aside,
+    /// rather than saved over.
+    fn load_metadata(path: &Path) -> ClipMetadata {
+        ClipMetadata::load(path).unwrap_or_else(|error| {
+            warn!(
+                "Unable to read the metadata for {:?}, starting it afresh: {}",
+                path.as_os_str(),
+                error
+            );
+            if let metadata::Error::Deserialization(_) = error {
+                let sidecar = ClipMetadata::sidecar_path(path);
+                let mut aside = sidecar.clone().into_os_string();
+                aside.push(".damaged");
+                if let Err(error) = std::fs::rename(&sidecar, &aside) {
+                    warn!("Unable to move {:?} aside: {}", sidecar.as_os_str(), error);
+                }
+            }
+            ClipMetadata::default()
+        })
+    }
+
+    /// Every sample in a WAV file, channels interleaved, with whatever's wrong with it. A
+    /// file hound won't read is salvaged, and garbage floats are zeroed.
+    pub(crate) fn read_file(path: &Path) -> Result<(WavSpec, Vec<f32>, Vec<Damage>), Error> {
+        let (spec, mut samples, mut damage) = match Self::read_samples(path) {
+            Ok((spec, samples)) => (spec, samples, Vec::new()),
+            Err(error) => {
+                warn!(
+                    "{:?} is damaged, salvaging what's there: {}",
+                    path.as_os_str(),
+                    error
+                );
+                let salvaged = salvage::read(path)?;
+                (salvaged.spec, salvaged.samples, salvaged.damage)
+            }
+        };
+        if spec.sample_format == SampleFormat::Float {
+            damage.extend(
+                salvage::zero_garbage(&mut samples)
+                    .into_iter()
+                    .map(|run| Damage {
+                        description: format!("{} unreadable samples, zeroed", run.len()),
+                        range: run,
+                    }),
+            );
+        }
+        Ok((spec, samples, damage))
+    }
+
+    /// Every sample in a WAV file, as long as nothing's wrong with it
+    fn read_samples(path: &Path) -> Result<(WavSpec, Vec<f32>), hound::Error> {
+        let mut reader = WavReader::open(path)?;
+        let spec = reader.spec();
+        let samples = match spec.sample_format {
+            SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+            SampleFormat::Int => reader
+                .samples::<i32>()
+                .map(|sample| Ok(Self::int_to_f32(sample?, spec.bits_per_sample)))
+                .collect::<Result<_, hound::Error>>()?,
+        };
+        Ok((spec, samples))
+    }
+
     pub fn id(&self) -> &ClipId {
         &self.id
     }
 
-    pub fn f32_to_i16(sample: f32) -> i16 {
-        (sample * i16::MAX as f32) as i16
+    /// Where the clip's WAV file is
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether samples are still being written to the clip
+    pub fn is_recording(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    pub fn save_metadata(&self) -> Result<(), Error> {
+        let order = self.saves.next.fetch_add(1, Ordering::Relaxed) + 1;
+        self.saves.save(order, &self.metadata, &self.path)
+    }
+
+    /// The metadata as it is now, to save once the clip's lock has been let go of, so
+    /// nothing waiting on the clip waits on the disk too
+    pub fn metadata_save(&self) -> MetadataSave {
+        MetadataSave {
+            order: self.saves.next.fetch_add(1, Ordering::Relaxed) + 1,
+            metadata: self.metadata.clone(),
+            path: self.path.clone(),
+            saves: self.saves.clone(),
+        }
+    }
+
+    pub fn add_marker(&mut self, marker: Marker) -> Result<(), Error> {
+        self.metadata.add_marker(marker);
+        self.save_metadata()
+    }
+
+    /// Swap the markers `replaced` picks out for new ones, saving once for the lot
+    pub fn replace_markers(
+        &mut self,
+        replaced: impl Fn(&Marker) -> bool,
+        markers: impl IntoIterator<Item = Marker>,
+    ) -> Result<(), Error> {
+        self.metadata.markers.retain(|marker| !replaced(marker));
+        for marker in markers {
+            self.metadata.add_marker(marker);
+        }
+        self.save_metadata()
+    }
+
+    /// Remember how the clip is being viewed. Skips the write if nothing changed.
+    pub fn set_view_state(&mut self, state: ViewState) -> Result<(), Error> {
+        if self.metadata.view.as_ref() == Some(&state) {
+            return Ok(());
+        }
+        self.metadata.view = Some(state);
+        self.save_metadata()
+    }
+
+    pub fn set_dial_frequency(&mut self, dial_frequency: Option<u64>) -> Result<(), Error> {
+        if self.metadata.dial_frequency == dial_frequency {
+            return Ok(());
+        }
+        self.metadata.dial_frequency = dial_frequency;
+        self.save_metadata()
+    }
+
+    /// Correct frequencies read off the clip by this many parts per million, or not at all
+    pub fn set_frequency_correction(&mut self, ppm: Option<f64>) -> Result<(), Error> {
+        if self.metadata.frequency_correction == ppm {
+            return Ok(());
+        }
+        self.metadata.frequency_correction = ppm;
+        self.save_metadata()
+    }
+
+    pub fn set_satellite_pass(&mut self, pass: Option<SatellitePass>) -> Result<(), Error> {
+        if self.metadata.satellite == pass {
+            return Ok(());
+        }
+        self.metadata.satellite = pass;
+        self.save_metadata()
+    }
+
+    pub fn set_callsign(&mut self, callsign: Option<String>) -> Result<(), Error> {
+        if self.metadata.callsign == callsign {
+            return Ok(());
+        }
+        self.metadata.callsign = callsign;
+        self.save_metadata()
+    }
+
+    pub fn set_qsos(&mut self, qsos: Vec<Qso>) -> Result<(), Error> {
+        if self.metadata.qsos == qsos {
+            return Ok(());
+        }
+        self.metadata.qsos = qsos;
+        self.save_metadata()
+    }
+
+    /// Note what the rig is tuned to as of the end of what's been recorded. The first
+    /// reading becomes the clip's dial frequency if it doesn't have one.
+    /// Note what the rig's tuned to from here on. It's left to the caller to save, after
+    /// letting go of the clip.
+    pub fn add_rig_reading(&mut self, frequency: u64, mode: impl Into<String>) -> MetadataSave {
+        self.metadata.rig.push(RigReading {
+            position: self.samples.len(),
+            frequency,
+            mode: mode.into(),
+        });
+        self.metadata.dial_frequency.get_or_insert(frequency);
+        self.metadata_save()
+    }
+
+    /// Note that recording was cut short because the input device went away
+    pub fn set_device_lost(&mut self) -> Result<(), Error> {
+        self.metadata.device_lost = true;
+        self.save_metadata()
+    }
+
+    /// Note when the first sample was captured
+    pub fn set_started(&mut self, started: DateTime<Utc>) -> Result<(), Error> {
+        self.metadata.started = Some(started);
+        self.save_metadata()
+    }
+
+    /// Note where the station is, unless the clip already knows where it was recorded
+    pub fn set_location(&mut self, location: Location) -> Result<(), Error> {
+        if self.metadata.location.is_some() {
+            return Ok(());
+        }
+        self.metadata.location = Some(location);
+        self.save_metadata()
+    }
+
+    /// The largest integer sample at this bit depth
+    fn int_max(bits_per_sample: u16) -> f32 {
+        ((1i64 << (bits_per_sample - 1)) - 1) as f32
+    }
+
+    pub fn f32_to_int(sample: f32, bits_per_sample: u16) -> i32 {
+        (sample.clamp(-1.0, 1.0) * Self::int_max(bits_per_sample)) as i32
     }
 
-    pub fn i16_to_f32(sample: i16) -> f32 {
-        sample as f32 / i16::MAX as f32
+    pub fn int_to_f32(sample: i32, bits_per_sample: u16) -> f32 {
+        sample as f32 / Self::int_max(bits_per_sample)
     }
 
+    /// Add frames to the end of the clip, with as many channels interleaved as its WAV file
+    /// has. They're in memory straight away, and reach the WAV file shortly after.
     pub fn write_samples(&mut self, samples: &[f32]) -> Result<(), Error> {
         match &mut self.writer {
             Some(writer) => {
-                // Store in memory
+                writer.write(samples)?;
                 self.samples.extend(samples);
-                // Write to wav file
-                for sample in samples {
-                    writer.write_sample(Self::f32_to_i16(*sample))?;
-                }
-                writer.flush()?;
-                // Report success
                 Ok(())
             }
             None => Err(Error::ReadOnly(self.id.clone())),
         }
     }
+
+    fn encode(
+        writer: &mut WavWriter<BufWriter<File>>,
+        samples: &[f32],
+    ) -> Result<(), hound::Error> {
+        let spec = writer.spec();
+        for &sample in samples {
+            match spec.sample_format {
+                SampleFormat::Float => writer.write_sample(sample)?,
+                SampleFormat::Int => {
+                    writer.write_sample(Self::f32_to_int(sample, spec.bits_per_sample))?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write part of the clip out to a WAV file of its own
+    pub fn export_range(
+        &self,
+        range: Range<usize>,
+        path: &Path,
+        spec: WavSpec,
+    ) -> Result<(), Error> {
+        Self::export_samples(&self.samples.range(range), path, spec)
+    }
+
+    /// Write samples out to a WAV file of their own
+    pub fn export_samples(samples: &[f32], path: &Path, spec: WavSpec) -> Result<(), Error> {
+        let mut writer = WavWriter::create(path, spec)?;
+        for &sample in samples {
+            match spec.sample_format {
+                SampleFormat::Float => writer.write_sample(sample)?,
+                SampleFormat::Int => {
+                    writer.write_sample(Self::f32_to_int(sample, spec.bits_per_sample))?
+                }
+            }
+        }
+        writer.finalize()?;
+        Ok(())
+    }
+
+    /// Finish recording, fixing up the WAV header. The clip is read-only afterwards.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.finish()?;
+            self.metadata.closed_cleanly = Some(true);
+            self.save_metadata()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WavClip {
+    /// A clip dropped while recording still gets its header fixed up
+    fn drop(&mut self) {
+        if let Err(error) = self.finish() {
+            warn!("Error finishing {}: {}", self.id, error);
+        }
+    }
 }
 
 pub type Clip = Arc<RwLock<WavClip>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::scratch_dir;
+
+    #[test]
+    fn dragging_right_selects_from_the_anchor() {
+        let mut selection = Selection::new(100, 100);
+        selection.extend_to(150);
+        assert_eq!(selection.range(), 100..151);
+        assert_eq!(selection.anchor(), 100);
+        assert_eq!(selection.edge(), 150);
+        assert_eq!(selection.range().len(), 51);
+    }
+
+    #[test]
+    fn dragging_left_selects_up_to_the_anchor() {
+        let mut selection = Selection::new(100, 100);
+        selection.extend_to(40);
+        assert_eq!(selection.range(), 40..101);
+        assert_eq!((selection.first(), selection.last()), (40, 100));
+        assert_eq!(selection.anchor(), 100);
+    }
+
+    #[test]
+    fn crossing_over_the_anchor_keeps_it() {
+        let mut selection = Selection::new(100, 100);
+        selection.extend_to(150);
+        selection.extend_to(60);
+        assert_eq!(selection.range(), 60..101);
+        selection.extend_to(120);
+        assert_eq!(selection.range(), 100..121);
+        assert_eq!(selection.anchor(), 100);
+    }
+
+    #[test]
+    fn edge_back_on_the_anchor_selects_one_sample() {
+        let mut selection = Selection::new(100, 150);
+        selection.extend_to(100);
+        assert_eq!(selection.range(), 100..101);
+        assert_eq!(selection.range().len(), 1);
+    }
+
+    #[test]
+    fn from_range_includes_both_ends() {
+        assert_eq!(Selection::from_range(&(10..20)).range(), 10..20);
+        assert_eq!(Selection::from_range(&(10..10)).range(), 10..11);
+    }
+
+    #[test]
+    fn loads_old_range_selections() {
+        let old: Selection = toml::from_str("range = { start = 10, end = 20 }").unwrap();
+        assert_eq!(old, Selection::new(10, 19));
+        let new: Selection = toml::from_str("anchor = 30\nedge = 5").unwrap();
+        assert_eq!(new.range(), 5..31);
+        let saved = toml::to_string(&new).unwrap();
+        assert_eq!(toml::from_str::<Selection>(&saved).unwrap(), new);
+    }
+
+    #[test]
+    fn damaged_clips_load_what_is_there() {
+        let dir = std::env::temp_dir().join(format!("hamshark-damaged-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let id = ClipId::from_datetimeutc(Utc::now());
+        let path = id.absolute_path_wav(&dir);
+        let mut writer = WavWriter::create(&path, BitDepth::Float32.wav_spec(8000)).unwrap();
+        for i in 0..1000 {
+            let sample = if (100..110).contains(&i) {
+                f32::NAN
+            } else {
+                0.25
+            };
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 400 - 2]).unwrap();
+
+        let clip = WavClip::from_file(&path).unwrap();
+        assert_eq!(clip.samples.len(), 899);
+        assert!(clip.samples.range(100..110).iter().all(|s| *s == 0.0));
+        let damaged: Vec<Range<usize>> = clip
+            .metadata
+            .damaged
+            .iter()
+            .map(|d| d.range.clone())
+            .collect();
+        assert_eq!(damaged, vec![100..110, 899..899]);
+        // Saving the metadata for something else doesn't make it stick
+        assert!(clip.metadata.markers.is_empty());
+        clip.metadata.save(&path).unwrap();
+        assert!(
+            WavClip::from_file(&path)
+                .unwrap()
+                .metadata
+                .markers
+                .is_empty()
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn stereo_clips_hold_a_sample_a_frame() {
+        let dir = scratch_dir("stereo");
+        let id = ClipId::from_datetimeutc(Utc::now());
+        let spec = WavSpec {
+            channels: 2,
+            ..BitDepth::Float32.wav_spec(8000)
+        };
+        let mut clip = WavClip::record_new(id.clone(), &dir, spec).unwrap();
+        clip.samples.set_cap(Some(100));
+        let frames: Vec<f32> = (0..1000).flat_map(|i| [i as f32 / 1000.0, 0.5]).collect();
+        clip.write_samples(&frames).unwrap();
+        clip.finish().unwrap();
+        let averaged = |i: usize| (i as f32 / 1000.0 + 0.5) / 2.0;
+        assert_eq!(clip.samples.len(), 1000);
+        // Some of it let go and read back from the file, and some still in memory
+        assert_eq!(clip.samples.range(10..11)[0], averaged(10));
+        assert_eq!(clip.samples.range(990..991)[0], averaged(990));
+        // Across where it was let go, read after the lock would have been
+        let fetched = clip.samples.fetch(5..995).read();
+        assert_eq!(fetched.len(), 990);
+        assert!(
+            fetched
+                .iter()
+                .enumerate()
+                .all(|(i, &x)| x == averaged(i + 5))
+        );
+
+        let loaded = WavClip::from_file(&id.absolute_path_wav(&dir)).unwrap();
+        assert_eq!(loaded.channels, 2);
+        assert_eq!(loaded.samples.len(), 1000);
+        assert_eq!(loaded.samples.range(500..501)[0], averaged(500));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn clips_with_garbage_metadata_still_load() {
+        let dir = scratch_dir("garbage-metadata");
+        let path = ClipId::from_datetimeutc(Utc::now()).absolute_path_wav(&dir);
+        WavClip::export_samples(&[0.25; 100], &path, BitDepth::Float32.wav_spec(8000)).unwrap();
+        let sidecar = ClipMetadata::sidecar_path(&path);
+        std::fs::write(&sidecar, "markers = [[[").unwrap();
+
+        let clip = WavClip::from_file(&path).unwrap();
+        assert_eq!(clip.samples.len(), 100);
+        assert!(clip.metadata.markers.is_empty());
+        // What was there is kept rather than saved over
+        let mut aside = sidecar.into_os_string();
+        aside.push(".damaged");
+        assert_eq!(std::fs::read_to_string(aside).unwrap(), "markers = [[[");
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
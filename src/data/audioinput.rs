@@ -1,29 +1,51 @@
 use cpal::{
-    BufferSize, Device, Host, HostId, StreamConfig, default_host, host_from_id,
+    BufferSize, Device, HostId, SampleRate, StreamConfig, available_hosts, default_host,
+    host_from_id,
     traits::{DeviceTrait, HostTrait},
 };
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use thiserror::Error as ThisError;
 
+/// Frames per callback we ask the input for
+const BUFFER_FRAMES: u32 = 128;
+
+/// A saved audio input configuration, so it can be switched back to by name. Devices are
+/// remembered by name since that's all that's stable between runs.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DevicePreset {
+    pub name: String,
+    pub host: String,
+    pub device: String,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Audio host unavailable: {0}")]
+    HostUnavailable(#[from] cpal::HostUnavailable),
+    #[error("Unable to list input devices: {0}")]
+    Devices(#[from] cpal::DevicesError),
+    #[error("No input device is selected")]
+    NoDevice,
+    #[error("No input configuration is selected")]
+    NoConfig,
+    #[error("{0} isn't available")]
+    NotFound(String),
+}
+
+#[derive(Clone)]
 pub struct AudioInputDevice {
-    pub host: Host,
+    pub host_id: HostId,
     pub device: Device,
     pub config: StreamConfig,
 }
 
-impl Clone for AudioInputDevice {
-    fn clone(&self) -> Self {
-        Self {
-            host: host_from_id(self.host.id()).expect("host id to exist"),
-            device: self.device.clone(),
-            config: self.config.clone(),
-        }
-    }
-}
-
 impl Debug for AudioInputDevice {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AudioInputDevice")
-            .field("host", &self.host.id())
+            .field("host", &self.host_id)
             .field("device", &self.device.name())
             .field("config", &self.config)
             .finish()
@@ -32,12 +54,24 @@ impl Debug for AudioInputDevice {
 
 impl PartialEq for AudioInputDevice {
     fn eq(&self, other: &Self) -> bool {
-        self.host.id() == other.host.id()
+        self.host_id == other.host_id
             && self.device.name() == other.device.name()
             && self.config == other.config
     }
 }
 
+impl AudioInputDevice {
+    pub fn to_preset(&self, name: &str) -> DevicePreset {
+        DevicePreset {
+            name: name.to_string(),
+            host: self.host_id.name().to_string(),
+            device: self.device.name().unwrap_or_default(),
+            channels: self.config.channels,
+            sample_rate: self.config.sample_rate.0,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct AudioInputDeviceBuilder {
     pub host_id: HostId,
@@ -57,13 +91,10 @@ impl Default for AudioInputDeviceBuilder {
     }
 }
 
-#[derive(Debug)]
-pub struct AudioInputBuilderIncomplete;
-
 impl From<AudioInputDevice> for AudioInputDeviceBuilder {
     fn from(value: AudioInputDevice) -> Self {
         AudioInputDeviceBuilder {
-            host_id: value.host.id(),
+            host_id: value.host_id,
             device: Some(value.device.clone()),
             config: Some(value.config.clone()),
         }
@@ -72,8 +103,7 @@ impl From<AudioInputDevice> for AudioInputDeviceBuilder {
 
 impl AudioInputDeviceBuilder {
     pub fn get_default_device(&self) -> Option<Device> {
-        let host = host_from_id(self.host_id).expect("the host ID to have been set sensibly");
-        host.default_input_device()
+        host_from_id(self.host_id).ok()?.default_input_device()
     }
     pub fn with_default_device(mut self) -> Self {
         self.device = self.get_default_device();
@@ -81,44 +111,78 @@ impl AudioInputDeviceBuilder {
     }
 
     pub fn get_default_config(&self) -> Option<StreamConfig> {
-        self.device.clone().map(|device| {
-            let mut config = device
-                .default_input_config()
-                .expect("device has not default input config")
-                .config();
-            config.buffer_size = BufferSize::Fixed(128);
-            config
-        })
+        let mut config = self.device.as_ref()?.default_input_config().ok()?.config();
+        config.buffer_size = BufferSize::Fixed(BUFFER_FRAMES);
+        Some(config)
     }
     pub fn with_default_config(mut self) -> Self {
         self.config = self.get_default_config();
         self
     }
 
-    pub fn input_devices(&self) -> Vec<Device> {
-        let host = host_from_id(self.host_id).expect("host must be set at this point");
-        host.input_devices()
-            .expect("host must have some input devices")
-            .collect()
+    /// Select the host's input device with this name, if there is one
+    pub fn with_device_named(mut self, name: &str) -> Option<Self> {
+        self.device = self
+            .input_devices()
+            .ok()?
+            .into_iter()
+            .find(|device| device.name().is_ok_and(|device_name| device_name == name));
+        self.device.as_ref()?;
+        Some(self.with_default_config())
+    }
+
+    /// Select an input by host and device name, at the given sample rate. Either name can be
+    /// empty for the default.
+    pub fn from_names(host: &str, device: &str, sample_rate: u32) -> Result<Self, Error> {
+        let mut builder = Self::default();
+        if !host.is_empty() {
+            builder.host_id = available_hosts()
+                .into_iter()
+                .find(|host_id| host_id.name() == host)
+                .ok_or_else(|| Error::NotFound(host.to_string()))?;
+            builder = builder.with_default_device().with_default_config();
+        }
+        if !device.is_empty() {
+            builder = builder
+                .with_device_named(device)
+                .ok_or_else(|| Error::NotFound(device.to_string()))?;
+        }
+        if let Some(config) = &mut builder.config {
+            config.sample_rate = SampleRate(sample_rate);
+        }
+        Ok(builder)
+    }
+
+    /// Select the host, device and config a preset describes, if the device is still around
+    pub fn from_preset(preset: &DevicePreset) -> Option<Self> {
+        let host_id = available_hosts()
+            .into_iter()
+            .find(|host_id| host_id.name() == preset.host)?;
+        let builder = Self {
+            host_id,
+            device: None,
+            config: None,
+        }
+        .with_device_named(&preset.device)?;
+        Some(Self {
+            config: Some(StreamConfig {
+                channels: preset.channels,
+                sample_rate: SampleRate(preset.sample_rate),
+                buffer_size: BufferSize::Fixed(BUFFER_FRAMES),
+            }),
+            ..builder
+        })
+    }
+
+    pub fn input_devices(&self) -> Result<Vec<Device>, Error> {
+        Ok(host_from_id(self.host_id)?.input_devices()?.collect())
     }
 
-    pub fn build(&self) -> Result<AudioInputDevice, AudioInputBuilderIncomplete> {
-        let host = match host_from_id(self.host_id) {
-            Ok(host) => host,
-            Err(_) => return Result::Err(AudioInputBuilderIncomplete {}),
-        };
-        let device = match self.device.clone() {
-            Some(device) => device,
-            None => return Result::Err(AudioInputBuilderIncomplete {}),
-        };
-        let config = match self.config.clone() {
-            Some(config) => config,
-            None => return Result::Err(AudioInputBuilderIncomplete {}),
-        };
+    pub fn build(&self) -> Result<AudioInputDevice, Error> {
         Ok(AudioInputDevice {
-            host,
-            device,
-            config,
+            host_id: self.host_id,
+            device: self.device.clone().ok_or(Error::NoDevice)?,
+            config: self.config.clone().ok_or(Error::NoConfig)?,
         })
     }
 }
@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, fmt};
+
+/// How successive spectra are averaged together
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AveragingMode {
+    #[default]
+    Off,
+    /// Each spectrum pulls the average 1/N of the way towards it
+    Exponential,
+    /// The plain mean of the last N spectra
+    Boxcar,
+}
+
+impl AveragingMode {
+    pub const ALL: [AveragingMode; 3] = [
+        AveragingMode::Off,
+        AveragingMode::Exponential,
+        AveragingMode::Boxcar,
+    ];
+}
+
+impl fmt::Display for AveragingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AveragingMode::Off => "Off",
+            AveragingMode::Exponential => "Exponential",
+            AveragingMode::Boxcar => "Boxcar",
+        })
+    }
+}
+
+/// Averaging over time and smoothing across frequency, so weak steady signals stand out
+/// from the noise
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Averaging {
+    pub mode: AveragingMode,
+    /// Spectra averaged together
+    pub frames: usize,
+    /// Bins either side each bin is smoothed with, none for no smoothing
+    pub smoothing: usize,
+}
+
+impl Default for Averaging {
+    fn default() -> Self {
+        Self {
+            mode: Default::default(),
+            frames: 4,
+            smoothing: 0,
+        }
+    }
+}
+
+/// Averages a run of spectra, in dB, as they come
+pub struct Averager {
+    averaging: Averaging,
+    /// The last N spectra, for the boxcar
+    history: VecDeque<Vec<f32>>,
+    /// The running average, for the exponential
+    average: Vec<f32>,
+}
+
+impl Averager {
+    pub fn new(averaging: Averaging) -> Self {
+        Self {
+            averaging,
+            history: VecDeque::new(),
+            average: Vec::new(),
+        }
+    }
+
+    pub fn averaging(&self) -> Averaging {
+        self.averaging
+    }
+
+    /// Forget the spectra seen so far
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.average.clear();
+    }
+
+    /// Smooth a spectrum and fold it into the average, giving back the average so far
+    pub fn push(&mut self, spectrum: Vec<f32>) -> Vec<f32> {
+        let spectrum = smooth(spectrum, self.averaging.smoothing);
+        let frames = self.averaging.frames.max(1);
+        match self.averaging.mode {
+            AveragingMode::Off => spectrum,
+            AveragingMode::Exponential => {
+                if self.average.len() != spectrum.len() {
+                    self.average = spectrum;
+                } else {
+                    let alpha = 1.0 / frames as f32;
+                    for (average, db) in self.average.iter_mut().zip(&spectrum) {
+                        *average += alpha * (db - *average);
+                    }
+                }
+                self.average.clone()
+            }
+            AveragingMode::Boxcar => {
+                if self
+                    .history
+                    .front()
+                    .is_some_and(|first| first.len() != spectrum.len())
+                {
+                    self.history.clear();
+                }
+                self.history.push_back(spectrum);
+                while self.history.len() > frames {
+                    self.history.pop_front();
+                }
+                let mut sum = vec![0.0; self.history[0].len()];
+                for spectrum in &self.history {
+                    for (total, db) in sum.iter_mut().zip(spectrum) {
+                        *total += db;
+                    }
+                }
+                let count = self.history.len() as f32;
+                sum.iter_mut().for_each(|total| *total /= count);
+                sum
+            }
+        }
+    }
+}
+
+/// The mean of each bin and `radius` bins either side, fewer at the edges
+fn smooth(spectrum: Vec<f32>, radius: usize) -> Vec<f32> {
+    if radius == 0 || spectrum.len() < 2 {
+        return spectrum;
+    }
+    let mut prefix = Vec::with_capacity(spectrum.len() + 1);
+    prefix.push(0.0f64);
+    for db in &spectrum {
+        prefix.push(prefix[prefix.len() - 1] + *db as f64);
+    }
+    (0..spectrum.len())
+        .map(|bin| {
+            let low = bin.saturating_sub(radius);
+            let high = (bin + radius + 1).min(spectrum.len());
+            ((prefix[high] - prefix[low]) / (high - low) as f64) as f32
+        })
+        .collect()
+}
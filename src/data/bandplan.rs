@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, ops::Range};
+
+/// IARU region, each of which publishes its own band plan
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Region {
+    /// Europe, Africa, the Middle East and northern Asia
+    One,
+    /// The Americas
+    #[default]
+    Two,
+    /// The rest of Asia and the Pacific
+    Three,
+}
+
+impl Region {
+    pub const ALL: [Region; 3] = [Region::One, Region::Two, Region::Three];
+
+    /// The HF band plan for the region
+    pub fn segments(&self) -> &'static [Segment] {
+        match self {
+            Region::One => REGION_1,
+            Region::Two => REGION_2,
+            Region::Three => REGION_3,
+        }
+    }
+
+    /// Segments overlapping a range of RF frequencies, in Hz
+    pub fn segments_in(&self, range: Range<u64>) -> impl Iterator<Item = &'static Segment> {
+        self.segments()
+            .iter()
+            .filter(move |segment| segment.start_hz < range.end && range.start < segment.end_hz)
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Region::One => write!(f, "IARU Region 1"),
+            Region::Two => write!(f, "IARU Region 2"),
+            Region::Three => write!(f, "IARU Region 3"),
+        }
+    }
+}
+
+/// What a band segment is set aside for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentMode {
+    Cw,
+    /// Narrow band digital modes
+    Digital,
+    /// Phone, and in most places anything else too
+    Phone,
+}
+
+impl Display for SegmentMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SegmentMode::Cw => write!(f, "CW"),
+            SegmentMode::Digital => write!(f, "Digital"),
+            SegmentMode::Phone => write!(f, "Phone"),
+        }
+    }
+}
+
+/// One stretch of a band plan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub band: &'static str,
+    pub start_hz: u64,
+    pub end_hz: u64,
+    pub mode: SegmentMode,
+}
+
+const fn segment(band: &'static str, start_khz: u64, end_khz: u64, mode: SegmentMode) -> Segment {
+    Segment {
+        band,
+        start_hz: start_khz * 1000,
+        end_hz: end_khz * 1000,
+        mode,
+    }
+}
+
+use SegmentMode::{Cw, Digital, Phone};
+
+// These are simplified from the IARU HF band plans. Beacon and DX windows are left out, and
+// the finer divisions of the digital segments are lumped together.
+
+const REGION_1: &[Segment] = &[
+    segment("160m", 1810, 1838, Cw),
+    segment("160m", 1838, 1840, Digital),
+    segment("160m", 1840, 2000, Phone),
+    segment("80m", 3500, 3580, Cw),
+    segment("80m", 3580, 3600, Digital),
+    segment("80m", 3600, 3800, Phone),
+    segment("40m", 7000, 7040, Cw),
+    segment("40m", 7040, 7060, Digital),
+    segment("40m", 7060, 7200, Phone),
+    segment("30m", 10100, 10130, Cw),
+    segment("30m", 10130, 10150, Digital),
+    segment("20m", 14000, 14070, Cw),
+    segment("20m", 14070, 14099, Digital),
+    segment("20m", 14101, 14350, Phone),
+    segment("17m", 18068, 18095, Cw),
+    segment("17m", 18095, 18109, Digital),
+    segment("17m", 18111, 18168, Phone),
+    segment("15m", 21000, 21070, Cw),
+    segment("15m", 21070, 21149, Digital),
+    segment("15m", 21151, 21450, Phone),
+    segment("12m", 24890, 24915, Cw),
+    segment("12m", 24915, 24929, Digital),
+    segment("12m", 24931, 24990, Phone),
+    segment("10m", 28000, 28070, Cw),
+    segment("10m", 28070, 28190, Digital),
+    segment("10m", 28300, 29700, Phone),
+];
+
+const REGION_2: &[Segment] = &[
+    segment("160m", 1800, 1840, Cw),
+    segment("160m", 1840, 1850, Digital),
+    segment("160m", 1850, 2000, Phone),
+    segment("80m", 3500, 3570, Cw),
+    segment("80m", 3570, 3600, Digital),
+    segment("80m", 3600, 4000, Phone),
+    segment("40m", 7000, 7040, Cw),
+    segment("40m", 7040, 7060, Digital),
+    segment("40m", 7060, 7300, Phone),
+    segment("30m", 10100, 10130, Cw),
+    segment("30m", 10130, 10150, Digital),
+    segment("20m", 14000, 14070, Cw),
+    segment("20m", 14070, 14099, Digital),
+    segment("20m", 14101, 14350, Phone),
+    segment("17m", 18068, 18095, Cw),
+    segment("17m", 18095, 18109, Digital),
+    segment("17m", 18111, 18168, Phone),
+    segment("15m", 21000, 21070, Cw),
+    segment("15m", 21070, 21149, Digital),
+    segment("15m", 21151, 21450, Phone),
+    segment("12m", 24890, 24915, Cw),
+    segment("12m", 24915, 24929, Digital),
+    segment("12m", 24931, 24990, Phone),
+    segment("10m", 28000, 28070, Cw),
+    segment("10m", 28070, 28190, Digital),
+    segment("10m", 28300, 29700, Phone),
+];
+
+const REGION_3: &[Segment] = &[
+    segment("160m", 1800, 1830, Cw),
+    segment("160m", 1830, 1840, Digital),
+    segment("160m", 1840, 2000, Phone),
+    segment("80m", 3500, 3535, Cw),
+    segment("80m", 3535, 3540, Digital),
+    segment("80m", 3540, 3900, Phone),
+    segment("40m", 7000, 7025, Cw),
+    segment("40m", 7025, 7040, Digital),
+    segment("40m", 7040, 7300, Phone),
+    segment("30m", 10100, 10130, Cw),
+    segment("30m", 10130, 10150, Digital),
+    segment("20m", 14000, 14070, Cw),
+    segment("20m", 14070, 14099, Digital),
+    segment("20m", 14101, 14350, Phone),
+    segment("17m", 18068, 18095, Cw),
+    segment("17m", 18095, 18109, Digital),
+    segment("17m", 18111, 18168, Phone),
+    segment("15m", 21000, 21070, Cw),
+    segment("15m", 21070, 21149, Digital),
+    segment("15m", 21151, 21450, Phone),
+    segment("12m", 24890, 24915, Cw),
+    segment("12m", 24915, 24929, Digital),
+    segment("12m", 24931, 24990, Phone),
+    segment("10m", 28000, 28070, Cw),
+    segment("10m", 28070, 28190, Digital),
+    segment("10m", 28300, 29700, Phone),
+];
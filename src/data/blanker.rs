@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, fmt};
+
+/// How a blanked impulse is filled in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlankingMode {
+    /// Silence it
+    Gate,
+    /// Draw a straight line across it, from the sample before to the sample after
+    #[default]
+    Interpolate,
+}
+
+impl fmt::Display for BlankingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BlankingMode::Gate => "Gate",
+            BlankingMode::Interpolate => "Interpolate",
+        })
+    }
+}
+
+/// Cutting short loud impulses, like ignition and power line noise, out of what's recorded
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BlankerSettings {
+    pub enabled: bool,
+    pub mode: BlankingMode,
+    /// How far above the average level a sample has to be to start an impulse, in dB
+    pub threshold_db: f64,
+    /// Anything loud for longer than this is signal, not an impulse
+    pub max_width_ms: f64,
+    /// Blanked either side of an impulse as well, for its leading and trailing edges
+    pub guard_ms: f64,
+}
+
+impl Default for BlankerSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: Default::default(),
+            threshold_db: 20.0,
+            max_width_ms: 5.0,
+            guard_ms: 0.5,
+        }
+    }
+}
+
+/// What the blanker is called in the pipeline
+pub const ELEMENT_NAME: &str = "Noise blanker";
+/// How long the average level takes to follow a change, in seconds
+const AVERAGE_SECONDS: f64 = 0.05;
+/// Levels below this never count as impulses, so silence doesn't trip the blanker
+const MIN_LEVEL: f64 = 1e-5;
+
+/// Finds impulses in interleaved audio and blanks them. The output runs a few milliseconds
+/// behind the input, so the whole of an impulse is seen before it's blanked.
+pub struct NoiseBlanker {
+    mode: BlankingMode,
+    channels: usize,
+    threshold: f64,
+    max_width: usize,
+    guard: usize,
+    /// Frames held back, oldest first
+    delay: VecDeque<f32>,
+    delay_frames: usize,
+    /// The frame being looked at, counting the silence the delay starts out with
+    position: usize,
+    /// Mean level of what isn't an impulse
+    average: Option<f64>,
+    alpha: f64,
+    /// First and last loud frames of the impulse going on, if there is one
+    impulse: Option<(usize, usize)>,
+    /// The last loud frame of a signal, loud for too long to be an impulse
+    signal: Option<usize>,
+}
+
+impl NoiseBlanker {
+    pub fn new(settings: &BlankerSettings, sample_rate: u32, channels: u16) -> Self {
+        let frames = |ms: f64| (ms.max(0.0) * sample_rate as f64 / 1000.0).round() as usize;
+        let max_width = frames(settings.max_width_ms).max(1);
+        let guard = frames(settings.guard_ms).min(max_width);
+        let channels = channels.max(1) as usize;
+        // Room for the guard before, the impulse, the quiet that ends it and a frame either
+        // side to interpolate from
+        let delay_frames = 2 * max_width + guard + 2;
+        Self {
+            mode: settings.mode,
+            channels,
+            threshold: 10f64.powf(settings.threshold_db / 20.0),
+            max_width,
+            guard,
+            delay: std::iter::repeat_n(0.0, delay_frames * channels).collect(),
+            delay_frames,
+            position: delay_frames,
+            average: None,
+            alpha: 1.0 / (AVERAGE_SECONDS * sample_rate as f64).max(1.0),
+            impulse: None,
+            signal: None,
+        }
+    }
+
+    /// How far the output runs behind the input, in frames
+    pub fn delay(&self) -> usize {
+        self.delay_frames
+    }
+
+    /// Blank the impulses in a block, in place, returning how many were finished in it
+    pub fn process(&mut self, block: &mut [f32]) -> usize {
+        let mut blanked = 0;
+        for frame in block.chunks_exact_mut(self.channels) {
+            let level = frame.iter().fold(0.0f32, |acc, x| acc.max(x.abs())) as f64;
+            let average = *self.average.get_or_insert(level);
+            let loud = level > self.threshold * average.max(MIN_LEVEL);
+            self.delay.extend(frame.iter());
+
+            // An impulse or a signal lasts until it's been quiet for as long as an impulse
+            // can be, so the quiet moments of a loud signal don't break it into impulses
+            if self
+                .signal
+                .is_some_and(|last| self.position > last + self.max_width)
+            {
+                self.signal = None;
+            }
+            if loud {
+                if self.signal.is_some() {
+                    self.signal = Some(self.position);
+                } else {
+                    let (first, _) = *self.impulse.get_or_insert((self.position, self.position));
+                    if self.position - first >= self.max_width {
+                        self.impulse = None;
+                        self.signal = Some(self.position);
+                    } else {
+                        self.impulse = Some((first, self.position));
+                    }
+                }
+            }
+            if let Some((first, last)) = self.impulse
+                && self.position > last + self.max_width
+            {
+                self.impulse = None;
+                self.blank(first.saturating_sub(self.guard), last + self.guard);
+                blanked += 1;
+            }
+            // Keep impulses out of the average, but let it follow a signal that comes up
+            if !loud || self.signal.is_some() {
+                self.average = Some(average + self.alpha * (level - average));
+            }
+
+            for sample in frame.iter_mut() {
+                *sample = self.delay.pop_front().unwrap_or_default();
+            }
+            self.position += 1;
+        }
+        blanked
+    }
+
+    /// Fill in frames `first..=last` between the ones either side. The delay runs from the
+    /// frame `delay_frames` back to the current one, so they're all still in it.
+    fn blank(&mut self, first: usize, last: usize) {
+        let front = self.position - self.delay_frames;
+        let first = first.max(front + 1);
+        let last = last.min(self.position - 1);
+        if last < first {
+            return;
+        }
+        let index = |frame: usize| (frame - front) * self.channels;
+        for channel in 0..self.channels {
+            let before = self.delay[index(first - 1) + channel];
+            let after = self.delay[index(last + 1) + channel];
+            let span = (last + 2 - first) as f32;
+            for frame in first..=last {
+                self.delay[index(frame) + channel] = match self.mode {
+                    BlankingMode::Gate => 0.0,
+                    BlankingMode::Interpolate => {
+                        let t = (frame + 1 - first) as f32 / span;
+                        before + t * (after - before)
+                    }
+                };
+            }
+        }
+    }
+}
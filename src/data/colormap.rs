@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+/// How many evenly spaced colors each map is built from
+pub const STOPS: usize = 5;
+
+/// How a level from 0 (quietest) to 1 (loudest) is colored on the waterfall
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Colormap {
+    #[default]
+    Grayscale,
+    Inferno,
+    Viridis,
+}
+
+impl Colormap {
+    /// The colors at 0, 1/4, 1/2, 3/4 and 1, each channel from 0 to 1. The GPU waterfall
+    /// interpolates between these the same way `rgb` does.
+    pub fn stops(&self) -> [[f32; 3]; STOPS] {
+        match self {
+            Colormap::Grayscale => [
+                [0.0, 0.0, 0.0],
+                [0.25, 0.25, 0.25],
+                [0.5, 0.5, 0.5],
+                [0.75, 0.75, 0.75],
+                [1.0, 1.0, 1.0],
+            ],
+            Colormap::Inferno => [
+                [0.0, 0.0, 0.016],
+                [0.341, 0.063, 0.431],
+                [0.733, 0.216, 0.329],
+                [0.976, 0.557, 0.035],
+                [0.988, 1.0, 0.643],
+            ],
+            Colormap::Viridis => [
+                [0.267, 0.005, 0.329],
+                [0.231, 0.322, 0.545],
+                [0.129, 0.569, 0.549],
+                [0.369, 0.788, 0.384],
+                [0.993, 0.906, 0.144],
+            ],
+        }
+    }
+
+    /// The color for a level as 8-bit red, green and blue
+    pub fn rgb(&self, level: f32) -> [u8; 3] {
+        let stops = self.stops();
+        let x = level.clamp(0.0, 1.0) * (STOPS - 1) as f32;
+        let i = (x as usize).min(STOPS - 2);
+        let t = x - i as f32;
+        let channel = |c: usize| {
+            let value = stops[i][c] + (stops[i + 1][c] - stops[i][c]) * t;
+            (value * 255.0).round() as u8
+        };
+        [channel(0), channel(1), channel(2)]
+    }
+}
@@ -0,0 +1,271 @@
+use crate::{
+    calibration,
+    data::{audio::Selection, averaging::Averaging, salvage::Damage},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use thiserror::Error as ThisError;
+
+const SIDECAR_EXTENSION: &str = "toml";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error reading clip metadata: {0}")]
+    Read(#[source] io::Error),
+    #[error("Error writing clip metadata: {0}")]
+    Write(#[source] io::Error),
+    #[error("Serialization error writing clip metadata: {0}")]
+    Serialization(#[source] toml::ser::Error),
+    #[error("Deserialization error reading clip metadata: {0}")]
+    Deserialization(#[source] toml::de::Error),
+}
+
+/// A labeled point of interest within a clip
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Marker {
+    /// Sample index the marker points at
+    pub position: usize,
+    pub label: String,
+}
+
+impl Marker {
+    pub fn new(position: usize, label: impl Into<String>) -> Self {
+        Self {
+            position,
+            label: label.into(),
+        }
+    }
+}
+
+/// What the rig was tuned to from some point in a clip on, as read from rig control
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct RigReading {
+    /// Sample index the rig was first seen like this at
+    pub position: usize,
+    /// Dial frequency in Hz
+    pub frequency: u64,
+    /// Mode as the rig reports it, like USB or PKTUSB
+    pub mode: String,
+}
+
+/// A satellite the clip heard a pass of, for tracing its downlink's Doppler shift
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct SatellitePass {
+    /// The satellite's two-line elements, from around when the clip was recorded
+    pub tle: String,
+    /// Downlink frequency in Hz, as it's sent
+    pub downlink: u64,
+}
+
+/// Where the station was, from GPS
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Maidenhead locator to six characters, like EM79ur
+    pub grid: String,
+}
+
+/// Transmissions close enough together to be one conversation
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Qso {
+    /// From the start of the first transmission to the end of the last, in samples
+    pub range: Range<usize>,
+    pub transmissions: Vec<Range<usize>>,
+}
+
+/// How the clip was last being looked at, so reopening a session picks up where we left off
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ViewState {
+    /// Whether the clip's window is open
+    pub open: bool,
+    /// Samples per pixel
+    pub scale: f32,
+    /// Amplitude scale of the sample view
+    pub vscale: f32,
+    /// The first sample in view. Meaningless while following live data.
+    pub offset: usize,
+    pub live: bool,
+    pub selection: Option<Selection>,
+    #[serde(default)]
+    pub loop_points: LoopPoints,
+    /// Whether the whole-clip overview is shown above the detail views
+    #[serde(default)]
+    pub split: bool,
+    /// Whether the selection's statistics are shown
+    #[serde(default)]
+    pub statistics: bool,
+    /// The waterfall's averaging, if it's been changed from the settings
+    #[serde(default)]
+    pub averaging: Option<Averaging>,
+}
+
+/// The A and B ends of the region played on a loop. Kept apart from the selection so
+/// picking something to analyze doesn't lose your place.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct LoopPoints {
+    pub a: Option<usize>,
+    pub b: Option<usize>,
+}
+
+impl LoopPoints {
+    /// The region between the points, once both are set, whichever way round they are
+    pub fn range(&self) -> Option<Range<usize>> {
+        match (self.a, self.b) {
+            (Some(a), Some(b)) if a != b => Some(a.min(b)..a.max(b)),
+            _ => None,
+        }
+    }
+}
+
+// Everything we know about a clip that doesn't fit in the WAV file. Lives in a
+// sidecar file next to the audio so the WAV stays playable by anything.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ClipMetadata {
+    #[serde(default)]
+    pub markers: Vec<Marker>,
+    #[serde(default)]
+    pub view: Option<ViewState>,
+    /// The frequency the receiver was tuned to in Hz. The audio is taken to be upper
+    /// sideband, so audio frequencies are added to this to get RF.
+    #[serde(default)]
+    pub dial_frequency: Option<u64>,
+    /// Every frequency and mode change rig control saw while recording, in order
+    #[serde(default)]
+    pub rig: Vec<RigReading>,
+    /// Where the station was when recording started
+    #[serde(default)]
+    pub location: Option<Location>,
+    /// When the first sample was captured, by the system clock
+    #[serde(default)]
+    pub started: Option<DateTime<Utc>>,
+    /// Parts per million to correct frequencies read off the clip by, as calibrated for the
+    /// input it was recorded from
+    #[serde(default)]
+    pub frequency_correction: Option<f64>,
+    #[serde(default)]
+    pub satellite: Option<SatellitePass>,
+    /// Whose signal the clip is of
+    #[serde(default)]
+    pub callsign: Option<String>,
+    /// The QSOs found in the clip, listed under it as sub-clips
+    #[serde(default)]
+    pub qsos: Vec<Qso>,
+    /// Recording was cut short because the input device went away
+    #[serde(default)]
+    pub device_lost: bool,
+    /// Whether recording finished with the WAV header fixed up. Unset for clips that
+    /// weren't recorded here.
+    #[serde(default)]
+    pub closed_cleanly: Option<bool>,
+    /// What was wrong with the WAV file when it was loaded. Never saved, so a file that's
+    /// repaired stops saying so.
+    #[serde(skip)]
+    pub damaged: Vec<Damage>,
+}
+
+impl ClipMetadata {
+    pub fn sidecar_path(wav_path: &Path) -> PathBuf {
+        wav_path.with_extension(SIDECAR_EXTENSION)
+    }
+
+    /// Load the sidecar for a WAV file. A missing sidecar is not an error, the
+    /// clip just doesn't have any metadata yet.
+    pub fn load(wav_path: &Path) -> Result<Self, Error> {
+        let file = Self::sidecar_path(wav_path);
+        match fs::read_to_string(file) {
+            Ok(serialized) => toml::from_str(serialized.as_str()).map_err(Error::Deserialization),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(Error::Read(error)),
+        }
+    }
+
+    pub fn save(&self, wav_path: &Path) -> Result<(), Error> {
+        let serialized = toml::to_string(self).map_err(Error::Serialization)?;
+        fs::write(Self::sidecar_path(wav_path), serialized).map_err(Error::Write)
+    }
+
+    /// Add a marker, keeping the list ordered by position
+    pub fn add_marker(&mut self, marker: Marker) {
+        let idx = self
+            .markers
+            .partition_point(|existing| existing.position <= marker.position);
+        self.markers.insert(idx, marker);
+    }
+
+    /// The dial frequency at a sample position: the last rig reading at or before it, or
+    /// the clip's dial frequency if rig control didn't see anything
+    pub fn dial_frequency_at(&self, position: usize) -> Option<u64> {
+        let idx = self
+            .rig
+            .partition_point(|reading| reading.position <= position);
+        match idx {
+            0 => self.dial_frequency,
+            idx => Some(self.rig[idx - 1].frequency),
+        }
+    }
+
+    /// Move every sample position by `ratio`, for when the clip's been resampled
+    pub fn rescale(&mut self, ratio: f64) {
+        let scale = |position: usize| (position as f64 * ratio).round() as usize;
+        let scale_range = |range: &Range<usize>| scale(range.start)..scale(range.end);
+        for marker in &mut self.markers {
+            marker.position = scale(marker.position);
+        }
+        for reading in &mut self.rig {
+            reading.position = scale(reading.position);
+        }
+        for qso in &mut self.qsos {
+            qso.range = scale_range(&qso.range);
+            for transmission in &mut qso.transmissions {
+                *transmission = scale_range(transmission);
+            }
+        }
+        if let Some(view) = &mut self.view {
+            view.scale *= ratio as f32;
+            view.offset = scale(view.offset);
+            if let Some(selection) = &mut view.selection {
+                *selection = selection.rescale(ratio);
+            }
+            view.loop_points.a = view.loop_points.a.map(scale);
+            view.loop_points.b = view.loop_points.b.map(scale);
+        }
+    }
+
+    /// A frequency read off the clip, corrected by its calibration if it has one
+    pub fn corrected(&self, hz: f64) -> f64 {
+        match self.frequency_correction {
+            Some(ppm) => calibration::correct(hz, ppm),
+            None => hz,
+        }
+    }
+}
+
+/// What we know about a session as a whole, kept in a file in its directory
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct SessionMetadata {
+    /// Where the station was last seen
+    #[serde(default)]
+    pub location: Option<Location>,
+}
+
+impl SessionMetadata {
+    /// A missing file is not an error, the session just doesn't have any metadata yet
+    pub fn load(file: &Path) -> Result<Self, Error> {
+        match fs::read_to_string(file) {
+            Ok(serialized) => toml::from_str(serialized.as_str()).map_err(Error::Deserialization),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(Error::Read(error)),
+        }
+    }
+
+    pub fn save(&self, file: &Path) -> Result<(), Error> {
+        let serialized = toml::to_string(self).map_err(Error::Serialization)?;
+        fs::write(file, serialized).map_err(Error::Write)
+    }
+}
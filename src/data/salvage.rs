@@ -0,0 +1,219 @@
+//! Getting what samples there are out of WAV files hound won't read, like ones cut off by a
+//! crash, with a header that was never brought up to date, or with garbage in the samples.
+
+use crate::data::audio::WavClip;
+use hound::{SampleFormat, WavSpec};
+use std::{fs, io, ops::Range, path::Path};
+use thiserror::Error as ThisError;
+
+/// Float samples bigger than this are taken to be garbage rather than loud
+const IMPLAUSIBLE: f32 = 1000.0;
+/// WAVE_FORMAT_PCM, WAVE_FORMAT_IEEE_FLOAT and WAVE_FORMAT_EXTENSIBLE
+const PCM: u16 = 1;
+const FLOAT: u16 = 3;
+const EXTENSIBLE: u16 = 0xfffe;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Unable to read WAV file: {0}")]
+    IO(#[from] io::Error),
+    #[error("Not a WAV file")]
+    NotWav,
+    #[error("No fmt chunk before the samples")]
+    NoFormat,
+    #[error("No samples in the file")]
+    NoData,
+    #[error("Can't read {0}-bit samples in format {1:#x}")]
+    Unsupported(u16, u16),
+}
+
+/// Something wrong with the file, and which samples it spoils. Empty where the file just
+/// stops.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Damage {
+    pub range: Range<usize>,
+    pub description: String,
+}
+
+/// What could be read of a damaged WAV file
+#[derive(Debug)]
+pub struct Salvaged {
+    pub spec: WavSpec,
+    pub samples: Vec<f32>,
+    pub damage: Vec<Damage>,
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Read the samples of a WAV file, trusting as little of the header as possible. A data
+/// chunk longer than the file is read to the end of the file, and a partial frame at the
+/// end is dropped.
+pub fn read(path: &Path) -> Result<Salvaged, Error> {
+    let bytes = fs::read(path)?;
+    if bytes.get(0..4) != Some(b"RIFF") || bytes.get(8..12) != Some(b"WAVE") {
+        return Err(Error::NotWav);
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut at = 12;
+    while let (Some(id), Some(size)) = (bytes.get(at..at + 4), u32_at(&bytes, at + 4)) {
+        let body = at + 8;
+        match id {
+            b"fmt " => {
+                let mut tag = u16_at(&bytes, body).ok_or(Error::NoFormat)?;
+                if tag == EXTENSIBLE {
+                    // The real format is at the start of the sub-format GUID
+                    tag = u16_at(&bytes, body + 24).ok_or(Error::NoFormat)?;
+                }
+                let channels = u16_at(&bytes, body + 2).ok_or(Error::NoFormat)?;
+                let sample_rate = u32_at(&bytes, body + 4).ok_or(Error::NoFormat)?;
+                let bits = u16_at(&bytes, body + 14).ok_or(Error::NoFormat)?;
+                format = Some((tag, channels.max(1), sample_rate, bits));
+            }
+            b"data" => {
+                // A header never brought up to date says zero, or the most a u32 holds
+                let promised = size as usize;
+                let end = match promised {
+                    0 | 0xffff_ffff => bytes.len(),
+                    _ => body.saturating_add(promised).min(bytes.len()),
+                };
+                data = Some((body.min(end)..end, promised));
+                break;
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        at = body + size as usize + size as usize % 2;
+    }
+
+    let (tag, channels, sample_rate, bits) = format.ok_or(Error::NoFormat)?;
+    let (range, promised) = data.ok_or(Error::NoData)?;
+    let (sample_format, width) = match (tag, bits) {
+        (PCM, 8 | 16 | 24 | 32) => (SampleFormat::Int, bits as usize / 8),
+        (FLOAT, 32 | 64) => (SampleFormat::Float, bits as usize / 8),
+        _ => return Err(Error::Unsupported(bits, tag)),
+    };
+    let frame = width * channels as usize;
+    let whole = range.len() / frame * frame;
+    let samples = decode(&bytes[range.start..range.start + whole], tag, width, bits);
+
+    let mut damage = Vec::new();
+    if !matches!(promised, 0 | 0xffff_ffff) && promised > whole {
+        damage.push(Damage {
+            range: samples.len()..samples.len(),
+            description: format!(
+                "The file ends here, {} samples short of what its header says",
+                (promised - whole) / width
+            ),
+        });
+    }
+    Ok(Salvaged {
+        spec: WavSpec {
+            channels,
+            sample_rate,
+            // Float64 is read into f32 like the rest
+            bits_per_sample: bits.min(32),
+            sample_format,
+        },
+        samples,
+        damage,
+    })
+}
+
+fn decode(bytes: &[u8], tag: u16, width: usize, bits: u16) -> Vec<f32> {
+    let chunks = bytes.chunks_exact(width);
+    match (tag, width) {
+        (FLOAT, 4) => chunks
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        (FLOAT, _) => chunks
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap_or_default()) as f32)
+            .collect(),
+        // 8-bit samples are the only unsigned ones
+        (_, 1) => chunks
+            .map(|b| WavClip::int_to_f32(b[0] as i32 - 128, bits))
+            .collect(),
+        _ => chunks
+            .map(|b| {
+                // Into the top of an i32 and back down, to sign extend
+                let mut word = [0; 4];
+                word[4 - width..].copy_from_slice(b);
+                let sample = i32::from_le_bytes(word) >> (8 * (4 - width));
+                WavClip::int_to_f32(sample, bits)
+            })
+            .collect(),
+    }
+}
+
+/// Zero the runs of float samples that can't be real, like NaNs and ones far past full
+/// scale. Returns where they were.
+pub fn zero_garbage(samples: &mut [f32]) -> Vec<Range<usize>> {
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for (index, sample) in samples.iter_mut().enumerate() {
+        if sample.is_finite() && sample.abs() <= IMPLAUSIBLE {
+            continue;
+        }
+        *sample = 0.0;
+        match runs.last_mut() {
+            Some(run) if run.end == index => run.end += 1,
+            _ => runs.push(index..index + 1),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::WavWriter;
+
+    #[test]
+    fn cut_off_files_read_up_to_the_cut() {
+        let path =
+            std::env::temp_dir().join(format!("hamshark-salvage-{}.wav", std::process::id()));
+        let spec = WavSpec {
+            channels: 2,
+            sample_rate: 8000,
+            bits_per_sample: 24,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for i in 0..2000 {
+            writer.write_sample((i - 1000) * 4000).unwrap();
+        }
+        writer.finalize().unwrap();
+        // Partway through a frame
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 3000 - 4]).unwrap();
+        assert!(
+            hound::WavReader::open(&path)
+                .unwrap()
+                .samples::<i32>()
+                .any(|s| s.is_err())
+        );
+
+        let salvaged = read(&path).unwrap();
+        assert_eq!(salvaged.spec, spec);
+        assert_eq!(salvaged.samples.len(), 998);
+        for (i, sample) in salvaged.samples.iter().enumerate() {
+            assert_eq!(*sample, WavClip::int_to_f32((i as i32 - 1000) * 4000, 24));
+        }
+        assert_eq!(salvaged.damage.len(), 1);
+        assert_eq!(salvaged.damage[0].range, 998..998);
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn garbage_is_zeroed() {
+        let mut samples = vec![0.5, f32::NAN, f32::INFINITY, 0.25, 1e20, -0.5];
+        assert_eq!(zero_garbage(&mut samples), vec![1..3, 4..5]);
+        assert_eq!(samples, vec![0.5, 0.0, 0.0, 0.25, 0.0, -0.5]);
+    }
+}
@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// The taper applied to each block of samples before it's transformed. A steeper taper leaks
+/// less energy into neighbouring bins at the cost of a wider main lobe.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WindowFunction {
+    Rectangular,
+    #[default]
+    Hann,
+    Hamming,
+    Blackman,
+    /// Four-term Blackman-Harris, for when a strong tone mustn't bury what's near it
+    BlackmanHarris,
+}
+
+impl WindowFunction {
+    /// One coefficient per input sample of a transform of size len
+    pub fn coefficients(&self, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|n| {
+                let phase = 2.0 * PI * n as f32 / len as f32;
+                match self {
+                    WindowFunction::Rectangular => 1.0,
+                    WindowFunction::Hann => 0.5 - 0.5 * phase.cos(),
+                    WindowFunction::Hamming => 0.54 - 0.46 * phase.cos(),
+                    WindowFunction::Blackman => {
+                        0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos()
+                    }
+                    WindowFunction::BlackmanHarris => {
+                        0.35875 - 0.48829 * phase.cos() + 0.14128 * (2.0 * phase).cos()
+                            - 0.01168 * (3.0 * phase).cos()
+                    }
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{
+    config::{DecodeOutputSettings, DecodeProtocol},
+    events::DecodeEvent,
+};
+use log::{info, warn};
+use std::{
+    io::{self, Write},
+    net::{TcpStream, UdpSocket},
+    sync::mpsc::{self, SyncSender, TrySendError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// Lines waiting to go out. Past this, new ones are dropped rather than holding up the
+/// decoders.
+const BACKLOG: usize = 256;
+/// How long to wait before trying a dropped TCP connection again
+const RECONNECT: Duration = Duration::from_secs(5);
+/// Don't let a stuck listener hold up the sending thread for long
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Where the lines go
+enum Destination {
+    Udp(UdpSocket),
+    Tcp {
+        stream: Option<TcpStream>,
+        /// When a connection last failed, to not keep hammering at it
+        failed: Option<Instant>,
+    },
+}
+
+impl Destination {
+    fn open(protocol: DecodeProtocol) -> Result<Self, io::Error> {
+        Ok(match protocol {
+            DecodeProtocol::Udp => Self::Udp(UdpSocket::bind("0.0.0.0:0")?),
+            DecodeProtocol::Tcp => Self::Tcp {
+                stream: None,
+                failed: None,
+            },
+        })
+    }
+
+    fn send(&mut self, address: &str, line: &[u8]) -> Result<(), io::Error> {
+        match self {
+            Self::Udp(socket) => socket.send_to(line, address).map(|_| ()),
+            Self::Tcp { stream, failed } => {
+                if stream.is_none() {
+                    if failed.is_some_and(|failed| failed.elapsed() < RECONNECT) {
+                        return Ok(());
+                    }
+                    let connected = TcpStream::connect(address)
+                        .and_then(|s| s.set_write_timeout(Some(WRITE_TIMEOUT)).map(|()| s));
+                    match connected {
+                        Ok(s) => {
+                            info!("Sending decodes to {}", address);
+                            *stream = Some(s);
+                        }
+                        Err(error) => {
+                            *failed = Some(Instant::now());
+                            return Err(error);
+                        }
+                    }
+                }
+                let Some(s) = stream else {
+                    return Ok(());
+                };
+                s.write_all(line).inspect_err(|_| {
+                    *stream = None;
+                    *failed = Some(Instant::now());
+                })
+            }
+        }
+    }
+}
+
+/// Sends each decoded line on to another program as a line of JSON, over UDP or TCP, so
+/// logging and alerting scripts can follow along. Stops when dropped.
+pub struct DecodeOutput {
+    lines: Option<SyncSender<Vec<u8>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DecodeOutput {
+    pub fn start(settings: &DecodeOutputSettings) -> Result<Self, io::Error> {
+        let mut destination = Destination::open(settings.protocol)?;
+        let address = settings.address.clone();
+        let (lines, receiver) = mpsc::sync_channel::<Vec<u8>>(BACKLOG);
+        let thread = thread::Builder::new()
+            .name("decode output".to_string())
+            .spawn(move || {
+                // Only say so once for each time it stops working
+                let mut failing = false;
+                while let Ok(line) = receiver.recv() {
+                    match destination.send(&address, &line) {
+                        Ok(()) => failing = false,
+                        Err(error) if !failing => {
+                            warn!("Unable to send decodes to {}: {}", address, error);
+                            failing = true;
+                        }
+                        Err(_) => {}
+                    }
+                }
+            })?;
+        info!(
+            "Forwarding decodes to {} over {:?}",
+            settings.address, settings.protocol
+        );
+        Ok(Self {
+            lines: Some(lines),
+            thread: Some(thread),
+        })
+    }
+
+    /// Queue a decode to be sent, without waiting for it to go
+    pub fn send(&self, event: &DecodeEvent) {
+        let Some(lines) = &self.lines else {
+            return;
+        };
+        let mut line = match serde_json::to_vec(event) {
+            Ok(line) => line,
+            Err(error) => {
+                warn!("Unable to serialize a decode: {}", error);
+                return;
+            }
+        };
+        line.push(b'\n');
+        if let Err(TrySendError::Full(_)) = lines.try_send(line) {
+            warn!("Decode output is falling behind, dropping a line");
+        }
+    }
+}
+
+impl Drop for DecodeOutput {
+    fn drop(&mut self) {
+        self.lines.take();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            warn!("Decode output thread panicked");
+        }
+    }
+}
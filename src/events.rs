@@ -0,0 +1,133 @@
+use crate::data::audio::ClipId;
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use rustfft::num_complex::Complex;
+use serde::Serialize;
+use std::{fmt::Display, sync::Arc};
+
+type Callback<T> = Box<dyn Fn(&T) + Send + Sync>;
+/// Samples come with how many channels are interleaved in them
+type SamplesCallback = Box<dyn Fn(&[f32], u16) + Send + Sync>;
+
+/// Something in the recording pipeline went wrong
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorEvent {
+    /// The name of the pipeline element it happened in
+    pub element: &'static str,
+    pub message: String,
+}
+
+/// A line of text decoded from the audio, by a CW, RTTY or PSK decoder
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodeEvent {
+    pub time: DateTime<Utc>,
+    /// Like CW, RTTY or PSK31
+    pub mode: String,
+    /// Where in the audio passband the signal was, in Hz
+    pub audio_frequency: Option<f32>,
+    /// The clip it was decoded from, if it was recorded
+    pub clip: Option<ClipId>,
+    pub text: String,
+}
+
+#[derive(Default)]
+struct Callbacks {
+    samples: Vec<SamplesCallback>,
+    fft: Vec<Callback<[Complex<f32>]>>,
+    clip_started: Vec<Callback<ClipId>>,
+    clip_finished: Vec<Callback<ClipId>>,
+    error: Vec<Callback<ErrorEvent>>,
+    decode: Vec<Callback<DecodeEvent>>,
+}
+
+/// Callbacks to run as things happen during capture, so an embedder can react to them
+/// instead of polling the clips. Samples and FFTs are delivered on the clip writer thread
+/// and errors on whichever thread they happen on, so keep the callbacks quick.
+#[derive(Default, Clone)]
+pub struct Observers {
+    callbacks: Arc<RwLock<Callbacks>>,
+}
+
+impl Observers {
+    /// Each block of samples as it's written to the clip, whole frames with the channels
+    /// interleaved, and how many channels there are
+    pub fn on_samples(&self, callback: impl Fn(&[f32], u16) + Send + Sync + 'static) {
+        self.callbacks.write().samples.push(Box::new(callback));
+    }
+
+    /// The spectrum of each FFT-sized run of recorded samples, windowed as the DSP settings
+    /// say. The FFT is only done while something is listening.
+    pub fn on_fft(&self, callback: impl Fn(&[Complex<f32>]) + Send + Sync + 'static) {
+        self.callbacks.write().fft.push(Box::new(callback));
+    }
+
+    pub fn on_clip_started(&self, callback: impl Fn(&ClipId) + Send + Sync + 'static) {
+        self.callbacks.write().clip_started.push(Box::new(callback));
+    }
+
+    /// Once the clip has been written out and closed
+    pub fn on_clip_finished(&self, callback: impl Fn(&ClipId) + Send + Sync + 'static) {
+        self.callbacks
+            .write()
+            .clip_finished
+            .push(Box::new(callback));
+    }
+
+    pub fn on_error(&self, callback: impl Fn(&ErrorEvent) + Send + Sync + 'static) {
+        self.callbacks.write().error.push(Box::new(callback));
+    }
+
+    /// Each line of text decoded, on the decoder's thread
+    pub fn on_decode(&self, callback: impl Fn(&DecodeEvent) + Send + Sync + 'static) {
+        self.callbacks.write().decode.push(Box::new(callback));
+    }
+
+    pub(crate) fn samples(&self, samples: &[f32], channels: u16) {
+        for callback in &self.callbacks.read().samples {
+            callback(samples, channels);
+        }
+    }
+
+    pub(crate) fn wants_fft(&self) -> bool {
+        !self.callbacks.read().fft.is_empty()
+    }
+
+    pub(crate) fn fft(&self, spectrum: &[Complex<f32>]) {
+        for callback in &self.callbacks.read().fft {
+            callback(spectrum);
+        }
+    }
+
+    pub(crate) fn clip_started(&self, id: &ClipId) {
+        for callback in &self.callbacks.read().clip_started {
+            callback(id);
+        }
+    }
+
+    pub(crate) fn clip_finished(&self, id: &ClipId) {
+        for callback in &self.callbacks.read().clip_finished {
+            callback(id);
+        }
+    }
+
+    pub(crate) fn error(&self, element: &'static str, error: &dyn Display) {
+        let callbacks = self.callbacks.read();
+        if callbacks.error.is_empty() {
+            return;
+        }
+        let event = ErrorEvent {
+            element,
+            message: error.to_string(),
+        };
+        for callback in &callbacks.error {
+            callback(&event);
+        }
+    }
+
+    /// For decoders to hand on each line they decode
+    pub fn decoded(&self, event: &DecodeEvent) {
+        for callback in &self.callbacks.read().decode {
+            callback(event);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! A C API for driving the recorder from other languages. See include/hamshark.h.
+//!
+//! Functions that can fail return 0 on success and -1 on failure, with the reason available
+//! from `hamshark_last_error` on the same thread.
+
+use crate::{
+    HamShark,
+    config::{Configuration, Settings},
+    data::audioinput::AudioInputDeviceBuilder,
+    session::{self, Session},
+    status::State,
+};
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char, c_void},
+    fmt::Display,
+    fs,
+    path::PathBuf,
+    ptr,
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(error: impl Display) {
+    let message = CString::new(error.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn check<T, E: Display>(result: Result<T, E>) -> i32 {
+    match result {
+        Ok(_) => 0,
+        Err(error) => {
+            set_last_error(error);
+            -1
+        }
+    }
+}
+
+/// Borrow a C string as a &str, or None for NULL. Anything that isn't UTF-8 is an error
+/// saying which argument it was, rather than being taken for NULL.
+///
+/// # Safety
+/// `string` must be NULL or a valid NUL-terminated string.
+unsafe fn to_str<'a>(string: *const c_char, argument: &str) -> Result<Option<&'a str>, String> {
+    if string.is_null() {
+        return Ok(None);
+    }
+    unsafe { CStr::from_ptr(string) }
+        .to_str()
+        .map(Some)
+        .map_err(|error| format!("{} isn't UTF-8: {}", argument, error))
+}
+
+/// The status as C sees it
+#[repr(C)]
+pub struct HamSharkStatus {
+    /// 0 idle, 1 recording, 2 paused, 3 failed
+    pub state: i32,
+    pub samples_captured: u64,
+    pub xruns: u64,
+    /// 0 to 1, or -1 when there's no buffer
+    pub buffer_fill: f32,
+}
+
+/// The user data pointer handed back to a sample callback. The caller promises it's fine
+/// to use from the writer thread.
+struct UserData(*mut c_void);
+unsafe impl Send for UserData {}
+unsafe impl Sync for UserData {}
+
+/// Given interleaved samples, `len` of them over `channels` channels. NULL is turned away.
+pub type SampleCallback =
+    Option<extern "C" fn(samples: *const f32, len: usize, channels: u16, user_data: *mut c_void)>;
+
+/// The message for the last failure on this thread, or NULL. Valid until the next failing
+/// call on this thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn hamshark_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Create an engine with the user's settings, recording into `session_dir` (made if it
+/// doesn't exist), or a new session under the configured base directory if it's NULL.
+/// Returns NULL on failure.
+///
+/// # Safety
+/// `session_dir` must be NULL or a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hamshark_new(session_dir: *const c_char) -> *mut HamShark {
+    let session_dir = match unsafe { to_str(session_dir, "session_dir") } {
+        Ok(session_dir) => session_dir.map(PathBuf::from),
+        Err(error) => {
+            set_last_error(error);
+            return ptr::null_mut();
+        }
+    };
+    let opened = Configuration::from_env()
+        .map_err(|error| error.to_string())
+        .and_then(|config| {
+            let settings = Settings::from_file(&config.settings_file_path)
+                .map_err(|error| error.to_string())?;
+            match session_dir {
+                Some(path) => fs::create_dir_all(&path)
+                    .map_err(session::Error::from)
+                    .and_then(|()| Session::open(path, &settings)),
+                None => Session::from_settings(&config, &settings),
+            }
+            .map_err(|error| error.to_string())
+        });
+    match opened {
+        Ok(session) => Box::into_raw(Box::new(HamShark::new(session))),
+        Err(error) => {
+            set_last_error(error);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Stop any recording and free the engine
+///
+/// # Safety
+/// `hamshark` must be NULL or have come from `hamshark_new`, and not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hamshark_free(hamshark: *mut HamShark) {
+    if !hamshark.is_null() {
+        drop(unsafe { Box::from_raw(hamshark) });
+    }
+}
+
+/// Record from the input device called `name`, or the default input if it's NULL
+///
+/// # Safety
+/// `hamshark` must have come from `hamshark_new`. `name` must be NULL or a valid
+/// NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hamshark_configure_device(
+    hamshark: *mut HamShark,
+    name: *const c_char,
+) -> i32 {
+    let hamshark = unsafe { &mut *hamshark };
+    let name = match unsafe { to_str(name, "name") } {
+        Ok(name) => name,
+        Err(error) => {
+            set_last_error(error);
+            return -1;
+        }
+    };
+    let builder = match name {
+        Some(name) => match AudioInputDeviceBuilder::default().with_device_named(name) {
+            Some(builder) => builder,
+            None => {
+                set_last_error(format!("No input device named '{}'", name));
+                return -1;
+            }
+        },
+        None => AudioInputDeviceBuilder::default(),
+    };
+    match builder.build() {
+        Ok(device) => check(hamshark.session_mut().configure(device)),
+        Err(error) => {
+            set_last_error(error);
+            -1
+        }
+    }
+}
+
+/// # Safety
+/// `hamshark` must have come from `hamshark_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hamshark_start(hamshark: *mut HamShark) -> i32 {
+    check(unsafe { &mut *hamshark }.start())
+}
+
+/// # Safety
+/// `hamshark` must have come from `hamshark_new`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hamshark_stop(hamshark: *mut HamShark) -> i32 {
+    check(unsafe { &mut *hamshark }.stop())
+}
+
+/// # Safety
+/// `hamshark` must have come from `hamshark_new` and `status` must point to a
+/// HamSharkStatus to fill in.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hamshark_status(hamshark: *const HamShark, status: *mut HamSharkStatus) {
+    let current = unsafe { &*hamshark }.status();
+    let state = match current.state {
+        State::Idle => 0,
+        State::Recording => 1,
+        State::Paused => 2,
+        State::Failed => 3,
+    };
+    unsafe {
+        *status = HamSharkStatus {
+            state,
+            samples_captured: current.samples_captured as u64,
+            xruns: current.xruns as u64,
+            buffer_fill: current.buffer_fill.unwrap_or(-1.0),
+        }
+    };
+}
+
+/// Call `callback` with each block of samples as it's written, whole frames with the
+/// channels interleaved. It runs on the clip writer thread, so it should be quick. Fails if
+/// `callback` is NULL.
+///
+/// # Safety
+/// `hamshark` must have come from `hamshark_new`. `user_data` is passed back untouched and
+/// must stay valid, and be safe to use from another thread, for as long as the engine lives.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hamshark_on_samples(
+    hamshark: *mut HamShark,
+    callback: SampleCallback,
+    user_data: *mut c_void,
+) -> i32 {
+    let Some(callback) = callback else {
+        set_last_error("callback is NULL");
+        return -1;
+    };
+    let user_data = UserData(user_data);
+    unsafe { &*hamshark }.on_samples(move |samples, channels| {
+        let user_data = &user_data;
+        callback(samples.as_ptr(), samples.len(), channels, user_data.0)
+    });
+    0
+}
@@ -0,0 +1,73 @@
+use parking_lot::Mutex;
+use rustfft::{Fft, FftNum, FftPlanner, num_complex::Complex};
+use std::sync::{Arc, LazyLock};
+
+static PLANNER_F32: LazyLock<Mutex<FftPlanner<f32>>> =
+    LazyLock::new(|| Mutex::new(FftPlanner::new()));
+static PLANNER_F64: LazyLock<Mutex<FftPlanner<f64>>> =
+    LazyLock::new(|| Mutex::new(FftPlanner::new()));
+
+/// A number FFTs are done in, with the planner shared by everything that uses it
+pub trait FftSample: FftNum + Default {
+    fn planner() -> &'static Mutex<FftPlanner<Self>>;
+}
+
+impl FftSample for f32 {
+    fn planner() -> &'static Mutex<FftPlanner<Self>> {
+        &PLANNER_F32
+    }
+}
+
+impl FftSample for f64 {
+    fn planner() -> &'static Mutex<FftPlanner<Self>> {
+        &PLANNER_F64
+    }
+}
+
+/// The plan for a forward transform of `size`. The planner keeps every plan it's made, so
+/// asking for a size again just hands back the same one.
+pub fn forward<T: FftSample>(size: usize) -> Arc<dyn Fft<T>> {
+    T::planner().lock().plan_fft_forward(size)
+}
+
+/// The plan for an inverse transform of `size`, unscaled
+pub fn inverse<T: FftSample>(size: usize) -> Arc<dyn Fft<T>> {
+    T::planner().lock().plan_fft_inverse(size)
+}
+
+/// A shared plan with a scratch buffer of its own, so running it again and again doesn't
+/// allocate
+pub struct Transform<T: FftSample> {
+    fft: Arc<dyn Fft<T>>,
+    scratch: Vec<Complex<T>>,
+}
+
+impl<T: FftSample> Transform<T> {
+    pub fn new(fft: Arc<dyn Fft<T>>) -> Self {
+        Self {
+            scratch: vec![Complex::default(); fft.get_inplace_scratch_len()],
+            fft,
+        }
+    }
+
+    pub fn forward(size: usize) -> Self {
+        Self::new(forward(size))
+    }
+
+    pub fn inverse(size: usize) -> Self {
+        Self::new(inverse(size))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fft.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fft.len() == 0
+    }
+
+    /// Transform a buffer of `len` in place
+    pub fn process(&mut self, buffer: &mut [Complex<T>]) {
+        self.fft.process_with_scratch(buffer, &mut self.scratch);
+    }
+}
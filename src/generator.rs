@@ -0,0 +1,139 @@
+use crate::tools::Error;
+use cpal::{
+    Stream, default_host,
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+};
+use parking_lot::Mutex;
+use std::{f64::consts::TAU, sync::Arc};
+
+/// What the signal generator plays
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Signal {
+    /// A single tone, in Hz
+    Tone(f64),
+    /// Two tones of equal level, for testing a transmitter's intermodulation
+    TwoTone(f64, f64),
+}
+
+impl Signal {
+    fn frequencies(&self) -> Vec<f64> {
+        match self {
+            Signal::Tone(frequency) => vec![*frequency],
+            Signal::TwoTone(low, high) => vec![*low, *high],
+        }
+    }
+}
+
+/// Makes the samples of a signal, one at a time
+pub struct Oscillator {
+    /// Phase of each tone, in cycles
+    phases: Vec<f64>,
+    /// How far each tone turns each sample, in cycles
+    increments: Vec<f64>,
+    /// Amplitude of each tone
+    amplitude: f64,
+}
+
+impl Oscillator {
+    /// `level_db` is the peak of the whole signal relative to full scale, so two tones are
+    /// each 6 dB below it
+    pub fn new(signal: Signal, level_db: f64, sample_rate: u32) -> Self {
+        let frequencies = signal.frequencies();
+        let amplitude = 10f64.powf(level_db / 20.0) / frequencies.len() as f64;
+        Self {
+            phases: vec![0.0; frequencies.len()],
+            increments: frequencies
+                .iter()
+                .map(|hz| hz / sample_rate as f64)
+                .collect(),
+            amplitude,
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        let mut sample = 0.0;
+        for (phase, increment) in self.phases.iter_mut().zip(&self.increments) {
+            sample += (TAU * *phase).sin();
+            *phase = (*phase + increment).fract();
+        }
+        (sample * self.amplitude) as f32
+    }
+}
+
+/// Plays a test signal out of the default output device until it's dropped
+pub struct SignalGenerator {
+    stream: Stream,
+    oscillator: Arc<Mutex<Oscillator>>,
+    sample_rate: u32,
+}
+
+impl SignalGenerator {
+    pub fn start(signal: Signal, level_db: f64) -> Result<Self, Error> {
+        let device = default_host()
+            .default_output_device()
+            .ok_or(Error::NoOutputDevice)?;
+        let config = device.default_output_config()?.config();
+        let channels = config.channels as usize;
+        let sample_rate = config.sample_rate.0;
+        let oscillator = Arc::new(Mutex::new(Oscillator::new(signal, level_db, sample_rate)));
+        let stream = device.build_output_stream(
+            &config,
+            {
+                let oscillator = oscillator.clone();
+                move |data: &mut [f32], _info| {
+                    let mut oscillator = oscillator.lock();
+                    for frame in data.chunks_mut(channels) {
+                        frame.fill(oscillator.next_sample());
+                    }
+                }
+            },
+            |err| log::error!("Error generating a signal: {}", Error::from(err)),
+            None,
+        )?;
+        stream.play()?;
+        Ok(Self {
+            stream,
+            oscillator,
+            sample_rate,
+        })
+    }
+
+    /// Play something else, without stopping
+    pub fn set_signal(&self, signal: Signal, level_db: f64) {
+        *self.oscillator.lock() = Oscillator::new(signal, level_db, self.sample_rate);
+    }
+}
+
+impl Drop for SignalGenerator {
+    fn drop(&mut self) {
+        self.stream.pause().ok();
+    }
+}
+
+/// An exponential sine sweep from `start_hz` to `end_hz`, spending as long on each octave.
+/// The ends are faded in and out over a few milliseconds so they don't click.
+pub fn log_sweep(
+    start_hz: f64,
+    end_hz: f64,
+    seconds: f64,
+    level_db: f64,
+    sample_rate: u32,
+) -> Vec<f32> {
+    let length = (seconds * sample_rate as f64) as usize;
+    let amplitude = 10f64.powf(level_db / 20.0);
+    let rate = (end_hz / start_hz).ln();
+    let fade = (sample_rate as usize / 200).min(length / 2).max(1);
+    (0..length)
+        .map(|n| {
+            let t = n as f64 / sample_rate as f64;
+            let phase = start_hz * seconds / rate * ((t / seconds * rate).exp() - 1.0);
+            let edge = n.min(length - 1 - n);
+            let gain = if edge < fade {
+                0.5 - 0.5 * (std::f64::consts::PI * edge as f64 / fade as f64).cos()
+            } else {
+                1.0
+            };
+            (amplitude * gain * (TAU * phase).sin()) as f32
+        })
+        .collect()
+}
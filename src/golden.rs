@@ -0,0 +1,177 @@
+//! Known signals made up from scratch, noise added to a given SNR, and checks that the mode
+//! classifier and the CW speed measurement still get them right. That's all this covers:
+//! nothing here decodes CW, RTTY, PSK or DTMF to text, so there's no decoded text to check.
+
+use crate::{
+    analysis,
+    classify::{Mode, classify},
+    config::KeyerSettings,
+    keyer,
+};
+use std::f64::consts::TAU;
+
+const SAMPLE_RATE: u32 = 8000;
+/// What SNRs are measured in, as in a receiver's SSB passband
+const NOISE_BANDWIDTH: f64 = 2500.0;
+/// The SNRs each signal is tried at, in dB in `NOISE_BANDWIDTH`
+const SNRS: [f64; 3] = [30.0, 10.0, 3.0];
+
+/// Random numbers that come out the same every run, so a failure can be gone back to
+struct Lcg(u64);
+
+impl Lcg {
+    /// Uniform in 0 to 1
+    fn next(&mut self) -> f64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    }
+
+    fn bit(&mut self) -> bool {
+        self.next() < 0.5
+    }
+
+    /// Normally distributed, by Box-Muller
+    fn gaussian(&mut self) -> f64 {
+        (-2.0 * self.next().ln()).sqrt() * (TAU * self.next()).cos()
+    }
+}
+
+/// Add white noise so the signal stands `snr` dB over what of it falls in
+/// `NOISE_BANDWIDTH`
+fn add_noise(signal: &[f64], snr: f64, seed: u64) -> Vec<f32> {
+    // Taken over the signal while it's on, so keying doesn't flatter the noise
+    let on: Vec<f64> = signal.iter().copied().filter(|s| *s != 0.0).collect();
+    let power = on.iter().map(|s| s * s).sum::<f64>() / on.len().max(1) as f64;
+    let nyquist = SAMPLE_RATE as f64 / 2.0;
+    let noise = (power / 10f64.powf(snr / 10.0) * nyquist / NOISE_BANDWIDTH).sqrt();
+    let mut random = Lcg(seed);
+    signal
+        .iter()
+        .map(|s| (s + noise * random.gaussian()) as f32 * 0.25)
+        .collect()
+}
+
+/// Text keyed as CW at `wpm`, with 5 ms edges so it doesn't click, after a word space
+fn cw(text: &str, wpm: f64, tone: f64) -> Vec<f64> {
+    let settings = KeyerSettings {
+        wpm,
+        tone_hz: tone,
+        rise_ms: 5.0,
+        level_db: 0.0,
+    };
+    let unit = (SAMPLE_RATE as f64 * 1.2 / wpm) as usize;
+    let mut samples = vec![0.0; 7 * unit];
+    samples.extend(
+        keyer::key(text, &settings, SAMPLE_RATE)
+            .into_iter()
+            .map(f64::from),
+    );
+    samples
+}
+
+/// 45.45 baud RTTY with a 170 Hz shift, sending random characters with a start bit and
+/// one and a half stop bits
+fn rtty(seconds: f64, mark: f64, seed: u64) -> Vec<f64> {
+    let bit = SAMPLE_RATE as f64 / 45.45;
+    let mut random = Lcg(seed);
+    let mut bits: Vec<(bool, f64)> = Vec::new();
+    while bits.iter().map(|(_, length)| length).sum::<f64>() < seconds * SAMPLE_RATE as f64 {
+        bits.push((false, bit));
+        bits.extend((0..5).map(|_| (random.bit(), bit)));
+        bits.push((true, 1.5 * bit));
+    }
+
+    let mut samples = Vec::new();
+    let mut phase = 0.0f64;
+    let mut end = 0.0;
+    for (is_mark, length) in bits {
+        end += length;
+        let hz = if is_mark { mark } else { mark + 170.0 };
+        while (samples.len() as f64) < end {
+            samples.push(phase.sin());
+            phase = (phase + TAU * hz / SAMPLE_RATE as f64) % TAU;
+        }
+    }
+    samples
+}
+
+/// BPSK31, random bits. Each zero reverses the phase, through a cosine dip in amplitude.
+fn psk31(seconds: f64, tone: f64, seed: u64) -> Vec<f64> {
+    let symbol = SAMPLE_RATE as usize * 32 / 1000;
+    let symbols = (seconds * 31.25) as usize;
+    let mut random = Lcg(seed);
+    let mut samples = Vec::with_capacity(symbols * symbol);
+    let mut sign = 1.0;
+    for _ in 0..symbols {
+        let reverse = !random.bit();
+        for i in 0..symbol {
+            let amplitude = if reverse {
+                sign * (std::f64::consts::PI * i as f64 / symbol as f64).cos()
+            } else {
+                sign
+            };
+            let t = samples.len() as f64 / SAMPLE_RATE as f64;
+            samples.push(amplitude * (TAU * tone * t).sin());
+        }
+        if reverse {
+            sign = -sign;
+        }
+    }
+    samples
+}
+
+/// Check the classifier takes the signal for `mode` at each of the SNRs
+fn assert_classified(signal: &[f64], mode: Mode, name: &str) {
+    for (index, snr) in SNRS.iter().enumerate() {
+        let samples = add_noise(signal, *snr, index as u64 + 1);
+        let classification = classify(&samples, SAMPLE_RATE)
+            .unwrap_or_else(|| panic!("{} at {} dB wasn't classified", name, snr));
+        assert_eq!(
+            classification.best(),
+            mode,
+            "{} at {} dB: {:?}",
+            name,
+            snr,
+            classification
+        );
+    }
+}
+
+#[test]
+fn cw_is_classified_as_cw() {
+    let signal = cw("CQ CQ CQ DE W1AW W1AW K", 20.0, 700.0);
+    assert_classified(&signal, Mode::Cw, "CW");
+}
+
+#[test]
+fn rtty_is_classified_as_rtty() {
+    assert_classified(&rtty(8.0, 2125.0, 7), Mode::Rtty, "RTTY");
+}
+
+#[test]
+fn psk31_is_classified_as_psk() {
+    assert_classified(&psk31(8.0, 1000.0, 11), Mode::Psk, "PSK31");
+}
+
+#[test]
+fn cw_speed_is_measured_through_noise() {
+    for wpm in [12.0, 20.0, 30.0] {
+        let signal = cw("CQ CQ CQ DE W1AW W1AW K", wpm, 600.0);
+        for (index, snr) in SNRS.iter().enumerate() {
+            let samples = add_noise(&signal, *snr, index as u64 + 1);
+            let speed = analysis::cw_speed(&samples, SAMPLE_RATE)
+                .unwrap_or_else(|| panic!("No CW found at {} WPM and {} dB", wpm, snr));
+            assert!(
+                (speed.wpm - wpm).abs() < wpm * 0.1,
+                "{} WPM at {} dB measured {:.1}",
+                wpm,
+                snr,
+                speed.wpm
+            );
+            assert!((speed.tone_hz - 600.0).abs() < 10.0);
+        }
+    }
+}
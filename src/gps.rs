@@ -0,0 +1,258 @@
+use crate::{
+    config::GpsSettings,
+    data::{
+        audio::Clip,
+        metadata::{Location, SessionMetadata},
+    },
+};
+use log::{info, warn};
+use parking_lot::RwLock;
+use serde::Deserialize;
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use thiserror::Error as ThisError;
+
+/// How long to wait for gpsd to connect
+const TIMEOUT: Duration = Duration::from_secs(2);
+/// How often a quiet connection checks whether it's time to stop
+const STOP_POLL: Duration = Duration::from_millis(500);
+/// How long to wait before trying gpsd again after losing it
+const RETRY: Duration = Duration::from_secs(5);
+/// Asks gpsd to stream its reports as JSON
+const WATCH: &[u8] = b"?WATCH={\"enable\":true,\"json\":true};\n";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error talking to gpsd: {0}")]
+    IO(#[from] io::Error),
+    #[error("No address found for gpsd at {0}")]
+    NoAddress(String),
+    #[error("gpsd closed the connection")]
+    Closed,
+}
+
+/// The Maidenhead locator for a position, to six characters
+pub fn grid_square(latitude: f64, longitude: f64) -> String {
+    // Work from the south pole and the antimeridian, staying just inside the far edges
+    let lon = (longitude + 180.0).clamp(0.0, 359.9999);
+    let lat = (latitude + 90.0).clamp(0.0, 179.9999);
+    let letter = |base: u8, n: f64| char::from(base + n as u8);
+    [
+        letter(b'A', lon / 20.0),
+        letter(b'A', lat / 10.0),
+        letter(b'0', lon % 20.0 / 2.0),
+        letter(b'0', lat % 10.0),
+        letter(b'a', lon % 2.0 * 12.0),
+        letter(b'a', lat % 1.0 * 24.0),
+    ]
+    .into_iter()
+    .collect()
+}
+
+/// The middle of a Maidenhead locator of two, four or six characters, as latitude and
+/// longitude
+pub fn grid_square_center(grid: &str) -> Option<(f64, f64)> {
+    let grid = grid.trim().as_bytes();
+    if !matches!(grid.len(), 2 | 4 | 6) {
+        return None;
+    }
+    // Each pair narrows it down from the one before, longitude first
+    let steps = [
+        (b'A', 18, 20.0, 10.0),
+        (b'0', 10, 2.0, 1.0),
+        (b'A', 24, 2.0 / 24.0, 1.0 / 24.0),
+    ];
+    let (mut lon, mut lat) = (-180.0, -90.0);
+    let (mut width, mut height) = (360.0, 180.0);
+    for (pair, (base, count, lon_size, lat_size)) in grid.chunks(2).zip(steps) {
+        let digit = |c: u8| {
+            let n = c.to_ascii_uppercase().wrapping_sub(base);
+            (n < count).then_some(n as f64)
+        };
+        lon += digit(pair[0])? * lon_size;
+        lat += digit(pair[1])? * lat_size;
+        (width, height) = (lon_size, lat_size);
+    }
+    Some((lat + height / 2.0, lon + width / 2.0))
+}
+
+/// The parts of a gpsd report we care about. Position reports are class TPV, and only
+/// have a position once there's a 2D (mode 2) or 3D (mode 3) fix.
+#[derive(Deserialize)]
+struct Report {
+    class: String,
+    #[serde(default)]
+    mode: u8,
+    lat: Option<f64>,
+    lon: Option<f64>,
+}
+
+impl Report {
+    fn location(&self) -> Option<Location> {
+        match (self.class.as_str(), self.mode, self.lat, self.lon) {
+            ("TPV", 2.., Some(latitude), Some(longitude)) => Some(Location {
+                latitude,
+                longitude,
+                grid: grid_square(latitude, longitude),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Tracked {
+    location: Option<Location>,
+    /// The clip being recorded, to stamp with the location
+    clip: Option<Clip>,
+}
+
+impl Tracked {
+    fn stamp_clip(&self) {
+        if let (Some(clip), Some(location)) = (&self.clip, &self.location)
+            && let Err(error) = clip.write().set_location(location.clone())
+        {
+            warn!("Unable to save clip location: {}", error);
+        }
+    }
+}
+
+/// Follows the station's location from gpsd, keeping the session's metadata up to date
+/// and stamping the clip being recorded with where it was made. Stops when dropped.
+pub struct GpsTracker {
+    tracked: Arc<RwLock<Tracked>>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl GpsTracker {
+    /// Start following gpsd, saving the location into the session metadata at
+    /// `session_file`
+    pub fn start(settings: &GpsSettings, session_file: PathBuf) -> Result<Self, io::Error> {
+        let tracked = Arc::new(RwLock::new(Tracked::default()));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let address = settings.address.clone();
+        let thread = thread::Builder::new().name("gps".to_string()).spawn({
+            let tracked = tracked.clone();
+            move || {
+                loop {
+                    let result = Self::follow(&address, &stopped, |location| {
+                        Self::update(&tracked, &session_file, location)
+                    });
+                    match result {
+                        Ok(()) => break,
+                        Err(error) => warn!("Lost gpsd at {}: {}", address, error),
+                    }
+                    match stopped.recv_timeout(RETRY) {
+                        Err(RecvTimeoutError::Timeout) => {}
+                        _ => break,
+                    }
+                }
+            }
+        })?;
+        Ok(Self {
+            tracked,
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
+    /// Read reports from gpsd until told to stop, or something goes wrong
+    fn follow(
+        address: &str,
+        stopped: &Receiver<()>,
+        mut on_location: impl FnMut(Location),
+    ) -> Result<(), Error> {
+        let addr = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::NoAddress(address.to_string()))?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(STOP_POLL))?;
+        stream.write_all(WATCH)?;
+        info!("Following gpsd at {}", address);
+
+        let mut reader = BufReader::new(stream);
+        let mut line = Vec::new();
+        loop {
+            if !matches!(stopped.try_recv(), Err(TryRecvError::Empty)) {
+                return Ok(());
+            }
+            // A timeout leaves what was read so far in the line, to carry on with next time
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => return Err(Error::Closed),
+                Ok(_) => {}
+                Err(error)
+                    if matches!(
+                        error.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            }
+            if let Ok(report) = serde_json::from_slice::<Report>(&line)
+                && let Some(location) = report.location()
+            {
+                on_location(location);
+            }
+            line.clear();
+        }
+    }
+
+    /// Take a new fix. The session metadata is only rewritten when the grid square changes.
+    fn update(tracked: &RwLock<Tracked>, session_file: &Path, location: Location) {
+        let mut tracked = tracked.write();
+        let moved = tracked
+            .location
+            .as_ref()
+            .is_none_or(|last| last.grid != location.grid);
+        tracked.location = Some(location.clone());
+        tracked.stamp_clip();
+        if moved {
+            info!("Station is in {}", location.grid);
+            let metadata = SessionMetadata::load(session_file).map(|mut metadata| {
+                metadata.location = Some(location);
+                metadata
+            });
+            if let Err(error) = metadata.and_then(|metadata| metadata.save(session_file)) {
+                warn!("Unable to save session location: {}", error);
+            }
+        }
+    }
+
+    /// Where the station is, if gpsd has said
+    pub fn location(&self) -> Option<Location> {
+        self.tracked.read().location.clone()
+    }
+
+    /// Stamp this clip with the location, now or once there's a fix. None once it's
+    /// finished recording.
+    pub fn track_clip(&self, clip: Option<Clip>) {
+        let mut tracked = self.tracked.write();
+        tracked.clip = clip;
+        tracked.stamp_clip();
+    }
+}
+
+impl Drop for GpsTracker {
+    fn drop(&mut self) {
+        // Hanging up wakes the thread
+        self.stop.take();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            warn!("GPS thread panicked");
+        }
+    }
+}
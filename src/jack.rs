@@ -0,0 +1,264 @@
+use crate::config::{JackConnection, JackSettings};
+use ::jack::{
+    AsyncClient, AudioIn, Client, ClientOptions, Control, Frames, LatencyType, NotificationHandler,
+    Port, PortFlags, PortSpec, ProcessHandler, ProcessScope, TransportState,
+};
+use log::{info, warn};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+use thiserror::Error as ThisError;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("JACK Error: {0}")]
+    Jack(#[from] ::jack::Error),
+    #[error("JACK xrun, samples were lost")]
+    Xrun,
+    #[error("The JACK server went away: {0}")]
+    Shutdown(String),
+    #[error("The JACK input isn't running")]
+    NotStarted,
+}
+
+/// What the JACK server is up to, for showing alongside the recording
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JackInfo {
+    pub sample_rate: u32,
+    /// Frames per process cycle
+    pub buffer_size: u32,
+    pub transport_rolling: bool,
+    /// Where the transport is, in frames
+    pub transport_frame: u32,
+}
+
+/// Short names for our ports: in_L and in_R for stereo, in_1, in_2… for anything wider
+fn port_names(channels: u16) -> Vec<String> {
+    match channels {
+        1 => vec!["in".to_string()],
+        2 => vec!["in_L".to_string(), "in_R".to_string()],
+        n => (1..=n).map(|i| format!("in_{}", i)).collect(),
+    }
+}
+
+type SampleFn = Box<dyn FnMut(&[f32], Duration) + Send>;
+type ErrorFn = Arc<dyn Fn(Error) + Send + Sync>;
+
+/// Interleaves the ports into one block per cycle
+struct Process {
+    ports: Vec<Port<AudioIn>>,
+    block: Vec<f32>,
+    on_samples: SampleFn,
+    /// How long ago, as far as JACK knows, the samples reached our ports
+    latency: Arc<AtomicU32>,
+    sample_rate: u32,
+}
+
+impl ProcessHandler for Process {
+    fn process(&mut self, _: &Client, scope: &ProcessScope) -> Control {
+        let frames = scope.n_frames() as usize;
+        let channels = self.ports.len();
+        self.block.resize(frames * channels, 0.0);
+        for (channel, port) in self.ports.iter().enumerate() {
+            for (frame, &sample) in port.as_slice(scope).iter().enumerate() {
+                self.block[frame * channels + channel] = sample;
+            }
+        }
+        let latency = self.latency.load(Ordering::Relaxed) as f64 / self.sample_rate as f64;
+        (self.on_samples)(&self.block, Duration::from_secs_f64(latency));
+        Control::Continue
+    }
+
+    fn buffer_size(&mut self, _: &Client, size: Frames) -> Control {
+        self.block.reserve(size as usize * self.ports.len());
+        Control::Continue
+    }
+}
+
+/// Passes trouble on to the recorder
+struct Notifications {
+    on_error: ErrorFn,
+}
+
+impl NotificationHandler for Notifications {
+    unsafe fn shutdown(&mut self, _: ::jack::ClientStatus, reason: &str) {
+        (self.on_error)(Error::Shutdown(reason.to_string()));
+    }
+
+    fn xrun(&mut self, _: &Client) -> Control {
+        (self.on_error)(Error::Xrun);
+        Control::Continue
+    }
+}
+
+/// A JACK client with an input port per channel, named so they're easy to find in a patchbay,
+/// like hamshark:in_L and hamshark:in_R.
+pub struct JackInput {
+    /// Until it's started
+    client: Option<Client>,
+    ports: Vec<Port<AudioIn>>,
+    active: Option<AsyncClient<Notifications, Process>>,
+    settings: JackSettings,
+    sample_rate: u32,
+    latency: Arc<AtomicU32>,
+}
+
+impl JackInput {
+    /// Connect to the JACK server and register our ports. Nothing is connected to them until
+    /// the input is started.
+    pub fn open(settings: &JackSettings) -> Result<Self, Error> {
+        let (client, _) = Client::new(&settings.client_name, ClientOptions::NO_START_SERVER)?;
+        let ports = port_names(settings.channels.max(1))
+            .iter()
+            .map(|name| client.register_port(name, AudioIn::default()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let sample_rate = client.sample_rate();
+        info!(
+            "JACK client {} at {} Hz, {} frames per cycle",
+            client.name(),
+            sample_rate,
+            client.buffer_size()
+        );
+        Ok(Self {
+            client: Some(client),
+            ports,
+            active: None,
+            settings: settings.clone(),
+            sample_rate,
+            latency: Arc::new(AtomicU32::new(0)),
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The ports move into the process handler when started, so this goes by the settings
+    pub fn channels(&self) -> u16 {
+        self.settings.channels.max(1)
+    }
+
+    fn client(&self) -> Option<&Client> {
+        self.active
+            .as_ref()
+            .map(AsyncClient::as_client)
+            .or(self.client.as_ref())
+    }
+
+    /// Start handing interleaved blocks to `on_samples` from JACK's process thread, with how
+    /// long ago they were captured, then restore the saved connections. With none saved, and
+    /// auto connect on, the ports are connected to the system capture ports in order.
+    pub fn start(
+        &mut self,
+        on_samples: impl FnMut(&[f32], Duration) + Send + 'static,
+        on_error: impl Fn(Error) + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        let client = self.client.take().ok_or(Error::NotStarted)?;
+        let ports = std::mem::take(&mut self.ports);
+        let names: Vec<String> = ports
+            .iter()
+            .map(|port| port.name())
+            .collect::<Result<_, _>>()?;
+        let latency = ports
+            .iter()
+            .map(|port| port.get_latency_range(LatencyType::Capture).1)
+            .max()
+            .unwrap_or(0);
+        self.latency.store(latency, Ordering::Relaxed);
+        let channels = ports.len();
+
+        let process = Process {
+            ports,
+            block: Vec::new(),
+            on_samples: Box::new(on_samples),
+            latency: self.latency.clone(),
+            sample_rate: self.sample_rate,
+        };
+        let notifications = Notifications {
+            on_error: Arc::new(on_error),
+        };
+        let active = client.activate_async(notifications, process)?;
+        let client = active.as_client();
+
+        if self.settings.connections.is_empty() {
+            if self.settings.auto_connect {
+                let capture = client.ports(
+                    None,
+                    Some(AudioIn::default().jack_port_type()),
+                    PortFlags::IS_PHYSICAL | PortFlags::IS_OUTPUT,
+                );
+                for (source, port) in capture.iter().zip(&names) {
+                    if let Err(error) = client.connect_ports_by_name(source, port) {
+                        warn!("Unable to connect {} to {}: {}", source, port, error);
+                    }
+                }
+            }
+        } else {
+            for connection in &self.settings.connections {
+                let port = format!("{}:{}", client.name(), connection.port);
+                if let Err(error) = client.connect_ports_by_name(&connection.source, &port) {
+                    warn!(
+                        "Unable to restore connection from {} to {}: {}",
+                        connection.source, port, error
+                    );
+                }
+            }
+        }
+        info!(
+            "Recording from JACK ports {}, {} channels",
+            names.join(", "),
+            channels
+        );
+        self.active = Some(active);
+        Ok(())
+    }
+
+    /// What's connected to our ports right now, to restore next time
+    pub fn connections(&self) -> Vec<JackConnection> {
+        let Some(client) = self.client() else {
+            return Vec::new();
+        };
+        port_names(self.channels())
+            .into_iter()
+            .filter_map(|port| {
+                let full = format!("{}:{}", client.name(), port);
+                client.port_by_name(&full).map(|p| (port, p))
+            })
+            .flat_map(|(port, p)| {
+                p.get_connections()
+                    .into_iter()
+                    .map(move |source| JackConnection {
+                        port: port.clone(),
+                        source,
+                    })
+            })
+            .collect()
+    }
+
+    pub fn info(&self) -> Option<JackInfo> {
+        let client = self.client()?;
+        let transport = client.transport().query().ok();
+        Some(JackInfo {
+            sample_rate: client.sample_rate(),
+            buffer_size: client.buffer_size(),
+            transport_rolling: transport
+                .as_ref()
+                .is_some_and(|t| t.state == TransportState::Rolling),
+            transport_frame: transport.map_or(0, |t| t.pos.frame()),
+        })
+    }
+}
+
+impl Drop for JackInput {
+    fn drop(&mut self) {
+        if let Some(active) = self.active.take()
+            && let Err(error) = active.deactivate()
+        {
+            warn!("Error closing the JACK client: {}", error);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{config::KeyerSettings, tools::Error};
+use cpal::{
+    Stream, default_host,
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+};
+use std::{
+    f64::consts::{PI, TAU},
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+/// Dits and dahs for a character, or None if there's no Morse for it
+pub fn morse(character: char) -> Option<&'static str> {
+    Some(match character.to_ascii_uppercase() {
+        'A' => ".-",
+        'B' => "-...",
+        'C' => "-.-.",
+        'D' => "-..",
+        'E' => ".",
+        'F' => "..-.",
+        'G' => "--.",
+        'H' => "....",
+        'I' => "..",
+        'J' => ".---",
+        'K' => "-.-",
+        'L' => ".-..",
+        'M' => "--",
+        'N' => "-.",
+        'O' => "---",
+        'P' => ".--.",
+        'Q' => "--.-",
+        'R' => ".-.",
+        'S' => "...",
+        'T' => "-",
+        'U' => "..-",
+        'V' => "...-",
+        'W' => ".--",
+        'X' => "-..-",
+        'Y' => "-.--",
+        'Z' => "--..",
+        '0' => "-----",
+        '1' => ".----",
+        '2' => "..---",
+        '3' => "...--",
+        '4' => "....-",
+        '5' => ".....",
+        '6' => "-....",
+        '7' => "--...",
+        '8' => "---..",
+        '9' => "----.",
+        '.' => ".-.-.-",
+        ',' => "--..--",
+        '?' => "..--..",
+        '/' => "-..-.",
+        '=' => "-...-",
+        '+' => ".-.-.",
+        '-' => "-....-",
+        '@' => ".--.-.",
+        _ => return None,
+    })
+}
+
+/// Key down or up, for so many dit units. Characters there's no Morse for are left out.
+fn keying(text: &str) -> Vec<(bool, usize)> {
+    let mut keying = Vec::new();
+    for word in text.split_whitespace() {
+        let characters: Vec<&str> = word.chars().filter_map(morse).collect();
+        if characters.is_empty() {
+            continue;
+        }
+        for (index, character) in characters.iter().enumerate() {
+            if index > 0 {
+                keying.push((false, 3));
+            }
+            for (index, element) in character.chars().enumerate() {
+                if index > 0 {
+                    keying.push((false, 1));
+                }
+                keying.push((true, if element == '-' { 3 } else { 1 }));
+            }
+        }
+        // Every word is followed by a word space, the last one too so it doesn't stop dead
+        keying.push((false, 7));
+    }
+    keying
+}
+
+/// Text keyed as CW sidetone. The unit is timed by PARIS, and each element fades in and out
+/// over the rise time so it doesn't click.
+pub fn key(text: &str, settings: &KeyerSettings, sample_rate: u32) -> Vec<f32> {
+    let unit = (sample_rate as f64 * 1.2 / settings.wpm.max(1.0)) as usize;
+    let rise = (sample_rate as f64 * settings.rise_ms / 1000.0) as usize;
+    let amplitude = 10f64.powf(settings.level_db / 20.0);
+    let mut samples = Vec::new();
+    for (down, units) in keying(text) {
+        let length = units * unit;
+        let edge = rise.min(length / 2);
+        for i in 0..length {
+            let from_edge = i.min(length - 1 - i);
+            let envelope = if !down {
+                0.0
+            } else if from_edge < edge {
+                0.5 - 0.5 * (PI * from_edge as f64 / edge as f64).cos()
+            } else {
+                1.0
+            };
+            let t = samples.len() as f64 / sample_rate as f64;
+            samples.push((amplitude * envelope * (TAU * settings.tone_hz * t).sin()) as f32);
+        }
+    }
+    samples
+}
+
+/// Plays text as CW out of the default output device, once, until it's done or dropped
+pub struct Keyer {
+    stream: Stream,
+    /// Frames played so far, of `length`
+    played: Arc<AtomicUsize>,
+    length: usize,
+}
+
+impl Keyer {
+    pub fn start(text: &str, settings: &KeyerSettings) -> Result<Self, Error> {
+        let device = default_host()
+            .default_output_device()
+            .ok_or(Error::NoOutputDevice)?;
+        let config = device.default_output_config()?.config();
+        let channels = config.channels as usize;
+        let samples = key(text, settings, config.sample_rate.0);
+        let length = samples.len();
+        let played = Arc::new(AtomicUsize::new(0));
+        let stream = device.build_output_stream(
+            &config,
+            {
+                let played = played.clone();
+                move |data: &mut [f32], _info| {
+                    let mut at = played.load(Ordering::Relaxed);
+                    for frame in data.chunks_mut(channels) {
+                        frame.fill(samples.get(at).copied().unwrap_or(0.0));
+                        at = (at + 1).min(samples.len());
+                    }
+                    played.store(at, Ordering::Relaxed);
+                }
+            },
+            |err| log::error!("Error keying: {}", Error::from(err)),
+            None,
+        )?;
+        stream.play()?;
+        Ok(Self {
+            stream,
+            played,
+            length,
+        })
+    }
+
+    /// How much has been sent, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        self.played.load(Ordering::Relaxed) as f32 / self.length.max(1) as f32
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.played.load(Ordering::Relaxed) >= self.length
+    }
+}
+
+impl Drop for Keyer {
+    fn drop(&mut self) {
+        self.stream.pause().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis;
+
+    #[test]
+    fn paris_is_fifty_units_and_reads_back_at_its_speed() {
+        let settings = KeyerSettings {
+            wpm: 20.0,
+            tone_hz: 700.0,
+            ..Default::default()
+        };
+        let unit = 8000 * 12 / 200;
+        assert_eq!(key("PARIS", &settings, 8000).len(), 50 * unit);
+        // Unknown characters and extra spaces make no difference
+        assert_eq!(key("  PA#RIS ~ ", &settings, 8000).len(), 50 * unit);
+
+        let samples = key("CQ CQ CQ DE W1AW W1AW K", &settings, 8000);
+        let speed = analysis::cw_speed(&samples, 8000).expect("No CW found");
+        assert!((speed.wpm - 20.0).abs() < 2.0, "measured {:.1}", speed.wpm);
+        assert!((speed.tone_hz - 700.0).abs() < 10.0);
+    }
+}
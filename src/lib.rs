@@ -0,0 +1,211 @@
+//! Recording a radio's audio into sessions of clips, with or without the GUI. The GUI is
+//! the hamshark-gui crate, built on this.
+//!
+//! [`HamShark`] is the place to start: give it a [`session::Session`] configured with an
+//! input device and start recording.
+
+/// Measurements of a stretch of a clip, like its SNR
+pub mod analysis;
+/// [`asynchronous::AsyncHamShark`], for driving the recorder from tokio
+#[cfg(feature = "async")]
+pub mod asynchronous;
+/// Cataloguing the birdies that keep turning up in a session's recordings
+pub mod birdies;
+/// Measuring how far off frequencies read are, against time-standard stations
+pub mod calibration;
+/// Capturing the blocks an input device delivers, to play back and reproduce bugs with
+pub mod capture;
+/// Finding steady carriers in the spectrum and logging when they're on
+pub mod carriers;
+/// Guessing what mode a signal is
+pub mod classify;
+/// Checking the system clock against a time server
+pub mod clock;
+/// Where things live on disk, and the user's settings
+pub mod config;
+/// Marking contacts logged in contest loggers
+pub mod contest;
+/// Clips, input devices, band plans and the other things recordings are made of
+pub mod data;
+/// Forwarding decoded text to other programs
+pub mod decodes;
+/// Callbacks for what happens while recording
+pub mod events;
+/// The C API, see include/hamshark.h
+pub mod ffi;
+/// Shared FFT plans, so each size is only planned once
+pub mod fft;
+/// Playing test signals out of the sound card
+pub mod generator;
+/// Following the station's location from gpsd
+pub mod gps;
+/// Recording from JACK through ports of our own
+#[cfg(feature = "jack")]
+pub mod jack;
+/// Sending typed text as CW sidetone, for code practice and testing the CW analysis
+pub mod keyer;
+/// Looking up callsigns on HamQTH or QRZ.com
+pub mod lookup;
+/// Logging the noise floor in a band through a session
+pub mod noisefloor;
+/// The status of each stage samples go through on their way into a clip
+pub mod pipeline;
+/// Loading plugins that add sinks, and only sinks, to the pipeline, see
+/// include/hamshark_plugin.h
+#[cfg(feature = "plugins")]
+pub mod plugins;
+/// Reporting how far long jobs over whole clips have got
+pub mod progress;
+/// Replaying an earlier session's clips as if they were being captured now
+pub mod replay;
+/// Converting clips from one sample rate to another
+pub mod resample;
+/// Measuring the frequency response of an audio chain with a sweep
+pub mod response;
+/// Reading the dial frequency and mode from the rig while recording
+pub mod rig;
+/// Predicting satellite passes, for the Doppler shift on their downlinks
+pub mod satellite;
+/// Automating the recorder with Rhai scripts
+#[cfg(feature = "scripting")]
+pub mod scripting;
+/// Splitting long recordings into transmissions and QSOs
+pub mod segments;
+/// [`server::serve`], HTTP and WebSocket remote control
+#[cfg(feature = "server")]
+pub mod server;
+/// A directory of clips, and recording new ones into it
+pub mod session;
+/// [`status::Status`] snapshots of the recorder
+pub mod status;
+/// [`streaming::IcecastSink`], for streaming what's recorded to an Icecast server
+#[cfg(feature = "icecast")]
+pub mod streaming;
+/// Comparing when a transmission reached two recordings, for direction finding
+pub mod tdoa;
+/// Where the time comes from for naming and stamping clips, so tests can set it
+pub mod timesource;
+/// Recording from and monitoring input devices
+pub mod tools;
+/// Finding speech in recordings, to mark it
+pub mod vad;
+/// Receiving audio sent over the network by VBAN
+pub mod vban;
+/// An input device that plays canned samples, for testing recording without a sound card
+pub mod virtualinput;
+/// Watching a recording's input for stalls and for running at the wrong rate
+pub mod watchdog;
+
+/// Checking the mode classifier and CW speed measurement against known signals, through noise
+#[cfg(test)]
+mod golden;
+/// Handing samples between threads without locking
+mod ring;
+/// What the tests share, like somewhere to record into
+#[cfg(test)]
+mod testutil;
+
+use crate::{
+    data::audio::ClipId,
+    events::{ErrorEvent, Observers},
+    session::{Error, Session},
+    status::Status,
+};
+use rustfft::num_complex::Complex;
+
+/// Drives the recording into a session: start a clip, pause and resume it, and stop it
+pub struct HamShark {
+    session: Session,
+}
+
+impl HamShark {
+    /// The session must already be configured with an input device to be able to start
+    pub fn new(session: Session) -> Self {
+        Self { session }
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    pub fn session_mut(&mut self) -> &mut Session {
+        &mut self.session
+    }
+
+    pub fn into_session(self) -> Session {
+        self.session
+    }
+
+    /// Start recording a new clip
+    pub fn start(&mut self) -> Result<(), Error> {
+        self.session.record_new_clip()
+    }
+
+    /// Stop taking in samples. The clip stays open and carries on where it left off when
+    /// resumed.
+    pub fn pause(&self) {
+        if let Some(pipeline) = self.session.pipeline() {
+            pipeline.pause_all();
+        }
+    }
+
+    pub fn resume(&self) {
+        if let Some(pipeline) = self.session.pipeline() {
+            pipeline.resume_all();
+        }
+    }
+
+    /// Finish the clip being recorded, writing out whatever is still buffered
+    pub fn stop(&mut self) -> Result<(), Error> {
+        self.session.stop_recording()
+    }
+
+    /// Finish the clip being recorded and carry on into a new one without stopping the
+    /// input, see [`Session::next_clip`]
+    pub fn next_clip(&mut self) -> Result<ClipId, Error> {
+        self.session.next_clip()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.session.is_recording()
+    }
+
+    pub fn observers(&self) -> &Observers {
+        self.session.observers()
+    }
+
+    /// See [`Observers::on_samples`]
+    pub fn on_samples(&self, callback: impl Fn(&[f32], u16) + Send + Sync + 'static) {
+        self.observers().on_samples(callback);
+    }
+
+    /// See [`Observers::on_fft`]
+    pub fn on_fft(&self, callback: impl Fn(&[Complex<f32>]) + Send + Sync + 'static) {
+        self.observers().on_fft(callback);
+    }
+
+    pub fn on_clip_started(&self, callback: impl Fn(&ClipId) + Send + Sync + 'static) {
+        self.observers().on_clip_started(callback);
+    }
+
+    pub fn on_clip_finished(&self, callback: impl Fn(&ClipId) + Send + Sync + 'static) {
+        self.observers().on_clip_finished(callback);
+    }
+
+    pub fn on_error(&self, callback: impl Fn(&ErrorEvent) + Send + Sync + 'static) {
+        self.observers().on_error(callback);
+    }
+
+    pub fn status(&self) -> Status {
+        self.session.status()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.session.pipeline().is_some_and(|pipeline| {
+            pipeline
+                .elements()
+                .iter()
+                .any(|element| element.is_paused())
+        })
+    }
+}
@@ -0,0 +1,362 @@
+use crate::{
+    config::{LookupService, LookupSettings},
+    contest::element,
+    timesource::SharedTimeSource,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{info, warn};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::Path,
+    sync::{
+        Arc,
+        mpsc::{self, Sender},
+    },
+    thread::{self, JoinHandle},
+};
+use thiserror::Error as ThisError;
+
+/// Where looked-up callsigns are kept between runs, in the cache directory
+const CACHE_FILE: &str = "callsigns.json";
+/// How long a looked-up callsign is good for before asking again
+const CACHE_DAYS: i64 = 30;
+const HAMQTH_URL: &str = "https://www.hamqth.com/xml.php";
+const QRZ_URL: &str = "https://xmldata.qrz.com/xml/current/";
+/// Who we tell the services we are
+const AGENT: &str = "hamshark";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Callsign lookups need hamshark built with the lookup feature")]
+    Unsupported,
+    #[cfg(feature = "lookup")]
+    #[error("Error talking to the lookup service: {0}")]
+    Http(#[from] ureq::Error),
+    #[error("The lookup service said: {0}")]
+    Service(String),
+    #[error("Unexpected response from the lookup service")]
+    Response,
+    /// The session timed out, log in again
+    #[error("The lookup session expired")]
+    Expired,
+}
+
+/// Who a callsign belongs to, as far as the lookup service knows
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CallsignInfo {
+    pub call: String,
+    pub name: String,
+    /// Maidenhead locator
+    pub grid: String,
+    pub country: String,
+    /// When it was looked up
+    pub fetched: DateTime<Utc>,
+}
+
+impl CallsignInfo {
+    /// Name, grid and country, leaving out whatever isn't known
+    pub fn summary(&self) -> String {
+        [self.name.as_str(), &self.grid, &self.country]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Where a lookup has got to
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lookup {
+    Pending,
+    Found(CallsignInfo),
+    NotFound,
+    Failed(String),
+}
+
+/// Looks callsigns up on HamQTH or QRZ.com in the background, remembering what it finds
+/// between runs. Stops when dropped.
+pub struct CallsignLookup {
+    results: Arc<Mutex<HashMap<String, Lookup>>>,
+    requests: Option<Sender<String>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl CallsignLookup {
+    pub fn start(
+        settings: &LookupSettings,
+        cache_dir: &Path,
+        time: &SharedTimeSource,
+    ) -> Result<Self, io::Error> {
+        let cache_file = cache_dir.join(CACHE_FILE);
+        let results = Arc::new(Mutex::new(load_cache(&cache_file, time.now())));
+        let (requests, callsigns) = mpsc::channel::<String>();
+        let mut client = Client {
+            settings: settings.clone(),
+            session: None,
+            time: time.clone(),
+        };
+        let thread = thread::Builder::new()
+            .name("callsign lookup".to_string())
+            .spawn({
+                let results = results.clone();
+                move || {
+                    for call in callsigns {
+                        let lookup = match client.lookup(&call) {
+                            Ok(Some(info)) => Lookup::Found(info),
+                            Ok(None) => Lookup::NotFound,
+                            Err(error) => {
+                                warn!("Unable to look up {}: {}", call, error);
+                                Lookup::Failed(error.to_string())
+                            }
+                        };
+                        let found = matches!(lookup, Lookup::Found(_));
+                        results.lock().insert(call, lookup);
+                        if found {
+                            save_cache(&cache_file, &results.lock());
+                        }
+                    }
+                }
+            })?;
+        if cfg!(feature = "lookup") {
+            info!("Looking up callsigns on {:?}", settings.service);
+        } else {
+            warn!("{}", Error::Unsupported);
+        }
+        Ok(Self {
+            results,
+            requests: Some(requests),
+            thread: Some(thread),
+        })
+    }
+
+    /// What's known about a callsign. The first time it's asked about, it's looked up in the
+    /// background and this says it's pending.
+    pub fn get(&self, callsign: &str) -> Lookup {
+        let call = callsign.trim().to_uppercase();
+        let mut results = self.results.lock();
+        if let Some(lookup) = results.get(&call) {
+            return lookup.clone();
+        }
+        results.insert(call.clone(), Lookup::Pending);
+        if let Some(requests) = &self.requests {
+            let _ = requests.send(call);
+        }
+        Lookup::Pending
+    }
+}
+
+impl Drop for CallsignLookup {
+    fn drop(&mut self) {
+        self.requests.take();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            warn!("Callsign lookup thread panicked");
+        }
+    }
+}
+
+/// Callsigns looked up in the month before `now`
+fn load_cache(path: &Path, now: DateTime<Utc>) -> HashMap<String, Lookup> {
+    let cached: HashMap<String, CallsignInfo> = match fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json).unwrap_or_else(|error| {
+            warn!("Ignoring unreadable callsign cache: {}", error);
+            Default::default()
+        }),
+        Err(_) => Default::default(),
+    };
+    let fresh = now - TimeDelta::days(CACHE_DAYS);
+    cached
+        .into_iter()
+        .filter(|(_, info)| info.fetched > fresh)
+        .map(|(call, info)| (call, Lookup::Found(info)))
+        .collect()
+}
+
+fn save_cache(path: &Path, results: &HashMap<String, Lookup>) {
+    let found: HashMap<&String, &CallsignInfo> = results
+        .iter()
+        .filter_map(|(call, lookup)| match lookup {
+            Lookup::Found(info) => Some((call, info)),
+            _ => None,
+        })
+        .collect();
+    let saved = serde_json::to_string(&found)
+        .map_err(io::Error::other)
+        .and_then(|json| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, json)
+        });
+    if let Err(error) = saved {
+        warn!(
+            "Unable to save callsign cache {:?}: {}",
+            path.as_os_str(),
+            error
+        );
+    }
+}
+
+/// Logs in to the lookup service when it needs to, and asks it about callsigns
+struct Client {
+    settings: LookupSettings,
+    /// The session key from logging in
+    session: Option<String>,
+    /// For when callsigns were fetched
+    time: SharedTimeSource,
+}
+
+impl Client {
+    /// None when the service doesn't know the callsign
+    fn lookup(&mut self, call: &str) -> Result<Option<CallsignInfo>, Error> {
+        // Sessions time out, so if it has, log in again and have another go
+        for _ in 0..2 {
+            let session = match &self.session {
+                Some(session) => session.clone(),
+                None => {
+                    let session = self.login()?;
+                    self.session = Some(session.clone());
+                    session
+                }
+            };
+            match self.search(&session, call) {
+                Err(Error::Expired) => self.session = None,
+                result => return result,
+            }
+        }
+        Err(Error::Expired)
+    }
+
+    fn login(&self) -> Result<String, Error> {
+        let LookupSettings {
+            username, password, ..
+        } = &self.settings;
+        let (response, key) = match self.settings.service {
+            LookupService::None => return Err(Error::Service("No service chosen".to_string())),
+            LookupService::HamQth => (
+                fetch(HAMQTH_URL, &[("u", username), ("p", password)])?,
+                "session_id",
+            ),
+            LookupService::Qrz => (
+                fetch(
+                    QRZ_URL,
+                    &[
+                        ("username", username),
+                        ("password", password),
+                        ("agent", AGENT),
+                    ],
+                )?,
+                "Key",
+            ),
+        };
+        match element(&response, key) {
+            Some(session) => Ok(session.trim().to_string()),
+            None => Err(service_error(&response)),
+        }
+    }
+
+    fn search(&self, session: &str, call: &str) -> Result<Option<CallsignInfo>, Error> {
+        let (response, found) = match self.settings.service {
+            LookupService::None => return Err(Error::Service("No service chosen".to_string())),
+            LookupService::HamQth => {
+                let response = fetch(
+                    HAMQTH_URL,
+                    &[("id", session), ("callsign", call), ("prg", AGENT)],
+                )?;
+                let found = element(&response, "search").map(|search| {
+                    let field = |name| text(search, name);
+                    let name = match field("adr_name") {
+                        name if name.is_empty() => field("nick"),
+                        name => name,
+                    };
+                    (field("callsign"), name, field("grid"), field("country"))
+                });
+                (response, found)
+            }
+            LookupService::Qrz => {
+                let response = fetch(QRZ_URL, &[("s", session), ("callsign", call)])?;
+                let found = element(&response, "Callsign").map(|callsign| {
+                    let field = |name| text(callsign, name);
+                    let name = [field("fname"), field("name")]
+                        .into_iter()
+                        .filter(|part| !part.is_empty())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    (field("call"), name, field("grid"), field("country"))
+                });
+                (response, found)
+            }
+        };
+        match found {
+            Some((found_call, name, grid, country)) => Ok(Some(CallsignInfo {
+                call: if found_call.is_empty() {
+                    call.to_string()
+                } else {
+                    found_call.to_uppercase()
+                },
+                name,
+                grid,
+                country,
+                fetched: self.time.now(),
+            })),
+            None => match service_error(&response) {
+                Error::Service(message) if message.to_lowercase().contains("not found") => Ok(None),
+                error => Err(error),
+            },
+        }
+    }
+}
+
+/// What went wrong, from the error element both services send
+fn service_error(response: &str) -> Error {
+    let Some(message) = element(response, "error").or_else(|| element(response, "Error")) else {
+        return Error::Response;
+    };
+    let message = unescape(message.trim());
+    let lower = message.to_lowercase();
+    if lower.contains("session") || lower.contains("expired") || lower.contains("timeout") {
+        Error::Expired
+    } else {
+        Error::Service(message)
+    }
+}
+
+/// The text of an element, with XML's escapes undone. Empty if it's not there.
+fn text(xml: &str, name: &str) -> String {
+    element(xml, name)
+        .map(|value| unescape(value.trim()))
+        .unwrap_or_default()
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(feature = "lookup")]
+fn fetch(url: &str, query: &[(&str, &str)]) -> Result<String, Error> {
+    use std::time::Duration;
+    let agent = ureq::Agent::new_with_config(
+        ureq::config::Config::builder()
+            .timeout_global(Some(Duration::from_secs(10)))
+            .build(),
+    );
+    let mut request = agent.get(url);
+    for (key, value) in query {
+        request = request.query(key, value);
+    }
+    Ok(request.call()?.body_mut().read_to_string()?)
+}
+
+#[cfg(not(feature = "lookup"))]
+fn fetch(_: &str, _: &[(&str, &str)]) -> Result<String, Error> {
+    Err(Error::Unsupported)
+}
@@ -0,0 +1,211 @@
+use crate::{
+    analysis::PowerSpectrum,
+    config::NoiseFloorSettings,
+    data::{
+        audio::{Clip, ClipId},
+        window::WindowFunction,
+    },
+    timesource::SharedTimeSource,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::warn;
+use parking_lot::RwLock;
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    ops::Range,
+    path::Path,
+    sync::{
+        Arc,
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// The noise floor log, in the session directory
+pub const LOG_FILE: &str = "noisefloor.csv";
+const HEADER: &str = "utc,low_hz,high_hz,level_dbfs";
+/// The FFT size the noise floor is measured with
+const FFT_SIZE: usize = 4096;
+/// The most samples measured at once, the last few tens of seconds of a long interval
+const MAX_SAMPLES: usize = 1 << 21;
+
+/// The noise floor in a band at one moment
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoiseFloorReading {
+    pub time: DateTime<Utc>,
+    pub band: Range<f64>,
+    /// The noise power in the whole band, in dBFS
+    pub level_db: f64,
+}
+
+impl NoiseFloorReading {
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split(',');
+        let time = DateTime::parse_from_rfc3339(fields.next()?).ok()?;
+        let low: f64 = fields.next()?.parse().ok()?;
+        let high: f64 = fields.next()?.parse().ok()?;
+        let level_db = fields.next()?.parse().ok()?;
+        Some(Self {
+            time: time.with_timezone(&Utc),
+            band: low..high,
+            level_db,
+        })
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{},{:.0},{:.0},{:.2}",
+            self.time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            self.band.start,
+            self.band.end,
+            self.level_db
+        )
+    }
+}
+
+/// The noise power in a band, from its median bin, so signals in the band don't pull it up.
+/// None if there aren't enough samples, or the band is empty.
+pub fn band_noise_floor(samples: &[f32], sample_rate: u32, band: Range<f64>) -> Option<f64> {
+    let size = FFT_SIZE.min(1 << samples.len().max(1).ilog2());
+    let spectrum = PowerSpectrum::new(samples, sample_rate, size, WindowFunction::Hann)?;
+    let mut bins = spectrum.power[spectrum.bins(band)].to_vec();
+    if bins.is_empty() {
+        return None;
+    }
+    bins.sort_by(f64::total_cmp);
+    let median = bins[bins.len() / 2];
+    Some(10.0 * (median * bins.len() as f64).max(1e-20).log10())
+}
+
+/// Read the readings logged in a session so far. A session that's never logged any has none,
+/// and lines that can't be read, like a last one cut short, are skipped.
+pub fn load_log(session_dir: &Path) -> Result<Vec<NoiseFloorReading>, io::Error> {
+    match fs::read_to_string(session_dir.join(LOG_FILE)) {
+        Ok(log) => Ok(log
+            .lines()
+            .skip(1)
+            .filter_map(NoiseFloorReading::parse)
+            .collect()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(error) => Err(error),
+    }
+}
+
+fn append(path: &Path, reading: &NoiseFloorReading) -> Result<(), io::Error> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if file.metadata()?.len() == 0 {
+        writeln!(file, "{HEADER}")?;
+    }
+    writeln!(file, "{}", reading.to_line())
+}
+
+#[derive(Default)]
+struct Logged {
+    /// The clip being recorded, to measure
+    clip: Option<Clip>,
+    readings: Vec<NoiseFloorReading>,
+}
+
+/// Measures the noise floor of the clip being recorded every so often and logs it in the
+/// session directory. Stops when dropped.
+pub struct NoiseFloorLogger {
+    logged: Arc<RwLock<Logged>>,
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl NoiseFloorLogger {
+    /// Start logging into `session_dir`, carrying on from what's already logged there
+    pub fn start(
+        settings: &NoiseFloorSettings,
+        session_dir: &Path,
+        time: &SharedTimeSource,
+    ) -> Result<Self, io::Error> {
+        let readings = load_log(session_dir)?;
+        let logged = Arc::new(RwLock::new(Logged {
+            clip: None,
+            readings,
+        }));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let path = session_dir.join(LOG_FILE);
+        let band = settings.low_hz.min(settings.high_hz)..settings.low_hz.max(settings.high_hz);
+        let interval = Duration::from_secs(settings.interval_seconds.max(1));
+        let thread = thread::Builder::new()
+            .name("noise floor".to_string())
+            .spawn({
+                let logged = logged.clone();
+                let time = time.clone();
+                move || {
+                    // Where the last measurement got to, in which clip
+                    let mut measured: Option<(ClipId, usize)> = None;
+                    while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                        let clip = logged.read().clip.clone();
+                        if let Some(clip) = clip
+                            && let Some(level_db) =
+                                Self::measure(&clip, &mut measured, band.clone())
+                        {
+                            let reading = NoiseFloorReading {
+                                time: time.now(),
+                                band: band.clone(),
+                                level_db,
+                            };
+                            if let Err(error) = append(&path, &reading) {
+                                warn!("Unable to log the noise floor: {}", error);
+                            }
+                            logged.write().readings.push(reading);
+                        }
+                    }
+                }
+            })?;
+        Ok(Self {
+            logged,
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
+    /// The noise floor of what's been recorded since the last measurement
+    fn measure(
+        clip: &Clip,
+        measured: &mut Option<(ClipId, usize)>,
+        band: Range<f64>,
+    ) -> Option<f64> {
+        // Copy the samples out, so recording isn't held up while they're measured
+        let (fetch, sample_rate) = {
+            let clip = clip.read();
+            let from = match measured {
+                Some((id, position)) if id == clip.id() => *position,
+                _ => 0,
+            };
+            let length = clip.samples.len();
+            *measured = Some((clip.id().clone(), length));
+            let from = from.min(length).max(length.saturating_sub(MAX_SAMPLES));
+            (clip.samples.fetch(from..length), clip.sample_rate.0)
+        };
+        band_noise_floor(&fetch.read(), sample_rate, band)
+    }
+
+    /// Everything logged in the session, oldest first
+    pub fn readings(&self) -> Vec<NoiseFloorReading> {
+        self.logged.read().readings.clone()
+    }
+
+    /// Measure this clip from now on. None once it's finished recording.
+    pub fn track_clip(&self, clip: Option<Clip>) {
+        self.logged.write().clip = clip;
+    }
+}
+
+impl Drop for NoiseFloorLogger {
+    fn drop(&mut self) {
+        // Hanging up wakes the thread
+        self.stop.take();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            warn!("Noise floor thread panicked");
+        }
+    }
+}
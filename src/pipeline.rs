@@ -0,0 +1,249 @@
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    fmt::Display,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// How many blocks the smoothed block interval and jitter take to follow a change
+const BLOCK_SMOOTHING: f32 = 16.0;
+
+/// What an element of the pipeline is up to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ElementState {
+    Running,
+    Paused,
+    /// Hit an error it can't recover from, and is dropping whatever it's given
+    Failed,
+}
+
+impl Display for ElementState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ElementState::Running => write!(f, "Running"),
+            ElementState::Paused => write!(f, "Paused"),
+            ElementState::Failed => write!(f, "Failed"),
+        }
+    }
+}
+
+/// The live status of one element of the pipeline. The element updates it as it runs
+/// (usually from some other thread), and anyone holding a reference can watch it or
+/// pause the element.
+pub struct ElementStatus {
+    name: &'static str,
+    /// Whether pausing this element means anything
+    pausable: bool,
+    paused: AtomicBool,
+    failed: AtomicBool,
+    /// Samples the element has passed along
+    processed: AtomicUsize,
+    errors: AtomicUsize,
+    /// Things the element has done worth counting, like impulses blanked
+    events: AtomicUsize,
+    last_error: Mutex<Option<String>>,
+    /// Samples waiting in the element's buffer, if it has one
+    queued: AtomicUsize,
+    capacity: usize,
+    started: Instant,
+    /// When the element was last handed a block, in nanoseconds after it started
+    last_block: AtomicU64,
+    /// The smoothed time between blocks, and how far from that they come, in seconds as f32
+    /// bits. Only the thread handing over blocks writes them.
+    block_interval: AtomicU32,
+    block_jitter: AtomicU32,
+}
+
+impl ElementStatus {
+    pub fn new(name: &'static str, pausable: bool) -> Self {
+        Self::with_capacity(name, pausable, 0)
+    }
+
+    /// Status for an element that buffers up to capacity samples
+    pub fn with_capacity(name: &'static str, pausable: bool, capacity: usize) -> Self {
+        Self {
+            name,
+            pausable,
+            paused: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+            processed: AtomicUsize::new(0),
+            errors: AtomicUsize::new(0),
+            events: AtomicUsize::new(0),
+            last_error: Mutex::new(None),
+            queued: AtomicUsize::new(0),
+            capacity,
+            started: Instant::now(),
+            last_block: AtomicU64::new(0),
+            block_interval: AtomicU32::new(0),
+            block_jitter: AtomicU32::new(0),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn state(&self) -> ElementState {
+        if self.failed.load(Ordering::Relaxed) {
+            ElementState::Failed
+        } else if self.paused.load(Ordering::Relaxed) {
+            ElementState::Paused
+        } else {
+            ElementState::Running
+        }
+    }
+
+    pub fn is_pausable(&self) -> bool {
+        self.pausable
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn is_failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Pause or resume the element. Ignored for elements that can't be paused.
+    pub fn set_paused(&self, paused: bool) {
+        if self.pausable {
+            self.paused.store(paused, Ordering::Relaxed);
+        }
+    }
+
+    pub fn processed(&self) -> usize {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    /// Samples passed along per second, on average since the element started
+    pub fn throughput(&self) -> f64 {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            self.processed() as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    pub fn record_processed(&self, samples: usize) {
+        self.processed.fetch_add(samples, Ordering::Relaxed);
+    }
+
+    /// Note a block arriving, for timing how evenly they come. Called by whatever hands the
+    /// element its blocks, which for an input is the audio callback, so it doesn't lock.
+    pub fn record_block(&self) {
+        let now = self.started.elapsed().as_nanos().max(1) as u64;
+        let last = self.last_block.swap(now, Ordering::Relaxed);
+        if last == 0 {
+            return;
+        }
+        let interval = (now - last) as f32 / 1e9;
+        let mean = f32::from_bits(self.block_interval.load(Ordering::Relaxed));
+        let jitter = f32::from_bits(self.block_jitter.load(Ordering::Relaxed));
+        let (mean, jitter) = if mean == 0.0 {
+            (interval, 0.0)
+        } else {
+            let mean = mean + (interval - mean) / BLOCK_SMOOTHING;
+            (
+                mean,
+                jitter + ((interval - mean).abs() - jitter) / BLOCK_SMOOTHING,
+            )
+        };
+        self.block_interval.store(mean.to_bits(), Ordering::Relaxed);
+        self.block_jitter.store(jitter.to_bits(), Ordering::Relaxed);
+    }
+
+    /// The usual time between blocks, once a couple have come
+    pub fn block_interval(&self) -> Option<Duration> {
+        let mean = f32::from_bits(self.block_interval.load(Ordering::Relaxed));
+        (mean > 0.0).then(|| Duration::from_secs_f32(mean))
+    }
+
+    /// How far blocks usually come from the usual time between them
+    pub fn block_jitter(&self) -> Option<Duration> {
+        self.block_interval()?;
+        let jitter = f32::from_bits(self.block_jitter.load(Ordering::Relaxed));
+        Some(Duration::from_secs_f32(jitter))
+    }
+
+    pub fn errors(&self) -> usize {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn events(&self) -> usize {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    pub fn record_events(&self, events: usize) {
+        self.events.fetch_add(events, Ordering::Relaxed);
+    }
+
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.lock().clone()
+    }
+
+    /// Count an error the element can carry on from
+    pub fn record_error(&self, error: &dyn Display) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        *self.last_error.lock() = Some(error.to_string());
+    }
+
+    /// Record an error the element can't carry on from
+    pub fn fail(&self, error: &dyn Display) {
+        self.record_error(error);
+        self.failed.store(true, Ordering::Relaxed);
+    }
+
+    /// How full the element's buffer is, from 0 to 1. None if it doesn't have one.
+    pub fn fill(&self) -> Option<f32> {
+        if self.capacity == 0 {
+            return None;
+        }
+        Some(self.queued.load(Ordering::Relaxed) as f32 / self.capacity as f32)
+    }
+
+    pub fn enqueue(&self, samples: usize) {
+        self.queued.fetch_add(samples, Ordering::Relaxed);
+    }
+
+    pub fn dequeue(&self, samples: usize) {
+        self.queued.fetch_sub(samples, Ordering::Relaxed);
+    }
+}
+
+/// The elements samples flow through, from the source to the sink
+#[derive(Default, Clone)]
+pub struct Pipeline {
+    elements: Vec<Arc<ElementStatus>>,
+}
+
+impl Pipeline {
+    /// Add an element to the end of the pipeline, handing back the status for it to update
+    pub fn add(&mut self, element: ElementStatus) -> Arc<ElementStatus> {
+        let element = Arc::new(element);
+        self.elements.push(element.clone());
+        element
+    }
+
+    pub fn elements(&self) -> &[Arc<ElementStatus>] {
+        &self.elements
+    }
+
+    pub fn pause_all(&self) {
+        for element in &self.elements {
+            element.set_paused(true);
+        }
+    }
+
+    pub fn resume_all(&self) {
+        for element in &self.elements {
+            element.set_paused(false);
+        }
+    }
+}
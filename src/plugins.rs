@@ -0,0 +1,412 @@
+//! Plugins are shared libraries that register sinks: they're handed every block recorded,
+//! and can report what they decode from it. That's all a plugin can be for now. There's no
+//! registering a source, so recordings still come from the inputs Hamshark has built in.
+
+use crate::{
+    config::PluginSettings,
+    data::audio::ClipId,
+    events::{DecodeEvent, Observers},
+    pipeline::{ElementStatus, Pipeline},
+    timesource::SharedTimeSource,
+};
+use libloading::{Library, Symbol};
+use log::{info, warn};
+use parking_lot::Mutex;
+use std::{
+    env::consts::DLL_EXTENSION,
+    ffi::{CStr, c_char, c_void},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error as ThisError;
+
+/// HAMSHARK_PLUGIN_ABI_VERSION in include/hamshark_plugin.h
+pub const ABI_VERSION: u32 = 1;
+/// What plugins export to register themselves
+const REGISTER: &[u8] = b"hamshark_plugin_register\0";
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error reading the plugins directory: {0}")]
+    IO(#[from] io::Error),
+    #[error("Error loading plugin: {0}")]
+    Load(#[from] libloading::Error),
+    #[error("{0:?} declined to be loaded")]
+    Declined(PathBuf),
+    #[error("The plugin can't take this recording")]
+    Unsupported,
+    #[error("The plugin failed, it's left out of the rest of the recording")]
+    Failed,
+}
+
+/// HamSharkSinkHost
+#[repr(C)]
+struct SinkHost {
+    context: *mut c_void,
+    decoded: extern "C" fn(*mut c_void, *const c_char, f64),
+}
+
+/// HamSharkSink
+#[repr(C)]
+struct SinkVTable {
+    name: *const c_char,
+    create: Option<unsafe extern "C" fn(u32, u16, *const SinkHost) -> *mut c_void>,
+    process: Option<unsafe extern "C" fn(*mut c_void, *const f32, usize) -> i32>,
+    destroy: Option<unsafe extern "C" fn(*mut c_void)>,
+}
+
+/// HamSharkPluginHost
+#[repr(C)]
+struct PluginHost {
+    abi_version: u32,
+    context: *mut c_void,
+    register_sink: extern "C" fn(*mut c_void, *const SinkVTable) -> i32,
+}
+
+type Register = unsafe extern "C" fn(*const PluginHost) -> i32;
+
+/// A sink a plugin registered, handed every block recorded
+pub struct PluginSink {
+    name: &'static str,
+    /// The library it came from
+    path: PathBuf,
+    create: unsafe extern "C" fn(u32, u16, *const SinkHost) -> *mut c_void,
+    process: unsafe extern "C" fn(*mut c_void, *const f32, usize) -> i32,
+    destroy: unsafe extern "C" fn(*mut c_void),
+}
+
+impl PluginSink {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Every plugin loaded. They stay loaded for as long as we run: unloading a library whose
+/// code a writer thread might still be in isn't worth the risk.
+struct Registry {
+    libraries: Vec<(PathBuf, Library)>,
+    sinks: Vec<Arc<PluginSink>>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    libraries: Vec::new(),
+    sinks: Vec::new(),
+});
+
+/// The sinks a plugin registers while it's being loaded
+struct Registering<'a> {
+    path: &'a Path,
+    sinks: Vec<PluginSink>,
+}
+
+extern "C" fn register_sink(context: *mut c_void, sink: *const SinkVTable) -> i32 {
+    // Safety: the context is the Registering load() passed the plugin, and the sink is the
+    // plugin's, valid for the call
+    let context = unsafe { &mut *(context as *mut Registering) };
+    let Some(sink) = (unsafe { sink.as_ref() }) else {
+        return -1;
+    };
+    let (Some(create), Some(process), Some(destroy)) = (sink.create, sink.process, sink.destroy)
+    else {
+        return -1;
+    };
+    if sink.name.is_null() {
+        return -1;
+    }
+    let name = unsafe { CStr::from_ptr(sink.name) }
+        .to_string_lossy()
+        .into_owned();
+    info!(
+        "Loaded plugin sink {} from {:?}",
+        name,
+        context.path.as_os_str()
+    );
+    context.sinks.push(PluginSink {
+        // Pipeline element names are static. Plugins are only loaded once, so this is too.
+        name: Box::leak(name.into_boxed_str()),
+        path: context.path.to_path_buf(),
+        create,
+        process,
+        destroy,
+    });
+    0
+}
+
+/// Load a plugin, unless it's already loaded
+pub fn load(path: &Path) -> Result<(), Error> {
+    let mut registry = REGISTRY.lock();
+    if registry.libraries.iter().any(|(loaded, _)| loaded == path) {
+        return Ok(());
+    }
+    // Safety: loading a library runs its initializers. Plugins are only loaded from the
+    // plugins directory, and only once the user has turned them on.
+    let library = unsafe { Library::new(path)? };
+    let mut registering = Registering {
+        path,
+        sinks: Vec::new(),
+    };
+    {
+        let register: Symbol<Register> = unsafe { library.get(REGISTER)? };
+        let host = PluginHost {
+            abi_version: ABI_VERSION,
+            context: &mut registering as *mut Registering as *mut c_void,
+            register_sink,
+        };
+        if unsafe { register(&host) } != 0 {
+            return Err(Error::Declined(path.to_path_buf()));
+        }
+    }
+    let sinks = registering.sinks;
+    registry.sinks.extend(sinks.into_iter().map(Arc::new));
+    registry.libraries.push((path.to_path_buf(), library));
+    Ok(())
+}
+
+/// Load every plugin in a directory. Ones that won't load are logged and skipped. Returns
+/// how many loaded.
+pub fn load_dir(dir: &Path) -> Result<usize, Error> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let mut loaded = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != DLL_EXTENSION) {
+            continue;
+        }
+        match load(&path) {
+            Ok(()) => loaded += 1,
+            Err(error) => warn!("Unable to load plugin {:?}: {}", path.as_os_str(), error),
+        }
+    }
+    Ok(loaded)
+}
+
+/// The sinks of every plugin loaded
+pub fn sinks() -> Vec<Arc<PluginSink>> {
+    REGISTRY.lock().sinks.clone()
+}
+
+/// Add the sinks that are turned on to the end of a pipeline
+pub(crate) fn add_sinks(
+    settings: &PluginSettings,
+    pipeline: &mut Pipeline,
+) -> Vec<(Arc<PluginSink>, Arc<ElementStatus>)> {
+    if !settings.enabled {
+        return Vec::new();
+    }
+    sinks()
+        .into_iter()
+        .filter(|sink| settings.sink_enabled(sink.name))
+        .map(|sink| {
+            let status = pipeline.add(ElementStatus::new(sink.name, false));
+            (sink, status)
+        })
+        .collect()
+}
+
+/// What a sink's decodes are passed on with
+struct DecodeContext {
+    observers: Observers,
+    mode: &'static str,
+    clip: ClipId,
+    /// The session's, for when decodes were heard
+    time: SharedTimeSource,
+}
+
+extern "C" fn decoded(context: *mut c_void, text: *const c_char, frequency_hz: f64) {
+    if text.is_null() {
+        return;
+    }
+    // Safety: the context is the DecodeContext the sink was created with, which lives as
+    // long as the sink
+    let context = unsafe { &*(context as *const DecodeContext) };
+    let text = unsafe { CStr::from_ptr(text) }.to_string_lossy();
+    context.observers.decoded(&DecodeEvent {
+        time: context.time.now(),
+        mode: context.mode.to_string(),
+        audio_frequency: (frequency_hz > 0.0).then_some(frequency_hz as f32),
+        clip: Some(context.clip.clone()),
+        text: text.into_owned(),
+    });
+}
+
+/// A plugin sink taking a recording, on the clip writer thread
+pub(crate) struct SinkInstance {
+    sink: Arc<PluginSink>,
+    status: Arc<ElementStatus>,
+    state: *mut c_void,
+    /// Handed to the plugin, so they mustn't move or go before it's destroyed
+    _host: Box<SinkHost>,
+    decodes: Box<DecodeContext>,
+}
+
+impl SinkInstance {
+    pub(crate) fn start(
+        sink: &Arc<PluginSink>,
+        status: &Arc<ElementStatus>,
+        sample_rate: u32,
+        channels: u16,
+        observers: &Observers,
+        clip: ClipId,
+        time: &SharedTimeSource,
+    ) -> Option<Self> {
+        let mut decodes = Box::new(DecodeContext {
+            observers: observers.clone(),
+            mode: sink.name,
+            clip,
+            time: time.clone(),
+        });
+        let host = Box::new(SinkHost {
+            context: &mut *decodes as *mut DecodeContext as *mut c_void,
+            decoded,
+        });
+        let state = unsafe { (sink.create)(sample_rate, channels, &*host) };
+        if state.is_null() {
+            status.fail(&Error::Unsupported);
+            observers.error(status.name(), &Error::Unsupported);
+            return None;
+        }
+        Some(Self {
+            sink: sink.clone(),
+            status: status.clone(),
+            state,
+            _host: host,
+            decodes,
+        })
+    }
+
+    /// Pass decodes on with this clip from now on
+    pub(crate) fn set_clip(&mut self, clip: ClipId) {
+        self.decodes.clip = clip;
+    }
+
+    pub(crate) fn process(&mut self, block: &[f32]) {
+        if self.status.is_failed() {
+            return;
+        }
+        if unsafe { (self.sink.process)(self.state, block.as_ptr(), block.len()) } == 0 {
+            self.status.record_processed(block.len());
+        } else {
+            self.status.fail(&Error::Failed);
+            self.decodes
+                .observers
+                .error(self.status.name(), &Error::Failed);
+        }
+    }
+}
+
+impl Drop for SinkInstance {
+    fn drop(&mut self) {
+        unsafe { (self.sink.destroy)(self.state) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Settings,
+        data::audio::{BitDepth, WavClip},
+        testutil::scratch_dir,
+        tools::SampleRecorder,
+        virtualinput::VirtualInput,
+    };
+    use chrono::Utc;
+    use parking_lot::RwLock;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static PROCESSED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn create(_: u32, _: u16, host: *const SinkHost) -> *mut c_void {
+        Box::into_raw(Box::new(unsafe { host.read() })) as *mut c_void
+    }
+
+    unsafe extern "C" fn process(state: *mut c_void, _: *const f32, len: usize) -> i32 {
+        let host = unsafe { &*(state as *const SinkHost) };
+        if PROCESSED.fetch_add(len, Ordering::Relaxed) == 0 {
+            (host.decoded)(host.context, c"CQ DE TEST".as_ptr(), 700.0);
+        }
+        0
+    }
+
+    unsafe extern "C" fn destroy(state: *mut c_void) {
+        drop(unsafe { Box::from_raw(state as *mut SinkHost) });
+    }
+
+    /// A sink in the registry until it's dropped, so it's gone again however the test ends
+    struct Registered(Arc<PluginSink>);
+
+    impl Registered {
+        fn new(sink: PluginSink) -> Self {
+            let sink = Arc::new(sink);
+            REGISTRY.lock().sinks.push(sink.clone());
+            Self(sink)
+        }
+    }
+
+    impl Drop for Registered {
+        fn drop(&mut self) {
+            REGISTRY
+                .lock()
+                .sinks
+                .retain(|sink| !Arc::ptr_eq(sink, &self.0));
+        }
+    }
+
+    #[test]
+    fn sinks_see_every_sample_and_report_decodes() {
+        let _registered = Registered::new(PluginSink {
+            name: "Test sink",
+            path: PathBuf::new(),
+            create,
+            process,
+            destroy,
+        });
+        let dir = scratch_dir("plugins");
+        let mut settings = Settings::default();
+        settings.plugins.enabled = true;
+        let observers = Observers::default();
+        let decodes = Arc::new(Mutex::new(Vec::new()));
+        observers.on_decode({
+            let decodes = decodes.clone();
+            move |event| decodes.lock().push(event.clone())
+        });
+        let input = VirtualInput::new("Test", 8000, 1, vec![0.1; 8000]);
+        let spec = BitDepth::Float32.wav_spec(8000);
+        let clip = WavClip::record_new(ClipId::from_datetimeutc(Utc::now()), &dir, spec).unwrap();
+        let clip = Arc::new(RwLock::new(clip));
+        let recorder = SampleRecorder::from_virtual(
+            &input,
+            clip,
+            observers,
+            &settings,
+            &crate::timesource::system(),
+        )
+        .unwrap();
+        assert!(
+            recorder
+                .pipeline()
+                .elements()
+                .iter()
+                .any(|e| e.name() == "Test sink")
+        );
+        input.play_to_end();
+        recorder.close().unwrap();
+
+        assert_eq!(PROCESSED.load(Ordering::Relaxed), 8000);
+        let decodes = decodes.lock();
+        assert_eq!(decodes.len(), 1);
+        assert_eq!(
+            (decodes[0].mode.as_str(), decodes[0].text.as_str()),
+            ("Test sink", "CQ DE TEST")
+        );
+        assert_eq!(decodes[0].audio_frequency, Some(700.0));
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// Told how far through a long job is, from 0 to 1. A job split across threads calls it from
+/// whichever thread moved it along.
+pub type Progress<'a> = &'a (dyn Fn(f32) + Sync);
+
+/// For callers that don't want to know
+pub fn ignore(_: f32) {}
+
+/// Adds up the pieces of a job finished on different threads and reports the whole
+pub struct Tally<'a> {
+    total: usize,
+    done: AtomicUsize,
+    progress: Progress<'a>,
+}
+
+impl<'a> Tally<'a> {
+    /// A job of `total` of whatever it's counted in
+    pub fn new(total: usize, progress: Progress<'a>) -> Self {
+        Self {
+            total,
+            done: AtomicUsize::new(0),
+            progress,
+        }
+    }
+
+    /// Count `count` more as done
+    pub fn add(&self, count: usize) {
+        let done = self.done.fetch_add(count, Ordering::Relaxed) + count;
+        (self.progress)((done as f32 / self.total.max(1) as f32).min(1.0));
+    }
+}
+
+/// The last progress a job reported, for another thread to show
+#[derive(Debug, Default)]
+pub struct Meter(AtomicU32);
+
+impl Meter {
+    pub fn report(&self, fraction: f32) {
+        self.0.store(fraction.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn fraction(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,177 @@
+use crate::{
+    data::audio::{self, ClipId, WavClip},
+    progress,
+    resample::resample,
+    virtualinput::VirtualInput,
+};
+use log::{info, warn};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use thiserror::Error as ThisError;
+
+/// How often the replay's clock moves on
+const TICK: Duration = Duration::from_millis(10);
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Unable to read the session to replay: {0}")]
+    IO(#[from] io::Error),
+    #[error("Unable to load a clip to replay: {0}")]
+    Audio(#[from] audio::Error),
+    #[error("No clips in {0:?} to replay")]
+    NoClips(PathBuf),
+    #[error("Error starting replay clock: {0}")]
+    SpawnClock(#[source] io::Error),
+}
+
+/// Plays an earlier session's clips through a [`VirtualInput`] as they were captured, so
+/// recording from it goes through the live pipeline like recording off the air. The clips
+/// follow each other with no gaps, at the rate of the first. The clock only runs while
+/// something's recording, so nothing's missed between recordings.
+pub struct Replay {
+    input: VirtualInput,
+    stop: Arc<AtomicBool>,
+    clock: Option<JoinHandle<()>>,
+}
+
+impl Replay {
+    /// Load every clip in a session directory, one after another
+    pub fn load(session: &Path) -> Result<VirtualInput, Error> {
+        let mut paths = BTreeMap::new();
+        for entry in fs::read_dir(session)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "wav")
+                && let Some(id) = ClipId::from_path_ref(&path)
+            {
+                paths.insert(id, path);
+            }
+        }
+
+        let mut rate = None;
+        let mut samples = Vec::new();
+        for path in paths.values() {
+            let clip = match WavClip::from_file(path) {
+                Ok(clip) => clip,
+                Err(error) => {
+                    warn!(
+                        "Leaving {:?} out of the replay: {}",
+                        path.as_os_str(),
+                        error
+                    );
+                    continue;
+                }
+            };
+            let from = clip.sample_rate.0;
+            let to = *rate.get_or_insert(from);
+            samples.extend(resample(
+                &clip.samples.from(0),
+                1,
+                from,
+                to,
+                &progress::ignore,
+            ));
+        }
+        let rate = rate.ok_or_else(|| Error::NoClips(session.to_path_buf()))?;
+        let name = session.file_name().map_or_else(
+            || session.display().to_string(),
+            |name| name.to_string_lossy().into(),
+        );
+        info!(
+            "Replaying {} clips from {:?}, {:.0} seconds",
+            paths.len(),
+            session.as_os_str(),
+            samples.len() as f64 / rate as f64
+        );
+        Ok(VirtualInput::new(
+            &format!("Replay of {}", name),
+            rate,
+            1,
+            samples,
+        ))
+    }
+
+    /// Start running the input's clock in real time. At the end, it's unplugged, finishing
+    /// the clip, and wound back for the next recording, or just wound back if `looping`.
+    pub fn start(input: VirtualInput, looping: bool) -> Result<Self, Error> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let clock = thread::Builder::new()
+            .name("replay clock".to_string())
+            .spawn({
+                let input = input.clone();
+                let stop = stop.clone();
+                move || {
+                    let mut last = Instant::now();
+                    while !stop.load(Ordering::Relaxed) {
+                        thread::sleep(TICK);
+                        let now = Instant::now();
+                        let elapsed = now - last;
+                        last = now;
+                        if !input.is_playing() {
+                            continue;
+                        }
+                        input.advance(elapsed);
+                        if input.remaining_frames() > 0 {
+                            continue;
+                        }
+                        info!("Replay finished, going back to the start");
+                        if !looping {
+                            input.unplug();
+                        }
+                        input.rewind();
+                    }
+                }
+            })
+            .map_err(Error::SpawnClock)?;
+        Ok(Self {
+            input,
+            stop,
+            clock: Some(clock),
+        })
+    }
+
+    /// What to record from
+    pub fn input(&self) -> &VirtualInput {
+        &self.input
+    }
+}
+
+impl Drop for Replay {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(clock) = self.clock.take()
+            && clock.join().is_err()
+        {
+            warn!("The replay clock panicked");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::audio::BitDepth, testutil::scratch_dir};
+
+    #[test]
+    fn clips_are_replayed_in_order_at_one_rate() {
+        let dir = scratch_dir("replay");
+        let spec = BitDepth::Float32.wav_spec(8000);
+        WavClip::export_samples(&[0.5; 800], &dir.join("2025-01-01_00-00-01.wav"), spec).unwrap();
+        // At twice the rate, so half as long once it's brought down to the first's
+        let spec = BitDepth::Float32.wav_spec(16000);
+        WavClip::export_samples(&[0.25; 1600], &dir.join("2025-01-01_00-00-02.wav"), spec).unwrap();
+
+        let input = Replay::load(&dir).unwrap();
+        assert_eq!(input.sample_rate(), 8000);
+        assert_eq!(input.remaining_frames(), 1600);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
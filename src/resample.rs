@@ -0,0 +1,119 @@
+use crate::{
+    data::audio::{self, Clip, ClipId, WavClip},
+    progress::{Progress, Tally},
+};
+use hound::WavSpec;
+use rayon::prelude::*;
+use std::{f64::consts::PI, fs, io};
+use thiserror::Error as ThisError;
+
+/// Filter taps either side of each sample worked out, at the lower of the two rates
+const HALF_TAPS: f64 = 16.0;
+/// Frames worked out at a time on each thread
+const CHUNK_FRAMES: usize = 4096;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("{0} is still being recorded")]
+    Recording(ClipId),
+    #[error("{0}")]
+    Audio(#[from] audio::Error),
+    #[error("Unable to swap in the resampled clip: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Change interleaved samples from one rate to another with a windowed sinc filter. It cuts
+/// off below the lower rate's Nyquist frequency, so going down doesn't alias.
+pub fn resample(
+    samples: &[f32],
+    channels: u16,
+    from: u32,
+    to: u32,
+    progress: Progress,
+) -> Vec<f32> {
+    let channels = channels.max(1) as usize;
+    if from == to || from == 0 || to == 0 {
+        return samples.to_vec();
+    }
+    let frames_in = samples.len() / channels;
+    let frames_out = (frames_in as u64 * to as u64 / from as u64) as usize;
+    let step = from as f64 / to as f64;
+    let cutoff = (to as f64 / from as f64).min(1.0);
+    let half = HALF_TAPS / cutoff;
+    let tally = Tally::new(frames_out, progress);
+    let mut resampled = vec![0.0; frames_out * channels];
+    resampled
+        .par_chunks_mut(CHUNK_FRAMES * channels)
+        .enumerate()
+        .for_each(|(chunk, block)| {
+            for (i, frame) in block.chunks_mut(channels).enumerate() {
+                let position = (chunk * CHUNK_FRAMES + i) as f64 * step;
+                let first = (position - half).ceil().max(0.0) as usize;
+                let last = ((position + half).floor() as usize).min(frames_in.saturating_sub(1));
+                let mut total = 0.0;
+                for k in first..=last {
+                    let x = position - k as f64;
+                    let weight = cutoff * sinc(cutoff * x) * hann(x / half);
+                    total += weight;
+                    for (out, &sample) in frame.iter_mut().zip(&samples[k * channels..]) {
+                        *out += sample * weight as f32;
+                    }
+                }
+                // Keeps the level right near the ends, where some of the taps are missing
+                if total > 0.0 {
+                    frame.iter_mut().for_each(|out| *out /= total as f32);
+                }
+            }
+            tally.add(block.len() / channels);
+        });
+    resampled
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Hann window over -1 to 1
+fn hann(x: f64) -> f64 {
+    if x.abs() >= 1.0 {
+        0.0
+    } else {
+        0.5 * (1.0 + (PI * x).cos())
+    }
+}
+
+/// Rewrite a clip's WAV file at another sample rate, scaling its markers and other
+/// positions to match. The original is kept alongside with .orig on the end. Returns the
+/// clip as it is now, for the caller to swap in.
+pub fn resample_clip(clip: &Clip, rate: u32, progress: Progress) -> Result<WavClip, Error> {
+    let (path, mut metadata) = {
+        let clip = clip.read();
+        if clip.is_recording() {
+            return Err(Error::Recording(clip.id().clone()));
+        }
+        clip.samples.close_file();
+        (clip.path().to_path_buf(), clip.metadata.clone())
+    };
+    // From the file rather than the clip, which only holds the channels averaged
+    let (spec, samples, _) = WavClip::read_file(&path)?;
+    let resampled = resample(&samples, spec.channels, spec.sample_rate, rate, progress);
+    metadata.rescale(rate as f64 / spec.sample_rate as f64);
+
+    let partial = path.with_extension("wav.partial");
+    WavClip::export_samples(
+        &resampled,
+        &partial,
+        WavSpec {
+            sample_rate: rate,
+            ..spec
+        },
+    )?;
+    fs::rename(&path, path.with_extension("wav.orig"))?;
+    fs::rename(&partial, &path)?;
+    metadata.save(&path).map_err(audio::Error::from)?;
+    Ok(WavClip::from_file(&path)?)
+}
@@ -0,0 +1,299 @@
+use crate::{
+    config::SweepSettings,
+    data::audioinput::{self, AudioInputDeviceBuilder},
+    fft::Transform,
+    generator::log_sweep,
+    tools::{self, downmix},
+};
+use cpal::{
+    Device, default_host,
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+};
+use parking_lot::Mutex;
+use rustfft::num_complex::Complex;
+use std::{
+    ops::Range,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use thiserror::Error as ThisError;
+
+/// Silence played before the sweep, while the streams settle
+const LEAD_IN: Duration = Duration::from_millis(250);
+/// How long to keep recording after the sweep, for the latency and the chain to ring down
+const TAIL: Duration = Duration::from_secs(1);
+/// The highest the sweep goes, as a fraction of the sample rate, so it stays clear of the
+/// anti-aliasing filters
+const TOP_OF_BAND: f64 = 0.45;
+/// Below the loudest point, in dB, where a recording counts as silent
+const SILENCE_DB: f64 = -80.0;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("{0}")]
+    Audio(#[from] tools::Error),
+    #[error("{0}")]
+    Input(#[from] audioinput::Error),
+    #[error("Unable to list output devices: {0}")]
+    Devices(#[from] cpal::DevicesError),
+    #[error("{0} isn't available")]
+    NotFound(String),
+    #[error("Nothing came back, check the loop is connected and the input is up")]
+    Silent,
+    #[error("The measurement thread panicked")]
+    Panicked,
+}
+
+/// The gain of an audio chain across the band a sweep covered
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrequencyResponse {
+    /// Gain at each frequency, as (Hz, dB), log-spaced
+    pub points: Vec<(f64, f64)>,
+    /// How long the sweep took to come back, in seconds
+    pub latency: f64,
+}
+
+impl FrequencyResponse {
+    /// The gain at a frequency, interpolated between points
+    pub fn gain_at(&self, hz: f64) -> Option<f64> {
+        let after = self.points.iter().position(|(f, _)| *f >= hz)?;
+        if after == 0 {
+            return (self.points[0].0 == hz).then_some(self.points[0].1);
+        }
+        let ((f0, db0), (f1, db1)) = (self.points[after - 1], self.points[after]);
+        let t = (hz / f0).ln() / (f1 / f0).ln();
+        Some(db0 + t * (db1 - db0))
+    }
+
+    /// The stretch around `reference_hz` that stays within `drop_db` of the gain there, like
+    /// the -3 dB passband
+    pub fn passband(&self, reference_hz: f64, drop_db: f64) -> Option<Range<f64>> {
+        let floor = self.gain_at(reference_hz)? - drop_db;
+        let centre = self.points.iter().position(|(f, _)| *f >= reference_hz)?;
+        let low = self.points[..centre]
+            .iter()
+            .rposition(|(_, db)| *db < floor)
+            .map_or(self.points[0].0, |i| self.points[i + 1].0);
+        let high = self.points[centre..]
+            .iter()
+            .position(|(_, db)| *db < floor)
+            .map_or(self.points[self.points.len() - 1].0, |i| {
+                self.points[centre + i.saturating_sub(1)].0
+            });
+        Some(low..high)
+    }
+}
+
+/// Compare what came back against the sweep that was sent. The gain is averaged over
+/// 1/`points_per_octave` of an octave around each point, from the start of `band` to the end.
+pub fn frequency_response(
+    sent: &[f32],
+    received: &[f32],
+    sample_rate: u32,
+    band: Range<f64>,
+    points_per_octave: u32,
+) -> Option<FrequencyResponse> {
+    if sent.is_empty() || received.is_empty() || band.start <= 0.0 || band.end <= band.start {
+        return None;
+    }
+    let size = (sent.len() + received.len()).next_power_of_two();
+    let mut forward = Transform::forward(size);
+    let mut transform = |samples: &[f32]| {
+        let mut buffer: Vec<Complex<f64>> = samples
+            .iter()
+            .map(|x| Complex::new(*x as f64, 0.0))
+            .chain(std::iter::repeat(Complex::default()))
+            .take(size)
+            .collect();
+        forward.process(&mut buffer);
+        buffer
+    };
+    let x = transform(sent);
+    let y = transform(received);
+
+    // The cross-correlation peaks where the sweep came back
+    let mut correlation: Vec<Complex<f64>> = y.iter().zip(&x).map(|(y, x)| y * x.conj()).collect();
+    Transform::inverse(size).process(&mut correlation);
+    let lag = correlation[..received.len()]
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.norm_sqr().total_cmp(&b.1.norm_sqr()))
+        .map_or(0, |(lag, _)| lag);
+
+    let bin_hz = sample_rate as f64 / size as f64;
+    let step = 2f64.powf(1.0 / points_per_octave.max(1) as f64);
+    let half = step.sqrt();
+    let mut points = Vec::new();
+    let mut centre = band.start;
+    while centre <= band.end * (1.0 + 1e-9) {
+        let low = ((centre / half / bin_hz).ceil() as usize).max(1);
+        let high = ((centre * half / bin_hz).floor() as usize).min(size / 2);
+        let bins = low..high.max(low + 1);
+        let sent_power: f64 = x[bins.clone()].iter().map(|c| c.norm_sqr()).sum();
+        let received_power: f64 = y[bins].iter().map(|c| c.norm_sqr()).sum();
+        if sent_power > 0.0 && received_power > 0.0 {
+            points.push((centre, 10.0 * (received_power / sent_power).log10()));
+        }
+        centre *= step;
+    }
+    Some(FrequencyResponse {
+        points,
+        latency: lag as f64 / sample_rate as f64,
+    })
+}
+
+/// The names of the output devices on the default host
+pub fn output_devices() -> Vec<String> {
+    default_host()
+        .output_devices()
+        .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// The names of the input devices on the default host
+pub fn input_devices() -> Vec<String> {
+    AudioInputDeviceBuilder::default()
+        .input_devices()
+        .map(|devices| {
+            devices
+                .iter()
+                .filter_map(|device| device.name().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn output_device(name: &str) -> Result<Device, Error> {
+    let host = default_host();
+    if name.is_empty() {
+        return host
+            .default_output_device()
+            .ok_or(Error::Audio(tools::Error::NoOutputDevice));
+    }
+    host.output_devices()?
+        .find(|device| device.name().is_ok_and(|device_name| device_name == name))
+        .ok_or_else(|| Error::NotFound(name.to_string()))
+}
+
+/// Play a sweep out of one device while recording through another, then work out the
+/// response of whatever is between them. Blocks for the length of the sweep and a bit.
+pub fn measure(settings: &SweepSettings) -> Result<FrequencyResponse, Error> {
+    let output = output_device(&settings.output_device)?;
+    let config = output
+        .default_output_config()
+        .map_err(tools::Error::from)?
+        .config();
+    let sample_rate = config.sample_rate.0;
+    let channels = config.channels as usize;
+    let band = settings.start_hz.max(1.0)
+        ..settings
+            .end_hz
+            .min(sample_rate as f64 * TOP_OF_BAND)
+            .max(settings.start_hz.max(1.0) * 2.0);
+    let sweep = log_sweep(
+        band.start,
+        band.end,
+        settings.seconds.max(0.5),
+        settings.level_db,
+        sample_rate,
+    );
+
+    // Record at the output's rate, so the two line up sample for sample
+    let input =
+        AudioInputDeviceBuilder::from_names("", &settings.input_device, sample_rate)?.build()?;
+    let input_channels = input.config.channels;
+    let recorded = Arc::new(Mutex::new(Vec::new()));
+    let recording = input
+        .device
+        .build_input_stream(
+            &input.config,
+            {
+                let recorded = recorded.clone();
+                move |data: &[f32], _info| recorded.lock().extend(downmix(data, input_channels))
+            },
+            |err| log::warn!("Error recording the sweep: {err}"),
+            None,
+        )
+        .map_err(tools::Error::from)?;
+
+    let lead_in = (LEAD_IN.as_secs_f64() * sample_rate as f64) as usize;
+    let mut sent = vec![0.0; lead_in];
+    sent.extend_from_slice(&sweep);
+    let finished = Arc::new(AtomicBool::new(false));
+    let playing = output
+        .build_output_stream(
+            &config,
+            {
+                let sent = sent.clone();
+                let finished = finished.clone();
+                let mut position = 0;
+                move |data: &mut [f32], _info| {
+                    for frame in data.chunks_mut(channels) {
+                        frame.fill(sent.get(position).copied().unwrap_or_default());
+                        position += 1;
+                    }
+                    if position >= sent.len() {
+                        finished.store(true, Ordering::Relaxed);
+                    }
+                }
+            },
+            |err| log::warn!("Error playing the sweep: {err}"),
+            None,
+        )
+        .map_err(tools::Error::from)?;
+    recording.play().map_err(tools::Error::from)?;
+    playing.play().map_err(tools::Error::from)?;
+
+    // Give up waiting on a stalled output well after it should have finished
+    let deadline = Duration::from_secs_f64(sent.len() as f64 / sample_rate as f64 * 2.0 + 2.0);
+    let started = std::time::Instant::now();
+    while !finished.load(Ordering::Relaxed) && started.elapsed() < deadline {
+        thread::sleep(Duration::from_millis(50));
+    }
+    thread::sleep(TAIL);
+    drop(playing);
+    drop(recording);
+
+    let received = std::mem::take(&mut *recorded.lock());
+    let peak = received.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+    if peak == 0.0 || 20.0 * (peak as f64).log10() < SILENCE_DB {
+        return Err(Error::Silent);
+    }
+    let mut response = frequency_response(
+        &sweep,
+        &received,
+        sample_rate,
+        band,
+        settings.points_per_octave,
+    )
+    .ok_or(Error::Silent)?;
+    response.latency = (response.latency - LEAD_IN.as_secs_f64()).max(0.0);
+    Ok(response)
+}
+
+/// A measurement running on a thread of its own
+pub struct SweepMeasurement {
+    thread: JoinHandle<Result<FrequencyResponse, Error>>,
+}
+
+impl SweepMeasurement {
+    pub fn start(settings: &SweepSettings) -> Self {
+        let settings = settings.clone();
+        Self {
+            thread: thread::spawn(move || measure(&settings)),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Wait for the measurement and take its result
+    pub fn finish(self) -> Result<FrequencyResponse, Error> {
+        self.thread.join().map_err(|_| Error::Panicked)?
+    }
+}
@@ -0,0 +1,250 @@
+use crate::{config::RigSettings, data::audio::Clip};
+use log::{info, warn};
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use thiserror::Error as ThisError;
+
+/// How long to wait for rigctld to answer before giving up on it
+const TIMEOUT: Duration = Duration::from_secs(2);
+/// How long to wait for rigctld to connect. Kept short, as it can't be cut short when
+/// recording stops.
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long each wait for an answer is, between checks on whether to stop waiting
+const READ_SLICE: Duration = Duration::from_millis(100);
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error talking to rigctld: {0}")]
+    IO(#[from] io::Error),
+    #[error("No address found for rigctld at {0}")]
+    NoAddress(String),
+    #[error("rigctld returned error {0}")]
+    Rig(i32),
+    #[error("Unexpected reply from rigctld: {0:?}")]
+    Reply(String),
+    #[error("Stopped waiting for rigctld")]
+    Stopped,
+}
+
+/// A connection to rigctld, the Hamlib rig control daemon, speaking its network protocol
+pub struct Rigctld {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    /// Set to give up waiting for an answer
+    stopping: Arc<AtomicBool>,
+}
+
+impl Rigctld {
+    pub fn connect(address: &str) -> Result<Self, Error> {
+        Self::connect_until(address, Arc::default())
+    }
+
+    /// Connect, giving up on any answer being waited for once `stopping` is set
+    pub fn connect_until(address: &str, stopping: Arc<AtomicBool>) -> Result<Self, Error> {
+        let addr = address
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::NoAddress(address.to_string()))?;
+        let writer = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+        writer.set_read_timeout(Some(READ_SLICE))?;
+        writer.set_write_timeout(Some(TIMEOUT))?;
+        Ok(Self {
+            reader: BufReader::new(writer.try_clone()?),
+            writer,
+            stopping,
+        })
+    }
+
+    /// A line of a reply, waited for a slice at a time so stopping doesn't wait on rigctld
+    fn read_line(&mut self) -> Result<String, Error> {
+        let mut line = Vec::new();
+        let waiting = Instant::now();
+        loop {
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) if line.is_empty() => {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                Ok(_) => break,
+                // Whatever came before the timeout is kept in the line
+                Err(error)
+                    if matches!(
+                        error.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    if self.stopping.load(Ordering::Relaxed) {
+                        return Err(Error::Stopped);
+                    }
+                    if waiting.elapsed() >= TIMEOUT {
+                        return Err(error.into());
+                    }
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+    }
+
+    /// Send a command and read back the lines of its reply. Errors come back as a single
+    /// RPRT line instead.
+    fn command(&mut self, command: &str, lines: usize) -> Result<Vec<String>, Error> {
+        self.writer.write_all(format!("{}\n", command).as_bytes())?;
+        let mut reply = Vec::with_capacity(lines);
+        while reply.len() < lines {
+            let line = self.read_line()?;
+            if let Some(code) = line.strip_prefix("RPRT ") {
+                let code = code
+                    .trim()
+                    .parse()
+                    .map_err(|_| Error::Reply(line.clone()))?;
+                return Err(Error::Rig(code));
+            }
+            reply.push(line);
+        }
+        Ok(reply)
+    }
+
+    /// The dial frequency in Hz
+    pub fn frequency(&mut self) -> Result<u64, Error> {
+        let reply = self.command("f", 1)?;
+        // Some rigs report a fractional frequency
+        reply[0]
+            .parse::<f64>()
+            .map(|hz| hz.round() as u64)
+            .map_err(|_| Error::Reply(reply[0].clone()))
+    }
+
+    /// The mode, like USB. rigctld sends the passband too, which we don't need.
+    pub fn mode(&mut self) -> Result<String, Error> {
+        let mut reply = self.command("m", 2)?;
+        Ok(reply.swap_remove(0))
+    }
+}
+
+/// Polls rigctld while a clip records, noting every change of frequency or mode in the
+/// clip's metadata. Stops when dropped, without waiting on rigctld to answer.
+pub struct RigTagger {
+    stop: Option<Sender<()>>,
+    stopping: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RigTagger {
+    pub fn start(settings: &RigSettings, clip: Clip) -> Result<Self, io::Error> {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let stopping = Arc::new(AtomicBool::new(false));
+        let address = settings.address.clone();
+        let poll = Duration::from_millis(settings.poll_ms.max(1));
+        let thread = thread::Builder::new()
+            .name("rig control".to_string())
+            .spawn({
+                let stopping = stopping.clone();
+                move || {
+                    let mut rig = None;
+                    let mut last = None;
+                    // Only complain when things change, not on every poll
+                    let mut failing = false;
+                    loop {
+                        let reading = match rig.as_mut() {
+                            Some(rig) => Ok(rig),
+                            None => Rigctld::connect_until(&address, stopping.clone())
+                                .map(|connected| rig.insert(connected)),
+                        }
+                        .and_then(|rig| Ok((rig.frequency()?, rig.mode()?)));
+                        match reading {
+                            Ok(reading) => {
+                                if failing {
+                                    info!("Reading the rig from rigctld at {} again", address);
+                                    failing = false;
+                                }
+                                if last.as_ref() != Some(&reading) {
+                                    let (frequency, mode) = reading.clone();
+                                    // Saved after letting go of the clip, so the writer isn't
+                                    // kept waiting on the disk
+                                    let save = clip.write().add_rig_reading(frequency, mode);
+                                    if let Err(error) = save.save() {
+                                        warn!("Unable to save rig reading: {}", error);
+                                    }
+                                    last = Some(reading);
+                                }
+                            }
+                            Err(Error::Stopped) => break,
+                            Err(error) => {
+                                if !failing {
+                                    warn!("Unable to read the rig from {}: {}", address, error);
+                                    failing = true;
+                                }
+                                // Start over with a fresh connection next time
+                                rig = None;
+                            }
+                        }
+                        match stopped.recv_timeout(poll) {
+                            Err(RecvTimeoutError::Timeout) => {}
+                            _ => break,
+                        }
+                    }
+                }
+            })?;
+        Ok(Self {
+            stop: Some(stop),
+            stopping,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for RigTagger {
+    fn drop(&mut self) {
+        // Hanging up wakes the thread between polls, and the flag stops it waiting on rigctld
+        self.stopping.store(true, Ordering::Relaxed);
+        self.stop.take();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            warn!("Rig control thread panicked");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data::audio::{BitDepth, ClipId, WavClip},
+        testutil::scratch_dir,
+    };
+    use chrono::Utc;
+    use parking_lot::RwLock;
+    use std::{fs, net::TcpListener};
+
+    #[test]
+    fn stopping_does_not_wait_on_a_rig_that_never_answers() {
+        let dir = scratch_dir("rig");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let settings = RigSettings {
+            enabled: true,
+            address: listener.local_addr().unwrap().to_string(),
+            poll_ms: 100,
+        };
+        let spec = BitDepth::Float32.wav_spec(8000);
+        let clip = WavClip::record_new(ClipId::from_datetimeutc(Utc::now()), &dir, spec).unwrap();
+        let tagger = RigTagger::start(&settings, Arc::new(RwLock::new(clip))).unwrap();
+        // Connected, and asked, but nothing ever comes back
+        let (_connection, _) = listener.accept().unwrap();
+        thread::sleep(Duration::from_millis(200));
+
+        let stopping = Instant::now();
+        drop(tagger);
+        assert!(stopping.elapsed() < TIMEOUT / 2, "{:?}", stopping.elapsed());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,116 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+};
+
+/// The samples both ends share. Each slot holds a sample's bits, so either end can get at
+/// them without a lock.
+struct Ring {
+    slots: Box<[AtomicU32]>,
+    /// Samples ever written and ever read. Only the writer moves `written` and only the
+    /// reader moves `read`, so neither ever waits for the other.
+    written: AtomicUsize,
+    read: AtomicUsize,
+    /// Set once the writing end has gone
+    closed: AtomicBool,
+}
+
+impl Ring {
+    fn len(&self) -> usize {
+        self.written.load(Ordering::Acquire) - self.read.load(Ordering::Acquire)
+    }
+}
+
+/// A wait-free buffer of `capacity` samples between one thread writing and another reading,
+/// for taking samples off an audio thread without allocating or locking
+pub fn sample_ring(capacity: usize) -> (RingWriter, RingReader) {
+    let ring = Arc::new(Ring {
+        slots: (0..capacity.max(1)).map(|_| AtomicU32::new(0)).collect(),
+        written: AtomicUsize::new(0),
+        read: AtomicUsize::new(0),
+        closed: AtomicBool::new(false),
+    });
+    (RingWriter { ring: ring.clone() }, RingReader { ring })
+}
+
+/// The end samples go in. Closes the ring when dropped.
+pub struct RingWriter {
+    ring: Arc<Ring>,
+}
+
+impl RingWriter {
+    /// Add all `len` samples, or none if there isn't room for them. Returns whether they
+    /// went in.
+    pub fn push_iter(&self, len: usize, samples: impl IntoIterator<Item = f32>) -> bool {
+        let ring = &self.ring;
+        let capacity = ring.slots.len();
+        let written = ring.written.load(Ordering::Relaxed);
+        if capacity - (written - ring.read.load(Ordering::Acquire)) < len {
+            return false;
+        }
+        for (index, sample) in samples.into_iter().take(len).enumerate() {
+            ring.slots[(written + index) % capacity].store(sample.to_bits(), Ordering::Relaxed);
+        }
+        ring.written.store(written + len, Ordering::Release);
+        true
+    }
+}
+
+impl Drop for RingWriter {
+    fn drop(&mut self) {
+        self.ring.closed.store(true, Ordering::Release);
+    }
+}
+
+/// The end samples come out
+pub struct RingReader {
+    ring: Arc<Ring>,
+}
+
+impl RingReader {
+    /// Samples waiting to be read
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the writer has gone and everything it wrote has been read
+    pub fn is_finished(&self) -> bool {
+        self.ring.closed.load(Ordering::Acquire) && self.is_empty()
+    }
+
+    /// The oldest sample, if there is one
+    pub fn pop(&self) -> Option<f32> {
+        let ring = &self.ring;
+        let read = ring.read.load(Ordering::Relaxed);
+        if ring.written.load(Ordering::Acquire) == read {
+            return None;
+        }
+        let sample = f32::from_bits(ring.slots[read % ring.slots.len()].load(Ordering::Relaxed));
+        ring.read.store(read + 1, Ordering::Release);
+        Some(sample)
+    }
+
+    /// Move up to `max` of the oldest samples onto the end of `out`. Returns how many.
+    pub fn pop_into(&self, out: &mut Vec<f32>, max: usize) -> usize {
+        let ring = &self.ring;
+        let read = ring.read.load(Ordering::Relaxed);
+        let count = (ring.written.load(Ordering::Acquire) - read).min(max);
+        out.extend((read..read + count).map(|index| {
+            f32::from_bits(ring.slots[index % ring.slots.len()].load(Ordering::Relaxed))
+        }));
+        ring.read.store(read + count, Ordering::Release);
+        count
+    }
+
+    /// Throw away up to `count` of the oldest samples
+    pub fn skip(&self, count: usize) {
+        let ring = &self.ring;
+        let read = ring.read.load(Ordering::Relaxed);
+        let count = (ring.written.load(Ordering::Acquire) - read).min(count);
+        ring.read.store(read + count, Ordering::Release);
+    }
+}
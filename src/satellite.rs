@@ -0,0 +1,125 @@
+use chrono::{DateTime, Utc};
+use sgp4::{Constants, Elements};
+use thiserror::Error as ThisError;
+
+/// WGS84 equatorial radius, km
+const EARTH_RADIUS: f64 = 6378.137;
+/// WGS84 flattening
+const EARTH_FLATTENING: f64 = 1.0 / 298.257_223_563;
+/// How fast the earth turns, radians per second
+const EARTH_ROTATION: f64 = 7.292_115e-5;
+/// Speed of light, km/s
+const LIGHT_SPEED: f64 = 299_792.458;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("No TLE found, expected two lines, or three with the name first")]
+    NoTle,
+    #[error("Unable to read the TLE: {0}")]
+    Tle(#[from] sgp4::TleError),
+    #[error("Unusable orbital elements: {0}")]
+    Elements(#[from] sgp4::ElementsError),
+    #[error("Unable to predict the orbit: {0}")]
+    Propagation(#[from] sgp4::Error),
+    #[error("Too far from the TLE's epoch: {0}")]
+    Epoch(#[from] sgp4::DatetimeToMinutesSinceEpochError),
+}
+
+/// Where a satellite is, as seen from the ground
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Look {
+    /// Degrees above the horizon, negative below it
+    pub elevation: f64,
+    /// Distance in km
+    pub range: f64,
+    /// How fast it's getting further away, in km/s. Negative as it approaches.
+    pub range_rate: f64,
+}
+
+impl Look {
+    /// How far off a signal sent on `frequency` arrives, in the same units
+    pub fn doppler(&self, frequency: f64) -> f64 {
+        -frequency * self.range_rate / LIGHT_SPEED
+    }
+}
+
+/// A satellite's orbit, from its two-line elements
+pub struct Satellite {
+    pub name: Option<String>,
+    elements: Elements,
+    constants: Constants,
+}
+
+impl Satellite {
+    /// Read a TLE, two lines or three with the name first. Anything after it is ignored.
+    pub fn from_tle(tle: &str) -> Result<Self, Error> {
+        let lines: Vec<&str> = tle
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        let (name, line1, line2) = match lines.as_slice() {
+            [line1, line2, ..] if line1.starts_with("1 ") => (None, line1, line2),
+            // Some sources put a 0 in front of the name, like line numbers
+            [name, line1, line2, ..] => (
+                Some(name.strip_prefix("0 ").unwrap_or(name).to_string()),
+                line1,
+                line2,
+            ),
+            _ => return Err(Error::NoTle),
+        };
+        let elements = Elements::from_tle(name.clone(), line1.as_bytes(), line2.as_bytes())?;
+        let constants = Constants::from_elements(&elements)?;
+        Ok(Self {
+            name,
+            elements,
+            constants,
+        })
+    }
+
+    /// Where the satellite is at `time`, seen from sea level at a latitude and longitude
+    pub fn look(&self, latitude: f64, longitude: f64, time: DateTime<Utc>) -> Result<Look, Error> {
+        let time = time.naive_utc();
+        let minutes = self.elements.datetime_to_minutes_since_epoch(&time)?;
+        let prediction = self.constants.propagate(minutes)?;
+
+        // Turn the prediction from the inertial frame into one that turns with the earth,
+        // where the station stays put
+        let sidereal = sgp4::iau_epoch_to_sidereal_time(sgp4::julian_years_since_j2000(&time));
+        let (sin, cos) = sidereal.sin_cos();
+        let [x, y, z] = prediction.position;
+        let [vx, vy, vz] = prediction.velocity;
+        let position = [cos * x + sin * y, cos * y - sin * x, z];
+        let velocity = [
+            cos * vx + sin * vy + EARTH_ROTATION * position[1],
+            cos * vy - sin * vx - EARTH_ROTATION * position[0],
+            vz,
+        ];
+
+        let (lat, lon) = (latitude.to_radians(), longitude.to_radians());
+        let station = station_position(lat, lon);
+        let offset: [f64; 3] = std::array::from_fn(|i| position[i] - station[i]);
+        let range = dot(&offset, &offset).sqrt();
+        let up = [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()];
+        Ok(Look {
+            elevation: (dot(&offset, &up) / range).asin().to_degrees(),
+            range,
+            range_rate: dot(&offset, &velocity) / range,
+        })
+    }
+}
+
+/// Where a point at sea level is, in km from the middle of the earth, turning with it
+fn station_position(lat: f64, lon: f64) -> [f64; 3] {
+    let e2 = EARTH_FLATTENING * (2.0 - EARTH_FLATTENING);
+    let n = EARTH_RADIUS / (1.0 - e2 * lat.sin().powi(2)).sqrt();
+    [
+        n * lat.cos() * lon.cos(),
+        n * lat.cos() * lon.sin(),
+        n * (1.0 - e2) * lat.sin(),
+    ]
+}
+
+fn dot(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
@@ -0,0 +1,445 @@
+//! Automating the recorder with [Rhai](https://rhai.rs) scripts. A script's top level runs
+//! once when it's loaded, then whichever of these it defines are called as things happen:
+//!
+//! - `on_tick()` every time the script is ticked
+//! - `on_squelch_open(level_db)` when the input gets louder than the squelch
+//! - `on_squelch_close()` when it drops back below
+//! - `on_decode(mode, text, frequency_hz)` for each line of text decoded
+//!
+//! and it can call:
+//!
+//! - `record()`, `record_for(seconds)` and `stop()`
+//! - `is_recording()`
+//! - `level_db()`, the loudest the input has been since the last tick, in dBFS
+//! - `set_squelch(db)`, -60 dBFS to begin with
+//! - `export_selection(start, end, path)`, seconds into the latest clip saved as a WAV file,
+//!   relative to the session directory
+//! - `run_decoder(mode, start, end)`, opening that stretch in the decoder set up for the
+//!   mode
+//!
+//! Recording 30 seconds whenever a carrier appears above -80 dB:
+//!
+//! ```rhai
+//! set_squelch(-80);
+//! fn on_squelch_open(level) { record_for(30); }
+//! ```
+
+use crate::{
+    config::{ExternalDecoder, Settings},
+    data::audio::{BitDepth, ClipId, WavClip},
+    events::DecodeEvent,
+    progress,
+    resample::resample,
+    session::{self, Session},
+    tools::LevelMeter,
+};
+use log::{info, warn};
+use parking_lot::Mutex;
+use rhai::{AST, Dynamic, Engine, EvalAltResult, FuncArgs, ParseError, Scope};
+use std::{
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicU32, Ordering},
+        mpsc::{self, Receiver},
+    },
+    time::{Duration, Instant},
+};
+use thiserror::Error as ThisError;
+
+/// Where the squelch starts out, in dBFS
+const DEFAULT_SQUELCH_DB: f64 = -60.0;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Unable to read script: {0}")]
+    IO(#[from] io::Error),
+    #[error("Error in script: {0}")]
+    Parse(#[from] ParseError),
+    #[error("Script failed: {0}")]
+    Run(#[from] Box<EvalAltResult>),
+    #[error("{0}")]
+    Session(#[from] session::Error),
+    #[error("No {0} decoder is set up")]
+    NoDecoder(String),
+    #[error("Nothing has been recorded yet")]
+    NoClip,
+}
+
+/// What a script asked for, done on the next tick with the session to hand
+#[derive(Clone)]
+enum Command {
+    Record,
+    RecordFor(Duration),
+    Stop,
+    Export(Range<f64>, PathBuf),
+    RunDecoder(String, Range<f64>),
+}
+
+/// What the functions a script calls share with the rest of it
+struct Shared {
+    commands: Vec<Command>,
+    recording: bool,
+    level_db: f64,
+    squelch_db: f64,
+}
+
+/// Seconds given as either a whole number or not
+fn seconds(value: Dynamic) -> Result<f64, Box<EvalAltResult>> {
+    let type_name = value.type_name();
+    value
+        .as_float()
+        .or_else(|_| value.as_int().map(|seconds| seconds as f64))
+        .map_err(|_| format!("Expected a number of seconds, not {}", type_name).into())
+}
+
+/// A script running the recorder. It's ticked by whatever polls the session, which is when
+/// its hooks are called and what it asked for is done.
+pub struct Script {
+    path: PathBuf,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    shared: Arc<Mutex<Shared>>,
+    decodes: Receiver<DecodeEvent>,
+    /// The loudest sample recorded since the last tick, as f32 bits
+    recorded_peak: Arc<AtomicU32>,
+    /// For the level while not recording
+    meter: Option<LevelMeter>,
+    squelch_open: bool,
+    /// When to stop the recording `record_for` started
+    stop_at: Option<Instant>,
+    /// The clip being recorded, or the last one
+    latest_clip: Arc<Mutex<Option<ClipId>>>,
+    decoders: Vec<ExternalDecoder>,
+    bit_depth: BitDepth,
+}
+
+impl Script {
+    /// Load a script to run `session`, running its top level
+    pub fn load(path: &Path, session: &Session, settings: &Settings) -> Result<Self, Error> {
+        let source = fs::read_to_string(path)?;
+        Self::compile(path, &source, session, settings)
+    }
+
+    fn compile(
+        path: &Path,
+        source: &str,
+        session: &Session,
+        settings: &Settings,
+    ) -> Result<Self, Error> {
+        let shared = Arc::new(Mutex::new(Shared {
+            commands: Vec::new(),
+            recording: session.is_recording(),
+            level_db: f64::NEG_INFINITY,
+            squelch_db: DEFAULT_SQUELCH_DB,
+        }));
+        let engine = Self::engine(path, &shared);
+        let ast = engine.compile(source)?;
+
+        let (sender, decodes) = mpsc::channel();
+        let recorded_peak = Arc::new(AtomicU32::new(0));
+        let latest_clip = Arc::new(Mutex::new(
+            session
+                .recording_clip()
+                .map(|clip| clip.read().id().clone()),
+        ));
+        let observers = session.observers();
+        // There's no taking these off again, they go quiet once the script is dropped
+        observers.on_decode(move |event| {
+            let _ = sender.send(event.clone());
+        });
+        observers.on_samples({
+            let peak = recorded_peak.clone();
+            move |samples, _| {
+                let loudest = samples.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+                peak.fetch_max(loudest.to_bits(), Ordering::Relaxed);
+            }
+        });
+        observers.on_clip_started({
+            let latest_clip = latest_clip.clone();
+            move |id| *latest_clip.lock() = Some(id.clone())
+        });
+
+        let mut script = Self {
+            path: path.to_path_buf(),
+            engine,
+            ast,
+            scope: Scope::new(),
+            shared,
+            decodes,
+            recorded_peak,
+            meter: None,
+            squelch_open: false,
+            stop_at: None,
+            latest_clip,
+            decoders: settings.decoders.clone(),
+            bit_depth: settings.recording.bit_depth,
+        };
+        script
+            .engine
+            .run_ast_with_scope(&mut script.scope, &script.ast)?;
+        info!("Running script {:?}", path.as_os_str());
+        Ok(script)
+    }
+
+    fn engine(path: &Path, shared: &Arc<Mutex<Shared>>) -> Engine {
+        let mut engine = Engine::new();
+        let name = path.display().to_string();
+        engine.on_print(move |text| info!("{}: {}", name, text));
+        let name = path.display().to_string();
+        engine.on_debug(move |text, _, position| info!("{} {}: {}", name, position, text));
+
+        let command = |command: Command| {
+            let shared = shared.clone();
+            move || shared.lock().commands.push(command.clone())
+        };
+        engine.register_fn("record", command(Command::Record));
+        engine.register_fn("stop", command(Command::Stop));
+        let shared_ = shared.clone();
+        engine.register_fn("record_for", move |length: Dynamic| {
+            let length = seconds(length)?.max(0.0);
+            shared_
+                .lock()
+                .commands
+                .push(Command::RecordFor(Duration::from_secs_f64(length)));
+            Ok::<_, Box<EvalAltResult>>(())
+        });
+        let shared_ = shared.clone();
+        engine.register_fn("is_recording", move || shared_.lock().recording);
+        let shared_ = shared.clone();
+        engine.register_fn("level_db", move || shared_.lock().level_db);
+        let shared_ = shared.clone();
+        engine.register_fn("set_squelch", move |db: Dynamic| {
+            shared_.lock().squelch_db = seconds(db)?;
+            Ok::<_, Box<EvalAltResult>>(())
+        });
+        let shared_ = shared.clone();
+        engine.register_fn(
+            "export_selection",
+            move |start: Dynamic, end: Dynamic, path: &str| {
+                let range = seconds(start)?..seconds(end)?;
+                shared_
+                    .lock()
+                    .commands
+                    .push(Command::Export(range, PathBuf::from(path)));
+                Ok::<_, Box<EvalAltResult>>(())
+            },
+        );
+        let shared_ = shared.clone();
+        engine.register_fn(
+            "run_decoder",
+            move |mode: &str, start: Dynamic, end: Dynamic| {
+                let range = seconds(start)?..seconds(end)?;
+                shared_
+                    .lock()
+                    .commands
+                    .push(Command::RunDecoder(mode.to_string(), range));
+                Ok::<_, Box<EvalAltResult>>(())
+            },
+        );
+        engine
+    }
+
+    /// Call a hook, if the script defines it
+    fn call(&mut self, hook: &str, args: impl FuncArgs) {
+        let mut values = Vec::new();
+        args.parse(&mut values);
+        if !self
+            .ast
+            .iter_functions()
+            .any(|function| function.name == hook && function.params.len() == values.len())
+        {
+            return;
+        }
+        if let Err(error) = self
+            .engine
+            .call_fn::<Dynamic>(&mut self.scope, &self.ast, hook, values)
+        {
+            warn!("{:?} failed in {}: {}", self.path.as_os_str(), hook, error);
+        }
+    }
+
+    /// The loudest the input's been since the last tick, listening to it while it's not
+    /// being recorded
+    fn take_level(&mut self, session: &Session) -> f64 {
+        let peak = if session.is_recording() {
+            // The recording has the device now
+            self.meter = None;
+            f32::from_bits(self.recorded_peak.swap(0, Ordering::Relaxed))
+        } else {
+            if self.meter.is_none()
+                && let Some(input) = session.configuration()
+            {
+                self.meter = LevelMeter::new(&input)
+                    .inspect_err(|error| warn!("Unable to listen to the input: {}", error))
+                    .ok();
+            }
+            self.meter.as_ref().map_or(0.0, LevelMeter::take_peak)
+        };
+        20.0 * (peak as f64).log10()
+    }
+
+    /// Call the hooks for whatever's happened since the last tick, then do what the script
+    /// asked for
+    pub fn tick(&mut self, session: &mut Session) {
+        let level_db = self.take_level(session);
+        let squelch_db = {
+            let mut shared = self.shared.lock();
+            shared.recording = session.is_recording();
+            shared.level_db = level_db;
+            shared.squelch_db
+        };
+        let open = level_db >= squelch_db;
+        if open != self.squelch_open {
+            self.squelch_open = open;
+            if open {
+                self.call("on_squelch_open", (level_db,));
+            } else {
+                self.call("on_squelch_close", ());
+            }
+        }
+        while let Ok(decode) = self.decodes.try_recv() {
+            let frequency = decode.audio_frequency.map_or(0.0, f64::from);
+            self.call("on_decode", (decode.mode, decode.text, frequency));
+        }
+        self.call("on_tick", ());
+
+        let commands = std::mem::take(&mut self.shared.lock().commands);
+        for command in commands {
+            if let Err(error) = self.run(command, session) {
+                warn!("{:?}: {}", self.path.as_os_str(), error);
+            }
+        }
+        if self
+            .stop_at
+            .is_some_and(|stop_at| Instant::now() >= stop_at)
+        {
+            self.stop_at = None;
+            if let Err(error) = session.stop_recording() {
+                warn!("{:?}: {}", self.path.as_os_str(), error);
+            }
+        }
+        self.shared.lock().recording = session.is_recording();
+    }
+
+    fn run(&mut self, command: Command, session: &mut Session) -> Result<(), Error> {
+        match command {
+            Command::Record => {
+                self.stop_at = None;
+                if !session.is_recording() {
+                    session.record_new_clip()?;
+                }
+            }
+            Command::RecordFor(length) => {
+                // Asking again while it's recording keeps it going
+                let stop_at = Instant::now() + length;
+                self.stop_at = Some(self.stop_at.map_or(stop_at, |at| at.max(stop_at)));
+                if !session.is_recording() {
+                    session.record_new_clip()?;
+                }
+            }
+            Command::Stop => {
+                self.stop_at = None;
+                session.stop_recording()?;
+            }
+            Command::Export(range, path) => {
+                let path = session.path.join(path);
+                let (samples, rate) = self.latest_samples(session, range)?;
+                WavClip::export_samples(&samples, &path, self.bit_depth.wav_spec(rate))
+                    .map_err(session::Error::from)?;
+                info!("Exported {:?}", path.as_os_str());
+            }
+            Command::RunDecoder(mode, range) => {
+                let decoder = self
+                    .decoders
+                    .iter()
+                    .find(|decoder| decoder.mode.to_string().eq_ignore_ascii_case(&mode))
+                    .ok_or(Error::NoDecoder(mode))?;
+                let (samples, rate) = self.latest_samples(session, range.clone())?;
+                let to = decoder.sample_rate.unwrap_or(rate);
+                let samples = resample(&samples, 1, rate, to, &progress::ignore);
+                let id = self.latest_clip.lock().clone().ok_or(Error::NoClip)?;
+                let path = std::env::temp_dir().join(format!("{}-{:.0}.wav", id, range.start));
+                WavClip::export_samples(&samples, &path, BitDepth::Int16.wav_spec(to))
+                    .map_err(session::Error::from)?;
+                decoder.launch(&path)?;
+                info!("Opened {:?} in {}", path.as_os_str(), decoder.program);
+            }
+        }
+        Ok(())
+    }
+
+    /// Part of the latest clip, given in seconds, with its sample rate
+    fn latest_samples(
+        &self,
+        session: &Session,
+        range: Range<f64>,
+    ) -> Result<(Vec<f32>, u32), Error> {
+        let id = self.latest_clip.lock().clone().ok_or(Error::NoClip)?;
+        let (fetch, rate) = {
+            let clip = session.clips.get(&id).ok_or(Error::NoClip)?.read();
+            let rate = clip.sample_rate.0;
+            let len = clip.samples.len();
+            let at = |seconds: f64| ((seconds.max(0.0) * rate as f64) as usize).min(len);
+            let range = at(range.start)..at(range.end).max(at(range.start));
+            (clip.samples.fetch(range), rate)
+        };
+        Ok((fetch.read(), rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testutil::scratch_dir, virtualinput::VirtualInput};
+
+    #[test]
+    fn decodes_can_start_a_recording() {
+        let dir = scratch_dir("script");
+        let settings = Settings::default();
+        let input = VirtualInput::new("Test", 8000, 1, vec![0.5; 8000]);
+        let mut session = Session::open(dir.clone(), &settings).unwrap();
+        session.configure_virtual(input.clone()).unwrap();
+        let source = r#"
+            set_squelch(-10);
+            fn on_decode(mode, text, frequency) {
+                if text.contains("CQ") { record_for(60); }
+            }
+            fn on_squelch_open(level) { export_selection(0, 0.25, "quarter.wav"); }
+        "#;
+        let mut script =
+            Script::compile(Path::new("test.rhai"), source, &session, &settings).unwrap();
+
+        script.tick(&mut session);
+        assert!(!session.is_recording());
+        session.observers().decoded(&DecodeEvent {
+            time: session.time_source().now(),
+            mode: "CW".to_string(),
+            audio_frequency: Some(700.0),
+            clip: None,
+            text: "CQ CQ DE W1AW".to_string(),
+        });
+        script.tick(&mut session);
+        assert!(session.is_recording());
+
+        // Half a second at -6 dBFS opens the squelch
+        input.advance(Duration::from_millis(500));
+        while session
+            .recording_clip()
+            .is_some_and(|clip| clip.read().samples.len() < 2000)
+        {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        script.tick(&mut session);
+        let exported = hound::WavReader::open(dir.join("quarter.wav")).unwrap();
+        assert_eq!(exported.len(), 2000);
+        drop(exported);
+
+        session.stop_recording().unwrap();
+        drop(session);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
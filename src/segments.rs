@@ -0,0 +1,149 @@
+use crate::{
+    config::{SegmentSettings, VadSettings},
+    data::{audio::Clip, metadata::Qso},
+    progress::Progress,
+    vad::SpeechDetector,
+};
+use std::ops::Range;
+
+/// Frames per second squelch is judged in
+const FRAMES_PER_SECOND: u32 = 50;
+/// The percentile of frame energies taken as the floor, the closed squelch or the band noise
+const FLOOR_PERCENTILE: f64 = 0.1;
+/// Digital silence has no level, so the floor is taken to be no lower than this, in dB
+const SILENCE_DB: f64 = -120.0;
+/// Samples read from a clip at a time, so recording isn't held up for long
+const CHUNK: usize = 1 << 16;
+
+/// Splits a long recording into transmissions and the transmissions into QSOs. A
+/// transmission is where the squelch was open, from the level of the whole band against its
+/// floor, or where someone was talking, from the speech detector, whichever catches it.
+/// Feed it a clip's samples in order, in pieces of any size, then ask for the QSOs.
+pub struct Segmenter {
+    settings: SegmentSettings,
+    sample_rate: u32,
+    speech: SpeechDetector,
+    frame_len: usize,
+    /// Samples waiting for a frame to fill
+    pending: Vec<f32>,
+    /// Energy of each frame, in dB
+    frames: Vec<f64>,
+}
+
+impl Segmenter {
+    pub fn new(settings: &SegmentSettings, vad: &VadSettings, sample_rate: u32) -> Self {
+        let frame_len = (sample_rate / FRAMES_PER_SECOND).max(1) as usize;
+        Self {
+            settings: settings.clone(),
+            sample_rate,
+            speech: SpeechDetector::new(vad, sample_rate),
+            frame_len,
+            pending: Vec::with_capacity(frame_len),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, mut samples: &[f32]) {
+        self.speech.push(samples);
+        while !samples.is_empty() {
+            let take = (self.frame_len - self.pending.len()).min(samples.len());
+            self.pending.extend_from_slice(&samples[..take]);
+            samples = &samples[take..];
+            if self.pending.len() < self.frame_len {
+                break;
+            }
+            let power =
+                self.pending.iter().map(|x| (x * x) as f64).sum::<f64>() / self.frame_len as f64;
+            self.frames.push((10.0 * power.log10()).max(SILENCE_DB));
+            self.pending.clear();
+        }
+    }
+
+    fn samples_in(&self, ms: u64) -> usize {
+        (ms * self.sample_rate as u64 / 1000) as usize
+    }
+
+    /// Where the squelch was open, in samples
+    fn squelch_open(&self) -> Vec<Range<usize>> {
+        if self.frames.is_empty() {
+            return Vec::new();
+        }
+        let mut energies = self.frames.clone();
+        energies.sort_by(f64::total_cmp);
+        let floor = energies[((energies.len() - 1) as f64 * FLOOR_PERCENTILE) as usize];
+        let threshold = floor + self.settings.squelch_db;
+        let mut open: Vec<Range<usize>> = Vec::new();
+        for (index, energy) in self.frames.iter().enumerate() {
+            if *energy < threshold {
+                continue;
+            }
+            let start = index * self.frame_len;
+            match open.last_mut() {
+                Some(last) if last.end == start => last.end = start + self.frame_len,
+                _ => open.push(start..start + self.frame_len),
+            }
+        }
+        open
+    }
+
+    /// The QSOs found, each with its transmissions, in order
+    pub fn finish(self) -> Vec<Qso> {
+        let hangover = self.samples_in(self.settings.hangover_ms);
+        let shortest = self.samples_in(self.settings.min_transmission_ms);
+        let gap = self.samples_in(self.settings.qso_gap_ms);
+        let mut active = self.squelch_open();
+        active.extend(self.speech.finish());
+        active.sort_by_key(|range| range.start);
+
+        // Join what overlaps or is only briefly apart into transmissions
+        let mut transmissions: Vec<Range<usize>> = Vec::new();
+        for range in active {
+            match transmissions.last_mut() {
+                Some(last) if range.start <= last.end + hangover => {
+                    last.end = last.end.max(range.end)
+                }
+                _ => transmissions.push(range),
+            }
+        }
+        transmissions.retain(|transmission| transmission.len() >= shortest);
+
+        // And transmissions into QSOs, when the next comes soon enough to be a reply
+        let mut qsos: Vec<Qso> = Vec::new();
+        for transmission in transmissions {
+            match qsos.last_mut() {
+                Some(qso) if transmission.start <= qso.range.end + gap => {
+                    qso.range.end = transmission.end;
+                    qso.transmissions.push(transmission);
+                }
+                _ => qsos.push(Qso {
+                    range: transmission.clone(),
+                    transmissions: vec![transmission],
+                }),
+            }
+        }
+        qsos
+    }
+}
+
+/// Find the QSOs in the whole of a clip, a piece at a time
+pub fn segment_clip(
+    clip: &Clip,
+    settings: &SegmentSettings,
+    vad: &VadSettings,
+    progress: Progress,
+) -> Vec<Qso> {
+    let mut segmenter = Segmenter::new(settings, vad, clip.read().sample_rate.0);
+    let mut position = 0;
+    loop {
+        let clip = clip.read();
+        let len = clip.samples.len();
+        let end = (position + CHUNK).min(len);
+        if position >= end {
+            break;
+        }
+        segmenter.push(&clip.samples.range(position..end));
+        position = end;
+        progress(position as f32 / len as f32);
+    }
+    segmenter.finish()
+}
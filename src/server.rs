@@ -0,0 +1,176 @@
+//! Remote control over HTTP, for a recorder running headless somewhere out of reach.
+//!
+//! - `GET /status`, `GET /clips`: what the recorder is up to, and what it's recorded
+//! - `GET /level`: the peak level since last asked, 0 to 1
+//! - `POST /start`, `/stop`, `/pause`, `/resume`: control the recording
+//! - `GET /events`: a WebSocket of events as JSON, as they happen. Levels are left out,
+//!   there are far too many of them.
+
+use crate::{
+    asynchronous::{self, AsyncHamShark, Event},
+    config::ServerSettings,
+    session,
+};
+use axum::{
+    Json, Router,
+    extract::{
+        Request, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{StatusCode, header::AUTHORIZATION},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use log::info;
+use parking_lot::Mutex;
+use serde_json::json;
+use std::{io, pin::pin, sync::Arc};
+use tokio::net::TcpListener;
+use tokio_stream::StreamExt;
+
+#[derive(Clone)]
+struct AppState {
+    hamshark: AsyncHamShark,
+    token: Option<Arc<str>>,
+    /// The loudest level since it was last fetched
+    peak: Arc<Mutex<f32>>,
+}
+
+/// An engine error, as an HTTP response
+struct ApiError(asynchronous::Error);
+
+impl From<asynchronous::Error> for ApiError {
+    fn from(error: asynchronous::Error) -> Self {
+        Self(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self.0 {
+            asynchronous::Error::Session(
+                session::Error::AlreadyRecording() | session::Error::NoAudioConfiguration(),
+            ) => StatusCode::CONFLICT,
+            asynchronous::Error::Stopped => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, Json(json!({ "error": self.0.to_string() }))).into_response()
+    }
+}
+
+/// Serve remote control of `hamshark` until `shutdown` completes
+pub async fn serve(
+    hamshark: AsyncHamShark,
+    settings: ServerSettings,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<(), io::Error> {
+    let peak = Arc::new(Mutex::new(0.0f32));
+    tokio::spawn({
+        let peak = peak.clone();
+        let events = hamshark.events();
+        async move {
+            let mut events = pin!(events);
+            while let Some(event) = events.next().await {
+                if let Event::Level(level) = event {
+                    let mut peak = peak.lock();
+                    *peak = peak.max(level);
+                }
+            }
+        }
+    });
+
+    let state = AppState {
+        hamshark,
+        token: (!settings.token.is_empty()).then(|| settings.token.as_str().into()),
+        peak,
+    };
+    let app = Router::new()
+        .route("/status", get(status))
+        .route("/clips", get(clips))
+        .route("/level", get(level))
+        .route("/start", post(start))
+        .route("/stop", post(stop))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/events", get(events))
+        .layer(middleware::from_fn_with_state(state.clone(), authorize))
+        .with_state(state);
+
+    let listener = TcpListener::bind(&settings.listen).await?;
+    info!("Remote control listening on {}", listener.local_addr()?);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown)
+        .await
+}
+
+/// Turn away anyone without the token, if there is one. WebSocket clients in a browser
+/// can't set headers, so it can come in the query too.
+async fn authorize(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if let Some(token) = &state.token {
+        let bearer = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let query = request.uri().query().and_then(|query| {
+            query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("token="))
+        });
+        if bearer.or(query) != Some(token.as_ref()) {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+    next.run(request).await
+}
+
+async fn status(State(state): State<AppState>) -> Result<Response, ApiError> {
+    Ok(Json(state.hamshark.status().await?).into_response())
+}
+
+async fn clips(State(state): State<AppState>) -> Result<Response, ApiError> {
+    Ok(Json(state.hamshark.clips().await?).into_response())
+}
+
+async fn level(State(state): State<AppState>) -> Response {
+    let peak = std::mem::take(&mut *state.peak.lock());
+    Json(json!({ "peak": peak })).into_response()
+}
+
+async fn start(State(state): State<AppState>) -> Result<Response, ApiError> {
+    let clip = state.hamshark.start().await?;
+    Ok(Json(json!({ "clip": clip })).into_response())
+}
+
+async fn stop(State(state): State<AppState>) -> Result<StatusCode, ApiError> {
+    state.hamshark.stop().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn pause(State(state): State<AppState>) -> Result<StatusCode, ApiError> {
+    state.hamshark.pause().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn resume(State(state): State<AppState>) -> Result<StatusCode, ApiError> {
+    state.hamshark.resume().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn events(State(state): State<AppState>, upgrade: WebSocketUpgrade) -> Response {
+    let events = state.hamshark.events();
+    upgrade.on_upgrade(move |socket| send_events(socket, events))
+}
+
+async fn send_events(mut socket: WebSocket, events: impl tokio_stream::Stream<Item = Event>) {
+    let mut events = pin!(events.filter(|event| !matches!(event, Event::Level(_))));
+    while let Some(event) = events.next().await {
+        let Ok(json) = serde_json::to_string(&event) else {
+            continue;
+        };
+        if socket.send(Message::Text(json.into())).await.is_err() {
+            break;
+        }
+    }
+}
@@ -1,31 +1,46 @@
+#[cfg(feature = "jack")]
+use crate::jack::{self, JackInfo, JackInput};
 use crate::{
-    config::Settings,
+    clock::ClockMonitor,
+    config::{Configuration, JackConnection, Settings},
+    contest::ContestListener,
     data::{
         audio::{self, Clip, ClipId, WavClip},
-        audioinput::AudioInputDevice,
+        audioinput::{self, AudioInputDevice, AudioInputDeviceBuilder, DevicePreset},
+        metadata::{ClipMetadata, Location},
     },
-    gui::audio::{ClipExplorer, OpenClips},
+    decodes::DecodeOutput,
+    events::Observers,
+    gps::GpsTracker,
+    noisefloor::NoiseFloorLogger,
+    pipeline::Pipeline,
+    rig::RigTagger,
+    status::Status,
+    timesource::{self, SharedTimeSource},
     tools::{self, SampleRecorder},
+    vban::{self, VbanReceiver},
+    virtualinput::VirtualInput,
 };
-use chrono::Local;
-use hound::{SampleFormat, WavSpec};
-use log::{debug, error, info};
-use parking_lot::RwLock;
-use rustfft::{Fft, FftPlanner, num_complex::Complex};
-use std::{fs, io};
+use cpal::traits::DeviceTrait;
+use hound::WavSpec;
+use log::{debug, error, info, warn};
+use parking_lot::{Mutex, RwLock};
+use std::{collections::BTreeMap, fs, io};
 use std::{
     path::{Path, PathBuf},
     sync::Arc,
 };
 use thiserror::Error as ThisError;
 
+/// Session metadata, in the session directory
 const SESSIONFILE: &str = "session.toml";
-const FFTSIZE: usize = 128;
 
 #[derive(Debug, ThisError)]
 pub enum Error {
     #[error("Tried to record new clip but was already recording")]
     AlreadyRecording(),
+    #[error("Not recording")]
+    NotRecording(),
     #[error("No audio configuration provided")]
     NoAudioConfiguration(),
     #[error("Error creating clip: {0}")]
@@ -36,59 +51,274 @@ pub enum Error {
     Audio(#[from] audio::Error),
     #[error("IO Error: {0}")]
     IO(#[from] io::Error),
+    #[error("A clip named {0} is already in the session")]
+    AlreadyImported(PathBuf),
+    #[error("Audio Input Error: {0}")]
+    AudioInput(#[from] audioinput::Error),
+    #[error("VBAN Error: {0}")]
+    Vban(#[from] vban::Error),
+    #[cfg(feature = "jack")]
+    #[error("{0}")]
+    Jack(#[from] jack::Error),
 }
 
-pub type Frequencies = Arc<RwLock<Vec<Vec<Complex<f32>>>>>;
+/// What a new clip is recorded from
+enum Input {
+    Vban(VbanReceiver),
+    #[cfg(feature = "jack")]
+    Jack(JackInput),
+    Virtual(VirtualInput),
+    Device,
+    /// The input device, with this microphone mixed in
+    Mixed(AudioInputDevice),
+}
 
 pub struct Session {
     pub path: PathBuf,
-    pub clips: OpenClips,
+    pub clips: BTreeMap<ClipId, Clip>,
 
     recorder: Option<SampleRecorder>,
+    /// Tags the clip being recorded with the rig's frequency, if rig control is on
+    rig: Option<RigTagger>,
+    /// Follows where the station is, if GPS is on
+    gps: Option<GpsTracker>,
+    /// Keeps an eye on the system clock, if checking is on
+    clock: Option<ClockMonitor>,
+    /// Logs the noise floor of the clip being recorded, if logging it is on
+    noise_floor: Option<NoiseFloorLogger>,
+    /// Marks logged contacts in the clip being recorded, if listening for them is on
+    contest: Option<ContestListener>,
+    /// Where decoded text goes, if forwarding it is on. Shared with the decode observer.
+    decodes: Arc<Mutex<Option<DecodeOutput>>>,
+    /// What was connected to the JACK ports when recording last stopped, if it's changed
+    /// since the settings were saved
+    jack_connections: Option<Vec<JackConnection>>,
 
-    fft: Arc<dyn Fft<f32>>,
     audioconfig: Option<AudioInputDevice>,
+    /// Takes the place of every other input when set, for tests
+    virtual_input: Option<VirtualInput>,
+    /// The input device that went away while recording, until it's resumed on or replaced
+    lost_device: Option<DevicePreset>,
+    /// How new clips are recorded
+    settings: Settings,
+    observers: Observers,
+    /// What time it is, for naming clips
+    time: SharedTimeSource,
+}
+
+/// Make a new directory named for now. Several engines can start in the same second, so
+/// later ones get a number on the end rather than sharing a directory.
+fn create_base_path_by_datetime(
+    base: &Path,
+    time: &SharedTimeSource,
+    utc: bool,
+) -> Result<PathBuf, io::Error> {
+    fs::create_dir_all(base)?;
+    let formatted = timesource::session_name(time.now(), utc);
+    let mut session_path = base.join(&formatted);
+    let mut n = 1;
+    loop {
+        match fs::create_dir(&session_path) {
+            Ok(()) => break,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                n += 1;
+                session_path = base.join(format!("{}_{}", formatted, n));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    info!("Created session directory {:?}", session_path.as_os_str());
+    Ok(session_path)
 }
 
-fn create_filename_from_now() -> String {
-    Local::now().format("%Y-%m-%d_%H-%M-%S").to_string()
+/// A service that failed to start is logged and left off, rather than taking the session
+/// down with it
+fn or_log<T, E: std::fmt::Display>(started: Result<Option<T>, E>, what: &str) -> Option<T> {
+    started.unwrap_or_else(|error| {
+        error!("{}: {}", what, error);
+        None
+    })
 }
 
-fn create_base_path_by_datetime(base: &Path) -> Result<PathBuf, io::Error> {
-    let formatted = create_filename_from_now();
-    let session_path = base.join(formatted);
-    info!("Creating session directory {:?}", session_path.as_os_str());
-    fs::create_dir_all(session_path.as_path())?;
-    return Ok(session_path);
+/// Hold no more of a loaded clip in memory than `cap`. A damaged one is held whole, since
+/// what was salvaged from it can't be read back from the file.
+fn cap_clip(clip: &mut WavClip, cap: Option<usize>) {
+    if clip.metadata.damaged.is_empty() {
+        clip.samples.set_cap(cap);
+    }
 }
 
 impl Session {
-    pub fn from_settings(settings: &Settings) -> Result<Session, Error> {
-        let base_dir = settings.session_base_dir.as_path();
-        let path = create_base_path_by_datetime(base_dir)?;
+    /// Start a new session in a fresh directory under the session base directory
+    pub fn from_settings(config: &Configuration, settings: &Settings) -> Result<Session, Error> {
+        Self::from_settings_with_time(config, settings, timesource::system())
+    }
 
-        let mut planner = FftPlanner::<f32>::new();
-        let fft = planner.plan_fft_forward(FFTSIZE);
+    /// Start a new session with the time coming from somewhere other than the system clock
+    pub fn from_settings_with_time(
+        config: &Configuration,
+        settings: &Settings,
+        time: SharedTimeSource,
+    ) -> Result<Session, Error> {
+        let base_dir = config.resolve(&settings.session_base_dir);
+        let path = create_base_path_by_datetime(&base_dir, &time, settings.recording.utc_names)?;
+        Self::open_with_time(path, settings, time)
+    }
 
+    /// Open an existing session directory, picking up any clips already in it
+    pub fn open(path: PathBuf, settings: &Settings) -> Result<Session, Error> {
+        Self::open_with_time(path, settings, timesource::system())
+    }
+
+    /// Open an existing session directory, with the time coming from somewhere other than
+    /// the system clock
+    pub fn open_with_time(
+        path: PathBuf,
+        settings: &Settings,
+        time: SharedTimeSource,
+    ) -> Result<Session, Error> {
+        info!("Opening session directory {:?}", path.as_os_str());
         let mut session = Session {
             path,
             clips: Default::default(),
             recorder: None,
-            fft,
+            rig: None,
+            gps: None,
+            clock: None,
+            noise_floor: None,
+            contest: None,
+            decodes: Default::default(),
+            jack_connections: None,
             audioconfig: None,
+            virtual_input: None,
+            lost_device: None,
+            settings: settings.clone(),
+            observers: Observers::default(),
+            time,
         };
 
         session.rescan_clips()?;
+        // None of these are worth refusing to open the session over
+        session.gps = or_log(session.start_gps(), "Unable to start following gpsd");
+        session.clock = or_log(session.start_clock(), "Unable to start checking the clock");
+        session.noise_floor = or_log(
+            session.start_noise_floor(),
+            "Unable to start logging the noise floor",
+        );
+        session.contest = or_log(
+            session.start_contest(),
+            "Unable to listen for logged contacts",
+        );
+        *session.decodes.lock() = or_log(
+            session.start_decodes(),
+            "Unable to start forwarding decodes",
+        );
+        session.observers.on_decode({
+            let decodes = session.decodes.clone();
+            move |event| {
+                if let Some(decodes) = &*decodes.lock() {
+                    decodes.send(event);
+                }
+            }
+        });
 
         Ok(session)
     }
 
-    pub fn configure(&mut self, newconfig: AudioInputDevice) -> Result<(), Error> {
-        if let Some(config) = &self.audioconfig {
-            if config == &newconfig {
-                return Ok(());
+    fn start_gps(&self) -> Result<Option<GpsTracker>, io::Error> {
+        if !self.settings.gps.enabled {
+            return Ok(None);
+        }
+        let gps = GpsTracker::start(&self.settings.gps, self.path.join(SESSIONFILE))?;
+        gps.track_clip(self.recording_clip().cloned());
+        Ok(Some(gps))
+    }
+
+    fn start_clock(&self) -> Result<Option<ClockMonitor>, io::Error> {
+        if !self.settings.clock.check {
+            return Ok(None);
+        }
+        ClockMonitor::start(&self.settings.clock).map(Some)
+    }
+
+    fn start_noise_floor(&self) -> Result<Option<NoiseFloorLogger>, io::Error> {
+        if !self.settings.noise_floor.enabled {
+            return Ok(None);
+        }
+        let logger = NoiseFloorLogger::start(&self.settings.noise_floor, &self.path, &self.time)?;
+        logger.track_clip(self.recording_clip().cloned());
+        Ok(Some(logger))
+    }
+
+    fn start_contest(&self) -> Result<Option<ContestListener>, io::Error> {
+        if !self.settings.contest.enabled {
+            return Ok(None);
+        }
+        let contest = ContestListener::start(
+            &self.settings.contest,
+            self.settings.recording.bit_depth,
+            self.path.clone(),
+        )?;
+        contest.track_clip(self.recording_clip().cloned());
+        Ok(Some(contest))
+    }
+
+    fn start_decodes(&self) -> Result<Option<DecodeOutput>, io::Error> {
+        if !self.settings.decodes.enabled {
+            return Ok(None);
+        }
+        DecodeOutput::start(&self.settings.decodes).map(Some)
+    }
+
+    /// Pick up changed settings. They apply to clips recorded from now on.
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        let gps_changed = settings.gps != self.settings.gps;
+        let clock_changed = settings.clock != self.settings.clock;
+        let noise_floor_changed = settings.noise_floor != self.settings.noise_floor;
+        let contest_changed = settings.contest != self.settings.contest
+            || settings.recording.bit_depth != self.settings.recording.bit_depth;
+        let decodes_changed = settings.decodes != self.settings.decodes;
+        if let Some(recorder) = &self.recorder {
+            recorder.set_mix_gains(settings.mix.rig_gain, settings.mix.microphone_gain);
+            if settings.blanker != self.settings.blanker {
+                recorder.set_blanker(&settings.blanker);
             }
         }
+        self.settings = settings.clone();
+        if clock_changed {
+            self.clock = None;
+            self.clock = or_log(self.start_clock(), "Unable to start checking the clock");
+        }
+        if noise_floor_changed {
+            self.noise_floor = None;
+            self.noise_floor = or_log(
+                self.start_noise_floor(),
+                "Unable to start logging the noise floor",
+            );
+        }
+        if contest_changed {
+            self.contest = None;
+            self.contest = or_log(self.start_contest(), "Unable to listen for logged contacts");
+        }
+        if decodes_changed {
+            // Stop the old output outside the lock, so decoders aren't held up waiting on it
+            let old = self.decodes.lock().take();
+            drop(old);
+            *self.decodes.lock() =
+                or_log(self.start_decodes(), "Unable to start forwarding decodes");
+        }
+        if gps_changed {
+            self.gps = None;
+            self.gps = or_log(self.start_gps(), "Unable to start following gpsd");
+        }
+    }
+
+    pub fn configure(&mut self, newconfig: AudioInputDevice) -> Result<(), Error> {
+        if let Some(config) = &self.audioconfig
+            && config == &newconfig
+        {
+            return Ok(());
+        }
         let was_recording = self.is_recording();
 
         if was_recording {
@@ -96,6 +326,7 @@ impl Session {
         }
 
         self.audioconfig = Some(newconfig);
+        self.lost_device = None;
         debug!(
             "Session configured with audio input device {:?}",
             self.audioconfig
@@ -108,188 +339,500 @@ impl Session {
         Ok(())
     }
 
+    /// Record from a virtual input instead, from the next clip on. Recording carries on
+    /// from it straight away if it was going.
+    pub fn configure_virtual(&mut self, input: VirtualInput) -> Result<(), Error> {
+        let was_recording = self.is_recording();
+        if was_recording {
+            self.stop_recording()?;
+        }
+        debug!("Session configured with virtual input {}", input.name());
+        self.virtual_input = Some(input);
+        if was_recording {
+            self.record_new_clip()?;
+        }
+        Ok(())
+    }
+
+    /// Whether there's something to record from
     pub fn is_configured(&self) -> bool {
         self.audioconfig.is_some()
+            || self.virtual_input.is_some()
+            || self.settings.vban.enabled
+            || (cfg!(feature = "jack") && self.settings.jack.enabled)
     }
 
     pub fn configuration(&self) -> Option<AudioInputDevice> {
-        self.audioconfig.as_ref().map(|x| x.clone())
+        self.audioconfig.clone()
     }
 
+    /// Load the clips in the session directory that aren't loaded yet. One that can't be
+    /// loaded is left out, rather than the whole session.
     pub fn rescan_clips(&mut self) -> Result<(), Error> {
+        let cap = self.settings.recording.memory_cap_samples();
         for result in fs::read_dir(self.path.as_path())? {
-            let entry = result?;
-            if entry.file_type()?.is_file() {
-                if let Some(clip_id) = ClipId::from_path_ref(&entry.path()) {
-                    match self.clips.entry(clip_id) {
-                        std::collections::btree_map::Entry::Vacant(vacant_entry) => {
-                            vacant_entry.insert(ClipExplorer::new(Arc::new(RwLock::new(
-                                WavClip::from_file(&entry.path())?,
-                            ))));
+            let Ok(entry) = result.inspect_err(|error| warn!("Unable to list a clip: {}", error))
+            else {
+                continue;
+            };
+            let path = entry.path();
+            // Only the WAV files are clips, anything else is metadata about them
+            if entry.file_type().is_ok_and(|file_type| file_type.is_file())
+                && path.extension().is_some_and(|ext| ext == "wav")
+                && let Some(clip_id) = ClipId::from_path_ref(&path)
+            {
+                match self.clips.entry(clip_id) {
+                    std::collections::btree_map::Entry::Vacant(vacant_entry) => {
+                        match WavClip::from_file(&path) {
+                            Ok(mut clip) => {
+                                cap_clip(&mut clip, cap);
+                                vacant_entry.insert(Arc::new(RwLock::new(clip)));
+                            }
+                            Err(error) => {
+                                error!("Unable to load {:?}: {}", path.as_os_str(), error)
+                            }
                         }
-                        std::collections::btree_map::Entry::Occupied(_) => {}
                     }
+                    std::collections::btree_map::Entry::Occupied(_) => {}
                 }
             }
         }
         Ok(())
     }
 
+    /// Copy a WAV file into the session directory, along with its sidecar if it has one,
+    /// and add it as a clip
+    pub fn import_clip(&mut self, wav_path: &Path) -> Result<(), Error> {
+        let file_name = wav_path
+            .file_name()
+            .ok_or_else(|| audio::Error::ClipIdResolutionFailure(wav_path.to_path_buf()))?;
+        let destination = self.path.join(file_name);
+        if destination.exists() {
+            return Err(Error::AlreadyImported(destination));
+        }
+        fs::copy(wav_path, &destination)?;
+        let sidecar = ClipMetadata::sidecar_path(wav_path);
+        if sidecar.exists() {
+            fs::copy(sidecar, ClipMetadata::sidecar_path(&destination))?;
+        }
+        info!("Imported {:?} into the session", wav_path.as_os_str());
+        self.rescan_clips()
+    }
+
+    /// The sample rate most of the clips were recorded at, and the ones that weren't. None
+    /// if they all agree.
+    pub fn odd_sample_rates(&self) -> Option<(u32, Vec<ClipId>)> {
+        let mut counts = BTreeMap::<u32, usize>::new();
+        for clip in self.clips.values() {
+            *counts.entry(clip.read().sample_rate.0).or_default() += 1;
+        }
+        // The higher rate wins a tie, so nothing is thrown away resampling to it
+        let (&rate, _) = counts.iter().max_by_key(|&(&rate, &count)| (count, rate))?;
+        let odd: Vec<ClipId> = self
+            .clips
+            .iter()
+            .filter(|(_, clip)| clip.read().sample_rate.0 != rate)
+            .map(|(id, _)| id.clone())
+            .collect();
+        (!odd.is_empty()).then_some((rate, odd))
+    }
+
+    /// Swap in a clip that's changed on disk, like after resampling it
+    pub fn replace_clip(&mut self, mut clip: WavClip) {
+        cap_clip(&mut clip, self.settings.recording.memory_cap_samples());
+        self.clips
+            .insert(clip.id().clone(), Arc::new(RwLock::new(clip)));
+    }
+
+    /// Where to subscribe to what happens while recording
+    pub fn observers(&self) -> &Observers {
+        &self.observers
+    }
+
+    /// Where the time comes from for naming new clips and stamping when they started, and
+    /// for the noise floor log, which is started over to pick it up
+    pub fn set_time_source(&mut self, time: SharedTimeSource) {
+        self.time = time;
+        if self.noise_floor.is_some() {
+            self.noise_floor = None;
+            self.noise_floor = or_log(
+                self.start_noise_floor(),
+                "Unable to start logging the noise floor",
+            );
+        }
+    }
+
+    pub fn time_source(&self) -> &SharedTimeSource {
+        &self.time
+    }
+
     pub fn is_recording(&self) -> bool {
         self.recorder.is_some()
     }
 
+    /// The clip being recorded, if we are
+    pub fn recording_clip(&self) -> Option<&Clip> {
+        self.recorder.as_ref().map(SampleRecorder::clip)
+    }
+
+    /// The pipeline that's recording, if we are
+    pub fn pipeline(&self) -> Option<&Pipeline> {
+        self.recorder.as_ref().map(SampleRecorder::pipeline)
+    }
+
+    /// Where the station is, if GPS is on and has a fix
+    pub fn location(&self) -> Option<Location> {
+        self.gps.as_ref().and_then(GpsTracker::location)
+    }
+
+    /// The system clock check, if it's on
+    pub fn clock(&self) -> Option<&ClockMonitor> {
+        self.clock.as_ref()
+    }
+
+    /// The noise floor log, if it's on
+    pub fn noise_floor(&self) -> Option<&NoiseFloorLogger> {
+        self.noise_floor.as_ref()
+    }
+
+    /// What the recording is up to right now
+    pub fn status(&self) -> Status {
+        Status::new(
+            self.recording_clip().map(|clip| clip.read().id().clone()),
+            self.pipeline(),
+        )
+    }
+
     pub fn record_new_clip(&mut self) -> Result<(), Error> {
         if self.is_recording() {
             return Err(Error::AlreadyRecording());
         }
-        if !self.is_configured() {
-            return Err(Error::NoAudioConfiguration());
-        }
+        let input = self.open_input()?;
+        let sample_rate = match (&input, &self.audioconfig) {
+            (Input::Virtual(input), _) => input.sample_rate(),
+            (Input::Vban(vban), _) => vban.format().sample_rate,
+            #[cfg(feature = "jack")]
+            (Input::Jack(jack), _) => jack.sample_rate(),
+            (Input::Device | Input::Mixed(_), Some(cfg)) => cfg.config.sample_rate.0,
+            (Input::Device | Input::Mixed(_), None) => return Err(Error::NoAudioConfiguration()),
+        };
 
-        let cfg = self.audioconfig.as_ref().unwrap().clone();
+        let input_name = self.input_name();
+        let correction = input_name
+            .as_ref()
+            .and_then(|(host, device)| self.settings.frequency_correction(host, device));
+
+        let mut spec = self.settings.recording.bit_depth.wav_spec(sample_rate);
+        // As many channels as the recorder writes, after the channel mapping
+        spec.channels = match (&input, &self.audioconfig) {
+            // The rig and the microphone, side by side
+            (Input::Mixed(_), _) => 2,
+            (Input::Vban(vban), _) => {
+                tools::vban_mapping(&self.settings).channels(vban.format().channels)
+            }
+            #[cfg(feature = "jack")]
+            (Input::Jack(jack), _) => tools::jack_mapping(&self.settings).channels(jack.channels()),
+            (Input::Virtual(input), _) => self
+                .settings
+                .channel_mapping(VirtualInput::HOST, input.name())
+                .channels(input.channels()),
+            (Input::Device, Some(cfg)) => {
+                tools::channel_mapping(&self.settings, cfg).channels(cfg.config.channels)
+            }
+            (Input::Device, None) => return Err(Error::NoAudioConfiguration()),
+        };
+        let clip = self.create_clip(spec, correction)?;
+        let clip_id = clip.read().id().clone();
+
+        // Recorder starts as soon as it is created
+        let observers = self.observers.clone();
+        let recorder = match (input, &self.audioconfig) {
+            (Input::Virtual(input), _) => SampleRecorder::from_virtual(
+                &input,
+                clip.clone(),
+                observers,
+                &self.settings,
+                &self.time,
+            )?,
+            (Input::Vban(vban), _) => SampleRecorder::from_vban(
+                vban,
+                clip.clone(),
+                observers,
+                &self.settings,
+                &self.time,
+            )?,
+            #[cfg(feature = "jack")]
+            (Input::Jack(jack), _) => SampleRecorder::from_jack(
+                jack,
+                clip.clone(),
+                observers,
+                &self.settings,
+                &self.time,
+            )?,
+            (Input::Device, Some(cfg)) => {
+                SampleRecorder::new(cfg, clip.clone(), observers, &self.settings, &self.time)?
+            }
+            (Input::Mixed(microphone), Some(cfg)) => SampleRecorder::mixed(
+                cfg,
+                &microphone,
+                clip.clone(),
+                observers,
+                &self.settings,
+                &self.time,
+            )?,
+            (Input::Device | Input::Mixed(_), None) => {
+                return Err(Error::NoAudioConfiguration());
+            }
+        };
+        self.recorder = Some(recorder);
+        self.track_clip(Some(&clip));
+        self.clips.insert(clip_id.clone(), clip);
+        self.observers.clip_started(&clip_id);
 
-        let clip_id = ClipId::from_datetimelocal(Local::now());
+        Ok(())
+    }
 
-        match self.clips.entry(clip_id.clone()) {
-            std::collections::btree_map::Entry::Vacant(vacant_entry) => {
-                // Clip does not exist, create it
-                let spec = WavSpec {
-                    channels: 1,
-                    sample_rate: cfg.config.sample_rate.0,
-                    bits_per_sample: 16,
-                    sample_format: SampleFormat::Int,
-                };
-                let clip = Arc::new(RwLock::new(WavClip::record_new(
-                    clip_id,
-                    self.path.as_path(),
-                    spec,
-                )?));
+    /// Finish the clip being recorded and carry straight on into a new one, like it, without
+    /// letting go of the input. Nothing is lost between the two.
+    pub fn next_clip(&mut self) -> Result<ClipId, Error> {
+        let Some(recorder) = &self.recorder else {
+            return Err(Error::NotRecording());
+        };
+        let (finished, spec, correction) = {
+            let clip = recorder.clip().read();
+            let spec = WavSpec {
+                channels: clip.channels,
+                ..self
+                    .settings
+                    .recording
+                    .bit_depth
+                    .wav_spec(clip.sample_rate.0)
+            };
+            (clip.id().clone(), spec, clip.metadata.frequency_correction)
+        };
+        let clip = self.create_clip(spec, correction)?;
+        let clip_id = clip.read().id().clone();
+        if let Some(recorder) = &mut self.recorder {
+            recorder.rotate(clip.clone());
+        }
+        self.track_clip(Some(&clip));
+        self.clips.insert(clip_id.clone(), clip);
+        self.observers.clip_finished(&finished);
+        self.observers.clip_started(&clip_id);
+        Ok(clip_id)
+    }
 
-                // Recorder starts as soon as it is created
-                self.recorder = Some(SampleRecorder::new(&cfg, clip.clone())?);
-                vacant_entry.insert(ClipExplorer::new(clip));
+    /// A new clip named for now, ready to record into
+    fn create_clip(&self, spec: WavSpec, correction: Option<f64>) -> Result<Clip, Error> {
+        let clip_id = timesource::clip_id(self.time.now(), self.settings.recording.utc_names)
+            .next_free(|id| {
+                self.clips.contains_key(id) || id.absolute_path_wav(&self.path).exists()
+            })?;
+        let mut wav = WavClip::record_new(clip_id, self.path.as_path(), spec)?;
+        wav.samples
+            .set_cap(self.settings.recording.memory_cap_samples());
+        if correction.is_some() {
+            wav.set_frequency_correction(correction)?;
+        }
+        Ok(Arc::new(RwLock::new(wav)))
+    }
 
-                Ok(())
-            }
-            std::collections::btree_map::Entry::Occupied(_) => Err(Error::AlreadyRecording()),
+    /// Point whatever tags the recording at `clip`, or at nothing once recording stops.
+    /// They only add to the metadata, so one that won't start doesn't stop the recording.
+    fn track_clip(&mut self, clip: Option<&Clip>) {
+        self.rig = None;
+        if let Some(clip) = clip
+            && self.settings.rig.enabled
+        {
+            self.rig = RigTagger::start(&self.settings.rig, clip.clone())
+                .inspect_err(|error| {
+                    warn!(
+                        "Unable to start reading the rig, recording without it: {}",
+                        error
+                    )
+                })
+                .ok();
+        }
+        if let Some(gps) = &self.gps {
+            gps.track_clip(clip.cloned());
+        }
+        if let Some(contest) = &self.contest {
+            contest.track_clip(clip.cloned());
+        }
+        if let Some(noise_floor) = &self.noise_floor {
+            noise_floor.track_clip(clip.cloned());
         }
     }
 
+    /// A virtual input, a VBAN stream or JACK take the place of the input device when
+    /// they're on. Mixing in a microphone only goes with an input device.
+    fn open_input(&self) -> Result<Input, Error> {
+        if let Some(input) = &self.virtual_input {
+            return Ok(Input::Virtual(input.clone()));
+        }
+        if self.settings.vban.enabled {
+            return Ok(Input::Vban(VbanReceiver::open(&self.settings.vban)?));
+        }
+        if self.settings.jack.enabled {
+            #[cfg(feature = "jack")]
+            return Ok(Input::Jack(JackInput::open(&self.settings.jack)?));
+            #[cfg(not(feature = "jack"))]
+            log::warn!("Recording from JACK needs hamshark built with the jack feature");
+        }
+        let mix = &self.settings.mix;
+        if mix.enabled
+            && let Some(cfg) = &self.audioconfig
+        {
+            let microphone = AudioInputDeviceBuilder::from_names(
+                &mix.host,
+                &mix.device,
+                cfg.config.sample_rate.0,
+            )?
+            .build()?;
+            return Ok(Input::Mixed(microphone));
+        }
+        Ok(Input::Device)
+    }
+
+    /// The host and device name of what new clips are recorded from, for looking up its
+    /// calibration. VBAN and JACK go by the stream and client name.
+    pub fn input_name(&self) -> Option<(String, String)> {
+        if let Some(input) = &self.virtual_input {
+            return Some((VirtualInput::HOST.to_string(), input.name().to_string()));
+        }
+        if self.settings.vban.enabled {
+            return Some((
+                tools::VBAN_HOST.to_string(),
+                self.settings.vban.stream.clone(),
+            ));
+        }
+        if cfg!(feature = "jack") && self.settings.jack.enabled {
+            return Some((
+                tools::JACK_HOST.to_string(),
+                self.settings.jack.client_name.clone(),
+            ));
+        }
+        let config = self.audioconfig.as_ref()?;
+        Some((
+            config.host_id.name().to_string(),
+            config.device.name().ok()?,
+        ))
+    }
+
+    /// The JACK server's buffer size and transport, when recording from JACK
+    #[cfg(feature = "jack")]
+    pub fn jack_info(&self) -> Option<JackInfo> {
+        self.recorder.as_ref().and_then(SampleRecorder::jack_info)
+    }
+
+    /// What was connected to the JACK ports when recording last stopped, if that's changed
+    /// from the settings. Save it in the settings to have the connections restored next time.
+    pub fn take_jack_connections(&mut self) -> Option<Vec<JackConnection>> {
+        self.jack_connections.take()
+    }
+
+    /// Save samples made up rather than recorded, like keyed CW, as a new clip named for now
+    pub fn add_samples(&mut self, samples: &[f32], sample_rate: u32) -> Result<ClipId, Error> {
+        let id = timesource::clip_id(self.time.now(), self.settings.recording.utc_names)
+            .next_free(|id| {
+                self.clips.contains_key(id) || id.absolute_path_wav(&self.path).exists()
+            })?;
+        let path = id.absolute_path_wav(&self.path);
+        let spec = self.settings.recording.bit_depth.wav_spec(sample_rate);
+        WavClip::export_samples(samples, &path, spec)?;
+        let clip = WavClip::from_file(&path)?;
+        info!("Saved {:?} into the session", path.as_os_str());
+        self.clips.insert(id.clone(), Arc::new(RwLock::new(clip)));
+        Ok(id)
+    }
+
     pub fn add_clip(&mut self, clip: Clip) -> Result<(), Error> {
         let id = clip.read().id().clone();
         if self.clips.contains_key(&id) {
             return Ok(());
         }
 
-        let editor = ClipExplorer::new(clip);
-
-        self.clips.insert(id, editor);
+        self.clips.insert(id, clip);
 
         Ok(())
     }
 
-    pub fn stop_recording(&mut self) -> Result<(), Error> {
-        if let Some(recorder) = self.recorder.take() {
-            recorder.close()?;
-        }
-        Ok(())
+    /// If the input device has gone away, finish the clip it was recording, noting it was
+    /// cut short, and return its ID. Call this regularly while recording.
+    pub fn check_device(&mut self) -> Result<Option<ClipId>, Error> {
+        let Some(recorder) = self
+            .recorder
+            .as_ref()
+            .filter(|recorder| recorder.device_lost())
+        else {
+            return Ok(None);
+        };
+        let clip = recorder.clip().clone();
+        self.lost_device = self.audioconfig.as_ref().map(|config| config.to_preset(""));
+        self.stop_recording()?;
+        let mut clip = clip.write();
+        warn!("The input device went away, {} was cut short", clip.id());
+        clip.set_device_lost()?;
+        Ok(Some(clip.id().clone()))
     }
 
-    pub fn start(&mut self) -> Result<(), Error> {
-        /*if self.is_started() {
-            return Err(Error::AlreadyRecording())
-        }
-
-        // Build filename for new clip
-        let clip = self.record()?;
+    /// The input device that went away while recording, if recording hasn't been resumed on
+    /// it or another device picked since
+    pub fn lost_device(&self) -> Option<&DevicePreset> {
+        self.lost_device.as_ref()
+    }
 
-        if !self.is_configured() {
-            return Err(Error::NoAudioConfiguration())
-        }
-        let cfg = self.audioconfig.as_ref().unwrap();
+    /// Whether the input device that went away is back. This looks through the host's
+    /// devices, so it's best not done every frame.
+    pub fn lost_device_is_back(&self) -> bool {
+        self.lost_device
+            .as_ref()
+            .is_some_and(|preset| AudioInputDeviceBuilder::from_preset(preset).is_some())
+    }
 
-        let mut last_ts: Option<InputStreamTimestamp> = None;
-        let mut buffer = vec![Complex{ re: 0.0, im: 0.0}; FFTSIZE];
-        let mut buffer_index = 0usize;
-        let mut data_index = 0usize;
-        let fft = Arc::clone(&self.fft);
-        let samples = clip.read().samples();
-        let frequencies = clip.read().frequencies();
-        self.stream = match cfg.device.build_input_stream(
-            &cfg.config,
-            move |data: &[f32], info| {
-                let mut writable_clip = clip.write();
-                let writer = writable_clip.writer().unwrap();
+    /// Start recording a new clip on the input device that went away, now it's back
+    pub fn resume_lost_device(&mut self) -> Result<(), Error> {
+        let preset = self
+            .lost_device
+            .as_ref()
+            .ok_or(Error::NoAudioConfiguration())?;
+        let device = AudioInputDeviceBuilder::from_preset(preset)
+            .ok_or_else(|| audioinput::Error::NotFound(preset.device.clone()))?
+            .build()?;
+        // The old handle is for the device before it went, so it's replaced even though
+        // it's the same device
+        self.audioconfig = Some(device);
+        self.lost_device = None;
+        self.record_new_clip()
+    }
 
-                // Profiling Data
-                let cur_ts = info.timestamp();
-                let dt = if let Some(last_ts) = last_ts {
-                    (
-                        cur_ts.capture.duration_since(&last_ts.capture).unwrap().as_secs_f64(),
-                        cur_ts.callback.duration_since(&last_ts.callback).unwrap().as_secs_f64()
-                    )
-                } else {
-                    (0.0f64, 0.0f64)
-                };
-                last_ts = Some(cur_ts);
-                trace!("Input Stream dt_cap={} dt_cb={} ts={:?} Length: {}", dt.0, dt.1, info.timestamp(), data.len());
-                trace!("Indexes: Buffer {} Data {}", buffer_index, data_index);
-
-                loop {
-
-                    // Every time through the loop, add the next data to buffer
-                    buffer[buffer_index] = Complex::from(data[data_index]);
-
-                    // Every time through the loop, add the next data to the recorded data
-                    samples.write().push(data[data_index]);
-
-                    // Write some BS so we can see progress without a mic for devtest only
-                    // TODO: temporary
-                    // let mut rng = rand::rng();
-                    // let rndval: f32 = rng.random_range(-0.5..0.5);
-                    // raw_amplitudes.write().push(rndval);
-
-                    // Write a sample
-                    writer.write_sample((data[data_index] * i16::MAX as f32) as i16)
-                        .expect("to write a sample");
-
-                    buffer_index += 1;
-                    data_index += 1;
-
-                    // When the buffer is full, do a FFT, which writes back to our buffer
-                    if buffer_index == buffer.len() {
-                        buffer_index = 0;
-                        fft.process(&mut buffer);
-                        // Whenever an FFT is generated, add it to recorded FFTs
-                        frequencies.write().push(buffer.clone());
-                        trace!("post fft {:?}", buffer);
-                    }
+    /// Give up on the input device that went away
+    pub fn forget_lost_device(&mut self) {
+        self.lost_device = None;
+    }
 
-                    // When we have consumed all the data, reset counter and wait for the next callback
-                    if data_index == data.len() {
-                        data_index = 0;
-                        break;
-                    }
-                }
-            },
-            move |err| {
-                error!("Stream Error: {:?}", err);
-            },
-            None
-        ) {
-            Ok(stream) => {
-                // Start the stream and store it if successful
-                match stream.play() {
-                    Ok(_) => Some(stream),
-                    Err(error) => return Err(Error::PlayStream(error)),
-                }
+    pub fn stop_recording(&mut self) -> Result<(), Error> {
+        self.track_clip(None);
+        if let Some(recorder) = self.recorder.take() {
+            #[cfg(feature = "jack")]
+            if let Some(connections) = recorder.jack_connections()
+                && connections != self.settings.jack.connections
+            {
+                self.settings.jack.connections = connections.clone();
+                self.jack_connections = Some(connections);
             }
-            Err(error) => {
-                return Err(Error::BuildStream(error))
-            },
-        };*/
-
+            let id = recorder.clip().read().id().clone();
+            recorder.close()?;
+            self.observers.clip_finished(&id);
+        }
         Ok(())
     }
 }
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        if let Err(error) = self.stop_recording() {
+            error!("Error stopping recording: {}", error);
+        }
+    }
+}
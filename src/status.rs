@@ -0,0 +1,121 @@
+use crate::{
+    data::{audio::ClipId, blanker},
+    pipeline::{ElementState, ElementStatus, Pipeline},
+    tools, watchdog,
+};
+use serde::Serialize;
+
+/// What the engine is doing overall
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Idle,
+    Recording,
+    /// Recording, but something in the pipeline is paused
+    Paused,
+    /// Recording, but something in the pipeline has failed and samples are being lost
+    Failed,
+}
+
+/// One pipeline element at the moment the status was taken
+#[derive(Debug, Clone, Serialize)]
+pub struct ElementSnapshot {
+    pub name: &'static str,
+    pub state: ElementState,
+    pub processed: usize,
+    pub errors: usize,
+    pub events: usize,
+    pub last_error: Option<String>,
+    pub fill: Option<f32>,
+    /// Samples per second
+    pub throughput: f64,
+    /// Seconds between the blocks the element's handed, and how far from that they come
+    pub block_interval: Option<f64>,
+    pub block_jitter: Option<f64>,
+}
+
+impl From<&ElementStatus> for ElementSnapshot {
+    fn from(element: &ElementStatus) -> Self {
+        Self {
+            name: element.name(),
+            state: element.state(),
+            processed: element.processed(),
+            errors: element.errors(),
+            events: element.events(),
+            last_error: element.last_error(),
+            fill: element.fill(),
+            throughput: element.throughput(),
+            block_interval: element.block_interval().map(|d| d.as_secs_f64()),
+            block_jitter: element.block_jitter().map(|d| d.as_secs_f64()),
+        }
+    }
+}
+
+/// A snapshot of the engine, for status bars and monitoring
+#[derive(Debug, Clone, Serialize)]
+pub struct Status {
+    pub state: State,
+    /// The clip being recorded into
+    pub clip: Option<ClipId>,
+    /// Samples written to the clip
+    pub samples_captured: usize,
+    /// Times samples were lost or glitched on the way to the clip, from input stream errors
+    /// and buffer overflows
+    pub xruns: usize,
+    /// How full the buffer between the input and the clip writer is, from 0 to 1
+    pub buffer_fill: Option<f32>,
+    /// Impulses the noise blanker has blanked, if it's in the pipeline
+    pub impulses_blanked: Option<usize>,
+    /// The last thing the watchdog found wrong with how the input's sending samples
+    pub watchdog: Option<String>,
+    /// Every element, from the source to the sink
+    pub elements: Vec<ElementSnapshot>,
+}
+
+impl Status {
+    pub fn new(clip: Option<ClipId>, pipeline: Option<&Pipeline>) -> Self {
+        let elements: Vec<ElementSnapshot> = pipeline
+            .map(|pipeline| {
+                pipeline
+                    .elements()
+                    .iter()
+                    .map(|e| e.as_ref().into())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let state = if pipeline.is_none() {
+            State::Idle
+        } else if elements.iter().any(|e| e.state == ElementState::Failed) {
+            State::Failed
+        } else if elements.iter().any(|e| e.state == ElementState::Paused) {
+            State::Paused
+        } else {
+            State::Recording
+        };
+        // What reaches the clip writer has been captured. Plugin sinks come after it.
+        let writer = elements
+            .iter()
+            .position(|e| e.name == tools::WRITER_ELEMENT_NAME)
+            .unwrap_or(elements.len().saturating_sub(1));
+        let (upstream, sink) = (
+            &elements[..writer.min(elements.len())],
+            elements.get(writer),
+        );
+        Self {
+            state,
+            clip,
+            samples_captured: sink.map_or(0, |sink| sink.processed),
+            xruns: upstream.iter().map(|e| e.errors).sum(),
+            buffer_fill: elements.iter().find_map(|e| e.fill),
+            impulses_blanked: elements
+                .iter()
+                .find(|e| e.name == blanker::ELEMENT_NAME)
+                .map(|e| e.events),
+            watchdog: elements
+                .iter()
+                .find(|e| e.name == watchdog::ELEMENT_NAME)
+                .and_then(|e| e.last_error.clone()),
+            elements,
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use crate::config::StreamingSettings;
+use audiopus::{Application, Bitrate, Channels, SampleRate, coder::Encoder};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use log::{info, warn};
+use ogg::{PacketWriteEndInfo, PacketWriter};
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error as ThisError;
+
+/// Opus always runs at 48 kHz here, whatever the input rate
+const OPUS_RATE: u32 = 48_000;
+/// Samples per Opus packet, 20 ms
+const FRAME: usize = 960;
+/// Packets per Ogg page. Fewer pages is less overhead, more is less delay for listeners.
+const PACKETS_PER_PAGE: usize = 10;
+/// Biggest packet we'll ask the encoder for
+const MAX_PACKET: usize = 4000;
+/// How many blocks of samples can wait to be sent before we start dropping them
+const BUFFER_BLOCKS: usize = 256;
+/// How long to wait for the server before giving up on it
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait before trying the server again
+const RETRY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error talking to the Icecast server: {0}")]
+    IO(#[from] io::Error),
+    #[error("No address found for the Icecast server at {0}")]
+    NoAddress(String),
+    #[error("The Icecast server refused the stream: {0}")]
+    Rejected(String),
+    #[error("Error encoding Opus: {0}")]
+    Opus(#[from] audiopus::Error),
+}
+
+/// Linear interpolation from the input rate to 48 kHz. Rough, but good enough for listening.
+struct Resampler {
+    /// Input samples per output sample
+    step: f64,
+    /// Where the next output sample falls, in input samples from the start of the next
+    /// block. -1 is the last sample of the block before.
+    position: f64,
+    previous: f32,
+}
+
+impl Resampler {
+    fn new(sample_rate: u32) -> Self {
+        Self {
+            step: sample_rate as f64 / OPUS_RATE as f64,
+            position: 0.0,
+            previous: 0.0,
+        }
+    }
+
+    fn push(&mut self, input: &[f32], output: &mut Vec<f32>) {
+        let Some(&last) = input.last() else {
+            return;
+        };
+        let previous = self.previous;
+        let at = |i: isize| if i < 0 { previous } else { input[i as usize] };
+        let end = input.len() as f64 - 1.0;
+        while self.position < end {
+            let i = self.position.floor();
+            let fraction = (self.position - i) as f32;
+            let (a, b) = (at(i as isize), at(i as isize + 1));
+            output.push(a + (b - a) * fraction);
+            self.position += self.step;
+        }
+        self.position -= input.len() as f64;
+        self.previous = last;
+    }
+}
+
+/// One connection to the server, as a single Ogg Opus stream
+struct Connection {
+    writer: PacketWriter<TcpStream>,
+    encoder: Encoder,
+    serial: u32,
+    /// Samples encoded so far, counting the encoder's lookahead, at 48 kHz
+    granule: u64,
+    packets: usize,
+}
+
+impl Connection {
+    fn open(settings: &StreamingSettings, sample_rate: u32) -> Result<Self, Error> {
+        let addr = settings
+            .server
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::NoAddress(settings.server.clone()))?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        let credentials = BASE64.encode(format!("{}:{}", settings.user, settings.password));
+        write!(
+            stream,
+            "PUT {} HTTP/1.1\r\n\
+             Host: {}\r\n\
+             Authorization: Basic {}\r\n\
+             User-Agent: hamshark/{}\r\n\
+             Content-Type: audio/ogg\r\n\
+             Ice-Name: {}\r\n\
+             Ice-Public: 0\r\n\
+             Expect: 100-continue\r\n\r\n",
+            settings.mount,
+            settings.server,
+            credentials,
+            env!("CARGO_PKG_VERSION"),
+            settings.name,
+        )?;
+        // Either 100 Continue or 200 OK means go ahead. Skip the headers after it.
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut status = String::new();
+        reader.read_line(&mut status)?;
+        let status = status.trim_end().to_string();
+        let code = status.split_whitespace().nth(1);
+        if !matches!(code, Some("100" | "200")) {
+            return Err(Error::Rejected(status));
+        }
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+            header.clear();
+        }
+
+        let mut encoder = Encoder::new(SampleRate::Hz48000, Channels::Mono, Application::Audio)?;
+        encoder.set_bitrate(Bitrate::BitsPerSecond(settings.bitrate as i32))?;
+        let pre_skip = encoder.lookahead()?;
+
+        // Ogg streams are told apart by serial number, any will do for a stream of one
+        let serial = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        let mut writer = PacketWriter::new(stream);
+
+        let mut head = b"OpusHead".to_vec();
+        head.push(1);
+        head.push(1);
+        head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+        head.extend_from_slice(&sample_rate.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes());
+        head.push(0);
+        writer.write_packet(head.into(), serial, PacketWriteEndInfo::EndPage, 0)?;
+
+        let vendor = concat!("hamshark ", env!("CARGO_PKG_VERSION"));
+        let title = format!("TITLE={}", settings.name);
+        let mut tags = b"OpusTags".to_vec();
+        tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+        tags.extend_from_slice(vendor.as_bytes());
+        tags.extend_from_slice(&1u32.to_le_bytes());
+        tags.extend_from_slice(&(title.len() as u32).to_le_bytes());
+        tags.extend_from_slice(title.as_bytes());
+        writer.write_packet(tags.into(), serial, PacketWriteEndInfo::EndPage, 0)?;
+
+        Ok(Self {
+            writer,
+            encoder,
+            serial,
+            granule: pre_skip as u64,
+            packets: 0,
+        })
+    }
+
+    /// Encode and send one packet's worth of samples
+    fn send(&mut self, frame: &[f32]) -> Result<(), Error> {
+        let mut packet = [0u8; MAX_PACKET];
+        let len = self.encoder.encode_float(frame, &mut packet)?;
+        self.granule += frame.len() as u64;
+        self.packets += 1;
+        let end = if self.packets.is_multiple_of(PACKETS_PER_PAGE) {
+            PacketWriteEndInfo::EndPage
+        } else {
+            PacketWriteEndInfo::NormalPacket
+        };
+        self.writer
+            .write_packet(packet[..len].into(), self.serial, end, self.granule)?;
+        Ok(())
+    }
+}
+
+/// Sends samples to an Icecast server from a thread of its own, reconnecting if the server
+/// goes away. Stops when dropped.
+pub struct IcecastSink {
+    /// Taken to hang up on the thread
+    sender: Option<SyncSender<Vec<f32>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl IcecastSink {
+    pub fn start(
+        settings: &StreamingSettings,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<Self, io::Error> {
+        let (sender, receiver) = mpsc::sync_channel::<Vec<f32>>(BUFFER_BLOCKS);
+        let settings = settings.clone();
+        let channels = channels.max(1) as usize;
+        let thread = thread::Builder::new()
+            .name("icecast".to_string())
+            .spawn(move || {
+                // Only complain when things change, not on every retry
+                let mut failing = false;
+                loop {
+                    let result = Connection::open(&settings, sample_rate).and_then(|connection| {
+                        info!("Streaming to {}{}", settings.server, settings.mount);
+                        failing = false;
+                        Self::stream(connection, &receiver, sample_rate, channels)
+                    });
+                    match result {
+                        Ok(()) => break,
+                        Err(error) if !failing => {
+                            warn!("Unable to stream to {}: {}", settings.server, error);
+                            failing = true;
+                        }
+                        Err(_) => {}
+                    }
+                    // Throw away what arrives until it's time to try again
+                    let retry = Instant::now() + RETRY;
+                    loop {
+                        match receiver.recv_timeout(retry.saturating_duration_since(Instant::now()))
+                        {
+                            Ok(_) => {}
+                            Err(RecvTimeoutError::Timeout) => break,
+                            Err(RecvTimeoutError::Disconnected) => return,
+                        }
+                    }
+                }
+            })?;
+        Ok(Self {
+            sender: Some(sender),
+            thread: Some(thread),
+        })
+    }
+
+    /// Send samples until the recorder hangs up
+    fn stream(
+        mut connection: Connection,
+        receiver: &Receiver<Vec<f32>>,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Result<(), Error> {
+        let mut resampler = Resampler::new(sample_rate);
+        let mut mono = Vec::new();
+        let mut pending = Vec::with_capacity(FRAME * 2);
+        while let Ok(block) = receiver.recv() {
+            mono.clear();
+            mono.extend(
+                block
+                    .chunks(channels)
+                    .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32),
+            );
+            resampler.push(&mono, &mut pending);
+            let mut frames = pending.chunks_exact(FRAME);
+            for frame in &mut frames {
+                connection.send(frame)?;
+            }
+            let left = frames.remainder().len();
+            pending.drain(..pending.len() - left);
+        }
+        Ok(())
+    }
+
+    /// Queue samples to send. They're dropped if the stream can't keep up.
+    pub fn push(&self, samples: &[f32]) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.try_send(samples.to_vec());
+        }
+    }
+}
+
+impl Drop for IcecastSink {
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            warn!("Icecast thread panicked");
+        }
+    }
+}
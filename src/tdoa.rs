@@ -0,0 +1,160 @@
+use crate::{
+    data::audio::{Clip, ClipId, WavClip},
+    fft::Transform,
+};
+use rustfft::num_complex::Complex;
+use std::ops::Range;
+use thiserror::Error as ThisError;
+
+/// How long the envelope is smoothed over when comparing envelopes, in seconds
+const ENVELOPE_SECONDS: f64 = 0.001;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("{0} doesn't know when it was recorded")]
+    NoStart(ClipId),
+    #[error("The clips were recorded at different sample rates, {0} and {1} Hz")]
+    SampleRates(u32, u32),
+    #[error("The clips don't overlap there")]
+    NoOverlap,
+    #[error("There's nothing to compare, pick a longer stretch")]
+    TooShort,
+}
+
+/// How much later a signal reached one recording than the other
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArrivalDifference {
+    /// In seconds, positive when it reached the second clip later
+    pub delay: f64,
+    /// How alike the two were at that delay, from 0 to 1
+    pub correlation: f64,
+}
+
+/// Where `b` best matches `a`, as (offset into `b` to a fraction of a sample, normalized
+/// correlation there). `b` has to be at least as long as `a`; only offsets that keep `a`
+/// entirely inside `b` are tried.
+pub fn best_match(a: &[f32], b: &[f32]) -> Option<(f64, f64)> {
+    if a.is_empty() || b.len() < a.len() {
+        return None;
+    }
+    let size = (a.len() + b.len()).next_power_of_two();
+    let mut forward = Transform::forward(size);
+    let mut transform = |samples: &[f32]| {
+        let mut buffer: Vec<Complex<f64>> = samples
+            .iter()
+            .map(|x| Complex::new(*x as f64, 0.0))
+            .chain(std::iter::repeat(Complex::default()))
+            .take(size)
+            .collect();
+        forward.process(&mut buffer);
+        buffer
+    };
+    let x = transform(a);
+    let mut correlation: Vec<Complex<f64>> = transform(b)
+        .iter()
+        .zip(&x)
+        .map(|(y, x)| y * x.conj())
+        .collect();
+    Transform::inverse(size).process(&mut correlation);
+    let values: Vec<f64> = correlation[..=b.len() - a.len()]
+        .iter()
+        .map(|c| c.re / size as f64)
+        .collect();
+
+    // The signal may come out of one receiver inverted, so the strongest either way counts
+    let (peak, _) = values
+        .iter()
+        .enumerate()
+        .max_by(|x, y| x.1.abs().total_cmp(&y.1.abs()))?;
+    let offset = match (peak.checked_sub(1), values.get(peak + 1)) {
+        (Some(before), Some(after)) => {
+            let (y0, y1, y2) = (values[before].abs(), values[peak].abs(), after.abs());
+            let curvature = y0 - 2.0 * y1 + y2;
+            if curvature < 0.0 {
+                peak as f64 + 0.5 * (y0 - y2) / curvature
+            } else {
+                peak as f64
+            }
+        }
+        _ => peak as f64,
+    };
+
+    let energy = |samples: &[f32]| samples.iter().map(|x| (x * x) as f64).sum::<f64>();
+    let matched = energy(a) * energy(&b[peak..peak + a.len()]);
+    let normalized = if matched > 0.0 {
+        values[peak].abs() / matched.sqrt()
+    } else {
+        0.0
+    };
+    Some((offset, normalized))
+}
+
+/// The smoothed magnitude of some samples, with its mean taken off. Receivers that aren't
+/// phase locked turn the same signal into audio that only lines up in its envelope.
+fn envelope(samples: &[f32], sample_rate: u32) -> Vec<f32> {
+    let width = ((ENVELOPE_SECONDS * sample_rate as f64) as usize).max(1);
+    let mut smoothed = Vec::with_capacity(samples.len());
+    let mut sum = 0.0f32;
+    for (index, sample) in samples.iter().enumerate() {
+        sum += sample.abs();
+        if index >= width {
+            sum -= samples[index - width].abs();
+        }
+        smoothed.push(sum / width as f32);
+    }
+    let mean = smoothed.iter().sum::<f32>() / smoothed.len().max(1) as f32;
+    smoothed.iter_mut().for_each(|x| *x -= mean);
+    smoothed
+}
+
+/// Compare when a transmission reached two clips recorded on synchronized clocks. The
+/// stretch `range` of the first clip is looked for in the second, up to `max_delay`
+/// seconds either side of where the clips' start times put it. Compare envelopes when the
+/// receivers weren't phase locked, as with SSB.
+pub fn arrival_difference(
+    first: &Clip,
+    range: Range<usize>,
+    second: &Clip,
+    max_delay: f64,
+    envelopes: bool,
+) -> Result<ArrivalDifference, Error> {
+    let first = first.read();
+    let second = second.read();
+    let started = |clip: &WavClip| {
+        clip.metadata
+            .started
+            .ok_or_else(|| Error::NoStart(clip.id().clone()))
+    };
+    let (first_started, second_started) = (started(&first)?, started(&second)?);
+    let sample_rate = first.sample_rate.0;
+    if second.sample_rate.0 != sample_rate {
+        return Err(Error::SampleRates(sample_rate, second.sample_rate.0));
+    }
+    let end = range.end.min(first.samples.len());
+    let a = first.samples.range(range.start..end);
+    if a.is_empty() {
+        return Err(Error::TooShort);
+    }
+
+    // Where the stretch would be in the second clip if it arrived at both at once
+    let offset = (first_started - second_started).as_seconds_f64() * sample_rate as f64;
+    let expected = range.start as f64 + offset;
+    let search = (max_delay.max(0.0) * sample_rate as f64).ceil();
+    let from = (expected - search).floor().max(0.0) as usize;
+    let to = ((expected + search).ceil() as usize + a.len()).min(second.samples.len());
+    if to < from + a.len() {
+        return Err(Error::NoOverlap);
+    }
+    let b = second.samples.range(from..to);
+
+    let matched = if envelopes {
+        best_match(&envelope(&a, sample_rate), &envelope(&b, sample_rate))
+    } else {
+        best_match(&a, &b)
+    };
+    let (found, correlation) = matched.ok_or(Error::TooShort)?;
+    Ok(ArrivalDifference {
+        delay: (from as f64 + found - expected) / sample_rate as f64,
+        correlation,
+    })
+}
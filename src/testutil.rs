@@ -0,0 +1,9 @@
+use std::{fs, path::PathBuf};
+
+/// A fresh directory to record into, emptied of anything an earlier run left behind
+pub(crate) fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("hamshark-{}-{}", name, std::process::id()));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
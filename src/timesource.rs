@@ -0,0 +1,72 @@
+use crate::data::audio::ClipId;
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// Where the time comes from for naming sessions and clips and stamping when recordings
+/// started. Everything asks the session's, so a test can say what time it is.
+pub trait TimeSource: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Shared between the session and its recorders
+pub type SharedTimeSource = Arc<dyn TimeSource>;
+
+/// The system clock, which is what's used unless something else is asked for
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemTime;
+
+impl TimeSource for SystemTime {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when it's told to, for tests
+#[derive(Debug, Clone)]
+pub struct ManualTime(Arc<Mutex<DateTime<Utc>>>);
+
+impl ManualTime {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self(Arc::new(Mutex::new(start)))
+    }
+
+    pub fn set(&self, time: DateTime<Utc>) {
+        *self.0.lock() = time;
+    }
+
+    pub fn advance(&self, by: TimeDelta) {
+        *self.0.lock() += by;
+    }
+}
+
+impl TimeSource for ManualTime {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock()
+    }
+}
+
+pub fn system() -> SharedTimeSource {
+    Arc::new(SystemTime)
+}
+
+/// The name of a session directory started at `time`, in UTC with a Z on the end, or local
+/// time
+pub fn session_name(time: DateTime<Utc>, utc: bool) -> String {
+    if utc {
+        time.format("%Y-%m-%d_%H-%M-%SZ").to_string()
+    } else {
+        time.with_timezone(&Local)
+            .format("%Y-%m-%d_%H-%M-%S")
+            .to_string()
+    }
+}
+
+/// The id of a clip started at `time`, named in UTC or local time like its session
+pub fn clip_id(time: DateTime<Utc>, utc: bool) -> ClipId {
+    if utc {
+        ClipId::from_datetimeutc(time)
+    } else {
+        ClipId::from_datetimelocal(time.with_timezone(&Local))
+    }
+}
@@ -1,16 +1,71 @@
-use crate::data::{
-    audio::{self, Clip},
-    audioinput::AudioInputDevice,
+#[cfg(feature = "plugins")]
+use crate::plugins::{self, SinkInstance};
+#[cfg(feature = "icecast")]
+use crate::streaming::IcecastSink;
+use crate::{
+    capture::{self, BlockTap, CaptureWriter},
+    config::{ChannelMapping, DspSettings, Settings},
+    data::{
+        audio::{self, Clip},
+        audioinput::AudioInputDevice,
+        blanker::{self, BlankerSettings, NoiseBlanker},
+        metadata::Marker,
+    },
+    events::Observers,
+    fft::Transform,
+    pipeline::{ElementStatus, Pipeline},
+    ring::{self, RingWriter},
+    timesource::SharedTimeSource,
+    vban::{self, VbanReceiver},
+    virtualinput::{VirtualInput, VirtualStream},
+    watchdog::{self, Watchdog},
 };
+#[cfg(feature = "jack")]
+use crate::{
+    config::JackConnection,
+    jack::{self, JackInfo, JackInput},
+};
+use chrono::{DateTime, TimeDelta, Utc};
 use cpal::{
-    Stream,
-    traits::{DeviceTrait, StreamTrait},
+    BuildStreamError, InputCallbackInfo, PlayStreamError, Stream, StreamError, default_host,
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+};
+use log::warn;
+use parking_lot::Mutex;
+use rustfft::num_complex::Complex;
+use std::{
+    io,
+    ops::Range,
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
 };
-use log::error;
-use parking_lot::RwLock;
-use std::sync::Arc;
 use thiserror::Error as ThisError;
 
+/// What the clip writer is called in the pipeline
+pub const WRITER_ELEMENT_NAME: &str = "Clip writer";
+/// How many seconds of input can be waiting to be written before we start dropping it
+const BUFFER_SECONDS: usize = 10;
+/// How often a paused writer checks whether it's been resumed
+const PAUSE_POLL: Duration = Duration::from_millis(10);
+/// How often the writer looks for more samples once it's written everything waiting
+const WRITE_POLL: Duration = Duration::from_millis(5);
+/// The most the writer takes at once, in fractions of a second
+const WRITES_PER_SECOND: usize = 20;
+/// How much of the microphone can wait for the rig when mixing, in fractions of a second.
+/// The two devices' clocks drift apart, so past this the oldest is dropped rather than have
+/// the microphone fall further and further behind.
+const MIX_SLACK: u32 = 4;
+/// How many seconds playback reads ahead of what's playing, and how many pieces a second it
+/// reads in
+const PREFETCH_SECONDS: usize = 2;
+const PREFETCHES_PER_SECOND: usize = 10;
+/// How often the read-ahead looks for room once it's full
+const PREFETCH_POLL: Duration = Duration::from_millis(10);
+
 #[derive(Debug, ThisError)]
 pub enum Error {
     #[error("Error playing input stream: {0}")]
@@ -21,78 +76,1058 @@ pub enum Error {
     DuringStream(#[from] cpal::StreamError),
     #[error("Error working with audio clip: {0}")]
     Audio(#[from] audio::Error),
+    #[error("{0}")]
+    Capture(#[from] capture::Error),
+    #[error("Error starting clip writer: {0}")]
+    SpawnWriter(#[source] io::Error),
+    #[error("Error starting stream: {0}")]
+    SpawnStream(#[source] io::Error),
+    #[error("Error starting playback read-ahead: {0}")]
+    SpawnPrefetch(#[source] io::Error),
+    #[error("{0}")]
+    Vban(#[from] vban::Error),
+    #[cfg(feature = "jack")]
+    #[error("{0}")]
+    Jack(#[from] jack::Error),
+    #[error("Buffer full, dropped {0} samples")]
+    BufferFull(usize),
+    #[error("The input device has gone away")]
+    DeviceGone,
+    #[error("The clip writer thread panicked")]
+    WriterPanicked,
+    #[error("No audio output device available")]
+    NoOutputDevice,
+    #[error("Error getting output configuration: {0}")]
+    OutputConfig(#[from] cpal::DefaultStreamConfigError),
 }
 
+/// Records from an input device, a VBAN stream or JACK into a clip. The source hands blocks of
+/// samples through a buffer to a writer thread, so a slow disk doesn't hold up the audio
+/// thread. Nothing the source does allocates or takes a lock it could wait on.
 pub struct SampleRecorder {
-    stream: Stream,
-    write_error: Arc<RwLock<Option<Error>>>,
+    clip: Clip,
+    /// Taken when the recorder shuts down
+    source: Option<Source>,
+    writer: Option<JoinHandle<()>>,
+    /// Capturing the blocks the input device delivers, if that's on
+    capture: Option<CaptureWriter>,
+    pipeline: Pipeline,
+    /// When mixing in a microphone
+    levels: Option<Arc<MixLevels>>,
+    /// New noise blanker settings for the writer to pick up
+    blanker_settings: Arc<Mutex<Option<BlankerSettings>>>,
+    /// The clip for the writer to carry on into, finishing the one it's writing
+    next_clip: Arc<Mutex<Option<Clip>>>,
+    errors: Arc<SourceErrors>,
+}
+
+/// Where a recorder's samples come from
+enum Source {
+    Device(Stream),
+    Mixed {
+        rig: Stream,
+        microphone: Stream,
+    },
+    Vban(VbanReceiver),
+    #[cfg(feature = "jack")]
+    Jack(JackInput),
+    Virtual(VirtualStream),
+}
+
+/// What went wrong at the source, flagged for the writer to report. Reporting means
+/// formatting and locking, which the audio thread can't afford.
+#[derive(Default)]
+struct SourceErrors {
+    /// Nothing more is coming from the source
+    failed: AtomicBool,
+    /// Because the device went away, rather than anything we can report and carry on from
+    device_lost: AtomicBool,
+    /// Samples dropped because the buffer was full, since the writer last looked
+    dropped: AtomicUsize,
+    /// Set when there's an error waiting in `latest`
+    pending: AtomicBool,
+    /// Only ever tried by the source, so it never waits for the writer
+    latest: Mutex<Option<Error>>,
+}
+
+/// The input end of a recorder, that a source pushes blocks of samples into
+#[derive(Clone)]
+struct Feed {
+    /// Closes the ring when the last clone goes, which is how the writer knows to finish
+    ring: Arc<RingWriter>,
+    input: Arc<ElementStatus>,
+    buffer: Arc<ElementStatus>,
+    errors: Arc<SourceErrors>,
+    /// When the first sample was captured, found here and saved by the writer
+    started: Arc<OnceLock<DateTime<Utc>>>,
+    /// What time it is, by the session's clock
+    time: SharedTimeSource,
+}
+
+impl Feed {
+    /// Hand a block to the writer. `latency` is how long ago it was captured.
+    fn push(&self, data: &[f32], latency: Duration) {
+        self.push_iter(data.len(), data.iter().copied(), latency);
+    }
+
+    /// Hand `len` samples to the writer as they're worked out, without a block to put them in
+    fn push_iter(&self, len: usize, samples: impl IntoIterator<Item = f32>, latency: Duration) {
+        if self.input.is_paused() || self.errors.failed.load(Ordering::Relaxed) {
+            return;
+        }
+        self.started.get_or_init(|| self.time.now() - latency);
+        self.input.record_block();
+        // Counted whether or not there's room for them, for the watchdog to see they came
+        self.input.record_processed(len);
+        // Counted before they're in the ring, so the writer can't take them off the count
+        // first and wrap it round
+        self.buffer.enqueue(len);
+        if !self.ring.push_iter(len, samples) {
+            self.buffer.dequeue(len);
+            self.errors.dropped.fetch_add(len, Ordering::Relaxed);
+        }
+    }
+
+    /// Pushes blocks of `channels` channels, mapped down to what's recorded
+    fn mapped(
+        self,
+        mapping: ChannelMapping,
+        channels: u16,
+    ) -> impl FnMut(&[f32], Duration) + Send + 'static {
+        move |data, latency| match mapping {
+            ChannelMapping::KeepAll => self.push(data, latency),
+            ChannelMapping::Average | ChannelMapping::Pick(_) => {
+                let frames = data.len() / channels.max(1) as usize;
+                self.push_iter(frames, mono(data, channels, mapping), latency);
+            }
+        }
+    }
+
+    fn error(&self, error: Error) {
+        if let Some(mut latest) = self.errors.latest.try_lock() {
+            *latest = Some(error);
+            self.errors.pending.store(true, Ordering::Release);
+        }
+    }
+
+    /// Nothing more is coming from the source
+    fn fail(&self, error: Error) {
+        self.errors.failed.store(true, Ordering::Relaxed);
+        self.error(error);
+    }
+
+    /// Pass on a stream error, which is the end of the recording if the device has gone
+    fn stream_error(&self, error: StreamError) {
+        if let StreamError::DeviceNotAvailable = error {
+            self.errors.device_lost.store(true, Ordering::Relaxed);
+            self.fail(Error::DeviceGone);
+        } else {
+            self.error(Error::from(error));
+        }
+    }
+}
+
+impl SourceErrors {
+    /// Pass on what the source flagged since the last time
+    fn report(&self, input: &ElementStatus, buffer: &ElementStatus, observers: &Observers) {
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            let error = Error::BufferFull(dropped);
+            buffer.record_error(&error);
+            observers.error(buffer.name(), &error);
+        }
+        if self.pending.swap(false, Ordering::Acquire)
+            && let Some(error) = self.latest.lock().take()
+        {
+            if self.failed.load(Ordering::Relaxed) {
+                input.fail(&error);
+            } else {
+                input.record_error(&error);
+            }
+            observers.error(input.name(), &error);
+        }
+    }
+}
+
+/// The gains for each side of a mixed recording, shared with the audio callbacks
+struct MixLevels {
+    rig: AtomicU32,
+    microphone: AtomicU32,
+}
+
+impl MixLevels {
+    fn new(rig_gain: f32, microphone_gain: f32) -> Self {
+        let levels = Self {
+            rig: AtomicU32::new(0),
+            microphone: AtomicU32::new(0),
+        };
+        levels.set(rig_gain, microphone_gain);
+        levels
+    }
+
+    /// Gains in dB
+    fn set(&self, rig_gain: f32, microphone_gain: f32) {
+        let linear = |db: f32| 10f32.powf(db / 20.0).to_bits();
+        self.rig.store(linear(rig_gain), Ordering::Relaxed);
+        self.microphone
+            .store(linear(microphone_gain), Ordering::Relaxed);
+    }
+
+    fn rig(&self) -> f32 {
+        f32::from_bits(self.rig.load(Ordering::Relaxed))
+    }
+
+    fn microphone(&self) -> f32 {
+        f32::from_bits(self.microphone.load(Ordering::Relaxed))
+    }
+}
+
+/// One channel's worth of a block, averaging each frame's channels
+pub(crate) fn downmix(data: &[f32], channels: u16) -> impl Iterator<Item = f32> + '_ {
+    mono(data, channels, ChannelMapping::Average)
+}
+
+/// One channel's worth of a block, mapping each frame down to one sample
+fn mono(data: &[f32], channels: u16, mapping: ChannelMapping) -> impl Iterator<Item = f32> + '_ {
+    data.chunks(channels.max(1) as usize)
+        .map(move |frame| mapping.mono(frame))
+}
+
+/// What VBAN and JACK inputs go by in place of a host name, for their channel mappings and
+/// frequency corrections
+pub const VBAN_HOST: &str = "VBAN";
+pub const JACK_HOST: &str = "JACK";
+
+/// Which channels to record from an input device
+pub(crate) fn channel_mapping(
+    settings: &Settings,
+    audioinput: &AudioInputDevice,
+) -> ChannelMapping {
+    let device = audioinput.device.name().unwrap_or_default();
+    settings.channel_mapping(audioinput.host_id.name(), &device)
+}
+
+/// Which channels to record from the VBAN stream, by its name
+pub(crate) fn vban_mapping(settings: &Settings) -> ChannelMapping {
+    settings.channel_mapping(VBAN_HOST, &settings.vban.stream)
+}
+
+/// Which channels to record from our JACK ports, by the client name
+#[cfg(feature = "jack")]
+pub(crate) fn jack_mapping(settings: &Settings) -> ChannelMapping {
+    settings.channel_mapping(JACK_HOST, &settings.jack.client_name)
+}
+
+/// Build a stream on an input device and start it. Each block goes to `on_data` with how long
+/// ago the device says it was captured, and to `capture` first if there is one.
+fn play_input(
+    audioinput: &AudioInputDevice,
+    mut capture: Option<BlockTap>,
+    mut on_data: impl FnMut(&[f32], Duration) + Send + 'static,
+    on_error: impl FnMut(StreamError) + Send + 'static,
+) -> Result<Stream, Error> {
+    let mut first = None;
+    let stream = audioinput.device.build_input_stream(
+        &audioinput.config,
+        move |data: &[f32], info: &InputCallbackInfo| {
+            let timestamp = info.timestamp();
+            let latency = timestamp
+                .callback
+                .duration_since(&timestamp.capture)
+                .unwrap_or_default();
+            if let Some(capture) = &mut capture {
+                let first = *first.get_or_insert(timestamp.callback);
+                let at = timestamp
+                    .callback
+                    .duration_since(&first)
+                    .unwrap_or_default();
+                capture.push(data, at, latency);
+            }
+            on_data(data, latency);
+        },
+        on_error,
+        None,
+    );
+    let stream = match stream {
+        Err(BuildStreamError::DeviceNotAvailable) => return Err(Error::DeviceGone),
+        stream => stream?,
+    };
+    match stream.play() {
+        Err(PlayStreamError::DeviceNotAvailable) => return Err(Error::DeviceGone),
+        played => played?,
+    }
+    Ok(stream)
+}
+
+/// Gathers recorded samples into FFT-sized runs and hands their spectra to the observers
+struct FftTap {
+    fft: Transform<f32>,
+    window: Vec<f32>,
+    pending: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+}
+
+impl FftTap {
+    fn new(dsp: &DspSettings) -> Self {
+        Self {
+            fft: Transform::forward(dsp.fft_size),
+            window: dsp.window_function.coefficients(dsp.fft_size),
+            pending: Vec::with_capacity(dsp.fft_size),
+            buffer: vec![Complex::default(); dsp.fft_size],
+        }
+    }
+
+    fn push(&mut self, samples: &[f32], observers: &Observers) {
+        for &sample in samples {
+            self.pending.push(sample);
+            if self.pending.len() == self.window.len() {
+                for ((out, sample), window) in
+                    self.buffer.iter_mut().zip(&self.pending).zip(&self.window)
+                {
+                    *out = Complex::new(sample * window, 0.0);
+                }
+                self.pending.clear();
+                self.fft.process(&mut self.buffer);
+                observers.fft(&self.buffer);
+            }
+        }
+    }
 }
 
 impl SampleRecorder {
-    pub fn new(audioinput: &AudioInputDevice, clip: Clip) -> Result<Self, Error> {
-        let write_error = Arc::new(RwLock::new(None));
+    pub fn new(
+        audioinput: &AudioInputDevice,
+        clip: Clip,
+        observers: Observers,
+        settings: &Settings,
+        time: &SharedTimeSource,
+    ) -> Result<Self, Error> {
+        let mapping = channel_mapping(settings, audioinput);
+        let channels = audioinput.config.channels;
+        let sample_rate = audioinput.config.sample_rate.0;
+        let (tap, capture) = if settings.recording.capture_blocks {
+            let path = capture::capture_path(clip.read().path());
+            let (tap, capture) = CaptureWriter::start(&path, sample_rate, channels)?;
+            (Some(tap), Some(capture))
+        } else {
+            (None, None)
+        };
+        let (mut recorder, feed) = Self::start_writer(
+            clip,
+            observers,
+            settings,
+            time,
+            "Audio input",
+            sample_rate,
+            mapping.channels(channels),
+        )?;
+        recorder.capture = capture;
 
-        let stream = match audioinput.device.build_input_stream(
-            &audioinput.config,
-            {
-                let write_error = write_error.clone();
-                move |data: &[f32], _info| {
-                    if write_error.read().is_some() {
-                        return;
-                    };
+        let stream = play_input(
+            audioinput,
+            tap,
+            feed.clone().mapped(mapping, channels),
+            // Nothing more is coming from an unplugged device, so that's the end of it
+            move |err| feed.stream_error(err),
+        )?;
 
-                    let mut clip_guard = clip.write();
-                    if let Err(error) = clip_guard.write_samples(data) {
-                        *write_error.write() = Some(Error::from(error));
-                    }
+        recorder.source = Some(Source::Device(stream));
+        Ok(recorder)
+    }
+
+    /// Record the rig on the left channel and a microphone on the right, each at its own
+    /// level. The rig's device sets the pace, the microphone has to run at the same rate.
+    pub fn mixed(
+        rig: &AudioInputDevice,
+        microphone: &AudioInputDevice,
+        clip: Clip,
+        observers: Observers,
+        settings: &Settings,
+        time: &SharedTimeSource,
+    ) -> Result<Self, Error> {
+        let sample_rate = rig.config.sample_rate.0;
+        let (mut recorder, feed) = Self::start_writer(
+            clip,
+            observers,
+            settings,
+            time,
+            "Mixed input",
+            sample_rate,
+            2,
+        )?;
+        let levels = Arc::new(MixLevels::new(
+            settings.mix.rig_gain,
+            settings.mix.microphone_gain,
+        ));
+        let slack = (sample_rate / MIX_SLACK) as usize;
+        // Room for the slack and then some, the rig trims it back each time round
+        let (waiting, microphone_samples) = ring::sample_ring(4 * slack);
+
+        // Each side is one channel of the mix, so keeping all of them means averaging
+        let one_of = |audioinput| match channel_mapping(settings, audioinput) {
+            ChannelMapping::KeepAll => ChannelMapping::Average,
+            mapping => mapping,
+        };
+        let (rig_mapping, microphone_mapping) = (one_of(rig), one_of(microphone));
+        let microphone = play_input(
+            microphone,
+            None,
+            {
+                let channels = microphone.config.channels;
+                move |data, _| {
+                    let frames = data.len() / channels.max(1) as usize;
+                    waiting.push_iter(frames, mono(data, channels, microphone_mapping));
                 }
             },
             {
-                let write_error = write_error.clone();
-                move |err| {
-                    if write_error.read().is_some() {
-                        return;
-                    };
-                    *write_error.write() = Some(Error::from(err));
-                }
+                let feed = feed.clone();
+                // Losing the microphone leaves the right channel quiet, the rig carries on
+                move |err| feed.error(Error::from(err))
             },
+        )?;
+        let rig = play_input(
+            rig,
             None,
-        ) {
-            Ok(stream) => match stream.play() {
-                Ok(_) => stream,
-                Err(err) => return Err(Error::from(err)),
+            {
+                let feed = feed.clone();
+                let levels = levels.clone();
+                let channels = rig.config.channels;
+                move |data, latency| {
+                    let (rig_level, microphone_level) = (levels.rig(), levels.microphone());
+                    let frames = data.len() / channels.max(1) as usize;
+                    let excess = microphone_samples.len().saturating_sub(slack + frames);
+                    microphone_samples.skip(excess);
+                    let mixed = mono(data, channels, rig_mapping).flat_map(|sample| {
+                        [
+                            sample * rig_level,
+                            microphone_samples.pop().unwrap_or(0.0) * microphone_level,
+                        ]
+                    });
+                    feed.push_iter(2 * frames, mixed, latency);
+                }
             },
-            Err(err) => return Err(Error::from(err)),
+            move |err| feed.stream_error(err),
+        )?;
+
+        recorder.source = Some(Source::Mixed { rig, microphone });
+        recorder.levels = Some(levels);
+        Ok(recorder)
+    }
+
+    /// Change the rig and microphone gains, in dB, while mixing
+    pub fn set_mix_gains(&self, rig_gain: f32, microphone_gain: f32) {
+        if let Some(levels) = &self.levels {
+            levels.set(rig_gain, microphone_gain);
+        }
+    }
+
+    /// Change the noise blanker settings while recording
+    pub fn set_blanker(&self, settings: &BlankerSettings) {
+        *self.blanker_settings.lock() = Some(settings.clone());
+    }
+
+    /// Carry on recording into `next`. The writer finishes the clip it's writing between one
+    /// block and the next, so the input never stops and nothing falls between the two.
+    pub fn rotate(&mut self, next: Clip) {
+        *self.next_clip.lock() = Some(next.clone());
+        self.clip = next;
+    }
+
+    /// Finish the clip being written and carry on into `next`, stamped with when the
+    /// recording started plus what's been written since, if it's started. Returns whether
+    /// it was stamped.
+    fn switch_clip(
+        clip: &mut Clip,
+        next: Clip,
+        started: Option<&DateTime<Utc>>,
+        written: usize,
+        sample_rate: u32,
+    ) -> bool {
+        let finished = clip.write().finish();
+        if let Err(error) = finished {
+            warn!("Error finishing {}: {}", clip.read().id(), error);
+        }
+        *clip = next;
+        let Some(started) = started else {
+            return false;
+        };
+        let since = TimeDelta::microseconds(written as i64 * 1_000_000 / sample_rate.max(1) as i64);
+        let stamped = clip.write().set_started(*started + since);
+        if let Err(error) = stamped {
+            warn!("Unable to save clip start time: {}", error);
+        }
+        true
+    }
+
+    /// Record what a VBAN sender sends, instead of an input device
+    pub fn from_vban(
+        mut receiver: VbanReceiver,
+        clip: Clip,
+        observers: Observers,
+        settings: &Settings,
+        time: &SharedTimeSource,
+    ) -> Result<Self, Error> {
+        let format = receiver.format();
+        let mapping = vban_mapping(settings);
+        let (mut recorder, feed) = Self::start_writer(
+            clip,
+            observers,
+            settings,
+            time,
+            "VBAN input",
+            format.sample_rate,
+            mapping.channels(format.channels),
+        )?;
+        receiver
+            .start(
+                {
+                    let mut push = feed.clone().mapped(mapping, format.channels);
+                    move |samples| push(samples, Duration::ZERO)
+                },
+                move |error| feed.error(Error::from(error)),
+            )
+            .map_err(vban::Error::from)?;
+
+        recorder.source = Some(Source::Vban(receiver));
+        Ok(recorder)
+    }
+
+    /// Record from a virtual input, instead of an input device. Its blocks are recorded as
+    /// they're handed over, so the test driving it decides when.
+    pub fn from_virtual(
+        input: &VirtualInput,
+        clip: Clip,
+        observers: Observers,
+        settings: &Settings,
+        time: &SharedTimeSource,
+    ) -> Result<Self, Error> {
+        let mapping = settings.channel_mapping(VirtualInput::HOST, input.name());
+        let channels = input.channels();
+        let (mut recorder, feed) = Self::start_writer(
+            clip,
+            observers,
+            settings,
+            time,
+            "Virtual input",
+            input.sample_rate(),
+            mapping.channels(channels),
+        )?;
+        let stream = input.play(feed.clone().mapped(mapping, channels), move |err| {
+            feed.stream_error(err)
+        });
+
+        recorder.source = Some(Source::Virtual(stream));
+        Ok(recorder)
+    }
+
+    /// Record from our own JACK ports, instead of an input device
+    #[cfg(feature = "jack")]
+    pub fn from_jack(
+        mut input: JackInput,
+        clip: Clip,
+        observers: Observers,
+        settings: &Settings,
+        time: &SharedTimeSource,
+    ) -> Result<Self, Error> {
+        let mapping = jack_mapping(settings);
+        let channels = input.channels();
+        let (mut recorder, feed) = Self::start_writer(
+            clip,
+            observers,
+            settings,
+            time,
+            "JACK input",
+            input.sample_rate(),
+            mapping.channels(channels),
+        )?;
+        input.start(
+            feed.clone().mapped(mapping, channels),
+            move |error| match error {
+                jack::Error::Shutdown(_) => feed.fail(Error::from(error)),
+                error => feed.error(Error::from(error)),
+            },
+        )?;
+
+        recorder.source = Some(Source::Jack(input));
+        Ok(recorder)
+    }
+
+    /// What's connected to the JACK ports, when recording from JACK
+    #[cfg(feature = "jack")]
+    pub fn jack_connections(&self) -> Option<Vec<JackConnection>> {
+        match &self.source {
+            Some(Source::Jack(input)) => Some(input.connections()),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "jack")]
+    pub fn jack_info(&self) -> Option<JackInfo> {
+        match &self.source {
+            Some(Source::Jack(input)) => input.info(),
+            _ => None,
+        }
+    }
+
+    /// Set up the pipeline and start the writer. The recorder has no source yet, that's up to
+    /// the caller, pushing into the feed.
+    fn start_writer(
+        clip: Clip,
+        observers: Observers,
+        settings: &Settings,
+        time: &SharedTimeSource,
+        input_name: &'static str,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<(Self, Feed), Error> {
+        let realtime = settings.recording.realtime_priority;
+        let mut pipeline = Pipeline::default();
+        let input = pipeline.add(ElementStatus::new(input_name, true));
+        let watchdog_status = pipeline.add(ElementStatus::new(watchdog::ELEMENT_NAME, false));
+        let capacity = BUFFER_SECONDS * sample_rate as usize * channels.max(1) as usize;
+        let buffer = pipeline.add(ElementStatus::with_capacity("Buffer", false, capacity));
+        let blanker_status = pipeline.add(ElementStatus::new(blanker::ELEMENT_NAME, false));
+        let sink = pipeline.add(ElementStatus::new(WRITER_ELEMENT_NAME, true));
+        #[cfg(feature = "plugins")]
+        let plugin_sinks = plugins::add_sinks(&settings.plugins, &mut pipeline);
+        #[cfg(feature = "plugins")]
+        let clip_id = clip.read().id().clone();
+
+        let (ring, receiver) = ring::sample_ring(capacity);
+        let errors = Arc::<SourceErrors>::default();
+        // Whole frames at a time, so the channels stay in step
+        let frame = channels.max(1) as usize;
+        let most = (sample_rate as usize / WRITES_PER_SECOND).max(1) * frame;
+        let blanker_settings = Arc::new(Mutex::new(Some(settings.blanker.clone())));
+        let next_clip = Arc::new(Mutex::new(None::<Clip>));
+        let started = Arc::new(OnceLock::<DateTime<Utc>>::new());
+
+        #[cfg(feature = "icecast")]
+        let streaming = settings
+            .streaming
+            .enabled
+            .then(|| IcecastSink::start(&settings.streaming, sample_rate, channels))
+            .transpose()
+            .map_err(Error::SpawnStream)?;
+        #[cfg(not(feature = "icecast"))]
+        {
+            if settings.streaming.enabled {
+                warn!("Streaming needs hamshark built with the icecast feature");
+            }
+        }
+
+        let writer = thread::Builder::new()
+            .name("clip writer".to_string())
+            .spawn({
+                let clip = clip.clone();
+                let input = input.clone();
+                let buffer = buffer.clone();
+                let errors = errors.clone();
+                let mut fft = FftTap::new(&settings.dsp);
+                let started = started.clone();
+                let blanker_settings = blanker_settings.clone();
+                let next_clip = next_clip.clone();
+                let mut watchdog = Watchdog::new(sample_rate, channels);
+                #[cfg(feature = "plugins")]
+                let time = time.clone();
+                move || {
+                    let mut clip = clip;
+                    let mut stamped = false;
+                    // Frames written since the recording started, over every clip
+                    let mut written = 0;
+                    let mut blanker = None;
+                    let mut block = Vec::with_capacity(most);
+                    // Created here, plugins are told it's the thread they'll be called on
+                    #[cfg(feature = "plugins")]
+                    let mut plugin_sinks: Vec<_> = plugin_sinks
+                        .iter()
+                        .filter_map(|(sink, status)| {
+                            SinkInstance::start(
+                                sink,
+                                status,
+                                sample_rate,
+                                channels,
+                                &observers,
+                                clip_id.clone(),
+                                &time,
+                            )
+                        })
+                        .collect();
+                    // Held for as long as the thread runs, there's no need to demote it
+                    let _priority = realtime
+                        .then(|| {
+                            audio_thread_priority::promote_current_thread_to_real_time(
+                                0,
+                                sample_rate,
+                            )
+                            .inspect_err(|error| {
+                                warn!("Unable to raise clip writer priority: {}", error)
+                            })
+                            .ok()
+                        })
+                        .flatten();
+                    loop {
+                        if let Some(next) = next_clip.lock().take() {
+                            stamped = Self::switch_clip(
+                                &mut clip,
+                                next,
+                                started.get(),
+                                written,
+                                sample_rate,
+                            );
+                            #[cfg(feature = "plugins")]
+                            for plugin in &mut plugin_sinks {
+                                plugin.set_clip(clip.read().id().clone());
+                            }
+                        }
+                        errors.report(&input, &buffer, &observers);
+                        let paused = input.is_paused() || sink.is_paused();
+                        if let Some(alarm) = watchdog.check(input.processed(), paused) {
+                            warn!("{}", alarm);
+                            watchdog_status.record_error(&alarm);
+                            observers.error(watchdog_status.name(), &alarm);
+                            if let Some(label) = alarm.marker_label() {
+                                let mut clip = clip.write();
+                                let position = clip.samples.len();
+                                if let Err(error) = clip.add_marker(Marker::new(position, label)) {
+                                    warn!("Unable to mark {}: {}", clip.id(), error);
+                                }
+                            }
+                        }
+                        if sink.is_paused() {
+                            thread::sleep(PAUSE_POLL);
+                            continue;
+                        }
+                        block.clear();
+                        let taken =
+                            receiver.pop_into(&mut block, most.min(receiver.len() / frame * frame));
+                        if taken == 0 {
+                            // Ends when the source is dropped and everything it sent is written
+                            if receiver.is_finished() {
+                                break;
+                            }
+                            thread::sleep(WRITE_POLL);
+                            continue;
+                        }
+                        buffer.dequeue(taken);
+                        if let Some(settings) = blanker_settings.lock().take() {
+                            blanker = settings
+                                .enabled
+                                .then(|| NoiseBlanker::new(&settings, sample_rate, channels));
+                        }
+                        if let Some(blanker) = &mut blanker {
+                            blanker_status.record_events(blanker.process(&mut block));
+                        }
+                        blanker_status.record_processed(block.len());
+                        if sink.is_failed() {
+                            continue;
+                        }
+                        match clip.write().write_samples(&block) {
+                            Ok(()) => {
+                                sink.record_processed(block.len());
+                                written += block.len() / frame;
+                            }
+                            Err(error) => {
+                                let error = Error::from(error);
+                                sink.fail(&error);
+                                observers.error(sink.name(), &error);
+                                continue;
+                            }
+                        }
+                        if !stamped && let Some(&started) = started.get() {
+                            stamped = true;
+                            if let Err(error) = clip.write().set_started(started) {
+                                warn!("Unable to save clip start time: {}", error);
+                            }
+                        }
+                        observers.samples(&block, channels);
+                        #[cfg(feature = "plugins")]
+                        for plugin in &mut plugin_sinks {
+                            plugin.process(&block);
+                        }
+                        #[cfg(feature = "icecast")]
+                        if let Some(streaming) = &streaming {
+                            streaming.push(&block);
+                        }
+                        if observers.wants_fft() {
+                            fft.push(&block, &observers);
+                        }
+                    }
+                    // Asked for just as the input went away, it's finished on shutdown
+                    if let Some(next) = next_clip.lock().take() {
+                        Self::switch_clip(&mut clip, next, started.get(), written, sample_rate);
+                    }
+                }
+            })
+            .map_err(Error::SpawnWriter)?;
+
+        let recorder = Self {
+            clip,
+            source: None,
+            writer: Some(writer),
+            capture: None,
+            pipeline,
+            levels: None,
+            blanker_settings,
+            next_clip,
+            errors: errors.clone(),
+        };
+        let feed = Feed {
+            ring: Arc::new(ring),
+            input,
+            buffer,
+            errors,
+            started,
+            time: time.clone(),
+        };
+        Ok((recorder, feed))
+    }
+
+    /// The clip being recorded into
+    pub fn clip(&self) -> &Clip {
+        &self.clip
+    }
+
+    pub fn pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
+
+    /// Whether the input device went away, so nothing more is coming
+    pub fn device_lost(&self) -> bool {
+        self.errors.device_lost.load(Ordering::Relaxed)
+    }
+
+    pub fn close(mut self) -> Result<(), Error> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<(), Error> {
+        let Some(source) = self.source.take() else {
+            return Ok(());
         };
+        // Dropping the source hangs up on the writer. Make sure it isn't paused so it can
+        // write out what's buffered and notice.
+        match source {
+            Source::Device(stream) => {
+                stream.pause().ok();
+                drop(stream);
+            }
+            Source::Mixed { rig, microphone } => {
+                rig.pause().ok();
+                microphone.pause().ok();
+                drop(rig);
+                drop(microphone);
+            }
+            Source::Vban(receiver) => drop(receiver),
+            #[cfg(feature = "jack")]
+            Source::Jack(input) => drop(input),
+            Source::Virtual(stream) => drop(stream),
+        }
+        // The tap went with the stream, so this writes out what's left and finishes
+        self.capture.take();
+        self.pipeline.resume_all();
+        let joined = self.writer.take().map_or(Ok(()), JoinHandle::join);
+        // Finish what was written even if the writer fell over
+        self.clip.write().finish()?;
+        joined.map_err(|_| Error::WriterPanicked)
+    }
+}
+
+impl Drop for SampleRecorder {
+    /// Make sure the clip is written out properly even if nobody closed the recorder
+    fn drop(&mut self) {
+        if let Err(error) = self.shutdown() {
+            warn!("Error finishing clip: {}", error);
+        }
+    }
+}
 
+/// Watches how loud an input is without recording it, for checking levels
+pub struct LevelMeter {
+    _stream: Stream,
+    /// The loudest sample since the peak was last taken, as f32 bits. Non-negative floats
+    /// order the same as their bits, so the callback can use `fetch_max`.
+    peak: Arc<AtomicU32>,
+}
+
+impl LevelMeter {
+    pub fn new(audioinput: &AudioInputDevice) -> Result<Self, Error> {
+        let peak = Arc::new(AtomicU32::new(0));
+        let stream = audioinput.device.build_input_stream(
+            &audioinput.config,
+            {
+                let peak = peak.clone();
+                move |data: &[f32], _info| {
+                    let loudest = data.iter().fold(0.0f32, |acc, x| acc.max(x.abs()));
+                    peak.fetch_max(loudest.to_bits(), Ordering::Relaxed);
+                }
+            },
+            |err| warn!("Error during level check: {}", err),
+            None,
+        )?;
+        stream.play()?;
         Ok(Self {
-            stream,
-            write_error,
+            _stream: stream,
+            peak,
         })
     }
 
-    pub fn close(self) -> Result<(), Error> {
-        self.stream.pause().ok();
-        drop(self.stream);
-
-        Ok(())
+    /// The loudest sample since last asked, from 0 to 1
+    pub fn take_peak(&self) -> f32 {
+        f32::from_bits(self.peak.swap(0, Ordering::Relaxed))
     }
 }
 
-pub struct SampleLoader {
+/// Plays part of a clip out of the default output device, optionally over and over. A
+/// thread of its own reads ahead into a ring, since a clip that's spilled has to be read
+/// back from the file, and the output callback only takes from the ring.
+pub struct SamplePlayer {
     stream: Stream,
-    read_error: Arc<RwLock<Option<Error>>>,
+    /// The frame being played
+    position: Arc<AtomicUsize>,
+    finished: Arc<AtomicBool>,
+    /// Tells the read-ahead thread to stop
+    stopping: Arc<AtomicBool>,
+    prefetch: Option<JoinHandle<()>>,
 }
 
-/*impl SampleLoader {
-    pub fn new (clip: Clip) -> Result<Self, Error> {
-        let read_error = Arc::new(RwLock::new(None));
+impl SamplePlayer {
+    pub fn new(clip: Clip, range: Range<usize>, looping: bool) -> Result<Self, Error> {
+        let device = default_host()
+            .default_output_device()
+            .ok_or(Error::NoOutputDevice)?;
+        let config = device.default_output_config()?.config();
+        let channels = config.channels as usize;
+        let clip_rate = clip.read().sample_rate.0;
+        // Step through the clip at its own rate, whatever rate the device runs at. It holds
+        // one sample a frame, whatever channels were recorded, so this counts frames.
+        let step = clip_rate as f64 / config.sample_rate.0 as f64;
+        let start = range.start;
+        // A loop goes round what's there now. Otherwise it plays on into what's recorded
+        // meanwhile, up to the end of the range.
+        let loop_end = range.end.min(clip.read().samples.len());
+        let loop_len = loop_end.saturating_sub(start);
 
-        // TODO: build loading thread
+        let chunk = (clip_rate as usize / PREFETCHES_PER_SECOND).max(1);
+        let (ring, reader) =
+            ring::sample_ring((clip_rate as usize * PREFETCH_SECONDS).max(2 * chunk));
 
-        Ok(Self {
+        let position = Arc::new(AtomicUsize::new(start));
+        let finished = Arc::new(AtomicBool::new(false));
+        // Both counted in frames from the start of the range, round and round if looping
+        let mut cursor = 0.0f64;
+        let mut popped = 0usize;
+        let mut current = 0.0;
+
+        let stream = device.build_output_stream(
+            &config,
+            {
+                let position = position.clone();
+                let finished = finished.clone();
+                move |data: &mut [f32], _info| {
+                    for frame in data.chunks_mut(channels) {
+                        let wanted = cursor as usize;
+                        while popped <= wanted {
+                            match reader.pop() {
+                                Some(sample) => {
+                                    current = sample;
+                                    popped += 1;
+                                }
+                                None => break,
+                            }
+                        }
+                        // Silence while the read-ahead catches up, or once it's done
+                        let sample = if popped > wanted {
+                            cursor += step;
+                            current
+                        } else {
+                            if reader.is_finished() {
+                                finished.store(true, Ordering::Relaxed);
+                            }
+                            0.0
+                        };
+                        frame.fill(sample);
+                    }
+                    let played = match (looping, loop_len) {
+                        (true, len) if len > 0 => cursor as usize % len,
+                        _ => cursor as usize,
+                    };
+                    position.store(start + played, Ordering::Relaxed);
+                }
+            },
+            |err| log::error!("Error during playback: {}", Error::from(err)),
+            None,
+        )?;
+
+        let stopping = Arc::new(AtomicBool::new(false));
+        let prefetch = thread::Builder::new()
+            .name("playback read-ahead".to_string())
+            .spawn({
+                let stopping = stopping.clone();
+                move || {
+                    let mut at = start;
+                    while !stopping.load(Ordering::Relaxed) {
+                        let end = if looping {
+                            loop_end
+                        } else {
+                            range.end.min(clip.read().samples.len())
+                        };
+                        if at >= end {
+                            if !looping || loop_len == 0 {
+                                break;
+                            }
+                            at = start;
+                        }
+                        let fetched = clip.read().samples.fetch(at..end.min(at + chunk)).read();
+                        // Nothing there means it couldn't be read back, so that's the end
+                        if fetched.is_empty() {
+                            break;
+                        }
+                        at += fetched.len();
+                        while !ring.push_iter(fetched.len(), fetched.iter().copied()) {
+                            if stopping.load(Ordering::Relaxed) {
+                                return;
+                            }
+                            thread::sleep(PREFETCH_POLL);
+                        }
+                    }
+                    // Dropping the ring's writer tells the callback that's the end
+                }
+            })
+            .map_err(Error::SpawnPrefetch)?;
+        // Made before playing, so the read-ahead's stopped if it won't play
+        let player = Self {
             stream,
-            read_error,
-        })
+            position,
+            finished,
+            stopping,
+            prefetch: Some(prefetch),
+        };
+        player.stream.play()?;
+        Ok(player)
+    }
+
+    pub fn position(&self) -> usize {
+        self.position.load(Ordering::Relaxed)
+    }
+
+    /// Whether playback ran off the end of the range. Never happens while looping.
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    pub fn stop(self) {
+        self.stream.pause().ok();
     }
-}*/
+}
+
+impl Drop for SamplePlayer {
+    fn drop(&mut self) {
+        self.stopping.store(true, Ordering::Relaxed);
+        if let Some(prefetch) = self.prefetch.take()
+            && prefetch.join().is_err()
+        {
+            warn!("The playback read-ahead panicked");
+        }
+    }
+}
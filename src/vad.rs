@@ -0,0 +1,122 @@
+use crate::{config::VadSettings, data::window::WindowFunction, fft::Transform};
+use rustfft::num_complex::Complex;
+use std::ops::Range;
+
+/// Frames per second the audio is judged in
+const FRAMES_PER_SECOND: u32 = 50;
+/// Where most of the energy of speech is, and all of it a voice channel passes
+const SPEECH_BAND: Range<f64> = 300.0..3400.0;
+/// The percentile of frame energies taken as the noise floor. Repeater recordings are mostly
+/// quiet, so a low one is safe.
+const FLOOR_PERCENTILE: f64 = 0.1;
+/// Spectral flatness above which a frame is taken to be noise. Noise sits around 0.56, the
+/// harmonics of a voice bring it well under.
+const MAX_FLATNESS: f64 = 0.45;
+
+/// What's measured of each frame
+struct Frame {
+    /// Energy in the speech band, in dB
+    energy_db: f64,
+    /// Geometric over arithmetic mean of the power in the speech band
+    flatness: f64,
+}
+
+/// Finds where people are talking, from how loud the voice band is against the noise, and
+/// how much more peaked than noise its spectrum is. Feed it a clip's samples in order, in
+/// pieces of any size, then ask for the segments.
+pub struct SpeechDetector {
+    settings: VadSettings,
+    fft: Transform<f32>,
+    buffer: Vec<Complex<f32>>,
+    window: Vec<f32>,
+    /// Samples in a frame, the rest of the FFT is zeroes
+    frame_len: usize,
+    bins: Range<usize>,
+    /// Samples waiting for a frame to fill
+    pending: Vec<f32>,
+    frames: Vec<Frame>,
+}
+
+impl SpeechDetector {
+    pub fn new(settings: &VadSettings, sample_rate: u32) -> Self {
+        let frame_len = (sample_rate / FRAMES_PER_SECOND).max(64) as usize;
+        let size = frame_len.next_power_of_two();
+        let bin_hz = sample_rate as f64 / size as f64;
+        let bins = ((SPEECH_BAND.start / bin_hz).round() as usize).max(1)
+            ..((SPEECH_BAND.end / bin_hz).round() as usize).clamp(2, size / 2);
+        Self {
+            settings: settings.clone(),
+            fft: Transform::forward(size),
+            buffer: vec![Complex::default(); size],
+            window: WindowFunction::Hann.coefficients(frame_len),
+            frame_len,
+            bins,
+            pending: Vec::with_capacity(frame_len),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, mut samples: &[f32]) {
+        while !samples.is_empty() {
+            let take = (self.frame_len - self.pending.len()).min(samples.len());
+            self.pending.extend_from_slice(&samples[..take]);
+            samples = &samples[take..];
+            if self.pending.len() < self.frame_len {
+                break;
+            }
+
+            let buffer = &mut self.buffer;
+            buffer.fill(Complex::default());
+            for ((out, sample), w) in buffer.iter_mut().zip(&self.pending).zip(&self.window) {
+                *out = Complex::new(sample * w, 0.0);
+            }
+            self.pending.clear();
+            self.fft.process(buffer);
+
+            let power: Vec<f64> = buffer[self.bins.clone()]
+                .iter()
+                .map(|bin| (bin.norm_sqr() as f64).max(1e-20))
+                .collect();
+            let mean = power.iter().sum::<f64>() / power.len() as f64;
+            let log_mean = power.iter().map(|p| p.ln()).sum::<f64>() / power.len() as f64;
+            self.frames.push(Frame {
+                energy_db: 10.0 * mean.log10(),
+                flatness: log_mean.exp() / mean,
+            });
+        }
+    }
+
+    /// Where speech starts and ends, in samples
+    pub fn finish(self) -> Vec<Range<usize>> {
+        if self.frames.is_empty() {
+            return Vec::new();
+        }
+        let mut energies: Vec<f64> = self.frames.iter().map(|frame| frame.energy_db).collect();
+        energies.sort_by(f64::total_cmp);
+        let floor = energies[((energies.len() - 1) as f64 * FLOOR_PERCENTILE) as usize];
+        let threshold = floor + self.settings.threshold_db;
+
+        let frames_in = |ms: u64| (ms * FRAMES_PER_SECOND as u64).div_ceil(1000) as usize;
+        let hangover = frames_in(self.settings.hangover_ms);
+        let shortest = frames_in(self.settings.min_speech_ms);
+
+        // Runs of speech-like frames, joined when the gap between them is short
+        let mut segments: Vec<Range<usize>> = Vec::new();
+        for (index, frame) in self.frames.iter().enumerate() {
+            if frame.energy_db < threshold || frame.flatness > MAX_FLATNESS {
+                continue;
+            }
+            match segments.last_mut() {
+                Some(last) if index - last.end <= hangover => last.end = index + 1,
+                _ => segments.push(index..index + 1),
+            }
+        }
+
+        let frame_len = self.frame_len;
+        segments
+            .into_iter()
+            .filter(|segment| segment.len() >= shortest)
+            .map(|segment| segment.start * frame_len..segment.end * frame_len)
+            .collect()
+    }
+}
@@ -0,0 +1,325 @@
+use crate::config::VbanSettings;
+use log::info;
+use std::{
+    io,
+    net::UdpSocket,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use thiserror::Error as ThisError;
+
+/// How long to wait for the stream to show up when starting
+const WAIT: Duration = Duration::from_secs(3);
+/// How often the receiving thread checks whether it's time to stop
+const STOP_POLL: Duration = Duration::from_millis(100);
+const HEADER_LEN: usize = 28;
+/// Big enough for any VBAN packet
+const MAX_PACKET: usize = 2048;
+/// Indexed by the low five bits of the fourth header byte
+const SAMPLE_RATES: [u32; 21] = [
+    6000, 12000, 24000, 48000, 96000, 192000, 384000, 8000, 16000, 32000, 64000, 128000, 256000,
+    512000, 11025, 22050, 44100, 88200, 176400, 352800, 705600,
+];
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("Error receiving VBAN: {0}")]
+    IO(#[from] io::Error),
+    #[error("No VBAN stream named {0} arrived")]
+    NoStream(String),
+    #[error("Lost {0} VBAN packets")]
+    Lost(u32),
+    #[error("The VBAN stream changed format from {0:?} to {1:?}")]
+    FormatChanged(VbanFormat, VbanFormat),
+}
+
+/// The shape of the audio in a VBAN stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VbanFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// An audio packet, with its samples converted to f32
+struct Packet<'a> {
+    stream: &'a [u8],
+    format: VbanFormat,
+    counter: u32,
+}
+
+/// Read the header of an uncompressed audio packet and convert its samples into `samples`.
+/// Anything else, like the other VBAN sub-protocols, is None.
+fn parse<'a>(packet: &'a [u8], samples: &mut Vec<f32>) -> Option<Packet<'a>> {
+    if packet.len() < HEADER_LEN || &packet[..4] != b"VBAN" {
+        return None;
+    }
+    // Audio is sub-protocol 0, and PCM is codec 0
+    if packet[4] & 0xe0 != 0 || packet[7] & 0xf0 != 0 {
+        return None;
+    }
+    let sample_rate = *SAMPLE_RATES.get((packet[4] & 0x1f) as usize)?;
+    let channels = packet[6] as u16 + 1;
+    let name = &packet[8..24];
+    let stream = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+    let counter = u32::from_le_bytes([packet[24], packet[25], packet[26], packet[27]]);
+
+    let data = &packet[HEADER_LEN..];
+    samples.clear();
+    match packet[7] & 0x07 {
+        0 => samples.extend(data.iter().map(|&b| (b as f32 - 128.0) / 128.0)),
+        1 => samples.extend(
+            data.chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0),
+        ),
+        2 => samples.extend(
+            data.chunks_exact(3)
+                .map(|b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2147483648.0),
+        ),
+        3 => samples.extend(
+            data.chunks_exact(4)
+                .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2147483648.0),
+        ),
+        4 => samples.extend(
+            data.chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        ),
+        5 => {
+            samples.extend(data.chunks_exact(8).map(|b| {
+                f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f32
+            }))
+        }
+        // 12 and 10 bit samples aren't worth the trouble
+        _ => return None,
+    }
+    Some(Packet {
+        stream,
+        format: VbanFormat {
+            sample_rate,
+            channels,
+        },
+        counter,
+    })
+}
+
+/// Receives a VBAN audio stream over UDP, as sent by VoiceMeeter and friends, so it can be
+/// recorded like an input device
+pub struct VbanReceiver {
+    socket: UdpSocket,
+    stream: String,
+    format: VbanFormat,
+    /// Cleared to stop the receiving thread, once started
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl VbanReceiver {
+    /// Listen for the stream, waiting a few seconds for it to show up so we know its format
+    pub fn open(settings: &VbanSettings) -> Result<Self, Error> {
+        let socket = UdpSocket::bind(&settings.listen)?;
+        socket.set_read_timeout(Some(STOP_POLL))?;
+        let deadline = Instant::now() + WAIT;
+        let mut buffer = [0u8; MAX_PACKET];
+        let mut samples = Vec::new();
+        let format = loop {
+            if Instant::now() >= deadline {
+                return Err(Error::NoStream(settings.stream.clone()));
+            }
+            let len = match socket.recv(&mut buffer) {
+                Ok(len) => len,
+                Err(error)
+                    if matches!(
+                        error.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            };
+            if let Some(packet) = parse(&buffer[..len], &mut samples)
+                && packet.stream == settings.stream.as_bytes()
+            {
+                break packet.format;
+            }
+        };
+        info!(
+            "Receiving VBAN stream {} on {}: {:?}",
+            settings.stream, settings.listen, format
+        );
+        Ok(Self {
+            socket,
+            stream: settings.stream.clone(),
+            format,
+            running: Arc::new(AtomicBool::new(true)),
+            thread: None,
+        })
+    }
+
+    pub fn format(&self) -> VbanFormat {
+        self.format
+    }
+
+    /// Hand each packet's samples to `on_samples` from a thread of our own, until dropped.
+    /// Lost packets and format changes go to `on_error`; packets in a different format are
+    /// dropped.
+    pub fn start(
+        &mut self,
+        mut on_samples: impl FnMut(&[f32]) + Send + 'static,
+        on_error: impl Fn(Error) + Send + 'static,
+    ) -> Result<(), io::Error> {
+        let socket = self.socket.try_clone()?;
+        let stream = self.stream.clone();
+        let format = self.format;
+        let running = self.running.clone();
+        let thread = thread::Builder::new()
+            .name("vban receiver".to_string())
+            .spawn(move || {
+                let mut buffer = [0u8; MAX_PACKET];
+                let mut samples = Vec::new();
+                let mut next_counter = None;
+                while running.load(Ordering::Relaxed) {
+                    let len = match socket.recv(&mut buffer) {
+                        Ok(len) => len,
+                        Err(error)
+                            if matches!(
+                                error.kind(),
+                                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                            ) =>
+                        {
+                            continue;
+                        }
+                        Err(error) => {
+                            on_error(error.into());
+                            continue;
+                        }
+                    };
+                    let Some(packet) = parse(&buffer[..len], &mut samples) else {
+                        continue;
+                    };
+                    if packet.stream != stream.as_bytes() {
+                        continue;
+                    }
+                    if packet.format != format {
+                        on_error(Error::FormatChanged(format, packet.format));
+                        continue;
+                    }
+                    if let Some(expected) = next_counter
+                        && packet.counter != expected
+                    {
+                        on_error(Error::Lost(packet.counter.wrapping_sub(expected)));
+                    }
+                    next_counter = Some(packet.counter.wrapping_add(1));
+                    on_samples(&samples);
+                }
+            })?;
+        self.thread = Some(thread);
+        Ok(())
+    }
+}
+
+impl Drop for VbanReceiver {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{ChannelMapping, Settings},
+        session::Session,
+        testutil::scratch_dir,
+        tools,
+    };
+    use std::fs;
+
+    /// A 16 bit VBAN audio packet at 8 kHz
+    fn packet(stream: &str, channels: u8, counter: u32, frames: &[i16]) -> Vec<u8> {
+        let mut packet = b"VBAN".to_vec();
+        packet.extend([
+            7,
+            (frames.len() / channels as usize - 1) as u8,
+            channels - 1,
+            1,
+        ]);
+        let mut name = [0u8; 16];
+        name[..stream.len()].copy_from_slice(stream.as_bytes());
+        packet.extend(name);
+        packet.extend(counter.to_le_bytes());
+        packet.extend(frames.iter().flat_map(|sample| sample.to_le_bytes()));
+        packet
+    }
+
+    #[test]
+    fn stereo_streams_are_recorded_through_the_channel_mapping() {
+        let dir = scratch_dir("vban");
+        let listen = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut settings = Settings::default();
+        settings.vban.enabled = true;
+        settings.vban.listen = listen.to_string();
+        // Averaged down to mono otherwise, like any other input
+        let stream = settings.vban.stream.clone();
+        settings.set_channel_mapping(tools::VBAN_HOST, &stream, ChannelMapping::KeepAll);
+
+        // Sends until the recording's done, so there's something to find when it opens
+        let sending = Arc::new(AtomicBool::new(true));
+        let sender = thread::spawn({
+            let sending = sending.clone();
+            let stream = settings.vban.stream.clone();
+            move || {
+                let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+                let mut counter = 0;
+                while sending.load(Ordering::Relaxed) {
+                    let frames: Vec<i16> = (0..128)
+                        .map(|i| if i % 2 == 0 { 1000 } else { -1000 })
+                        .collect();
+                    socket
+                        .send_to(&packet(&stream, 2, counter, &frames), listen)
+                        .unwrap();
+                    counter += 1;
+                    thread::sleep(Duration::from_millis(8));
+                }
+            }
+        });
+
+        let mut session = Session::open(dir.clone(), &settings).unwrap();
+        let record = |session: &mut Session| {
+            session.record_new_clip().unwrap();
+            let clip = session.recording_clip().unwrap().clone();
+            thread::sleep(Duration::from_millis(300));
+            session.stop_recording().unwrap();
+            let reader = hound::WavReader::open(clip.read().path()).unwrap();
+            let channels = reader.spec().channels;
+            let samples: Vec<i32> = reader.into_samples().map(Result::unwrap).collect();
+            assert!(samples.len() >= 2);
+            (channels, samples)
+        };
+
+        let (channels, samples) = record(&mut session);
+        assert_eq!(channels, 2);
+        assert_eq!(samples.len() % 2, 0);
+        assert!(samples[0] > 0 && samples[1] < 0);
+
+        settings.set_channel_mapping(tools::VBAN_HOST, &stream, ChannelMapping::Pick(1));
+        session.apply_settings(&settings);
+        let (channels, samples) = record(&mut session);
+        assert_eq!(channels, 1);
+        assert!(samples.iter().all(|&sample| sample < 0));
+
+        sending.store(false, Ordering::Relaxed);
+        sender.join().unwrap();
+        drop(session);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,423 @@
+use crate::capture::Capture;
+use cpal::StreamError;
+use parking_lot::Mutex;
+use std::{ops::Range, sync::Arc, time::Duration};
+
+/// Frames in each block handed over, like a device's buffer size
+const BLOCK_FRAMES: usize = 128;
+
+type DataCallback = Box<dyn FnMut(&[f32], Duration) + Send>;
+type ErrorCallback = Box<dyn FnMut(StreamError) + Send>;
+
+/// An input device that plays canned samples instead of a sound card's, for testing
+/// recording without one. Its clock only moves when [`VirtualInput::advance`] says so, and
+/// blocks are handed over on the thread that moved it, so a test knows exactly what's been
+/// captured by when. Clones share the same playback.
+#[derive(Clone)]
+pub struct VirtualInput {
+    name: String,
+    sample_rate: u32,
+    channels: u16,
+    playback: Arc<Mutex<Playback>>,
+}
+
+/// A block of the samples, and when it's handed over
+struct Block {
+    range: Range<usize>,
+    at: Duration,
+    /// How long before that it's said to have been captured
+    latency: Duration,
+}
+
+struct Playback {
+    /// Interleaved, like a device hands them over
+    samples: Vec<f32>,
+    blocks: Vec<Block>,
+    /// The next block to hand over
+    next: usize,
+    elapsed: Duration,
+    /// Where blocks go, while something is recording. Numbered so a stream that's been
+    /// replaced can't disconnect the one that replaced it.
+    stream: Option<(u64, DataCallback, ErrorCallback)>,
+    streams: u64,
+}
+
+/// Cut `len` samples into blocks of `frames` frames, each handed over once its last frame
+/// is due
+fn even_blocks(len: usize, sample_rate: u32, channels: u16, frames: usize) -> Vec<Block> {
+    let block = frames.max(1) * channels as usize;
+    (0..len)
+        .step_by(block)
+        .map(|start| {
+            let end = (start + block).min(len);
+            // Rounded up to the nanosecond, so steps that add up to a block make one
+            let rate = sample_rate.max(1) as u128;
+            let nanos = ((end / channels as usize) as u128 * 1_000_000_000).div_ceil(rate);
+            Block {
+                range: start..end,
+                at: Duration::from_nanos(nanos as u64),
+                latency: Duration::ZERO,
+            }
+        })
+        .collect()
+}
+
+impl VirtualInput {
+    /// What the host is called, for looking up settings kept by host and device name
+    pub const HOST: &str = "Virtual";
+
+    pub fn new(name: &str, sample_rate: u32, channels: u16, samples: Vec<f32>) -> Self {
+        let channels = channels.max(1);
+        let blocks = even_blocks(samples.len(), sample_rate, channels, BLOCK_FRAMES);
+        Self::with_blocks(name, sample_rate, channels, samples, blocks)
+    }
+
+    /// Play back the blocks an input device delivered, as they were delivered and when
+    pub fn from_capture(name: &str, capture: &Capture) -> Self {
+        let mut samples = Vec::new();
+        let mut blocks = Vec::with_capacity(capture.blocks.len());
+        for block in &capture.blocks {
+            blocks.push(Block {
+                range: samples.len()..samples.len() + block.samples.len(),
+                at: block.at,
+                latency: block.latency,
+            });
+            samples.extend_from_slice(&block.samples);
+        }
+        Self::with_blocks(
+            name,
+            capture.sample_rate,
+            capture.channels.max(1),
+            samples,
+            blocks,
+        )
+    }
+
+    fn with_blocks(
+        name: &str,
+        sample_rate: u32,
+        channels: u16,
+        samples: Vec<f32>,
+        blocks: Vec<Block>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            sample_rate,
+            channels,
+            playback: Arc::new(Mutex::new(Playback {
+                samples,
+                blocks,
+                next: 0,
+                elapsed: Duration::ZERO,
+                stream: None,
+                streams: 0,
+            })),
+        }
+    }
+
+    /// Cut the samples into blocks of this many frames instead, before playing any
+    pub fn with_block_frames(self, frames: usize) -> Self {
+        {
+            let mut playback = self.playback.lock();
+            playback.blocks = even_blocks(
+                playback.samples.len(),
+                self.sample_rate,
+                self.channels,
+                frames,
+            );
+        }
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// How long the clock has run
+    pub fn elapsed(&self) -> Duration {
+        self.playback.lock().elapsed
+    }
+
+    /// Frames not handed over yet
+    pub fn remaining_frames(&self) -> usize {
+        let playback = self.playback.lock();
+        let position = playback
+            .blocks
+            .get(playback.next)
+            .map_or(playback.samples.len(), |block| block.range.start);
+        (playback.samples.len() - position) / self.channels as usize
+    }
+
+    /// Move the clock on, handing over every block that's come by then. Returns how many
+    /// frames were handed over. Like a real device, they're lost if nothing's recording.
+    pub fn advance(&self, by: Duration) -> usize {
+        let mut playback = self.playback.lock();
+        playback.elapsed += by;
+        self.deliver(&mut playback)
+    }
+
+    /// Move the clock on to the last block, handing over everything left
+    pub fn play_to_end(&self) -> usize {
+        let mut playback = self.playback.lock();
+        if let Some(last) = playback.blocks.last() {
+            playback.elapsed = playback.elapsed.max(last.at);
+        }
+        self.deliver(&mut playback)
+    }
+
+    /// Whether something's recording from it
+    pub fn is_playing(&self) -> bool {
+        self.playback.lock().stream.is_some()
+    }
+
+    /// Go back to the start, with the clock at zero
+    pub fn rewind(&self) {
+        let mut playback = self.playback.lock();
+        playback.next = 0;
+        playback.elapsed = Duration::ZERO;
+    }
+
+    /// Report the device gone, as if it had been unplugged
+    pub fn unplug(&self) {
+        if let Some((_, _, on_error)) = &mut self.playback.lock().stream {
+            on_error(StreamError::DeviceNotAvailable);
+        }
+    }
+
+    /// Hand over the blocks due by now
+    fn deliver(&self, playback: &mut Playback) -> usize {
+        let Playback {
+            samples,
+            blocks,
+            next,
+            elapsed,
+            stream,
+            ..
+        } = playback;
+        let mut frames = 0;
+        while let Some(block) = blocks.get(*next).filter(|block| block.at <= *elapsed) {
+            if let Some((_, on_data, _)) = stream {
+                on_data(&samples[block.range.clone()], block.latency);
+            }
+            frames += block.range.len() / self.channels as usize;
+            *next += 1;
+        }
+        frames
+    }
+
+    /// Start handing blocks to `on_data`, until the stream returned is dropped. Any stream
+    /// already playing is replaced.
+    pub(crate) fn play(
+        &self,
+        on_data: impl FnMut(&[f32], Duration) + Send + 'static,
+        on_error: impl FnMut(StreamError) + Send + 'static,
+    ) -> VirtualStream {
+        let mut playback = self.playback.lock();
+        playback.streams += 1;
+        let id = playback.streams;
+        playback.stream = Some((id, Box::new(on_data), Box::new(on_error)));
+        VirtualStream {
+            playback: self.playback.clone(),
+            id,
+        }
+    }
+}
+
+/// A virtual input's blocks going somewhere, until dropped
+pub(crate) struct VirtualStream {
+    playback: Arc<Mutex<Playback>>,
+    id: u64,
+}
+
+impl Drop for VirtualStream {
+    fn drop(&mut self) {
+        // Taken out before it's dropped, since the callbacks may hold things that lock
+        let stream = {
+            let mut playback = self.playback.lock();
+            match &playback.stream {
+                Some((id, _, _)) if *id == self.id => playback.stream.take(),
+                _ => None,
+            }
+        };
+        drop(stream);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Settings,
+        data::audio::{BitDepth, ClipId, WavClip},
+        events::Observers,
+        session::Session,
+        testutil::scratch_dir,
+        timesource::{self, ManualTime},
+        tools::SampleRecorder,
+    };
+    use chrono::{TimeDelta, TimeZone, Utc};
+    use parking_lot::RwLock;
+    use std::{fs, path::Path};
+
+    fn ramp(len: usize) -> Vec<f32> {
+        (0..len).map(|i| (i % 100) as f32 / 100.0 - 0.5).collect()
+    }
+
+    #[test]
+    fn advancing_hands_over_whole_blocks() {
+        let input = VirtualInput::new("Test", 8000, 2, ramp(2 * 1000)).with_block_frames(100);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let _stream = input.play(
+            {
+                let received = received.clone();
+                move |data, _| received.lock().extend_from_slice(data)
+            },
+            |_| {},
+        );
+        // 25 ms is 200 frames, and 250 is only two whole blocks
+        assert_eq!(input.advance(Duration::from_millis(25)), 200);
+        assert_eq!(input.advance(Duration::from_micros(6250)), 0);
+        assert_eq!(input.advance(Duration::from_micros(6250)), 100);
+        assert_eq!(input.play_to_end(), 700);
+        assert_eq!(input.remaining_frames(), 0);
+        assert_eq!(*received.lock(), ramp(2 * 1000));
+    }
+
+    #[test]
+    fn nothing_is_handed_over_once_the_stream_is_dropped() {
+        let input = VirtualInput::new("Test", 8000, 1, ramp(1000));
+        let received = Arc::new(Mutex::new(0));
+        let stream = input.play(
+            {
+                let received = received.clone();
+                move |data, _| *received.lock() += data.len()
+            },
+            |_| {},
+        );
+        input.advance(Duration::from_millis(50));
+        drop(stream);
+        input.play_to_end();
+        assert_eq!(*received.lock(), 384);
+    }
+
+    #[test]
+    fn recorder_writes_everything_played() {
+        let dir = scratch_dir("virtual-recorder");
+        let samples = ramp(48000);
+        let input = VirtualInput::new("Test", 48000, 1, samples.clone());
+        let spec = BitDepth::Float32.wav_spec(48000);
+        let clip = WavClip::record_new(ClipId::from_datetimeutc(Utc::now()), &dir, spec).unwrap();
+        let clip = Arc::new(RwLock::new(clip));
+        let recorder = SampleRecorder::from_virtual(
+            &input,
+            clip.clone(),
+            Observers::default(),
+            &Settings::default(),
+            &timesource::system(),
+        )
+        .unwrap();
+        input.play_to_end();
+        recorder.close().unwrap();
+
+        let clip = clip.read();
+        assert_eq!(clip.samples.len(), samples.len());
+        assert_eq!(&*clip.samples.range(0..samples.len()), &samples[..]);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn session_finishes_the_clip_when_unplugged() {
+        let dir = scratch_dir("virtual-session");
+        let input = VirtualInput::new("Test", 8000, 1, ramp(8000));
+        let mut session = Session::open(dir.clone(), &Settings::default()).unwrap();
+        session.configure_virtual(input.clone()).unwrap();
+        session.record_new_clip().unwrap();
+        input.advance(Duration::from_millis(500));
+        input.unplug();
+
+        let id = session
+            .check_device()
+            .unwrap()
+            .expect("the clip was cut short");
+        assert!(!session.is_recording());
+        let clip = session.clips[&id].read();
+        assert!(clip.metadata.device_lost);
+        assert_eq!(clip.samples.len(), 3968);
+        drop(clip);
+        drop(session);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn clips_are_named_and_stamped_by_the_session_clock() {
+        let dir = scratch_dir("virtual-time");
+        let input = VirtualInput::new("Test", 8000, 1, ramp(8000));
+        let mut settings = Settings::default();
+        settings.recording.utc_names = true;
+        let time = ManualTime::new(Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap());
+        let mut session = Session::open(dir.clone(), &settings).unwrap();
+        session.set_time_source(Arc::new(time.clone()));
+        session.configure_virtual(input.clone()).unwrap();
+
+        session.record_new_clip().unwrap();
+        time.advance(TimeDelta::milliseconds(250));
+        input.advance(Duration::from_millis(100));
+        session.stop_recording().unwrap();
+        let id = ClipId::from_path_ref(Path::new("2025-06-01_12-00-00.000000000Z.wav")).unwrap();
+        let clip = session.clips[&id].read();
+        assert_eq!(
+            clip.metadata.started,
+            Some(
+                Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap() + TimeDelta::milliseconds(250)
+            )
+        );
+        drop(clip);
+        drop(session);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn next_clip_carries_on_without_losing_anything() {
+        let dir = scratch_dir("virtual-next");
+        let samples = ramp(8000);
+        let input = VirtualInput::new("Test", 8000, 1, samples.clone());
+        let start = Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap();
+        let time = ManualTime::new(start);
+        let mut session = Session::open(dir.clone(), &Settings::default()).unwrap();
+        session.set_time_source(Arc::new(time.clone()));
+        session.configure_virtual(input.clone()).unwrap();
+
+        session.record_new_clip().unwrap();
+        let first = session.recording_clip().unwrap().clone();
+        input.advance(Duration::from_millis(500));
+        time.advance(TimeDelta::seconds(1));
+        let id = session.next_clip().unwrap();
+        input.play_to_end();
+        session.stop_recording().unwrap();
+
+        let first = first.read();
+        let second = session.clips[&id].read();
+        assert_eq!(first.metadata.closed_cleanly, Some(true));
+        assert_eq!(first.samples.len() + second.samples.len(), samples.len());
+        let mut recorded = first.samples.from(0).into_owned();
+        recorded.extend_from_slice(&second.samples.from(0));
+        assert_eq!(recorded, samples);
+        // It started where the first left off, by the samples rather than the clock
+        let offset = first.samples.len() as i64 * 1_000_000 / 8000;
+        assert_eq!(
+            second.metadata.started,
+            Some(start + TimeDelta::microseconds(offset))
+        );
+        drop((first, second));
+        drop(session);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
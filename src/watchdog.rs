@@ -0,0 +1,105 @@
+use std::time::{Duration, Instant};
+use thiserror::Error as ThisError;
+
+/// What the watchdog is called in the pipeline
+pub const ELEMENT_NAME: &str = "Watchdog";
+/// How long the input can go without sending anything before it's taken to have stalled
+const STALL_AFTER: Duration = Duration::from_secs(1);
+/// How long the input's rate is measured over
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+/// How far off its rate the input can run before it's reported, as a fraction
+const RATE_TOLERANCE: f64 = 0.05;
+
+/// Something wrong with how the input is sending samples
+#[derive(Debug, Clone, PartialEq, ThisError)]
+pub enum Alarm {
+    #[error("The input has stopped sending samples")]
+    Stalled,
+    #[error("The input stopped sending samples for {0:.1} s")]
+    Gap(f64),
+    #[error("The input ran {:.0}% {}", .0.abs() * 100.0, if *.0 < 0.0 { "slow" } else { "fast" })]
+    Rate(f64),
+}
+
+impl Alarm {
+    /// What to mark the clip with where it happened, if it left a mark on the recording
+    pub fn marker_label(&self) -> Option<String> {
+        match self {
+            Alarm::Stalled => None,
+            Alarm::Gap(seconds) => Some(format!("Gap of {:.1} s", seconds)),
+            Alarm::Rate(off) => Some(format!("Input {:+.0}% off rate", off * 100.0)),
+        }
+    }
+}
+
+/// Watches the samples arriving from an input against the rate they should, to catch it
+/// stalling, or overrunning and underrunning enough to run fast or slow
+pub struct Watchdog {
+    /// Samples a second the input should send, over all its channels
+    expected: f64,
+    /// The input's count when it last sent something, and when that was. None until it
+    /// sends its first.
+    last: Option<(usize, Instant)>,
+    /// When the stall started, while stalled
+    stalled: Option<Instant>,
+    /// Where the rate's being measured from
+    window: (usize, Instant),
+    /// Whether the input stalled or was paused since the window started, which throws
+    /// the rate off
+    disturbed: bool,
+}
+
+impl Watchdog {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            expected: sample_rate as f64 * channels.max(1) as f64,
+            last: None,
+            stalled: None,
+            window: (0, Instant::now()),
+            disturbed: false,
+        }
+    }
+
+    /// Look at how many samples the input has sent in all. Returns whatever's newly gone
+    /// wrong: a stall once when it starts, and again as a gap once samples come back.
+    pub fn check(&mut self, count: usize, paused: bool) -> Option<Alarm> {
+        let now = Instant::now();
+        let Some((last_count, last_time)) = self.last else {
+            if count > 0 {
+                self.last = Some((count, now));
+                self.window = (count, now);
+            }
+            return None;
+        };
+        if paused {
+            // Nothing's expected while paused, start over once resumed
+            self.last = Some((count, now));
+            self.disturbed = true;
+            return None;
+        }
+        if count == last_count {
+            if self.stalled.is_none() && now - last_time >= STALL_AFTER {
+                self.stalled = Some(last_time);
+                self.disturbed = true;
+                return Some(Alarm::Stalled);
+            }
+            return None;
+        }
+        self.last = Some((count, now));
+        if let Some(since) = self.stalled.take() {
+            return Some(Alarm::Gap((now - since).as_secs_f64()));
+        }
+
+        let (window_count, window_start) = self.window;
+        let elapsed = (now - window_start).as_secs_f64();
+        if elapsed < RATE_WINDOW.as_secs_f64() {
+            return None;
+        }
+        self.window = (count, now);
+        if std::mem::take(&mut self.disturbed) {
+            return None;
+        }
+        let off = (count - window_count) as f64 / (elapsed * self.expected) - 1.0;
+        (off.abs() > RATE_TOLERANCE).then_some(Alarm::Rate(off))
+    }
+}